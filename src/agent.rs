@@ -0,0 +1,91 @@
+use crate::chatter_json::ChatterJSON;
+use crate::events::AgentProgress;
+use crate::schema::Schema;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestUserMessageArgs,
+};
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use serde::de::DeserializeOwned;
+
+// The AI agents share one way of talking to the model: their system prompt, then the messages of
+// the request, with the response returned as a JSON object of the fields of its schema. An agent
+// implements the accessors, and `chat` sends the request, warns about any fields beyond the
+// schema's, and deserializes the response into the agent's type, such as Code or Correction. The
+// chatter asks the model again for a response that's missing the schema's required fields.
+// `chat_samples` does the same for each of the responses sampled from the request.
+
+#[async_trait]
+pub trait Agent: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn system_prompt(&self) -> &str;
+
+    fn chatter(&self) -> &ChatterJSON;
+
+    // The schema of the JSON response. Missing required fields are an error.
+    fn schema(&self) -> &'static Schema;
+
+    async fn chat<T: DeserializeOwned>(
+        &self,
+        progress: &AgentProgress,
+        msgs: &[ChatCompletionRequestMessage],
+    ) -> Result<T> {
+        Ok(self.chat_samples(progress, msgs).await?.remove(0))
+    }
+
+    // The responses that don't match the schema are dropped, unless none of them do.
+    async fn chat_samples<T: DeserializeOwned>(
+        &self,
+        progress: &AgentProgress,
+        msgs: &[ChatCompletionRequestMessage],
+    ) -> Result<Vec<T>> {
+        let mut request = vec![ChatCompletionRequestSystemMessageArgs::default()
+            .content(self.system_prompt())
+            .build()?
+            .into()];
+        request.extend_from_slice(msgs);
+        let samples = self
+            .chatter()
+            .chat(progress, &request, Some(self.schema()))
+            .await?;
+
+        let mut responses = vec![];
+        let mut first_error = None;
+        for json in samples {
+            let extra_keys = match self.schema().validate(&json) {
+                Ok(extra_keys) => extra_keys,
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                    continue;
+                }
+            };
+            if !extra_keys.is_empty() {
+                progress.note(&format!(
+                    "{}: Warning: Extra keys in the {} response: {:?}",
+                    self.name(),
+                    self.schema(),
+                    extra_keys
+                ));
+            }
+            match serde_json::from_value(json) {
+                Ok(response) => responses.push(response),
+                Err(e) => {
+                    first_error.get_or_insert(e.into());
+                }
+            }
+        }
+        match (responses.is_empty(), first_error) {
+            (true, Some(e)) => Err(e),
+            _ => Ok(responses),
+        }
+    }
+}
+
+pub fn user_message(content: &str) -> Result<ChatCompletionRequestMessage> {
+    Ok(ChatCompletionRequestUserMessageArgs::default()
+        .content(content)
+        .build()?
+        .into())
+}
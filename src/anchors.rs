@@ -0,0 +1,191 @@
+use crate::critic::Location;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::{ImplItem, Item};
+
+// A critic may say where in the code each correction applies, by line number, function name, or
+// both. The locations are checked against the code, since a model's line numbers are often off: a
+// function must exist, and a line must be in the code and, if a function is given too, in that
+// function. A function without a usable line anchors the correction at its first line. To give
+// the critics line numbers to refer to, the code that they review is numbered, with a window of it
+// numbered by its lines in the whole code. The fixer
+// is then given the code with a marker comment on each anchored line, so that its fixes land in
+// the right place, and the markers are stripped from the code that it returns.
+
+// The marker that ends an anchored line, followed by the numbers of its comments.
+const MARKER: &str = "// <- review";
+
+// A comment of a review anchored to a line of the code, counting from 1.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Anchor {
+    // The comment's index in the review.
+    pub comment: usize,
+    pub line: usize,
+}
+
+// A function of the code, by name and by the name qualified by its impl's type, with its lines.
+pub struct Function {
+    pub name: String,
+    pub qualified: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+// The line that the location refers to, if it checks out against the code.
+pub fn resolve(code: &str, location: &Location) -> Option<usize> {
+    let lines = code.lines().count();
+    let line = location.line.filter(|line| (1..=lines).contains(line));
+    let Some(name) = &location.function else {
+        return line;
+    };
+    let name = name.trim().trim_start_matches("fn ").trim_end_matches("()");
+    let function = functions(code)
+        .into_iter()
+        .find(|f| f.name == name || f.qualified.as_deref() == Some(name))?;
+    match line {
+        Some(line) if (function.start..=function.end).contains(&line) => Some(line),
+        _ => Some(function.start),
+    }
+}
+
+// The functions of the code, including those in modules and impl blocks.
+pub fn functions(code: &str) -> Vec<Function> {
+    let mut found = vec![];
+    if let Ok(file) = syn::parse_file(code) {
+        collect_functions(&file.items, &mut found);
+    }
+    found
+}
+
+fn collect_functions(items: &[Item], found: &mut Vec<Function>) {
+    for item in items {
+        match item {
+            Item::Fn(f) => found.push(function(f.sig.ident.to_string(), None, f.span())),
+            Item::Mod(module) => {
+                if let Some((_, content)) = &module.content {
+                    collect_functions(content, found);
+                }
+            }
+            Item::Impl(block) => {
+                let ty = match block.self_ty.as_ref() {
+                    syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+                    _ => None,
+                };
+                for impl_item in block.items.iter() {
+                    if let ImplItem::Fn(f) = impl_item {
+                        let name = f.sig.ident.to_string();
+                        let qualified = ty.as_ref().map(|ty| format!("{}::{}", ty, name));
+                        found.push(function(name, qualified, f.span()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn function(name: String, qualified: Option<String>, span: proc_macro2::Span) -> Function {
+    Function {
+        name,
+        qualified,
+        start: span.start().line,
+        end: span.end().line,
+    }
+}
+
+// The code with each line prefixed by its number, counting from `first`, as the critics see it.
+pub fn numbered(code: &str, first: usize) -> String {
+    let width = (first + code.lines().count()).to_string().len();
+    code.lines()
+        .enumerate()
+        .map(|(i, line)| format!("{:>width$} | {}\n", first + i, line, width = width))
+        .collect()
+}
+
+// Mark each anchored line of the code with the numbers, counting from 1, of the comments about it.
+pub fn annotate(code: &str, anchors: &[Anchor]) -> String {
+    let lines: Vec<String> = code
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let numbers: Vec<String> = anchors
+                .iter()
+                .filter(|anchor| anchor.line == i + 1)
+                .map(|anchor| format!("[{}]", anchor.comment + 1))
+                .collect();
+            match numbers.is_empty() {
+                true => line.to_string(),
+                false => format!("{} {} {}", line, MARKER, numbers.join(" ")),
+            }
+        })
+        .collect();
+    format!("{}\n", lines.join("\n"))
+}
+
+// Remove the markers that annotate() added, in case the fixer kept them.
+pub fn strip(code: &str) -> String {
+    let re = Regex::new(&format!(r"(?m)[ \t]*{}( \[\d+\])+[ \t]*$", MARKER)).unwrap();
+    re.replace_all(code, "").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "fn parse(s: &str) -> i32 {\n    s.parse().unwrap()\n}\n\nstruct Stack;\n\n\
+                        impl Stack {\n    fn push(&mut self) {\n        todo!()\n    }\n}\n";
+
+    fn location(line: Option<usize>, function: Option<&str>) -> Location {
+        Location {
+            line,
+            function: function.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_numbered() {
+        assert_eq!(numbered("fn f() {\n}\n", 9), " 9 | fn f() {\n10 | }\n");
+        assert_eq!(numbered("", 1), "");
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve(CODE, &location(Some(2), None)), Some(2));
+        assert_eq!(resolve(CODE, &location(Some(99), None)), None);
+        assert_eq!(resolve(CODE, &location(None, Some("parse"))), Some(1));
+        assert_eq!(
+            resolve(CODE, &location(Some(9), Some("Stack::push"))),
+            Some(9)
+        );
+        // A line outside the function is replaced by the function's first line.
+        assert_eq!(
+            resolve(CODE, &location(Some(2), Some("fn push()"))),
+            Some(8)
+        );
+        assert_eq!(resolve(CODE, &location(Some(2), Some("pop"))), None);
+        assert_eq!(resolve(CODE, &location(None, None)), None);
+    }
+
+    #[test]
+    fn test_annotate_and_strip() {
+        let anchors = [
+            Anchor {
+                comment: 0,
+                line: 2,
+            },
+            Anchor {
+                comment: 2,
+                line: 2,
+            },
+            Anchor {
+                comment: 1,
+                line: 9,
+            },
+        ];
+        let annotated = annotate(CODE, &anchors);
+        assert!(annotated.contains("    s.parse().unwrap() // <- review [1] [3]\n"));
+        assert!(annotated.contains("        todo!() // <- review [2]\n"));
+        assert_eq!(strip(&annotated), CODE);
+    }
+}
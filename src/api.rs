@@ -0,0 +1,215 @@
+use crate::run_context::RunContext;
+use crate::worker::JobQueue;
+use axum::extract::{Path, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// The worker's REST API, for services that submit problems and fetch their results over HTTP
+// rather than through the CLI:
+//   POST /problems          queue a problem, given as the text body or as {"problem": "..."} in
+//                           a JSON body; answers 202 with the run's ID and URL
+//   GET /runs/{id}          the run's status (queued, running, or finished), with its latest
+//                           state and, once it has finished, its result
+//   GET /runs/{id}/events   the run's transcript so far, as JSON lines
+// Everything that is fetched is read from the run's artifacts, so the runs of earlier workers that
+// shared the output directory can be fetched too.
+//
+// A submitted problem becomes code that runs on the host, at the cost of the API's budget, so with
+// a token every route requires it as `Authorization: Bearer <token>`. The worker refuses to serve
+// without one except on a loopback address.
+
+const EVENTS_CONTENT_TYPE: &str = "application/x-ndjson";
+
+pub fn router(queue: Arc<JobQueue>, token: Option<String>) -> Router {
+    Router::new()
+        .route("/problems", post(submit))
+        .route("/runs/:id", get(run))
+        .route("/runs/:id/events", get(events))
+        .layer(middleware::from_fn_with_state(Arc::new(token), authorize))
+        .with_state(queue)
+}
+
+// Pass the request on if it has the token, or if there's no token to have.
+async fn authorize(
+    State(token): State<Arc<Option<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match token.as_deref() {
+        Some(token) if !has_token(request.headers(), token) => error(
+            StatusCode::UNAUTHORIZED,
+            "The request must have the worker's token as `Authorization: Bearer <token>`.",
+        ),
+        _ => next.run(request).await,
+    }
+}
+
+// Whether the request's bearer token is the token, compared in constant time.
+fn has_token(headers: &HeaderMap, token: &str) -> bool {
+    let Some(given) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+fn not_found(id: &str) -> Response {
+    error(StatusCode::NOT_FOUND, &format!("There's no run {}.", id))
+}
+
+// The problem in the body, which is JSON if the request says so and the text of the problem
+// otherwise.
+fn problem_of(headers: &HeaderMap, body: &str) -> Option<String> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let problem = match is_json {
+        true => serde_json::from_str::<Value>(body)
+            .ok()?
+            .get("problem")?
+            .as_str()?
+            .to_string(),
+        false => body.to_string(),
+    };
+    (!problem.trim().is_empty()).then_some(problem)
+}
+
+async fn submit(State(queue): State<Arc<JobQueue>>, headers: HeaderMap, body: String) -> Response {
+    let Some(problem) = problem_of(&headers, &body) else {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "The request's body must be the problem, as text or as {\"problem\": \"...\"}.",
+        );
+    };
+    match queue.submit(&problem, "POST /problems") {
+        Ok(id) => {
+            let body = json!({ "id": id, "url": format!("/runs/{}", id) });
+            (StatusCode::ACCEPTED, Json(body)).into_response()
+        }
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+// A run is finished once it has a result and running once it has entered a state.
+fn status_of(context: &RunContext) -> Value {
+    let (state, result) = (context.state(), context.result());
+    let status = match (&state, &result) {
+        (_, Some(_)) => "finished",
+        (Some(_), None) => "running",
+        (None, None) => "queued",
+    };
+    json!({
+        "id": context.id,
+        "status": status,
+        "state": state,
+        "result": result,
+    })
+}
+
+async fn run(State(queue): State<Arc<JobQueue>>, Path(id): Path<String>) -> Response {
+    match RunContext::open(queue.output_dir(), &id) {
+        Some(context) => Json(status_of(&context)).into_response(),
+        None => not_found(&id),
+    }
+}
+
+async fn events(State(queue): State<Arc<JobQueue>>, Path(id): Path<String>) -> Response {
+    match RunContext::open(queue.output_dir(), &id) {
+        Some(context) => (
+            [(header::CONTENT_TYPE, EVENTS_CONTENT_TYPE)],
+            context.transcript(),
+        )
+            .into_response(),
+        None => not_found(&id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use std::fs;
+
+    #[test]
+    fn test_problem_of() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            problem_of(&headers, "Add two numbers."),
+            Some("Add two numbers.".to_string())
+        );
+        assert_eq!(problem_of(&headers, "  \n"), None);
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        assert_eq!(
+            problem_of(&headers, r#"{"problem": "Add two numbers."}"#),
+            Some("Add two numbers.".to_string())
+        );
+        assert_eq!(
+            problem_of(&headers, r#"{"task": "Add two numbers."}"#),
+            None
+        );
+        assert_eq!(problem_of(&headers, "Add two numbers."), None);
+    }
+
+    #[test]
+    fn test_has_token() {
+        let mut headers = HeaderMap::new();
+        assert!(!has_token(&headers, "s3cret"));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("s3cret"));
+        assert!(!has_token(&headers, "s3cret"));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer s3cre"),
+        );
+        assert!(!has_token(&headers, "s3cret"));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer s3cret"),
+        );
+        assert!(has_token(&headers, "s3cret"));
+    }
+
+    #[test]
+    fn test_status_of() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let context = RunContext::create(output_dir.path(), "worker-1").unwrap();
+        assert_eq!(status_of(&context)["status"], "queued");
+        fs::write(
+            context.dir.join("state.json"),
+            r#"{"state": "coding", "proposal": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(status_of(&context)["status"], "running");
+        assert_eq!(status_of(&context)["state"]["proposal"], 1);
+        fs::write(context.dir.join("result.json"), r#"{"outcome": "Solved"}"#).unwrap();
+        let status = status_of(&context);
+        assert_eq!(status["status"], "finished");
+        assert_eq!(status["result"]["outcome"], "Solved");
+
+        // Only the runs in the output directory can be opened.
+        assert!(RunContext::open(output_dir.path(), "worker-1").is_some());
+        assert!(RunContext::open(output_dir.path(), "worker-2").is_none());
+        assert!(RunContext::open(output_dir.path(), "..").is_none());
+        assert!(RunContext::open(output_dir.path(), "worker-1/../worker-1").is_none());
+    }
+}
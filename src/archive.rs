@@ -0,0 +1,316 @@
+use crate::errors::AiCriticError;
+use color_eyre::eyre::Result;
+use proc_macro2::{TokenStream, TokenTree};
+use serde::Serialize;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+// With --collect-k N, the problem is solved again after the first success until N distinct
+// solutions have passed, for studying how differently it can be solved or as teaching material.
+// Each attempt is a run of its own, as with --runs, with a fresh seed: consecutive seeds from
+// --seed, or from 0. Solutions are compared by their normalized syntax: the code's token trees with
+// its comments, doc comments, and formatting dropped, so that a solution that's only reformatted or
+// recommented isn't counted again. The distinct solutions are written to solutions/solution_N.rs
+// beside the runs' directories, with an index in solutions.json of the run that each came from. A
+// problem with fewer solutions than asked for is given up on after MAX_ATTEMPTS_PER_SOLUTION
+// attempts per solution. The exit status is the number of distinct solutions collected. As every
+// passing run would publish its solution, the publishing options can't be used with --collect-k.
+
+pub const SOLUTIONS_DIRNAME: &str = "solutions";
+pub const INDEX_FILENAME: &str = "solutions.json";
+const MAX_ATTEMPTS_PER_SOLUTION: u32 = 3;
+
+// The most runs made to collect k solutions.
+pub fn max_attempts(k: u32) -> u32 {
+    k * MAX_ATTEMPTS_PER_SOLUTION
+}
+
+// A run made to collect a solution: its ID, its result, and the code it ended with, if any.
+pub struct Attempt {
+    pub run_id: String,
+    pub result: Result<usize>,
+    pub code: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArchivedSolution {
+    pub file: String,
+    pub run_id: String,
+    pub proposals: usize,
+    // The later solutions that turned out the same as this one.
+    pub duplicates: usize,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Archive {
+    pub attempts: usize,
+    pub solutions: Vec<ArchivedSolution>,
+    // The normalized code of each solution, to compare new ones with.
+    #[serde(skip)]
+    normalized: Vec<String>,
+}
+
+impl Archive {
+    pub fn count(&self) -> usize {
+        self.solutions.len()
+    }
+
+    // Count an attempt that didn't pass.
+    pub fn fail(&mut self) {
+        self.attempts += 1;
+    }
+
+    // Add a passing solution, writing it to the solutions directory under the given directory if
+    // it's new. Return its path, or None if it duplicates an earlier one.
+    pub fn add(
+        &mut self,
+        dir: &Path,
+        run_id: &str,
+        proposals: usize,
+        code: &str,
+    ) -> Result<Option<PathBuf>> {
+        self.attempts += 1;
+        let normalized = normalize(code);
+        if let Some(i) = self.normalized.iter().position(|n| *n == normalized) {
+            self.solutions[i].duplicates += 1;
+            return Ok(None);
+        }
+        let file = format!("solution_{}.rs", self.solutions.len() + 1);
+        let path = dir.join(SOLUTIONS_DIRNAME).join(&file);
+        fs::create_dir_all(dir.join(SOLUTIONS_DIRNAME))?;
+        fs::write(&path, code)?;
+        self.normalized.push(normalized);
+        self.solutions.push(ArchivedSolution {
+            file: format!("{}/{}", SOLUTIONS_DIRNAME, file),
+            run_id: run_id.to_string(),
+            proposals,
+            duplicates: 0,
+        });
+        Ok(Some(path))
+    }
+
+    // Write the index of the solutions to the directory.
+    pub fn write_index(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(INDEX_FILENAME);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+// Make the attempts, numbered from 0, until k distinct solutions have passed, an attempt is
+// cancelled, or the attempts run out, archiving each new solution under the directory and passing
+// it to `found`. Return the archive and the collection's result: the number of distinct solutions,
+// or the last attempt's error if it was cancelled or no solution passed.
+pub async fn collect<F, Fut>(
+    dir: &Path,
+    k: u32,
+    mut attempt: F,
+    mut found: impl FnMut(&Archive, &Path),
+) -> Result<(Archive, Result<usize>)>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<Attempt>>,
+{
+    let mut archive = Archive::default();
+    let mut result = Ok(0);
+    for i in 0..max_attempts(k) {
+        let Attempt {
+            run_id,
+            result: attempt_result,
+            code,
+        } = attempt(i).await?;
+        match (&attempt_result, code) {
+            (Ok(proposals), Some(code)) => {
+                if let Some(path) = archive.add(dir, &run_id, *proposals, &code)? {
+                    found(&archive, &path);
+                }
+            }
+            _ => archive.fail(),
+        }
+        let cancelled = matches!(
+            attempt_result
+                .as_ref()
+                .map_err(|e| e.downcast_ref::<AiCriticError>()),
+            Err(Some(AiCriticError::Cancelled))
+        );
+        result = attempt_result;
+        if cancelled {
+            return Ok((archive, result));
+        }
+        if archive.count() >= k as usize {
+            break;
+        }
+    }
+    let result = match archive.count() {
+        0 => result,
+        count => Ok(count),
+    };
+    Ok((archive, result))
+}
+
+// The code's tokens without its comments, doc comments, and formatting. Code that can't be
+// tokenized is compared by its words instead.
+fn normalize(code: &str) -> String {
+    match code.parse::<TokenStream>() {
+        Ok(tokens) => without_docs(tokens).to_string(),
+        Err(_) => code.split_whitespace().collect::<Vec<_>>().join(" "),
+    }
+}
+
+// The tokens with the `#[doc = "..."]` and `#![doc = "..."]` attributes that doc comments become
+// removed, at every depth.
+fn without_docs(tokens: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let is_doc = |tree: Option<&TokenTree>| match tree {
+        Some(TokenTree::Group(group)) => matches!(
+            group.stream().into_iter().next(),
+            Some(TokenTree::Ident(ident)) if ident == "doc"
+        ),
+        _ => false,
+    };
+    let is_punct = |tree: Option<&TokenTree>, c: char| matches!(tree, Some(TokenTree::Punct(punct)) if punct.as_char() == c);
+    let mut kept = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_punct(tokens.get(i), '#') {
+            let bang = usize::from(is_punct(tokens.get(i + 1), '!'));
+            if is_doc(tokens.get(i + 1 + bang)) {
+                i += 2 + bang;
+                continue;
+            }
+        }
+        kept.push(match &tokens[i] {
+            TokenTree::Group(group) => TokenTree::Group(proc_macro2::Group::new(
+                group.delimiter(),
+                without_docs(group.stream()),
+            )),
+            tree => tree.clone(),
+        });
+        i += 1;
+    }
+    kept.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive() {
+        let first = "/// Adds.\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+        let with_tests =
+            "// The sum.\nfn add(a: i32, b: i32) -> i32 { a + b }\n\nmod tests {\n    \
+                           //! Tests.\n}\n";
+        let different = "fn add(a: i32, b: i32) -> i32 {\n    b + a\n}\n";
+        assert_eq!(
+            normalize(first),
+            normalize("fn add(a: i32, b: i32) -> i32 { a + b }")
+        );
+        assert_ne!(normalize(first), normalize(different));
+
+        let dir = TempDir::new().unwrap();
+        let mut archive = Archive::default();
+        let path = archive.add(dir.path(), "r-1", 2, first).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), first);
+        archive.fail();
+        assert!(archive
+            .add(
+                dir.path(),
+                "r-3",
+                1,
+                "/// The sum.\nfn add(a: i32, b: i32) -> i32 { a + b }"
+            )
+            .unwrap()
+            .is_none());
+        assert!(archive
+            .add(dir.path(), "r-4", 3, with_tests)
+            .unwrap()
+            .is_some());
+        assert!(archive
+            .add(dir.path(), "r-5", 1, different)
+            .unwrap()
+            .is_some());
+        assert_eq!(archive.count(), 3);
+        assert_eq!(archive.attempts, 5);
+
+        let index: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(archive.write_index(dir.path()).unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(index["solutions"][0]["duplicates"], 1);
+        assert_eq!(index["solutions"][2]["file"], "solutions/solution_3.rs");
+        assert_eq!(index["solutions"][2]["run_id"], "r-5");
+    }
+
+    // Collect from attempts that end as given: with the code if it passed, or else with the error.
+    async fn collect_from(
+        k: u32,
+        outcomes: Vec<Result<&str, AiCriticError>>,
+    ) -> (Archive, Result<usize>) {
+        let dir = TempDir::new().unwrap();
+        let mut outcomes = outcomes.into_iter();
+        let mut found = 0;
+        let attempt = |i: u32| {
+            let outcome = outcomes.next().expect("too many attempts");
+            async move {
+                Ok(Attempt {
+                    run_id: format!("r-{}", i + 1),
+                    code: outcome.as_ref().ok().map(|code| code.to_string()),
+                    result: outcome.map(|_| 1).map_err(|e| e.into()),
+                })
+            }
+        };
+        let collected = collect(dir.path(), k, attempt, |_, _| found += 1)
+            .await
+            .unwrap();
+        assert_eq!(found, collected.0.count());
+        collected
+    }
+
+    #[tokio::test]
+    async fn test_collect() {
+        let diverged = || AiCriticError::MaxProposalsExceeded { proposals: 5 };
+        let (archive, result) = collect_from(
+            2,
+            vec![
+                Ok("fn f() {}"),
+                Err(diverged()),
+                Ok("fn f() {\n}\n"),
+                Ok("fn g() {}"),
+            ],
+        )
+        .await;
+        assert_eq!((archive.count(), archive.attempts), (2, 4));
+        assert_eq!(result.unwrap(), 2);
+
+        // The attempts run out with one of the two solutions.
+        let (archive, result) = collect_from(
+            2,
+            vec![Ok("fn f() {}"), Err(diverged()), Ok("fn f() {}")]
+                .into_iter()
+                .chain((0..3).map(|_| Err(diverged())))
+                .collect(),
+        )
+        .await;
+        assert_eq!((archive.count(), archive.attempts), (1, 6));
+        assert_eq!(result.unwrap(), 1);
+
+        let (archive, result) = collect_from(1, (0..3).map(|_| Err(diverged())).collect()).await;
+        assert_eq!((archive.count(), archive.attempts), (0, 3));
+        assert!(result
+            .unwrap_err()
+            .downcast_ref::<AiCriticError>()
+            .unwrap()
+            .is_divergence());
+
+        let (archive, result) =
+            collect_from(3, vec![Ok("fn f() {}"), Err(AiCriticError::Cancelled)]).await;
+        assert_eq!(archive.count(), 1);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AiCriticError>(),
+            Some(AiCriticError::Cancelled)
+        ));
+    }
+}
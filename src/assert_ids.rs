@@ -0,0 +1,135 @@
+use crate::fixer::{ReviewNeeded, ReviewType};
+use regex::Regex;
+use std::collections::HashSet;
+
+// The coder prompt asks for each assert to carry a unique 6-digit hex `assert_id` in its message, so
+// that a failed assertion can be found in the code from the test output alone. With
+// --test-fix-window, a test failure is fixed by sending the fixer only the lines around the failed
+// assertion instead of the whole file, and the lines it returns are spliced back in. The bug may lie
+// outside those lines, in the code under test, so each assertion gets one such fix. If it fails
+// again, the fixer gets the whole file.
+
+// A range of the code's lines, from `start` up to but not including `end`, counting from 0.
+#[derive(Debug, PartialEq)]
+pub struct Window {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Window {
+    pub fn text(&self, code: &str) -> String {
+        let lines: Vec<&str> = code
+            .lines()
+            .skip(self.start)
+            .take(self.end - self.start)
+            .collect();
+        format!("{}\n", lines.join("\n"))
+    }
+
+    // Replace the window's lines of the code with the given ones.
+    pub fn splice(&self, code: &str, replacement: &str) -> String {
+        let lines: Vec<&str> = code.lines().collect();
+        let mut spliced: Vec<&str> = lines[..self.start].to_vec();
+        spliced.extend(replacement.lines());
+        spliced.extend(&lines[self.end..]);
+        format!("{}\n", spliced.join("\n"))
+    }
+}
+
+// The assert IDs in the test output, in the order that they appear.
+pub fn failed_ids(output: &str) -> Vec<String> {
+    let re = Regex::new(r"(?i)assert_id\W{0,4}(?:0x)?([0-9a-f]{6})\b").unwrap();
+    let mut ids: Vec<String> = vec![];
+    for captures in re.captures_iter(output) {
+        let id = captures[1].to_lowercase();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+// The lines within `radius` of the one line of the code that holds the assert ID. An ID that's
+// missing or used more than once can't be located.
+pub fn window(code: &str, id: &str, radius: usize) -> Option<Window> {
+    let lines: Vec<&str> = code.lines().collect();
+    let mut matches = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.to_lowercase().contains(id));
+    let (line, _) = matches.next()?;
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(Window {
+        start: line.saturating_sub(radius),
+        end: (line + radius + 1).min(lines.len()),
+    })
+}
+
+// Tracks the assertions that have had a windowed fix over a run.
+pub struct TestFixWindows {
+    radius: Option<usize>,
+    used: HashSet<String>,
+}
+
+impl TestFixWindows {
+    pub fn new(radius: Option<usize>) -> Self {
+        TestFixWindows {
+            radius,
+            used: HashSet::new(),
+        }
+    }
+
+    // The window to send the fixer for the review, if it's a test failure and the first failed
+    // assertion can be located and hasn't had a windowed fix yet. Return it with the assert ID.
+    pub fn next(&mut self, code: &str, review: &ReviewNeeded) -> Option<(String, Window)> {
+        let radius = self.radius?;
+        if review.review_type != ReviewType::TestFix {
+            return None;
+        }
+        let id = failed_ids(&review.comments.join("\n")).into_iter().next()?;
+        let window = window(code, &id, radius)?;
+        match self.used.insert(id.clone()) {
+            true => Some((id, window)),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "fn double(x: i32) -> i32 {\n    x * 3\n}\n\n#[cfg(test)]\nmod tests {\n    \
+                        use super::*;\n    #[test]\n    fn test_double() {\n        assert_eq!(double(2), \
+                        4, \"assert_id: 1A2B3C\");\n    }\n}\n";
+
+    #[test]
+    fn test_window() {
+        let output = "thread 'tests::test_double' panicked at code.rs:10:9:\n\
+                      assertion `left == right` failed: assert_id: 1A2B3C\n  left: 6\n right: 4\n\
+                      assert_id=0xffee00 assert_id: 1a2b3c";
+        assert_eq!(failed_ids(output), ["1a2b3c", "ffee00"]);
+
+        let window = window(CODE, "1a2b3c", 1).unwrap();
+        assert_eq!(window, Window { start: 8, end: 11 });
+        assert!(window.text(CODE).starts_with("    fn test_double() {\n"));
+        let fixed = window.splice(
+            CODE,
+            "    fn test_double() {\n        assert_eq!(double(2), 6);\n    }\n",
+        );
+        assert!(fixed.contains("assert_eq!(double(2), 6);\n    }\n}\n"));
+        assert_eq!(fixed.lines().count(), CODE.lines().count());
+        assert!(super::window(CODE, "ffee00", 1).is_none());
+
+        let review = ReviewNeeded::new(ReviewType::TestFix, vec![output.to_string()]);
+        let mut windows = TestFixWindows::new(Some(2));
+        assert_eq!(
+            windows.next(CODE, &review).unwrap().1,
+            Window { start: 7, end: 12 }
+        );
+        assert!(windows.next(CODE, &review).is_none());
+        assert!(TestFixWindows::new(None).next(CODE, &review).is_none());
+    }
+}
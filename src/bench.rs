@@ -0,0 +1,485 @@
+use crate::build_service::BuildService;
+use crate::differential::{self, Comparison};
+use crate::events::EventBus;
+use crate::output::{self, Verbosity};
+use crate::provider::Provider;
+use crate::steering::Steering;
+use crate::tester::{TesterAgent, TesterResult};
+use crate::{code_policy, create_tester, errors::AiCriticError, run_loop, Args};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// The bench subcommand measures the pipeline on a benchmark of problems with hidden tests, in the
+// style of HumanEval and MBPP. Each problem is solved `samples` times, and a sample passes if the
+// solution the critics accept also passes the hidden tests, which the agents never see. The results
+// are reported as pass@k, the chance that at least one of k samples passes.
+//
+// The problems are read from JSON lines in either format, with the tests written in Rust:
+//   HumanEval: {"task_id": "...", "prompt": "<signature>", "test": "<tests>", "entry_point": "..."}
+//   MBPP:      {"task_id": 1, "text": "<description>", "test_list": ["assert_eq!(...);", ...]}
+// The tests are either statements, such as asserts, or #[test] functions.
+//
+// A problem may also give a reference solution, as HumanEval's `canonical_solution`, which
+// completes its prompt, or MBPP's `code`. The reference is checked against the hidden tests once,
+// and each solution that the critics accept is compared with it on generated inputs; see
+// differential.rs. A solution that behaves differently is reported as a finding even if it passed
+// the hidden tests. A reference that fails the hidden tests isn't compared with.
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchArgs {
+    /// JSONL file of HumanEval- or MBPP-style problems with Rust tests.
+    pub file: PathBuf,
+
+    /// Number of times to solve each problem.
+    #[arg(long, default_value_t = 1)]
+    pub samples: usize,
+
+    /// The k values of the pass@k statistics to report, e.g. --k 1,5.
+    #[arg(long, value_delimiter = ',', default_value = "1")]
+    pub k: Vec<usize>,
+
+    /// Number of runs to execute concurrently.
+    #[arg(long, default_value_t = 4)]
+    pub jobs: usize,
+
+    /// Only run the first N problems.
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Write the results to this file as JSON.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+// A benchmark problem: the prompt given to the agents and the tests kept from them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProblemSpec {
+    pub id: String,
+    pub prompt: String,
+    pub hidden_tests: String,
+    // The reference solution, if the problem gives one, and the name of its function to compare.
+    pub reference: Option<String>,
+    pub entry_point: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BenchEntry {
+    Mbpp {
+        task_id: Value,
+        text: String,
+        test_list: Vec<String>,
+        #[serde(default)]
+        test_setup_code: String,
+        code: Option<String>,
+    },
+    HumanEval {
+        task_id: Value,
+        prompt: String,
+        test: String,
+        entry_point: Option<String>,
+        canonical_solution: Option<String>,
+    },
+}
+
+fn task_id(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+impl From<BenchEntry> for ProblemSpec {
+    fn from(entry: BenchEntry) -> Self {
+        match entry {
+            BenchEntry::Mbpp {
+                task_id: id,
+                text,
+                test_list,
+                test_setup_code,
+                code,
+            } => {
+                // As in MBPP's own prompts, one test shows the expected function signature.
+                let example = test_list.first().cloned().unwrap_or_default();
+                ProblemSpec {
+                    id: task_id(id),
+                    prompt: format!(
+                        "{}\n\nThe code must pass tests like this one:\n{}",
+                        text, example
+                    ),
+                    hidden_tests: format!("{}\n{}", test_setup_code, test_list.join("\n")),
+                    reference: code,
+                    entry_point: None,
+                }
+            }
+            BenchEntry::HumanEval {
+                task_id: id,
+                prompt,
+                test,
+                entry_point,
+                canonical_solution,
+            } => {
+                let mut full_prompt = format!(
+                    "Complete this Rust function, keeping its signature:\n\n{}",
+                    prompt
+                );
+                let reference =
+                    canonical_solution.map(|solution| format!("{}{}", prompt, solution));
+                if let Some(entry_point) = &entry_point {
+                    full_prompt.push_str(&format!(
+                        "\n\nThe function must be named `{}`.",
+                        entry_point
+                    ));
+                }
+                ProblemSpec {
+                    id: task_id(id),
+                    prompt: full_prompt,
+                    hidden_tests: test,
+                    reference,
+                    entry_point,
+                }
+            }
+        }
+    }
+}
+
+impl ProblemSpec {
+    // Add the hidden tests to the solution as their own test module.
+    pub fn with_hidden_tests(&self, code: &str) -> String {
+        let tests = if self.hidden_tests.contains("#[test]") {
+            self.hidden_tests.clone()
+        } else {
+            format!("#[test]\nfn hidden_test() {{\n{}\n}}", self.hidden_tests)
+        };
+        format!(
+            "{}\n\n#[cfg(test)]\nmod hidden_tests {{\nuse super::*;\n\n{}\n}}\n",
+            code, tests
+        )
+    }
+}
+
+pub fn parse_problems(jsonl: &str) -> Result<Vec<ProblemSpec>> {
+    jsonl
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let entry: BenchEntry = serde_json::from_str(line)?;
+            Ok(entry.into())
+        })
+        .collect()
+}
+
+// The unbiased estimator of pass@k from the HumanEval paper: the probability that at least one of k
+// samples drawn without replacement from n samples, of which c passed, passes.
+pub fn pass_at_k(n: usize, c: usize, k: usize) -> f64 {
+    if n - c < k {
+        return 1.0;
+    }
+    1.0 - ((n - c + 1)..=n)
+        .map(|i| 1.0 - k as f64 / i as f64)
+        .product::<f64>()
+}
+
+#[derive(Serialize)]
+struct ProblemResult {
+    id: String,
+    samples: usize,
+    passed: usize,
+    // Whether the reference passes the hidden tests, if the problem gives one.
+    reference_passed: Option<bool>,
+    // The samples whose solution behaved differently from the reference, and how.
+    diverged: usize,
+    findings: Vec<String>,
+}
+
+// What became of a sample: whether its solution passed the hidden tests, and how it compared with
+// the reference, if the problem gives one.
+struct Sample {
+    passed: bool,
+    comparison: Option<Comparison>,
+}
+
+#[derive(Serialize)]
+struct BenchReport {
+    problems: Vec<ProblemResult>,
+    pass_at_k: BTreeMap<usize, f64>,
+}
+
+// The tester that runs the hidden tests.
+fn hidden_tester(args: &Args) -> TesterAgent {
+    TesterAgent::new(1)
+        .with_sandbox(args.sandbox)
+        .with_policy(code_policy(args))
+        .with_test_seed(args.test_seed)
+        .with_max_output(args.max_test_output)
+        .with_test_timeout(args.test_timeout)
+}
+
+// Whether the code passes the problem's hidden tests.
+async fn passes_hidden_tests(args: &Args, spec: &ProblemSpec, code: &str) -> Result<bool> {
+    let code = spec.with_hidden_tests(code);
+    match hidden_tester(args).compile_and_test(&code).await? {
+        TesterResult::Success { .. } => Ok(true),
+        TesterResult::Failure { .. } => Ok(false),
+    }
+}
+
+// Compare the solution with the reference on generated inputs.
+async fn compare_with_reference(
+    args: &Args,
+    spec: &ProblemSpec,
+    reference: &str,
+    code: &str,
+) -> Result<Comparison> {
+    let build = BuildService::default()
+        .with_sandbox(args.sandbox)
+        .with_test_seed(args.test_seed)
+        .with_max_output(args.max_test_output)
+        .with_test_timeout(args.test_timeout);
+    let dir = TempDir::new()?;
+    differential::compare(
+        &build,
+        dir.path(),
+        reference,
+        code,
+        spec.entry_point.as_deref(),
+        &spec.hidden_tests,
+    )
+    .await
+}
+
+// Solve the problem once and check the solution against the hidden tests and the reference.
+async fn run_sample(args: &Args, provider: &Provider, spec: &ProblemSpec) -> Result<Sample> {
+    // The concurrent runs aren't displayed, so their events go nowhere.
+    let tester = create_tester(args, &spec.prompt, None).await?;
+    let dispatcher = EventBus::start(vec![], Steering::default());
+    let result = run_loop(dispatcher.bus(), args, provider, &tester, &spec.prompt).await;
+    dispatcher.close().await;
+    let solution = match result {
+        Ok(solution) => solution,
+        Err(e) => match e.downcast_ref::<AiCriticError>() {
+            Some(e) if e.is_divergence() => {
+                return Ok(Sample {
+                    passed: false,
+                    comparison: None,
+                })
+            }
+            _ => return Err(e),
+        },
+    };
+    let code = &solution.code.code;
+    let comparison = match &spec.reference {
+        Some(reference) => Some(compare_with_reference(args, spec, reference, code).await?),
+        None => None,
+    };
+    Ok(Sample {
+        passed: passes_hidden_tests(args, spec, code).await?,
+        comparison,
+    })
+}
+
+fn load_problems(path: &Path, limit: Option<usize>) -> Result<Vec<ProblemSpec>> {
+    let mut problems = parse_problems(&fs::read_to_string(path)?)?;
+    if let Some(limit) = limit {
+        problems.truncate(limit);
+    }
+    Ok(problems)
+}
+
+// Run the benchmark, at most `jobs` runs at a time, and report pass@k.
+pub async fn run(args: &Args, bench: &BenchArgs, provider: &Provider) -> Result<()> {
+    let problems = load_problems(&bench.file, bench.limit)?;
+    output::print(
+        Verbosity::Normal,
+        &format!(
+            "[bench] Running {} problems, {} samples each, {} at a time.",
+            problems.len(),
+            bench.samples,
+            bench.jobs
+        ),
+    );
+
+    let mut reference_passed = vec![];
+    for spec in problems.iter() {
+        let passed = match &spec.reference {
+            Some(reference) => Some(passes_hidden_tests(args, spec, reference).await?),
+            None => None,
+        };
+        if passed == Some(false) {
+            output::print(
+                Verbosity::Quiet,
+                &format!(
+                    "[bench] {}: the reference fails the hidden tests, so it isn't compared with.",
+                    spec.id
+                ),
+            );
+        }
+        reference_passed.push(passed);
+    }
+
+    let args = Arc::new(args.clone());
+    let provider = Arc::new(provider.clone());
+    let semaphore = Arc::new(Semaphore::new(bench.jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, spec) in problems.iter().enumerate() {
+        for _ in 0..bench.samples {
+            let (args, provider, semaphore) = (args.clone(), provider.clone(), semaphore.clone());
+            let mut spec = spec.clone();
+            if reference_passed[index] == Some(false) {
+                spec.reference = None;
+            }
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                (index, run_sample(&args, &provider, &spec).await)
+            });
+        }
+    }
+
+    let mut passed = vec![0; problems.len()];
+    let mut diverged = vec![0; problems.len()];
+    let mut findings: Vec<Vec<String>> = vec![vec![]; problems.len()];
+    let mut finished = vec![0; problems.len()];
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = joined?;
+        finished[index] += 1;
+        match result {
+            Ok(sample) => {
+                passed[index] += usize::from(sample.passed);
+                match sample.comparison {
+                    Some(Comparison::Diverged { divergences }) => {
+                        output::print(
+                            Verbosity::Normal,
+                            &format!(
+                                "[bench] {}: the solution diverges from the reference: {}",
+                                problems[index].id, divergences[0]
+                            ),
+                        );
+                        diverged[index] += 1;
+                        findings[index].extend(divergences);
+                    }
+                    Some(Comparison::Skipped { reason }) => output::print(
+                        Verbosity::Verbose,
+                        &format!(
+                            "[bench] {}: not compared with the reference: {}",
+                            problems[index].id, reason
+                        ),
+                    ),
+                    Some(Comparison::Agreed { .. }) | None => {}
+                }
+            }
+            // A failed run counts as a failed sample.
+            Err(e) => output::print(
+                Verbosity::Quiet,
+                &format!("[bench] {}: the run failed: {}", problems[index].id, e),
+            ),
+        }
+        if finished[index] == bench.samples {
+            output::print(
+                Verbosity::Normal,
+                &format!(
+                    "[bench] {}: {}/{} samples passed.",
+                    problems[index].id, passed[index], bench.samples
+                ),
+            );
+        }
+    }
+
+    let mut report = BenchReport {
+        problems: problems
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| ProblemResult {
+                id: spec.id.clone(),
+                samples: bench.samples,
+                passed: passed[i],
+                reference_passed: reference_passed[i],
+                diverged: diverged[i],
+                findings: findings[i].clone(),
+            })
+            .collect(),
+        pass_at_k: BTreeMap::new(),
+    };
+    for &k in bench.k.iter() {
+        if k == 0 || k > bench.samples {
+            output::print(
+                Verbosity::Normal,
+                &format!(
+                    "[bench] Skipping pass@{}: it needs 1 to {} samples.",
+                    k, bench.samples
+                ),
+            );
+            continue;
+        }
+        let score = passed
+            .iter()
+            .map(|&c| pass_at_k(bench.samples, c, k))
+            .sum::<f64>()
+            / problems.len().max(1) as f64;
+        output::print(
+            Verbosity::Quiet,
+            &format!("[bench] pass@{}: {:.1}%", k, score * 100.0),
+        );
+        report.pass_at_k.insert(k, score);
+    }
+    if let Some(path) = &bench.output {
+        serde_json::to_writer_pretty(File::create(path)?, &report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_problems() {
+        let jsonl = r#"
+{"task_id": "HumanEval/0", "prompt": "fn add(a: i32, b: i32) -> i32 {", "test": "assert_eq!(add(1, 2), 3);", "entry_point": "add", "canonical_solution": "\n    a + b\n}\n"}
+{"task_id": 11, "text": "Write a function to double a number.", "test_list": ["assert_eq!(double(2), 4);", "assert_eq!(double(0), 0);"], "code": "fn double(x: i32) -> i32 { 2 * x }"}
+{"task_id": 12, "text": "Write a function to negate a number.", "test_list": ["assert_eq!(negate(2), -2);"]}
+"#;
+        let problems = parse_problems(jsonl).unwrap();
+        assert_eq!(problems.len(), 3);
+        assert_eq!(problems[0].id, "HumanEval/0");
+        assert!(problems[0]
+            .prompt
+            .contains("fn add(a: i32, b: i32) -> i32 {"));
+        assert!(problems[0].prompt.contains("named `add`"));
+        assert_eq!(problems[1].id, "11");
+        assert!(problems[1].prompt.contains("assert_eq!(double(2), 4);"));
+        assert!(!problems[1].prompt.contains("double(0)"));
+        assert!(problems[1].hidden_tests.contains("double(0)"));
+        assert_eq!(
+            problems[0].reference.as_deref(),
+            Some("fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n")
+        );
+        assert_eq!(problems[0].entry_point.as_deref(), Some("add"));
+        assert_eq!(
+            problems[1].reference.as_deref(),
+            Some("fn double(x: i32) -> i32 { 2 * x }")
+        );
+        assert_eq!(problems[2].reference, None);
+
+        let code = problems[0].with_hidden_tests("fn add(a: i32, b: i32) -> i32 { a + b }");
+        assert!(code.contains("mod hidden_tests {\nuse super::*;"));
+        assert!(code.contains("fn hidden_test() {\nassert_eq!(add(1, 2), 3);\n}"));
+    }
+
+    #[test]
+    fn test_pass_at_k() {
+        assert_eq!(pass_at_k(5, 0, 1), 0.0);
+        assert_eq!(pass_at_k(5, 5, 1), 1.0);
+        assert!((pass_at_k(5, 2, 1) - 0.4).abs() < 1e-9);
+        // 1 - C(3, 2) / C(5, 2) = 1 - 3 / 10.
+        assert!((pass_at_k(5, 2, 2) - 0.7).abs() < 1e-9);
+        assert_eq!(pass_at_k(5, 4, 2), 1.0);
+    }
+}
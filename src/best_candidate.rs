@@ -0,0 +1,183 @@
+use crate::events::RunEvent;
+use crate::fixer::{ReviewNeeded, ReviewType};
+use crate::tester::failed_tests;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::sync::OnceLock;
+
+// A run that ends without passing tests, whether it ran out of proposals or was stopped early,
+// still leaves its best proposal behind, written to best_candidate.rs in the run's directory with a
+// summary of its failure. A proposal that compiled is better than one that didn't, and of those that
+// compiled, the one with the fewest failing tests is best. Of two that are alike, the later one is,
+// since it's had more fixes.
+
+struct Candidate {
+    proposal: usize,
+    code: String,
+    compiled: bool,
+    // The number of failed tests, if the test harness reported them.
+    failed: Option<usize>,
+    summary: String,
+}
+
+impl Candidate {
+    fn new(proposal: usize, code: &str, failure: &ReviewNeeded) -> Self {
+        let output = failure.comments.join("\n");
+        let compiled = matches!(
+            failure.review_type,
+            ReviewType::TestFix
+                | ReviewType::FlakyTestFix
+                | ReviewType::RuntimeFix
+                | ReviewType::ComplexityFix
+        );
+        let counts = compiled.then(|| test_counts(&output)).flatten();
+        let summary = match (failure.review_type, counts) {
+            (ReviewType::CompilerFix, _) => match first_error(&output) {
+                Some(error) => format!("it doesn't compile: {}", error),
+                None => "it doesn't compile".to_string(),
+            },
+            (ReviewType::PolicyFix, _) => "it uses APIs that the policy denies".to_string(),
+            (ReviewType::FlakyTestFix, _) => {
+                "its tests pass in some runs and fail in others".to_string()
+            }
+            (ReviewType::ComplexityFix, _) => {
+                "its tests pass, but it's slower than the problem allows".to_string()
+            }
+            (_, Some((passed, failed))) => format!(
+                "{} of its {} tests fail: {}",
+                failed,
+                passed + failed,
+                failed_tests(&output).join(", ")
+            ),
+            _ => format!(
+                "it compiles, but fails: {}",
+                output.lines().next().unwrap_or_default().trim()
+            ),
+        };
+        Candidate {
+            proposal,
+            code: code.to_string(),
+            compiled,
+            failed: counts.map(|(_, failed)| failed),
+            summary,
+        }
+    }
+
+    fn rank(&self) -> (bool, Reverse<usize>) {
+        (self.compiled, Reverse(self.failed.unwrap_or(usize::MAX)))
+    }
+}
+
+// The numbers of passed and failed tests in the test harness's summary line.
+fn test_counts(output: &str) -> Option<(usize, usize)> {
+    let captures = summary_line().captures(output)?;
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?))
+}
+
+// The test harness's summary line of a failed run.
+fn summary_line() -> &'static Regex {
+    static SUMMARY_LINE: OnceLock<Regex> = OnceLock::new();
+    SUMMARY_LINE
+        .get_or_init(|| Regex::new(r"test result: FAILED\. (\d+) passed; (\d+) failed").unwrap())
+}
+
+fn first_error(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("error"))
+}
+
+#[derive(Default)]
+pub struct BestCandidate {
+    best: Option<Candidate>,
+}
+
+impl BestCandidate {
+    // Record a proposal that failed the tester.
+    pub fn record(&mut self, proposal: usize, code: &str, failure: &ReviewNeeded) {
+        let candidate = Candidate::new(proposal, code, failure);
+        if self
+            .best
+            .as_ref()
+            .is_some_and(|best| best.rank() > candidate.rank())
+        {
+            return;
+        }
+        self.best = Some(candidate);
+    }
+
+    // The event that reports the best proposal, if any were tested.
+    pub fn event(&self) -> Option<RunEvent> {
+        self.best.as_ref().map(|best| RunEvent::BestCandidate {
+            proposal: best.proposal,
+            code: best.code.clone(),
+            summary: best.summary.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(review_type: ReviewType, output: &str) -> ReviewNeeded {
+        ReviewNeeded::new(review_type, vec![output.to_string()])
+    }
+
+    #[test]
+    fn test_best_candidate() {
+        let two_failed = "running 3 tests\ntest tests::test_a ... ok\ntest tests::test_b ... \
+                          FAILED\ntest tests::test_c ... FAILED\n\ntest result: FAILED. 1 passed; \
+                          2 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s\n";
+        let one_failed = "running 3 tests\ntest tests::test_c ... FAILED\n\ntest result: FAILED. \
+                          2 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out\n";
+        let mut best = BestCandidate::default();
+        assert!(best.event().is_none());
+        best.record(
+            1,
+            "fn a(",
+            &failure(
+                ReviewType::CompilerFix,
+                "\nerror: expected one of `)`, `,`, or `:`, found `{`\n --> code.rs:1:6\n",
+            ),
+        );
+        assert_eq!(
+            best.event(),
+            Some(RunEvent::BestCandidate {
+                proposal: 1,
+                code: "fn a(".to_string(),
+                summary: "it doesn't compile: error: expected one of `)`, `,`, or `:`, found `{`"
+                    .to_string(),
+            })
+        );
+        best.record(2, "fn b() {}", &failure(ReviewType::TestFix, one_failed));
+        best.record(3, "fn c() {}", &failure(ReviewType::TestFix, two_failed));
+        best.record(
+            4,
+            "fn d(",
+            &failure(ReviewType::CompilerFix, "error[E0308]"),
+        );
+        assert_eq!(
+            best.event(),
+            Some(RunEvent::BestCandidate {
+                proposal: 2,
+                code: "fn b() {}".to_string(),
+                summary: "1 of its 3 tests fail: tests::test_c".to_string(),
+            })
+        );
+        best.record(5, "fn e() {}", &failure(ReviewType::TestFix, one_failed));
+        assert!(matches!(
+            best.event(),
+            Some(RunEvent::BestCandidate { proposal: 5, .. })
+        ));
+
+        let mut best = BestCandidate::default();
+        best.record(1, "fn a() {}", &failure(ReviewType::TestFix, two_failed));
+        assert!(matches!(
+            best.event(),
+            Some(RunEvent::BestCandidate { summary, .. })
+                if summary == "2 of its 3 tests fail: tests::test_b, tests::test_c"
+        ));
+    }
+}
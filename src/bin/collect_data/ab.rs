@@ -0,0 +1,327 @@
+use crate::sweep::{Cell, Sweep};
+use crate::{write_csv_header, DataCollector};
+use ai_critics::stats::{self, Summary};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+// An A/B experiment runs the same problems with two variants, which differ in their prompt bundles
+// (see --prompts-dir), their models, or both, and compares them: the success rate, the iterations
+// of the successful runs, and the cost of each run. Each problem's runs with A are followed by its
+// runs with B, so that a cell is done, and can be resumed from, as soon as its runs finish. A
+// change in the API's behavior during the batch can therefore affect the variants unequally, so a
+// difference is best confirmed by a second batch. Runs that ended in an error say nothing about the
+// variant and are left out of the success rates.
+
+const OUTPUT_FILENAME: &str = "ab_data.csv";
+const RUNS_FILENAME: &str = "ab_runs.jsonl";
+const COMPARISON_FILENAME: &str = "ab_comparison.json";
+const NUM_CRITICS: usize = 3;
+const NUM_ITERATIONS: usize = 5;
+// Differences with p-values below this are marked as significant.
+const SIGNIFICANCE: f64 = 0.05;
+
+#[derive(clap::Args, Debug)]
+pub struct AbArgs {
+    /// Directory of prompt templates for variant A. Defaults to the built-in prompts.
+    #[arg(long)]
+    a_prompts: Option<String>,
+
+    /// Directory of prompt templates for variant B. Defaults to the built-in prompts.
+    #[arg(long)]
+    b_prompts: Option<String>,
+
+    /// Model for variant A. Defaults to the provider's model.
+    #[arg(long)]
+    a_model: Option<String>,
+
+    /// Model for variant B. Defaults to the provider's model.
+    #[arg(long)]
+    b_model: Option<String>,
+
+    /// The problems to run, e.g. 1,3,4. Defaults to all of them.
+    #[arg(long, value_delimiter = ',')]
+    problems: Vec<usize>,
+
+    /// Number of critics in every run.
+    #[arg(long, default_value_t = NUM_CRITICS)]
+    num_critics: usize,
+
+    /// Number of runs of each problem by each variant.
+    #[arg(long, default_value_t = NUM_ITERATIONS)]
+    iterations: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Variant {
+    model: Option<String>,
+    prompts_dir: Option<String>,
+}
+
+// The fields of a run in the runs log that the comparison needs.
+#[derive(Deserialize)]
+struct Run {
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    prompts_dir: Option<String>,
+    iterations: usize,
+    #[serde(default)]
+    cost_usd: f64,
+}
+
+#[derive(Serialize, Debug)]
+struct VariantStats {
+    model: Option<String>,
+    prompts_dir: Option<String>,
+    // The runs that didn't end in an error.
+    runs: usize,
+    errors: usize,
+    successes: usize,
+    success_rate: f64,
+    // The 95% Wilson interval of the success rate.
+    success_rate_ci: (f64, f64),
+    // The iterations of the successful runs.
+    iterations: Option<Summary>,
+    // The cost of each run, whatever its outcome.
+    cost_usd: Option<Summary>,
+}
+
+#[derive(Serialize, Debug)]
+struct Comparison {
+    a: VariantStats,
+    b: VariantStats,
+    // The two-sided p-values of the differences between the variants.
+    success_rate_p: f64,
+    iterations_p: f64,
+    cost_usd_p: f64,
+}
+
+pub fn run(args: &AbArgs, data_collector: DataCollector) -> io::Result<()> {
+    let a = Variant {
+        model: args.a_model.clone(),
+        prompts_dir: args.a_prompts.clone(),
+    };
+    let b = Variant {
+        model: args.b_model.clone(),
+        prompts_dir: args.b_prompts.clone(),
+    };
+    if a == b {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the variants are the same; give them different --a-prompts/--b-prompts or \
+             --a-model/--b-model",
+        ));
+    }
+    let problems = match args.problems.is_empty() {
+        true => Sweep::default().problems,
+        false => args.problems.clone(),
+    };
+    let cells = cells(&problems, args.num_critics, &a, &b);
+
+    let data_collector = data_collector.with_runs_log(Box::new(File::create(RUNS_FILENAME)?));
+    let mut file = File::create(OUTPUT_FILENAME)?;
+    write_csv_header(&mut file)?;
+    println!(
+        "[collect_data] Comparing 2 variants on {} problems, {} iterations each...",
+        problems.len(),
+        args.iterations
+    );
+    data_collector.process_cells(&cells, args.iterations, &mut file)?;
+
+    let runs = read_runs(Path::new(RUNS_FILENAME))?;
+    let comparison = compare(&runs, &a, &b);
+    print_comparison(&comparison);
+    fs::write(
+        COMPARISON_FILENAME,
+        serde_json::to_string_pretty(&comparison)?,
+    )?;
+    println!(
+        "[collect_data] Wrote the comparison to {}.",
+        COMPARISON_FILENAME
+    );
+    Ok(())
+}
+
+// Each problem's cell for A followed by its cell for B.
+fn cells(problems: &[usize], num_critics: usize, a: &Variant, b: &Variant) -> Vec<Cell> {
+    problems
+        .iter()
+        .flat_map(|&problem| {
+            [a, b].map(|variant| Cell {
+                problem,
+                num_critics,
+                model: variant.model.clone(),
+                prompts_dir: variant.prompts_dir.clone(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+fn read_runs(path: &Path) -> io::Result<Vec<Run>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+fn compare(runs: &[Run], a: &Variant, b: &Variant) -> Comparison {
+    let of = |variant: &Variant| -> Vec<&Run> {
+        runs.iter()
+            .filter(|run| run.model == variant.model && run.prompts_dir == variant.prompts_dir)
+            .collect()
+    };
+    let (runs_a, runs_b) = (of(a), of(b));
+    let a_stats = variant_stats(a, &runs_a);
+    let b_stats = variant_stats(b, &runs_b);
+    Comparison {
+        success_rate_p: stats::proportion_p_value(
+            a_stats.successes,
+            a_stats.runs,
+            b_stats.successes,
+            b_stats.runs,
+        ),
+        iterations_p: stats::permutation_p_value(
+            &successful_iterations(&runs_a),
+            &successful_iterations(&runs_b),
+        ),
+        cost_usd_p: stats::permutation_p_value(&costs(&runs_a), &costs(&runs_b)),
+        a: a_stats,
+        b: b_stats,
+    }
+}
+
+fn variant_stats(variant: &Variant, runs: &[&Run]) -> VariantStats {
+    let errors = runs.iter().filter(|run| run.iterations == 0).count();
+    let iterations = successful_iterations(runs);
+    let completed = runs.len() - errors;
+    VariantStats {
+        model: variant.model.clone(),
+        prompts_dir: variant.prompts_dir.clone(),
+        runs: completed,
+        errors,
+        successes: iterations.len(),
+        success_rate: match completed {
+            0 => 0.0,
+            n => iterations.len() as f64 / n as f64,
+        },
+        success_rate_ci: stats::wilson_interval(iterations.len(), completed),
+        iterations: stats::summarize(&iterations),
+        cost_usd: stats::summarize(&costs(runs)),
+    }
+}
+
+// The iterations of the runs that found a solution, leaving out errors (0) and divergences (255).
+fn successful_iterations(runs: &[&Run]) -> Vec<f64> {
+    runs.iter()
+        .filter(|run| run.iterations != 0 && run.iterations != 255)
+        .map(|run| run.iterations as f64)
+        .collect()
+}
+
+fn costs(runs: &[&Run]) -> Vec<f64> {
+    runs.iter().map(|run| run.cost_usd).collect()
+}
+
+fn print_comparison(comparison: &Comparison) {
+    let (a, b) = (&comparison.a, &comparison.b);
+    let name = |stats: &VariantStats| {
+        format!(
+            "model {}, prompts {}",
+            stats.model.as_deref().unwrap_or("default"),
+            stats.prompts_dir.as_deref().unwrap_or("default")
+        )
+    };
+    println!("A: {}", name(a));
+    println!("B: {}", name(b));
+    println!("Metric            A                       B                       p");
+    let row = |metric: &str, a: String, b: String, p: f64| {
+        let mark = match p < SIGNIFICANCE {
+            true => " *",
+            false => "",
+        };
+        println!("{:<16}  {:<22}  {:<22}  {:.3}{}", metric, a, b, p, mark);
+    };
+    let rate = |s: &VariantStats| {
+        format!(
+            "{:.2} [{:.2}, {:.2}]",
+            s.success_rate, s.success_rate_ci.0, s.success_rate_ci.1
+        )
+    };
+    let mean = |summary: &Option<Summary>, precision: usize| match summary {
+        Some(s) => format!(
+            "{:.p$} [{:.p$}, {:.p$}]",
+            s.mean,
+            s.ci.0,
+            s.ci.1,
+            p = precision
+        ),
+        None => "-".to_string(),
+    };
+    row("Success rate", rate(a), rate(b), comparison.success_rate_p);
+    row(
+        "Iterations",
+        mean(&a.iterations, 2),
+        mean(&b.iterations, 2),
+        comparison.iterations_p,
+    );
+    row(
+        "Cost per run ($)",
+        mean(&a.cost_usd, 4),
+        mean(&b.cost_usd, 4),
+        comparison.cost_usd_p,
+    );
+    println!(
+        "Runs: A {} ({} errors), B {} ({} errors). * p < {}.",
+        a.runs, a.errors, b.runs, b.errors, SIGNIFICANCE
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(prompts_dir: Option<&str>, iterations: usize, cost_usd: f64) -> Run {
+        Run {
+            model: None,
+            prompts_dir: prompts_dir.map(str::to_string),
+            iterations,
+            cost_usd,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = Variant {
+            model: None,
+            prompts_dir: None,
+        };
+        let b = Variant {
+            model: None,
+            prompts_dir: Some("terse".to_string()),
+        };
+        let cells = cells(&[1, 2], 3, &a, &b);
+        assert_eq!(cells.len(), 4);
+        assert_eq!(cells[1].prompts_dir.as_deref(), Some("terse"));
+        assert_eq!(cells[2].problem, 2);
+
+        let mut runs = vec![run(None, 0, 0.0)];
+        for _ in 0..10 {
+            runs.push(run(None, 2, 0.01));
+            runs.push(run(Some("terse"), 255, 0.05));
+            runs.push(run(Some("terse"), 6, 0.03));
+        }
+        let comparison = compare(&runs, &a, &b);
+        assert_eq!((comparison.a.runs, comparison.a.errors), (10, 1));
+        assert_eq!(comparison.a.success_rate, 1.0);
+        assert_eq!((comparison.b.runs, comparison.b.successes), (20, 10));
+        assert_eq!(comparison.b.iterations.as_ref().unwrap().mean, 6.0);
+        assert!((comparison.b.cost_usd.as_ref().unwrap().mean - 0.04).abs() < 1e-9);
+        assert!(comparison.success_rate_p < SIGNIFICANCE);
+        assert!(comparison.iterations_p < SIGNIFICANCE);
+        assert!(comparison.cost_usd_p < SIGNIFICANCE);
+    }
+}
@@ -0,0 +1,377 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+// How much each type of critic helps, to decide whether a critic is worth its tokens. Each run's
+// events are split into iterations, one per proposal, noting which types of critic commented on the
+// proposal and whether the test outcome improved after it. The proposal that the critics review
+// isn't always tested, as the critics review before the tests by default, so the outcome before
+// the comments is that of the latest tested proposal up to it, and the outcome after them that of
+// the next tested proposal. An outcome is better if it gets further, from not compiling to failing
+// tests to passing, or has fewer compile errors or failing tests. A critic's type is the first word
+// of its name, such as Syntax for "Syntax Critic 2"; a batched critic's tokens count as Batched.
+// The events count a request's prompt in tokens but its response only in streamed chunks, which are
+// about a token each, so the two are kept apart.
+
+fn failed_tests() -> &'static Regex {
+    static FAILED: OnceLock<Regex> = OnceLock::new();
+    FAILED.get_or_init(|| Regex::new(r"test result: FAILED\. \d+ passed; (\d+) failed").unwrap())
+}
+
+fn error_line() -> &'static Regex {
+    static ERROR: OnceLock<Regex> = OnceLock::new();
+    ERROR.get_or_init(|| Regex::new(r"(?m)^error(\[E\d+\])?: ").unwrap())
+}
+
+// How a tested proposal did, from worst to best.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum TestOutcome {
+    // The number of compile errors and failing tests, negated so that fewer is better.
+    CompileError(i64),
+    TestsFailed(i64),
+    Passed,
+}
+
+impl TestOutcome {
+    fn failed(output: &str, compile_error: bool) -> Self {
+        match compile_error {
+            true => {
+                let errors = error_line()
+                    .find_iter(output)
+                    .filter(|m| !output[m.start()..].starts_with("error: could not compile"))
+                    .count();
+                TestOutcome::CompileError(-(errors.max(1) as i64))
+            }
+            false => {
+                let failed: i64 = failed_tests()
+                    .captures_iter(output)
+                    .filter_map(|c| c[1].parse::<i64>().ok())
+                    .sum();
+                TestOutcome::TestsFailed(-failed.max(1))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    Improved,
+    Unchanged,
+    Regressed,
+}
+
+// The critics' comments on one proposal and what followed them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CriticIteration {
+    pub proposal: usize,
+    // The number of corrections from each type of critic that commented.
+    pub comments: BTreeMap<String, usize>,
+    // Whether the test outcome improved, or none if there was no outcome to compare before or after.
+    pub effect: Option<Effect>,
+}
+
+// What the critics of a run did: the iterations that they commented on, and their prompt tokens and
+// response chunks by type.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CriticEffects {
+    pub iterations: Vec<CriticIteration>,
+    #[serde(default)]
+    pub prompt_tokens: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub chunks: BTreeMap<String, u64>,
+}
+
+impl CriticEffects {
+    // Read the effects from the run's events as JSON lines, skipping other lines.
+    pub fn from_json_lines(events: &[u8]) -> Self {
+        let mut effects = CriticEffects::default();
+        let mut steps: Vec<(CriticIteration, Option<TestOutcome>)> = vec![];
+        for line in events.split(|&b| b == b'\n') {
+            let Ok(event) = serde_json::from_slice::<Value>(line) else {
+                continue;
+            };
+            if let Some(critic_type) = event["agent"].as_str().and_then(critic_type) {
+                let counts = match event["type"].as_str() {
+                    Some("chat_requested") => Some((&mut effects.prompt_tokens, "prompt_tokens")),
+                    Some("chat_responded") => Some((&mut effects.chunks, "chunks")),
+                    _ => None,
+                };
+                if let Some((counts, field)) = counts {
+                    *counts.entry(critic_type).or_default() += event[field].as_u64().unwrap_or(0);
+                }
+            }
+            if let Some("proposal_created") = event["type"].as_str() {
+                let proposal = event["number"].as_u64().unwrap_or_default() as usize;
+                let iteration = CriticIteration {
+                    proposal,
+                    ..Default::default()
+                };
+                steps.push((iteration, None));
+            }
+            let Some((iteration, outcome)) = steps.last_mut() else {
+                continue;
+            };
+            match event["type"].as_str() {
+                Some("critic_finished") if event["lgtm"] == Value::Bool(false) => {
+                    let corrections = event["corrections"].as_array().map_or(0, |c| c.len());
+                    let critic = event["critic"].as_str().and_then(critic_type);
+                    if let (Some(critic), 1..) = (critic, corrections) {
+                        *iteration.comments.entry(critic).or_default() += corrections;
+                    }
+                }
+                Some("test_failed") => {
+                    let output = event["output"].as_str().unwrap_or_default();
+                    let compile_error = event["compile_error"] == Value::Bool(true);
+                    *outcome = Some(TestOutcome::failed(output, compile_error));
+                }
+                Some("test_passed") => *outcome = Some(TestOutcome::Passed),
+                _ => {}
+            }
+        }
+
+        for (i, (iteration, _)) in steps.iter().enumerate() {
+            if iteration.comments.is_empty() {
+                continue;
+            }
+            let before = steps[..=i].iter().rev().find_map(|(_, outcome)| *outcome);
+            let after = steps[i + 1..].iter().find_map(|(_, outcome)| *outcome);
+            let effect = match (before, after) {
+                (Some(before), Some(after)) if after > before => Some(Effect::Improved),
+                (Some(before), Some(after)) if after < before => Some(Effect::Regressed),
+                (Some(_), Some(_)) => Some(Effect::Unchanged),
+                _ => None,
+            };
+            effects.iterations.push(CriticIteration {
+                effect,
+                ..iteration.clone()
+            });
+        }
+        effects
+    }
+}
+
+// The type of the critic or agent with the name, if it's a critic's.
+fn critic_type(name: &str) -> Option<String> {
+    let (first, rest) = name.split_once(' ')?;
+    rest.starts_with("Critic").then(|| first.to_string())
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct TypeStats {
+    // The iterations that the type commented on, and the corrections it made in them.
+    iterations: u64,
+    comments: u64,
+    // The iterations whose effect is known, and of those, the ones that improved or regressed.
+    measured: u64,
+    improved: u64,
+    regressed: u64,
+    prompt_tokens: u64,
+    chunks: u64,
+}
+
+// The effects of the critics over a batch's runs, by critic type.
+#[derive(Default, Debug)]
+pub struct Effectiveness {
+    types: BTreeMap<String, TypeStats>,
+}
+
+#[derive(Deserialize)]
+struct LoggedRun {
+    #[serde(default)]
+    critic_effects: CriticEffects,
+}
+
+impl Effectiveness {
+    // Sum the effects of the runs in a runs log, as written by collect_data, so that a resumed
+    // batch includes the runs from before it was interrupted.
+    pub fn from_runs_log(path: &Path) -> io::Result<Self> {
+        let mut effectiveness = Effectiveness::default();
+        for line in fs::read_to_string(path)?.lines() {
+            if let Ok(run) = serde_json::from_str::<LoggedRun>(line) {
+                effectiveness.add(&run.critic_effects);
+            }
+        }
+        Ok(effectiveness)
+    }
+
+    pub fn add(&mut self, effects: &CriticEffects) {
+        for iteration in effects.iterations.iter() {
+            for (critic_type, comments) in iteration.comments.iter() {
+                let stats = self.types.entry(critic_type.clone()).or_default();
+                stats.iterations += 1;
+                stats.comments += *comments as u64;
+                if let Some(effect) = iteration.effect {
+                    stats.measured += 1;
+                    stats.improved += (effect == Effect::Improved) as u64;
+                    stats.regressed += (effect == Effect::Regressed) as u64;
+                }
+            }
+        }
+        for (critic_type, tokens) in effects.prompt_tokens.iter() {
+            self.types
+                .entry(critic_type.clone())
+                .or_default()
+                .prompt_tokens += tokens;
+        }
+        for (critic_type, chunks) in effects.chunks.iter() {
+            self.types.entry(critic_type.clone()).or_default().chunks += chunks;
+        }
+    }
+
+    pub fn write_csv<W: Write>(&self, file: &mut W) -> io::Result<()> {
+        writeln!(
+            file,
+            "CriticType,Iterations,Comments,Measured,Improved,Regressed,ImprovedRate,PromptTokens,Chunks"
+        )?;
+        for (critic_type, stats) in self.types.iter() {
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{:.3},{},{}",
+                critic_type,
+                stats.iterations,
+                stats.comments,
+                stats.measured,
+                stats.improved,
+                stats.regressed,
+                stats.improved_rate(),
+                stats.prompt_tokens,
+                stats.chunks
+            )?;
+        }
+        Ok(())
+    }
+
+    // The table for the terminal.
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:<12} {:>10} {:>9} {:>9} {:>9} {:>10} {:>13} {:>9}\n",
+            "Critic",
+            "Iterations",
+            "Comments",
+            "Measured",
+            "Improved",
+            "Regressed",
+            "PromptTokens",
+            "Chunks"
+        );
+        for (critic_type, stats) in self.types.iter() {
+            let _ = writeln!(
+                table,
+                "{:<12} {:>10} {:>9} {:>9} {:>8.0}% {:>10} {:>13} {:>9}",
+                critic_type,
+                stats.iterations,
+                stats.comments,
+                stats.measured,
+                stats.improved_rate() * 100.0,
+                stats.regressed,
+                stats.prompt_tokens,
+                stats.chunks
+            );
+        }
+        table
+    }
+}
+
+impl TypeStats {
+    // The fraction of the measured iterations that improved.
+    fn improved_rate(&self) -> f64 {
+        match self.measured {
+            0 => 0.0,
+            measured => self.improved as f64 / measured as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeSet;
+
+    fn lines(events: &[Value]) -> Vec<u8> {
+        let lines: Vec<String> = events.iter().map(|e| e.to_string()).collect();
+        lines.join("\n").into_bytes()
+    }
+
+    fn critic(name: &str, corrections: &[&str]) -> Value {
+        json!({"type": "critic_finished", "critic": name, "lgtm": corrections.is_empty(),
+               "corrections": corrections, "evidence": []})
+    }
+
+    #[test]
+    fn test_effects() {
+        let events = lines(&[
+            json!({"type": "proposal_created", "number": 1, "code": ""}),
+            json!({"type": "chat_requested", "agent": "Syntax Critic 1", "model": "gpt-4o",
+                   "messages": [], "prompt_tokens": 100}),
+            json!({"type": "chat_responded", "agent": "Syntax Critic 1", "response": "",
+                   "chunks": 20}),
+            json!({"type": "test_failed", "compile_error": true,
+                   "output": "error[E0425]: cannot find value `x`\nerror: expected `;`\n\
+                              error: could not compile `p` due to 2 previous errors"}),
+            critic("Syntax Critic 1", &["missing semicolon"]),
+            critic("Design Critic 2", &[]),
+            json!({"type": "proposal_created", "number": 2, "code": ""}),
+            critic("Design Critic 2", &["rename x", "split main"]),
+            json!({"type": "proposal_created", "number": 3, "code": ""}),
+            json!({"type": "test_failed", "compile_error": false,
+                   "output": "test result: FAILED. 2 passed; 1 failed"}),
+            json!({"type": "proposal_created", "number": 4, "code": ""}),
+            critic("Syntax Critic 1 (sample 2)", &["unused import"]),
+            json!({"type": "proposal_created", "number": 5, "code": ""}),
+        ]);
+        let effects = CriticEffects::from_json_lines(&events);
+        assert_eq!(
+            effects.prompt_tokens,
+            BTreeMap::from([("Syntax".to_string(), 100)])
+        );
+        assert_eq!(effects.chunks, BTreeMap::from([("Syntax".to_string(), 20)]));
+        let summary: Vec<_> = effects
+            .iterations
+            .iter()
+            .map(|i| (i.proposal, i.comments.keys().cloned().collect(), i.effect))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    1,
+                    BTreeSet::from(["Syntax".to_string()]),
+                    Some(Effect::Improved)
+                ),
+                (
+                    2,
+                    BTreeSet::from(["Design".to_string()]),
+                    Some(Effect::Improved)
+                ),
+                (4, BTreeSet::from(["Syntax".to_string()]), None),
+            ]
+        );
+
+        let mut effectiveness = Effectiveness::default();
+        effectiveness.add(&effects);
+        let mut csv = vec![];
+        effectiveness.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.contains("Design,1,2,1,1,0,1.000,0,0"));
+        assert!(csv.contains("Syntax,2,2,1,1,0,1.000,100,20"));
+        assert!(effectiveness.table().contains("Syntax"));
+    }
+
+    #[test]
+    fn test_outcome_order() {
+        let one_error = TestOutcome::failed("error: expected `;`", true);
+        let two_errors = TestOutcome::failed("error: a\nerror[E0308]: b", true);
+        let one_failed = TestOutcome::failed("test result: FAILED. 3 passed; 1 failed", false);
+        assert!(two_errors < one_error);
+        assert!(one_error < one_failed);
+        assert!(one_failed < TestOutcome::Passed);
+    }
+}
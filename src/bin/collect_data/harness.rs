@@ -0,0 +1,88 @@
+use crate::CommandRunner;
+use ai_critics::{exit_code, new_run_id, solve, Args, JsonLinesObserver};
+use clap::Parser;
+use std::io::{self, Write};
+use std::iter;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+// A harness makes one attempt at a problem given the ai_critics arguments. By default the runs are
+// made in this process through the ai_critics library, which avoids a `cargo run` per attempt and
+// reports why a run failed. Any CommandRunner is also a harness that runs ai_critics with cargo, as
+// a fallback and so that the tests can mock the runs.
+
+// What one attempt produced: the exit code of ai_critics, the events it wrote as JSON lines, and
+// the error that ended it, which is only known in process.
+pub struct Attempt {
+    pub code: Option<i32>,
+    pub events: Vec<u8>,
+    pub error: Option<String>,
+}
+
+pub trait Harness: Sync {
+    fn attempt(&self, args: &[String]) -> io::Result<Attempt>;
+}
+
+impl<T: CommandRunner + ?Sized> Harness for T {
+    fn attempt(&self, args: &[String]) -> io::Result<Attempt> {
+        let mut cargo_args = vec!["run".to_string(), "--".to_string()];
+        cargo_args.extend_from_slice(args);
+        let output = self.run(&cargo_args)?;
+        Ok(Attempt {
+            code: output.status.code(),
+            events: output.stdout,
+            error: None,
+        })
+    }
+}
+
+pub struct InProcessRunner {
+    runtime: Runtime,
+}
+
+impl InProcessRunner {
+    pub fn new() -> io::Result<Self> {
+        Ok(InProcessRunner {
+            runtime: Runtime::new()?,
+        })
+    }
+}
+
+impl Harness for InProcessRunner {
+    fn attempt(&self, args: &[String]) -> io::Result<Attempt> {
+        let args =
+            Args::try_parse_from(iter::once("ai_critics").chain(args.iter().map(|a| a.as_str())))
+                .map_err(io::Error::other)?;
+        let events = SharedBuffer::default();
+        let observer = JsonLinesObserver::new(Box::new(events.clone()), &new_run_id());
+        let result = self
+            .runtime
+            .block_on(solve(&args, vec![Box::new(observer)]));
+        let error = result.as_ref().err().map(|e| e.to_string());
+        Ok(Attempt {
+            code: Some(exit_code(&result)),
+            events: events.take(),
+            error,
+        })
+    }
+}
+
+// A buffer that the run's observer writes to while the harness keeps a handle to read it.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
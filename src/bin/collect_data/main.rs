@@ -0,0 +1,896 @@
+use ab::AbArgs;
+use ai_critics::notify::{self, Outcome as NotifyOutcome, Report};
+use clap::{Parser, Subcommand};
+use effectiveness::{CriticEffects, Effectiveness};
+use harness::{Harness, InProcessRunner};
+use manifest::Manifest;
+use metrics::{EventCounts, Metrics};
+use rate_limiter::RateLimiter;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use sweep::{Cell, Sweep, CSV_AXES};
+use tokio::runtime::Runtime;
+#[cfg(not(test))]
+use {std::thread::sleep, std::time::Duration};
+
+mod ab;
+mod effectiveness;
+mod harness;
+mod manifest;
+mod metrics;
+mod rate_limiter;
+mod sweep;
+
+//
+// Run like:
+// $ cargo run --bin collect_data
+//
+// To chart the batch in Grafana while it runs, serve Prometheus metrics:
+// $ cargo run --bin collect_data -- --metrics 127.0.0.1:9898
+//
+// To collect the data with another provider, such as Gemini:
+// $ cargo run --bin collect_data -- --provider gemini
+//
+// To sweep other axes, such as models and temperatures, than the default problems and critics:
+// $ cargo run --bin collect_data -- --sweep sweep.toml
+//
+// To resume a batch that stopped partway, skipping the cells that are done:
+// $ cargo run --bin collect_data -- --resume
+//
+// Each run's events are kept in transcripts/ for the report. To keep them elsewhere:
+// $ cargo run --bin collect_data -- --transcripts runs/
+//
+// To run 4 problems at a time, starting at most 10 runs a minute:
+// $ cargo run --bin collect_data -- --jobs 4 --runs-per-minute 10
+//
+// The runs are made in this process. To run each one with `cargo run` instead:
+// $ cargo run --bin collect_data -- --subprocess
+//
+// To post a summary of the batch to a Slack or Discord webhook when it finishes:
+// $ cargo run --bin collect_data -- --notify-webhook https://hooks.slack.com/services/...
+//
+// Each batch also writes a table of how often each type of critic's comments were followed by a
+// better test outcome, to critic_effectiveness.csv.
+//
+// To compare two prompt bundles, or two models, on problems 1 to 4:
+// $ cargo run --bin collect_data -- ab --b-prompts experiments/terse --problems 1,2,3,4
+//
+// Test like:
+// $ cargo test --bin collect_data -- --nocapture
+
+const OUTPUT_FILENAME: &str = "iterations_data.csv";
+const RUNS_FILENAME: &str = "runs_data.jsonl";
+const MANIFEST_FILENAME: &str = "iterations_data.manifest";
+const EFFECTIVENESS_FILENAME: &str = "critic_effectiveness.csv";
+const NUM_RETRIES: usize = 3;
+// The exit code of an ai_critics run that was cancelled with Ctrl-C.
+const CANCELLED_EXIT_CODE: i32 = 130;
+
+#[derive(Parser, Debug)]
+#[command(about = "Run ai_critics over the problems and record the outcomes.")]
+struct Args {
+    /// Serve Prometheus metrics for the batch at http://<ADDR>/metrics.
+    #[arg(long)]
+    metrics: Option<SocketAddr>,
+
+    /// The provider that ai_critics uses for the runs, e.g. `gemini`, to compare providers.
+    #[arg(long)]
+    provider: Option<String>,
+
+    /// Number of runs to execute concurrently.
+    #[arg(long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Start at most this many runs per minute across the jobs, to stay under the API's rate
+    /// limits. 0 means no limit.
+    #[arg(long, default_value_t = 0)]
+    runs_per_minute: u32,
+
+    /// TOML file of the sweep's axes: problems, num_critics, models, general_critic_only,
+    /// temperatures, self_review, prompts_dirs, and iterations.
+    #[arg(long)]
+    sweep: Option<PathBuf>,
+
+    /// Resume an interrupted batch, skipping the cells already written and appending the rest.
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+
+    /// Run each problem with `cargo run` instead of in this process.
+    #[arg(long, default_value_t = false)]
+    subprocess: bool,
+
+    /// Directory to write each run's events to as JSON lines, which the report shows.
+    #[arg(long, default_value = "transcripts")]
+    transcripts: PathBuf,
+
+    /// URL to post a summary of the batch to when it finishes or stops, e.g. a Slack or Discord
+    /// incoming webhook.
+    #[arg(long)]
+    notify_webhook: Option<String>,
+
+    #[command(subcommand)]
+    experiment: Option<Experiment>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Experiment {
+    /// Compare two prompt bundles or models on the same problems.
+    Ab(AbArgs),
+}
+
+#[derive(Default, Debug, Clone)]
+struct Outcome {
+    // The number of times that the AI critics found a solution.
+    success_count: usize,
+    // The number of failures by network or unknown reason.
+    failure_count: usize,
+    // The number of times the AI critics failed to find a solution.
+    divergence_count: usize,
+    // The number of iterations that the AI critic needed to find a solution.
+    success_iterations: usize,
+    // The wall-clock time, tokens, and cost of all of the runs, whatever their outcome.
+    duration_secs: f64,
+    total_tokens: u64,
+    cost_usd: f64,
+}
+
+impl Outcome {
+    // Count a run by its exit code: the number of iterations it took, 0 for an error, or 255 when
+    // the critics failed to converge.
+    fn record(&mut self, run: &RunRecord) {
+        self.duration_secs += run.duration_secs;
+        self.total_tokens += run.counts.total_tokens();
+        self.cost_usd += run.counts.cost_usd;
+        match run.iterations {
+            0 => self.failure_count += 1,
+            255 => self.divergence_count += 1,
+            _ => {
+                self.success_count += 1;
+                self.success_iterations += run.iterations;
+            }
+        }
+    }
+
+    fn add(&mut self, other: &Outcome) {
+        self.success_count += other.success_count;
+        self.failure_count += other.failure_count;
+        self.divergence_count += other.divergence_count;
+        self.success_iterations += other.success_iterations;
+        self.duration_secs += other.duration_secs;
+        self.total_tokens += other.total_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+}
+
+// What one run of a problem produced over all of its attempts. The runs are written to
+// RUNS_FILENAME for analysis beyond the counts in the CSV.
+#[derive(Serialize, Default, Debug)]
+struct RunRecord {
+    // The exit code of the last attempt: the number of iterations, 0 for an error, or 255 for a
+    // divergence.
+    iterations: usize,
+    attempts: usize,
+    // The error that ended the last attempt, if it failed in process.
+    error: Option<String>,
+    duration_secs: f64,
+    #[serde(flatten)]
+    counts: EventCounts,
+    // The file that the last attempt's events were written to, if transcripts are kept.
+    transcript: Option<String>,
+    // Which types of critic commented on each proposal of the last attempt, and whether the tests
+    // then did better.
+    critic_effects: CriticEffects,
+    #[serde(skip)]
+    events: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct RunLine<'a> {
+    #[serde(flatten)]
+    cell: &'a Cell,
+    iteration: usize,
+    #[serde(flatten)]
+    run: &'a RunRecord,
+}
+
+// Runs are made from several worker threads with --jobs.
+pub trait CommandRunner: Sync {
+    fn run(&self, args: &[String]) -> io::Result<Output>;
+}
+
+pub struct RealCommandRunner;
+
+impl CommandRunner for RealCommandRunner {
+    fn run(&self, args: &[String]) -> io::Result<Output> {
+        Command::new("cargo")
+            .env("RUST_LOG", "info")
+            .args(args)
+            .output()
+    }
+}
+
+pub struct DataCollector<'a> {
+    harness: &'a dyn Harness,
+    metrics: Arc<Metrics>,
+    provider: Option<String>,
+    jobs: usize,
+    rate_limiter: RateLimiter,
+    runs_log: Option<Mutex<Box<dyn Write + Send>>>,
+    manifest: Option<Mutex<Manifest>>,
+    transcripts: Option<PathBuf>,
+    // The outcomes of the cells written so far.
+    total: Mutex<Outcome>,
+}
+
+impl<'a> DataCollector<'a> {
+    pub fn new(harness: &'a dyn Harness) -> Self {
+        DataCollector {
+            harness,
+            metrics: Arc::new(Metrics::default()),
+            provider: None,
+            jobs: 1,
+            rate_limiter: RateLimiter::per_minute(0),
+            runs_log: None,
+            manifest: None,
+            transcripts: None,
+            total: Mutex::default(),
+        }
+    }
+
+    pub fn with_provider(mut self, provider: Option<String>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    // Run up to `jobs` runs at a time, starting at most `runs_per_minute` of them per minute.
+    pub fn with_jobs(mut self, jobs: usize, runs_per_minute: u32) -> Self {
+        self.jobs = jobs;
+        self.rate_limiter = RateLimiter::per_minute(runs_per_minute);
+        self
+    }
+
+    // Write each run's record to the log as a JSON line.
+    pub fn with_runs_log(mut self, log: Box<dyn Write + Send>) -> Self {
+        self.runs_log = Some(Mutex::new(log));
+        self
+    }
+
+    // Skip the cells in the manifest and add each cell to it once its row is written.
+    pub fn with_manifest(mut self, manifest: Manifest) -> Self {
+        self.manifest = Some(Mutex::new(manifest));
+        self
+    }
+
+    // Write each run's events to a file in the directory.
+    pub fn with_transcripts(mut self, dir: PathBuf) -> Self {
+        self.transcripts = Some(dir);
+        self
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    pub fn collect_data<W: Write + Send>(&self, sweep: &Sweep, file: &mut W) -> io::Result<()> {
+        let mut cells = sweep.cells();
+        if let Some(manifest) = &self.manifest {
+            let manifest = manifest.lock().unwrap();
+            let total = cells.len();
+            cells.retain(|cell| !manifest.contains(cell));
+            if cells.len() < total {
+                println!(
+                    "[collect_data] Skipping {} cells that are already done.",
+                    total - cells.len()
+                );
+            }
+        }
+        println!(
+            "[collect_data] Running ai_critic for {} cells, {} iterations each...",
+            cells.len(),
+            sweep.iterations
+        );
+        self.process_cells(&cells, sweep.iterations, file)
+    }
+
+    // Run the iterations of each cell, up to `jobs` runs at a time, writing each cell's row to the
+    // file when its last iteration finishes.
+    fn process_cells<W: Write + Send>(
+        &self,
+        cells: &[Cell],
+        iterations: usize,
+        file: &mut W,
+    ) -> io::Result<()> {
+        self.run_cells(cells, iterations, |cell, outcome| {
+            writeln!(
+                file,
+                "{},{},{},{},{},{:.1},{},{:.4}",
+                cell.csv_axes(),
+                outcome.success_count,
+                outcome.failure_count,
+                outcome.divergence_count,
+                outcome.success_iterations,
+                outcome.duration_secs,
+                outcome.total_tokens,
+                outcome.cost_usd
+            )?;
+            file.flush()?;
+            self.total.lock().unwrap().add(outcome);
+            match &self.manifest {
+                Some(manifest) => manifest.lock().unwrap().record(cell),
+                None => Ok(()),
+            }
+        })
+    }
+
+    // Run the iterations of the cells on `jobs` worker threads, calling `on_done` with each cell's
+    // outcome once all of its iterations are done. The first error stops the workers.
+    fn run_cells(
+        &self,
+        cells: &[Cell],
+        iterations: usize,
+        on_done: impl FnMut(&Cell, &Outcome) -> io::Result<()> + Send,
+    ) -> io::Result<()> {
+        let runs: VecDeque<_> = (0..cells.len())
+            .flat_map(|c| (1..=iterations).map(move |i| (c, i)))
+            .collect();
+        let runs = Mutex::new(runs);
+        let outcomes: Mutex<HashMap<usize, (Outcome, usize)>> = Mutex::default();
+        let on_done = Mutex::new(on_done);
+        let error: Mutex<Option<io::Error>> = Mutex::default();
+
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.max(1) {
+                scope.spawn(|| loop {
+                    if error.lock().unwrap().is_some() {
+                        return;
+                    }
+                    let Some((c, i)) = runs.lock().unwrap().pop_front() else {
+                        return;
+                    };
+                    let cell = &cells[c];
+                    let result = self.run_iteration(cell, i).and_then(|run| {
+                        let mut outcomes = outcomes.lock().unwrap();
+                        let (outcome, done) = outcomes.entry(c).or_default();
+                        outcome.record(&run);
+                        *done += 1;
+                        match *done == iterations {
+                            true => (on_done.lock().unwrap())(cell, outcome),
+                            false => Ok(()),
+                        }
+                    });
+                    if let Err(e) = result {
+                        error.lock().unwrap().get_or_insert(e);
+                        return;
+                    }
+                });
+            }
+        });
+
+        match error.into_inner().unwrap() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // Run one iteration of the cell, waiting for the rate limiter first.
+    fn run_iteration(&self, cell: &Cell, iteration: usize) -> io::Result<RunRecord> {
+        self.rate_limiter.wait();
+        println!(
+            "[collect_data] Running {:?}, iteration {}...",
+            cell, iteration
+        );
+        let mut run = self.run_command_with_retries(cell)?;
+        self.save_transcript(cell, iteration, &mut run)?;
+        self.log_run(cell, iteration, &run)?;
+        let iterations = run.iterations; // 0 indicates error.
+        println!(
+            "[collect_data] {:?}, iteration {} ==> iterations {}.",
+            cell, iteration, iterations
+        );
+        self.metrics
+            .record_outcome(cell.problem, cell.num_critics, iterations);
+        Ok(run)
+    }
+
+    // Run the problem, retrying attempts that end in an error, and return what the run produced.
+    fn run_command_with_retries(&self, cell: &Cell) -> io::Result<RunRecord> {
+        let mut args = cell.args();
+        // The events are counted for the metrics.
+        args.push("--log-format=json".to_string());
+        if let Some(provider) = &self.provider {
+            args.push(format!("--provider={}", provider));
+        }
+        let start = Instant::now();
+        let mut record = RunRecord::default();
+        while record.attempts < NUM_RETRIES {
+            println!("[collect_data] Running `ai_critics {}`...", args.join(" "));
+            let attempt = self.harness.attempt(&args)?;
+            let counts = EventCounts::from_json_lines(&attempt.events);
+            self.metrics
+                .record_counts(cell.problem, cell.num_critics, &counts);
+            record.attempts += 1;
+            record.counts.add(counts);
+            record.error = attempt.error;
+            record.critic_effects = CriticEffects::from_json_lines(&attempt.events);
+            record.events = attempt.events;
+            record.duration_secs = start.elapsed().as_secs_f64();
+            match attempt.code {
+                Some(code) if code < 0 => {
+                    return Err(io::Error::other(format!(
+                        "unexpected error (exit code: {}); exiting",
+                        code
+                    )));
+                }
+                Some(CANCELLED_EXIT_CODE) => {
+                    // The user cancelled the run, so stop the batch rather than record it.
+                    return Err(io::Error::new(
+                        io::ErrorKind::Interrupted,
+                        "the run was cancelled; exiting",
+                    ));
+                }
+                Some(code) if code > 0 => {
+                    // An exit code > 0 indicates success where the value indicates the number of
+                    // iterations. 255 indicates a convergence failure.
+                    record.iterations = code as usize;
+                    return Ok(record);
+                }
+                Some(_) => {
+                    // An exit code of 0 indicates a program error; retry.
+                }
+                None => {
+                    // Unknown error; retry.
+                }
+            };
+
+            #[cfg(not(test))]
+            if record.attempts < NUM_RETRIES {
+                println!("[collect_data] Sleeping for 5 seconds before retry...");
+                sleep(Duration::from_secs(5));
+            }
+        }
+
+        Ok(record)
+    }
+
+    // Write the events of the run's last attempt to the transcripts directory and note the file in
+    // its record.
+    fn save_transcript(
+        &self,
+        cell: &Cell,
+        iteration: usize,
+        run: &mut RunRecord,
+    ) -> io::Result<()> {
+        let Some(dir) = &self.transcripts else {
+            return Ok(());
+        };
+        let path = dir.join(format!("{}-{}.jsonl", cell.file_stem(), iteration));
+        fs::write(&path, &run.events)?;
+        run.transcript = Some(path.display().to_string());
+        Ok(())
+    }
+
+    // Append the run's record to the runs log as a JSON line.
+    fn log_run(&self, cell: &Cell, iteration: usize, run: &RunRecord) -> io::Result<()> {
+        let Some(log) = &self.runs_log else {
+            return Ok(());
+        };
+        let line = RunLine {
+            cell,
+            iteration,
+            run,
+        };
+        let mut log = log.lock().unwrap();
+        writeln!(log, "{}", serde_json::to_string(&line)?)?;
+        log.flush()
+    }
+}
+
+// The columns of the rows that process_cells writes.
+fn write_csv_header<W: Write>(file: &mut W) -> io::Result<()> {
+    writeln!(
+        file,
+        "{},SuccessCount,FailureCount,DivergenceCount,SuccessIterations,DurationSecs,TotalTokens,CostUsd",
+        CSV_AXES
+    )
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let sweep = match &args.sweep {
+        Some(path) => Sweep::load(path)?,
+        None => Sweep::default(),
+    };
+    let in_process_runner;
+    let harness: &dyn Harness = if args.subprocess {
+        &RealCommandRunner
+    } else {
+        in_process_runner = InProcessRunner::new()?;
+        &in_process_runner
+    };
+    // The output is appended to when resuming and replaced otherwise.
+    let open = |path: &str| match args.resume {
+        true => OpenOptions::new().create(true).append(true).open(path),
+        false => File::create(path),
+    };
+    let data_collector = DataCollector::new(harness)
+        .with_provider(args.provider)
+        .with_jobs(args.jobs, args.runs_per_minute)
+        .with_transcripts(args.transcripts.clone());
+    fs::create_dir_all(&args.transcripts)?;
+    if let Some(addr) = args.metrics {
+        metrics::serve(addr, data_collector.metrics())?;
+    }
+    if let Some(Experiment::Ab(ab_args)) = &args.experiment {
+        return ab::run(ab_args, data_collector);
+    }
+
+    let manifest = match args.resume {
+        true => Manifest::open(Path::new(MANIFEST_FILENAME))?,
+        false => Manifest::create(Path::new(MANIFEST_FILENAME))?,
+    };
+    let data_collector = data_collector
+        .with_runs_log(Box::new(open(RUNS_FILENAME)?))
+        .with_manifest(manifest);
+    let mut file = open(OUTPUT_FILENAME)?;
+    if file.metadata()?.len() == 0 {
+        write_csv_header(&mut file)?;
+    }
+
+    let result = data_collector.collect_data(&sweep, &mut file);
+    // The table covers every run in the log, including those from before a resume.
+    let effectiveness = Effectiveness::from_runs_log(Path::new(RUNS_FILENAME))?;
+    effectiveness.write_csv(&mut File::create(EFFECTIVENESS_FILENAME)?)?;
+    println!(
+        "[collect_data] Critic effectiveness, also in {}:\n{}",
+        EFFECTIVENESS_FILENAME,
+        effectiveness.table()
+    );
+    if let Some(url) = &args.notify_webhook {
+        let report = batch_report(
+            args.sweep.as_deref(),
+            &data_collector.total.lock().unwrap(),
+            &result,
+        );
+        Runtime::new()?.block_on(notify::notify(Some(url), &report));
+    }
+    result
+}
+
+// The summary of the batch that the webhook is notified of.
+fn batch_report(sweep: Option<&Path>, total: &Outcome, result: &io::Result<()>) -> Report {
+    let runs = total.success_count + total.failure_count + total.divergence_count;
+    let solved = format!(
+        "Solved {} of {} runs ({} diverged, {} errors)",
+        total.success_count, runs, total.divergence_count, total.failure_count
+    );
+    let (status, outcome) = match result {
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+            (NotifyOutcome::Cancelled, format!("Cancelled. {}", solved))
+        }
+        Err(e) => (NotifyOutcome::Error, format!("Stopped: {}. {}", e, solved)),
+        Ok(()) if total.divergence_count > 0 => (NotifyOutcome::Diverged, solved),
+        Ok(()) if total.failure_count > 0 => (NotifyOutcome::Error, solved),
+        Ok(()) => (NotifyOutcome::Solved, solved),
+    };
+    let problem = match sweep {
+        Some(path) => format!("the sweep of {}", path.display()),
+        None => "the default sweep".to_string(),
+    };
+    Report {
+        id: "collect_data".to_string(),
+        problem,
+        status,
+        outcome,
+        proposals: Some(total.success_iterations),
+        cost_usd: total.cost_usd,
+        artifacts: notify::artifacts_link(Path::new(OUTPUT_FILENAME)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    #[derive(Debug)]
+    struct MockCommandRunner {
+        exit_codes: Mutex<Vec<i32>>,
+    }
+
+    impl MockCommandRunner {
+        fn new(mut exit_codes: Vec<i32>) -> Self {
+            // Reverse the order of the exit codes so that later we can use pop() to remove them in
+            // the correct order.
+            exit_codes.reverse();
+            MockCommandRunner {
+                exit_codes: Mutex::new(exit_codes),
+            }
+        }
+    }
+
+    impl CommandRunner for MockCommandRunner {
+        fn run(&self, _args: &[String]) -> io::Result<Output> {
+            let exit_code = self.exit_codes.lock().unwrap().pop().unwrap_or(0);
+            // Shift the exit code into the higher-order bits.
+            let status_code = exit_code << 8;
+            Ok(Output {
+                status: ExitStatus::from_raw(status_code),
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+    }
+
+    fn cell(problem: usize, num_critics: usize) -> Cell {
+        Cell {
+            problem,
+            num_critics,
+            ..Default::default()
+        }
+    }
+
+    // The default sweep with only the given numbers of critics.
+    fn sweep(num_critics: &[usize]) -> Sweep {
+        Sweep {
+            num_critics: num_critics.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    // Run the iterations of problem 1 with 1 critic and return the outcome.
+    fn run_iterations_for_problem(data_collector: &DataCollector) -> Outcome {
+        let mut result = Outcome::default();
+        data_collector
+            .run_cells(&[cell(1, 1)], 3, |_, outcome| {
+                result = outcome.clone();
+                Ok(())
+            })
+            .unwrap();
+        result
+    }
+
+    #[test]
+    fn test_process_problems_for_num_critics_all_success() {
+        let mock_command_runner = MockCommandRunner::new(vec![1, 2, 3, 1, 2, 3, 1, 2, 3]);
+        let data_collector = DataCollector::new(&mock_command_runner);
+        let mut mock_file = Vec::new();
+
+        data_collector
+            .collect_data(&sweep(&[1]), &mut mock_file)
+            .unwrap();
+
+        let output = std::str::from_utf8(&mock_file).unwrap();
+        // "Problem,NumCritics,Model,GeneralCriticOnly,Temperature,SelfReview,PromptsDir,
+        //  SuccessCount,FailureCount,DivergenceCount,SuccessIterations,DurationSecs,TotalTokens,CostUsd"
+        assert!(output.contains("1,1,,false,,false,,3,0,0,6")); // First problem.
+        assert!(output.contains("2,1,,false,,false,,3,0,0,6")); // Second problem.
+        assert!(output.contains("3,1,,false,,false,,3,0,0,6")); // ...
+        assert!(output.contains("4,1,,false,,false,,0,3,0,0")); // Exit codes are 0 after 9th one above...
+        assert!(output.contains("5,1,,false,,false,,0,3,0,0"));
+        assert!(output.contains("6,1,,false,,false,,0,3,0,0"));
+        assert!(output.contains("7,1,,false,,false,,0,3,0,0"));
+        assert!(output.contains("8,1,,false,,false,,0,3,0,0"));
+    }
+
+    #[test]
+    fn test_process_problems_for_num_critics_mixed_outcomes() {
+        let mock_command_runner = MockCommandRunner::new(vec![1, 0, 255, 2, 0, 255, 3, 0, 255]);
+        let data_collector = DataCollector::new(&mock_command_runner);
+        let mut mock_file = Vec::new();
+
+        data_collector
+            .collect_data(&sweep(&[1]), &mut mock_file)
+            .unwrap();
+
+        let output = std::str::from_utf8(&mock_file).unwrap();
+
+        // "Problem,NumCritics,Model,GeneralCriticOnly,Temperature,SelfReview,PromptsDir,
+        //  SuccessCount,FailureCount,DivergenceCount,SuccessIterations,DurationSecs,TotalTokens,CostUsd"
+        // First problem:
+        //   NUM_ITERATIONS = 3, exit codes to consume = [1, 0, 255, 2, 0, 255, 3, 0, 255]
+        //   iteration 1: 1 => a success (+1 iteration)
+        //   iteration 2: 0 is retried, 255 => a divergence
+        //   iteration 3: 2  => a success (+2 iteration)
+        // So we have problem 1, 1 critic, 2 successes, no failures, 1 divergence, and 3 iterations:
+        // 1,1,,false,,false,,2,0,1,3
+        assert!(output.contains("1,1,,false,,false,,2,0,1,3")); // First problem.
+        assert!(output.contains("2,1,,false,,false,,1,0,2,3")); // Second.
+        assert!(output.contains("3,1,,false,,false,,0,3,0,0")); // ...
+        assert!(output.contains("4,1,,false,,false,,0,3,0,0"));
+        assert!(output.contains("5,1,,false,,false,,0,3,0,0"));
+        assert!(output.contains("6,1,,false,,false,,0,3,0,0"));
+        assert!(output.contains("7,1,,false,,false,,0,3,0,0"));
+        assert!(output.contains("8,1,,false,,false,,0,3,0,0"));
+    }
+
+    #[test]
+    fn test_process_problems_in_parallel() {
+        let sweep = sweep(&[1, 3]);
+        let cells = sweep.cells();
+        let mock_command_runner = MockCommandRunner::new(vec![2; 3 * 8 * 2]);
+        let data_collector = DataCollector::new(&mock_command_runner).with_jobs(4, 0);
+        let mut mock_file = Vec::new();
+
+        data_collector.collect_data(&sweep, &mut mock_file).unwrap();
+
+        let output = std::str::from_utf8(&mock_file).unwrap();
+        assert_eq!(output.lines().count(), 8 * 2);
+        for cell in cells {
+            assert!(output.contains(&format!("{},3,0,0,6,", cell.csv_axes())));
+        }
+    }
+
+    #[test]
+    fn test_batch_report() {
+        let mock_command_runner = MockCommandRunner::new(vec![1, 0, 255, 2, 0, 255, 3, 0, 255]);
+        let data_collector = DataCollector::new(&mock_command_runner);
+        let result = data_collector.collect_data(&sweep(&[1]), &mut Vec::new());
+
+        let total = data_collector.total.lock().unwrap();
+        let report = batch_report(None, &total, &result);
+        assert_eq!(report.status, NotifyOutcome::Diverged);
+        assert_eq!(report.problem, "the default sweep");
+        // The exit codes are 0 after the 9th, so the other problems' runs are errors.
+        assert_eq!(
+            report.outcome,
+            "Solved 3 of 24 runs (3 diverged, 18 errors)"
+        );
+        assert_eq!(report.proposals, Some(6));
+
+        let stopped = io::Error::new(io::ErrorKind::Interrupted, "the run was cancelled");
+        let report = batch_report(None, &total, &Err(stopped));
+        assert_eq!(report.status, NotifyOutcome::Cancelled);
+    }
+
+    #[test]
+    fn test_resume_skips_cells_in_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = Manifest::create(&dir.path().join("manifest")).unwrap();
+        manifest.record(&cell(1, 1)).unwrap();
+        let mock_command_runner = MockCommandRunner::new(vec![2, 2, 2]);
+        let data_collector = DataCollector::new(&mock_command_runner).with_manifest(manifest);
+        let sweep = Sweep {
+            problems: vec![1, 2],
+            ..sweep(&[1])
+        };
+        let mut mock_file = Vec::new();
+
+        data_collector.collect_data(&sweep, &mut mock_file).unwrap();
+
+        let output = std::str::from_utf8(&mock_file).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with("2,1,,false,,false,,3,0,0,6,"));
+        let manifest = data_collector.manifest.unwrap().into_inner().unwrap();
+        assert!(manifest.contains(&cell(2, 1)));
+    }
+
+    #[test]
+    fn test_run_iteration_saves_transcript() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock_command_runner = MockCommandRunner::new(vec![2]);
+        let data_collector =
+            DataCollector::new(&mock_command_runner).with_transcripts(dir.path().to_path_buf());
+
+        let run = data_collector.run_iteration(&cell(3, 5), 2).unwrap();
+
+        let path = dir.path().join("problem3-critics5-2.jsonl");
+        assert_eq!(run.transcript, Some(path.display().to_string()));
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_outcome_totals_every_run() {
+        let mut outcome = Outcome::default();
+        for iterations in [2, 255] {
+            outcome.record(&RunRecord {
+                iterations,
+                duration_secs: 1.5,
+                counts: EventCounts {
+                    prompt_tokens: 100,
+                    completion_tokens: 20,
+                    cost_usd: 0.01,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        }
+        assert_eq!(outcome.success_iterations, 2);
+        assert_eq!(outcome.divergence_count, 1);
+        assert_eq!(outcome.duration_secs, 3.0);
+        assert_eq!(outcome.total_tokens, 240);
+        assert!((outcome.cost_usd - 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_run_command_with_retries_success() {
+        let mock_command_runner = MockCommandRunner::new(vec![4]);
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let result = data_collector.run_command_with_retries(&cell(1, 1));
+        assert_eq!(result.unwrap().iterations, 4);
+    }
+
+    #[test]
+    fn test_run_command_with_retries_failure() {
+        let mock_command_runner = MockCommandRunner::new(vec![0, 0, 0, 0, 0, 0]); // 6 Retry fails.
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let result = data_collector.run_command_with_retries(&cell(1, 1));
+        assert_eq!(result.unwrap().iterations, 0);
+    }
+
+    #[test]
+    fn test_run_command_with_retries_divergence() {
+        // Note that the run_command_with_retries() doesn't know about the exit code 255, so it returns it as is.
+        let mock_command_runner = MockCommandRunner::new(vec![255]);
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let result = data_collector.run_command_with_retries(&cell(1, 1));
+        assert_eq!(result.unwrap().iterations, 255);
+    }
+
+    #[test]
+    fn test_run_command_with_retries_cancelled() {
+        let mock_command_runner = MockCommandRunner::new(vec![CANCELLED_EXIT_CODE]);
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let result = data_collector.run_command_with_retries(&cell(1, 1));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn test_run_command_with_retries_retry() {
+        let mock_command_runner = MockCommandRunner::new(vec![0, 0, 2]); // Fails twice, then succeeds.
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let run = data_collector
+            .run_command_with_retries(&cell(1, 1))
+            .unwrap();
+        assert_eq!(run.iterations, 2);
+        assert_eq!(run.attempts, 3);
+    }
+
+    #[test]
+    fn test_run_iterations_for_problem_success() {
+        let mock_command_runner = MockCommandRunner::new(vec![1, 2, 3]); // Three successes.
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let outcome = run_iterations_for_problem(&data_collector);
+        assert_eq!(outcome.success_count, 3);
+        assert_eq!(outcome.failure_count, 0);
+        assert_eq!(outcome.divergence_count, 0);
+        assert_eq!(outcome.success_iterations, 6); // 1 + 2 + 3.
+    }
+
+    #[test]
+    fn test_run_iterations_for_problem_failure() {
+        let mock_command_runner = MockCommandRunner::new(vec![0, 0, 0, 0, 0]);
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let outcome = run_iterations_for_problem(&data_collector);
+        assert_eq!(outcome.success_count, 0);
+        assert_eq!(outcome.failure_count, 3);
+        assert_eq!(outcome.divergence_count, 0);
+        assert_eq!(outcome.success_iterations, 0);
+    }
+
+    #[test]
+    fn test_run_iterations_for_problem_divergence() {
+        let mock_command_runner = MockCommandRunner::new(vec![255, 255, 255]); // Three divergence failures
+        let data_collector = DataCollector::new(&mock_command_runner);
+
+        let outcome = run_iterations_for_problem(&data_collector);
+        assert_eq!(outcome.success_count, 0);
+        assert_eq!(outcome.failure_count, 0);
+        assert_eq!(outcome.divergence_count, 3);
+        assert_eq!(outcome.success_iterations, 0);
+    }
+}
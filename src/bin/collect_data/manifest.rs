@@ -0,0 +1,77 @@
+use crate::sweep::Cell;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+// The manifest lists the cells whose rows have been written to the CSV, one per line, so that a
+// batch that stops partway can be resumed with --resume without rerunning them. A cell is added
+// after its row is flushed, so a crash in between reruns the cell rather than losing its row.
+
+pub struct Manifest {
+    file: File,
+    done: HashSet<String>,
+}
+
+impl Manifest {
+    // Start a new manifest, replacing any existing one.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Manifest {
+            file: File::create(path)?,
+            done: HashSet::new(),
+        })
+    }
+
+    // Open the manifest to resume a batch, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let done = match fs::read_to_string(path) {
+            Ok(text) => text.lines().map(str::to_string).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Manifest {
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+            done,
+        })
+    }
+
+    pub fn contains(&self, cell: &Cell) -> bool {
+        self.done.contains(&cell.csv_axes())
+    }
+
+    pub fn record(&mut self, cell: &Cell) -> io::Result<()> {
+        let key = cell.csv_axes();
+        writeln!(self.file, "{}", key)?;
+        self.file.flush()?;
+        self.done.insert(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_from_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest.txt");
+        let cell = |problem| Cell {
+            problem,
+            num_critics: 1,
+            ..Default::default()
+        };
+
+        let mut manifest = Manifest::create(&path).unwrap();
+        manifest.record(&cell(1)).unwrap();
+        assert!(manifest.contains(&cell(1)));
+
+        let mut manifest = Manifest::open(&path).unwrap();
+        assert!(manifest.contains(&cell(1)));
+        assert!(!manifest.contains(&cell(2)));
+        manifest.record(&cell(2)).unwrap();
+        assert!(Manifest::open(&path).unwrap().contains(&cell(2)));
+
+        assert!(!Manifest::create(&path).unwrap().contains(&cell(1)));
+    }
+}
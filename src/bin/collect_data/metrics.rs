@@ -0,0 +1,349 @@
+use ai_critics::pricing;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Batch metrics in the Prometheus text format, served on `/metrics` so that long experiments can be
+// charted in Grafana while they run. Each run of ai_critics writes its events as JSON lines, on
+// stdout (`--log-format json`) or to a buffer when run in process; the retries, tokens, and test
+// failures are counted from those, and the outcome and iterations from the exit code. Every series
+// is labelled with the problem and the number of critics.
+
+// Upper bounds of the iterations histogram buckets. Runs stop at 20 proposals.
+const ITERATION_BUCKETS: [usize; 7] = [1, 2, 3, 5, 8, 13, 20];
+
+// The longest that a client of /metrics may take to send its request line.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The labels of a series: the problem number and the number of critics.
+type Key = (usize, usize);
+
+#[derive(Default, Clone)]
+struct Histogram {
+    // Cumulative counts for each of ITERATION_BUCKETS.
+    buckets: [u64; ITERATION_BUCKETS.len()],
+    sum: usize,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: usize) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(ITERATION_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+// The counts of the events written by a run of ai_critics, and the cost of its requests.
+#[derive(Serialize, Default, Debug, Clone, Copy, PartialEq)]
+pub struct EventCounts {
+    pub api_retries: u64,
+    // Including the prompts resent by retries.
+    pub prompt_tokens: u64,
+    // Counted as streamed chunks.
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+    pub compile_failures: u64,
+    pub test_failures: u64,
+}
+
+impl EventCounts {
+    // Count the events in the JSON lines. Other lines, such as a panic message, are skipped.
+    pub fn from_json_lines(stdout: &[u8]) -> Self {
+        let mut counts = EventCounts::default();
+        // The model and prompt tokens of each agent's latest request, which a retry sends again.
+        let mut requests: HashMap<String, (String, u64)> = HashMap::new();
+        for line in stdout.split(|&b| b == b'\n') {
+            let Ok(event) = serde_json::from_slice::<Value>(line) else {
+                continue;
+            };
+            let agent = event["agent"].as_str().unwrap_or_default().to_string();
+            match event["type"].as_str() {
+                Some("chat_requested") => {
+                    let model = event["model"].as_str().unwrap_or_default().to_string();
+                    let tokens = event["prompt_tokens"].as_u64().unwrap_or_default();
+                    counts.add_prompt(&model, tokens);
+                    requests.insert(agent, (model, tokens));
+                }
+                Some("chat_retried") => {
+                    counts.api_retries += 1;
+                    if let Some((model, tokens)) = requests.get(&agent) {
+                        counts.add_prompt(model, *tokens);
+                    }
+                }
+                Some("chat_responded") => {
+                    let tokens = event["chunks"].as_u64().unwrap_or_default();
+                    counts.completion_tokens += tokens;
+                    if let Some((model, _)) = requests.get(&agent) {
+                        counts.cost_usd += pricing::completion_cost(model, tokens);
+                    }
+                }
+                Some("test_failed") if event["compile_error"] == Value::Bool(true) => {
+                    counts.compile_failures += 1
+                }
+                Some("test_failed") => counts.test_failures += 1,
+                _ => {}
+            }
+        }
+        counts
+    }
+
+    fn add_prompt(&mut self, model: &str, tokens: u64) {
+        self.prompt_tokens += tokens;
+        self.cost_usd += pricing::prompt_cost(model, tokens);
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn add(&mut self, other: EventCounts) {
+        self.api_retries += other.api_retries;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost_usd += other.cost_usd;
+        self.compile_failures += other.compile_failures;
+        self.test_failures += other.test_failures;
+    }
+}
+
+#[derive(Default)]
+struct MetricsState {
+    runs: BTreeMap<(usize, usize, &'static str), u64>,
+    iterations: BTreeMap<Key, Histogram>,
+    api_retries: BTreeMap<Key, u64>,
+    completion_tokens: BTreeMap<Key, u64>,
+    compile_failures: BTreeMap<Key, u64>,
+    test_failures: BTreeMap<Key, u64>,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+impl Metrics {
+    // Add the counts of the events written by one attempt at a problem.
+    pub fn record_counts(&self, problem: usize, num_critics: usize, counts: &EventCounts) {
+        let key = (problem, num_critics);
+        let mut state = self.state.lock().unwrap();
+        *state.api_retries.entry(key).or_default() += counts.api_retries;
+        *state.completion_tokens.entry(key).or_default() += counts.completion_tokens;
+        *state.compile_failures.entry(key).or_default() += counts.compile_failures;
+        *state.test_failures.entry(key).or_default() += counts.test_failures;
+    }
+
+    // Record the outcome of a run, and its iterations if it succeeded.
+    pub fn record_outcome(&self, problem: usize, num_critics: usize, iterations: usize) {
+        let mut state = self.state.lock().unwrap();
+        let outcome = match iterations {
+            0 => "failure",
+            255 => "divergence",
+            _ => "success",
+        };
+        *state
+            .runs
+            .entry((problem, num_critics, outcome))
+            .or_default() += 1;
+        if outcome == "success" {
+            state
+                .iterations
+                .entry((problem, num_critics))
+                .or_default()
+                .observe(iterations);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "ai_critics_runs_total",
+            "counter",
+            "Runs by outcome.",
+        );
+        for ((problem, critics, outcome), n) in state.runs.iter() {
+            let _ = writeln!(
+                out,
+                "ai_critics_runs_total{{problem=\"{}\",num_critics=\"{}\",outcome=\"{}\"}} {}",
+                problem, critics, outcome, n
+            );
+        }
+
+        header(
+            &mut out,
+            "ai_critics_iterations",
+            "histogram",
+            "Proposals needed by successful runs.",
+        );
+        for ((problem, critics), h) in state.iterations.iter() {
+            let labels = format!("problem=\"{}\",num_critics=\"{}\"", problem, critics);
+            for (bound, n) in ITERATION_BUCKETS.iter().zip(h.buckets) {
+                let _ = writeln!(
+                    out,
+                    "ai_critics_iterations_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, n
+                );
+            }
+            let _ = writeln!(
+                out,
+                "ai_critics_iterations_bucket{{{},le=\"+Inf\"}} {}",
+                labels, h.count
+            );
+            let _ = writeln!(out, "ai_critics_iterations_sum{{{}}} {}", labels, h.sum);
+            let _ = writeln!(out, "ai_critics_iterations_count{{{}}} {}", labels, h.count);
+        }
+
+        let counters = [
+            (
+                "ai_critics_api_retries_total",
+                "Retried API requests.",
+                &state.api_retries,
+            ),
+            (
+                "ai_critics_completion_tokens_total",
+                "Completion tokens received, counted as streamed chunks.",
+                &state.completion_tokens,
+            ),
+            (
+                "ai_critics_compile_failures_total",
+                "Proposals that failed to compile.",
+                &state.compile_failures,
+            ),
+            (
+                "ai_critics_test_failures_total",
+                "Proposals that compiled but failed their tests.",
+                &state.test_failures,
+            ),
+        ];
+        for (name, help, series) in counters {
+            header(&mut out, name, "counter", help);
+            for ((problem, critics), n) in series.iter() {
+                let _ = writeln!(
+                    out,
+                    "{}{{problem=\"{}\",num_critics=\"{}\"}} {}",
+                    name, problem, critics, n
+                );
+            }
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Serve the metrics on `GET /metrics` from a background thread for the rest of the batch.
+pub fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!(
+        "[collect_data] Serving metrics at http://{}/metrics",
+        listener.local_addr()?
+    );
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let metrics = metrics.clone();
+            thread::spawn(move || respond(stream, &metrics));
+        }
+    });
+    Ok(())
+}
+
+// Answer the request on its own thread, so that a client that doesn't send one holds up only its
+// own connection, and only until the read times out.
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let (status, body) = match request_line.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", String::new()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_events() {
+        let metrics = Metrics::default();
+        let stdout = indoc::indoc! {r#"
+            {"run_id":"1-1","iteration":0,"type":"chat_requested","agent":"Coder_1","model":"gpt-4o","messages":[],"prompt_tokens":1000}
+            {"run_id":"1-1","iteration":0,"type":"chat_responded","agent":"Coder_1","response":"{}","finish_reason":"stop","chunks":120}
+            {"run_id":"1-1","iteration":1,"type":"chat_retried","agent":"Fixer_1","attempt":1}
+            {"run_id":"1-1","iteration":1,"type":"test_failed","output":"error[E0308]","compile_error":true}
+            {"run_id":"1-1","iteration":2,"type":"test_failed","output":"panicked","compile_error":false}
+            Not JSON, e.g. a panic message.
+        "#};
+        let counts = EventCounts::from_json_lines(stdout.as_bytes());
+        assert_eq!(counts.completion_tokens, 120);
+        assert_eq!(counts.prompt_tokens, 1000);
+        // 1000 prompt tokens at $2.50 per million and 120 completion tokens at $10 per million.
+        assert!((counts.cost_usd - 0.0037).abs() < 1e-9);
+        metrics.record_counts(3, 1, &counts);
+        metrics.record_outcome(3, 1, 2);
+
+        let rendered = metrics.render();
+        for line in [
+            "ai_critics_runs_total{problem=\"3\",num_critics=\"1\",outcome=\"success\"} 1",
+            "ai_critics_iterations_bucket{problem=\"3\",num_critics=\"1\",le=\"1\"} 0",
+            "ai_critics_iterations_bucket{problem=\"3\",num_critics=\"1\",le=\"2\"} 1",
+            "ai_critics_iterations_sum{problem=\"3\",num_critics=\"1\"} 2",
+            "ai_critics_api_retries_total{problem=\"3\",num_critics=\"1\"} 1",
+            "ai_critics_completion_tokens_total{problem=\"3\",num_critics=\"1\"} 120",
+            "ai_critics_compile_failures_total{problem=\"3\",num_critics=\"1\"} 1",
+            "ai_critics_test_failures_total{problem=\"3\",num_critics=\"1\"} 1",
+        ] {
+            assert!(rendered.contains(line), "missing {}", line);
+        }
+    }
+
+    #[test]
+    fn test_idle_client_doesnt_block() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        serve(addr, Arc::new(Metrics::default())).unwrap();
+        // The first client connects and sends nothing.
+        let _idle = TcpStream::connect(addr).unwrap();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(REQUEST_TIMEOUT / 2)).unwrap();
+        write!(client, "GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut status = String::new();
+        BufReader::new(&client).read_line(&mut status).unwrap();
+        assert_eq!(status, "HTTP/1.1 200 OK\r\n");
+    }
+
+    #[test]
+    fn test_divergence_is_not_an_iteration_count() {
+        let metrics = Metrics::default();
+        metrics.record_outcome(1, 3, 255);
+        let rendered = metrics.render();
+        assert!(rendered.contains(
+            "ai_critics_runs_total{problem=\"1\",num_critics=\"3\",outcome=\"divergence\"} 1"
+        ));
+        assert!(!rendered.contains("ai_critics_iterations_count"));
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// Spaces out the starts of the runs shared by the parallel workers so that a large --jobs doesn't
+// start every run at once and trip the API's rate limits.
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    // Allow `per_minute` starts per minute. Zero means no limit.
+    pub fn per_minute(per_minute: u32) -> Self {
+        let interval = match per_minute {
+            0 => Duration::ZERO,
+            n => Duration::from_secs(60) / n,
+        };
+        RateLimiter {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    // Wait until the next start is allowed. The slot is reserved before sleeping so that concurrent
+    // callers queue up behind each other.
+    pub fn wait(&self) {
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let start = (*next).max(Instant::now());
+            *next = start + self.interval;
+            start
+        };
+        sleep(start.saturating_duration_since(Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_spaces_out_starts() {
+        let limiter = RateLimiter::per_minute(60 * 20); // One start every 50 ms.
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.wait();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let unlimited = RateLimiter::per_minute(0);
+        let start = Instant::now();
+        for _ in 0..100 {
+            unlimited.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
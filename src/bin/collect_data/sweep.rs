@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A sweep spec is a TOML file that defines the axes of an experiment, e.g.
+//
+//   problems = [1, 2, 3]
+//   num_critics = [1, 3, 5]
+//   models = ["gpt-4-1106-preview", "gpt-4o"]
+//   general_critic_only = [false, true]
+//   temperatures = [0.1, 0.7]
+//   self_review = [false, true]
+//   prompts_dirs = ["prompts", "prompts-terse"]
+//   iterations = 3
+//
+// Every combination of the axes' values is a cell, and each cell is run `iterations` times. An axis
+// that is left out takes its default: all the problems, 1, 3, and 5 critics, the specialized
+// critics, ai_critics' own model, temperature, and prompts, and no self-review by the coder.
+
+const NUM_PROBLEMS: usize = 8;
+const NUM_ITERATIONS: usize = 3;
+const NUM_CRITICS_VALUES: [usize; 3] = [1, 3, 5];
+const GENERAL_CRITIC_ONLY: bool = false;
+const PROBLEM_BASE: &str = "problems/coding_problem";
+const PROBLEM_SUFFIX: &str = ".txt";
+
+// The CSV columns of a cell's axes.
+pub const CSV_AXES: &str =
+    "Problem,NumCritics,Model,GeneralCriticOnly,Temperature,SelfReview,PromptsDir";
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sweep {
+    pub problems: Vec<usize>,
+    pub num_critics: Vec<usize>,
+    // Empty for the provider's default model.
+    pub models: Vec<String>,
+    pub general_critic_only: Vec<bool>,
+    // Empty for ai_critics' default temperature.
+    pub temperatures: Vec<f32>,
+    pub self_review: Vec<bool>,
+    // Directories of prompt templates, passed as --prompts-dir. Empty for the default prompts.
+    pub prompts_dirs: Vec<String>,
+    pub iterations: usize,
+}
+
+impl Default for Sweep {
+    fn default() -> Self {
+        Sweep {
+            problems: (1..=NUM_PROBLEMS).collect(),
+            num_critics: NUM_CRITICS_VALUES.to_vec(),
+            models: vec![],
+            general_critic_only: vec![GENERAL_CRITIC_ONLY],
+            temperatures: vec![],
+            self_review: vec![false],
+            prompts_dirs: vec![],
+            iterations: NUM_ITERATIONS,
+        }
+    }
+}
+
+// One combination of the axes' values.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Cell {
+    pub problem: usize,
+    pub num_critics: usize,
+    pub model: Option<String>,
+    pub general_critic_only: bool,
+    pub temperature: Option<f32>,
+    pub self_review: bool,
+    pub prompts_dir: Option<String>,
+}
+
+impl Sweep {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        toml::from_str(&fs::read_to_string(path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid sweep spec {}: {}", path.display(), e),
+            )
+        })
+    }
+
+    // The cells in the order that they run, with the problems varying fastest.
+    pub fn cells(&self) -> Vec<Cell> {
+        let models = optional(&self.models);
+        let temperatures = optional(&self.temperatures);
+        let prompts_dirs = optional(&self.prompts_dirs);
+        let mut cells = vec![];
+        for prompts_dir in prompts_dirs.iter() {
+            for model in models.iter() {
+                for temperature in temperatures.iter() {
+                    for &self_review in self.self_review.iter() {
+                        for &general_critic_only in self.general_critic_only.iter() {
+                            for &num_critics in self.num_critics.iter() {
+                                for &problem in self.problems.iter() {
+                                    cells.push(Cell {
+                                        problem,
+                                        num_critics,
+                                        model: model.clone(),
+                                        general_critic_only,
+                                        temperature: *temperature,
+                                        self_review,
+                                        prompts_dir: prompts_dir.clone(),
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        cells
+    }
+}
+
+// The values of an optional axis, where an empty axis is a single default value.
+fn optional<T: Clone>(values: &[T]) -> Vec<Option<T>> {
+    match values {
+        [] => vec![None],
+        values => values.iter().cloned().map(Some).collect(),
+    }
+}
+
+impl Cell {
+    // The ai_critics arguments that run the cell.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![
+            format!(
+                "--problem-file={}{}{}",
+                PROBLEM_BASE, self.problem, PROBLEM_SUFFIX
+            ),
+            format!("--num-critics={}", self.num_critics),
+        ];
+        if self.general_critic_only {
+            args.push("--general-critic-only".to_string());
+        }
+        if let Some(model) = &self.model {
+            args.push(format!("--model={}", model));
+        }
+        if let Some(temperature) = self.temperature {
+            args.push(format!("--temperature={}", temperature));
+        }
+        if self.self_review {
+            args.push("--self-review".to_string());
+        }
+        if let Some(dir) = &self.prompts_dir {
+            args.push(format!("--prompts-dir={}", dir));
+        }
+        args
+    }
+
+    // A name for the cell's files, such as problem4-critics3-gpt-4o-temp0.7.
+    pub fn file_stem(&self) -> String {
+        let mut stem = format!("problem{}-critics{}", self.problem, self.num_critics);
+        if let Some(model) = &self.model {
+            stem.push_str(&format!("-{}", model.replace(['/', '\\'], "_")));
+        }
+        if self.general_critic_only {
+            stem.push_str("-general");
+        }
+        if let Some(temperature) = self.temperature {
+            stem.push_str(&format!("-temp{}", temperature));
+        }
+        if self.self_review {
+            stem.push_str("-self-review");
+        }
+        if let Some(dir) = &self.prompts_dir {
+            let name = Path::new(dir).file_name().unwrap_or_default();
+            stem.push_str(&format!("-prompts-{}", name.to_string_lossy()));
+        }
+        stem
+    }
+
+    // The values of the cell's CSV_AXES. A default model, temperature, or prompts dir is left empty.
+    pub fn csv_axes(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.problem,
+            self.num_critics,
+            self.model.as_deref().unwrap_or_default(),
+            self.general_critic_only,
+            self.temperature.map(|t| t.to_string()).unwrap_or_default(),
+            self.self_review,
+            self.prompts_dir.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep_cells() {
+        let sweep: Sweep = toml::from_str(
+            r#"
+            problems = [2, 4]
+            models = ["gpt-4o"]
+            temperatures = [0.1, 0.7]
+            prompts_dirs = ["experiments/terse/"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(sweep.num_critics, NUM_CRITICS_VALUES);
+        let cells = sweep.cells();
+        assert_eq!(cells.len(), 2 * 3 * 2);
+        assert_eq!(
+            cells[1].csv_axes(),
+            "4,1,gpt-4o,false,0.1,false,experiments/terse/"
+        );
+        assert_eq!(
+            cells[1].file_stem(),
+            "problem4-critics1-gpt-4o-temp0.1-prompts-terse"
+        );
+        assert_eq!(
+            cells[1].args(),
+            [
+                "--problem-file=problems/coding_problem4.txt",
+                "--num-critics=1",
+                "--model=gpt-4o",
+                "--temperature=0.1",
+                "--prompts-dir=experiments/terse/",
+            ]
+        );
+        assert_eq!(Sweep::default().cells()[0].csv_axes(), "1,1,,false,,false,");
+
+        assert!(toml::from_str::<Sweep>("critics = [1]").is_err());
+    }
+}
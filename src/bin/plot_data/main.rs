@@ -0,0 +1,604 @@
+use ai_critics::stats::{summarize, Summary};
+use clap::{Parser, ValueEnum};
+use plotters::coord::types::{RangedCoordf32, RangedCoordu32};
+use plotters::coord::Shift;
+use plotters::{define_color, doc, prelude::*};
+use polars::datatypes::{DataType, Field};
+use polars::lazy::dsl::col;
+use polars::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod stats;
+
+const SYMBOL_SIZE: i32 = 5;
+// The plot is written to plot.png, plot.svg, or plot.pdf.
+const OUTPUT_STEM: &str = "plot";
+
+// The statistics of the iterations of each (problem, num_critics) cell.
+type Summaries = BTreeMap<(u32, u32), Summary>;
+
+define_color!(DARK_ORANGE, 255, 140, 0, "DarkOrange");
+define_color!(DARK_GREEN, 0, 100, 0, "DarkGreen");
+define_color!(DARK_BLUE, 0, 0, 139, "DarkBlue");
+define_color!(PURPLE, 128, 0, 128, "Purple");
+
+#[derive(Parser, Debug)]
+#[command(about = "Plot the data collected by collect_data versus the number of critics.")]
+struct Args {
+    /// CSV file written by collect_data.
+    file: PathBuf,
+
+    /// The value to plot for each problem.
+    #[arg(long, value_enum, default_value_t = Chart::Iterations)]
+    chart: Chart,
+
+    /// Print the mean, median, standard deviation, and 95% confidence interval of the iterations of
+    /// each problem and number of critics, and draw the intervals as error bars.
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// The runs file written by collect_data, which --stats reads.
+    #[arg(long, default_value = "runs_data.jsonl")]
+    runs: PathBuf,
+
+    /// The image format. PDFs are converted from SVG by rsvg-convert, which must be installed.
+    #[arg(long, value_enum, default_value_t = Format::Png)]
+    format: Format,
+
+    /// The image size in pixels, e.g. 1600x1200.
+    #[arg(long, value_parser = parse_size, default_value = "1024x768")]
+    size: (u32, u32),
+
+    /// The title of the plot instead of the chart's default.
+    #[arg(long)]
+    title: Option<String>,
+
+    /// The colors of the plot.
+    #[arg(long, value_enum, default_value_t = Theme::Light)]
+    theme: Theme,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Png,
+    Svg,
+    Pdf,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Svg => "svg",
+            Format::Pdf => "pdf",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    fn background(&self) -> RGBColor {
+        match self {
+            Theme::Light => WHITE,
+            Theme::Dark => RGBColor(30, 30, 30),
+        }
+    }
+
+    // The color of the text, axes, and legend border.
+    fn foreground(&self) -> RGBColor {
+        match self {
+            Theme::Light => BLACK,
+            Theme::Dark => RGBColor(220, 220, 220),
+        }
+    }
+
+    // The colors of the problems' lines. The dark colors of the light theme are lost on a dark
+    // background, so they are replaced with lighter ones.
+    fn palette(&self) -> [RGBColor; 7] {
+        match self {
+            Theme::Light => [
+                BLACK,
+                RED,
+                DARK_GREEN,
+                DARK_BLUE,
+                PURPLE,
+                MAGENTA,
+                DARK_ORANGE,
+            ],
+            Theme::Dark => [
+                self.foreground(),
+                RGBColor(255, 99, 71),
+                RGBColor(144, 238, 144),
+                RGBColor(135, 206, 250),
+                RGBColor(221, 160, 221),
+                MAGENTA,
+                DARK_ORANGE,
+            ],
+        }
+    }
+}
+
+// How the plot is drawn, from the command line.
+struct Style {
+    chart: Chart,
+    size: (u32, u32),
+    title: Option<String>,
+    theme: Theme,
+}
+
+// Parse an image size such as 1024x768.
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("expected WIDTHxHEIGHT, such as 1024x768, not {}", s);
+    let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+    match (width.trim().parse(), height.trim().parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Chart {
+    // The average iterations of the successful runs.
+    Iterations,
+    // The average cost of a run in dollars.
+    Cost,
+    // The average wall-clock time of a run in seconds.
+    Time,
+    // The fraction of the runs that succeeded.
+    SuccessRate,
+    // The fraction of the runs that diverged.
+    Divergence,
+}
+
+impl Chart {
+    // The column that process_data computes for the chart.
+    fn column(&self) -> &'static str {
+        match self {
+            Chart::Iterations => "AvgIterations",
+            Chart::Cost => "AvgCostUsd",
+            Chart::Time => "AvgDurationSecs",
+            Chart::SuccessRate => "SuccessRate",
+            Chart::Divergence => "DivergenceRate",
+        }
+    }
+
+    fn caption(&self) -> &'static str {
+        match self {
+            Chart::Iterations => "Iterations Required vs Number of Critics",
+            Chart::Cost => "Cost per Run ($) vs Number of Critics",
+            Chart::Time => "Time per Run (s) vs Number of Critics",
+            Chart::SuccessRate => "Success Rate vs Number of Critics",
+            Chart::Divergence => "Divergence Rate vs Number of Critics",
+        }
+    }
+}
+
+fn read_data(path: &Path) -> Result<DataFrame, PolarsError> {
+    // The sweep's other axes, such as the model, are read with their inferred types.
+    let schema = Schema::from_iter(vec![
+        Field::new("Problem", DataType::UInt32),
+        Field::new("NumCritics", DataType::UInt32),
+        Field::new("SuccessCount", DataType::UInt32),
+        Field::new("FailureCount", DataType::UInt32),
+        Field::new("DivergenceCount", DataType::UInt32),
+        Field::new("SuccessIterations", DataType::UInt32),
+    ]);
+    let df = CsvReader::from_path(path)?
+        .with_dtypes(Some(Arc::new(schema)))
+        .has_header(true)
+        .finish()?;
+    Ok(df)
+}
+
+fn process_data(df: DataFrame) -> Result<DataFrame, PolarsError> {
+    let runs = (col("SuccessCount") + col("FailureCount") + col("DivergenceCount"))
+        .cast(DataType::Float64);
+    // The totals of every run are averaged over all of them. Older files don't have these columns.
+    let averages = [
+        ("DurationSecs", "AvgDurationSecs"),
+        ("CostUsd", "AvgCostUsd"),
+    ]
+    .into_iter()
+    .filter(|(total, _)| df.get_column_names().contains(total))
+    .map(|(total, avg)| (col(total).cast(DataType::Float64) / runs.clone()).alias(avg))
+    .collect::<Vec<_>>();
+    let df = df
+        .lazy()
+        .with_column(
+            (col("SuccessIterations").cast(DataType::Float64)
+                / col("SuccessCount").cast(DataType::Float64))
+            .alias("AvgIterations"),
+        )
+        .with_columns([
+            (col("SuccessCount").cast(DataType::Float64) / runs.clone()).alias("SuccessRate"),
+            (col("DivergenceCount").cast(DataType::Float64) / runs.clone()).alias("DivergenceRate"),
+        ])
+        .with_columns(averages)
+        .collect()?;
+    let lf = df
+        .lazy()
+        .with_column(col("Problem").cast(DataType::UInt32))
+        .with_column(col("NumCritics").cast(DataType::UInt32))
+        .with_column(col("SuccessIterations").cast(DataType::UInt32))
+        .collect()?;
+    Ok(lf)
+}
+
+fn triangle_shape(color: &RGBColor) -> Polygon<(i32, i32)> {
+    Polygon::new(
+        vec![
+            (0, SYMBOL_SIZE),
+            (SYMBOL_SIZE, -SYMBOL_SIZE),
+            (-SYMBOL_SIZE, -SYMBOL_SIZE),
+        ],
+        ShapeStyle::from(color).filled(),
+    )
+}
+fn square_shape(color: &RGBColor) -> Polygon<(i32, i32)> {
+    Polygon::new(
+        vec![
+            (-SYMBOL_SIZE, SYMBOL_SIZE),
+            (SYMBOL_SIZE, SYMBOL_SIZE),
+            (SYMBOL_SIZE, -SYMBOL_SIZE),
+            (-SYMBOL_SIZE, -SYMBOL_SIZE),
+        ],
+        ShapeStyle::from(color).filled(),
+    )
+}
+
+fn diamond_shape(color: &RGBColor) -> Polygon<(i32, i32)> {
+    Polygon::new(
+        vec![
+            (-SYMBOL_SIZE, 0),
+            (0, SYMBOL_SIZE),
+            (SYMBOL_SIZE, 0),
+            (0, -SYMBOL_SIZE),
+        ],
+        ShapeStyle::from(color).filled(),
+    )
+}
+
+fn create_series(
+    problem: u32,
+    lf: &DataFrame,
+    column: &str,
+) -> Result<Vec<(u32, f64)>, Box<dyn Error>> {
+    let mask_expr = col("Problem").eq(lit(problem));
+    let filtered_data = lf.clone().lazy().filter(mask_expr).collect()?;
+    let critics_data: Vec<u32> = filtered_data
+        .column("NumCritics")?
+        .u32()?
+        .into_no_null_iter()
+        .collect();
+    let values: Vec<f64> = filtered_data
+        .column(column)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+    let line_data: Vec<(u32, f64)> = critics_data.into_iter().zip(values).collect();
+    Ok(line_data)
+}
+
+fn add_problem_to_plot<DB: DrawingBackend>(
+    problem: u32,
+    lf: &DataFrame,
+    column: &str,
+    summaries: Option<&Summaries>,
+    theme: Theme,
+    chart: &mut ChartContext<'_, DB, Cartesian2d<RangedCoordu32, RangedCoordf32>>,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let line_data = create_series(problem, lf, column)?;
+    let colors = theme.palette();
+    let color = colors[problem as usize % colors.len()];
+    chart
+        .draw_series(LineSeries::new(
+            line_data.iter().map(|&(x, y)| (x, y as f32)),
+            color,
+        ))?
+        .label(format!("Problem {}", problem))
+        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    chart.draw_series(line_data.iter().map(|&(x, y)| {
+        let shape = match problem % 3 {
+            0 => triangle_shape(&color),
+            1 => square_shape(&color),
+            _ => diamond_shape(&color),
+        };
+        EmptyElement::at((x, y as f32)) + shape
+    }))?;
+    if let Some(summaries) = summaries {
+        chart.draw_series(summaries.range((problem, 0)..=(problem, u32::MAX)).map(
+            |(&(_, num_critics), summary)| {
+                let (low, high) = summary.ci;
+                ErrorBar::new_vertical(
+                    num_critics,
+                    low as f32,
+                    summary.mean as f32,
+                    high as f32,
+                    color.filled(),
+                    10,
+                )
+            },
+        ))?;
+    }
+    Ok(())
+}
+
+// The ranges of the axes: the numbers of critics in the data, with a margin on each side so that the
+// end points aren't drawn on the axes, and from zero to a little above the largest value or
+// confidence interval.
+fn axis_ranges(
+    lf: &DataFrame,
+    column: &str,
+    summaries: Option<&Summaries>,
+) -> Result<((u32, u32), f32), Box<dyn Error>> {
+    let critics = lf.column("NumCritics")?.u32()?;
+    let x_min = critics.min().unwrap_or(0).saturating_sub(1);
+    let x_max = critics.max().unwrap_or(0) + 1;
+    let data_max = lf.column(column)?.f64()?.max().unwrap_or(0.0);
+    let ci_max = summaries
+        .into_iter()
+        .flat_map(|s| s.values().map(|summary| summary.ci.1))
+        .fold(data_max, f64::max);
+    let y_max = match ci_max > 0.0 {
+        true => ci_max * 1.1,
+        false => 1.0,
+    };
+    Ok(((x_min, x_max), y_max as f32))
+}
+
+// Plot the chart for each problem on the drawing area. The summaries' confidence intervals are drawn
+// as error bars on the iterations chart.
+fn draw_plot<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    lf: &DataFrame,
+    style: &Style,
+    summaries: Option<&Summaries>,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let kind = style.chart;
+    let summaries = summaries.filter(|_| kind == Chart::Iterations);
+    let ((x_min, x_max), y_max) = axis_ranges(lf, kind.column(), summaries)?;
+    let (background, foreground) = (style.theme.background(), style.theme.foreground());
+    let title = style.title.as_deref().unwrap_or(kind.caption());
+
+    root.fill(&background)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 40).into_font().color(&foreground))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(x_min..x_max, 0f32..y_max)?;
+    chart
+        .configure_mesh()
+        .axis_style(foreground)
+        .bold_line_style(foreground.mix(0.2))
+        .light_line_style(foreground.mix(0.05))
+        .label_style(("sans-serif", 15).into_font().color(&foreground))
+        .draw()?;
+
+    let unique_problems: Vec<u32> = lf
+        .column("Problem")?
+        .unique()?
+        .u32()?
+        .into_no_null_iter()
+        .collect();
+    for &problem in unique_problems.iter() {
+        add_problem_to_plot(
+            problem,
+            lf,
+            kind.column(),
+            summaries,
+            style.theme,
+            &mut chart,
+        )?;
+    }
+    chart
+        .configure_series_labels()
+        .background_style(background.mix(0.8))
+        .border_style(foreground)
+        .label_font(("sans-serif", 15).into_font().color(&foreground))
+        .draw()?;
+    drop(chart);
+    root.present()?;
+    Ok(())
+}
+
+// Write the plot to the file in the format. Plotters can't draw PDFs, so the plot is drawn as an SVG
+// and converted.
+fn create_plot(
+    lf: DataFrame,
+    style: &Style,
+    format: Format,
+    path: &Path,
+    summaries: Option<&Summaries>,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Png => {
+            let root = BitMapBackend::new(path, style.size).into_drawing_area();
+            draw_plot(root, &lf, style, summaries)
+        }
+        Format::Svg => {
+            let root = SVGBackend::new(path, style.size).into_drawing_area();
+            draw_plot(root, &lf, style, summaries)
+        }
+        Format::Pdf => {
+            let svg = tempfile::Builder::new().suffix(".svg").tempfile()?;
+            create_plot(lf, style, Format::Svg, svg.path(), summaries)?;
+            let status = Command::new("rsvg-convert")
+                .args(["--format", "pdf", "--output"])
+                .arg(path)
+                .arg(svg.path())
+                .status()
+                .map_err(|e| format!("Writing a PDF requires rsvg-convert (librsvg): {}", e))?;
+            match status.success() {
+                true => Ok(()),
+                false => Err(format!("rsvg-convert failed with {}", status).into()),
+            }
+        }
+    }
+}
+
+// Summarize the iterations of the runs and print the statistics.
+fn read_summaries(runs: &Path) -> Result<Summaries, Box<dyn Error>> {
+    let summaries: Summaries = stats::read_runs(runs)?
+        .into_iter()
+        .filter_map(|(key, values)| Some((key, summarize(&values)?)))
+        .collect();
+    println!("Problem  NumCritics   N    Mean  Median  StdDev  95% CI");
+    for ((problem, num_critics), s) in summaries.iter() {
+        println!(
+            "{:>7}  {:>10}  {:>2}  {:>6.2}  {:>6.2}  {:>6.2}  [{:.2}, {:.2}]",
+            problem, num_critics, s.n, s.mean, s.median, s.stddev, s.ci.0, s.ci.1
+        );
+    }
+    Ok(summaries)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let df = read_data(&args.file)?;
+
+    let lf = process_data(df)?;
+
+    let summaries = match args.stats {
+        true => Some(read_summaries(&args.runs)?),
+        false => None,
+    };
+    let style = Style {
+        chart: args.chart,
+        size: args.size,
+        title: args.title,
+        theme: args.theme,
+    };
+    let output = PathBuf::from(OUTPUT_STEM).with_extension(args.format.extension());
+    create_plot(lf, &style, args.format, &output, summaries.as_ref())?;
+
+    // Display the result.
+    if cfg!(target_os = "macos") {
+        Command::new("open")
+            .arg(&output)
+            .status()
+            .expect("Failed to open image");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mock data for testing
+    fn mock_data_frame() -> DataFrame {
+        let s0 = Series::new("Problem", &[1, 2, 1, 2]);
+        let s1 = Series::new("NumCritics", &[3, 4, 3, 4]);
+        let s2 = Series::new("SuccessCount", &[2, 2, 2, 2]);
+        let s3 = Series::new("FailureCount", &[0, 0, 1, 0]);
+        let s4 = Series::new("DivergenceCount", &[0, 1, 0, 0]);
+        let s5 = Series::new("SuccessIterations", &[10, 20, 10, 20]);
+        let s6 = Series::new("DurationSecs", &[60.0, 90.0, 75.0, 40.0]);
+        let s7 = Series::new("CostUsd", &[0.2, 0.3, 0.25, 0.1]);
+        DataFrame::new(vec![s0, s1, s2, s3, s4, s5, s6, s7]).expect("Failed to create DataFrame")
+    }
+
+    fn style(chart: Chart) -> Style {
+        Style {
+            chart,
+            size: (1024, 768),
+            title: None,
+            theme: Theme::Light,
+        }
+    }
+
+    #[test]
+    fn test_process_data() {
+        let df = mock_data_frame();
+        let result = process_data(df).expect("Failed to process data");
+        assert!(result.column("Problem").is_ok());
+        assert!(result.column("NumCritics").is_ok());
+        assert!(result.column("SuccessCount").is_ok());
+        assert!(result.column("FailureCount").is_ok());
+        assert!(result.column("DivergenceCount").is_ok());
+        assert!(result.column("AvgIterations").is_ok());
+        // Averaged over the 3 runs of problem 1 with 3 critics.
+        let avg_cost = result.column("AvgCostUsd").unwrap().f64().unwrap();
+        assert!((avg_cost.get(2).unwrap() - 0.25 / 3.0).abs() < 1e-9);
+        // Problem 2 with 4 critics succeeded twice and diverged once.
+        let success_rate = result.column("SuccessRate").unwrap().f64().unwrap();
+        assert!((success_rate.get(1).unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        let divergence_rate = result.column("DivergenceRate").unwrap().f64().unwrap();
+        assert!((divergence_rate.get(1).unwrap() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(divergence_rate.get(0), Some(0.0));
+    }
+
+    #[test]
+    fn test_create_plot() {
+        let df = mock_data_frame();
+        let lf = process_data(df).expect("Failed to process data");
+        let dir = tempfile::tempdir().unwrap();
+        let png = dir.path().join("plot.png");
+        let iterations = style(Chart::Iterations);
+        assert!(create_plot(lf.clone(), &iterations, Format::Png, &png, None).is_ok());
+        assert!(png.exists());
+
+        let cost = Style {
+            title: Some("Cost".to_string()),
+            theme: Theme::Dark,
+            size: (400, 300),
+            ..style(Chart::Cost)
+        };
+        let svg = dir.path().join("plot.svg");
+        assert!(create_plot(lf.clone(), &cost, Format::Svg, &svg, None).is_ok());
+        let contents = std::fs::read_to_string(&svg).unwrap();
+        assert!(contents.contains("width=\"400\""));
+        assert!(contents.contains("Cost") && !contents.contains(Chart::Cost.caption()));
+
+        let divergence = style(Chart::Divergence);
+        assert!(create_plot(lf.clone(), &divergence, Format::Png, &png, None).is_ok());
+
+        let summaries = Summaries::from([((1, 3), summarize(&[4.0, 5.0, 6.0]).unwrap())]);
+        assert!(create_plot(lf, &iterations, Format::Png, &png, Some(&summaries)).is_ok());
+    }
+
+    #[test]
+    fn test_axis_ranges() {
+        let lf = process_data(mock_data_frame()).unwrap();
+        // The iterations average 5 and 10, and the critics range from 3 to 4.
+        let ((x_min, x_max), y_max) = axis_ranges(&lf, "AvgIterations", None).unwrap();
+        assert_eq!((x_min, x_max), (2, 5));
+        assert!((y_max - 11.0).abs() < 1e-4);
+
+        let summaries = Summaries::from([(
+            (1, 3),
+            Summary {
+                n: 3,
+                mean: 5.0,
+                median: 5.0,
+                stddev: 1.0,
+                ci: (4.0, 20.0),
+            },
+        )]);
+        let (_, y_max) = axis_ranges(&lf, "AvgIterations", Some(&summaries)).unwrap();
+        assert!((y_max - 22.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1600x1200"), Ok((1600, 1200)));
+        assert!(parse_size("1600").is_err());
+        assert!(parse_size("0x100").is_err());
+    }
+}
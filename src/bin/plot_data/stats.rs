@@ -0,0 +1,30 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The iterations of the successful runs of each (problem, num_critics) cell, read from the runs
+// that collect_data writes to runs_data.jsonl, for ai_critics::stats to summarize.
+
+#[derive(Deserialize)]
+struct Run {
+    problem: u32,
+    num_critics: u32,
+    iterations: usize,
+}
+
+// Read the iterations of the successful runs, grouped by (problem, num_critics).
+pub fn read_runs(path: &Path) -> io::Result<BTreeMap<(u32, u32), Vec<f64>>> {
+    let mut runs: BTreeMap<(u32, u32), Vec<f64>> = BTreeMap::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let run: Run = serde_json::from_str(line)?;
+        // 0 is an error and 255 a divergence.
+        if run.iterations != 0 && run.iterations != 255 {
+            runs.entry((run.problem, run.num_critics))
+                .or_default()
+                .push(run.iterations as f64);
+        }
+    }
+    Ok(runs)
+}
@@ -0,0 +1,201 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// The history of a run, read from the events in its transcript: the problem, each proposal with the
+// critics' reviews, fixes, and test failure, the final code, and the API's anomalies. Only the events
+// shown in the report are parsed. The rest, and lines that aren't events, are skipped.
+
+#[derive(Default, Debug, PartialEq)]
+pub struct History {
+    pub problem: Option<String>,
+    pub proposals: Vec<Proposal>,
+    pub final_code: Option<String>,
+    pub outcome: Option<String>,
+    // The number of each kind of API anomaly, such as length_stop.
+    pub anomalies: BTreeMap<String, usize>,
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct Proposal {
+    pub number: usize,
+    pub code: String,
+    pub reviews: Vec<Review>,
+    pub fixes: Vec<Fix>,
+    pub test_failure: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Review {
+    pub critic: String,
+    pub lgtm: bool,
+    pub corrections: Vec<String>,
+    pub evidence: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Fix {
+    pub fixer: String,
+    pub code: String,
+    pub similarity: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    ProblemLoaded {
+        problem: String,
+    },
+    ProposalCreated {
+        number: usize,
+        code: String,
+    },
+    CriticFinished {
+        critic: String,
+        lgtm: bool,
+        corrections: Vec<String>,
+        #[serde(default)]
+        evidence: Vec<String>,
+    },
+    CriticFailed {
+        critic: String,
+        error: String,
+    },
+    FixApplied {
+        fixer: String,
+        code: String,
+        #[serde(default)]
+        similarity: Option<f64>,
+    },
+    TestFailed {
+        output: String,
+    },
+    TestPassed {
+        code: String,
+    },
+    RunFinished {
+        outcome: String,
+    },
+    ApiAnomaly {
+        anomaly: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl History {
+    pub fn from_json_lines(text: &str) -> Self {
+        let mut history = History::default();
+        for line in text.lines() {
+            let Ok(event) = serde_json::from_str::<Event>(line) else {
+                continue;
+            };
+            history.add(event);
+        }
+        history
+    }
+
+    fn add(&mut self, event: Event) {
+        match event {
+            Event::ProblemLoaded { problem } => self.problem = Some(problem),
+            Event::ProposalCreated { number, code } => self.proposals.push(Proposal {
+                number,
+                code,
+                ..Default::default()
+            }),
+            Event::RunFinished { outcome } => self.outcome = Some(outcome),
+            Event::TestPassed { code } => self.final_code = Some(code),
+            Event::ApiAnomaly { anomaly } => *self.anomalies.entry(anomaly).or_default() += 1,
+            event => {
+                // The rest belong to the latest proposal.
+                let Some(proposal) = self.proposals.last_mut() else {
+                    return;
+                };
+                match event {
+                    Event::CriticFinished {
+                        critic,
+                        lgtm,
+                        corrections,
+                        evidence,
+                    } => proposal.reviews.push(Review {
+                        critic,
+                        lgtm,
+                        corrections,
+                        evidence,
+                    }),
+                    Event::CriticFailed { critic, error } => proposal.reviews.push(Review {
+                        critic,
+                        lgtm: false,
+                        corrections: vec![format!("The critic failed: {}", error)],
+                        evidence: vec![],
+                    }),
+                    Event::FixApplied {
+                        fixer,
+                        code,
+                        similarity,
+                    } => proposal.fixes.push(Fix {
+                        fixer,
+                        code,
+                        similarity,
+                    }),
+                    Event::TestFailed { output } => proposal.test_failure = Some(output),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_json_lines() {
+        let transcript = r#"{"run_id":"r","iteration":0,"type":"problem_loaded","path":"p.txt","problem":"Add two numbers."}
+{"run_id":"r","iteration":1,"type":"proposal_created","number":1,"code":"fn add() {}"}
+{"run_id":"r","iteration":1,"type":"critic_finished","critic":"General Critic","lgtm":false,"corrections":["Take two arguments."]}
+{"run_id":"r","iteration":1,"type":"critic_failed","critic":"Style Critic","error":"timed out"}
+{"run_id":"r","iteration":1,"type":"chat_retried","agent":"Coder","attempt":1}
+{"run_id":"r","iteration":1,"type":"api_anomaly","agent":"Coder","anomaly":"blank_flood"}
+not an event
+{"run_id":"r","iteration":2,"type":"proposal_created","number":2,"code":"fn add(a: i32, b: i32) -> i32 { a + b }"}
+{"run_id":"r","iteration":2,"type":"test_failed","output":"error[E0308]","compile_error":true}
+{"run_id":"r","iteration":3,"type":"proposal_created","number":3,"code":"fn add(a: i64, b: i64) -> i64 { a + b }"}
+{"run_id":"r","iteration":3,"type":"test_passed","proposal":3,"code":"fn add(a: i64, b: i64) -> i64 { a + b }","output":"ok"}
+{"run_id":"r","iteration":3,"type":"run_finished","outcome":"Solved after 3 proposals"}
+"#;
+        let history = History::from_json_lines(transcript);
+        assert_eq!(history.problem.as_deref(), Some("Add two numbers."));
+        assert_eq!(history.proposals.len(), 3);
+        assert_eq!(
+            history.proposals[0].reviews,
+            [
+                Review {
+                    critic: "General Critic".to_string(),
+                    lgtm: false,
+                    corrections: vec!["Take two arguments.".to_string()],
+                    evidence: vec![],
+                },
+                Review {
+                    critic: "Style Critic".to_string(),
+                    lgtm: false,
+                    corrections: vec!["The critic failed: timed out".to_string()],
+                    evidence: vec![],
+                },
+            ]
+        );
+        assert_eq!(
+            history.proposals[1].test_failure.as_deref(),
+            Some("error[E0308]")
+        );
+        assert_eq!(
+            history.final_code.as_deref(),
+            Some("fn add(a: i64, b: i64) -> i64 { a + b }")
+        );
+        assert_eq!(history.outcome.as_deref(), Some("Solved after 3 proposals"));
+        assert_eq!(
+            history.anomalies,
+            BTreeMap::from([("blank_flood".to_string(), 1)])
+        );
+    }
+}
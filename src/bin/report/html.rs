@@ -0,0 +1,331 @@
+use crate::history::History;
+use crate::Run;
+use base64::Engine;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+// The report is a single HTML file with no external resources, so that it can be archived or mailed
+// with the experiment: the plots are embedded, SVGs inline and PNGs as data URLs. Each problem and
+// each of its runs is a <details> element, so the page opens on the results and plots and the
+// histories of the runs are expanded on demand.
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 70em; color: #222; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: right; }
+th { background: #f0f0f0; }
+details { margin: 0.4em 0; }
+details details { margin-left: 1.5em; }
+summary { cursor: pointer; }
+pre { background: #f6f6f6; padding: 0.6em; overflow-x: auto; }
+.plot svg, .plot img { max-width: 100%; height: auto; }
+.lgtm { color: #060; }
+.rejected { color: #a00; }
+";
+
+// The CSV written by collect_data, as text. Its values never contain commas.
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn parse(csv: &str) -> Self {
+        let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+        let split = |line: &str| line.split(',').map(|v| v.trim().to_string()).collect();
+        Table {
+            header: lines.next().map(split).unwrap_or_default(),
+            rows: lines.map(split).collect(),
+        }
+    }
+}
+
+pub enum Plot {
+    Svg(String),
+    // Base64-encoded.
+    Png(String),
+}
+
+impl Plot {
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("svg") => {
+                let svg = fs::read_to_string(path)?;
+                // The XML declaration isn't allowed inside the HTML.
+                let svg = match svg.trim_start().strip_prefix("<?xml") {
+                    Some(rest) => rest.split_once("?>").map_or("", |(_, svg)| svg).to_string(),
+                    None => svg,
+                };
+                Ok(Plot::Svg(svg))
+            }
+            Some("png") => Ok(Plot::Png(
+                base64::engine::general_purpose::STANDARD.encode(fs::read(path)?),
+            )),
+            _ => Err(format!("{}: only PNG and SVG plots can be included", path.display()).into()),
+        }
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn render(table: &Table, plots: &[Plot], problems: &BTreeMap<usize, Vec<Run>>) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>ai_critics report</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>ai_critics Experiment Report</h1>\n",
+        STYLE
+    );
+
+    html.push_str("<h2>Results</h2>\n<table>\n<tr>");
+    for column in table.header.iter() {
+        let _ = write!(html, "<th>{}</th>", escape(column));
+    }
+    html.push_str("</tr>\n");
+    for row in table.rows.iter() {
+        html.push_str("<tr>");
+        for value in row.iter() {
+            let _ = write!(html, "<td>{}</td>", escape(value));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+
+    if !plots.is_empty() {
+        html.push_str("<h2>Plots</h2>\n");
+        for plot in plots {
+            match plot {
+                Plot::Svg(svg) => {
+                    let _ = writeln!(html, "<div class=\"plot\">{}</div>", svg);
+                }
+                Plot::Png(data) => {
+                    let _ = writeln!(
+                        html,
+                        "<div class=\"plot\"><img src=\"data:image/png;base64,{}\"></div>",
+                        data
+                    );
+                }
+            }
+        }
+    }
+
+    render_anomalies(&mut html, problems);
+
+    html.push_str("<h2>Problems</h2>\n");
+    for (problem, runs) in problems.iter() {
+        render_problem(&mut html, *problem, runs);
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// The mean number of each kind of API anomaly per run, for each cell of the sweep, so that the
+// models can be compared on how often they misbehave. Only the runs with transcripts are counted.
+fn render_anomalies(html: &mut String, problems: &BTreeMap<usize, Vec<Run>>) {
+    let mut cells: BTreeMap<String, Vec<&History>> = BTreeMap::new();
+    for run in problems.values().flatten() {
+        if let Some(history) = &run.history {
+            cells.entry(run.cell()).or_default().push(history);
+        }
+    }
+    let kinds: BTreeSet<&String> = cells
+        .values()
+        .flatten()
+        .flat_map(|history| history.anomalies.keys())
+        .collect();
+    if kinds.is_empty() {
+        return;
+    }
+    html.push_str("<h2>API Anomalies per Run</h2>\n<table>\n<tr><th>Cell</th><th>Runs</th>");
+    for kind in kinds.iter() {
+        let _ = write!(html, "<th>{}</th>", escape(kind));
+    }
+    html.push_str("</tr>\n");
+    for (cell, histories) in cells.iter() {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td>",
+            escape(cell),
+            histories.len()
+        );
+        for kind in kinds.iter() {
+            let total: usize = histories
+                .iter()
+                .filter_map(|history| history.anomalies.get(*kind))
+                .sum();
+            let _ = write!(
+                html,
+                "<td>{:.2}</td>",
+                total as f64 / histories.len() as f64
+            );
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+fn render_problem(html: &mut String, problem: usize, runs: &[Run]) {
+    let count = |f: fn(&Run) -> bool| runs.iter().filter(|run| f(run)).count();
+    let _ = writeln!(
+        html,
+        "<details>\n<summary><b>Problem {}</b>: {} runs, {} solved, {} diverged, {} errors</summary>",
+        problem,
+        runs.len(),
+        count(|run| !matches!(run.iterations, 0 | 255)),
+        count(|run| run.iterations == 255),
+        count(|run| run.iterations == 0),
+    );
+    let statement = runs
+        .iter()
+        .find_map(|run| run.history.as_ref()?.problem.as_ref());
+    if let Some(statement) = statement {
+        let _ = writeln!(html, "<pre>{}</pre>", escape(statement));
+    }
+    for run in runs {
+        render_run(html, run);
+    }
+    html.push_str("</details>\n");
+}
+
+fn render_run(html: &mut String, run: &Run) {
+    let _ = writeln!(
+        html,
+        "<details>\n<summary>{}, run {}: {}, {:.1} s, ${:.4}</summary>",
+        escape(&run.cell()),
+        run.iteration,
+        run.outcome(),
+        run.duration_secs,
+        run.cost_usd
+    );
+    if let Some(error) = &run.error {
+        let _ = writeln!(html, "<p class=\"rejected\">{}</p>", escape(error));
+    }
+    match &run.history {
+        Some(history) => render_history(html, history),
+        None => html.push_str("<p>No transcript.</p>\n"),
+    }
+    html.push_str("</details>\n");
+}
+
+fn render_history(html: &mut String, history: &History) {
+    for proposal in history.proposals.iter() {
+        let _ = writeln!(
+            html,
+            "<h4>Proposal #{}</h4>\n<pre>{}</pre>",
+            proposal.number,
+            escape(&proposal.code)
+        );
+        if !proposal.reviews.is_empty() {
+            html.push_str("<ul>\n");
+            for review in proposal.reviews.iter() {
+                let (class, verdict) = match review.lgtm {
+                    true => ("lgtm", "approved"),
+                    false => ("rejected", "rejected"),
+                };
+                let _ = write!(
+                    html,
+                    "<li>{}: <span class=\"{}\">{}</span>",
+                    escape(&review.critic),
+                    class,
+                    verdict
+                );
+                if !review.corrections.is_empty() || !review.evidence.is_empty() {
+                    html.push_str("<ul>");
+                    for correction in review.corrections.iter() {
+                        let _ = write!(html, "<li>{}</li>", escape(correction));
+                    }
+                    for example in review.evidence.iter() {
+                        let _ = write!(html, "<li>Traced: {}</li>", escape(example));
+                    }
+                    html.push_str("</ul>");
+                }
+                html.push_str("</li>\n");
+            }
+            html.push_str("</ul>\n");
+        }
+        for fix in proposal.fixes.iter() {
+            let kept = match fix.similarity {
+                Some(similarity) => format!(", keeping {:.0}% of the lines", similarity * 100.0),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                html,
+                "<p>Fixed by {}{}:</p>\n<pre>{}</pre>",
+                escape(&fix.fixer),
+                kept,
+                escape(&fix.code)
+            );
+        }
+        if let Some(output) = &proposal.test_failure {
+            let _ = writeln!(
+                html,
+                "<p class=\"rejected\">The tests failed:</p>\n<pre>{}</pre>",
+                escape(output)
+            );
+        }
+    }
+    if let Some(code) = &history.final_code {
+        let _ = writeln!(html, "<h4>Final code</h4>\n<pre>{}</pre>", escape(code));
+    }
+    if let Some(outcome) = &history.outcome {
+        let _ = writeln!(html, "<p>{}</p>", escape(outcome));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let table = Table::parse("Problem,NumCritics,SuccessCount\n1,3,2\n");
+        let history = History::from_json_lines(
+            r#"{"type":"proposal_created","number":1,"code":"fn f() -> Vec<u8> { vec![] }"}
+{"type":"critic_finished","critic":"General Critic","lgtm":true,"corrections":[]}
+{"type":"api_anomaly","agent":"Coder","anomaly":"length_stop"}
+{"type":"test_passed","proposal":1,"code":"fn f() -> Vec<u8> { vec![] }","output":"ok"}"#,
+        );
+        let runs = vec![
+            Run {
+                problem: 1,
+                num_critics: 3,
+                iteration: 1,
+                iterations: 1,
+                history: Some(history),
+                ..Default::default()
+            },
+            Run {
+                problem: 1,
+                num_critics: 3,
+                iteration: 2,
+                iterations: 255,
+                ..Default::default()
+            },
+        ];
+        let problems = BTreeMap::from([(1, runs)]);
+        let plots = [Plot::Svg("<svg></svg>".to_string())];
+
+        let html = render(&table, &plots, &problems);
+        assert!(html.contains("<th>NumCritics</th>"));
+        assert!(html.contains("<td>2</td>"));
+        assert!(html.contains("<div class=\"plot\"><svg></svg></div>"));
+        assert!(html.contains("Problem 1</b>: 2 runs, 1 solved, 1 diverged, 0 errors"));
+        assert!(html.contains("3 critics, run 1: solved with 1 proposals"));
+        assert!(html.contains("<pre>fn f() -&gt; Vec&lt;u8&gt; { vec![] }</pre>"));
+        assert!(html.contains("General Critic: <span class=\"lgtm\">approved</span>"));
+        assert!(html.contains("3 critics, run 2: diverged"));
+        assert!(html.contains("No transcript."));
+        assert!(html.contains(
+            "<tr><th>Cell</th><th>Runs</th><th>length_stop</th></tr>\n\
+             <tr><td>3 critics</td><td>1</td><td>1.00</td></tr>"
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use clap::Parser;
+use history::History;
+use html::{Plot, Table};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+mod history;
+mod html;
+
+//
+// After collecting the data and plotting it, run like:
+// $ cargo run --bin report -- iterations_data.csv --plot plot.svg
+//
+// The report is written to report.html. It reads the runs from runs_data.jsonl and each run's
+// transcript from the file recorded there.
+
+#[derive(Parser, Debug)]
+#[command(about = "Combine the data, transcripts, and plots of collect_data into an HTML report.")]
+struct Args {
+    /// CSV file written by collect_data.
+    file: PathBuf,
+
+    /// The runs file written by collect_data.
+    #[arg(long, default_value = "runs_data.jsonl")]
+    runs: PathBuf,
+
+    /// A PNG or SVG plot to include, such as one written by plot_data. May be repeated.
+    #[arg(long)]
+    plot: Vec<PathBuf>,
+
+    /// The HTML file to write.
+    #[arg(long, default_value = "report.html")]
+    output: PathBuf,
+}
+
+// A line of the runs file written by collect_data. The columns added since the first version of the
+// file default to empty.
+#[derive(Deserialize, Debug, Default)]
+pub struct Run {
+    problem: usize,
+    num_critics: usize,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    general_critic_only: bool,
+    #[serde(default)]
+    temperature: Option<f32>,
+    #[serde(default)]
+    self_review: bool,
+    #[serde(default)]
+    prompts_dir: Option<String>,
+    iteration: usize,
+    // The number of proposals, 0 for an error, or 255 for a divergence.
+    iterations: usize,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    duration_secs: f64,
+    #[serde(default)]
+    cost_usd: f64,
+    #[serde(default)]
+    transcript: Option<String>,
+    // Read from the transcript.
+    #[serde(skip)]
+    history: Option<History>,
+}
+
+impl Run {
+    fn outcome(&self) -> String {
+        match self.iterations {
+            0 => "error".to_string(),
+            255 => "diverged".to_string(),
+            n => format!("solved with {} proposals", n),
+        }
+    }
+
+    // The cell of the sweep that the run belongs to, such as "3 critics, gpt-4o, temperature 0.7".
+    fn cell(&self) -> String {
+        let mut cell = format!("{} critics", self.num_critics);
+        if let Some(model) = &self.model {
+            cell.push_str(&format!(", {}", model));
+        }
+        if self.general_critic_only {
+            cell.push_str(", general critic only");
+        }
+        if let Some(temperature) = self.temperature {
+            cell.push_str(&format!(", temperature {}", temperature));
+        }
+        if self.self_review {
+            cell.push_str(", self-review");
+        }
+        if let Some(dir) = &self.prompts_dir {
+            cell.push_str(&format!(", prompts {}", dir));
+        }
+        cell
+    }
+}
+
+// Read the runs and their transcripts, grouped by problem. A missing transcript is reported and
+// the run is shown without its history.
+fn read_runs(text: &str) -> Result<BTreeMap<usize, Vec<Run>>, Box<dyn Error>> {
+    let mut problems: BTreeMap<usize, Vec<Run>> = BTreeMap::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let mut run: Run = serde_json::from_str(line)?;
+        if let Some(path) = &run.transcript {
+            match fs::read_to_string(path) {
+                Ok(transcript) => run.history = Some(History::from_json_lines(&transcript)),
+                Err(e) => println!("[report] Skipping the transcript {}: {}", path, e),
+            }
+        }
+        problems.entry(run.problem).or_default().push(run);
+    }
+    Ok(problems)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let table = Table::parse(&fs::read_to_string(&args.file)?);
+    let problems = read_runs(&fs::read_to_string(&args.runs)?)?;
+    let plots = args
+        .plot
+        .iter()
+        .map(|path| Plot::read(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    fs::write(&args.output, html::render(&table, &plots, &problems))?;
+    println!("[report] Wrote {}.", args.output.display());
+    Ok(())
+}
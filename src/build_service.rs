@@ -0,0 +1,400 @@
+use crate::errors::AiCriticError;
+use crate::processes;
+use clap::ValueEnum;
+use color_eyre::eyre::Result;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+// Compiling a single file of code with rustc and running its tests, for the tester, which judges the
+// proposals, and the mutation tester, which judges their tests. The results say what happened,
+// leaving what it means, such as the review that the fixer is given, to the callers.
+
+const WASM_TARGET: &str = "wasm32-wasip1";
+// wasmtime exits with this code when the module traps. Under WASI, panics abort, so a failed test
+// traps rather than exiting with libtest's 101.
+const WASM_TRAP_EXIT_CODE: i32 = 134;
+// The variable that holds the test seed, if one is given, for code that seeds its random numbers
+// from the environment.
+pub const TEST_SEED_VAR: &str = "AI_CRITICS_TEST_SEED";
+// The default number of bytes of stdout and of stderr that a test or program may print before it's
+// killed.
+pub const DEFAULT_MAX_OUTPUT: usize = 64 * 1024;
+// The default time that a test harness may run before it's killed.
+pub const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Where the generated code runs. With `wasm`, it's compiled for WASI and run under wasmtime, which
+// grants it no filesystem or network access, isolating it from the machine on any platform.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Sandbox {
+    #[default]
+    None,
+    Wasm,
+}
+
+pub enum Build {
+    Built { exec_path: PathBuf, stdout: String },
+    Failed { stderr: String },
+}
+
+pub enum TestRun {
+    Passed { stdout: String },
+    // The test harness's output, without any stack trace.
+    Failed { output: String },
+    // The test harness was killed by the signal, as on a stack overflow, with its output.
+    Crashed { signal: i32, output: String },
+    // The test harness exited with a code other than a test failure's, with its output.
+    Exited { code: i32, output: String },
+    // The test harness printed more than the limit and was killed, with its output up to there.
+    OutputExceeded { limit: usize, output: String },
+    // The test harness ran longer than the limit and was killed.
+    TimedOut { limit: Duration },
+}
+
+pub struct BuildService {
+    // A directory kept for the service's lifetime, in which each build is compiled at the same path
+    // with rustc's incremental compilation. Successive proposals usually share most of their code,
+    // so later compiles reuse much of the earlier work. The builds are done one at a time.
+    build_dir: Option<TempDir>,
+    sandbox: Sandbox,
+    test_seed: Option<u64>,
+    // The most bytes of stdout and of stderr that the tests or a program may print.
+    max_output: usize,
+    // The longest that the tests may run.
+    test_timeout: Duration,
+    // Whether the compiler's output is written beside the code, for a tester that keeps its builds.
+    keeps_output: bool,
+}
+
+impl Default for BuildService {
+    fn default() -> Self {
+        BuildService {
+            build_dir: None,
+            sandbox: Sandbox::default(),
+            test_seed: None,
+            max_output: DEFAULT_MAX_OUTPUT,
+            test_timeout: DEFAULT_TEST_TIMEOUT,
+            keeps_output: false,
+        }
+    }
+}
+
+impl BuildService {
+    // Keep a build directory across the builds, as for the proposals of a run.
+    pub fn with_build_cache(self) -> Result<Self> {
+        Ok(BuildService {
+            build_dir: Some(TempDir::new()?),
+            ..self
+        })
+    }
+
+    pub fn with_sandbox(self, sandbox: Sandbox) -> Self {
+        BuildService { sandbox, ..self }
+    }
+
+    pub fn with_test_seed(self, test_seed: Option<u64>) -> Self {
+        BuildService { test_seed, ..self }
+    }
+
+    pub fn with_max_output(self, max_output: usize) -> Self {
+        BuildService { max_output, ..self }
+    }
+
+    pub fn with_kept_output(self) -> Self {
+        BuildService {
+            keeps_output: true,
+            ..self
+        }
+    }
+
+    pub fn with_test_timeout(self, test_timeout: Duration) -> Self {
+        BuildService {
+            test_timeout,
+            ..self
+        }
+    }
+
+    pub fn max_output(&self) -> usize {
+        self.max_output
+    }
+
+    pub fn test_timeout(&self) -> Duration {
+        self.test_timeout
+    }
+
+    // The environment that the code's tests and programs run in, which is the same on every machine
+    // so that their results are too: the tests run one at a time rather than racing each other, in
+    // UTC and the C locale, and with the test seed, if there is one.
+    pub fn test_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("RUST_TEST_THREADS", "1".to_string()),
+            ("TZ", "UTC".to_string()),
+            ("LANG", "C.UTF-8".to_string()),
+            ("LC_ALL", "C.UTF-8".to_string()),
+        ];
+        if let Some(seed) = self.test_seed {
+            env.push((TEST_SEED_VAR, seed.to_string()));
+        }
+        env
+    }
+
+    // The build directory, if the builds are cached.
+    pub fn build_dir(&self) -> Option<&Path> {
+        self.build_dir.as_ref().map(TempDir::path)
+    }
+
+    // Check that the sandbox's tools are installed, so that a missing one is reported before the
+    // run rather than as a failure of the first proposal.
+    pub async fn check_sandbox(&self) -> Result<()> {
+        if self.sandbox == Sandbox::None {
+            return Ok(());
+        }
+        let unavailable = |reason: String| AiCriticError::SandboxUnavailable { reason };
+        let wasmtime = processes::output(Command::new("wasmtime").arg("--version")).await;
+        if !wasmtime.is_ok_and(|output| output.status.success()) {
+            return Err(unavailable("wasmtime is not installed".to_string()).into());
+        }
+        let libdir = processes::output(
+            Command::new("rustc")
+                .args(["--print", "target-libdir", "--target"])
+                .arg(WASM_TARGET),
+        )
+        .await?;
+        let libdir = String::from_utf8_lossy(&libdir.stdout).trim().to_string();
+        if libdir.is_empty() || !Path::new(&libdir).exists() {
+            return Err(unavailable(format!(
+                "the {} target is not installed; run `rustup target add {}`",
+                WASM_TARGET, WASM_TARGET
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    // The command that runs the given executable in the test environment, within the sandbox if
+    // there is one. wasmtime doesn't pass its own environment on, so it's given the variables.
+    pub fn command(&self, exec_path: &Path) -> Command {
+        match self.sandbox {
+            Sandbox::None => {
+                let mut command = Command::new(exec_path);
+                command.envs(self.test_env());
+                command
+            }
+            Sandbox::Wasm => {
+                let mut command = Command::new("wasmtime");
+                command.arg("run");
+                for (name, value) in self.test_env() {
+                    command.arg("--env").arg(format!("{}={}", name, value));
+                }
+                command.arg(exec_path);
+                command
+            }
+        }
+    }
+
+    // Compile the code's tests in the given directory.
+    pub async fn build_test(&self, dir: &Path, code: &str) -> Result<Build> {
+        self.build(dir, code, "test", &["--test"]).await
+    }
+
+    // Compile the code as a program in the given directory.
+    pub async fn build_program(&self, dir: &Path, code: &str) -> Result<Build> {
+        self.build(dir, code, "program", &[]).await
+    }
+
+    // Compile the code in the given directory into the named executable, passing extra arguments
+    // to rustc. The test harness and the program are built with different configurations, so each
+    // name has its own incremental state.
+    pub async fn build(
+        &self,
+        dir: &Path,
+        code: &str,
+        exec_name: &str,
+        extra_args: &[&str],
+    ) -> Result<Build> {
+        let rs_file_path = dir.join("code.rs");
+        let exec_path = dir.join(exec_name);
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&rs_file_path)?;
+        write!(file, "{}", code)?;
+
+        // Below, the unwrap()s guard against invalid UTF-8, but tempfile::Builder::new() generates
+        // valid UTF-8.
+        let mut command = Command::new("rustc");
+        command.args(extra_args);
+        if self.sandbox == Sandbox::Wasm {
+            command.args(["--target", WASM_TARGET]);
+        }
+        if let Some(build_dir) = self.build_dir() {
+            command.arg("-C").arg(format!(
+                "incremental={}",
+                build_dir
+                    .join(format!("incremental-{}", exec_name))
+                    .display()
+            ));
+        }
+        let output = processes::output(
+            command
+                .arg("-o")
+                .arg(exec_path.to_str().unwrap())
+                .arg(rs_file_path.to_str().unwrap()),
+        )
+        .await?;
+
+        // The compiler's raw output is left beside the code when the builds are kept.
+        if self.keeps_output {
+            std::fs::write(dir.join(format!("{}.stderr", exec_name)), &output.stderr)?;
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        match output.status.code() {
+            Some(0) => Ok(Build::Built { exec_path, stdout }),
+            Some(_) => Ok(Build::Failed { stderr }),
+            None => Err(AiCriticError::ProcessTerminated.into()),
+        }
+    }
+
+    // Run the given test executable, killing it if it prints more than the limit or runs longer
+    // than the test timeout. A test harness that's killed other than by crashing, as when the run
+    // is cancelled, is an error.
+    pub async fn run_tests(&self, exec_path: &Path) -> Result<TestRun> {
+        let mut command = self.command(exec_path);
+        if self.sandbox == Sandbox::Wasm {
+            // The test harness can't print a captured panic message once the panic aborts.
+            command.arg("--nocapture");
+        }
+        let run = processes::output_limited(&mut command, None, self.max_output);
+        // The harness is killed when the timed-out run is dropped.
+        let limited = match tokio::time::timeout(self.test_timeout, run).await {
+            Ok(limited) => limited?,
+            Err(_) => {
+                return Ok(TestRun::TimedOut {
+                    limit: self.test_timeout,
+                })
+            }
+        };
+        let output = limited.output;
+        let stdout = String::from_utf8_lossy(&output.stdout.to_owned()).to_string();
+        if limited.exceeded {
+            return Ok(TestRun::OutputExceeded {
+                limit: self.max_output,
+                output: format!("{}{}", stdout, String::from_utf8_lossy(&output.stderr)),
+            });
+        }
+
+        match output.status.code() {
+            Some(0) => Ok(TestRun::Passed { stdout }),
+            Some(WASM_TRAP_EXIT_CODE) if self.sandbox == Sandbox::Wasm => {
+                // The panic message is on stderr, followed by wasmtime's report of the trap.
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let panic = stderr.split("Error: failed to run").next().unwrap_or("");
+                Ok(TestRun::Failed {
+                    output: format!("{}{}", remove_stacktrace(&stdout), panic),
+                })
+            }
+            Some(101) => Ok(TestRun::Failed {
+                output: remove_stacktrace(&stdout).to_string(),
+            }),
+            code => {
+                let signal = processes::crash_signal(&output.status);
+                let output = format!(
+                    "{}{}",
+                    remove_stacktrace(&stdout),
+                    remove_stacktrace(&String::from_utf8_lossy(&output.stderr))
+                );
+                match (code, signal) {
+                    (Some(code), _) => Ok(TestRun::Exited { code, output }),
+                    (None, Some(signal)) => Ok(TestRun::Crashed { signal, output }),
+                    (None, None) => Err(AiCriticError::ProcessTerminated.into()),
+                }
+            }
+        }
+    }
+}
+
+// The project uses stack traces, but we don't need to send them to GPT-4.
+pub fn remove_stacktrace(input: &str) -> &str {
+    match input.find("stack backtrace:") {
+        Some(index) => &input[0..index],
+        None => input,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTS: &str = "#[cfg(test)]\nmod tests {\n    #[test]\n    fn test_add() {\n        \
+                         assert_eq!(1 + 1, 2);\n    }\n\n    #[test]\n    fn test_sub() {\n        \
+                         assert_eq!(2 - 1, 0);\n    }\n}\n";
+
+    #[tokio::test]
+    async fn test_build_and_run_tests() {
+        let dir = TempDir::new().unwrap();
+        let build = BuildService::default().with_build_cache().unwrap();
+        assert!(matches!(
+            build.build_test(dir.path(), "fn f( {}").await.unwrap(),
+            Build::Failed { stderr } if stderr.contains("error")
+        ));
+
+        let exec_path = match build.build_test(dir.path(), TESTS).await.unwrap() {
+            Build::Built { exec_path, .. } => exec_path,
+            Build::Failed { stderr } => panic!("{}", stderr),
+        };
+        match build.run_tests(&exec_path).await.unwrap() {
+            TestRun::Failed { output } => {
+                assert!(output.contains("test tests::test_sub ... FAILED"))
+            }
+            TestRun::Passed { stdout } => panic!("{}", stdout),
+            _ => panic!("the tests didn't run"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_env() {
+        let dir = TempDir::new().unwrap();
+        let build = BuildService::default().with_test_seed(Some(7));
+        let code = "#[test]\nfn test_env() {\n    assert_eq!(std::env::var(\"TZ\").unwrap(), \
+                    \"UTC\");\n    assert_eq!(std::env::var(\"AI_CRITICS_TEST_SEED\").unwrap(), \
+                    \"7\");\n}\n";
+        let exec_path = match build.build_test(dir.path(), code).await.unwrap() {
+            Build::Built { exec_path, .. } => exec_path,
+            Build::Failed { stderr } => panic!("{}", stderr),
+        };
+        assert!(matches!(
+            build.run_tests(&exec_path).await.unwrap(),
+            TestRun::Passed { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_test_timeout() {
+        let dir = TempDir::new().unwrap();
+        let build = BuildService::default().with_test_timeout(Duration::from_millis(500));
+        let code = "#[test]\nfn test_forever() {\n    loop {\n        \
+                    std::thread::sleep(std::time::Duration::from_secs(1));\n    }\n}\n";
+        let exec_path = match build.build_test(dir.path(), code).await.unwrap() {
+            Build::Built { exec_path, .. } => exec_path,
+            Build::Failed { stderr } => panic!("{}", stderr),
+        };
+        assert!(matches!(
+            build.run_tests(&exec_path).await.unwrap(),
+            TestRun::TimedOut { limit } if limit == Duration::from_millis(500)
+        ));
+    }
+
+    #[test]
+    fn test_remove_stacktrace() {
+        assert_eq!(
+            remove_stacktrace("panicked at 'oops'\nstack backtrace:\n  0: main"),
+            "panicked at 'oops'\n"
+        );
+        assert_eq!(remove_stacktrace("ok"), "ok");
+    }
+}
@@ -0,0 +1,260 @@
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent, CreateChatCompletionRequest, Role,
+};
+use serde_json::{json, Value};
+
+// Reasoning models, such as o1 and o3-mini, reject parts of the request that the chat models take:
+// they fix the temperature, limit the response with `max_completion_tokens` instead of
+// `max_tokens`, and some of them stream no responses, take no JSON mode, or have no system role.
+// What each family of models accepts is kept in a table, matched by the prefix of the model's name,
+// and the request is adapted to it. The limit also covers the tokens that the model reasons in, so
+// it's the chat models' limit on the response plus a budget for the reasoning, which is set with
+// --reasoning-tokens. An Azure deployment is matched by its name, so it
+// should be named after its model.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenLimit {
+    // The response is limited with `max_tokens`.
+    MaxTokens,
+    // The response, with the model's reasoning, is limited with `max_completion_tokens`.
+    MaxCompletionTokens,
+}
+
+// The role that the system prompt is sent in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SystemRole {
+    System,
+    Developer,
+    // The model has no system prompt, so it's sent as the first user message.
+    User,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Capabilities {
+    // The prefix of the names of the family's models.
+    pub family: &'static str,
+    // Whether the model takes a temperature and top_p.
+    pub sampling: bool,
+    pub token_limit: TokenLimit,
+    // Whether the model takes the JSON object response format.
+    pub json_mode: bool,
+    pub streaming: bool,
+    pub system_role: SystemRole,
+}
+
+// The default budget for a reasoning model's reasoning, which with the response's 4096 tokens makes
+// a limit of 32768.
+pub const DEFAULT_REASONING_TOKENS: u32 = 28_672;
+
+// The families, with the more specific prefixes of a family before the less specific ones.
+static FAMILIES: &[Capabilities] = &[
+    Capabilities {
+        family: "o1-mini",
+        sampling: false,
+        token_limit: TokenLimit::MaxCompletionTokens,
+        json_mode: false,
+        streaming: false,
+        system_role: SystemRole::User,
+    },
+    Capabilities {
+        family: "o1-preview",
+        sampling: false,
+        token_limit: TokenLimit::MaxCompletionTokens,
+        json_mode: false,
+        streaming: false,
+        system_role: SystemRole::User,
+    },
+    Capabilities {
+        family: "o1",
+        sampling: false,
+        token_limit: TokenLimit::MaxCompletionTokens,
+        json_mode: true,
+        streaming: false,
+        system_role: SystemRole::Developer,
+    },
+    Capabilities {
+        family: "o3-mini",
+        sampling: false,
+        token_limit: TokenLimit::MaxCompletionTokens,
+        json_mode: true,
+        streaming: true,
+        system_role: SystemRole::Developer,
+    },
+    Capabilities {
+        family: "o3",
+        sampling: false,
+        token_limit: TokenLimit::MaxCompletionTokens,
+        json_mode: true,
+        streaming: false,
+        system_role: SystemRole::Developer,
+    },
+    Capabilities {
+        family: "o4-mini",
+        sampling: false,
+        token_limit: TokenLimit::MaxCompletionTokens,
+        json_mode: true,
+        streaming: true,
+        system_role: SystemRole::Developer,
+    },
+];
+
+// The chat models, such as gpt-4o, and any model that isn't in the table.
+static CHAT: Capabilities = Capabilities {
+    family: "",
+    sampling: true,
+    token_limit: TokenLimit::MaxTokens,
+    json_mode: true,
+    streaming: true,
+    system_role: SystemRole::System,
+};
+
+// The capabilities of the model's family.
+pub fn of(model: &str) -> &'static Capabilities {
+    FAMILIES
+        .iter()
+        .find(|capabilities| {
+            model
+                .strip_prefix(capabilities.family)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+        })
+        .unwrap_or(&CHAT)
+}
+
+impl Capabilities {
+    // Whether the model reasons before it responds.
+    pub fn reasons(&self) -> bool {
+        self.token_limit == TokenLimit::MaxCompletionTokens
+    }
+
+    // Whether the request has to be sent as JSON that async-openai's request can't express.
+    pub fn needs_raw_requests(&self) -> bool {
+        *self != CHAT
+    }
+
+    // Drop the settings that the model rejects from the request, and move the system prompt to the
+    // first user message if the model has none.
+    pub fn adapt(&self, request: &mut CreateChatCompletionRequest) {
+        if !self.sampling {
+            request.temperature = None;
+            request.top_p = None;
+        }
+        if !self.json_mode {
+            request.response_format = None;
+        }
+        if self.system_role == SystemRole::User {
+            request.messages = request
+                .messages
+                .drain(..)
+                .map(|msg| match msg {
+                    ChatCompletionRequestMessage::System(m) => {
+                        ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                            content: m.content.map(ChatCompletionRequestUserMessageContent::Text),
+                            role: Role::User,
+                            name: m.name,
+                        })
+                    }
+                    msg => msg,
+                })
+                .collect();
+        }
+    }
+
+    // The body of the request as the model takes it, with the tokens that a reasoning model may
+    // reason in added to the limit on the response.
+    pub fn request_body(
+        &self,
+        request: &CreateChatCompletionRequest,
+        reasoning_tokens: u32,
+    ) -> Value {
+        let mut body = serde_json::to_value(request).unwrap_or_else(|_| json!({}));
+        if self.token_limit == TokenLimit::MaxCompletionTokens {
+            if let Some(fields) = body.as_object_mut() {
+                fields.remove("max_tokens");
+            }
+            let limit = request.max_tokens.map_or(0, u32::from) + reasoning_tokens;
+            body["max_completion_tokens"] = json!(limit);
+        }
+        if self.system_role == SystemRole::Developer {
+            for msg in body["messages"].as_array_mut().into_iter().flatten() {
+                if msg["role"] == "system" {
+                    msg["role"] = json!("developer");
+                }
+            }
+        }
+        body["stream"] = json!(self.streaming);
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::{
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionResponseFormat, ChatCompletionResponseFormatType,
+        CreateChatCompletionRequestArgs,
+    };
+
+    fn request(model: &str) -> CreateChatCompletionRequest {
+        CreateChatCompletionRequestArgs::default()
+            .model(model)
+            .max_tokens(4096_u16)
+            .temperature(0.1)
+            .response_format(ChatCompletionResponseFormat {
+                r#type: ChatCompletionResponseFormatType::JsonObject,
+            })
+            .messages([
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content("Be a critic.")
+                    .build()
+                    .unwrap()
+                    .into(),
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content("Review this.")
+                    .build()
+                    .unwrap()
+                    .into(),
+            ])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_of() {
+        assert_eq!(of("o1-mini-2024-09-12").family, "o1-mini");
+        assert_eq!(of("o1-2024-12-17").family, "o1");
+        assert_eq!(of("o3-mini").family, "o3-mini");
+        assert!(!of("gpt-4o").needs_raw_requests());
+        // A prefix only matches a whole part of the name.
+        assert_eq!(of("o1x").family, "");
+    }
+
+    #[test]
+    fn test_adapt() {
+        let mut chat = request("gpt-4o");
+        of("gpt-4o").adapt(&mut chat);
+        assert_eq!(chat, request("gpt-4o"));
+        let body = of("gpt-4o").request_body(&chat, DEFAULT_REASONING_TOKENS);
+        assert_eq!(body["max_tokens"], 4096);
+        assert_eq!(body["messages"][0]["role"], "system");
+
+        let mut o1 = request("o1");
+        of("o1").adapt(&mut o1);
+        let body = of("o1").request_body(&o1, DEFAULT_REASONING_TOKENS);
+        assert!(body.get("temperature").is_none() && body.get("max_tokens").is_none());
+        assert_eq!(body["max_completion_tokens"], 32_768);
+        let body = of("o1").request_body(&o1, 1000);
+        assert_eq!(body["max_completion_tokens"], 5096);
+        assert_eq!(body["response_format"]["type"], "json_object");
+        assert_eq!(body["messages"][0]["role"], "developer");
+        assert_eq!(body["stream"], false);
+
+        let mut mini = request("o1-mini");
+        of("o1-mini").adapt(&mut mini);
+        let body = of("o1-mini").request_body(&mini, DEFAULT_REASONING_TOKENS);
+        assert!(body.get("response_format").is_none());
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"], "Be a critic.");
+    }
+}
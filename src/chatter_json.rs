@@ -1,11 +1,20 @@
-use crate::{errors::AiCriticError, DoublingProgressBar};
+use crate::{
+    capabilities::{self, Capabilities},
+    errors::AiCriticError,
+    events::{AgentProgress, Anomaly},
+    json_repair,
+    lenient::{self, Reply},
+    provider::Provider,
+    request_limiter::LimitedClient,
+    schema::Schema,
+};
 use async_openai::{
-    config::OpenAIConfig,
+    config::Config,
     error::OpenAIError,
     types::{
-        ChatCompletionRequestMessage, ChatCompletionResponseFormat,
-        ChatCompletionResponseFormatType, ChatCompletionResponseStream,
-        CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+        ChatCompletionRequestMessage, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionResponseFormat, ChatCompletionResponseFormatType,
+        ChatCompletionResponseStream, CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
         CreateChatCompletionStreamResponse, FinishReason,
     },
     Client,
@@ -13,17 +22,79 @@ use async_openai::{
 use async_trait::async_trait;
 use color_eyre::eyre::Result;
 use futures::StreamExt;
-use log::info;
 use serde_json::{json, Map, Value};
-use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::time::timeout;
 
-const MODEL: &str = "gpt-4-1106-preview";
+pub const MODEL: &str = "gpt-4-1106-preview";
 //const MODEL: &str = "gpt-4"; // Try comparing.
 const MAX_TOKENS: u16 = 4096;
-const TEMPERATURE: f32 = 0.1;
+// The model's context window holds the prompt and the response, so this is what's left for prompts.
+const CONTEXT_WINDOW: usize = 128_000;
+pub const PROMPT_BUDGET: usize = CONTEXT_WINDOW - MAX_TOKENS as usize;
+pub const TEMPERATURE: f32 = 0.1;
 const MAX_RETRIES: usize = 5;
-const TIMEOUT_DURATION: std::time::Duration = std::time::Duration::from_secs(30);
+// A stream can fail slowly as well as stop: a chunk every few seconds never trips the timeout on
+// the next chunk. So a response is also limited in its total time, and once it's streamed for
+// STALL_WINDOW, in its rate of chunks over the latest STALL_WINDOW. Any of the three retries the
+// request.
+const STALL_WINDOW: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamTimeouts {
+    // The longest wait for the next chunk.
+    pub chunk: Duration,
+    // The longest that a whole response may take.
+    pub request: Duration,
+    // The fewest chunks per second over STALL_WINDOW, or 0 to not check.
+    pub min_rate: f64,
+}
+
+impl Default for StreamTimeouts {
+    fn default() -> Self {
+        StreamTimeouts {
+            chunk: Duration::from_secs(30),
+            request: Duration::from_secs(300),
+            min_rate: 1.0,
+        }
+    }
+}
+
+// Watches the arrival of a stream's chunks for a stall, timed from the first chunk so that the wait
+// for the response to start isn't counted.
+struct StallDetector {
+    min_rate: f64,
+    arrivals: VecDeque<Instant>,
+    first: Option<Instant>,
+}
+
+impl StallDetector {
+    fn new(min_rate: f64) -> Self {
+        StallDetector {
+            min_rate,
+            arrivals: VecDeque::new(),
+            first: None,
+        }
+    }
+
+    // Record a chunk arriving at `now` and return whether the stream has stalled.
+    fn stalled(&mut self, now: Instant) -> bool {
+        let first = *self.first.get_or_insert(now);
+        self.arrivals.push_back(now);
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|&t| now.duration_since(t) > STALL_WINDOW)
+        {
+            self.arrivals.pop_front();
+        }
+        now.duration_since(first) >= STALL_WINDOW
+            && (self.arrivals.len() as f64) < self.min_rate * STALL_WINDOW.as_secs_f64()
+    }
+}
 // The OpenAI API has a bug where the model will return a stream of spaces and newlines instead of
 // the actual text response. Eventually, this stream will exceed the max_tokens limit and the API
 // will return a 'Length' stop reason in the response's ChatChoice. But there's no reason to wait
@@ -31,10 +102,26 @@ const TIMEOUT_DURATION: std::time::Duration = std::time::Duration::from_secs(30)
 // Instead, we'll allow only MAX_CONSECUTIVE_BLANKS consecutive empty chunks in the response stream.
 const MAX_CONSECUTIVE_BLANKS: usize = 300;
 
+// A retry resends the same request when the stream failed, which says nothing about the response.
+// When the response itself was malformed, the retry tells the model what was wrong with it, since
+// the same request tends to get the same mistake.
+const TRUNCATED: &str = "was cut off before its JSON was complete. Keep it within the length \
+                         limit, leaving out explanations";
+const NESTED_CODE: &str = "had an object as its `code` field instead of the code";
+
+// With n > 1, each request samples n responses, as the choices of its stream, for about the cost
+// of the one prompt. The choices are processed independently, those that are malformed are dropped,
+// and the request is only retried if none of them can be used.
+
 #[derive(Debug, PartialEq)]
 enum ProcessingOutcome {
-    ApiSuccess(String, Option<FinishReason>),
+    // Each choice's text and finish reason, in the order of their indices.
+    ApiSuccess(Vec<(String, Option<FinishReason>)>),
     Retry,
+    // Retry, telling the model how its response was malformed.
+    Correct(String),
+    // The user asked for the response to be regenerated, optionally with a hint.
+    Regenerate(Option<String>),
     Done(Value),
 }
 
@@ -45,11 +132,39 @@ pub trait OpenAIClientTrait {
         &self,
         request: CreateChatCompletionRequest,
     ) -> Result<ChatCompletionResponseStream, OpenAIError>;
+
+    // Undo any provider-specific wrapping of the JSON response before it is parsed.
+    fn clean_response(&self, response: String) -> String {
+        response
+    }
 }
 
-// Implement the trait for the real OpenAI Client.
+// A client given to a provider, which its chatters share.
+pub type SharedClient = Arc<dyn OpenAIClientTrait + Send + Sync>;
+
 #[async_trait]
-impl OpenAIClientTrait for Client<OpenAIConfig> {
+impl OpenAIClientTrait for SharedClient {
+    async fn create_chat_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        self.as_ref().create_chat_stream(request).await
+    }
+
+    fn clean_response(&self, response: String) -> String {
+        self.as_ref().clean_response(response)
+    }
+}
+
+impl fmt::Debug for dyn OpenAIClientTrait + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OpenAIClientTrait")
+    }
+}
+
+// Implement the trait for the real OpenAI Client, whether it talks to OpenAI or Azure.
+#[async_trait]
+impl<C: Config + Send + Sync> OpenAIClientTrait for Client<C> {
     async fn create_chat_stream(
         &self,
         request: CreateChatCompletionRequest,
@@ -60,29 +175,99 @@ impl OpenAIClientTrait for Client<OpenAIConfig> {
 
 pub struct ChatterJSON {
     client: Box<dyn OpenAIClientTrait + Send + Sync>,
+    model: String,
+    temperature: f32,
+    top_p: Option<f32>,
+    seed: Option<i64>,
+    timeouts: StreamTimeouts,
+    // Whether to read a response in Markdown rather than retry it, and the kind of reply to read
+    // it as, if the agent's replies can be read.
+    lenient: bool,
+    reply: Option<Reply>,
+    // The number of responses to sample from each request.
+    samples: u8,
+    // What the model accepts in its requests.
+    capabilities: &'static Capabilities,
+}
+
+// One of the choices of a response, as its chunks stream in.
+#[derive(Debug, Default)]
+struct ChoiceStream {
+    chunks: Vec<String>,
+    consecutive_blanks: usize,
+    finish_reason: Option<FinishReason>,
 }
 
 #[cfg(test)]
 impl ChatterJSON {
     pub fn with_client(client: Box<dyn OpenAIClientTrait + Send + Sync>) -> Self {
-        ChatterJSON { client }
+        ChatterJSON {
+            client,
+            model: MODEL.to_string(),
+            temperature: TEMPERATURE,
+            top_p: None,
+            seed: None,
+            timeouts: StreamTimeouts::default(),
+            lenient: false,
+            reply: None,
+            samples: 1,
+            capabilities: capabilities::of(MODEL),
+        }
     }
 }
 
 impl ChatterJSON {
-    pub fn new() -> Self {
+    pub fn new(provider: &Provider) -> Self {
+        let client = match &provider.client {
+            Some(client) => client.clone(),
+            None => provider.clients.client(provider),
+        };
+        let client: Box<dyn OpenAIClientTrait + Send + Sync> = match &provider.request_limiter {
+            Some(limiter) => Box::new(LimitedClient {
+                client: Box::new(client),
+                limiter: limiter.clone(),
+            }),
+            None => Box::new(client),
+        };
         ChatterJSON {
-            client: Box::new(Client::new()),
+            client,
+            model: provider.model().to_string(),
+            temperature: provider.temperature,
+            top_p: provider.top_p,
+            seed: provider.seed,
+            timeouts: provider.timeouts,
+            lenient: provider.lenient_parsing,
+            reply: None,
+            samples: provider.samples,
+            capabilities: capabilities::of(provider.model()),
         }
     }
 
+    // Read the responses as this kind of reply if they're Markdown and lenient parsing is on.
+    pub fn expecting(self, reply: Reply) -> Self {
+        ChatterJSON {
+            reply: Some(reply),
+            ..self
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples as usize
+    }
+
     fn create_request(
+        model: &str,
+        temperature: f32,
         msgs: &[ChatCompletionRequestMessage],
     ) -> Result<CreateChatCompletionRequest, color_eyre::eyre::Error> {
         let request = CreateChatCompletionRequestArgs::default()
-            .model(MODEL)
+            .model(model)
             .max_tokens(MAX_TOKENS)
-            .temperature(TEMPERATURE)
+            .temperature(temperature)
             .response_format(ChatCompletionResponseFormat {
                 r#type: ChatCompletionResponseFormatType::JsonObject,
             })
@@ -92,6 +277,43 @@ impl ChatterJSON {
         Ok(request)
     }
 
+    // The request for the messages with this chatter's sampling settings, adapted to what the model
+    // accepts.
+    fn request(
+        &self,
+        msgs: &[ChatCompletionRequestMessage],
+    ) -> Result<CreateChatCompletionRequest, color_eyre::eyre::Error> {
+        let mut request = Self::create_request(&self.model, self.temperature, msgs)?;
+        request.top_p = self.top_p;
+        request.seed = self.seed;
+        request.n = Some(self.samples);
+        self.capabilities.adapt(&mut request);
+        Ok(request)
+    }
+
+    // Pass the user's hint for a regenerated response on to the model.
+    fn hint_message(hint: &str) -> Result<ChatCompletionRequestMessage> {
+        Ok(ChatCompletionRequestUserMessageArgs::default()
+            .content(format!(
+                "Guidance from the user for this response: {}",
+                hint
+            ))
+            .build()?
+            .into())
+    }
+
+    // Tell the model how its previous response was malformed.
+    fn correction_message(malformation: &str) -> Result<ChatCompletionRequestMessage> {
+        Ok(ChatCompletionRequestUserMessageArgs::default()
+            .content(format!(
+                "Your previous response {}. Respond again with only the JSON object that was \
+                 asked for, with the code, if any, as a single string in its `code` field.",
+                malformation
+            ))
+            .build()?
+            .into())
+    }
+
     fn check_for_excessive_blanks(consecutive_blanks: &mut usize, content: &str) -> bool {
         *consecutive_blanks = if content.trim().is_empty() {
             *consecutive_blanks + 1
@@ -101,80 +323,127 @@ impl ChatterJSON {
         *consecutive_blanks > MAX_CONSECUTIVE_BLANKS
     }
 
-    // Process the chunk, accumulating them into `chunks`. Also, watch for a finish reason to be
-    // returned and watch for excessive blank chunks. Return true if the request should be retried.
+    // Process the chunk, accumulating the text of each of its choices into that choice's stream.
+    // Also, watch for a finish reason to be returned and watch for excessive blank chunks. Return
+    // true if the request should be retried.
     fn process_chunk(
-        pb: &mut DoublingProgressBar,
+        progress: &AgentProgress,
         response: CreateChatCompletionStreamResponse,
-        chunks: &mut Vec<String>,
-        consecutive_blanks: &mut usize,
-        last_finish_reason: &mut Option<FinishReason>,
+        choices: &mut [ChoiceStream],
     ) -> bool {
-        if response.choices.len() > 1 {
-            println!(
-                "Expected 1 ChatChoice in response but received {}. Retrying.",
-                response.choices.len()
-            );
-            return true;
-        }
-        let chat_choice = &response.choices[0];
-        if let Some(ref content) = chat_choice.delta.content {
-            chunks.push(content.clone());
-            if content.trim().is_empty() {
-                pb.dec();
-            } else {
-                pb.inc();
-            }
-            if Self::check_for_excessive_blanks(consecutive_blanks, content) {
-                println!("Retrying due to too many empty chunks returned by the API.");
+        for chat_choice in response.choices {
+            let Some(choice) = choices.get_mut(chat_choice.index as usize) else {
+                progress.anomaly(Anomaly::ExtraChoices);
+                progress.note(&format!(
+                    "Expected {} ChatChoices in response but received choice {}. Retrying.",
+                    choices.len(),
+                    chat_choice.index
+                ));
                 return true;
+            };
+            if let Some(ref content) = chat_choice.delta.content {
+                choice.chunks.push(content.clone());
+                progress.chunk(content);
+                if Self::check_for_excessive_blanks(&mut choice.consecutive_blanks, content) {
+                    progress.anomaly(Anomaly::BlankFlood);
+                    progress.note("Retrying due to too many empty chunks returned by the API.");
+                    return true;
+                }
+            }
+            if let Some(reason) = chat_choice.finish_reason {
+                choice.finish_reason = Some(reason);
             }
-        }
-        if let Some(reason) = chat_choice.finish_reason {
-            *last_finish_reason = Some(reason);
         }
         false
     }
 
     // The OpenAI API stream will return chunks, each of which has some text and an optional finish
-    // reason. This function collects all of the chunks into a single string and return the combined
-    // text and the last finish reason which contains the reason the stream ended.
+    // reason for one of the choices. This function collects each choice's chunks into a single
+    // string and returns the combined texts and the last finish reasons which contain the reasons
+    // the choices ended.
     async fn collect_chunks(
         &self,
-        pb: &mut DoublingProgressBar,
+        progress: &AgentProgress,
         request: &CreateChatCompletionRequest,
     ) -> Result<ProcessingOutcome> {
-        let mut stream = self.client.create_chat_stream(request.clone()).await?;
-        let mut chunks = vec![];
-        let mut last_finish_reason: Option<FinishReason> = None;
-
-        let mut consecutive_blanks = 0;
+        let mut stream = self
+            .client
+            .create_chat_stream(request.clone())
+            .await
+            .map_err(|e| AiCriticError::from_api(e, &self.model))?;
+        let mut choices: Vec<ChoiceStream> = (0..request.n.unwrap_or(1).max(1))
+            .map(|_| ChoiceStream::default())
+            .collect();
+        let _streaming = progress.streaming();
+        let deadline = Instant::now() + self.timeouts.request;
+        let mut started = false;
+        let mut stall = StallDetector::new(self.timeouts.min_rate);
         loop {
-            match timeout(TIMEOUT_DURATION, stream.next()).await {
+            // A reasoning model sends nothing until it's done reasoning.
+            let chunk_timeout = match started || !self.capabilities.reasons() {
+                true => self.timeouts.chunk,
+                false => self.timeouts.request,
+            };
+            let wait = chunk_timeout.min(deadline.saturating_duration_since(Instant::now()));
+            let next = tokio::select! {
+                biased;
+                regenerate = progress.regenerate_requested() => {
+                    progress.note("Regenerating the response at the user's request.");
+                    return Ok(ProcessingOutcome::Regenerate(regenerate.hint));
+                }
+                next = timeout(wait, stream.next()) => next,
+            };
+            match next {
                 Ok(Some(message)) => {
+                    started = true;
                     if Self::process_chunk(
-                        pb,
-                        message?,
-                        &mut chunks,
-                        &mut consecutive_blanks,
-                        &mut last_finish_reason,
+                        progress,
+                        message.map_err(|e| AiCriticError::from_api(e, &self.model))?,
+                        &mut choices,
                     ) {
                         return Ok(ProcessingOutcome::Retry);
                     }
+                    if stall.stalled(Instant::now()) {
+                        progress.anomaly(Anomaly::Stall);
+                        progress.note(&format!(
+                            "The response slowed to under {} chunks per second. Retrying...",
+                            self.timeouts.min_rate
+                        ));
+                        return Ok(ProcessingOutcome::Retry);
+                    }
                 }
                 Ok(None) => {
                     break; // Stream finished.
                 }
+                Err(_) if Instant::now() >= deadline => {
+                    progress.anomaly(Anomaly::RequestDeadline);
+                    progress.note(&format!(
+                        "The response took longer than {:?}. Retrying...",
+                        self.timeouts.request
+                    ));
+                    return Ok(ProcessingOutcome::Retry);
+                }
                 Err(_) => {
-                    println!("Request timed out. Retrying...");
+                    progress.anomaly(Anomaly::Timeout);
+                    progress.note("Request timed out. Retrying...");
                     return Ok(ProcessingOutcome::Retry);
                 }
             }
         }
-        Ok(ProcessingOutcome::ApiSuccess(
-            chunks.join(""),
-            last_finish_reason,
-        ))
+        // A provider that doesn't support n sends only the first choice.
+        let responses = choices
+            .into_iter()
+            .enumerate()
+            .filter(|(i, choice)| {
+                *i == 0 || !choice.chunks.is_empty() || choice.finish_reason.is_some()
+            })
+            .map(|(_, choice)| {
+                let json_str = self.client.clean_response(choice.chunks.join(""));
+                progress.response(&json_str, choice.finish_reason, choice.chunks.len());
+                (json_str, choice.finish_reason)
+            })
+            .collect();
+        Ok(ProcessingOutcome::ApiSuccess(responses))
     }
 
     fn describe_value(value: &Value, indent: usize) {
@@ -240,11 +509,17 @@ impl ChatterJSON {
     // other value. This function will parse the known variations and return the correct Object
     // (Map<String, String>) as a Value so that can be parsed by serde into a Code object elsewhere.
     // If it can't find a parsable value, it will return a retry request.
-    fn process_code_value(map: &Map<String, Value>) -> Result<ProcessingOutcome> {
+    fn process_code_value(
+        progress: &AgentProgress,
+        map: &Map<String, Value>,
+    ) -> Result<ProcessingOutcome> {
         match map.get("code") {
             None => {
+                progress.anomaly(Anomaly::MalformedCode);
                 log::info!("The 'code' value is missing. Retrying");
-                Ok(ProcessingOutcome::Retry)
+                Ok(ProcessingOutcome::Correct(
+                    "had no `code` field".to_string(),
+                ))
             }
             Some(Value::String(_)) => {
                 // Ideal: The code value is a String.
@@ -258,12 +533,14 @@ impl ChatterJSON {
                 // Sometimes, the API returns the value as an Object that has the code as both the
                 // key and the value. Weird! Check for this case and recover.
                 if m.len() != 1 {
+                    progress.anomaly(Anomaly::MalformedCode);
                     log::info!(
                         "Found an object for the 'code' value with {} keys. Retrying",
                         map.keys().len()
                     );
-                    Ok(ProcessingOutcome::Retry)
+                    Ok(ProcessingOutcome::Correct(NESTED_CODE.to_string()))
                 } else {
+                    progress.anomaly(Anomaly::CodeAsKey);
                     let (key, value) = m.iter().next().unwrap();
                     // Sometimes the API returns the code as the key and a comment as the value.
                     log::info!("Found a key / value for the 'code'. Returning the key");
@@ -272,21 +549,53 @@ impl ChatterJSON {
                     Ok(ProcessingOutcome::Done(json!({ "code": key })))
                 }
             }
-            _ => {
+            Some(value) => {
+                progress.anomaly(Anomaly::MalformedCode);
                 log::info!("Found an expected type for the 'code' value. Retrying; here it is:");
-                Self::describe_value(map.get("code").unwrap(), 0);
-                Ok(ProcessingOutcome::Retry)
+                Self::describe_value(value, 0);
+                Ok(ProcessingOutcome::Correct(format!(
+                    "had {} as its `code` field instead of a string",
+                    describe_type(value)
+                )))
             }
         }
     }
 
     // Process the JSON string returned by the OpenAI API when the STOP finish reason is returned.
     // Return it as a Value for further processing.
-    fn process_stop(json_str: String) -> Result<ProcessingOutcome> {
-        let value: Value = serde_json::from_str(&json_str)?;
+    // If the JSON is invalid, repair it, or with lenient parsing, read it as Markdown of the given
+    // kind of reply. If it can't be read, tell the model why.
+    fn process_stop(
+        progress: &AgentProgress,
+        json_str: String,
+        lenient: Option<Reply>,
+    ) -> Result<ProcessingOutcome> {
+        let value: Value = match serde_json::from_str(&json_str) {
+            Ok(value) => value,
+            Err(e) => {
+                if let Some(value) = json_repair::repair(&json_str) {
+                    progress.anomaly(Anomaly::RepairedJson);
+                    progress.note("Repaired the response's invalid JSON.");
+                    value
+                } else {
+                    let Some(value) = lenient.and_then(|reply| lenient::extract(&json_str, reply))
+                    else {
+                        return Ok(ProcessingOutcome::Correct(format!(
+                            "wasn't valid JSON: {}",
+                            e
+                        )));
+                    };
+                    progress.anomaly(Anomaly::Markdown);
+                    progress.note("Read the response from its Markdown.");
+                    value
+                }
+            }
+        };
         match &value {
             // Code objects need extra processing...
-            Value::Object(map) if map.contains_key("code") => Self::process_code_value(map),
+            Value::Object(map) if map.contains_key("code") => {
+                Self::process_code_value(progress, map)
+            }
             Value::Object(_) => Ok(ProcessingOutcome::Done(value)),
             _ => Err(AiCriticError::UnexpectedJsonStructure { json: value }.into()),
         }
@@ -297,60 +606,130 @@ impl ChatterJSON {
     // ProcessingOutcome requesting to retry the request. Otherwise, process and return it.
     fn process_api_result(
         &self,
-        pb: &mut DoublingProgressBar,
+        progress: &AgentProgress,
         json_str: String,
         finish_reason: Option<FinishReason>,
     ) -> Result<ProcessingOutcome> {
         match finish_reason {
-            Some(FinishReason::Stop) => Self::process_stop(json_str),
+            Some(FinishReason::Stop) => {
+                let lenient = self.reply.filter(|_| self.lenient);
+                Self::process_stop(progress, json_str, lenient)
+            }
             Some(FinishReason::Length) => {
-                pb.clone().println("Retrying due to unfinished chat.");
-                pb.reset_to_zero();
-                Ok(ProcessingOutcome::Retry)
+                progress.anomaly(Anomaly::LengthStop);
+                progress.note("Retrying due to unfinished chat.");
+                progress.reset();
+                Ok(ProcessingOutcome::Correct(TRUNCATED.to_string()))
             }
             Some(r) => {
-                pb.clone()
-                    .println(&format!("Unexpected finish reason: {:?}. Retrying", r));
-                pb.reset_to_zero();
+                progress.anomaly(Anomaly::OtherFinish);
+                progress.note(&format!("Unexpected finish reason: {:?}. Retrying", r));
+                progress.reset();
                 Ok(ProcessingOutcome::Retry)
             }
             None => {
-                pb.clone()
-                    .println("Missing finish reason. Retrying the request.");
-                pb.reset_to_zero();
+                progress.anomaly(Anomaly::MissingFinish);
+                progress.note("Missing finish reason. Retrying the request.");
+                progress.reset();
                 Ok(ProcessingOutcome::Retry)
             }
         }
     }
 
+    // Tell the model which of the schema's required fields its response is missing, if any.
+    fn check_schema(schema: Option<&Schema>, value: Value) -> ProcessingOutcome {
+        let missing = schema
+            .map(|schema| schema.missing(&value))
+            .unwrap_or_default();
+        if missing.is_empty() {
+            return ProcessingOutcome::Done(value);
+        }
+        let fields: Vec<String> = missing.iter().map(|field| format!("`{}`", field)).collect();
+        ProcessingOutcome::Correct(format!(
+            "was missing the required fields {}",
+            fields.join(", ")
+        ))
+    }
+
+    // Chat, returning each of the sampled responses that could be used, of which there's at least
+    // one. A response missing the required fields of the schema, if there is one, is corrected.
     pub async fn chat(
         &self,
-        pb: &mut DoublingProgressBar,
+        progress: &AgentProgress,
         msgs: &[ChatCompletionRequestMessage],
-    ) -> Result<Value> {
-        let request = Self::create_request(msgs)?;
-        info!("   ==> Request: {:?}", request);
-
-        for i in 1..=MAX_RETRIES {
-            match self.collect_chunks(pb, &request).await {
-                Ok(ProcessingOutcome::ApiSuccess(json_str, finish_reason)) => {
-                    info!("   ==> Response: {}", json_str);
-                    match self.process_api_result(pb, json_str, finish_reason)? {
-                        ProcessingOutcome::Done(value) => return Ok(value),
-                        ProcessingOutcome::Retry => {}
-                        ProcessingOutcome::ApiSuccess(_, _) => unreachable!(),
+        schema: Option<&Schema>,
+    ) -> Result<Vec<Value>> {
+        let mut msgs = msgs.to_vec();
+        let mut request = self.request(&msgs)?;
+        progress.request(&self.model, &msgs);
+
+        let mut i = 1;
+        while i <= MAX_RETRIES {
+            match self.collect_chunks(progress, &request).await {
+                Ok(ProcessingOutcome::ApiSuccess(responses)) => {
+                    let sampled = responses.len();
+                    let mut values = vec![];
+                    let mut correction = None;
+                    let mut error = None;
+                    for (json_str, finish_reason) in responses {
+                        let outcome = self
+                            .process_api_result(progress, json_str, finish_reason)
+                            .map(|outcome| match outcome {
+                                ProcessingOutcome::Done(value) => Self::check_schema(schema, value),
+                                outcome => outcome,
+                            });
+                        match outcome {
+                            Ok(ProcessingOutcome::Done(value)) => values.push(value),
+                            Ok(ProcessingOutcome::Retry) => {}
+                            Ok(ProcessingOutcome::Correct(malformation)) => {
+                                correction.get_or_insert(malformation);
+                            }
+                            Ok(_) => unreachable!(),
+                            Err(e) => {
+                                error.get_or_insert(e);
+                            }
+                        }
+                    }
+                    if !values.is_empty() {
+                        if values.len() < sampled {
+                            progress.note(&format!(
+                                "Dropped {} of the {} sampled responses that were malformed.",
+                                sampled - values.len(),
+                                sampled
+                            ));
+                        }
+                        return Ok(values);
+                    }
+                    if let Some(e) = error {
+                        return Err(e);
+                    }
+                    // Only the latest correction is sent, so that they don't pile up.
+                    if let Some(malformation) = correction {
+                        let mut corrected = msgs.clone();
+                        corrected.push(Self::correction_message(&malformation)?);
+                        request = self.request(&corrected)?;
                     }
                 }
                 Ok(ProcessingOutcome::Retry) => {
-                    pb.reset_to_zero();
+                    progress.reset();
+                }
+                // A regeneration the user asked for doesn't count against the retries.
+                Ok(ProcessingOutcome::Regenerate(hint)) => {
+                    progress.reset();
+                    if let Some(hint) = hint {
+                        msgs.push(Self::hint_message(&hint)?);
+                        request = self.request(&msgs)?;
+                        progress.request(&self.model, &msgs);
+                    }
+                    continue;
                 }
-                Ok(ProcessingOutcome::Done(_)) => unreachable!(),
+                Ok(ProcessingOutcome::Done(_) | ProcessingOutcome::Correct(_)) => unreachable!(),
                 Err(e) => {
                     return Err(e);
                 }
             };
-            info!("Retry attempt: {}", i);
-            println!("Retry attempt: {}", i);
+            progress.retry(i);
+            i += 1;
         }
 
         Err(AiCriticError::MaxRetriesExceeded {
@@ -358,34 +737,26 @@ impl ChatterJSON {
         }
         .into())
     }
+}
 
-    // Validate fields from a JSON Value object. Return a list of missing fields as an error. Return
-    // any extra fields as a result. If they're the same, the result will be empty.
-    pub fn validate_fields(value: &Value, fields: Vec<&str>) -> Result<Vec<String>> {
-        // Iterate over fields if it's an object.
-        match value.as_object() {
-            Some(obj) => {
-                let obj_keys: HashSet<_> = obj.keys().cloned().collect();
-                let fields_set: HashSet<_> = fields.iter().map(|&k| k.to_string()).collect();
-
-                let missing_keys: Vec<_> = fields_set.difference(&obj_keys).cloned().collect();
-                if !missing_keys.is_empty() {
-                    return Err(AiCriticError::MissingJsonFields {
-                        fields: missing_keys,
-                    }
-                    .into());
-                }
-                Ok(obj_keys.difference(&fields_set).cloned().collect())
-            }
-            None => Err(AiCriticError::NotJsonObject.into()),
-        }
+// The JSON type of the value, for telling the model what it sent.
+fn describe_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::DoublingProgressBar;
+    use crate::events::AgentProgress;
+    use crate::scripted::ScriptedClient;
+    use crate::steering::Steering;
     use async_openai::types::{
         ChatCompletionRequestUserMessageArgs, ChatCompletionResponseStreamMessage,
         ChatCompletionStreamResponseDelta, CreateChatCompletionStreamResponse, Role,
@@ -408,9 +779,17 @@ mod tests {
     fn create_chunk(
         msg: &str,
         finish_reason: Option<FinishReason>,
+    ) -> CreateChatCompletionStreamResponse {
+        create_choice_chunk(0, msg, finish_reason)
+    }
+
+    fn create_choice_chunk(
+        index: u32,
+        msg: &str,
+        finish_reason: Option<FinishReason>,
     ) -> CreateChatCompletionStreamResponse {
         let chat_choice = ChatCompletionResponseStreamMessage {
-            index: 0,
+            index,
             #[allow(deprecated)]
             delta: ChatCompletionStreamResponseDelta {
                 content: Some(msg.to_string()),
@@ -475,13 +854,36 @@ mod tests {
         assert_eq!(blanks, MAX_CONSECUTIVE_BLANKS + 1);
     }
 
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    // StallDetector tests
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    #[test]
+    fn test_stall_detector() {
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        // A trickle of a chunk every 29 seconds.
+        let mut stall = StallDetector::new(1.0);
+        assert!(!stall.stalled(at(40.0)));
+        assert!(!stall.stalled(at(69.0)));
+        assert!(stall.stalled(at(98.0)));
+
+        // A steady stream of 10 chunks per second.
+        let mut stall = StallDetector::new(1.0);
+        assert!((0..600).all(|i| !stall.stalled(at(i as f64 / 10.0))));
+
+        let mut stall = StallDetector::new(0.0);
+        assert!((0..10).all(|i| !stall.stalled(at(i as f64 * 29.0))));
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // process_stop() tests
     ////////////////////////////////////////////////////////////////////////////////////////////////
     #[test]
     fn test_process_stop_with_code() {
         let json_str = r#"{"code": "print('Hello, World!')"}"#.to_string();
-        let result = ChatterJSON::process_stop(json_str).unwrap();
+        let result =
+            ChatterJSON::process_stop(&AgentProgress::detached("test"), json_str, None).unwrap();
         assert_eq!(
             result,
             ProcessingOutcome::Done(json!({"code": "print('Hello, World!')"}))
@@ -491,25 +893,55 @@ mod tests {
     #[test]
     fn test_process_stop_with_invalid_json() {
         let json_str = r#"{"code": "print('Hello, World!')"#.to_string();
-        let result = ChatterJSON::process_stop(json_str);
-        assert!(result.is_err());
+        let result =
+            ChatterJSON::process_stop(&AgentProgress::detached("test"), json_str, None).unwrap();
         assert_eq!(
-            result.unwrap_err().to_string(),
-            "EOF while parsing a string at line 1 column 32"
+            result,
+            ProcessingOutcome::Done(json!({"code": "print('Hello, World!')"}))
+        );
+
+        // JSON that can't be repaired is corrected.
+        let json_str = r#"{"code": }"#.to_string();
+        let result =
+            ChatterJSON::process_stop(&AgentProgress::detached("test"), json_str, None).unwrap();
+        assert_eq!(
+            result,
+            ProcessingOutcome::Correct(
+                "wasn't valid JSON: expected value at line 1 column 10".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_stop_with_markdown() {
+        let progress = AgentProgress::detached("test");
+        let markdown = "Here you go:\n```rust\nfn main() {}\n```".to_string();
+        let unread = |reply| match ChatterJSON::process_stop(&progress, markdown.clone(), reply) {
+            Ok(ProcessingOutcome::Correct(malformation)) => {
+                malformation.starts_with("wasn't valid")
+            }
+            _ => false,
+        };
+        assert!(unread(None));
+        assert!(unread(Some(Reply::Review)));
+        assert_eq!(
+            ChatterJSON::process_stop(&progress, markdown, Some(Reply::Code)).unwrap(),
+            ProcessingOutcome::Done(json!({"code": "fn main() {}"}))
         );
     }
 
     #[test]
     fn test_process_stop_with_object_value() {
         let json_str = r#"{"key": "value"}"#.to_string();
-        let result = ChatterJSON::process_stop(json_str).unwrap();
+        let result =
+            ChatterJSON::process_stop(&AgentProgress::detached("test"), json_str, None).unwrap();
         assert_eq!(result, ProcessingOutcome::Done(json!({"key": "value"})));
     }
 
     #[test]
     fn test_process_stop_with_unexpected_json_structure() {
         let json_str = r#"["an", "array"]"#.to_string();
-        let result = ChatterJSON::process_stop(json_str);
+        let result = ChatterJSON::process_stop(&AgentProgress::detached("test"), json_str, None);
         assert!(result.is_err());
 
         let error = result.unwrap_err();
@@ -525,12 +957,12 @@ mod tests {
 
     #[test]
     fn test_process_api_result_with_stop() {
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
+        let progress = AgentProgress::detached("test");
         let json_str = r#"{"code": "print('Hello, World!')"}"#.to_string();
         let finish_reason = Some(FinishReason::Stop);
-        let cj = ChatterJSON::new();
+        let cj = ChatterJSON::new(&Provider::default());
         let result = cj
-            .process_api_result(&mut pb, json_str, finish_reason)
+            .process_api_result(&progress, json_str, finish_reason)
             .unwrap();
         assert_eq!(
             result,
@@ -540,36 +972,36 @@ mod tests {
 
     #[test]
     fn test_process_api_result_with_length() {
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap(); // Pass the required argument to the function.
+        let progress = AgentProgress::detached("test");
         let json_str = r#"{"message": "Hello, World!"}"#.to_string();
         let finish_reason = Some(FinishReason::Length);
-        let cj = ChatterJSON::new();
+        let cj = ChatterJSON::new(&Provider::default());
         let result = cj
-            .process_api_result(&mut pb, json_str, finish_reason)
+            .process_api_result(&progress, json_str, finish_reason)
             .unwrap();
-        assert_eq!(result, ProcessingOutcome::Retry);
+        assert_eq!(result, ProcessingOutcome::Correct(TRUNCATED.to_string()));
     }
 
     #[test]
     fn test_process_api_result_with_unexpected_reason() {
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
+        let progress = AgentProgress::detached("test");
         let json_str = r#"{"message": "Hello, World!"}"#.to_string();
         let finish_reason = None;
-        let cj = ChatterJSON::new();
+        let cj = ChatterJSON::new(&Provider::default());
         let result = cj
-            .process_api_result(&mut pb, json_str, finish_reason)
+            .process_api_result(&progress, json_str, finish_reason)
             .unwrap();
         assert_eq!(result, ProcessingOutcome::Retry);
     }
 
     #[test]
     fn test_process_api_result_without_reason() {
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
+        let progress = AgentProgress::detached("test");
         let json_str = r#"{"message": "Hello, World!"}"#.to_string();
         let finish_reason = None;
-        let cj = ChatterJSON::new();
+        let cj = ChatterJSON::new(&Provider::default());
         let result = cj
-            .process_api_result(&mut pb, json_str, finish_reason)
+            .process_api_result(&progress, json_str, finish_reason)
             .unwrap();
         assert_eq!(result, ProcessingOutcome::Retry);
     }
@@ -580,108 +1012,64 @@ mod tests {
 
     #[test]
     fn test_process_chunk() {
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let mut chunks = Vec::new();
-        let mut consecutive_blanks = 0;
-        let mut last_finish_reason = None;
+        let progress = AgentProgress::detached("test");
+        let mut choices = vec![ChoiceStream::default()];
 
         let response_chunk = create_chunk("Hello", Some(FinishReason::Stop));
-        let retry = ChatterJSON::process_chunk(
-            &mut pb,
-            response_chunk,
-            &mut chunks,
-            &mut consecutive_blanks,
-            &mut last_finish_reason,
-        );
+        let retry = ChatterJSON::process_chunk(&progress, response_chunk, &mut choices);
         assert!(!retry);
-        assert_eq!(chunks, vec!["Hello"]);
+        assert_eq!(choices[0].chunks, vec!["Hello"]);
     }
 
     #[test]
     fn test_process_chunk_consecutive_blanks() {
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let mut chunks = Vec::new();
-        let mut consecutive_blanks = 0;
-        let mut last_finish_reason = None;
+        let progress = AgentProgress::detached("test");
+        let mut choices = vec![ChoiceStream::default()];
 
         // Test empty chunk.
         let chunk = create_chunk("", None);
-        let retry = ChatterJSON::process_chunk(
-            &mut pb,
-            chunk,
-            &mut chunks,
-            &mut consecutive_blanks,
-            &mut last_finish_reason,
-        );
+        let retry = ChatterJSON::process_chunk(&progress, chunk, &mut choices);
         assert!(!retry);
-        assert_eq!(consecutive_blanks, 1);
+        assert_eq!(choices[0].consecutive_blanks, 1);
 
         let chunk = create_chunk(" ", None);
-        let retry = ChatterJSON::process_chunk(
-            &mut pb,
-            chunk,
-            &mut chunks,
-            &mut consecutive_blanks,
-            &mut last_finish_reason,
-        );
+        let retry = ChatterJSON::process_chunk(&progress, chunk, &mut choices);
         assert!(!retry);
-        assert_eq!(consecutive_blanks, 2);
+        assert_eq!(choices[0].consecutive_blanks, 2);
 
         let chunk = create_chunk(" \n   ", Some(FinishReason::Stop));
-        let retry = ChatterJSON::process_chunk(
-            &mut pb,
-            chunk,
-            &mut chunks,
-            &mut consecutive_blanks,
-            &mut last_finish_reason,
-        );
+        let retry = ChatterJSON::process_chunk(&progress, chunk, &mut choices);
         assert!(!retry);
-        assert_eq!(consecutive_blanks, 3);
+        assert_eq!(choices[0].consecutive_blanks, 3);
 
         // Test consecutive_blanks reset.
         let chunk = create_chunk("a", None);
-        let retry = ChatterJSON::process_chunk(
-            &mut pb,
-            chunk,
-            &mut chunks,
-            &mut consecutive_blanks,
-            &mut last_finish_reason,
-        );
+        let retry = ChatterJSON::process_chunk(&progress, chunk, &mut choices);
         assert!(!retry);
-        assert_eq!(consecutive_blanks, 0);
+        assert_eq!(choices[0].consecutive_blanks, 0);
 
         // Too many consecutive blanks.
-        consecutive_blanks = MAX_CONSECUTIVE_BLANKS;
+        choices[0].consecutive_blanks = MAX_CONSECUTIVE_BLANKS;
         let chunk = create_chunk(" ", Some(FinishReason::Stop));
-        let retry = ChatterJSON::process_chunk(
-            &mut pb,
-            chunk,
-            &mut chunks,
-            &mut consecutive_blanks,
-            &mut last_finish_reason,
-        );
+        let retry = ChatterJSON::process_chunk(&progress, chunk, &mut choices);
         assert!(retry);
-        assert_eq!(consecutive_blanks, MAX_CONSECUTIVE_BLANKS + 1);
+        assert_eq!(choices[0].consecutive_blanks, MAX_CONSECUTIVE_BLANKS + 1);
     }
 
     #[test]
     fn test_process_chunk_finish_reason() {
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let mut chunks = Vec::new();
-        let mut consecutive_blanks = 0;
-        let mut last_finish_reason = None;
+        let progress = AgentProgress::detached("test");
+        let mut choices = vec![ChoiceStream::default()];
 
         // Test empty chunk.
         let chunk = create_chunk("foo", Some(FinishReason::Stop));
-        let retry = ChatterJSON::process_chunk(
-            &mut pb,
-            chunk,
-            &mut chunks,
-            &mut consecutive_blanks,
-            &mut last_finish_reason,
-        );
+        let retry = ChatterJSON::process_chunk(&progress, chunk, &mut choices);
         assert!(!retry);
-        assert_eq!(last_finish_reason, Some(FinishReason::Stop));
+        assert_eq!(choices[0].finish_reason, Some(FinishReason::Stop));
+
+        // A choice beyond those requested.
+        let chunk = create_choice_chunk(1, "bar", Some(FinishReason::Stop));
+        assert!(ChatterJSON::process_chunk(&progress, chunk, &mut choices));
     }
 
     ////////////////////////////////////////////////////////////////////////////////////////////////
@@ -691,7 +1079,7 @@ mod tests {
     async fn test_collect_chunks() {
         let msg = create_message("Request: Hello");
 
-        let request = ChatterJSON::create_request(&[msg]).unwrap();
+        let request = ChatterJSON::create_request(MODEL, TEMPERATURE, &[msg]).unwrap();
 
         let response_chunks = vec![create_chunk(
             r#"{"message": "Hello, World!"}"#,
@@ -700,21 +1088,21 @@ mod tests {
 
         let mock = make_mock(response_chunks);
         let chatter = ChatterJSON::with_client(Box::new(mock));
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let result = chatter.collect_chunks(&mut pb, &request).await.unwrap();
+        let progress = AgentProgress::detached("test");
+        let result = chatter.collect_chunks(&progress, &request).await.unwrap();
         assert_eq!(
             result,
-            ProcessingOutcome::ApiSuccess(
+            ProcessingOutcome::ApiSuccess(vec![(
                 r#"{"message": "Hello, World!"}"#.to_string(),
                 Some(FinishReason::Stop)
-            )
+            )])
         );
     }
     #[tokio::test]
     async fn test_collect_chunks_length() {
         let msg = create_message("Request: Hello");
 
-        let request = ChatterJSON::create_request(&[msg]).unwrap();
+        let request = ChatterJSON::create_request(MODEL, TEMPERATURE, &[msg]).unwrap();
 
         let response_chunks = vec![create_chunk(
             r#"{"message": "Hello, World!"}"#,
@@ -723,32 +1111,60 @@ mod tests {
 
         let mock = make_mock(response_chunks);
         let chatter = ChatterJSON::with_client(Box::new(mock));
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let result = chatter.collect_chunks(&mut pb, &request).await.unwrap();
+        let progress = AgentProgress::detached("test");
+        let result = chatter.collect_chunks(&progress, &request).await.unwrap();
         assert_eq!(
             result,
-            ProcessingOutcome::ApiSuccess(
+            ProcessingOutcome::ApiSuccess(vec![(
                 r#"{"message": "Hello, World!"}"#.to_string(),
                 Some(FinishReason::Length)
-            )
+            )])
         );
     }
     #[tokio::test]
     async fn test_collect_chunks_too_many_blanks() {
         let msg = create_message("Request: Hello");
 
-        let request = ChatterJSON::create_request(&[msg]).unwrap();
+        let request = ChatterJSON::create_request(MODEL, TEMPERATURE, &[msg]).unwrap();
 
         let response_chunks =
             vec![create_chunk("", Some(FinishReason::Stop)); MAX_CONSECUTIVE_BLANKS + 1];
 
         let mock = make_mock(response_chunks);
         let chatter = ChatterJSON::with_client(Box::new(mock));
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let result = chatter.collect_chunks(&mut pb, &request).await.unwrap();
+        let progress = AgentProgress::detached("test");
+        let result = chatter.collect_chunks(&progress, &request).await.unwrap();
         assert_eq!(result, ProcessingOutcome::Retry);
     }
 
+    #[tokio::test]
+    async fn test_collect_chunks_regenerate() {
+        let request =
+            ChatterJSON::create_request(MODEL, TEMPERATURE, &[create_message("Request: Hello")])
+                .unwrap();
+
+        // A response slow enough to be interrupted.
+        let client = ScriptedClient::new(|_, _| json!({"message": "Hello, World!"}).to_string())
+            .with_chunks(1, Duration::from_millis(20));
+        let chatter = ChatterJSON::with_client(Box::new(client));
+        let steering = Steering::default();
+        let progress = AgentProgress::with_steering("test", steering.clone());
+        // A request from before the response started streaming is ignored.
+        steering.request(Some("stale".to_string()));
+        let requester = steering.clone();
+        tokio::spawn(async move {
+            while !requester.is_streaming("test") {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            requester.request(Some("be brief".to_string()));
+        });
+        let result = chatter.collect_chunks(&progress, &request).await.unwrap();
+        assert_eq!(
+            result,
+            ProcessingOutcome::Regenerate(Some("be brief".to_string()))
+        );
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // chat() tests
     ////////////////////////////////////////////////////////////////////////////////////////////////
@@ -764,9 +1180,9 @@ mod tests {
 
         let mock = make_mock(response_chunks);
         let chatter = ChatterJSON::with_client(Box::new(mock));
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let result = chatter.chat(&mut pb, &[request]).await.unwrap();
-        assert_eq!(result, json!({"message": "Hello, World!"})); // Adjust this assertion based on your actual expected output
+        let progress = AgentProgress::detached("test");
+        let result = chatter.chat(&progress, &[request], None).await.unwrap();
+        assert_eq!(result, vec![json!({"message": "Hello, World!"})]);
     }
 
     #[tokio::test]
@@ -783,9 +1199,9 @@ mod tests {
         )];
         let mock = make_mock(response_chunks);
         let chatter = ChatterJSON::with_client(Box::new(mock));
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let result = chatter.chat(&mut pb, &msgs).await.unwrap();
-        assert_eq!(result, json!({"message": "Hello, World!"})); // Adjust this assertion based on your actual expected output
+        let progress = AgentProgress::detached("test");
+        let result = chatter.chat(&progress, &msgs, None).await.unwrap();
+        assert_eq!(result, vec![json!({"message": "Hello, World!"})]);
     }
 
     #[tokio::test]
@@ -800,9 +1216,37 @@ mod tests {
 
         let mock = make_mock(response_chunks);
         let chatter = ChatterJSON::with_client(Box::new(mock));
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let result = chatter.chat(&mut pb, &[request]).await.unwrap();
-        assert_eq!(result, json!({"message": "Hello, World!"})); // Adjust this assertion based on your actual expected output
+        let progress = AgentProgress::detached("test");
+        let result = chatter.chat(&progress, &[request], None).await.unwrap();
+        assert_eq!(result, vec![json!({"message": "Hello, World!"})]);
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_samples() {
+        let request = create_message("Request: Hello, World!");
+
+        // The choices' chunks are interleaved, and the second choice can't be used.
+        let response_chunks = vec![
+            create_choice_chunk(0, r#"{"lgtm": "#, None),
+            create_choice_chunk(1, r#"{"lgtm": "#, None),
+            create_choice_chunk(2, r#"{"lgtm": false}"#, Some(FinishReason::Stop)),
+            create_choice_chunk(0, "true}", Some(FinishReason::Stop)),
+            create_choice_chunk(1, "tr", Some(FinishReason::Length)),
+        ];
+        let mut mock = MockOpenAIClient::new();
+        mock.expect_create_chat_stream()
+            .times(1)
+            .withf(|request| request.n == Some(3))
+            .returning(move |_| {
+                Ok(Box::pin(stream::iter(
+                    response_chunks.clone().into_iter().map(Ok),
+                )))
+            });
+        let mut chatter = ChatterJSON::with_client(Box::new(mock));
+        chatter.samples = 3;
+        let progress = AgentProgress::detached("test");
+        let result = chatter.chat(&progress, &[request], None).await.unwrap();
+        assert_eq!(result, vec![json!({"lgtm": true}), json!({"lgtm": false})]);
     }
 
     #[tokio::test]
@@ -813,8 +1257,8 @@ mod tests {
 
         let mock = make_mock(response_chunks);
         let chatter = ChatterJSON::with_client(Box::new(mock));
-        let mut pb = DoublingProgressBar::new("test_progress_bar").unwrap();
-        let result = chatter.chat(&mut pb, &[request]).await;
+        let progress = AgentProgress::detached("test");
+        let result = chatter.chat(&progress, &[request], None).await;
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
@@ -822,71 +1266,90 @@ mod tests {
         );
     }
 
-    ////////////////////////////////////////////////////////////////////////////////////////////////
-    // validate_fields() tests
-    ////////////////////////////////////////////////////////////////////////////////////////////////
-
-    // Test valid fields
-    #[test]
-    fn test_validate_fields_valid() {
-        let fields = vec!["field1", "field2"];
-        let value = json!({
-          "field1": "value1",
-          "field2": "value2"
-        });
-
-        let fields = ChatterJSON::validate_fields(&value, fields).unwrap();
-        assert!(fields.is_empty());
-    }
-
-    #[test]
-    fn test_validate_fields_extra() {
-        let fields = vec!["field1", "field2"];
-        let value = json!({
-          "field1": "value1",
-          "field2": "value2",
-          "field3": "value3"
-        });
-
-        let extra_fields = ChatterJSON::validate_fields(&value, fields).unwrap();
-        assert_eq!(extra_fields, vec!["field3"]);
-    }
-
-    #[test]
-    fn test_validate_fields_missing() {
-        let fields = vec!["field1", "field2"];
-        let value = json!({
-          "field1": "value1"
-        });
+    #[tokio::test]
+    async fn test_chat_corrects_malformed_response() {
+        let respond = |json: &'static str| {
+            move |_| -> Result<ChatCompletionResponseStream, OpenAIError> {
+                let chunk = create_chunk(json, Some(FinishReason::Stop));
+                Ok(Box::pin(stream::iter(vec![Ok(chunk)])))
+            }
+        };
+        let mut mock = MockOpenAIClient::new();
+        let mut sequence = mockall::Sequence::new();
+        mock.expect_create_chat_stream()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .withf(|request| request.messages.len() == 1)
+            .returning(respond(r#"{"code": {"fn a() {}": "", "fn b() {}": ""}}"#));
+        mock.expect_create_chat_stream()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .withf(|request| {
+                request.messages.len() == 2
+                    && format!("{:?}", request.messages[1]).contains(NESTED_CODE)
+            })
+            .returning(respond(r#"{"code": 7}"#));
+        mock.expect_create_chat_stream()
+            .times(1)
+            .in_sequence(&mut sequence)
+            // The second correction replaces the first.
+            .withf(|request| {
+                request.messages.len() == 2
+                    && format!("{:?}", request.messages[1]).contains("a number")
+            })
+            .returning(respond(r#"{"code": "fn a() {}"}"#));
 
-        match ChatterJSON::validate_fields(&value, fields) {
-            Ok(_) => panic!("Expected an error for missing fields, but got Ok"),
-            Err(e) => match e.downcast_ref::<AiCriticError>() {
-                Some(AiCriticError::MissingJsonFields { fields }) => {
-                    let expected_missing: HashSet<_> = ["field2"].iter().cloned().collect();
-                    let actual_missing: HashSet<_> = fields.iter().map(|s| s.as_str()).collect();
-                    assert_eq!(
-                        expected_missing, actual_missing,
-                        "Unexpected missing fields"
-                    );
-                }
-                _ => panic!("Expected MissingJsonFields error, got different error"),
-            },
-        }
+        let chatter = ChatterJSON::with_client(Box::new(mock));
+        let progress = AgentProgress::detached("test");
+        let result = chatter
+            .chat(&progress, &[create_message("Request: code")], None)
+            .await
+            .unwrap();
+        assert_eq!(result, vec![json!({"code": "fn a() {}"})]);
     }
 
-    #[test]
-    fn test_validate_fields_not_json_object() {
-        let value = json!("This is not a JSON object");
-
-        let fields = vec!["field1", "field2"]; // These fields are irrelevant in this case
+    #[tokio::test]
+    async fn test_chat_corrects_invalid_response() {
+        let respond = |json: &'static str| {
+            move |_| -> Result<ChatCompletionResponseStream, OpenAIError> {
+                let chunk = create_chunk(json, Some(FinishReason::Stop));
+                Ok(Box::pin(stream::iter(vec![Ok(chunk)])))
+            }
+        };
+        let corrected = |malformation: &'static str| {
+            move |request: &CreateChatCompletionRequest| {
+                request.messages.len() == 2
+                    && format!("{:?}", request.messages[1]).contains(malformation)
+            }
+        };
+        let mut mock = MockOpenAIClient::new();
+        let mut sequence = mockall::Sequence::new();
+        mock.expect_create_chat_stream()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(respond("not JSON"));
+        mock.expect_create_chat_stream()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .withf(corrected("wasn't valid JSON: expected ident"))
+            .returning(respond(r#"{"lgtm": true}"#));
+        mock.expect_create_chat_stream()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .withf(corrected("was missing the required fields `corrections`"))
+            .returning(respond(r#"{"lgtm": true, "corrections": []}"#));
 
-        match ChatterJSON::validate_fields(&value, fields) {
-            Ok(_) => panic!("Expected an error for non-object JSON, but got Ok"),
-            Err(e) => match e.downcast_ref::<AiCriticError>() {
-                Some(AiCriticError::NotJsonObject) => {}
-                _ => panic!("Expected NotJsonObject error, got different error"),
-            },
-        }
+        let chatter = ChatterJSON::with_client(Box::new(mock));
+        let progress = AgentProgress::detached("test");
+        let schema = crate::schema::latest(crate::schema::Kind::Review);
+        let result = chatter
+            .chat(
+                &progress,
+                &[create_message("Request: review")],
+                Some(schema),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, vec![json!({"lgtm": true, "corrections": []})]);
     }
 }
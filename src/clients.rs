@@ -0,0 +1,269 @@
+use crate::capabilities;
+use crate::chatter_json::{OpenAIClientTrait, SharedClient};
+use crate::fake;
+use crate::gemini::GeminiClient;
+use crate::provider::{Provider, Service};
+use crate::reasoning::ReasoningClient;
+use async_openai::error::OpenAIError;
+use async_openai::types::{ChatCompletionResponseStream, CreateChatCompletionRequest};
+use async_openai::Client;
+use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+// The agents of a run share their providers' clients rather than each building its own, so that
+// their requests reuse the pooled connections of one HTTP client, skipping a TLS handshake per
+// agent, and the network settings and retries apply to all of them alike. A pool is created with
+// the run's provider, and its clones, as in the providers derived from it, share the clients. A
+// client is built the first time that an agent uses its service, model, and settings.
+
+// How often a request that fails to start is retried, and the wait before the first retry, which
+// doubles with each one.
+const RETRIES: usize = 3;
+const BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Default)]
+pub struct ClientPool {
+    http: reqwest::Client,
+    clients: Arc<Mutex<HashMap<String, SharedClient>>>,
+}
+
+impl ClientPool {
+    pub fn new(http: reqwest::Client) -> Self {
+        ClientPool {
+            http,
+            clients: Arc::default(),
+        }
+    }
+
+    // The shared client of the provider's service, model, and settings. The service's settings,
+    // such as its base URL and key, the request timeout, and the reasoning budget are built into
+    // the client, so providers that differ in any of them, as critics configured with another
+    // endpoint do, get their own.
+    pub fn client(&self, provider: &Provider) -> SharedClient {
+        let key = format!(
+            "{:?}/{:?}/{}",
+            provider.service, provider.timeouts.request, provider.reasoning_tokens
+        );
+        let mut clients = self.clients.lock().unwrap();
+        clients
+            .entry(key)
+            .or_insert_with(|| {
+                Arc::new(RetryingClient {
+                    client: self.build(provider),
+                    retries: RETRIES,
+                    backoff: BACKOFF,
+                })
+            })
+            .clone()
+    }
+
+    fn build(&self, provider: &Provider) -> Arc<dyn OpenAIClientTrait + Send + Sync> {
+        let capabilities = capabilities::of(provider.model());
+        let raw = capabilities.needs_raw_requests();
+        let timeout = provider.timeouts.request;
+        let http = &self.http;
+        let tokens = provider.reasoning_tokens;
+        match &provider.service {
+            Service::OpenAi { config, .. } if raw => Arc::new(
+                ReasoningClient::new(config, capabilities, timeout, http)
+                    .with_reasoning_tokens(tokens),
+            ),
+            Service::Azure { config, .. } if raw => Arc::new(
+                ReasoningClient::new(config, capabilities, timeout, http)
+                    .with_reasoning_tokens(tokens),
+            ),
+            Service::OpenAi { config, .. } => {
+                Arc::new(Client::with_config(config.clone()).with_http_client(http.clone()))
+            }
+            Service::Azure { config, .. } => {
+                Arc::new(Client::with_config(config.clone()).with_http_client(http.clone()))
+            }
+            Service::Gemini { api_key, model } => Arc::new(GeminiClient::new(api_key, model, http)),
+            Service::Fake => Arc::new(fake::client()),
+        }
+    }
+}
+
+// Retries the requests whose streams fail to open, or fail before their first chunk, for reasons
+// that are likely to pass, such as a refused connection or an overloaded server, backing off
+// exponentially. An OpenAI stream opens before its request is sent, so its HTTP status arrives as
+// its first item. Errors that say what's wrong with the request or the account, such as a rejected
+// key, are returned at once, as are errors in streams that have started, which ChatterJSON handles.
+// The stream is returned at once, and opened when it's first polled, so that ChatterJSON's timeouts
+// cover the retries too.
+struct RetryingClient {
+    client: Arc<dyn OpenAIClientTrait + Send + Sync>,
+    retries: usize,
+    backoff: Duration,
+}
+
+impl RetryingClient {
+    // Open the request's stream and wait for its first item, retrying transient errors.
+    async fn open(
+        client: Arc<dyn OpenAIClientTrait + Send + Sync>,
+        request: CreateChatCompletionRequest,
+        retries: usize,
+        mut backoff: Duration,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let mut attempt = 0;
+        loop {
+            let opened = match client.create_chat_stream(request.clone()).await {
+                Ok(mut stream) => match stream.next().await {
+                    Some(Err(e)) => Err(e),
+                    first => Ok(stream::iter(first).chain(stream).boxed()),
+                },
+                Err(e) => Err(e),
+            };
+            match opened {
+                Err(e) if attempt < retries && is_transient(&e) => {
+                    log::warn!("Retrying a request in {:?}: {}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                opened => return opened,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl OpenAIClientTrait for RetryingClient {
+    async fn create_chat_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let opened = Self::open(self.client.clone(), request, self.retries, self.backoff);
+        Ok(stream::once(opened)
+            .flat_map(|opened| match opened {
+                Ok(stream) => stream,
+                Err(e) => stream::once(future::ready(Err(e))).boxed(),
+            })
+            .boxed())
+    }
+
+    fn clean_response(&self, response: String) -> String {
+        self.client.clean_response(response)
+    }
+}
+
+// A server error's status, at the start of the message of an OpenAI stream that failed to open,
+// as "Invalid status code: 503 Service Unavailable", or of the other clients' errors, as
+// "Gemini returned 503 Service Unavailable: ...".
+static SERVER_ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:Invalid status code:|\S+ returned) (?:500|502|503|504)\b").unwrap()
+});
+
+fn is_transient(error: &OpenAIError) -> bool {
+    match error {
+        OpenAIError::Reqwest(e) => e.is_connect() || e.is_timeout(),
+        OpenAIError::ApiError(e) => e.r#type.as_deref() == Some("server_error"),
+        OpenAIError::StreamError(message) => SERVER_ERROR.is_match(message),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::config::OpenAIConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Fails with the given error until it has been asked `failures` times, either to open its
+    // stream or, if `in_stream`, as the stream's first item.
+    struct FlakyClient {
+        failures: usize,
+        error: &'static str,
+        in_stream: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl OpenAIClientTrait for FlakyClient {
+        async fn create_chat_stream(
+            &self,
+            _request: CreateChatCompletionRequest,
+        ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+            let error = OpenAIError::StreamError(self.error.to_string());
+            match self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                true if self.in_stream => Ok(Box::pin(stream::iter([Err(error)]))),
+                true => Err(error),
+                false => Ok(Box::pin(stream::empty())),
+            }
+        }
+    }
+
+    // Whether the retrying client's stream ends without an error, and the number of requests.
+    async fn retry(failures: usize, error: &'static str, in_stream: bool) -> (bool, usize) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let client = FlakyClient {
+            failures,
+            error,
+            in_stream,
+            calls: calls.clone(),
+        };
+        let client = RetryingClient {
+            client: Arc::new(client),
+            retries: RETRIES,
+            backoff: Duration::from_millis(1),
+        };
+        let request = CreateChatCompletionRequest::default();
+        let mut stream = client.create_chat_stream(request).await.unwrap();
+        let ok = stream.next().await.is_none();
+        (ok, calls.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        for in_stream in [false, true] {
+            let overloaded = "Gemini returned 503 Service Unavailable: overloaded";
+            assert_eq!(retry(2, overloaded, in_stream).await, (true, 3));
+            let bad_gateway = "Invalid status code: 502 Bad Gateway";
+            assert_eq!(retry(9, bad_gateway, in_stream).await, (false, RETRIES + 1));
+            let unauthorized = "Gemini returned 401 Unauthorized: bad key";
+            assert_eq!(retry(1, unauthorized, in_stream).await, (false, 1));
+        }
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let transient = |message: &str| is_transient(&OpenAIError::StreamError(message.into()));
+        assert!(transient("Invalid status code: 504 Gateway Timeout"));
+        assert!(transient("o1-mini returned 500 Internal Server Error: "));
+        assert!(!transient("Invalid status code: 400 Bad Request"));
+        assert!(!transient(
+            "Gemini returned 400 Bad Request: max_tokens must be under 503"
+        ));
+        assert!(!transient("expected value at line 1 column 500"));
+    }
+
+    #[test]
+    fn test_client_is_shared() {
+        let pool = ClientPool::default();
+        let provider = Provider {
+            service: Service::Fake,
+            ..Default::default()
+        };
+        let client = pool.client(&provider);
+        assert!(Arc::ptr_eq(&client, &pool.clone().client(&provider)));
+        assert!(!Arc::ptr_eq(
+            &client,
+            &pool.client(&Provider::default().with_model("gpt-4o"))
+        ));
+        // Another endpoint for the same model gets its own client.
+        let openai = Provider::default();
+        let other = Provider {
+            service: Service::OpenAi {
+                config: OpenAIConfig::new().with_api_base("http://localhost:8080/v1"),
+                model: openai.model().to_string(),
+            },
+            ..Provider::default()
+        };
+        assert!(Arc::ptr_eq(&pool.client(&openai), &pool.client(&openai)));
+        assert!(!Arc::ptr_eq(&pool.client(&openai), &pool.client(&other)));
+    }
+}
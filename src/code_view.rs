@@ -0,0 +1,143 @@
+use similar::{ChangeTag, TextDiff};
+use std::env;
+use std::io::{self, IsTerminal};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+// The console shows each proposal with line numbers, followed by a unified diff against the
+// previous proposal, so that a run can be followed from one proposal to the next. On a terminal,
+// the code is highlighted with syntect and the diff's additions and removals are colored. Setting
+// NO_COLOR, or sending the output elsewhere, renders the same text without colors.
+
+const THEME: &str = "base16-ocean.dark";
+// The unchanged lines shown around each change of a diff.
+const CONTEXT_LINES: usize = 3;
+
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+pub struct CodeView {
+    // The syntaxes and theme to highlight with, or None to render without colors.
+    highlighter: Option<(SyntaxSet, Theme)>,
+}
+
+impl CodeView {
+    pub fn new(color: bool) -> Self {
+        let highlighter = color
+            .then(|| ThemeSet::load_defaults().themes.remove(THEME))
+            .flatten()
+            .map(|theme| (SyntaxSet::load_defaults_newlines(), theme));
+        CodeView { highlighter }
+    }
+
+    // Use colors if stdout is a terminal and NO_COLOR isn't set.
+    pub fn for_terminal() -> Self {
+        CodeView::new(io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none())
+    }
+
+    // The code with a line number before each line.
+    pub fn numbered(&self, code: &str) -> String {
+        let lines = self.highlight(code);
+        let width = lines.len().to_string().len();
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let number = format!("{:>width$} │", i + 1, width = width);
+                format!("{} {}", self.paint(DIM, &number), line)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // A unified diff from the old code to the new, with a few lines of context around each change.
+    pub fn diff(&self, old: &str, new: &str) -> String {
+        let diff = TextDiff::from_lines(old, new);
+        let mut out = vec![];
+        for hunk in diff
+            .unified_diff()
+            .context_radius(CONTEXT_LINES)
+            .iter_hunks()
+        {
+            out.push(self.paint(CYAN, &hunk.header().to_string()));
+            for change in hunk.iter_changes() {
+                let line = change.value().trim_end_matches('\n');
+                out.push(match change.tag() {
+                    ChangeTag::Delete => self.paint(RED, &format!("-{}", line)),
+                    ChangeTag::Insert => self.paint(GREEN, &format!("+{}", line)),
+                    ChangeTag::Equal => format!(" {}", line),
+                });
+            }
+        }
+        match out.is_empty() {
+            true => "(no changes)".to_string(),
+            false => out.join("\n"),
+        }
+    }
+
+    // Each line of the code, highlighted if there are colors.
+    fn highlight(&self, code: &str) -> Vec<String> {
+        let Some((syntaxes, theme)) = &self.highlighter else {
+            return code.lines().map(String::from).collect();
+        };
+        let syntax = syntaxes
+            .find_syntax_by_extension("rs")
+            .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        LinesWithEndings::from(code)
+            .map(|line| match highlighter.highlight_line(line, syntaxes) {
+                Ok(ranges) => format!(
+                    "{}{}",
+                    as_24_bit_terminal_escaped(&ranges, false).trim_end_matches('\n'),
+                    RESET
+                ),
+                Err(_) => line.trim_end_matches('\n').to_string(),
+            })
+            .collect()
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        match self.highlighter {
+            Some(_) => format!("{}{}{}", color, text, RESET),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "fn answer() -> i32 {\n    41\n}\n";
+    const NEW: &str = "fn answer() -> i32 {\n    42\n}\n";
+
+    #[test]
+    fn test_plain() {
+        let view = CodeView::new(false);
+        assert_eq!(
+            view.numbered(OLD),
+            "1 │ fn answer() -> i32 {\n2 │     41\n3 │ }"
+        );
+        assert_eq!(
+            view.diff(OLD, NEW),
+            "@@ -1,3 +1,3 @@\n fn answer() -> i32 {\n-    41\n+    42\n }"
+        );
+        assert_eq!(view.diff(OLD, OLD), "(no changes)");
+    }
+
+    #[test]
+    fn test_colored() {
+        let view = CodeView::new(true);
+        let numbered = view.numbered(OLD);
+        assert!(numbered.contains("\x1b[38;2;"));
+        assert_eq!(numbered.lines().count(), 3);
+        assert!(view
+            .diff(OLD, NEW)
+            .contains(&format!("{}+    42{}", GREEN, RESET)));
+    }
+}
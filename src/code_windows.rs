@@ -0,0 +1,216 @@
+use crate::assert_ids::Window;
+use crate::tokenizer::count_tokens;
+use syn::spanned::Spanned;
+use syn::{ImplItem, Item, ItemMod, Type};
+
+// With --critic-window-tokens, a proposal too long for a critic to review well at once is split
+// into windows of whole items, found with syn, of up to about that many tokens each. An item over
+// the budget on its own, such as a large impl block or test module, is split into its own items. The
+// windows partition the code, and each one also takes a few lines of its neighbors for context, so
+// they overlap. The critics review the windows in parallel, and each correction is labelled with
+// the lines and items of the window it's about.
+
+// The lines of context that a window shares with its neighbors on each side.
+const OVERLAP_LINES: usize = 5;
+
+#[derive(Debug, PartialEq)]
+pub struct CodeWindow {
+    pub lines: Window,
+    // The names of the items that the window holds, such as `fn parse`.
+    pub items: Vec<String>,
+}
+
+impl CodeWindow {
+    // The window's lines, counting from 1, e.g. "lines 40-92".
+    pub fn lines_label(&self) -> String {
+        format!("lines {}-{}", self.lines.start + 1, self.lines.end)
+    }
+
+    // The window's lines and items, e.g. "lines 40-92 (fn parse, impl Lexer)".
+    pub fn label(&self) -> String {
+        match self.items.is_empty() {
+            true => self.lines_label(),
+            false => format!("{} ({})", self.lines_label(), self.items.join(", ")),
+        }
+    }
+}
+
+// An item, or an item of an impl block, with its name, if it has one, and its lines, counting from 0
+// and ending before `end`.
+struct Unit {
+    name: Option<String>,
+    start: usize,
+    end: usize,
+}
+
+impl Unit {
+    fn new(name: Option<String>, span: proc_macro2::Span) -> Self {
+        Unit {
+            name,
+            start: span.start().line.saturating_sub(1),
+            end: span.end().line,
+        }
+    }
+}
+
+// Split the code into windows of up to about `max_tokens` tokens. Code within the budget, or that
+// doesn't parse, is a single window.
+pub fn split(code: &str, max_tokens: usize) -> Vec<CodeWindow> {
+    let lines: Vec<&str> = code.lines().collect();
+    let whole = vec![CodeWindow {
+        lines: Window {
+            start: 0,
+            end: lines.len(),
+        },
+        items: vec![],
+    }];
+    if count_tokens(code) <= max_tokens {
+        return whole;
+    }
+    let Ok(file) = syn::parse_file(code) else {
+        return whole;
+    };
+    let tokens =
+        |start: usize, end: usize| count_tokens(&lines[start..end.min(lines.len())].join("\n"));
+    let mut units = vec![];
+    item_units(&file.items, max_tokens, &tokens, &mut units);
+
+    // Fill each window with items until the next would take it over the budget.
+    let mut parts: Vec<(usize, usize, Vec<String>)> = vec![];
+    let mut start = 0;
+    let mut items: Vec<String> = vec![];
+    let mut filled = false;
+    for unit in units {
+        if filled && tokens(start, unit.end) > max_tokens {
+            parts.push((start, unit.start, std::mem::take(&mut items)));
+            start = unit.start;
+        }
+        filled = true;
+        items.extend(unit.name);
+    }
+    parts.push((start, lines.len(), items));
+
+    parts
+        .into_iter()
+        .map(|(start, end, items)| CodeWindow {
+            lines: Window {
+                start: start.saturating_sub(OVERLAP_LINES),
+                end: (end + OVERLAP_LINES).min(lines.len()),
+            },
+            items,
+        })
+        .collect()
+}
+
+// Add the units of the items, splitting the modules and impl blocks that are over the budget into
+// their own items.
+fn item_units(
+    items: &[Item],
+    max_tokens: usize,
+    tokens: &impl Fn(usize, usize) -> usize,
+    units: &mut Vec<Unit>,
+) {
+    for item in items {
+        let unit = Unit::new(item_name(item), item.span());
+        if tokens(unit.start, unit.end) <= max_tokens {
+            units.push(unit);
+            continue;
+        }
+        match item {
+            Item::Mod(ItemMod {
+                content: Some((_, content)),
+                ..
+            }) => item_units(content, max_tokens, tokens, units),
+            Item::Impl(block) => {
+                let ty = type_name(&block.self_ty);
+                for impl_item in block.items.iter() {
+                    let name = match impl_item {
+                        ImplItem::Fn(f) => Some(format!("fn {}::{}", ty, f.sig.ident)),
+                        _ => None,
+                    };
+                    units.push(Unit::new(name, impl_item.span()));
+                }
+            }
+            _ => units.push(unit),
+        }
+    }
+}
+
+fn item_name(item: &Item) -> Option<String> {
+    let name = match item {
+        Item::Fn(f) => format!("fn {}", f.sig.ident),
+        Item::Struct(s) => format!("struct {}", s.ident),
+        Item::Enum(e) => format!("enum {}", e.ident),
+        Item::Trait(t) => format!("trait {}", t.ident),
+        Item::Mod(m) => format!("mod {}", m.ident),
+        Item::Impl(block) => match &block.trait_ {
+            Some((_, path, _)) => format!(
+                "impl {} for {}",
+                path.segments.last()?.ident,
+                type_name(&block.self_ty)
+            ),
+            None => format!("impl {}", type_name(&block.self_ty)),
+        },
+        _ => return None,
+    };
+    Some(name)
+}
+
+// The name of a type, without its path or generics.
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map_or_else(String::new, |segment| segment.ident.to_string()),
+        _ => "_".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str) -> String {
+        let body: Vec<String> = (0..10)
+            .map(|i| format!("    let x{} = x * {} + {};", i, i, i))
+            .collect();
+        format!(
+            "// {}.\nfn {}(x: i64) -> i64 {{\n{}\n    x\n}}\n",
+            name,
+            name,
+            body.join("\n")
+        )
+    }
+
+    #[test]
+    fn test_split() {
+        let code = format!(
+            "use std::fmt;\n\n{}\n{}\nstruct S;\n\nimpl S {{\n{}\n{}}}\n",
+            function("first"),
+            function("second"),
+            function("third").replace('\n', "\n    "),
+            function("fourth").replace('\n', "\n    ")
+        );
+        let lines = code.lines().count();
+        let budget = count_tokens(&function("first")) + 20;
+        let windows = split(&code, budget);
+
+        assert_eq!(split(&code, count_tokens(&code)).len(), 1);
+        assert!(windows.len() >= 3, "{:#?}", windows);
+        assert_eq!(windows[0].lines.start, 0);
+        assert_eq!(windows.last().unwrap().lines.end, lines);
+        // The windows overlap, covering every line.
+        for pair in windows.windows(2) {
+            assert!(pair[1].lines.start < pair[0].lines.end);
+        }
+        let items: Vec<&String> = windows.iter().flat_map(|w| w.items.iter()).collect();
+        assert!(items.contains(&&"fn first".to_string()));
+        assert!(items.contains(&&"fn S::third".to_string()));
+        assert!(items.contains(&&"fn S::fourth".to_string()));
+        assert!(windows[0].label().starts_with("lines 1-"));
+
+        assert_eq!(split("fn broken( {", 1).len(), 1);
+    }
+}
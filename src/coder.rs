@@ -1,26 +1,24 @@
 use std::fmt;
 
-use crate::{chatter_json::ChatterJSON, DoublingProgressBar};
-use async_openai::types::{
-    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-    ChatCompletionRequestUserMessageArgs,
-};
+use crate::agent::{user_message, Agent};
+use crate::images::{self, ProblemImage};
+use crate::lenient::Reply;
+use crate::prompts::{Prompt, Prompts};
+use crate::schema::{self, Kind, Schema};
+use crate::{chatter_json::ChatterJSON, events::AgentProgress, provider::Provider};
+use async_openai::types::ChatCompletionRequestAssistantMessageArgs;
 use color_eyre::eyre::Result;
 use serde::Deserialize;
+use serde_json::json;
 
 const CODER_NAME: &str = "Coder";
-const SYSTEM_PROMPT: &str = "
-    Write the requested program in Rust. Include complete unit tests. Return the code as JSON in a 
-    string field called `code`.
-    Any clarifying explanations should be included in the code as // comments. 
-    Be sure that the tests demonstrate that the code solves the requested problem. 
-    Any `assert` used should include a custom message with a unique 6-digit hex number labelled 
-    `assert_id` that uniquely identifies the assert line so that line numbers are not required.
-";
 
 pub struct CoderAgent {
     pub name: String,
-    system_msg: ChatCompletionRequestMessage,
+    system_prompt: String,
+    // The self-review is a cheap check for obvious omissions, such as a missing requirement or
+    // test, so that the critics' more expensive round starts from a more complete solution.
+    self_review_prompt: String,
     chatter: ChatterJSON,
 }
 
@@ -36,38 +34,60 @@ impl fmt::Display for Code {
 }
 
 impl CoderAgent {
-    pub fn new(id: usize) -> Result<Self> {
-        let system_msg = ChatCompletionRequestSystemMessageArgs::default()
-            .content(SYSTEM_PROMPT)
-            .build()?
-            .into();
-
+    pub fn new(id: usize, provider: &Provider, prompts: &Prompts) -> Result<Self> {
         Ok(CoderAgent {
             name: format!("{}_{}", CODER_NAME, id),
-            system_msg,
-            chatter: ChatterJSON::new(),
+            system_prompt: prompts.get(Prompt::Coder).to_string(),
+            self_review_prompt: prompts.get(Prompt::CoderSelfReview).to_string(),
+            chatter: ChatterJSON::new(provider).expecting(Reply::Code),
         })
     }
 
-    pub async fn chat(&self, pb: &mut DoublingProgressBar, msg: &str) -> Result<Code> {
-        let user_msg = ChatCompletionRequestUserMessageArgs::default()
-            .content(msg)
-            .build()?
-            .into();
+    // Write a solution to the problem, which the images, if any, illustrate.
+    pub async fn write(
+        &self,
+        progress: &AgentProgress,
+        msg: &str,
+        images: &[ProblemImage],
+    ) -> Result<Code> {
+        self.chat(progress, &[images::user_message(msg, images)])
+            .await
+    }
+
+    // Ask the coder to check its solution to the problem and return it with any omissions fixed.
+    pub async fn self_review(
+        &self,
+        progress: &AgentProgress,
+        problem: &str,
+        images: &[ProblemImage],
+        code: &Code,
+    ) -> Result<Code> {
+        let msgs = [
+            images::user_message(problem, images),
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .content(json!({ "code": code.code }).to_string())
+                .build()?
+                .into(),
+            user_message(&self.self_review_prompt)?,
+        ];
+        self.chat(progress, &msgs).await
+    }
+}
+
+impl Agent for CoderAgent {
+    fn name(&self) -> &str {
+        &self.name
+    }
 
-        let json = self
-            .chatter
-            .chat(pb, &[self.system_msg.clone(), user_msg])
-            .await?;
+    fn system_prompt(&self) -> &str {
+        &self.system_prompt
+    }
+
+    fn chatter(&self) -> &ChatterJSON {
+        &self.chatter
+    }
 
-        // Check the fields. Should only be one: `code`.
-        let extra_keys = ChatterJSON::validate_fields(&json, vec!["code"])?;
-        if !extra_keys.is_empty() {
-            println!(
-                "{}: Warning: Extra keys in Coder response: {:?}",
-                self.name, extra_keys
-            );
-        }
-        Ok(serde_json::from_value(json)?)
+    fn schema(&self) -> &'static Schema {
+        schema::latest(Kind::Code)
     }
 }
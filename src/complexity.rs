@@ -0,0 +1,301 @@
+use crate::build_service::{Build, BuildService};
+use crate::processes;
+use color_eyre::eyre::Result;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// A problem can declare how its solution must scale, on a line such as
+//
+//   Complexity: O(n log n), no O(n^2)
+//
+// The solution must then define `fn bench(n: usize)`, which runs it on a generated input of size n,
+// and the problem should ask for it. Once the code passes its tests, the tester builds it with a
+// harness that times bench() on sizes that double until a call takes long enough, fits the exponent
+// k of time ~ n^k to the timings of the largest sizes, and fails the code if k is beyond what the
+// constraint allows, or if bench() can't be timed, because it panics or is too slow even for the
+// smallest sizes. Timings tell polynomial degrees apart but not log factors, so O(n) and
+// O(n log n) are measured alike, as are O(1) and O(log n).
+
+// How far the fitted exponent may be from a class's degree and still be taken for it.
+const TOLERANCE: f64 = 0.5;
+// The exponent is fitted to the timings of at most this many of the largest sizes, where fixed
+// costs matter least, and needs at least the minimum.
+const FIT_POINTS: usize = 5;
+const MIN_FIT_POINTS: usize = 3;
+// The harness is killed if it runs longer than this, as for an exponential solution.
+const MEASURE_TIMEOUT: Duration = Duration::from_secs(30);
+// The name of the function that the solution defines.
+pub const BENCH_FN: &str = "bench";
+const LINE_PREFIX: &str = "ai_critics_complexity";
+
+// The harness times each size by calling bench() repeatedly for at least 20 ms, and stops doubling
+// once a single call takes 100 ms or the size reaches 2^24.
+const HARNESS: &str = r#"
+#[cfg(test)]
+mod ai_critics_complexity {
+    #[test]
+    fn measure() {
+        let mut n: usize = 64;
+        while n <= 1 << 24 {
+            let start = std::time::Instant::now();
+            let mut calls = 0u32;
+            while calls == 0 || start.elapsed().as_millis() < 20 {
+                super::bench(std::hint::black_box(n));
+                calls += 1;
+            }
+            let secs = start.elapsed().as_secs_f64() / calls as f64;
+            println!("ai_critics_complexity {} {}", n, secs);
+            if secs > 0.1 {
+                break;
+            }
+            n *= 2;
+        }
+    }
+}
+"#;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Class {
+    Constant,
+    Logarithmic,
+    Linear,
+    Linearithmic,
+    Quadratic,
+    Cubic,
+}
+
+impl Class {
+    // The class of the big-O expression, such as `O(n log n)` or `O(n²)`.
+    fn parse(text: &str) -> Option<Class> {
+        let text: String = text
+            .to_lowercase()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let inner = text.strip_prefix("o(")?.strip_suffix(')')?;
+        match inner {
+            "1" => Some(Class::Constant),
+            "logn" => Some(Class::Logarithmic),
+            "n" => Some(Class::Linear),
+            "nlogn" | "n*logn" => Some(Class::Linearithmic),
+            "n^2" | "n²" | "n*n" => Some(Class::Quadratic),
+            "n^3" | "n³" => Some(Class::Cubic),
+            _ => None,
+        }
+    }
+
+    // The exponent that the class's timings show.
+    fn degree(self) -> f64 {
+        match self {
+            Class::Constant | Class::Logarithmic => 0.0,
+            Class::Linear | Class::Linearithmic => 1.0,
+            Class::Quadratic => 2.0,
+            Class::Cubic => 3.0,
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Class::Constant => "O(1)",
+            Class::Logarithmic => "O(log n)",
+            Class::Linear => "O(n)",
+            Class::Linearithmic => "O(n log n)",
+            Class::Quadratic => "O(n^2)",
+            Class::Cubic => "O(n^3)",
+        };
+        f.write_str(text)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Constraint {
+    // The class that the solution must be within, if one is given.
+    allowed: Option<Class>,
+    // The classes that the solution must be better than.
+    forbidden: Vec<Class>,
+}
+
+// The problem's complexity constraint, from its first line starting with "Complexity:", if it has
+// one. The line lists big-O classes separated by commas, each prefixed with "no" or "not" if it's
+// forbidden.
+pub fn parse(problem: &str) -> Option<Constraint> {
+    let line = problem.lines().find_map(|line| {
+        let (heading, rest) = line.trim().split_once(':')?;
+        heading.eq_ignore_ascii_case("complexity").then_some(rest)
+    })?;
+    let mut constraint = Constraint::default();
+    for part in line.split([',', ';']) {
+        let part = part.trim();
+        let lower = part.to_lowercase();
+        let (forbidden, class) = match lower.strip_prefix("no ").or(lower.strip_prefix("not ")) {
+            Some(class) => (true, class),
+            None => (false, lower.as_str()),
+        };
+        match (Class::parse(class), forbidden) {
+            (Some(class), true) => constraint.forbidden.push(class),
+            (Some(class), false) => constraint.allowed = Some(class),
+            (None, _) => log::warn!("Ignoring the complexity {:?}", part),
+        }
+    }
+    (constraint.allowed.is_some() || !constraint.forbidden.is_empty()).then_some(constraint)
+}
+
+impl Constraint {
+    // The largest exponent that the timings may show.
+    fn max_exponent(&self) -> f64 {
+        let allowed = self
+            .allowed
+            .map_or(f64::INFINITY, |class| class.degree() + TOLERANCE);
+        self.forbidden
+            .iter()
+            .map(|class| class.degree() - TOLERANCE)
+            .fold(allowed, f64::min)
+    }
+
+    pub fn allows(&self, exponent: f64) -> bool {
+        exponent < self.max_exponent()
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = self.allowed.iter().map(Class::to_string).collect();
+        parts.extend(self.forbidden.iter().map(|class| format!("no {}", class)));
+        f.write_str(&parts.join(", "))
+    }
+}
+
+// The outcome of timing the code's bench() on growing sizes.
+pub enum Measurement {
+    // The timings of each size, in seconds per call, and the exponent fitted to them, if there are
+    // enough.
+    Timed {
+        timings: Vec<(usize, f64)>,
+        exponent: Option<f64>,
+    },
+    // The code didn't build with the harness, as when it doesn't define bench().
+    Unbuilt {
+        stderr: String,
+    },
+    // The harness didn't finish, as when bench() panics, with how it ended and its stderr.
+    Crashed {
+        reason: String,
+        stderr: String,
+    },
+    // The harness didn't finish in time.
+    TimedOut {
+        limit: Duration,
+    },
+}
+
+// Build the code with the harness in a subdirectory of the directory, leaving the code's own build
+// alone, and time its bench().
+pub async fn measure(build: &BuildService, dir: &Path, code: &str) -> Result<Measurement> {
+    let dir = dir.join("complexity");
+    fs::create_dir_all(&dir)?;
+    let code = format!("{}\n{}", code, HARNESS);
+    let exec_path = match build.build(&dir, &code, "complexity", &["--test"]).await? {
+        Build::Built { exec_path, .. } => exec_path,
+        Build::Failed { stderr } => return Ok(Measurement::Unbuilt { stderr }),
+    };
+    let mut command = build.command(&exec_path);
+    command.args(["--exact", "ai_critics_complexity::measure", "--nocapture"]);
+    let run = processes::output_limited(&mut command, None, build.max_output());
+    let limited = match tokio::time::timeout(MEASURE_TIMEOUT, run).await {
+        Ok(limited) => limited?,
+        Err(_) => {
+            return Ok(Measurement::TimedOut {
+                limit: MEASURE_TIMEOUT,
+            })
+        }
+    };
+    let output = limited.output;
+    let reason = match output.status.code() {
+        _ if limited.exceeded => Some(format!("printed more than {} bytes", build.max_output())),
+        Some(0) => None,
+        Some(code) => Some(format!("exited with code {}", code)),
+        None => Some("was killed by a signal".to_string()),
+    };
+    if let Some(reason) = reason {
+        return Ok(Measurement::Crashed {
+            reason,
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+    let timings = timings(&String::from_utf8_lossy(&output.stdout));
+    Ok(Measurement::Timed {
+        exponent: fit(&timings),
+        timings,
+    })
+}
+
+// The sizes and timings that the harness printed.
+fn timings(stdout: &str) -> Vec<(usize, f64)> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.strip_prefix(LINE_PREFIX)?.split_whitespace();
+            Some((words.next()?.parse().ok()?, words.next()?.parse().ok()?))
+        })
+        .collect()
+}
+
+// The slope of the least-squares line through the logs of the largest sizes' timings, which is the
+// exponent k of time ~ n^k.
+fn fit(timings: &[(usize, f64)]) -> Option<f64> {
+    let points: Vec<(f64, f64)> = timings
+        .iter()
+        .rev()
+        .take(FIT_POINTS)
+        .filter(|(_, secs)| *secs > 0.0)
+        .map(|&(n, secs)| ((n as f64).ln(), secs.ln()))
+        .collect();
+    if points.len() < MIN_FIT_POINTS {
+        return None;
+    }
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / count;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / count;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    Some(covariance / variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let problem = "Sort the numbers.\nComplexity: O(n log n), no O(n²)\n";
+        let constraint = parse(problem).unwrap();
+        assert_eq!(constraint.allowed, Some(Class::Linearithmic));
+        assert_eq!(constraint.forbidden, vec![Class::Quadratic]);
+        assert_eq!(constraint.to_string(), "O(n log n), no O(n^2)");
+        assert!(constraint.allows(1.1));
+        assert!(!constraint.allows(1.9));
+
+        let constraint = parse("complexity: not O(n^3)").unwrap();
+        assert!(constraint.allows(2.0) && !constraint.allows(3.0));
+        assert_eq!(parse("Sort the numbers."), None);
+        assert_eq!(parse("Complexity: fast"), None);
+    }
+
+    #[test]
+    fn test_fit() {
+        let quadratic: Vec<(usize, f64)> = (6..12)
+            .map(|k| (1 << k, 1e-9 * ((1 << k) as f64).powi(2)))
+            .collect();
+        assert!((fit(&quadratic).unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(fit(&quadratic[..2]), None);
+        let stdout = "running 1 test\nai_critics_complexity 64 0.5\nai_critics_complexity 128 1\n";
+        assert_eq!(timings(stdout), vec![(64, 0.5), (128, 1.0)]);
+    }
+}
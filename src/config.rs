@@ -0,0 +1,119 @@
+use crate::critic::CriticType;
+use crate::errors::AiCriticError;
+use crate::network::NetworkArgs;
+use crate::provenance::ProvenanceConfig;
+use crate::provider::ProviderKind;
+use crate::store::StoreConfig;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+// A TOML file that configures the critics, so that an ensemble can mix providers and models, e.g.
+//
+//   [[critics]]
+//   type = "design"
+//   provider = "openai"
+//   model = "gpt-4-1106-preview"
+//
+//   [[critics]]
+//   type = "correctness"
+//   provider = "gemini"
+//   count = 2
+//   temperature = 0.9
+//   top_p = 0.95
+//
+// Each entry adds `count` critics of the type. The provider's default model is used if none is given,
+// and the temperature and top_p of --critic-temperatures and --critic-top-p, if not. The file may
+// also have a [store] table, which saves the runs' artifacts elsewhere too; see store.rs, and a
+// [network] table, which sets the proxy and certificates that the APIs are reached with; see
+// network.rs, and a [provenance] table, which stamps the published code with where it came from;
+// see provenance.rs.
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub critics: Vec<CriticConfig>,
+    pub store: Option<StoreConfig>,
+    pub network: Option<NetworkArgs>,
+    pub provenance: Option<ProvenanceConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CriticConfig {
+    #[serde(rename = "type")]
+    pub critic_type: CriticType,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    #[serde(default = "default_count")]
+    pub count: usize,
+}
+
+fn default_count() -> usize {
+    1
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let invalid = |reason: String| AiCriticError::InvalidConfig {
+            path: path.display().to_string(),
+            reason,
+        };
+        let contents = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        Ok(toml::from_str(&contents).map_err(|e| invalid(e.to_string()))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [[critics]]
+            type = "design"
+            model = "gpt-4"
+
+            [[critics]]
+            type = "correctness"
+            provider = "gemini"
+            count = 2
+            temperature = 0.9
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.critics.len(), 2);
+        assert_eq!(config.critics[0].critic_type, CriticType::Design);
+        assert_eq!(config.critics[0].provider, ProviderKind::Openai);
+        assert_eq!(config.critics[0].model.as_deref(), Some("gpt-4"));
+        assert_eq!(config.critics[0].count, 1);
+        assert_eq!(config.critics[1].provider, ProviderKind::Gemini);
+        assert_eq!(config.critics[1].count, 2);
+        assert_eq!(config.critics[1].temperature, Some(0.9));
+        assert_eq!(config.critics[1].top_p, None);
+
+        assert!(config.store.is_none() && config.network.is_none());
+        assert!(config.provenance.is_none());
+        assert!(toml::from_str::<Config>("[[critics]]\ntype = \"stylish\"").is_err());
+
+        let config: Config = toml::from_str("[store]\nkind = \"s3\"\nbucket = \"runs\"").unwrap();
+        assert!(config.critics.is_empty());
+        assert!(matches!(config.store, Some(StoreConfig::S3 { .. })));
+
+        let config: Config = toml::from_str("[network]\nproxy = \"http://proxy:8080\"").unwrap();
+        assert_eq!(
+            config.network.unwrap().proxy.as_deref(),
+            Some("http://proxy:8080")
+        );
+
+        let config: Config = toml::from_str("[provenance]\nnotice = \"MIT\"").unwrap();
+        assert_eq!(config.provenance.unwrap().notice, "MIT");
+    }
+}
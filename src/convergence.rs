@@ -0,0 +1,241 @@
+use crate::errors::AiCriticError;
+use crate::fixer::ReviewNeeded;
+use crate::pricing::Spend;
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+// A run proposes code until its tests pass or a convergence policy stops it. The policies are
+// checked before each proposal against the run's progress so far: the number of proposals, the
+// tester's failures, the tokens and estimated cost of the API requests, and the time elapsed.
+// They're combined with AnyOf, so `--max-cost 2 --deadline 10m` stops a run after $2 or 10
+// minutes, whichever comes first. Since a proposal isn't interrupted, a run can overshoot a limit
+// by the cost and time of one proposal.
+
+// What the policies know about a run.
+pub struct Progress {
+    // The number of proposals made so far.
+    pub proposals: usize,
+    // The tokens and estimated cost of the run's requests.
+    pub spend: Spend,
+    started: Instant,
+    // The failure of each tested proposal, in order, normalized for comparison, or None if it
+    // passed.
+    failures: Vec<Option<String>>,
+}
+
+impl Progress {
+    pub fn start() -> Self {
+        Progress {
+            proposals: 0,
+            spend: Spend::default(),
+            started: Instant::now(),
+            failures: vec![],
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // Record the tester's result for the latest proposal.
+    pub fn record_test(&mut self, failure: Option<&ReviewNeeded>) {
+        // The test harness reports how long the tests took, which differs between otherwise
+        // identical failures.
+        let timing = Regex::new(r"finished in [0-9.]+s").unwrap();
+        self.failures.push(failure.map(|review| {
+            timing
+                .replace_all(&review.comments.join("\n"), "")
+                .trim()
+                .to_string()
+        }));
+    }
+}
+
+pub trait ConvergencePolicy: Send + Sync {
+    // Return the error to stop the run with, or None to let it make another proposal.
+    fn check(&self, progress: &Progress) -> Option<AiCriticError>;
+}
+
+fn stopped(progress: &Progress, reason: String) -> Option<AiCriticError> {
+    Some(AiCriticError::Stopped {
+        proposals: progress.proposals,
+        reason,
+    })
+}
+
+pub struct MaxProposals(pub usize);
+
+impl ConvergencePolicy for MaxProposals {
+    fn check(&self, progress: &Progress) -> Option<AiCriticError> {
+        (progress.proposals >= self.0)
+            .then_some(AiCriticError::MaxProposalsExceeded { proposals: self.0 })
+    }
+}
+
+// Stop when the last N tested proposals all failed in the same way, which suggests that the fixer
+// is stuck.
+pub struct RepeatedFailures(pub usize);
+
+impl ConvergencePolicy for RepeatedFailures {
+    fn check(&self, progress: &Progress) -> Option<AiCriticError> {
+        let n = self.0.max(1);
+        let recent = progress.failures.iter().rev().take(n).collect::<Vec<_>>();
+        let last = recent.first()?.as_ref()?;
+        if recent.len() < n || recent.iter().any(|failure| failure.as_ref() != Some(last)) {
+            return None;
+        }
+        stopped(
+            progress,
+            format!("the last {} tested proposals failed identically", n),
+        )
+    }
+}
+
+fn over_budget(progress: &Progress, reason: String) -> Option<AiCriticError> {
+    Some(AiCriticError::BudgetExceeded {
+        proposals: progress.proposals,
+        reason,
+    })
+}
+
+// Stop once the estimated cost of the requests reaches this many dollars.
+pub struct CostCeiling(pub f64);
+
+impl ConvergencePolicy for CostCeiling {
+    fn check(&self, progress: &Progress) -> Option<AiCriticError> {
+        let cost = progress.spend.cost_usd;
+        if cost < self.0 {
+            return None;
+        }
+        over_budget(
+            progress,
+            format!("spent ${:.2} of the ${:.2} limit", cost, self.0),
+        )
+    }
+}
+
+// Stop once the prompt and completion tokens of the requests reach this many.
+pub struct TokenCeiling(pub u64);
+
+impl ConvergencePolicy for TokenCeiling {
+    fn check(&self, progress: &Progress) -> Option<AiCriticError> {
+        let tokens = progress.spend.prompt_tokens + progress.spend.completion_tokens;
+        if tokens < self.0 {
+            return None;
+        }
+        over_budget(
+            progress,
+            format!("used {} of the {} token limit", tokens, self.0),
+        )
+    }
+}
+
+// Stop once the run has taken this long.
+pub struct Deadline(pub Duration);
+
+impl ConvergencePolicy for Deadline {
+    fn check(&self, progress: &Progress) -> Option<AiCriticError> {
+        if progress.elapsed() < self.0 {
+            return None;
+        }
+        stopped(
+            progress,
+            format!("reached the deadline of {}s", self.0.as_secs()),
+        )
+    }
+}
+
+// Stop when any of the policies would, with the first one's error.
+pub struct AnyOf(pub Vec<Box<dyn ConvergencePolicy>>);
+
+impl ConvergencePolicy for AnyOf {
+    fn check(&self, progress: &Progress) -> Option<AiCriticError> {
+        self.0.iter().find_map(|policy| policy.check(progress))
+    }
+}
+
+// Parse a duration such as 90s, 10m, or 1h. A bare number is seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("unknown unit `{}`; use s, m, or h", unit)),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("`{}` isn't a duration such as 90s, 10m, or 1h", s))?;
+    Ok(Duration::from_secs_f64(number * seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixer::ReviewType;
+
+    fn failure(output: &str) -> ReviewNeeded {
+        ReviewNeeded::new(ReviewType::TestFix, vec![output.to_string()])
+    }
+
+    #[test]
+    fn test_policies() {
+        let mut progress = Progress::start();
+        let policy = AnyOf(vec![
+            Box::new(MaxProposals(5)),
+            Box::new(RepeatedFailures(2)),
+            Box::new(CostCeiling(2.0)),
+            Box::new(TokenCeiling(100_000)),
+            Box::new(Deadline(Duration::from_secs(600))),
+        ]);
+        assert!(policy.check(&progress).is_none());
+
+        progress.proposals = 2;
+        progress.record_test(Some(&failure("test result: FAILED. finished in 0.01s")));
+        progress.record_test(None);
+        progress.record_test(Some(&failure("test result: FAILED. finished in 0.01s")));
+        assert!(policy.check(&progress).is_none());
+        progress.record_test(Some(&failure("test result: FAILED. finished in 0.03s")));
+        let error = policy.check(&progress).unwrap();
+        assert_eq!(
+            error.to_string(),
+            "stopped after 2 proposals: the last 2 tested proposals failed identically"
+        );
+
+        let mut progress = Progress::start();
+        progress.spend.cost_usd = 2.5;
+        assert!(matches!(
+            policy.check(&progress),
+            Some(AiCriticError::BudgetExceeded { reason, .. })
+                if reason == "spent $2.50 of the $2.00 limit"
+        ));
+        progress.spend.cost_usd = 0.0;
+        progress.spend.prompt_tokens = 90_000;
+        progress.spend.completion_tokens = 10_000;
+        assert_eq!(
+            policy.check(&progress).unwrap().to_string(),
+            "over budget after 0 proposals: used 100000 of the 100000 token limit"
+        );
+        progress.spend = Spend::default();
+        progress.proposals = 5;
+        assert!(matches!(
+            policy.check(&progress),
+            Some(AiCriticError::MaxProposalsExceeded { proposals: 5 })
+        ));
+        assert!(Deadline(Duration::ZERO).check(&progress).is_some());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::errors::AiCriticError;
+use crate::fixer::{ReviewNeeded, ReviewType};
+use crate::processes;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+// Coverage is measured with rustc's `-C instrument-coverage`. Running the instrumented test binary
+// writes a .profraw file, `llvm-profdata` merges it into a .profdata file, and `llvm-cov` reports
+// the line and branch counts. The LLVM tools must match rustc's LLVM version, so the ones installed
+// by `rustup component add llvm-tools-preview` are preferred over any on the PATH.
+
+// Only tell the fixer about this many uncovered lines to keep the prompt short.
+const MAX_UNCOVERED_LINES: usize = 20;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoverageCount {
+    pub count: u64,
+    pub covered: u64,
+}
+
+impl CoverageCount {
+    // The covered fraction, or None if there was nothing to cover. Branch counts are only
+    // available on nightly with `-Z coverage-options=branch`, so they are often zero.
+    pub fn fraction(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.covered as f64 / self.count as f64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UncoveredLine {
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CoverageReport {
+    pub lines: CoverageCount,
+    pub branches: CoverageCount,
+    pub uncovered: Vec<UncoveredLine>,
+}
+
+impl CoverageReport {
+    // The line coverage, treating code with no lines as fully covered.
+    pub fn line_fraction(&self) -> f64 {
+        self.lines.fraction().unwrap_or(1.0)
+    }
+
+    // Build a review asking the fixer to add tests that exercise the uncovered lines.
+    pub fn review(&self) -> ReviewNeeded {
+        ReviewNeeded::new(
+            ReviewType::TestAugmentation,
+            self.uncovered
+                .iter()
+                .take(MAX_UNCOVERED_LINES)
+                .map(|u| format!("No test executes line {}: `{}`", u.line, u.text.trim()))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Coverage: {}/{} lines ({:.0}%)",
+            self.lines.covered,
+            self.lines.count,
+            self.line_fraction() * 100.0
+        )?;
+        match self.branches.fraction() {
+            Some(fraction) => write!(
+                f,
+                ", {}/{} branches ({:.0}%)",
+                self.branches.covered,
+                self.branches.count,
+                fraction * 100.0
+            ),
+            None => write!(f, ", branch coverage unavailable"),
+        }
+    }
+}
+
+// Find an LLVM tool, preferring the one installed with the Rust toolchain.
+async fn llvm_tool(name: &str) -> PathBuf {
+    let sysroot = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .await
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default();
+    let rustlib = Path::new(&sysroot).join("lib").join("rustlib");
+    if let Ok(entries) = std::fs::read_dir(rustlib) {
+        for entry in entries.flatten() {
+            let candidate = entry.path().join("bin").join(name);
+            if candidate.exists() {
+                return candidate;
+            }
+        }
+    }
+    PathBuf::from(name)
+}
+
+async fn run_tool(mut command: Command, tool: &str) -> Result<String> {
+    let output =
+        processes::output(&mut command)
+            .await
+            .map_err(|e| AiCriticError::CoverageFailed {
+                reason: format!("could not run {}: {}", tool, e),
+            })?;
+    if !output.status.success() {
+        return Err(AiCriticError::CoverageFailed {
+            reason: format!(
+                "{} failed: {}",
+                tool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        }
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Merge the raw profiles written by the instrumented test binary in `dir` and produce a report.
+pub async fn report(dir: &Path, exec_path: &Path) -> Result<CoverageReport> {
+    let profdata = dir.join("coverage.profdata");
+    let mut merge = Command::new(llvm_tool("llvm-profdata").await);
+    merge.args(["merge", "-sparse", "-o"]).arg(&profdata);
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        if entry.path().extension().is_some_and(|e| e == "profraw") {
+            merge.arg(entry.path());
+        }
+    }
+    run_tool(merge, "llvm-profdata").await?;
+
+    let llvm_cov = llvm_tool("llvm-cov").await;
+    let mut export = Command::new(&llvm_cov);
+    export
+        .args(["export", "--summary-only", "--instr-profile"])
+        .arg(&profdata)
+        .arg(exec_path);
+    let mut report = parse_summary(&run_tool(export, "llvm-cov").await?)?;
+
+    let mut show = Command::new(&llvm_cov);
+    show.args(["show", "--instr-profile"])
+        .arg(&profdata)
+        .arg(exec_path);
+    report.uncovered = parse_uncovered(&run_tool(show, "llvm-cov").await?);
+    Ok(report)
+}
+
+// Parse the totals from `llvm-cov export --summary-only` JSON.
+fn parse_summary(json: &str) -> Result<CoverageReport> {
+    let value: Value = serde_json::from_str(json)?;
+    let totals = &value["data"][0]["totals"];
+    if totals.is_null() {
+        return Err(AiCriticError::UnexpectedJsonStructure { json: value }.into());
+    }
+    Ok(CoverageReport {
+        lines: serde_json::from_value(totals["lines"].clone())?,
+        branches: serde_json::from_value(totals["branches"].clone()).unwrap_or_default(),
+        uncovered: vec![],
+    })
+}
+
+// Parse the uncovered lines from `llvm-cov show`, whose lines look like `   12|      0|  code`. A
+// count of 0 means the line was never executed; a blank count means the line has no code.
+fn parse_uncovered(show: &str) -> Vec<UncoveredLine> {
+    show.lines()
+        .filter_map(|l| {
+            let mut fields = l.splitn(3, '|');
+            let line = fields.next()?.trim().parse().ok()?;
+            let count = fields.next()?.trim();
+            let text = fields.next()?;
+            (count == "0").then(|| UncoveredLine {
+                line,
+                text: text.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_summary() {
+        let json = r#"{"data": [{"totals": {
+            "lines": {"count": 10, "covered": 8, "percent": 80.0},
+            "branches": {"count": 0, "covered": 0, "notcovered": 0, "percent": 0.0}
+        }}]}"#;
+        let report = parse_summary(json).unwrap();
+        assert_eq!(
+            report.lines,
+            CoverageCount {
+                count: 10,
+                covered: 8
+            }
+        );
+        assert_eq!(report.line_fraction(), 0.8);
+        assert_eq!(report.branches.fraction(), None);
+        assert_eq!(
+            report.to_string(),
+            "Coverage: 8/10 lines (80%), branch coverage unavailable"
+        );
+    }
+
+    #[test]
+    fn test_parse_uncovered() {
+        let show =
+            "    1|      2|fn f(x: i32) -> i32 {\n    2|      0|    x + 1\n    3|       |}\n";
+        let uncovered = parse_uncovered(show);
+        assert_eq!(
+            uncovered,
+            vec![UncoveredLine {
+                line: 2,
+                text: "    x + 1".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use crate::errors::AiCriticError;
+use crate::provider::ProviderKind;
+use color_eyre::eyre::Result;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// A provider's API key is looked for in three places, in order: its environment variable, such as
+// OPENAI_API_KEY, then ~/.config/ai_critics/credentials.toml, which maps each provider to its key,
+// then the OS keychain, through macOS's `security` or the Secret Service's `secret-tool` on Linux.
+// `ai_critics auth set <provider>` stores a key in the file, or in the keychain with --keychain, so
+// that it needn't be exported in every shell. The key is read without echoing it, and it's never
+// passed on a command line, where other users could see it. The file is kept readable only by its
+// owner.
+//
+//     openai = "sk-..."
+//     gemini = "..."
+
+// The keychain service that the keys are stored under, with the provider's name as the account.
+const KEYCHAIN_SERVICE: &str = "ai_critics";
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AuthArgs {
+    #[command(subcommand)]
+    pub command: AuthCommand,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum AuthCommand {
+    /// Store a provider's API key, prompted for or piped in, in
+    /// ~/.config/ai_critics/credentials.toml.
+    Set {
+        /// Provider whose key it is.
+        #[arg(value_enum)]
+        provider: ProviderKind,
+
+        /// Store the key in the OS keychain instead of the credentials file.
+        #[arg(long, default_value_t = false)]
+        keychain: bool,
+    },
+}
+
+// The provider's name in the credentials file and keychain.
+fn name(kind: ProviderKind) -> &'static str {
+    match kind {
+        ProviderKind::Openai => "openai",
+        ProviderKind::Azure => "azure",
+        ProviderKind::Gemini => "gemini",
+        ProviderKind::Fake => "fake",
+    }
+}
+
+// The environment variable that holds the provider's key, if it takes one.
+pub fn env_var(kind: ProviderKind) -> Option<&'static str> {
+    match kind {
+        ProviderKind::Openai => Some("OPENAI_API_KEY"),
+        ProviderKind::Azure => Some("AZURE_OPENAI_API_KEY"),
+        ProviderKind::Gemini => Some("GEMINI_API_KEY"),
+        ProviderKind::Fake => None,
+    }
+}
+
+// Where the places that the provider's key is looked for are, for telling the user.
+pub fn sources(kind: ProviderKind) -> String {
+    let file = credentials_path().map_or("the credentials file".to_string(), |path| {
+        path.display().to_string()
+    });
+    format!(
+        "{}, {}, or the OS keychain (see `ai_critics auth set {}`)",
+        env_var(kind).unwrap_or_default(),
+        file,
+        name(kind)
+    )
+}
+
+// The provider's API key from the first place that has one, or an error if the credentials file
+// can't be read.
+pub fn api_key(kind: ProviderKind) -> Result<Option<String>> {
+    let env_key = env_var(kind).and_then(|var| env::var(var).ok());
+    lookup(kind, env_key, credentials_path().as_deref(), || {
+        keychain_get(name(kind))
+    })
+}
+
+// The first non-empty key of the environment's, the file's, and the keychain's.
+fn lookup(
+    kind: ProviderKind,
+    env_key: Option<String>,
+    path: Option<&Path>,
+    keychain: impl FnOnce() -> Option<String>,
+) -> Result<Option<String>> {
+    let non_empty = |key: &String| !key.is_empty();
+    if let Some(key) = env_key.filter(non_empty) {
+        return Ok(Some(key));
+    }
+    if let Some(path) = path {
+        if let Some(key) = read_keys(path)?.remove(name(kind)).filter(non_empty) {
+            return Ok(Some(key));
+        }
+    }
+    Ok(keychain().filter(non_empty))
+}
+
+// Read the key from the terminal without echoing it, or from stdin if it's piped in.
+fn read_key(provider: &str) -> Result<String> {
+    let prompt = format!("API key for {}: ", provider);
+    if io::stdin().is_terminal() {
+        return Ok(rpassword::prompt_password(prompt)?);
+    }
+    let mut key = String::new();
+    io::stdin().lock().read_line(&mut key)?;
+    Ok(key)
+}
+
+pub fn run(args: &AuthArgs) -> Result<()> {
+    match &args.command {
+        AuthCommand::Set { provider, keychain } => {
+            let key = read_key(name(*provider))?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(AiCriticError::MissingSetting {
+                    setting: "the API key".to_string(),
+                }
+                .into());
+            }
+            let stored_in = if *keychain {
+                keychain_set(name(*provider), key)?;
+                "the OS keychain".to_string()
+            } else {
+                let path = credentials_path().ok_or_else(|| AiCriticError::MissingSetting {
+                    setting: "HOME, to find the credentials file".to_string(),
+                })?;
+                store_key(&path, name(*provider), key)?;
+                path.display().to_string()
+            };
+            println!("Stored the {} key in {}.", name(*provider), stored_in);
+            Ok(())
+        }
+    }
+}
+
+fn credentials_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("ai_critics").join("credentials.toml"))
+}
+
+// The keys in the credentials file, by provider, or none if there's no file.
+fn read_keys(path: &Path) -> Result<BTreeMap<String, String>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    toml::from_str(&text).map_err(|e| {
+        AiCriticError::InvalidConfig {
+            path: path.display().to_string(),
+            reason: e.message().to_string(),
+        }
+        .into()
+    })
+}
+
+// Add or replace the provider's key in the credentials file, keeping the others.
+fn store_key(path: &Path, provider: &str, key: &str) -> Result<()> {
+    let mut keys = read_keys(path)?;
+    keys.insert(provider.to_string(), key.to_string());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // The mode is only given to a file that's created, so an existing one is narrowed too.
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(toml::to_string(&keys)?.as_bytes())?;
+    Ok(())
+}
+
+// The key stored in the keychain, if the keychain's tool is installed and has one.
+fn keychain_get(account: &str) -> Option<String> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(["find-generic-password", "-w", "-s", KEYCHAIN_SERVICE])
+            .args(["-a", account])
+            .stderr(Stdio::null())
+            .output()
+    } else {
+        Command::new("secret-tool")
+            .args(["lookup", "service", KEYCHAIN_SERVICE, "account", account])
+            .stderr(Stdio::null())
+            .output()
+    }
+    .ok()?;
+    let key = String::from_utf8(output.stdout).ok()?;
+    output.status.success().then(|| key.trim().to_string())
+}
+
+// A string quoted for a command of `security -i`.
+fn security_quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Run the command with the input on its stdin, keeping it off the command line.
+fn output_with_input(command: &mut Command, input: &str) -> io::Result<std::process::Output> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())?;
+    child.wait_with_output()
+}
+
+fn keychain_set(account: &str, key: &str) -> Result<()> {
+    let (program, output) = if cfg!(target_os = "macos") {
+        // `security -i` reads its commands from stdin, so the key isn't in its arguments.
+        let command = format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            security_quoted(KEYCHAIN_SERVICE),
+            security_quoted(account),
+            security_quoted(key)
+        );
+        let output = output_with_input(Command::new("security").arg("-i"), &command);
+        ("security", output)
+    } else {
+        let output = output_with_input(
+            Command::new("secret-tool")
+                .args(["store", "--label", "ai_critics API key"])
+                .args(["service", KEYCHAIN_SERVICE, "account", account]),
+            key,
+        );
+        ("secret-tool", output)
+    };
+    let output = output.map_err(|e| AiCriticError::MissingSetting {
+        setting: format!("`{}` for the keychain ({})", program, e),
+    })?;
+    if !output.status.success() {
+        return Err(AiCriticError::MissingSetting {
+            setting: format!(
+                "a working keychain: `{}` failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ai_critics").join("credentials.toml");
+        assert!(read_keys(&path).unwrap().is_empty());
+
+        store_key(&path, "openai", "sk-1").unwrap();
+        store_key(&path, "gemini", "g-1").unwrap();
+        store_key(&path, "openai", "sk-2").unwrap();
+        let keys = read_keys(&path).unwrap();
+        assert_eq!(keys["openai"], "sk-2");
+        assert_eq!(keys["gemini"], "g-1");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::write(&path, "openai = [").unwrap();
+        assert!(read_keys(&path).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_store_key_narrows_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.toml");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        store_key(&path, "openai", "sk-1").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.toml");
+        fs::write(&path, "openai = \"sk-file\"\ngemini = \"\"\n").unwrap();
+        let openai = ProviderKind::Openai;
+        let keychain = || Some("sk-keychain".to_string());
+        let env_key = Some("sk-env".to_string());
+
+        let key = lookup(openai, env_key, Some(&path), keychain).unwrap();
+        assert_eq!(key.as_deref(), Some("sk-env"));
+        let key = lookup(openai, Some(String::new()), Some(&path), keychain).unwrap();
+        assert_eq!(key.as_deref(), Some("sk-file"));
+        let key = lookup(openai, None, None, keychain).unwrap();
+        assert_eq!(key.as_deref(), Some("sk-keychain"));
+        // An empty key in the file is passed over.
+        let key = lookup(ProviderKind::Gemini, None, Some(&path), keychain).unwrap();
+        assert_eq!(key.as_deref(), Some("sk-keychain"));
+        assert_eq!(lookup(openai, None, None, || None).unwrap(), None);
+
+        fs::write(&path, "openai = [").unwrap();
+        assert!(lookup(openai, None, Some(&path), keychain).is_err());
+    }
+
+    #[test]
+    fn test_security_quoted() {
+        assert_eq!(security_quoted("sk-1"), "\"sk-1\"");
+        assert_eq!(security_quoted("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
@@ -1,13 +1,19 @@
+use crate::agent::{user_message, Agent};
+use crate::anchors;
 use crate::chatter_json::ChatterJSON;
-use crate::DoublingProgressBar;
-use async_openai::types::{
-    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-    ChatCompletionRequestUserMessageArgs,
-};
+use crate::code_windows::CodeWindow;
+use crate::errors::AiCriticError;
+use crate::events::AgentProgress;
+use crate::lenient::Reply;
+use crate::prompts::{Prompt, Prompts};
+use crate::provider::Provider;
+use crate::schema::{self, Kind, Schema};
+use crate::tokenizer::truncate_middle;
 use color_eyre::eyre::Result;
-use serde::Deserialize;
 use serde::Deserializer;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 
 // There are 3 types critic agents that vary based the type of critique they give. Roughly these are:
 //
@@ -17,66 +23,96 @@ use serde_json::Value;
 //
 // As an alternative to these specialized agents, general agent combines the above into
 // a single prompt.
+//
+// With --idiomatic, an Idiomatic critic joins them, which judges whether the code is idiomatic
+// Rust by the Rust API guidelines. Along with the code, it's given clippy's pedantic lints of it.
+//
+// In chain-of-verification mode, the Correctness critic follows a two-step protocol instead of
+// judging the code at a glance: it derives concrete input/output examples from the problem
+// statement, then traces the code by hand on each of them. The traced examples are returned as
+// evidence, which the fixer gets along with the corrections.
+//
+// All critic agents share the base prompt, followed by the prompt of their type.
+//
+// With --batch-critics, a single batched critic takes the place of one critic of each type. Its
+// prompt has the perspectives of all of them, and it returns their reviews in one response, keyed
+// by type, which are split into the reviews of the critics that it stands for. This saves round
+// trips at the cost of the critics no longer being independent of one another.
+//
+// A correction may come with its location in the code, which is checked against the code and used
+// to point the fixer at the lines it's about.
 
-// All critic agents share the base prompt.
-const BASE_PROMPT: &str = "
-    Evaluate this code based on the criteria below. Make no comments or explanations.
-    Return JSON with two fields:
-    1. a field named `lgtm` with value `true` if the code is correct, else `false`.
-    2. a field `corrections` containing list of the errors, if any, else `None`.
-";
-
-const GENERAL_SYSTEM_PROMPT: &str = "
-    Review the code for design, correctness, and syntax issues.
-";
-
-const DESIGN_SYSTEM_PROMPT: &str = "
-    Evaluation Criteria: Evaluate the _design_ of the solution, considering the following questions: 
-    1. Is this the right the design to solve the problem?
-    2. Does the method chosen meet the constraints of the problem?
-    3. Does it use a the correct algorithms and data structures to solve the problem?
-";
-
-const CORRECTNESS_SYSTEM_PROMPT: &str = "
-    Evaluation Criteria: Evaluate the _correctness_ of the solution, considering the following 
-    questions: 
-    1. Does the code correctly implement the intended solution approach?
-    2. Does the code generate the expected output?
-    3. Does the output meet the original problem constraints?
-    4. Are there enough tests to demonstrate the correctness of the solution?
-    5. Do the tests correctly capture situations that validate or invalidate the solution?
-";
-
-const SYNTAX_SYSTEM_PROMPT: &str = "
-    Evaluation Criteria: Evaluate the _syntax_ of the solution, considering the following questions: 
-    1. Are there any syntactic errors?
-    2. Will the code and tests compile and run?
-    3. Are there any language errors such as borrowing violations or lifetime problems?
-    4. Are there any cleanups needed such as unused variables or imports?
-";
+// The compiler and test output that the critics see is trimmed to about this many tokens.
+const MAX_TESTER_OUTPUT_TOKENS: usize = 1000;
+// The lints that the Idiomatic critic sees are trimmed to about this many tokens.
+const MAX_LINT_TOKENS: usize = 1000;
 
+// A general critic can't be disabled, as it stands in for the others.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum CriticType {
+    #[value(skip)]
     General,
     Design,
     Correctness,
     Syntax,
+    Idiomatic,
 }
 
 pub struct CriticAgent {
     pub name: String,
     pub critic_type: CriticType,
-    system_msg: ChatCompletionRequestMessage,
+    system_prompt: String,
     chatter: ChatterJSON,
+    verifies: bool,
+    id: usize,
+    // The types of critic that a batched critic reviews as. Empty for the other critics.
+    batch: Vec<CriticType>,
+    // Clippy's lints of the code, for a critic that reviews as an Idiomatic critic.
+    lints: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Eq, PartialEq, Hash)]
+#[serde(from = "RawCorrection")]
 pub struct Correction {
-    #[serde(skip_deserializing)]
     pub name: String,
-    #[serde(default)]
     pub lgtm: bool,
-    #[serde(deserialize_with = "deserialize_corrections")]
     pub corrections: Vec<String>,
+    // Where in the code each correction applies, as far as the critic said.
+    pub locations: Vec<Location>,
+    // The examples traced through the code in chain-of-verification mode.
+    pub evidence: Vec<String>,
+}
+
+// Where a correction applies: a line of the code, counting from 1, and the function it's in.
+#[derive(Deserialize, Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Location {
+    pub line: Option<usize>,
+    pub function: Option<String>,
+}
+
+// A critic's response, in which each correction is a string or an object with its location.
+#[derive(Deserialize)]
+struct RawCorrection {
+    #[serde(default)]
+    lgtm: bool,
+    #[serde(deserialize_with = "deserialize_located")]
+    corrections: Vec<(String, Location)>,
+    #[serde(default, deserialize_with = "deserialize_corrections")]
+    evidence: Vec<String>,
+}
+
+impl From<RawCorrection> for Correction {
+    fn from(raw: RawCorrection) -> Self {
+        let (corrections, locations) = raw.corrections.into_iter().unzip();
+        Correction {
+            name: String::new(),
+            lgtm: raw.lgtm,
+            corrections,
+            locations,
+            evidence: raw.evidence,
+        }
+    }
 }
 
 // The `#[serde(default)]` annotation doesn't, so we need to do this manually.
@@ -100,61 +136,366 @@ where
     }
 }
 
-impl CriticAgent {
-    pub fn new(critic_type: CriticType, id: usize) -> Result<Self> {
-        let name = match critic_type {
-            CriticType::General => format!("General Critic {}", id),
-            CriticType::Design => format!("Design Critic {}", id),
-            CriticType::Correctness => format!("Correctness Critic {}", id),
-            CriticType::Syntax => format!("Syntax Critic {}", id),
-        };
+// Like deserialize_corrections, but each correction may instead be an object holding it in a
+// `correction` field, with optional `line` and `function` fields. A line that isn't a positive
+// number is ignored.
+fn deserialize_located<'de, D>(deserializer: D) -> Result<Vec<(String, Location)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let v = Value::deserialize(deserializer)?;
+    let items = match v {
+        Value::Null => return Ok(Vec::new()),
+        Value::Array(arr) => arr,
+        _ => return Err(serde::de::Error::custom("Expected array or null")),
+    };
+    items
+        .into_iter()
+        .map(|item| match item {
+            Value::String(s) => Ok((s, Location::default())),
+            Value::Object(obj) => {
+                let correction =
+                    obj.get("correction")
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| {
+                            serde::de::Error::custom("Expected a `correction` string in the object")
+                        })?;
+                let location = Location {
+                    line: obj
+                        .get("line")
+                        .and_then(Value::as_u64)
+                        .filter(|&line| line > 0)
+                        .map(|line| line as usize),
+                    function: obj
+                        .get("function")
+                        .and_then(Value::as_str)
+                        .map(String::from),
+                };
+                Ok((correction.to_string(), location))
+            }
+            _ => Err(serde::de::Error::custom("Expected string or object")),
+        })
+        .collect()
+}
 
-        let critic_prompt = match critic_type {
-            CriticType::General => format!("{}\n{}", BASE_PROMPT, GENERAL_SYSTEM_PROMPT),
-            CriticType::Design => format!("{}\n{}", BASE_PROMPT, DESIGN_SYSTEM_PROMPT),
-            CriticType::Correctness => format!("{}\n{}", BASE_PROMPT, CORRECTNESS_SYSTEM_PROMPT),
-            CriticType::Syntax => format!("{}\n{}", BASE_PROMPT, SYNTAX_SYSTEM_PROMPT),
-        };
+impl CriticType {
+    // The type's name in config files and in the responses of batched critics.
+    pub fn key(self) -> &'static str {
+        match self {
+            CriticType::General => "general",
+            CriticType::Design => "design",
+            CriticType::Correctness => "correctness",
+            CriticType::Syntax => "syntax",
+            CriticType::Idiomatic => "idiomatic",
+        }
+    }
 
-        let system_msg = ChatCompletionRequestSystemMessageArgs::default()
-            .content(critic_prompt)
-            .build()?
-            .into();
+    // The name that the type's critics are numbered after, as in "Design Critic 2".
+    pub fn critic_name(self) -> &'static str {
+        match self {
+            CriticType::General => "General Critic",
+            CriticType::Design => "Design Critic",
+            CriticType::Correctness => "Correctness Critic",
+            CriticType::Syntax => "Syntax Critic",
+            CriticType::Idiomatic => "Idiomatic Critic",
+        }
+    }
+
+    // The type of the critic with the name, such as "Design Critic 2 (gpt-4o)" or one of its
+    // samples, "Design Critic 2 (sample 3)".
+    pub fn of_critic(name: &str) -> Option<Self> {
+        <CriticType as clap::ValueEnum>::value_variants()
+            .iter()
+            .copied()
+            .find(|critic_type| {
+                name.strip_prefix(critic_type.critic_name())
+                    .is_some_and(|rest| rest.starts_with(' '))
+            })
+    }
+}
 
-        let chatter = ChatterJSON::new();
+impl CriticAgent {
+    pub fn new(
+        critic_type: CriticType,
+        id: usize,
+        provider: &Provider,
+        prompts: &Prompts,
+    ) -> Result<Self> {
+        let chatter = ChatterJSON::new(provider).expecting(Reply::Review);
 
         Ok(CriticAgent {
-            name,
+            name: critic_name(critic_type, id),
             critic_type,
-            system_msg,
+            system_prompt: critic_prompt(prompts, critic_type),
             chatter,
+            verifies: false,
+            id,
+            batch: vec![],
+            lints: None,
+        })
+    }
+
+    // A critic that reviews the code as each of the given types of critic in one request. Like a
+    // general critic, it judges every aspect of the code, but it gives each its own review.
+    pub fn batched(
+        types: &[CriticType],
+        id: usize,
+        provider: &Provider,
+        prompts: &Prompts,
+    ) -> Result<Self> {
+        let perspectives: Vec<String> = types
+            .iter()
+            .map(|critic_type| {
+                format!(
+                    "Perspective `{}`:\n{}",
+                    critic_type.key(),
+                    prompts.get(type_prompt(*critic_type))
+                )
+            })
+            .collect();
+        Ok(CriticAgent {
+            name: format!("Batched Critic {}", id),
+            critic_type: CriticType::General,
+            system_prompt: format!(
+                "{}\n\n{}\n\n{}",
+                prompts.get(Prompt::CriticBase),
+                prompts.get(Prompt::CriticBatch),
+                perspectives.join("\n\n")
+            ),
+            chatter: ChatterJSON::new(provider).expecting(Reply::Review),
+            verifies: false,
+            id,
+            batch: types.to_vec(),
+            lints: None,
+        })
+    }
+
+    // Have a Correctness critic trace examples through the code and return them as evidence. Other
+    // critics are unchanged.
+    pub fn with_chain_of_verification(self, prompts: &Prompts) -> Result<Self> {
+        if self.critic_type != CriticType::Correctness {
+            return Ok(self);
+        }
+        let prompt = format!(
+            "{}\n\n{}",
+            critic_prompt(prompts, self.critic_type),
+            prompts.get(Prompt::CriticVerification)
+        );
+        Ok(CriticAgent {
+            system_prompt: prompt,
+            verifies: true,
+            ..self
+        })
+    }
+
+    // Whether the critic reviews as an Idiomatic critic, alone or as part of a batch.
+    pub fn wants_lints(&self) -> bool {
+        self.critic_type == CriticType::Idiomatic || self.batch.contains(&CriticType::Idiomatic)
+    }
+
+    // Give the lints of the code to the critic if it wants them. Other critics are unchanged.
+    pub fn with_lints(self, lints: &str) -> Self {
+        if !self.wants_lints() {
+            return self;
+        }
+        CriticAgent {
+            lints: Some(lints.to_string()),
+            ..self
+        }
+    }
+
+    // The critic's reviews, one for each sampled response, or a batched critic's, one for each of
+    // its types in each sampled response.
+    pub async fn reviews(&self, progress: &AgentProgress, msg: &str) -> Result<Vec<Correction>> {
+        let msgs = [user_message(&lint_message(msg, self.lints.as_deref()))?];
+        if self.batch.is_empty() {
+            let samples: Vec<Correction> = self.chat_samples(progress, &msgs).await?;
+            return Ok(samples
+                .into_iter()
+                .enumerate()
+                .map(|(i, mut correction)| {
+                    correction.name = sample_name(&self.name, i);
+                    correction
+                })
+                .collect());
+        }
+        let samples: Vec<HashMap<String, Correction>> = self.chat_samples(progress, &msgs).await?;
+        let mut reviews = vec![];
+        for (i, mut sample) in samples.into_iter().enumerate() {
+            for critic_type in self.batch.iter() {
+                let mut correction = sample.remove(critic_type.key()).ok_or_else(|| {
+                    AiCriticError::MissingJsonFields {
+                        fields: vec![critic_type.key().to_string()],
+                    }
+                })?;
+                correction.name = sample_name(&critic_name(*critic_type, self.id), i);
+                reviews.push(correction);
+            }
+        }
+        Ok(reviews)
+    }
+
+    // The names and types of the critics whose reviews the critic returns: itself, or those that a
+    // batched critic stands for, once for each sampled response.
+    pub fn members(&self) -> Vec<(String, CriticType)> {
+        let members = match self.batch.is_empty() {
+            true => vec![(self.name.clone(), self.critic_type)],
+            false => self
+                .batch
+                .iter()
+                .map(|critic_type| (critic_name(*critic_type, self.id), *critic_type))
+                .collect(),
+        };
+        (0..self.chatter.samples())
+            .flat_map(|i| {
+                members
+                    .iter()
+                    .map(move |(name, critic_type)| (sample_name(name, i), *critic_type))
+            })
+            .collect()
+    }
+}
+
+impl Agent for CriticAgent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn system_prompt(&self) -> &str {
+        &self.system_prompt
+    }
+
+    fn chatter(&self) -> &ChatterJSON {
+        &self.chatter
+    }
+
+    fn schema(&self) -> &'static Schema {
+        schema::latest(match (self.batch.is_empty(), self.verifies) {
+            (false, _) => Kind::BatchedReview,
+            (true, true) => Kind::VerifiedReview,
+            (true, false) => Kind::Review,
         })
     }
+}
+
+// The message that the critics review: the problem, the code, and what the tester reported for the
+// previous version of the code, if it has been tested.
+pub fn review_message(problem: &str, code: &str, tester_output: Option<&str>) -> String {
+    let msg = format!("{}\n\n------\n\n{}", problem, code);
+    match tester_output {
+        Some(output) => {
+            let (output, _) = truncate_middle(output, MAX_TESTER_OUTPUT_TOKENS);
+            format!("{}\n\n------\n\n{}", msg, output)
+        }
+        None => msg,
+    }
+}
+
+// The review message followed by the lints of the code, if the critic is given them.
+fn lint_message(msg: &str, lints: Option<&str>) -> String {
+    match lints {
+        Some(lints) => {
+            let (lints, _) = truncate_middle(lints, MAX_LINT_TOKENS);
+            format!("{}\n\n------\n\nclippy::pedantic:\n{}", msg, lints)
+        }
+        None => msg.to_string(),
+    }
+}
+
+// The message that the critics review for a window of the code: the review message with only the
+// window's lines, numbered as in the whole code, introduced by the window prompt.
+pub fn window_message(
+    prompts: &Prompts,
+    problem: &str,
+    code: &str,
+    window: &CodeWindow,
+    tester_output: Option<&str>,
+) -> String {
+    let part = format!(
+        "{}\n\nThis is {} of the code's {} lines:\n\n{}",
+        prompts.get(Prompt::CriticWindow),
+        window.lines_label(),
+        code.lines().count(),
+        anchors::numbered(&window.lines.text(code), window.lines.start + 1)
+    );
+    review_message(problem, &part, tester_output)
+}
 
-    pub async fn chat(&self, pb: &mut DoublingProgressBar, msg: &str) -> Result<Correction> {
-        let user_msg = ChatCompletionRequestUserMessageArgs::default()
-            .content(msg)
-            .build()?
-            .into();
-
-        let json = self
-            .chatter
-            .chat(pb, &[self.system_msg.clone(), user_msg])
-            .await?;
-
-        // Check the fields. Should only be two: `lgtm` and `corrections`.
-        let extra_keys = ChatterJSON::validate_fields(&json, vec!["lgtm", "corrections"])?;
-        if !extra_keys.is_empty() {
-            println!(
-                "{}: Warning: Extra keys in critic response: {:?}",
-                self.name, extra_keys
-            );
+// Merge a critic's reviews of the windows of the code into one, labelling each correction and
+// example with the window that it's about. The code is correct only if every window is.
+pub fn merge_windows(name: &str, reviews: Vec<(String, Correction)>) -> Correction {
+    let label = |label: &str, items: &[String]| -> Vec<String> {
+        items
+            .iter()
+            .map(|item| format!("In {}: {}", label, item))
+            .collect()
+    };
+    Correction {
+        name: name.to_string(),
+        lgtm: reviews.iter().all(|(_, review)| review.lgtm),
+        corrections: reviews
+            .iter()
+            .flat_map(|(window, review)| label(window, &review.corrections))
+            .collect(),
+        locations: reviews
+            .iter()
+            .flat_map(|(_, review)| review.locations.iter().cloned())
+            .collect(),
+        evidence: reviews
+            .iter()
+            .flat_map(|(window, review)| label(window, &review.evidence))
+            .collect(),
+    }
+}
+
+// Merge a critic's reviews of the windows of the code, each made up of the reviews of its members,
+// into one review for each member. A member whose sample of a window was dropped is judged on the
+// rest of the windows.
+pub fn merge_window_reviews(labels: Vec<String>, reviews: Vec<Vec<Correction>>) -> Vec<Correction> {
+    let mut members: Vec<(String, Vec<(String, Correction)>)> = vec![];
+    for (label, window_reviews) in labels.into_iter().zip(reviews) {
+        for review in window_reviews {
+            match members.iter_mut().find(|(name, _)| *name == review.name) {
+                Some((_, reviews)) => reviews.push((label.clone(), review)),
+                None => members.push((review.name.clone(), vec![(label.clone(), review)])),
+            }
         }
-        // Ok(serde_json::from_value(json)?) // Convert to AiCriticError.
-        let mut correction: Correction = serde_json::from_value(json)?;
-        correction.name = self.name.clone();
-        Ok(correction)
     }
+    members
+        .into_iter()
+        .map(|(name, reviews)| merge_windows(&name, reviews))
+        .collect()
+}
+
+// The name of the critic's review in the sampled response with the index, counting from 0. The first
+// keeps the critic's name.
+fn sample_name(name: &str, sample: usize) -> String {
+    match sample {
+        0 => name.to_string(),
+        i => format!("{} (sample {})", name, i + 1),
+    }
+}
+
+fn critic_name(critic_type: CriticType, id: usize) -> String {
+    format!("{} {}", critic_type.critic_name(), id)
+}
+
+fn type_prompt(critic_type: CriticType) -> Prompt {
+    match critic_type {
+        CriticType::General => Prompt::CriticGeneral,
+        CriticType::Design => Prompt::CriticDesign,
+        CriticType::Correctness => Prompt::CriticCorrectness,
+        CriticType::Syntax => Prompt::CriticSyntax,
+        CriticType::Idiomatic => Prompt::CriticIdiomatic,
+    }
+}
+
+fn critic_prompt(prompts: &Prompts, critic_type: CriticType) -> String {
+    format!(
+        "{}\n\n{}",
+        prompts.get(Prompt::CriticBase),
+        prompts.get(type_prompt(critic_type))
+    )
 }
 
 #[cfg(test)]
@@ -186,4 +527,210 @@ mod tests {
         let result4 = deserialize_corrections(&input4);
         assert!(result4.is_err());
     }
+
+    #[test]
+    fn test_review_message() {
+        assert_eq!(
+            review_message("Add.", "fn add() {}", None),
+            "Add.\n\n------\n\nfn add() {}"
+        );
+        let output = (1..=1000)
+            .map(|i| format!("error[E0425]: cannot find value `x{}`", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let msg = review_message("Add.", "fn add() {}", Some(&output));
+        assert!(msg.starts_with("Add.\n\n------\n\nfn add() {}\n\n------\n\nerror[E0425]"));
+        assert!(msg.contains("tokens omitted"));
+    }
+
+    #[test]
+    fn test_correction_evidence() {
+        let correction: Correction = serde_json::from_value(serde_json::json!({
+            "lgtm": false,
+            "corrections": ["fib(0) returns 1."],
+            "evidence": ["fib(0): expected 0, traced 1"],
+        }))
+        .unwrap();
+        assert_eq!(correction.evidence, vec!["fib(0): expected 0, traced 1"]);
+
+        // Critics that don't verify return no evidence.
+        let correction: Correction =
+            serde_json::from_value(serde_json::json!({"lgtm": true, "corrections": null})).unwrap();
+        assert!(correction.evidence.is_empty());
+    }
+
+    #[test]
+    fn test_correction_locations() {
+        let correction: Correction = serde_json::from_value(serde_json::json!({
+            "lgtm": false,
+            "corrections": [
+                "Handle the empty input.",
+                {"correction": "Don't unwrap.", "line": 12, "function": "parse"},
+                {"correction": "Check the bound.", "line": 0},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            correction.corrections,
+            vec![
+                "Handle the empty input.",
+                "Don't unwrap.",
+                "Check the bound."
+            ]
+        );
+        assert_eq!(
+            correction.locations,
+            vec![
+                Location::default(),
+                Location {
+                    line: Some(12),
+                    function: Some("parse".to_string()),
+                },
+                Location::default(),
+            ]
+        );
+        let missing = serde_json::json!({"lgtm": false, "corrections": [{"line": 3}]});
+        assert!(serde_json::from_value::<Correction>(missing).is_err());
+    }
+
+    #[test]
+    fn test_windows() {
+        let code = "fn a() {}\nfn b() {}\nfn c() {}\n";
+        let window = CodeWindow {
+            lines: crate::assert_ids::Window { start: 1, end: 3 },
+            items: vec!["fn b".to_string(), "fn c".to_string()],
+        };
+        let msg = window_message(&Prompts::default(), "Add.", code, &window, None);
+        assert!(msg.contains(
+            "This is lines 2-3 of the code's 3 lines:\n\n2 | fn b() {}\n3 | fn c() {}\n"
+        ));
+        assert!(!msg.contains("fn a()"));
+
+        let review = |lgtm, corrections: &[&str]| Correction {
+            name: String::new(),
+            lgtm,
+            corrections: corrections.iter().map(|c| c.to_string()).collect(),
+            locations: vec![Location::default(); corrections.len()],
+            evidence: vec![],
+        };
+        let merged = merge_windows(
+            "Syntax Critic 1",
+            vec![
+                ("lines 1-2 (fn a)".to_string(), review(true, &[])),
+                (
+                    "lines 2-3 (fn b)".to_string(),
+                    review(false, &["`b` is empty."]),
+                ),
+            ],
+        );
+        assert_eq!(merged.name, "Syntax Critic 1");
+        assert!(!merged.lgtm);
+        assert_eq!(merged.corrections, ["In lines 2-3 (fn b): `b` is empty."]);
+    }
+
+    #[tokio::test]
+    async fn test_sampled_reviews() {
+        let client = crate::scripted::ScriptedClient::new(|_, _| {
+            r#"{"lgtm": false, "corrections": ["`add` subtracts."]}"#.to_string()
+        });
+        let provider = Provider::default()
+            .with_client(std::sync::Arc::new(client))
+            .with_samples(3);
+        let critic =
+            CriticAgent::new(CriticType::Syntax, 1, &provider, &Prompts::default()).unwrap();
+        let reviews = critic
+            .reviews(&AgentProgress::detached("test"), "Add.")
+            .await
+            .unwrap();
+        let names: Vec<&str> = reviews.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Syntax Critic 1",
+                "Syntax Critic 1 (sample 2)",
+                "Syntax Critic 1 (sample 3)"
+            ]
+        );
+        assert!(reviews.iter().all(|c| !c.lgtm));
+        assert_eq!(critic.members().len(), 3);
+        assert_eq!(critic.members()[2].0, "Syntax Critic 1 (sample 3)");
+    }
+
+    #[tokio::test]
+    async fn test_idiomatic_lints() {
+        let client = crate::scripted::ScriptedClient::new(|system, user| {
+            let idiomatic = system.contains("Rust API guidelines");
+            assert_eq!(user.contains("clippy::pedantic:\nwarning: "), idiomatic);
+            r#"{"lgtm": true, "corrections": null}"#.to_string()
+        });
+        let provider = Provider::default().with_client(std::sync::Arc::new(client));
+        let prompts = Prompts::default();
+        let lints = "warning: writing `&Vec` instead of `&[_]`";
+        for critic_type in [CriticType::Idiomatic, CriticType::Syntax] {
+            let critic = CriticAgent::new(critic_type, 1, &provider, &prompts)
+                .unwrap()
+                .with_lints(lints);
+            assert_eq!(critic.lints.is_some(), critic_type == CriticType::Idiomatic);
+            let reviews = critic
+                .reviews(&AgentProgress::detached("test"), "Add.")
+                .await
+                .unwrap();
+            assert!(reviews[0].lgtm);
+        }
+        assert_eq!(critic_name(CriticType::Idiomatic, 2), "Idiomatic Critic 2");
+    }
+
+    #[tokio::test]
+    async fn test_batched_reviews() {
+        let client = crate::scripted::ScriptedClient::new(|system, _| {
+            assert!(system.contains("Perspective `correctness`:"));
+            serde_json::json!({
+                "design": {"lgtm": true, "corrections": []},
+                "correctness": {"lgtm": false, "corrections": ["`add` subtracts."]},
+                "syntax": {"lgtm": true, "corrections": null},
+            })
+            .to_string()
+        });
+        let provider = Provider::default().with_client(std::sync::Arc::new(client));
+        let types = [
+            CriticType::Design,
+            CriticType::Correctness,
+            CriticType::Syntax,
+        ];
+        let critic = CriticAgent::batched(&types, 2, &provider, &Prompts::default()).unwrap();
+        let window_reviews = vec![
+            critic
+                .reviews(&AgentProgress::detached("test"), "Add.")
+                .await
+                .unwrap(),
+            critic
+                .reviews(&AgentProgress::detached("test"), "Add.")
+                .await
+                .unwrap(),
+        ];
+        let names: Vec<&str> = window_reviews[0].iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["Design Critic 2", "Correctness Critic 2", "Syntax Critic 2"]
+        );
+        assert_eq!(
+            critic.members()[1],
+            ("Correctness Critic 2".to_string(), CriticType::Correctness)
+        );
+
+        let merged = merge_window_reviews(
+            vec!["lines 1-2".to_string(), "lines 3-4".to_string()],
+            window_reviews,
+        );
+        assert_eq!(merged.len(), 3);
+        assert!(merged[0].lgtm && merged[2].lgtm);
+        assert_eq!(merged[1].name, "Correctness Critic 2");
+        assert_eq!(
+            merged[1].corrections,
+            [
+                "In lines 1-2: `add` subtracts.",
+                "In lines 3-4: `add` subtracts."
+            ]
+        );
+    }
 }
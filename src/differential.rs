@@ -0,0 +1,673 @@
+use crate::build_service::{Build, BuildService};
+use crate::processes;
+use color_eyre::eyre::Result;
+use regex::Regex;
+use std::path::Path;
+use std::process::Output;
+use std::sync::OnceLock;
+use std::time::Duration;
+use syn::visit::{self, Visit};
+use syn::{FnArg, GenericArgument, Item, ItemFn, PathArguments, ReturnType, Type};
+
+// A benchmark problem may come with a reference solution. Passing the hidden tests shows that a
+// solution agrees with the reference on the tests' inputs, which can be few, so the solution is also
+// compared with the reference on generated inputs. The reference and the solution are compiled as
+// modules of one test harness, which calls the entry point of each with the same pseudo-random
+// arguments and prints what they return, by its Debug text, or that they panicked. The results are
+// compared here, with floats that differ only by rounding taken as the same. Any difference is a
+// divergence in behavior, a correctness finding even if every test passed.
+//
+// The harness prints each case's arguments before it calls the reference, and the reference's
+// result before it calls the solution, so that when the harness doesn't finish, because a call
+// aborted it, exited it, overflowed its stack, or flooded its output, the call that stopped it is
+// known. A solution that stops the harness where the reference returns has diverged. A reference
+// that stops it leaves nothing to compare.
+//
+// Only entry points whose arguments can be generated are compared: the integer, float, bool, char,
+// and string types, and vectors, slices, and tuples of them, taken by value or by shared reference.
+// The return type must implement Debug, and mustn't hold a HashMap or HashSet, whose Debug text is
+// in no particular order.
+
+// The number of argument lists that each comparison generates.
+const CASES: usize = 200;
+// Only this many divergences are reported.
+const MAX_DIVERGENCES: usize = 5;
+// Generated collections and strings are shorter than this.
+const MAX_LEN: usize = 8;
+// The harness is killed if it runs longer than this, as when the solution loops on an input.
+const COMPARE_TIMEOUT: Duration = Duration::from_secs(30);
+// The harness prints every case, so it may print more than the tests are allowed to.
+const MAX_OUTPUT: usize = 4 * 1024 * 1024;
+// Floats that differ by less than this, relative to the larger of them or 1, are the same.
+const FLOAT_TOLERANCE: f64 = 1e-6;
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+// The prefixes of the lines that the harness prints.
+const INPUT: &str = "ai_critics_input ";
+const EXPECTED: &str = "ai_critics_expected ";
+const ACTUAL: &str = "ai_critics_actual ";
+const DONE: &str = "ai_critics_done";
+
+// The outcome of comparing the solution with the reference on generated inputs.
+#[derive(Debug, PartialEq)]
+pub enum Comparison {
+    Agreed { cases: usize },
+    // What each divergence's arguments made each of them do.
+    Diverged { divergences: Vec<String> },
+    // The comparison couldn't be made, and why.
+    Skipped { reason: String },
+}
+
+// A value that the harness generates, by its type.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Int(String),
+    Float(String),
+    Bool,
+    Char,
+    String,
+    Vec(Box<Value>),
+    Tuple(Vec<Value>),
+}
+
+// An argument of the entry point, which is generated as its owned value and then passed by value
+// or borrowed.
+#[derive(Debug, PartialEq)]
+struct Param {
+    value: Value,
+    borrowed: bool,
+}
+
+const INTS: [&str; 12] = [
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+];
+
+impl Value {
+    fn of(ty: &Type) -> Option<Value> {
+        match ty {
+            Type::Path(path) => {
+                let segment = path.path.segments.last()?;
+                let name = segment.ident.to_string();
+                match name.as_str() {
+                    _ if INTS.contains(&name.as_str()) => Some(Value::Int(name)),
+                    "f32" | "f64" => Some(Value::Float(name)),
+                    "bool" => Some(Value::Bool),
+                    "char" => Some(Value::Char),
+                    "String" => Some(Value::String),
+                    "Vec" => match &segment.arguments {
+                        PathArguments::AngleBracketed(args) => match args.args.first()? {
+                            GenericArgument::Type(ty) => Some(Value::Vec(Box::new(Value::of(ty)?))),
+                            _ => None,
+                        },
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            Type::Tuple(tuple) if !tuple.elems.is_empty() => Some(Value::Tuple(
+                tuple.elems.iter().map(Value::of).collect::<Option<_>>()?,
+            )),
+            Type::Paren(paren) => Value::of(&paren.elem),
+            _ => None,
+        }
+    }
+
+    fn rust_type(&self) -> String {
+        match self {
+            Value::Int(name) | Value::Float(name) => name.clone(),
+            Value::Bool => "bool".to_string(),
+            Value::Char => "char".to_string(),
+            Value::String => "String".to_string(),
+            Value::Vec(value) => format!("Vec<{}>", value.rust_type()),
+            Value::Tuple(values) => format!("({},)", types(values)),
+        }
+    }
+
+    // An expression that generates a value from the harness's `rng`.
+    fn generate(&self) -> String {
+        let letter = "(b'a' + (next(&mut rng) % 26) as u8) as char";
+        match self {
+            Value::Int(name) if name.starts_with('u') => {
+                format!("(next(&mut rng) % 101) as {}", name)
+            }
+            Value::Int(name) => format!("((next(&mut rng) % 201) as i64 - 100) as {}", name),
+            Value::Float(name) => format!(
+                "(((next(&mut rng) % 2001) as f64 - 1000.0) / 100.0) as {}",
+                name
+            ),
+            Value::Bool => "next(&mut rng) % 2 == 0".to_string(),
+            Value::Char => letter.to_string(),
+            Value::String => format!(
+                "(0..next(&mut rng) % {}).map(|_| {}).collect::<String>()",
+                MAX_LEN, letter
+            ),
+            Value::Vec(value) => format!(
+                "(0..next(&mut rng) % {}).map(|_| {}).collect::<Vec<{}>>()",
+                MAX_LEN,
+                value.generate(),
+                value.rust_type()
+            ),
+            Value::Tuple(values) => format!(
+                "({},)",
+                values
+                    .iter()
+                    .map(Value::generate)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+fn types(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(Value::rust_type)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Param {
+    fn of(ty: &Type) -> Option<Param> {
+        let Type::Reference(reference) = ty else {
+            return Some(Param {
+                value: Value::of(ty)?,
+                borrowed: false,
+            });
+        };
+        if reference.mutability.is_some() {
+            return None;
+        }
+        let value = match reference.elem.as_ref() {
+            Type::Path(path) if path.path.is_ident("str") => Value::String,
+            Type::Slice(slice) => Value::Vec(Box::new(Value::of(&slice.elem)?)),
+            ty => Value::of(ty)?,
+        };
+        Some(Param {
+            value,
+            borrowed: true,
+        })
+    }
+}
+
+// The reference's entry point: the function of the given name, or else the first of its top-level
+// functions that the hidden tests call.
+fn entry_point<'a>(
+    reference: &'a syn::File,
+    name: Option<&str>,
+    tests: &str,
+) -> Option<&'a ItemFn> {
+    let mut functions = reference.items.iter().filter_map(|item| match item {
+        Item::Fn(f) if f.sig.ident != "main" => Some(f),
+        _ => None,
+    });
+    let called = |f: &&ItemFn| match name {
+        Some(name) => f.sig.ident == name,
+        None => tests.contains(&format!("{}(", f.sig.ident)),
+    };
+    functions.find(called)
+}
+
+// Whether a type holds a HashMap or HashSet.
+struct Unordered(bool);
+
+impl<'ast> Visit<'ast> for Unordered {
+    fn visit_path_segment(&mut self, segment: &'ast syn::PathSegment) {
+        self.0 |= segment.ident == "HashMap" || segment.ident == "HashSet";
+        visit::visit_path_segment(self, segment);
+    }
+}
+
+// The entry point's name and parameters, or why it can't be compared.
+fn signature(
+    reference: &str,
+    name: Option<&str>,
+    tests: &str,
+) -> std::result::Result<(String, Vec<Param>), String> {
+    let file =
+        syn::parse_file(reference).map_err(|e| format!("the reference doesn't parse: {}", e))?;
+    let f = entry_point(&file, name, tests)
+        .ok_or_else(|| "the reference has no entry point that the tests call".to_string())?;
+    let name = f.sig.ident.to_string();
+    let output = match &f.sig.output {
+        ReturnType::Type(_, output) if f.sig.generics.params.is_empty() => output,
+        _ => return Err(format!("`{}` is generic or returns nothing", name)),
+    };
+    let mut unordered = Unordered(false);
+    unordered.visit_type(output);
+    if unordered.0 {
+        return Err(format!(
+            "`{}` returns a collection in no particular order",
+            name
+        ));
+    }
+    let params = f
+        .sig
+        .inputs
+        .iter()
+        .map(|arg| match arg {
+            FnArg::Typed(typed) => Param::of(&typed.ty),
+            FnArg::Receiver(_) => None,
+        })
+        .collect::<Option<Vec<Param>>>()
+        .ok_or_else(|| format!("`{}` takes arguments that can't be generated", name))?;
+    Ok((name, params))
+}
+
+// The harness that compares the solution with the reference, each in its own module. The entry
+// point may be private to its module, so each module gets a public function that calls it and
+// formats what it returns.
+fn harness(reference: &str, code: &str, name: &str, params: &[Param]) -> String {
+    let mut generate = String::new();
+    let mut inputs = vec![];
+    let mut args = vec![];
+    for (i, param) in params.iter().enumerate() {
+        let ty = param.value.rust_type();
+        generate.push_str(&format!(
+            "            let arg{}: {} = {};\n",
+            i,
+            ty,
+            param.value.generate()
+        ));
+        match param.borrowed {
+            true => {
+                inputs.push(format!("arg{}: &{}", i, ty));
+                args.push(format!("&arg{}", i));
+            }
+            false => {
+                inputs.push(format!("arg{}: {}", i, ty));
+                args.push(format!("arg{}.clone()", i));
+            }
+        }
+    }
+    let inputs = inputs.join(", ");
+    let forwarded = (0..params.len())
+        .map(|i| format!("arg{}", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let call = format!(
+        "\npub(crate) fn ai_critics_call({inputs}) -> String {{\n    format!(\"{{:?}}\", {name}({forwarded}))\n}}\n"
+    );
+    let args = args.join(", ");
+    let shown = (0..params.len())
+        .map(|i| format!("format!(\"{{:?}}\", arg{})", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        r#"
+mod ai_critics_reference {{
+{reference}
+{call}}}
+
+mod ai_critics_solution {{
+{code}
+{call}}}
+
+#[cfg(test)]
+mod ai_critics_differential {{
+    fn next(rng: &mut u64) -> u64 {{
+        *rng ^= *rng << 13;
+        *rng ^= *rng >> 7;
+        *rng ^= *rng << 17;
+        *rng
+    }}
+
+    // The result as one line, or that it panicked.
+    fn outcome(result: std::thread::Result<String>) -> String {{
+        result
+            .unwrap_or_else(|_| "a panic".to_string())
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    }}
+
+    #[test]
+    fn compare() {{
+        std::panic::set_hook(Box::new(|_| {{}}));
+        let mut rng: u64 = {SEED};
+        for _ in 0..{CASES} {{
+{generate}            let shown: Vec<String> = vec![{shown}];
+            println!("{INPUT}{{}}", shown.join(", ").replace('\n', "\\n"));
+            let expected = outcome(std::panic::catch_unwind(|| {{
+                super::ai_critics_reference::ai_critics_call({args})
+            }}));
+            println!("{EXPECTED}{{}}", expected);
+            let actual = outcome(std::panic::catch_unwind(|| {{
+                super::ai_critics_solution::ai_critics_call({args})
+            }}));
+            println!("{ACTUAL}{{}}", actual);
+        }}
+        println!("{DONE}");
+    }}
+}}
+"#
+    )
+}
+
+// Compare the code with the reference on generated arguments to the entry point, which is named, or
+// else found by the hidden tests' calls. The harness is built in a subdirectory of the directory.
+pub async fn compare(
+    build: &BuildService,
+    dir: &Path,
+    reference: &str,
+    code: &str,
+    name: Option<&str>,
+    tests: &str,
+) -> Result<Comparison> {
+    let (name, params) = match signature(reference, name, tests) {
+        Ok(signature) => signature,
+        Err(reason) => return Ok(Comparison::Skipped { reason }),
+    };
+    let dir = dir.join("differential");
+    std::fs::create_dir_all(&dir)?;
+    let harness = harness(reference, code, &name, &params);
+    let exec_path = match build
+        .build(&dir, &harness, "differential", &["--test"])
+        .await?
+    {
+        Build::Built { exec_path, .. } => exec_path,
+        Build::Failed { .. } => {
+            return Ok(Comparison::Skipped {
+                reason: format!("the harness comparing `{}` didn't compile", name),
+            })
+        }
+    };
+    let mut command = build.command(&exec_path);
+    command.args(["--exact", "ai_critics_differential::compare", "--nocapture"]);
+    let limit = build.max_output().max(MAX_OUTPUT);
+    let run = processes::output_limited(&mut command, None, limit);
+    let (output, exceeded) = match tokio::time::timeout(COMPARE_TIMEOUT, run).await {
+        Ok(limited) => {
+            let limited = limited?;
+            (limited.output, limited.exceeded)
+        }
+        Err(_) => {
+            return Ok(Comparison::Skipped {
+                reason: format!("the comparison took longer than {:?}", COMPARE_TIMEOUT),
+            })
+        }
+    };
+    let stopped = stopped(&output, exceeded, limit);
+    Ok(outcome(
+        &name,
+        &String::from_utf8_lossy(&output.stdout),
+        stopped.as_deref(),
+    ))
+}
+
+// How the harness was stopped, if it didn't finish by exiting successfully.
+fn stopped(output: &Output, exceeded: bool, limit: usize) -> Option<String> {
+    if exceeded {
+        return Some(format!("by printing more than {} bytes", limit));
+    }
+    match output.status.code() {
+        Some(0) => None,
+        Some(code) => Some(format!("with exit code {}", code)),
+        None => Some("by a signal".to_string()),
+    }
+}
+
+// A case that the harness printed, as far as it got.
+#[derive(Debug, Default)]
+struct Case {
+    input: String,
+    expected: Option<String>,
+    actual: Option<String>,
+}
+
+// The cases that the harness printed, and whether it printed them all. libtest starts the first
+// line of a test's output with the test's name, so the prefixes are found anywhere in a line.
+fn cases(stdout: &str) -> (Vec<Case>, bool) {
+    let mut cases: Vec<Case> = vec![];
+    let mut done = false;
+    for line in stdout.lines() {
+        if let Some((_, input)) = line.split_once(INPUT) {
+            cases.push(Case {
+                input: input.to_string(),
+                ..Case::default()
+            });
+        } else if let Some((_, expected)) = line.split_once(EXPECTED) {
+            if let Some(case) = cases.last_mut() {
+                case.expected = Some(expected.to_string());
+            }
+        } else if let Some((_, actual)) = line.split_once(ACTUAL) {
+            if let Some(case) = cases.last_mut() {
+                case.actual = Some(actual.to_string());
+            }
+        } else if line.ends_with(DONE) {
+            done = true;
+        }
+    }
+    (cases, done)
+}
+
+// The comparison of the cases that the harness printed, given how it was stopped, if it was. A
+// divergence that recurs, as for an entry point without arguments, is reported once.
+fn outcome(name: &str, stdout: &str, stopped: Option<&str>) -> Comparison {
+    let (cases, done) = cases(stdout);
+    let stopped = match (done, stopped) {
+        (_, Some(stopped)) => Some(stopped),
+        (false, None) => Some("by exiting early"),
+        (true, None) => None,
+    };
+    let mut divergences: Vec<String> = vec![];
+    let mut add = |divergence: String| {
+        if divergences.len() < MAX_DIVERGENCES && !divergences.contains(&divergence) {
+            divergences.push(divergence);
+        }
+    };
+    let mut compared = 0;
+    for case in &cases {
+        if let (Some(expected), Some(actual)) = (&case.expected, &case.actual) {
+            compared += 1;
+            if !agree(expected, actual) {
+                add(format!(
+                    "{}({}) returns {}, but the reference returns {}.",
+                    name, case.input, actual, expected
+                ));
+            }
+        }
+    }
+    if let Some(stopped) = stopped {
+        match cases.last() {
+            Some(Case {
+                input,
+                expected: Some(expected),
+                actual: None,
+            }) => add(format!(
+                "{}({}) stops the harness {}, but the reference returns {}.",
+                name, input, stopped, expected
+            )),
+            Some(Case {
+                input,
+                expected: None,
+                ..
+            }) if divergences.is_empty() => {
+                return Comparison::Skipped {
+                    reason: format!(
+                        "the reference stops the harness {} on {}({})",
+                        stopped, name, input
+                    ),
+                }
+            }
+            _ if divergences.is_empty() => {
+                return Comparison::Skipped {
+                    reason: format!(
+                        "the harness was stopped {} after {} cases",
+                        stopped, compared
+                    ),
+                }
+            }
+            _ => {}
+        }
+    }
+    match divergences.is_empty() {
+        true => Comparison::Agreed { cases: compared },
+        false => Comparison::Diverged { divergences },
+    }
+}
+
+fn float() -> &'static Regex {
+    static FLOAT: OnceLock<Regex> = OnceLock::new();
+    FLOAT.get_or_init(|| Regex::new(r"-?(?:\d+(?:\.\d+)?e-?\d+|\d+\.\d+|NaN|inf)").unwrap())
+}
+
+// Whether the results are the same, apart from floats that differ only by rounding.
+fn agree(expected: &str, actual: &str) -> bool {
+    if expected == actual {
+        return true;
+    }
+    let (expected_floats, actual_floats): (Vec<_>, Vec<_>) = (
+        float().find_iter(expected).collect(),
+        float().find_iter(actual).collect(),
+    );
+    if expected_floats.len() != actual_floats.len()
+        || float().split(expected).ne(float().split(actual))
+    {
+        return false;
+    }
+    expected_floats.iter().zip(&actual_floats).all(|(e, a)| {
+        match (e.as_str().parse::<f64>(), a.as_str().parse::<f64>()) {
+            (Ok(e), Ok(a)) if e.is_nan() && a.is_nan() => true,
+            (Ok(e), Ok(a)) if e == a => true,
+            (Ok(e), Ok(a)) => (e - a).abs() <= FLOAT_TOLERANCE * e.abs().max(a.abs()).max(1.0),
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_signature() {
+        let reference = "fn helper() {}\nfn add(xs: &[i64], s: &str, t: (u8, bool)) -> i64 { 0 }";
+        let (name, params) = signature(
+            reference,
+            None,
+            "assert_eq!(add(&[1], \"a\", (1, true)), 0);",
+        )
+        .unwrap();
+        assert_eq!(name, "add");
+        assert_eq!(
+            params[0].value,
+            Value::Vec(Box::new(Value::Int("i64".to_string())))
+        );
+        assert!(params[0].borrowed);
+        assert_eq!(params[1].value, Value::String);
+        assert_eq!(params[2].value.rust_type(), "(u8, bool,)");
+        assert!(!params[2].borrowed);
+
+        assert!(signature("fn f(m: &mut Vec<i32>) -> i32 { 0 }", Some("f"), "").is_err());
+        assert!(signature("fn f<T>(t: T) -> T { t }", Some("f"), "").is_err());
+        assert!(signature("fn f(x: i32) -> i32 { x }", Some("g"), "").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_compare() {
+        let dir = TempDir::new().unwrap();
+        let build = BuildService::default();
+        let reference = "fn largest(xs: Vec<i32>) -> Option<i32> {\n    xs.into_iter().max()\n}\n";
+        let agrees = "fn largest(xs: Vec<i32>) -> Option<i32> {\n    let mut best = None;\n    \
+                      for x in xs {\n        if best.map_or(true, |b| x > b) {\n            \
+                      best = Some(x);\n        }\n    }\n    best\n}\n";
+        let comparison = compare(&build, dir.path(), reference, agrees, Some("largest"), "")
+            .await
+            .unwrap();
+        assert_eq!(comparison, Comparison::Agreed { cases: CASES });
+
+        // Wrong for lists of negative numbers, and panics on empty ones.
+        let diverges = "fn largest(xs: Vec<i32>) -> Option<i32> {\n    let mut best = 0;\n    \
+                        for x in &xs {\n        best = best.max(*x);\n    }\n    \
+                        Some(best + xs[0] - xs[0])\n}\n";
+        let Comparison::Diverged { divergences } =
+            compare(&build, dir.path(), reference, diverges, Some("largest"), "")
+                .await
+                .unwrap()
+        else {
+            panic!("expected a divergence");
+        };
+        assert!(divergences.len() <= MAX_DIVERGENCES);
+        assert!(divergences[0].starts_with("largest(["));
+        assert!(divergences[0].contains("but the reference returns"));
+
+        let stdout = format!(
+            "running 1 test\ntest ai_critics_differential::compare ... {INPUT}\n{EXPECTED}2\n\
+             {ACTUAL}1\n{INPUT}\n{EXPECTED}2\n{ACTUAL}1\n{DONE}\n"
+        );
+        assert_eq!(
+            outcome("answer", &stdout, None),
+            Comparison::Diverged {
+                divergences: vec!["answer() returns 1, but the reference returns 2.".to_string()]
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compare_stopped() {
+        let dir = TempDir::new().unwrap();
+        let build = BuildService::default();
+        let reference = "fn half(x: i32) -> i32 {\n    x / 2\n}\n";
+        let exits = "fn half(x: i32) -> i32 {\n    std::process::exit(x / 2)\n}\n";
+        let Comparison::Diverged { divergences } =
+            compare(&build, dir.path(), reference, exits, Some("half"), "")
+                .await
+                .unwrap()
+        else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(divergences.len(), 1);
+        assert!(divergences[0].contains(") stops the harness with exit code"));
+
+        // A reference that stops the harness leaves nothing to compare.
+        let Comparison::Skipped { reason } =
+            compare(&build, dir.path(), exits, reference, Some("half"), "")
+                .await
+                .unwrap()
+        else {
+            panic!("expected the comparison to be skipped");
+        };
+        assert!(reason.starts_with("the reference stops the harness"));
+
+        let stdout = format!("{INPUT}1\n{EXPECTED}0\n{ACTUAL}0\n{INPUT}2\n{EXPECTED}1\n");
+        assert_eq!(
+            outcome("half", &stdout, Some("by a signal")),
+            Comparison::Diverged {
+                divergences: vec![format!(
+                    "half(2) stops the harness {}, but the reference returns 1.",
+                    "by a signal"
+                )]
+            }
+        );
+        let stdout = format!("{INPUT}1\n{EXPECTED}0\n{ACTUAL}0\n");
+        assert!(matches!(
+            outcome("half", &stdout, None),
+            Comparison::Skipped { .. }
+        ));
+    }
+
+    #[test]
+    fn test_agree() {
+        assert!(agree("Some(0.30000000000000004)", "Some(0.3)"));
+        assert!(agree(
+            "[1.0, NaN, -2.5e-7]",
+            "[1.0000000001, NaN, -2.5000000001e-7]"
+        ));
+        assert!(agree("(1, \"a\")", "(1, \"a\")"));
+        assert!(!agree("Some(0.3)", "Some(0.31)"));
+        assert!(!agree("Some(0.3)", "Ok(0.3)"));
+        assert!(!agree("[1.0]", "[1.0, 2.0]"));
+        assert!(!agree("10", "11"));
+    }
+
+    #[test]
+    fn test_signature_unordered() {
+        let reference = "use std::collections::HashMap;\n\
+                         fn count(s: &str) -> Vec<HashMap<char, usize>> { vec![] }";
+        assert_eq!(
+            signature(reference, Some("count"), "").unwrap_err(),
+            "`count` returns a collection in no particular order"
+        );
+        assert!(signature("fn f(s: &str) -> BTreeMap<char, u8> { 0 }", Some("f"), "").is_ok());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::agent::{user_message, Agent};
+use crate::critic::{self, CriticAgent};
+use crate::pricing::{completion_cost, prompt_cost};
+use crate::prompts::{Prompt, Prompts};
+use crate::tokenizer::count_prompt_tokens;
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs};
+use color_eyre::eyre::Result;
+use std::fmt;
+
+// `--dry-run` estimates what a run would cost without sending a request. It builds the agents'
+// prompts for the problem and counts their tokens, then counts the calls of the shortest run, in
+// which the critics approve the first proposal and it passes its tests, and of the longest, which
+// makes every proposal that --max-proposals allows and fixes each one twice, after the critics'
+// review and after the tests. The code isn't known until the run writes it, so the code in the
+// prompts and responses is assumed to be ASSUMED_CODE_TOKENS long. Retries aren't counted.
+
+const ASSUMED_CODE_TOKENS: usize = 1_000;
+// The length of a critic's corrections, which the fixer is given too.
+const ASSUMED_REVIEW_TOKENS: usize = 200;
+// The length of the tester's report of the previous proposal, which the critics are given.
+const ASSUMED_TESTER_OUTPUT_TOKENS: usize = 300;
+
+// One kind of call that a run makes.
+struct Call {
+    agent: String,
+    model: String,
+    prompt_tokens: usize,
+    completion_tokens: usize,
+    // The number of these calls in the shortest and the longest runs.
+    fewest: usize,
+    most: usize,
+}
+
+impl Call {
+    fn cost(&self, calls: usize) -> f64 {
+        calls as f64
+            * (prompt_cost(&self.model, self.prompt_tokens as u64)
+                + completion_cost(&self.model, self.completion_tokens as u64))
+    }
+}
+
+pub struct Plan {
+    calls: Vec<Call>,
+}
+
+impl Plan {
+    // Plan the calls of a run of the problem by the coder and fixer with the given model and by the
+    // critics. The self-review is included if `self_review` is set.
+    pub fn new(
+        prompts: &Prompts,
+        problem: &str,
+        model: &str,
+        critics: &[CriticAgent],
+        self_review: bool,
+        max_proposals: usize,
+    ) -> Result<Self> {
+        let call =
+            |agent: &str, model: &str, prompt_tokens, completion_tokens, fewest, most| Call {
+                agent: agent.to_string(),
+                model: model.to_string(),
+                prompt_tokens,
+                completion_tokens,
+                fewest,
+                most,
+            };
+        let coder_prompt = prompt_tokens(prompts.get(Prompt::Coder), &[problem])?;
+        let mut calls = vec![call(
+            "Coder",
+            model,
+            coder_prompt,
+            ASSUMED_CODE_TOKENS,
+            1,
+            1,
+        )];
+        if self_review {
+            let prompt = prompt_tokens(
+                prompts.get(Prompt::Coder),
+                &[problem, prompts.get(Prompt::CoderSelfReview)],
+            )? + ASSUMED_CODE_TOKENS;
+            calls.push(call(
+                "Coder self-review",
+                model,
+                prompt,
+                ASSUMED_CODE_TOKENS,
+                1,
+                1,
+            ));
+        }
+        let review = critic::review_message(problem, "", None);
+        for critic in critics {
+            let prompt = prompt_tokens(critic.system_prompt(), &[&review])?
+                + ASSUMED_CODE_TOKENS
+                + ASSUMED_TESTER_OUTPUT_TOKENS;
+            calls.push(call(
+                critic.name(),
+                critic.chatter().model(),
+                prompt,
+                ASSUMED_REVIEW_TOKENS,
+                1,
+                max_proposals,
+            ));
+        }
+        for (agent, review_prompt, comments) in [
+            (
+                "Fixer (review)",
+                Prompt::FixerCodeReview,
+                ASSUMED_REVIEW_TOKENS * critics.len(),
+            ),
+            (
+                "Fixer (tests)",
+                Prompt::FixerTestFix,
+                ASSUMED_TESTER_OUTPUT_TOKENS,
+            ),
+        ] {
+            let prompt = prompt_tokens(prompts.get(Prompt::Fixer), &[prompts.get(review_prompt)])?
+                + ASSUMED_CODE_TOKENS
+                + comments;
+            calls.push(call(
+                agent,
+                model,
+                prompt,
+                ASSUMED_CODE_TOKENS,
+                0,
+                max_proposals,
+            ));
+        }
+        Ok(Plan { calls })
+    }
+
+    fn total(&self, calls: impl Fn(&Call) -> usize) -> (usize, f64) {
+        self.calls.iter().fold((0, 0.0), |(count, cost), call| {
+            (count + calls(call), cost + call.cost(calls(call)))
+        })
+    }
+}
+
+// The tokens of a request with the system prompt and user messages.
+fn prompt_tokens(system: &str, user: &[&str]) -> Result<usize> {
+    let mut msgs: Vec<ChatCompletionRequestMessage> =
+        vec![ChatCompletionRequestSystemMessageArgs::default()
+            .content(system)
+            .build()?
+            .into()];
+    for text in user {
+        msgs.push(user_message(text)?);
+    }
+    Ok(count_prompt_tokens(&msgs))
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Dry run: no requests were sent. Code is assumed to be {} tokens.\n",
+            ASSUMED_CODE_TOKENS
+        )?;
+        writeln!(
+            f,
+            "{:<28} {:<20} {:>7} {:>11} {:>7} {:>7}",
+            "Call", "Model", "Prompt", "Completion", "Fewest", "Most"
+        )?;
+        for call in self.calls.iter() {
+            writeln!(
+                f,
+                "{:<28} {:<20} {:>7} {:>11} {:>7} {:>7}",
+                call.agent,
+                call.model,
+                call.prompt_tokens,
+                call.completion_tokens,
+                call.fewest,
+                call.most
+            )?;
+        }
+        let (fewest, low) = self.total(|call| call.fewest);
+        let (most, high) = self.total(|call| call.most);
+        write!(
+            f,
+            "\nA run would make {} to {} calls, costing an estimated ${:.2} to ${:.2}, not \
+             counting retries.",
+            fewest, most, low, high
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::critic::CriticType;
+    use crate::provider::Provider;
+
+    #[test]
+    fn test_plan() {
+        let problem = "Add two numbers.";
+        let prompts = Prompts::load(None, problem).unwrap();
+        let provider = Provider::default();
+        let critics: Vec<CriticAgent> = [CriticType::Design, CriticType::Syntax]
+            .into_iter()
+            .map(|critic_type| CriticAgent::new(critic_type, 1, &provider, &prompts).unwrap())
+            .collect();
+        let plan = Plan::new(&prompts, problem, provider.model(), &critics, true, 5).unwrap();
+
+        // The coder and its self-review, then the critics, then the two fixes.
+        assert_eq!(plan.calls.len(), 6);
+        assert!(plan.calls[0].prompt_tokens > 0);
+        assert_eq!(plan.total(|call| call.fewest).0, 4);
+        assert_eq!(plan.total(|call| call.most).0, 2 + 5 * 4);
+        let (_, low) = plan.total(|call| call.fewest);
+        let (_, high) = plan.total(|call| call.most);
+        assert!(0.0 < low && low < high);
+        let report = plan.to_string();
+        assert!(report.contains("Design Critic 1"));
+        assert!(report.ends_with("not counting retries."));
+    }
+}
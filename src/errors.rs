@@ -1,6 +1,9 @@
+use async_openai::error::OpenAIError;
+use regex::Regex;
 use serde_json::Value;
 use std::error::Error;
 use std::fmt;
+use std::sync::OnceLock;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -20,9 +23,24 @@ pub enum AiCriticError {
     #[error("OpenAI error: {}", source)]
     OpenAI {
         #[from]
-        source: async_openai::error::OpenAIError,
+        source: OpenAIError,
     },
 
+    #[error("the API rejected the key: {}", details)]
+    AuthenticationFailed { details: String },
+
+    #[error("the API is rate limiting the requests: {}", details)]
+    RateLimited { details: String },
+
+    #[error("the account's quota is exhausted: {}", details)]
+    QuotaExhausted { details: String },
+
+    #[error("the model {} wasn't found: {}", model, details)]
+    ModelNotFound { model: String, details: String },
+
+    #[error("the request exceeded the model's context length: {}", details)]
+    ContextLengthExceeded { details: String },
+
     #[error("Regex error: {}", source)]
     Regex {
         #[from]
@@ -32,8 +50,14 @@ pub enum AiCriticError {
     #[error("the process was terminated by signal")]
     ProcessTerminated,
 
-    #[error("the test exited with exit code {}", exit_code)]
-    TestingFailed { exit_code: i32 },
+    #[error("coverage measurement failed: {}", reason)]
+    CoverageFailed { reason: String },
+
+    #[error("the sandbox is unavailable: {}", reason)]
+    SandboxUnavailable { reason: String },
+
+    #[error("the store couldn't save {} artifact(s): {}", failed.len(), failed.join(", "))]
+    StoreFailed { failed: Vec<String> },
 
     #[error("the returned JSON is not an object")]
     NotJsonObject,
@@ -55,6 +79,104 @@ pub enum AiCriticError {
 
     #[error("too many proposals: {}", proposals)]
     MaxProposalsExceeded { proposals: usize },
+
+    #[error("stopped after {} proposals: {}", proposals, reason)]
+    Stopped { proposals: usize, reason: String },
+
+    #[error("over budget after {} proposals: {}", proposals, reason)]
+    BudgetExceeded { proposals: usize, reason: String },
+
+    #[error("only {} critics succeeded, but {} are required", succeeded, required)]
+    TooFewCritics { succeeded: usize, required: usize },
+
+    #[error("invalid config file {}: {}", path, reason)]
+    InvalidConfig { path: String, reason: String },
+
+    #[error("invalid image {}: {}", path, reason)]
+    InvalidImage { path: String, reason: String },
+
+    #[error("missing {}", setting)]
+    MissingSetting { setting: String },
+
+    #[error("`git {}` failed: {}", command, stderr)]
+    GitFailed { command: String, stderr: String },
+
+    #[error("not a GitHub issue URL: {}", url)]
+    InvalidIssueUrl { url: String },
+
+    #[error("GitHub returned {}: {}", status, body)]
+    GitHubFailed { status: u16, body: String },
+
+    #[error("the run was cancelled")]
+    Cancelled,
+
+    #[error("there's no run {} in {}", id, dir)]
+    RunNotFound { id: String, dir: String },
+}
+
+impl AiCriticError {
+    // Whether the error stopped a run that was working but didn't converge, rather than one that
+    // failed.
+    pub fn is_divergence(&self) -> bool {
+        matches!(
+            self,
+            AiCriticError::MaxProposalsExceeded { .. }
+                | AiCriticError::Stopped { .. }
+                | AiCriticError::BudgetExceeded { .. }
+        )
+    }
+
+    // Classify a failed request to the given model by what the user can do about it. The API's error
+    // object says what went wrong by its code and type, but a streamed request reports only the HTTP
+    // status, plus the response body for Gemini. Only the stream's status is read as one, since an
+    // error object's message may hold other numbers, such as a count of tokens. Errors that can't be
+    // classified are kept as they are.
+    pub fn from_api(error: OpenAIError, model: &str) -> Self {
+        let (details, code, status) = match &error {
+            OpenAIError::ApiError(e) => (
+                e.message.clone(),
+                format!(
+                    "{} {}",
+                    e.code.as_ref().and_then(Value::as_str).unwrap_or_default(),
+                    e.r#type.as_deref().unwrap_or_default()
+                ),
+                None,
+            ),
+            OpenAIError::StreamError(message) => {
+                let status = stream_status()
+                    .captures(message)
+                    .and_then(|captures| captures.get(1))
+                    .map(|m| m.as_str());
+                (message.clone(), message.clone(), status)
+            }
+            _ => return error.into(),
+        };
+        if code.contains("context_length_exceeded") || details.contains("maximum context length") {
+            AiCriticError::ContextLengthExceeded { details }
+        } else if code.contains("insufficient_quota") {
+            AiCriticError::QuotaExhausted { details }
+        } else if code.contains("rate_limit") || status == Some("429") {
+            AiCriticError::RateLimited { details }
+        } else if code.contains("model_not_found") || status == Some("404") {
+            AiCriticError::ModelNotFound {
+                model: model.to_string(),
+                details,
+            }
+        } else if code.contains("invalid_api_key") || matches!(status, Some("401" | "403")) {
+            AiCriticError::AuthenticationFailed { details }
+        } else {
+            error.into()
+        }
+    }
+}
+
+// The HTTP status of a stream's error that the user can act on, as in "Invalid status code: 401
+// Unauthorized" from async-openai, or "Gemini returned 404 Not Found: ..." from the other clients.
+fn stream_status() -> &'static Regex {
+    static STREAM_STATUS: OnceLock<Regex> = OnceLock::new();
+    STREAM_STATUS.get_or_init(|| {
+        Regex::new(r"^(?:Invalid status code:|\S+ returned) (401|403|404|429)\b").unwrap()
+    })
 }
 
 // Here's how to define a Result<> type for AiCriticError:
@@ -68,6 +190,7 @@ pub enum AiCriticError {
 // we can provide custom behavior for how AiCriticError is converted and represented in the context
 // of `color_eyre::Report`.
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct AiCriticReport(AiCriticError);
 
 // Implement `fmt::Display` for AiCriticReport.
@@ -99,3 +222,70 @@ impl From<AiCriticError> for AiCriticReport {
         AiCriticReport(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::error::ApiError;
+    use serde_json::json;
+
+    fn api_error(code: &str, message: &str) -> OpenAIError {
+        OpenAIError::ApiError(ApiError {
+            message: message.to_string(),
+            r#type: Some("invalid_request_error".to_string()),
+            param: None,
+            code: Some(json!(code)),
+        })
+    }
+
+    #[test]
+    fn test_from_api() {
+        let from_api = |error| AiCriticError::from_api(error, "gpt-4");
+        assert!(matches!(
+            from_api(OpenAIError::StreamError(
+                "Invalid status code: 401 Unauthorized".to_string()
+            )),
+            AiCriticError::AuthenticationFailed { .. }
+        ));
+        assert!(matches!(
+            from_api(OpenAIError::StreamError(
+                "Invalid status code: 429 Too Many Requests".to_string()
+            )),
+            AiCriticError::RateLimited { .. }
+        ));
+        assert!(matches!(
+            from_api(OpenAIError::StreamError(
+                "Gemini returned 404 Not Found: models/gemini-0 is not found".to_string()
+            )),
+            AiCriticError::ModelNotFound { model, .. } if model == "gpt-4"
+        ));
+        assert!(matches!(
+            from_api(api_error(
+                "insufficient_quota",
+                "You exceeded your current quota."
+            )),
+            AiCriticError::QuotaExhausted { .. }
+        ));
+        assert!(matches!(
+            from_api(api_error(
+                "context_length_exceeded",
+                "This model's maximum context length is 8192 tokens."
+            )),
+            AiCriticError::ContextLengthExceeded { .. }
+        ));
+        assert!(matches!(
+            from_api(OpenAIError::StreamError(
+                "Invalid status code: 500 Internal Server Error".to_string()
+            )),
+            AiCriticError::OpenAI { .. }
+        ));
+        // A number in an error object's message isn't its status.
+        assert!(matches!(
+            from_api(api_error(
+                "invalid_value",
+                "The prompt is 429 tokens over the limit of 401."
+            )),
+            AiCriticError::OpenAI { .. }
+        ));
+    }
+}
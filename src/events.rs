@@ -0,0 +1,1059 @@
+use crate::code_view::CodeView;
+use crate::critic::CriticType;
+use crate::images;
+use crate::output::{self, Verbosity};
+use crate::preview;
+use crate::pricing::Spend;
+use crate::progress_bar::{DoublingProgressBar, ResponseEstimates};
+use crate::proposal_diff::ProposalDiff;
+use crate::run_state::RunState;
+use crate::steering::{Regenerate, Steering, Stream};
+use crate::tester::FailureKind;
+use crate::tokenizer;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessageContent, FinishReason,
+};
+use color_eyre::eyre::Result;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indoc::indoc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+// The run loop reports what it is doing by emitting RunEvents on an EventBus instead of printing.
+// A dispatcher task receives the events in order and hands each one to every registered Observer,
+// which decides how to present it: printing to the console, drawing progress bars, writing JSON
+// lines, updating the dashboard, or streaming to the web page. This keeps the orchestration in
+// lib.rs independent of how the run is displayed.
+
+// The number of lines of code shown in a live preview.
+const PREVIEW_LINES: usize = 8;
+// When stdout isn't a terminal, how often a line of each agent's progress is printed.
+const PROGRESS_LINE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Coding,
+    SelfReviewing,
+    Reviewing,
+    Fixing,
+    Ranking,
+    Testing,
+    MeasuringCoverage,
+    MutationTesting,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Phase::Coding => "Coder writing solution",
+            Phase::SelfReviewing => "Coder checking its solution",
+            Phase::Reviewing => "Critics reviewing",
+            Phase::Fixing => "Fixer correcting",
+            Phase::Ranking => "Ranker comparing the fixers' candidates",
+            Phase::Testing => "Tester compiling and testing",
+            Phase::MeasuringCoverage => "Measuring test coverage of the accepted solution",
+            Phase::MutationTesting => "Mutation testing the accepted solution",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+// An update on an agent's streamed response.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProgressUpdate {
+    Chunk { text: String },
+    BlankChunk { text: String },
+    // The response is being retried, so progress starts over.
+    Reset,
+    Note { message: String },
+}
+
+// A way that the API misbehaved in a response, counted per run to compare the models. Each one is
+// worked around, usually by retrying the request.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Anomaly {
+    // A stream of blank chunks instead of the response.
+    BlankFlood,
+    // The response was cut off at the token limit.
+    LengthStop,
+    // The response ended without a finish reason.
+    MissingFinish,
+    // The response ended with a finish reason other than Stop or Length, such as a content filter.
+    OtherFinish,
+    // The stream stopped for longer than the timeout on the next chunk.
+    Timeout,
+    // The stream slowed to under the minimum rate of chunks.
+    Stall,
+    // The whole response took longer than the request timeout.
+    RequestDeadline,
+    // A chunk had more than the one choice requested.
+    ExtraChoices,
+    // The code was returned as the key of an object instead of as a string, and was recovered.
+    CodeAsKey,
+    // The code was missing, or was neither a string nor an object holding it.
+    MalformedCode,
+    // The JSON was invalid, but was repaired.
+    RepairedJson,
+    // The response was in Markdown instead of JSON, and was read with --lenient-parsing.
+    Markdown,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RunEvent {
+    ProblemLoaded {
+        path: String,
+        problem: String,
+    },
+    PhaseStarted {
+        phase: Phase,
+    },
+    ProposalCreated {
+        number: usize,
+        code: String,
+    },
+    // What changed from the previous proposal to this one.
+    ProposalDiffed {
+        diff: ProposalDiff,
+    },
+    AgentStarted {
+        agent: String,
+    },
+    AgentProgress {
+        agent: String,
+        update: ProgressUpdate,
+    },
+    AgentFinished {
+        agent: String,
+    },
+    ChatRequested {
+        agent: String,
+        model: String,
+        messages: Vec<ChatCompletionRequestMessage>,
+        prompt_tokens: usize,
+    },
+    ChatResponded {
+        agent: String,
+        response: String,
+        finish_reason: Option<FinishReason>,
+        // The API streams about one token per chunk, so this approximates the completion tokens.
+        chunks: usize,
+    },
+    ChatRetried {
+        agent: String,
+        attempt: usize,
+    },
+    ApiAnomaly {
+        agent: String,
+        anomaly: Anomaly,
+    },
+    // The types of critic that review the run's proposals, without those of --disable-critic.
+    CriticsChosen {
+        critic_types: Vec<CriticType>,
+    },
+    CriticFinished {
+        critic: String,
+        lgtm: bool,
+        corrections: Vec<String>,
+        // The examples traced in chain-of-verification mode.
+        evidence: Vec<String>,
+    },
+    CriticFailed {
+        critic: String,
+        error: String,
+    },
+    CriticsAgreed,
+    // The ranker preferred the winner's candidate fix to the loser's.
+    CandidateRanked {
+        winner: String,
+        loser: String,
+        reason: String,
+    },
+    FixApplied {
+        fixer: String,
+        code: String,
+        // The similarity of the fixed code to the code before the fix, from 0 for a rewrite to 1
+        // for no change.
+        similarity: f64,
+    },
+    TestPassed {
+        proposal: usize,
+        code: String,
+        output: String,
+    },
+    TestFailed {
+        output: String,
+        compile_error: bool,
+        failure: FailureKind,
+    },
+    CoverageMeasured {
+        summary: String,
+        line_fraction: f64,
+    },
+    MutationTested {
+        summary: String,
+        score: f64,
+    },
+    Notice {
+        message: String,
+    },
+    // The best proposal of a run that was stopped before its tests passed.
+    BestCandidate {
+        proposal: usize,
+        code: String,
+        // Why it falls short.
+        summary: String,
+    },
+    // The run loop moved to this state.
+    StateEntered {
+        state: RunState,
+    },
+    RunFinished {
+        outcome: String,
+    },
+}
+
+// Describe an event as the console shows it, or None for events that aren't shown as text.
+pub fn describe(event: &RunEvent) -> Option<String> {
+    match event {
+        RunEvent::ProblemLoaded { path, problem } => Some(format!(
+            "Reading file '{}'\nThe coding problem is:\n\n{}\n",
+            path, problem
+        )),
+        RunEvent::PhaseStarted { phase } => Some(format!("\n==> {}...", phase)),
+        RunEvent::ProposalCreated { number, code } => Some(format!(
+            "Proposed code #{}: -----------\n{}\n------------------------------\n",
+            number, code
+        )),
+        RunEvent::CriticFinished {
+            critic,
+            lgtm,
+            corrections,
+            evidence,
+        } => {
+            let mut text = format!("  {}:\n    Correct? {}", critic, lgtm);
+            if !lgtm {
+                for c in corrections.iter() {
+                    text.push_str(&format!("\n    • {}", c));
+                }
+            }
+            for e in evidence.iter() {
+                text.push_str(&format!("\n    Traced: {}", e));
+            }
+            Some(text)
+        }
+        RunEvent::ChatRetried { attempt, .. } => Some(format!("Retry attempt: {}", attempt)),
+        RunEvent::CriticFailed { critic, error } => Some(format!("  {} failed: {}", critic, error)),
+        RunEvent::CriticsAgreed => Some("All of the critics agree that code is correct.".into()),
+        RunEvent::CandidateRanked {
+            winner,
+            loser,
+            reason,
+        } => Some(format!(
+            "  Ranker preferred {}'s fix to {}'s: {}",
+            winner, loser, reason
+        )),
+        RunEvent::FixApplied {
+            fixer, similarity, ..
+        } => Some(format!(
+            "{} kept {:.0}% of the code's lines.",
+            fixer,
+            similarity * 100.0
+        )),
+        RunEvent::TestPassed {
+            proposal,
+            code,
+            output,
+        } => Some(format!(
+            indoc! {"
+                Success after {} proposals.
+                Final code:
+                --------------------------------------------------------------------------------
+                {}
+                --------------------------------------------------------------------------------
+                Test output:
+                --------------------------------------------------------------------------------
+                {}
+                --------------------------------------------------------------------------------
+            "},
+            proposal, code, output
+        )),
+        RunEvent::TestFailed { output, .. } => Some(format!(
+            indoc! {"
+                Compiling/Testing failure:
+                --------------------------------------------------------------------------------
+                {}
+                --------------------------------------------------------------------------------
+            "},
+            output
+        )),
+        RunEvent::CoverageMeasured { summary, .. } => Some(summary.clone()),
+        RunEvent::MutationTested { summary, .. } => Some(summary.clone()),
+        RunEvent::Notice { message } => Some(message.clone()),
+        RunEvent::BestCandidate {
+            proposal, summary, ..
+        } => Some(format!(
+            "The best proposal was #{}, though {}.",
+            proposal, summary
+        )),
+        RunEvent::AgentProgress {
+            update: ProgressUpdate::Note { message },
+            ..
+        } => Some(message.clone()),
+        _ => None,
+    }
+}
+
+pub trait Observer: Send {
+    fn on_event(&mut self, event: &RunEvent);
+}
+
+enum Envelope {
+    Event(RunEvent),
+    Flush(oneshot::Sender<()>),
+}
+
+// The sending side of the event channel. It is cheap to clone, so each agent task gets its own. It
+// also carries the user's requests to regenerate a response back to the agents, and tallies the
+// spend of the requests that the agents report.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: mpsc::UnboundedSender<Envelope>,
+    steering: Steering,
+    spend: Arc<Mutex<Spend>>,
+}
+
+// Owns the dispatcher task. Closing it waits until every event sent so far has been observed and
+// then drops the observers, which lets them restore the terminal or flush their files.
+pub struct EventDispatcher {
+    bus: EventBus,
+    task: JoinHandle<()>,
+}
+
+impl EventBus {
+    pub fn start(mut observers: Vec<Box<dyn Observer>>, steering: Steering) -> EventDispatcher {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(async move {
+            while let Some(envelope) = receiver.recv().await {
+                match envelope {
+                    Envelope::Event(event) => {
+                        for o in observers.iter_mut() {
+                            o.on_event(&event);
+                        }
+                    }
+                    Envelope::Flush(ack) => {
+                        let _ = ack.send(());
+                    }
+                }
+            }
+        });
+        EventDispatcher {
+            bus: EventBus {
+                sender,
+                steering,
+                spend: Arc::default(),
+            },
+            task,
+        }
+    }
+
+    // Send an event. If the dispatcher has stopped, the event is dropped.
+    pub fn emit(&self, event: RunEvent) {
+        self.spend.lock().unwrap().record(&event);
+        let _ = self.sender.send(Envelope::Event(event));
+    }
+
+    // Wait until all events sent so far have been observed, e.g. before prompting the user.
+    pub async fn flush(&self) {
+        let (ack, done) = oneshot::channel();
+        if self.sender.send(Envelope::Flush(ack)).is_ok() {
+            let _ = done.await;
+        }
+    }
+
+    // The tokens and cost of the requests made so far.
+    pub fn spend(&self) -> Spend {
+        self.spend.lock().unwrap().clone()
+    }
+
+    pub fn agent(&self, agent: &str) -> AgentProgress {
+        AgentProgress {
+            agent: agent.to_string(),
+            bus: self.clone(),
+        }
+    }
+}
+
+impl EventDispatcher {
+    pub fn bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    pub async fn close(self) {
+        self.bus.flush().await;
+        self.task.abort();
+        let _ = self.task.await;
+    }
+}
+
+// The handle an agent uses to report progress on its streamed response.
+#[derive(Clone)]
+pub struct AgentProgress {
+    agent: String,
+    bus: EventBus,
+}
+
+impl AgentProgress {
+    // A handle whose updates go nowhere, for tests.
+    #[cfg(test)]
+    pub fn detached(agent: &str) -> Self {
+        Self::with_steering(agent, Steering::default())
+    }
+
+    #[cfg(test)]
+    pub fn with_steering(agent: &str, steering: Steering) -> Self {
+        let (sender, _) = mpsc::unbounded_channel();
+        AgentProgress {
+            agent: agent.to_string(),
+            bus: EventBus {
+                sender,
+                steering,
+                spend: Arc::default(),
+            },
+        }
+    }
+
+    fn send(&self, update: ProgressUpdate) {
+        self.bus.emit(RunEvent::AgentProgress {
+            agent: self.agent.clone(),
+            update,
+        });
+    }
+
+    pub fn start(&self) {
+        self.bus.emit(RunEvent::AgentStarted {
+            agent: self.agent.clone(),
+        });
+    }
+
+    pub fn chunk(&self, text: &str) {
+        let text = text.to_string();
+        self.send(if text.trim().is_empty() {
+            ProgressUpdate::BlankChunk { text }
+        } else {
+            ProgressUpdate::Chunk { text }
+        });
+    }
+
+    pub fn reset(&self) {
+        self.send(ProgressUpdate::Reset);
+    }
+
+    pub fn note(&self, message: &str) {
+        self.send(ProgressUpdate::Note {
+            message: message.to_string(),
+        });
+    }
+
+    pub fn request(&self, model: &str, messages: &[ChatCompletionRequestMessage]) {
+        self.bus.emit(RunEvent::ChatRequested {
+            agent: self.agent.clone(),
+            model: model.to_string(),
+            messages: messages.to_vec(),
+            prompt_tokens: tokenizer::count_prompt_tokens(messages),
+        });
+    }
+
+    pub fn response(&self, response: &str, finish_reason: Option<FinishReason>, chunks: usize) {
+        self.bus.emit(RunEvent::ChatResponded {
+            agent: self.agent.clone(),
+            response: response.to_string(),
+            finish_reason,
+            chunks,
+        });
+    }
+
+    pub fn anomaly(&self, anomaly: Anomaly) {
+        self.bus.emit(RunEvent::ApiAnomaly {
+            agent: self.agent.clone(),
+            anomaly,
+        });
+    }
+
+    pub fn retry(&self, attempt: usize) {
+        self.bus.emit(RunEvent::ChatRetried {
+            agent: self.agent.clone(),
+            attempt,
+        });
+    }
+
+    pub fn finish(&self) {
+        self.bus.emit(RunEvent::AgentFinished {
+            agent: self.agent.clone(),
+        });
+    }
+
+    // Note that the agent's response is streaming, so that the user can ask for it to be
+    // regenerated, until the returned Stream is dropped.
+    pub fn streaming(&self) -> Stream {
+        self.bus.steering.stream(&self.agent)
+    }
+
+    // Wait for the user to ask for the agent's response to be regenerated.
+    pub async fn regenerate_requested(&self) -> Regenerate {
+        self.bus.steering.requested(&self.agent).await
+    }
+}
+
+// Prints the events to stdout at their verbosity, with each proposal numbered by line and diffed
+// against the last.
+pub struct ConsoleObserver {
+    // Whether the "Critic results:" header has been printed for the current review.
+    critic_header_printed: bool,
+    view: CodeView,
+    previous: Option<(usize, String)>,
+}
+
+impl ConsoleObserver {
+    pub fn new(view: CodeView) -> Self {
+        ConsoleObserver {
+            critic_header_printed: false,
+            view,
+            previous: None,
+        }
+    }
+
+    fn print_proposal(&mut self, number: usize, code: &str) {
+        let mut text = format!(
+            "Proposed code #{}: -----------\n{}\n------------------------------\n",
+            number,
+            self.view.numbered(code)
+        );
+        if let Some((previous, old)) = &self.previous {
+            text.push_str(&format!(
+                "Changes from proposal #{}: ----\n{}\n------------------------------\n",
+                previous,
+                self.view.diff(old, code)
+            ));
+        }
+        output::print(Verbosity::Normal, &text);
+        self.previous = Some((number, code.to_string()));
+    }
+}
+
+impl Observer for ConsoleObserver {
+    fn on_event(&mut self, event: &RunEvent) {
+        match event {
+            RunEvent::ProposalCreated { number, code } => {
+                return self.print_proposal(*number, code);
+            }
+            RunEvent::PhaseStarted { .. } => self.critic_header_printed = false,
+            RunEvent::CriticFinished { .. } if !self.critic_header_printed => {
+                self.critic_header_printed = true;
+                output::print(Verbosity::Normal, "Critic results:");
+            }
+            // Notes are printed by the ProgressObserver above its bars.
+            RunEvent::AgentProgress { .. } => return,
+            _ => {}
+        }
+        let level = verbosity_of(event);
+        if !output::shows(level) {
+            return;
+        }
+        let text = match level {
+            Verbosity::Verbose => describe_chat(event),
+            _ => describe(event),
+        };
+        if let Some(text) = text {
+            output::print(level, &text);
+        }
+    }
+}
+
+// The verbosity at which the console shows the event: the run's result and its errors at every
+// level, and the full prompts and responses only when verbose.
+fn verbosity_of(event: &RunEvent) -> Verbosity {
+    match event {
+        RunEvent::TestPassed { .. }
+        | RunEvent::BestCandidate { .. }
+        | RunEvent::CriticFailed { .. } => Verbosity::Quiet,
+        RunEvent::ChatRequested { .. } | RunEvent::ChatResponded { .. } => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    }
+}
+
+// Describe a request or response in full, with the role of each message of the request.
+fn describe_chat(event: &RunEvent) -> Option<String> {
+    match event {
+        RunEvent::ChatRequested {
+            agent,
+            model,
+            messages,
+            prompt_tokens,
+        } => {
+            let mut text = format!(
+                "--- {} request to {} ({} tokens) ---",
+                agent, model, prompt_tokens
+            );
+            for message in messages {
+                let (role, content) = match message {
+                    ChatCompletionRequestMessage::System(m) => ("system", m.content.clone()),
+                    ChatCompletionRequestMessage::User(m) => match &m.content {
+                        Some(ChatCompletionRequestUserMessageContent::Text(t)) => {
+                            ("user", Some(t.clone()))
+                        }
+                        Some(content) => (
+                            "user",
+                            Some(format!(
+                                "{}\n({} images)",
+                                images::content_text(content),
+                                images::content_images(content)
+                            )),
+                        ),
+                        None => ("user", None),
+                    },
+                    ChatCompletionRequestMessage::Assistant(m) => ("assistant", m.content.clone()),
+                    ChatCompletionRequestMessage::Tool(m) => ("tool", m.content.clone()),
+                    ChatCompletionRequestMessage::Function(m) => ("function", m.content.clone()),
+                };
+                text.push_str(&format!("\n[{}]\n{}", role, content.unwrap_or_default()));
+            }
+            Some(text)
+        }
+        RunEvent::ChatResponded {
+            agent,
+            response,
+            finish_reason,
+            ..
+        } => Some(format!(
+            "--- {} response ({:?}) ---\n{}",
+            agent, finish_reason, response
+        )),
+        _ => None,
+    }
+}
+
+// The live preview of the code an agent is writing, drawn below its progress bar.
+struct CodePreview {
+    bar: ProgressBar,
+    response: String,
+}
+
+// Draws a progress bar for each agent while its response streams in, optionally with a preview of
+// the code it is writing.
+pub struct ProgressObserver {
+    multi_progress: MultiProgress,
+    bars: HashMap<String, DoublingProgressBar>,
+    estimates: ResponseEstimates,
+    previews: Option<HashMap<String, CodePreview>>,
+}
+
+impl ProgressObserver {
+    pub fn new(preview: bool) -> Self {
+        ProgressObserver {
+            multi_progress: MultiProgress::new(),
+            bars: HashMap::new(),
+            estimates: ResponseEstimates::default(),
+            previews: preview.then(HashMap::new),
+        }
+    }
+
+    fn update_preview(&mut self, agent: &str, update: &ProgressUpdate) {
+        let Some(preview) = self.previews.as_mut().and_then(|p| p.get_mut(agent)) else {
+            return;
+        };
+        match update {
+            ProgressUpdate::Chunk { text } | ProgressUpdate::BlankChunk { text } => {
+                preview.response.push_str(text)
+            }
+            ProgressUpdate::Reset => preview.response.clear(),
+            ProgressUpdate::Note { .. } => return,
+        }
+        if let Some(code) = preview::partial_code(&preview.response) {
+            let lines: Vec<_> = preview::tail(&code, PREVIEW_LINES)
+                .into_iter()
+                .map(|l| format!("    {}", preview::highlight(l)))
+                .collect();
+            preview.bar.set_message(lines.join("\n"));
+        }
+    }
+}
+
+impl Observer for ProgressObserver {
+    fn on_event(&mut self, event: &RunEvent) {
+        match event {
+            RunEvent::AgentStarted { agent } => {
+                let expected = self.estimates.expected(agent);
+                if let Ok(pb) =
+                    DoublingProgressBar::new_multi(&self.multi_progress, agent, expected)
+                {
+                    self.bars.insert(agent.clone(), pb);
+                }
+                if let Some(previews) = self.previews.as_mut() {
+                    let bar = self.multi_progress.add(ProgressBar::new_spinner());
+                    bar.set_style(ProgressStyle::with_template("{wide_msg}").unwrap());
+                    let response = String::new();
+                    previews.insert(agent.clone(), CodePreview { bar, response });
+                }
+            }
+            RunEvent::AgentProgress { agent, update } => {
+                if let Some(pb) = self.bars.get_mut(agent) {
+                    pb.apply(update);
+                }
+                self.update_preview(agent, update);
+                if let ProgressUpdate::Note { message } = update {
+                    let _ = self.multi_progress.println(message);
+                }
+            }
+            RunEvent::ChatRetried { agent, attempt } => {
+                if let Some(pb) = self.bars.get_mut(agent) {
+                    pb.set_retries(*attempt);
+                }
+            }
+            RunEvent::AgentFinished { agent } => {
+                if let Some(pb) = self.bars.remove(agent) {
+                    self.estimates.record(agent, pb.tokens());
+                    pb.finish_and_clear();
+                }
+                if let Some(preview) = self.previews.as_mut().and_then(|p| p.remove(agent)) {
+                    preview.bar.finish_and_clear();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Prints the agents' progress as occasional lines instead of bars, for when stdout is redirected to
+// a log, as in collect_data's runs, where the bars' redrawing would garble it.
+#[derive(Default)]
+pub struct ProgressLinesObserver {
+    // Each agent's progress, with when its last line was printed.
+    agents: HashMap<String, (DoublingProgressBar, Instant)>,
+    estimates: ResponseEstimates,
+}
+
+impl ProgressLinesObserver {
+    fn line(agent: &str, progress: &DoublingProgressBar) -> String {
+        format!(
+            "[progress] {} ({}s): {}",
+            agent,
+            progress.bar().elapsed().as_secs(),
+            progress.status()
+        )
+    }
+}
+
+impl Observer for ProgressLinesObserver {
+    fn on_event(&mut self, event: &RunEvent) {
+        match event {
+            RunEvent::AgentStarted { agent } => {
+                let expected = self.estimates.expected(agent);
+                if let Ok(progress) = DoublingProgressBar::new_hidden(agent, expected) {
+                    self.agents
+                        .insert(agent.clone(), (progress, Instant::now()));
+                }
+            }
+            RunEvent::AgentProgress { agent, update } => {
+                if let ProgressUpdate::Note { message } = update {
+                    output::print(Verbosity::Normal, message);
+                }
+                if let Some((progress, printed)) = self.agents.get_mut(agent) {
+                    progress.apply(update);
+                    if printed.elapsed() >= PROGRESS_LINE_INTERVAL {
+                        output::print(Verbosity::Normal, &Self::line(agent, progress));
+                        *printed = Instant::now();
+                    }
+                }
+            }
+            RunEvent::ChatRetried { agent, attempt } => {
+                if let Some((progress, _)) = self.agents.get_mut(agent) {
+                    progress.set_retries(*attempt);
+                }
+            }
+            RunEvent::AgentFinished { agent } => {
+                if let Some((progress, _)) = self.agents.remove(agent) {
+                    output::print(Verbosity::Normal, &Self::line(agent, &progress));
+                    self.estimates.record(agent, progress.tokens());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Identify a run in logs that collect many runs. The start time keeps the IDs sortable and the
+// process ID keeps concurrent runs apart.
+pub fn new_run_id() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format!("{}-{}", secs, std::process::id())
+}
+
+// A line of the JSON log: the event labelled with its run and the proposal it belongs to. The
+// iteration is 0 until the first proposal is reviewed.
+#[derive(Serialize)]
+struct LogLine<'a> {
+    run_id: &'a str,
+    iteration: usize,
+    #[serde(flatten)]
+    event: &'a RunEvent,
+}
+
+// Writes each event as a line of JSON. Per-chunk progress updates are omitted because there are
+// thousands of them per run.
+pub struct JsonLinesObserver {
+    writer: Box<dyn Write + Send>,
+    run_id: String,
+    iteration: usize,
+}
+
+impl JsonLinesObserver {
+    pub fn new(writer: Box<dyn Write + Send>, run_id: &str) -> Self {
+        JsonLinesObserver {
+            writer,
+            run_id: run_id.to_string(),
+            iteration: 0,
+        }
+    }
+
+    pub fn create(path: &Path, run_id: &str) -> Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::new(Box::new(file), run_id))
+    }
+
+    pub fn stdout(run_id: &str) -> Self {
+        Self::new(Box::new(io::stdout()), run_id)
+    }
+}
+
+impl Observer for JsonLinesObserver {
+    fn on_event(&mut self, event: &RunEvent) {
+        match event {
+            RunEvent::AgentProgress {
+                update: ProgressUpdate::Chunk { .. } | ProgressUpdate::BlankChunk { .. },
+                ..
+            } => return,
+            RunEvent::ProposalCreated { number, .. } => self.iteration = *number,
+            _ => {}
+        }
+        let line = LogLine {
+            run_id: &self.run_id,
+            iteration: self.iteration,
+            event,
+        };
+        if let Ok(line) = serde_json::to_string(&line) {
+            // Flushed line by line, so that the transcript can be read while the run goes on.
+            let _ = writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush());
+        }
+    }
+}
+
+impl Drop for JsonLinesObserver {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingObserver {
+        events: Arc<Mutex<Vec<RunEvent>>>,
+    }
+
+    impl Observer for RecordingObserver {
+        fn on_event(&mut self, event: &RunEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_bus_delivers_in_order() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let dispatcher = EventBus::start(
+            vec![Box::new(RecordingObserver {
+                events: events.clone(),
+            })],
+            Steering::default(),
+        );
+        let bus = dispatcher.bus().clone();
+        bus.emit(RunEvent::PhaseStarted {
+            phase: Phase::Coding,
+        });
+        let progress = bus.agent("Coder_1");
+        progress.chunk("fn");
+        progress.reset();
+        dispatcher.close().await;
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                RunEvent::PhaseStarted {
+                    phase: Phase::Coding
+                },
+                RunEvent::AgentProgress {
+                    agent: "Coder_1".to_string(),
+                    update: ProgressUpdate::Chunk {
+                        text: "fn".to_string()
+                    }
+                },
+                RunEvent::AgentProgress {
+                    agent: "Coder_1".to_string(),
+                    update: ProgressUpdate::Reset
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_event_serialization() {
+        let event = RunEvent::AgentProgress {
+            agent: "Fixer_1".to_string(),
+            update: ProgressUpdate::Note {
+                message: "Retrying".to_string(),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "type": "agent_progress",
+                "agent": "Fixer_1",
+                "update": {"kind": "note", "message": "Retrying"},
+            })
+        );
+    }
+
+    // A writer whose contents can be read after it is moved into the observer.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_observer() {
+        let buffer = SharedBuffer::default();
+        let mut observer = JsonLinesObserver::new(Box::new(buffer.clone()), "run-1");
+        observer.on_event(&RunEvent::ProposalCreated {
+            number: 2,
+            code: "fn main() {}".to_string(),
+        });
+        observer.on_event(&RunEvent::AgentProgress {
+            agent: "Fixer_1".to_string(),
+            update: ProgressUpdate::Chunk {
+                text: "fn".to_string(),
+            },
+        });
+        observer.on_event(&RunEvent::ChatResponded {
+            agent: "Fixer_1".to_string(),
+            response: "{}".to_string(),
+            finish_reason: Some(FinishReason::Stop),
+            chunks: 1,
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            serde_json::json!({
+                "run_id": "run-1",
+                "iteration": 2,
+                "type": "chat_responded",
+                "agent": "Fixer_1",
+                "response": "{}",
+                "finish_reason": "stop",
+                "chunks": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let event = RunEvent::CriticFinished {
+            critic: "Syntax Critic 1".to_string(),
+            lgtm: false,
+            corrections: vec!["unused import".to_string()],
+            evidence: vec![],
+        };
+        assert_eq!(
+            describe(&event).unwrap(),
+            "  Syntax Critic 1:\n    Correct? false\n    • unused import"
+        );
+        let event = RunEvent::CriticFinished {
+            critic: "Correctness Critic 1".to_string(),
+            lgtm: false,
+            corrections: vec!["fib(0) returns 1.".to_string()],
+            evidence: vec!["fib(0): expected 0, traced 1".to_string()],
+        };
+        assert_eq!(
+            describe(&event).unwrap(),
+            "  Correctness Critic 1:\n    Correct? false\n    • fib(0) returns 1.\n    \
+             Traced: fib(0): expected 0, traced 1"
+        );
+        assert_eq!(
+            describe(&RunEvent::PhaseStarted {
+                phase: Phase::Testing
+            })
+            .unwrap(),
+            "\n==> Tester compiling and testing..."
+        );
+        assert!(describe(&RunEvent::AgentFinished {
+            agent: "Coder_1".to_string()
+        })
+        .is_none());
+    }
+
+    #[test]
+    fn test_describe_chat() {
+        let request = RunEvent::ChatRequested {
+            agent: "Coder_1".to_string(),
+            model: "gpt-4o".to_string(),
+            messages: vec![crate::agent::user_message("Add two numbers.").unwrap()],
+            prompt_tokens: 5,
+        };
+        assert_eq!(verbosity_of(&request), Verbosity::Verbose);
+        assert_eq!(
+            describe_chat(&request).unwrap(),
+            "--- Coder_1 request to gpt-4o (5 tokens) ---\n[user]\nAdd two numbers."
+        );
+        let passed = RunEvent::TestPassed {
+            proposal: 2,
+            code: String::new(),
+            output: String::new(),
+        };
+        assert_eq!(verbosity_of(&passed), Verbosity::Quiet);
+        let failed = RunEvent::CriticFailed {
+            critic: "Design Critic".to_string(),
+            error: "timed out".to_string(),
+        };
+        assert_eq!(verbosity_of(&failed), Verbosity::Quiet);
+        assert_eq!(verbosity_of(&RunEvent::CriticsAgreed), Verbosity::Normal);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::scripted::ScriptedClient;
+use serde_json::json;
+use std::time::Duration;
+
+// `--provider fake` answers the requests offline, so that a whole run, with its progress bars,
+// tester, and data collection, can be exercised without an API key, as in CI. Its agents play a
+// fixed game that the real tester judges: the coder writes a draft whose test fails, the critics
+// reject the draft and approve anything else, and the fixer revises it for the critics, still
+// failing the test, then fixes the test failure. So each run is solved in its second proposal. The
+// ranker always prefers the first candidate.
+// The agents are told apart by the default prompts, so custom prompts may confuse them.
+
+pub const FAKE_MODEL: &str = "fake";
+// Marks each proposal's stage in its first line.
+const MARKER: &str = "// fake proposal:";
+// The responses are streamed a few characters at a time so that the progress bars move.
+const CHUNK_CHARS: usize = 16;
+const CHUNK_DELAY: Duration = Duration::from_millis(2);
+
+pub fn client() -> ScriptedClient {
+    ScriptedClient::new(respond).with_chunks(CHUNK_CHARS, CHUNK_DELAY)
+}
+
+fn respond(system: &str, user: &str) -> String {
+    let draft = format!("{} draft", MARKER);
+    let response = if system.contains("lgtm") {
+        let mut review = if user.contains(&draft) {
+            json!({"lgtm": false, "corrections": [
+                {"correction": "`answer` should return 42, not 41.", "function": "answer"}
+            ]})
+        } else {
+            json!({"lgtm": true, "corrections": []})
+        };
+        if system.contains("evidence") {
+            review["evidence"] = json!(["answer(): expected 42, traced 42"]);
+        }
+        // A batched critic gives the same review from each of its perspectives.
+        let mut reviews = serde_json::Map::new();
+        for key in ["design", "correctness", "syntax"] {
+            if system.contains(&format!("Perspective `{}`", key)) {
+                reviews.insert(key.to_string(), review.clone());
+            }
+        }
+        match reviews.is_empty() {
+            true => review,
+            false => reviews.into(),
+        }
+    } else if system.contains("`better`") {
+        json!({"better": "A", "reason": "It addresses the review."})
+    } else if !user.contains(MARKER) {
+        json!({"code": program("draft", 41)})
+    } else if user.contains("FAILED") || user.contains("error[") {
+        json!({"code": program("final", 42)})
+    } else {
+        json!({"code": program("revised", 41)})
+    };
+    response.to_string()
+}
+
+// The program at the given stage, whose test passes if `answer` is 42.
+fn program(stage: &str, answer: i32) -> String {
+    format!(
+        "{} {}\nfn answer() -> i32 {{\n    {}\n}}\n\nfn main() {{\n    println!(\"{{}}\", \
+         answer());\n}}\n\n#[cfg(test)]\nmod tests {{\n    use super::*;\n\n    #[test]\n    fn \
+         test_answer() {{\n        assert_eq!(answer(), 42);\n    }}\n}}\n",
+        MARKER, stage, answer
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_respond() {
+        let coder = "Write the requested program in Rust.";
+        let critic = "Return a field named `lgtm`.";
+        let fixer = "Correct the code.";
+        let code = |response: String| {
+            serde_json::from_str::<serde_json::Value>(&response).unwrap()["code"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+
+        let draft = code(respond(coder, "Add two numbers."));
+        assert!(draft.starts_with("// fake proposal: draft"));
+        let review: serde_json::Value = serde_json::from_str(&respond(critic, &draft)).unwrap();
+        assert_eq!(review["lgtm"], false);
+        assert!(review.get("evidence").is_none());
+
+        let revised = code(respond(fixer, &draft));
+        assert!(revised.contains("revised") && revised.contains("41"));
+        let review = respond(&format!("{} Also `evidence`.", critic), &revised);
+        assert!(review.contains("\"lgtm\":true") && review.contains("evidence"));
+        let batched = format!("{}\nPerspective `design`:\nPerspective `syntax`:", critic);
+        let reviews: serde_json::Value = serde_json::from_str(&respond(&batched, &draft)).unwrap();
+        assert_eq!(reviews["syntax"]["lgtm"], false);
+        assert!(reviews.get("correctness").is_none());
+
+        let output = format!("{}\ntest tests::test_answer ... FAILED", revised);
+        let fixed = code(respond(fixer, &output));
+        assert!(fixed.contains("final") && fixed.contains("    42\n"));
+
+        let ranking = respond("Return a field named `better`.", &fixed);
+        assert!(ranking.contains("\"better\":\"A\""));
+    }
+}
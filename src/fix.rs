@@ -0,0 +1,261 @@
+use crate::assert_ids::TestFixWindows;
+use crate::best_candidate::BestCandidate;
+use crate::coder::Code;
+use crate::convergence::Progress;
+use crate::errors::AiCriticError;
+use crate::events::{EventBus, Phase, RunEvent};
+use crate::provider::Provider;
+use crate::run_context::BUILDS_DIRNAME;
+use crate::run_history::RunHistory;
+use crate::run_state::LoopOrder;
+use crate::tester::{TesterAgent, TesterResult};
+use crate::{
+    ai_fix_code, ai_review_code, check_convergence, convergence_policy, critics_config,
+    emit_proposal, gate_review, load_prompts, report_test_result, tester_feedback, Args,
+    FixContext,
+};
+use color_eyre::eyre::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The fix command repairs a file of an existing Rust project instead of writing a solution from
+// scratch. The file as it is stands in for the coder's first proposal, and the project's own
+// `cargo test` replaces the tester's rustc build, so the file is judged together with the rest of
+// the project. The critics see the goal, the file's path, and any context files given, along with
+// the test results. Each proposal is written to the file to be tested. If the run doesn't end with
+// passing tests, the original file is restored.
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct FixArgs {
+    /// The source file to fix.
+    #[arg(long)]
+    pub file: PathBuf,
+
+    /// What the fixed file should achieve, e.g. "make the tests pass".
+    #[arg(long)]
+    pub goal: String,
+
+    /// Another file of the project to show the critics, such as a module the file uses. May be
+    /// repeated.
+    #[arg(long)]
+    pub context: Vec<PathBuf>,
+}
+
+pub async fn run(
+    bus: &EventBus,
+    args: &Args,
+    fix_args: &FixArgs,
+    provider: &Provider,
+    run_dir: &Path,
+) -> Result<usize> {
+    let project_dir = project_dir(&fix_args.file)?;
+    let problem = problem_statement(fix_args)?;
+    bus.emit(RunEvent::ProblemLoaded {
+        path: fix_args.file.display().to_string(),
+        problem: problem.clone(),
+    });
+    let config = critics_config(args)?;
+    let prompts = load_prompts(args, &problem)?;
+    let mut file = EditedFile::open(&fix_args.file)?;
+    let mut tester = TesterAgent::new(1)
+        .with_test_seed(args.test_seed)
+        .with_max_output(args.max_test_output)
+        .with_test_timeout(args.test_timeout);
+    if args.keep_artifacts {
+        tester = tester.with_kept_builds(&run_dir.join(BUILDS_DIRNAME));
+    }
+
+    // Test the project as it is, so that the critics start from its failures.
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::Testing,
+    });
+    let initial_failure = match tester.cargo_test(&project_dir, &fix_args.file).await? {
+        TesterResult::Success { .. } => None,
+        TesterResult::Failure { review, .. } => Some(review),
+    };
+    let mut tester_output = Some(tester_feedback(
+        initial_failure.as_ref(),
+        LoopOrder::ReviewFirst,
+    ));
+    let mut code = Code {
+        code: file.original.clone(),
+    };
+    let mut history = RunHistory::default();
+    let mut windows = TestFixWindows::new(args.test_fix_window);
+    let policy = convergence_policy(args);
+    let mut progress = Progress::start();
+    let mut best = BestCandidate::default();
+    let mut last_proposal: Option<String> = None;
+    let fix_context = FixContext {
+        bus,
+        provider,
+        prompts: &prompts,
+        tester: &tester,
+        candidates: 1,
+    };
+
+    loop {
+        if let Some(stop) = check_convergence(bus, &policy, &mut progress, &best) {
+            return Err(stop.into());
+        }
+        progress.proposals += 1;
+        let proposal_count = progress.proposals;
+        emit_proposal(bus, proposal_count, &code.code, last_proposal.as_deref());
+        last_proposal = Some(code.code.clone());
+        let review_res = ai_review_code(
+            bus,
+            provider,
+            &prompts,
+            args,
+            config.as_ref(),
+            &problem,
+            &code,
+            tester_output.as_deref(),
+        )
+        .await?;
+        if let Some(review_needed) = gate_review(bus, args, review_res).await? {
+            (code, _) = ai_fix_code(
+                &fix_context,
+                &code,
+                review_needed,
+                &mut history,
+                &mut windows,
+            )
+            .await?;
+        }
+
+        file.write(&code.code)?;
+        bus.emit(RunEvent::PhaseStarted {
+            phase: Phase::Testing,
+        });
+        let result = tester.cargo_test(&project_dir, &fix_args.file).await?;
+        let failure = report_test_result(bus, proposal_count, &code, result);
+        progress.record_test(failure.as_ref());
+        if let Some(failure) = &failure {
+            best.record(proposal_count, &code.code, failure);
+        }
+        tester_output = Some(tester_feedback(failure.as_ref(), LoopOrder::ReviewFirst));
+        match failure {
+            Some(review_needed) => {
+                (code, _) = ai_fix_code(
+                    &fix_context,
+                    &code,
+                    review_needed,
+                    &mut history,
+                    &mut windows,
+                )
+                .await?;
+            }
+            None => {
+                file.keep();
+                return Ok(proposal_count);
+            }
+        }
+    }
+}
+
+// The directory of the nearest Cargo.toml above the file.
+fn project_dir(file: &Path) -> Result<PathBuf> {
+    let file = fs::canonicalize(file)?;
+    file.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| {
+            AiCriticError::InvalidConfig {
+                path: file.display().to_string(),
+                reason: "it is not in a Cargo project".to_string(),
+            }
+            .into()
+        })
+}
+
+fn problem_statement(fix_args: &FixArgs) -> Result<String> {
+    let mut problem = format!(
+        "Change the Rust file {} of an existing Cargo project to meet this goal:\n{}\n\n\
+         The file must keep compiling with the rest of the project, which is tested with \
+         `cargo test`. Keep the signatures of its public items unless the goal requires \
+         changing them.",
+        fix_args.file.display(),
+        fix_args.goal.trim()
+    );
+    for path in fix_args.context.iter() {
+        let contents = fs::read_to_string(path)?;
+        problem.push_str(&format!(
+            "\n\nFor context, the project's file {} is:\n{}",
+            path.display(),
+            contents
+        ));
+    }
+    Ok(problem)
+}
+
+// The file being fixed. Its original contents are restored when it's dropped unless the fix was
+// kept, including when the run fails or is cancelled.
+struct EditedFile {
+    path: PathBuf,
+    original: String,
+    keep: bool,
+}
+
+impl EditedFile {
+    fn open(path: &Path) -> Result<Self> {
+        Ok(EditedFile {
+            path: path.to_path_buf(),
+            original: fs::read_to_string(path)?,
+            keep: false,
+        })
+    }
+
+    fn write(&self, code: &str) -> Result<()> {
+        Ok(fs::write(&self.path, code)?)
+    }
+
+    fn keep(&mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for EditedFile {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        if let Err(e) = fs::write(&self.path, &self.original) {
+            log::warn!("Failed to restore {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_dir_and_edited_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "[package]\n").unwrap();
+        let path = dir.path().join("src/lib.rs");
+        fs::write(&path, "fn original() {}\n").unwrap();
+
+        assert_eq!(
+            project_dir(&path).unwrap(),
+            fs::canonicalize(dir.path()).unwrap()
+        );
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("main.rs"), "").unwrap();
+        assert!(project_dir(&outside.path().join("main.rs")).is_err());
+
+        let file = EditedFile::open(&path).unwrap();
+        file.write("fn broken(").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn original() {}\n");
+
+        let mut file = EditedFile::open(&path).unwrap();
+        file.write("fn fixed() {}\n").unwrap();
+        file.keep();
+        drop(file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn fixed() {}\n");
+    }
+}
@@ -1,87 +1,181 @@
-use crate::{chatter_json::ChatterJSON, coder::Code, DoublingProgressBar};
-use async_openai::types::{
-    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-    ChatCompletionRequestUserMessageArgs,
-};
+use crate::agent::{user_message, Agent};
+use crate::anchors::{self, Anchor};
+use crate::assert_ids::Window;
+use crate::chatter_json::{ChatterJSON, PROMPT_BUDGET};
+use crate::lenient::Reply;
+use crate::prompts::{Prompt, Prompts};
+use crate::schema::{self, Kind, Schema};
+use crate::tokenizer::{count_tokens, truncate_middle};
+use crate::{coder::Code, events::AgentProgress, provider::Provider};
 use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
 
 const FIXER_NAME: &str = "Fixer";
-const SYSTEM_PROMPT: &str = "
-    Correct the code, returning the fixed code as JSON in a string field called `code`.";
-
-const CODE_REVIEW_PROMPT: &str = "
-    Specifically address these code review issues:
-";
-
-const COMPILE_FIX_PROMPT: &str = "
-    Fix the code so that it compiles.
-    Correct the compilation errors without changing the code's functionality.
-    The code failed to compile with the following errors:
-";
-
-const TEST_FIX_PROMPT: &str = "
-    The code failed its unit tests as shown below. Fix the code so that it passes all tests.
-    1. Match the given `assert_id` value to the assert() in the code to find the assertion that 
-       failed.
-    2. Is the test correct? If not, write the correct test.
-    3. Is the assertion correct? If not, write the correct assertion.
-    4. Only if the test and assertion are correct, correct the non-test code.
-    This is the output of the failed test:
-";
 
+// The part of the budget that the comments keep even when the code alone would fill it.
+const MIN_COMMENTS_SHARE: usize = 4;
+// The most of the budget that the earlier rounds' issues may take.
+const MAX_RESOLVED_SHARE: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub enum ReviewType {
     CodeReview,
     CompilerFix,
     TestFix,
+    // The tests passed in some runs and failed in others.
+    FlakyTestFix,
+    // The tests or the program panicked outside an assertion, crashed, timed out, or exited with an
+    // unexpected code.
+    RuntimeFix,
+    // The code passed its tests, but is slower than the problem allows.
+    ComplexityFix,
+    TestStrengthening,
+    TestAugmentation,
+    PolicyFix,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ReviewNeeded {
     pub review_type: ReviewType,
     pub comments: Vec<String>,
+    // The lines of the code that the comments are about, where they're known.
+    #[serde(default)]
+    pub anchors: Vec<Anchor>,
+}
+
+impl ReviewNeeded {
+    // A review that isn't anchored to lines of the code.
+    pub fn new(review_type: ReviewType, comments: Vec<String>) -> Self {
+        ReviewNeeded {
+            review_type,
+            comments,
+            anchors: vec![],
+        }
+    }
 }
 
 pub struct FixerAgent {
     pub name: String,
-    system_msg: ChatCompletionRequestMessage,
+    system_prompt: String,
+    prompts: Prompts,
     chatter: ChatterJSON,
 }
 
 impl FixerAgent {
-    pub fn new(id: usize) -> Result<Self> {
-        let system_msg = ChatCompletionRequestSystemMessageArgs::default()
-            .content(SYSTEM_PROMPT)
-            .build()?
-            .into();
-
+    pub fn new(id: usize, provider: &Provider, prompts: &Prompts) -> Result<Self> {
         Ok(FixerAgent {
             name: format!("{}_{}", FIXER_NAME, id),
-            system_msg,
-            chatter: ChatterJSON::new(),
+            system_prompt: prompts.get(Prompt::Fixer).to_string(),
+            prompts: prompts.clone(),
+            chatter: ChatterJSON::new(provider).expecting(Reply::Code),
         })
     }
 
-    pub async fn chat(
+    pub async fn fix(
         &self,
-        pb: &mut DoublingProgressBar,
+        progress: &AgentProgress,
         code: &str,
         review: ReviewNeeded,
+        resolved: &[String],
     ) -> Result<Code> {
         let review_prompt = match review.review_type {
-            ReviewType::CodeReview => CODE_REVIEW_PROMPT,
-            ReviewType::CompilerFix => COMPILE_FIX_PROMPT,
-            ReviewType::TestFix => TEST_FIX_PROMPT,
+            ReviewType::CodeReview => Prompt::FixerCodeReview,
+            ReviewType::CompilerFix => Prompt::FixerCompileFix,
+            ReviewType::TestFix => Prompt::FixerTestFix,
+            ReviewType::FlakyTestFix => Prompt::FixerFlakyTestFix,
+            ReviewType::RuntimeFix => Prompt::FixerRuntimeFix,
+            ReviewType::ComplexityFix => Prompt::FixerComplexityFix,
+            ReviewType::TestStrengthening => Prompt::FixerTestStrengthening,
+            ReviewType::TestAugmentation => Prompt::FixerTestAugmentation,
+            ReviewType::PolicyFix => Prompt::FixerPolicyFix,
         };
-        let msg = format!(
-            "{}\n\n{}\n\n{}",
+        if review.anchors.is_empty() {
+            return self
+                .request(
+                    progress,
+                    review_prompt,
+                    code,
+                    &review.comments,
+                    resolved,
+                    false,
+                )
+                .await;
+        }
+        // Number the comments, and mark the lines that they're about with their numbers.
+        let comments: Vec<String> = review
+            .comments
+            .iter()
+            .enumerate()
+            .map(|(i, comment)| format!("[{}] {}", i + 1, comment))
+            .collect();
+        let annotated = anchors::annotate(code, &review.anchors);
+        let fixed = self
+            .request(
+                progress,
+                review_prompt,
+                &annotated,
+                &comments,
+                resolved,
+                true,
+            )
+            .await?;
+        Ok(Code {
+            code: anchors::strip(&fixed.code),
+        })
+    }
+
+    // Have the fixer correct only the window's lines of the code for a failed test, splicing the
+    // corrected lines back into the code.
+    pub async fn fix_window(
+        &self,
+        progress: &AgentProgress,
+        code: &str,
+        window: &Window,
+        review: ReviewNeeded,
+        resolved: &[String],
+    ) -> Result<Code> {
+        let lines = window.text(code);
+        let fixed = self
+            .request(
+                progress,
+                Prompt::FixerTestFixWindow,
+                &lines,
+                &review.comments,
+                resolved,
+                false,
+            )
+            .await?;
+        Ok(Code {
+            code: window.splice(code, &fixed.code),
+        })
+    }
+
+    async fn request(
+        &self,
+        progress: &AgentProgress,
+        review_prompt: Prompt,
+        code: &str,
+        comments: &[String],
+        resolved: &[String],
+        anchored: bool,
+    ) -> Result<Code> {
+        let budget = PROMPT_BUDGET.saturating_sub(count_tokens(self.prompts.get(Prompt::Fixer)));
+        let (msg, omitted) = build_message(
+            &self.prompts,
             review_prompt,
-            review
-                .comments
-                .iter()
-                .map(|comment| format!("• {}", comment))
-                .collect::<Vec<_>>()
-                .join("\n"),
+            comments,
+            resolved,
             code,
+            anchored,
+            budget,
         );
+        if omitted > 0 {
+            progress.note(&format!(
+                "{}: Trimmed {} tokens from the review to fit the model's context window.",
+                self.name, omitted
+            ));
+        }
 
         log::info!(
             "Review request for {} is {} characters.",
@@ -89,24 +183,193 @@ impl FixerAgent {
             msg.len(),
         );
 
-        let user_msg = ChatCompletionRequestUserMessageArgs::default()
-            .content(msg)
-            .build()?
-            .into();
+        self.chat(progress, &[user_message(&msg)?]).await
+    }
+}
 
-        let json = self
-            .chatter
-            .chat(pb, &[self.system_msg.clone(), user_msg])
-            .await?;
+impl Agent for FixerAgent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn system_prompt(&self) -> &str {
+        &self.system_prompt
+    }
 
-        // Check the fields. Should only be one: `code`.
-        let extra_keys = ChatterJSON::validate_fields(&json, vec!["code"])?;
-        if !extra_keys.is_empty() {
-            println!(
-                "{}: Warning: Extra keys in fixer response: {:?}",
-                self.name, extra_keys
-            );
+    fn chatter(&self) -> &ChatterJSON {
+        &self.chatter
+    }
+
+    fn schema(&self) -> &'static Schema {
+        schema::latest(Kind::Code)
+    }
+}
+
+// Build the fixer's request, trimming it to about `budget` tokens. The issues resolved in earlier
+// rounds get at most 1/MAX_RESOLVED_SHARE of it, keeping the most recent ones. The comments, which
+// may hold long compiler or test output, are trimmed next: short comments are kept whole and the
+// long ones share what's left. The code is trimmed only if it doesn't fit on its own. If the code is
+// `anchored`, marked with the numbers of the comments, the prompt explains the markers. Return the
+// message and the number of tokens removed.
+fn build_message(
+    prompts: &Prompts,
+    review_prompt: Prompt,
+    comments: &[String],
+    resolved: &[String],
+    code: &str,
+    anchored: bool,
+    budget: usize,
+) -> (String, usize) {
+    let review_prompt = match anchored {
+        true => format!(
+            "{}{}",
+            prompts.get(review_prompt),
+            prompts.get(Prompt::FixerAnchors)
+        ),
+        false => prompts.get(review_prompt).to_string(),
+    };
+    let resolved_prompt = prompts.get(Prompt::FixerResolvedIssues);
+    let budget = budget.saturating_sub(count_tokens(&review_prompt));
+    let (resolved, resolved_tokens, mut omitted) =
+        fit_resolved_issues(resolved_prompt, resolved, budget / MAX_RESOLVED_SHARE);
+    let budget = budget.saturating_sub(resolved_tokens);
+    let code_tokens = count_tokens(code);
+    let comments_budget = budget
+        .saturating_sub(code_tokens)
+        .max(budget / MIN_COMMENTS_SHARE);
+
+    let mut fitted = vec![String::new(); comments.len()];
+    let mut order: Vec<(usize, usize)> = comments
+        .iter()
+        .map(|c| count_tokens(c))
+        .enumerate()
+        .collect();
+    order.sort_by_key(|&(_, tokens)| tokens);
+    let mut remaining = comments_budget;
+    for (n, (i, tokens)) in order.into_iter().enumerate() {
+        let share = remaining / (comments.len() - n);
+        let (comment, removed) = if tokens <= share {
+            (comments[i].clone(), 0)
+        } else {
+            truncate_middle(&comments[i], share)
+        };
+        remaining = remaining.saturating_sub(tokens - removed);
+        omitted += removed;
+        fitted[i] = comment;
+    }
+
+    let code_budget = budget.saturating_sub(comments_budget - remaining);
+    let (code, removed) = truncate_middle(code, code_budget);
+    omitted += removed;
+
+    let mut msg = format!("{}\n\n{}\n\n", review_prompt, bullets(&fitted));
+    if !resolved.is_empty() {
+        msg.push_str(&format!("{}\n{}\n\n", resolved_prompt, bullets(&resolved)));
+    }
+    msg.push_str(&code);
+    (msg, omitted)
+}
+
+fn bullets(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("• {}", item))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Keep the resolved issues, most recent first, while they fit in the budget. Return them with the
+// tokens they use and the tokens of the ones dropped.
+fn fit_resolved_issues(
+    prompt: &str,
+    resolved: &[String],
+    budget: usize,
+) -> (Vec<String>, usize, usize) {
+    if resolved.is_empty() {
+        return (vec![], 0, 0);
+    }
+    let mut used = count_tokens(prompt);
+    let (mut kept, mut omitted) = (vec![], 0);
+    for issue in resolved {
+        let tokens = count_tokens(issue) + 1;
+        if omitted == 0 && used + tokens <= budget {
+            used += tokens;
+            kept.push(issue.clone());
+        } else {
+            omitted += tokens;
         }
-        Ok(serde_json::from_value(json)?)
+    }
+    match kept.is_empty() {
+        true => (kept, 0, omitted),
+        false => (kept, used, omitted),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_message_within_budget() {
+        let comments = vec!["Rename x.".to_string()];
+        let (msg, omitted) = build_message(
+            &Prompts::default(),
+            Prompt::FixerCodeReview,
+            &comments,
+            &[],
+            "fn main() {}",
+            false,
+            1000,
+        );
+        assert_eq!(omitted, 0);
+        assert!(msg.contains("• Rename x."));
+        assert!(msg.ends_with("fn main() {}"));
+    }
+
+    #[test]
+    fn test_build_message_trims_long_comments_first() {
+        let output = (1..=500)
+            .map(|i| format!("error[E0308]: mismatched types on line {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let comments = vec!["Short comment.".to_string(), output];
+        let code = "fn main() {\n    println!(\"hi\");\n}";
+        let (msg, omitted) = build_message(
+            &Prompts::default(),
+            Prompt::FixerCompileFix,
+            &comments,
+            &[],
+            code,
+            false,
+            500,
+        );
+        assert!(omitted > 0);
+        assert!(msg.contains("• Short comment."));
+        assert!(msg.contains("tokens omitted"));
+        assert!(msg.ends_with(code));
+        assert!(count_tokens(&msg) <= 550);
+    }
+
+    #[test]
+    fn test_build_message_keeps_recent_resolved_issues() {
+        let comments = vec!["Rename x.".to_string()];
+        let resolved: Vec<String> = (1..=100)
+            .map(|i| format!("Handle the edge case number {} of the input.", i))
+            .collect();
+        let (msg, omitted) = build_message(
+            &Prompts::default(),
+            Prompt::FixerCodeReview,
+            &comments,
+            &resolved,
+            "fn main() {}",
+            false,
+            1000,
+        );
+        assert!(omitted > 0);
+        assert!(msg.contains("Do not reintroduce them"));
+        assert!(msg.contains("• Handle the edge case number 1 of the input."));
+        assert!(!msg.contains("number 100 "));
+        assert!(msg.contains("• Rename x."));
+        assert!(msg.ends_with("fn main() {}"));
     }
 }
@@ -0,0 +1,316 @@
+use crate::chatter_json::OpenAIClientTrait;
+use crate::images::ProblemImage;
+use crate::sse;
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPart,
+    ChatCompletionRequestUserMessageContent, ChatCompletionResponseStream,
+    ChatCompletionResponseStreamMessage, ChatCompletionStreamResponseDelta,
+    CreateChatCompletionRequest, CreateChatCompletionStreamResponse, FinishReason, Role,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// A client for Google's Gemini models that looks like the OpenAI client to ChatterJSON, so that the
+// same prompts and response handling can be compared across providers. Requests are translated to
+// Gemini's streamGenerateContent API and its streamed chunks back to OpenAI's. Gemini differs in a
+// few ways that matter here:
+// - There is no system role. System messages become the request's system instruction.
+// - Even in JSON mode, the response is sometimes wrapped in a Markdown code fence.
+// - Its finish reasons are mapped to OpenAI's, so a truncated or filtered response is retried.
+// - A blocked prompt returns no candidates, only feedback with the reason.
+
+const API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+pub const DEFAULT_GEMINI_MODEL: &str = "gemini-1.5-pro";
+
+pub struct GeminiClient {
+    http: reqwest::Client,
+    api_key: String,
+    model: String,
+}
+
+impl GeminiClient {
+    pub fn new(api_key: &str, model: &str, http: &reqwest::Client) -> Self {
+        GeminiClient {
+            http: http.clone(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl OpenAIClientTrait for GeminiClient {
+    async fn create_chat_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/models/{}:streamGenerateContent?alt=sse",
+                API_BASE, self.model
+            ))
+            .header("x-goog-api-key", &self.api_key)
+            .json(&to_gemini_request(&request))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(OpenAIError::StreamError(format!(
+                "Gemini returned {}: {}",
+                status, body
+            )));
+        }
+
+        Ok(sse::stream(response, parse_event))
+    }
+
+    fn clean_response(&self, response: String) -> String {
+        strip_code_fence(&response).to_string()
+    }
+}
+
+// Translate an OpenAI chat request into the body of a Gemini request.
+fn to_gemini_request(request: &CreateChatCompletionRequest) -> Value {
+    let mut system = vec![];
+    let mut contents = vec![];
+    for msg in &request.messages {
+        let (role, parts) = match msg {
+            ChatCompletionRequestMessage::System(m) => {
+                system.extend(m.content.clone());
+                continue;
+            }
+            ChatCompletionRequestMessage::User(m) => match &m.content {
+                Some(ChatCompletionRequestUserMessageContent::Text(text)) => {
+                    ("user", vec![json!({"text": text})])
+                }
+                Some(ChatCompletionRequestUserMessageContent::Array(parts)) => {
+                    ("user", parts.iter().filter_map(to_gemini_part).collect())
+                }
+                None => continue,
+            },
+            ChatCompletionRequestMessage::Assistant(m) => match &m.content {
+                Some(text) => ("model", vec![json!({"text": text})]),
+                None => continue,
+            },
+            _ => continue,
+        };
+        contents.push(json!({"role": role, "parts": parts}));
+    }
+
+    let mut body = json!({
+        "contents": contents,
+        "generationConfig": {"responseMimeType": "application/json"},
+    });
+    if !system.is_empty() {
+        body["systemInstruction"] = json!({"parts": [{"text": system.join("\n\n")}]});
+    }
+    if let Some(temperature) = request.temperature {
+        body["generationConfig"]["temperature"] = json!(temperature);
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        body["generationConfig"]["maxOutputTokens"] = json!(max_tokens);
+    }
+    if let Some(top_p) = request.top_p {
+        body["generationConfig"]["topP"] = json!(top_p);
+    }
+    if let Some(seed) = request.seed {
+        body["generationConfig"]["seed"] = json!(seed);
+    }
+    body
+}
+
+// Gemini takes images inline rather than as URLs, so only images in data URLs are sent.
+fn to_gemini_part(part: &ChatCompletionRequestMessageContentPart) -> Option<Value> {
+    match part {
+        ChatCompletionRequestMessageContentPart::Text(part) => Some(json!({"text": part.text})),
+        ChatCompletionRequestMessageContentPart::Image(part) => {
+            let (mime_type, data) = ProblemImage::parse_data_url(&part.image_url.url)?;
+            Some(json!({"inlineData": {"mimeType": mime_type, "data": data}}))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiChunk {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<Content>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize)]
+struct Part {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+fn to_finish_reason(reason: &str) -> Option<FinishReason> {
+    match reason {
+        "STOP" => Some(FinishReason::Stop),
+        "MAX_TOKENS" => Some(FinishReason::Length),
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => {
+            Some(FinishReason::ContentFilter)
+        }
+        _ => None,
+    }
+}
+
+// Convert a Gemini chunk into the OpenAI chunk that ChatterJSON expects.
+fn to_openai_chunk(chunk: GeminiChunk) -> CreateChatCompletionStreamResponse {
+    let (content, finish_reason) = match chunk.candidates.into_iter().next() {
+        Some(candidate) => (
+            candidate
+                .content
+                .map(|c| c.parts.into_iter().filter_map(|p| p.text).collect()),
+            candidate
+                .finish_reason
+                .as_deref()
+                .and_then(to_finish_reason),
+        ),
+        None => (
+            None,
+            chunk
+                .prompt_feedback
+                .and_then(|f| f.block_reason)
+                .map(|_| FinishReason::ContentFilter),
+        ),
+    };
+    CreateChatCompletionStreamResponse {
+        id: String::new(),
+        choices: vec![ChatCompletionResponseStreamMessage {
+            index: 0,
+            #[allow(deprecated)]
+            delta: ChatCompletionStreamResponseDelta {
+                content,
+                role: Some(Role::Assistant),
+                tool_calls: None,
+                function_call: None, // Deprecated.
+            },
+            finish_reason,
+        }],
+        created: 0,
+        model: String::new(),
+        object: "chat.completion.chunk".to_string(),
+        system_fingerprint: None,
+    }
+}
+
+// The chunk of an event of Gemini's stream.
+fn parse_event(data: &str) -> Result<CreateChatCompletionStreamResponse, OpenAIError> {
+    serde_json::from_str::<GeminiChunk>(data)
+        .map(to_openai_chunk)
+        .map_err(OpenAIError::JSONDeserialize)
+}
+
+fn strip_code_fence(response: &str) -> &str {
+    let trimmed = response.trim();
+    match trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+    {
+        Some(inner) => inner.trim(),
+        None => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::images;
+    use async_openai::types::{
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+        CreateChatCompletionRequestArgs,
+    };
+
+    #[test]
+    fn test_to_gemini_request() {
+        let request = CreateChatCompletionRequestArgs::default()
+            .model("unused")
+            .max_tokens(100_u16)
+            .top_p(0.9)
+            .seed(7)
+            .messages([
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content("Be a critic.")
+                    .build()
+                    .unwrap()
+                    .into(),
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content("Review this.")
+                    .build()
+                    .unwrap()
+                    .into(),
+                images::user_message(
+                    "See the diagram.",
+                    &[ProblemImage {
+                        media_type: "image/png",
+                        data: "iVBORw==".to_string(),
+                    }],
+                ),
+            ])
+            .build()
+            .unwrap();
+        let body = to_gemini_request(&request);
+        assert_eq!(
+            body["systemInstruction"]["parts"][0]["text"],
+            "Be a critic."
+        );
+        assert_eq!(body["contents"][0]["role"], "user");
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "Review this.");
+        assert_eq!(body["contents"][1]["parts"][0]["text"], "See the diagram.");
+        assert_eq!(
+            body["contents"][1]["parts"][1]["inlineData"],
+            json!({"mimeType": "image/png", "data": "iVBORw=="})
+        );
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 100);
+        assert_eq!(body["generationConfig"]["topP"], 0.9_f32);
+        assert_eq!(body["generationConfig"]["seed"], 7);
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let first = r#"{"candidates": [{"content": {"parts": [{"text": "{\"lgtm\""}]}}]}"#;
+        let chunk = parse_event(first).unwrap();
+        assert_eq!(chunk.choices[0].delta.content.as_deref(), Some("{\"lgtm\""));
+
+        let last = r#"{"candidates": [{"finishReason": "MAX_TOKENS"}]}"#;
+        let chunk = parse_event(last).unwrap();
+        assert_eq!(chunk.choices[0].finish_reason, Some(FinishReason::Length));
+
+        let blocked = r#"{"promptFeedback": {"blockReason": "SAFETY"}}"#;
+        let chunk = parse_event(blocked).unwrap();
+        assert_eq!(
+            chunk.choices[0].finish_reason,
+            Some(FinishReason::ContentFilter)
+        );
+    }
+
+    #[test]
+    fn test_strip_code_fence() {
+        assert_eq!(strip_code_fence("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+        assert_eq!(strip_code_fence("{\"a\": 1}"), "{\"a\": 1}");
+    }
+}
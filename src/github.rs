@@ -0,0 +1,250 @@
+use crate::bench::ProblemSpec;
+use crate::errors::AiCriticError;
+use color_eyre::eyre::Result;
+use reqwest::RequestBuilder;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+
+// A small client for the parts of GitHub's REST API that are used here: reading an issue to solve,
+// and opening a pull request with the solution. Requests are authorized by a token, normally from
+// GITHUB_TOKEN. Public issues can be read without one, but at a much lower rate limit.
+
+const API_BASE: &str = "https://api.github.com";
+// The most comments read from an issue. Later ones are left out of the problem.
+const MAX_COMMENTS: usize = 100;
+
+pub struct GitHubClient {
+    http: reqwest::Client,
+    token: Option<String>,
+}
+
+// An issue, as addressed by its URL, e.g. https://github.com/owner/repo/issues/12.
+#[derive(Debug, PartialEq)]
+pub struct IssueRef {
+    pub owner: String,
+    pub repo: String,
+    pub number: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Issue {
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(skip)]
+    pub comments: Vec<Comment>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Comment {
+    pub user: User,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct User {
+    pub login: String,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    html_url: String,
+}
+
+impl GitHubClient {
+    pub fn new(token: Option<&str>) -> Self {
+        GitHubClient {
+            http: reqwest::Client::new(),
+            token: token.filter(|t| !t.is_empty()).map(str::to_string),
+        }
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "ai_critics");
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let response = self.authorize(request).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(AiCriticError::GitHubFailed {
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(response.json().await?)
+    }
+
+    // Read the issue with its comments.
+    pub async fn issue(&self, issue: &IssueRef) -> Result<Issue> {
+        let path = format!(
+            "{}/repos/{}/{}/issues/{}",
+            API_BASE, issue.owner, issue.repo, issue.number
+        );
+        let mut result: Issue = self.send(self.http.get(&path)).await?;
+        result.comments = self
+            .send(
+                self.http
+                    .get(format!("{}/comments", path))
+                    .query(&[("per_page", MAX_COMMENTS)]),
+            )
+            .await?;
+        Ok(result)
+    }
+
+    // Open a pull request to merge the head branch into the base branch. Return its URL.
+    pub async fn open_pull_request(
+        &self,
+        owner: &str,
+        repo: &str,
+        head: &str,
+        base: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<String> {
+        if self.token.is_none() {
+            return Err(AiCriticError::MissingSetting {
+                setting: "GITHUB_TOKEN".to_string(),
+            }
+            .into());
+        }
+        let request = self
+            .http
+            .post(format!("{}/repos/{}/{}/pulls", API_BASE, owner, repo))
+            .json(&json!({"title": title, "head": head, "base": base, "body": body}));
+        let pull_request: PullRequest = self.send(request).await?;
+        Ok(pull_request.html_url)
+    }
+}
+
+impl IssueRef {
+    pub fn parse(url: &str) -> Result<Self> {
+        let invalid = || AiCriticError::InvalidIssueUrl {
+            url: url.to_string(),
+        };
+        let (_, path) = url.split_once("github.com/").ok_or_else(invalid)?;
+        let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        match parts[..] {
+            [owner, repo, "issues", number] if !owner.is_empty() && !repo.is_empty() => {
+                Ok(IssueRef {
+                    owner: owner.to_string(),
+                    repo: repo.to_string(),
+                    number: number.parse().map_err(|_| invalid())?,
+                })
+            }
+            _ => Err(invalid().into()),
+        }
+    }
+
+    pub fn id(&self) -> String {
+        format!("{}/{}#{}", self.owner, self.repo, self.number)
+    }
+}
+
+impl Issue {
+    // The issue as a problem for the agents: its title and description, followed by the discussion,
+    // which often refines the requirements. An issue has no hidden tests.
+    pub fn to_problem(&self, issue: &IssueRef) -> ProblemSpec {
+        let mut prompt = format!("{}\n\n{}", self.title.trim(), text(&self.body));
+        let comments: Vec<String> = self
+            .comments
+            .iter()
+            .filter(|c| !text(&c.body).is_empty())
+            .map(|c| format!("{} wrote:\n{}", c.user.login, text(&c.body)))
+            .collect();
+        if !comments.is_empty() {
+            prompt.push_str("\n\nThe issue's comments:\n\n");
+            prompt.push_str(&comments.join("\n\n"));
+        }
+        ProblemSpec {
+            id: issue.id(),
+            prompt: format!("{}\n", prompt.trim_end()),
+            hidden_tests: String::new(),
+            reference: None,
+            entry_point: None,
+        }
+    }
+}
+
+fn text(body: &Option<String>) -> &str {
+    body.as_deref().unwrap_or_default().trim()
+}
+
+// The owner and name of a GitHub repository from its remote URL, either
+// https://github.com/owner/name.git or git@github.com:owner/name.git.
+pub fn repo_from_remote(remote: &str) -> Option<(String, String)> {
+    let (_, path) = remote
+        .split_once("github.com/")
+        .or_else(|| remote.split_once("github.com:"))?;
+    let (owner, name) = path.trim_end_matches('/').split_once('/')?;
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    if owner.is_empty() || name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some((owner.to_string(), name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_ref_parse() {
+        let issue = IssueRef::parse("https://github.com/owner/repo/issues/12").unwrap();
+        assert_eq!(issue.id(), "owner/repo#12");
+        assert!(IssueRef::parse("https://github.com/owner/repo/pull/12").is_err());
+        assert!(IssueRef::parse("https://github.com/owner/repo/issues/x").is_err());
+        assert!(IssueRef::parse("https://gitlab.com/owner/repo/issues/12").is_err());
+    }
+
+    #[test]
+    fn test_to_problem() {
+        let issue = Issue {
+            title: "Parse durations ".to_string(),
+            body: Some("Parse strings like `1h30m` into seconds.".to_string()),
+            comments: vec![
+                Comment {
+                    user: User {
+                        login: "alice".to_string(),
+                    },
+                    body: Some("Reject negative values.".to_string()),
+                },
+                Comment {
+                    user: User {
+                        login: "bob".to_string(),
+                    },
+                    body: None,
+                },
+            ],
+        };
+        let issue_ref = IssueRef::parse("https://github.com/owner/repo/issues/3").unwrap();
+        let problem = issue.to_problem(&issue_ref);
+        assert_eq!(problem.id, "owner/repo#3");
+        assert_eq!(
+            problem.prompt,
+            "Parse durations\n\nParse strings like `1h30m` into seconds.\n\n\
+             The issue's comments:\n\nalice wrote:\nReject negative values.\n"
+        );
+    }
+
+    #[test]
+    fn test_repo_from_remote() {
+        let expected = Some(("owner".to_string(), "name".to_string()));
+        assert_eq!(
+            repo_from_remote("https://github.com/owner/name.git"),
+            expected
+        );
+        assert_eq!(repo_from_remote("git@github.com:owner/name.git"), expected);
+        assert_eq!(repo_from_remote("https://github.com/owner/name"), expected);
+        assert_eq!(repo_from_remote("https://gitlab.com/owner/name.git"), None);
+    }
+}
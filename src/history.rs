@@ -0,0 +1,583 @@
+use crate::code_view::CodeView;
+use crate::convergence::parse_duration;
+use crate::critic::CriticType;
+use crate::errors::AiCriticError;
+use crate::output::{self, Verbosity};
+use crate::proposal_diff::ProposalDiff;
+use crate::run_context::RunContext;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+// The history, show, and diff-runs subcommands browse the runs kept in the output directory.
+// history lists them, the latest first, from each run's result.json and state.json, so a run that
+// is still going or was killed is listed as unfinished. show prints one run's outcome, the timeline
+// of its reviews, fixes, and tests from its transcript, and its final code. diff-runs compares two
+// runs, as when the same problem is solved with different prompts or models: their outcomes, their
+// proposals side by side, the corrections of each type of critic, and a diff of their final code.
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct HistoryArgs {
+    /// Only list the runs whose problem file contains this text, e.g. coding_problem3.
+    #[arg(long)]
+    pub problem: Option<String>,
+
+    /// Only list the runs updated within this long, e.g. 90m, 12h, or 2d.
+    #[arg(long, value_parser = parse_age)]
+    pub since: Option<Duration>,
+
+    /// List at most this many runs.
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ShowArgs {
+    /// ID of the run, as listed by the history subcommand.
+    pub run_id: String,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiffRunsArgs {
+    /// ID of the first run, whose code the diff starts from.
+    pub first: String,
+
+    /// ID of the second run.
+    pub second: String,
+}
+
+// Parse an age such as 2d, or a duration such as 12h.
+fn parse_age(s: &str) -> Result<Duration, String> {
+    match s.trim().strip_suffix('d') {
+        Some(days) => days
+            .parse::<f64>()
+            .map(|days| Duration::from_secs_f64(days * 86400.0))
+            .map_err(|_| format!("`{}` isn't an age such as 12h or 2d", s)),
+        None => parse_duration(s),
+    }
+}
+
+// What a run's result.json says about it. Runs from before a field was added lack it.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RunResult {
+    problem_file: String,
+    outcome: String,
+    proposals: usize,
+    model: Option<String>,
+    cost_usd: Option<f64>,
+}
+
+// A run as history lists it.
+struct PastRun {
+    context: RunContext,
+    updated: SystemTime,
+    status: &'static str,
+    result: Option<RunResult>,
+}
+
+impl PastRun {
+    fn read(context: RunContext) -> Self {
+        let result = context
+            .result()
+            .and_then(|value| serde_json::from_value::<RunResult>(value).ok());
+        let state = context.state();
+        let state = state.as_ref().and_then(|state| state["state"].as_str());
+        let status = match (state, &result) {
+            (Some("done"), _) => "solved",
+            (Some("diverged"), _) => "diverged",
+            (_, Some(result)) if result.outcome == "Cancelled" => "cancelled",
+            (_, Some(_)) => "error",
+            (_, None) => "unfinished",
+        };
+        PastRun {
+            updated: context.updated().unwrap_or(SystemTime::UNIX_EPOCH),
+            context,
+            status,
+            result,
+        }
+    }
+
+    fn row(&self) -> String {
+        let (proposals, cost, model, problem) = match &self.result {
+            Some(result) => (
+                result.proposals.to_string(),
+                result
+                    .cost_usd
+                    .map_or("-".to_string(), |cost| format!("${:.4}", cost)),
+                result.model.clone().unwrap_or("-".to_string()),
+                result.problem_file.clone(),
+            ),
+            None => ("-".into(), "-".into(), "-".into(), "-".into()),
+        };
+        format!(
+            "{:<24}  {:>8}  {:<10}  {:>9}  {:>9}  {:<16}  {}",
+            self.context.id,
+            age(self.updated),
+            self.status,
+            proposals,
+            cost,
+            model,
+            problem
+        )
+    }
+}
+
+// How long ago the time was, to the largest whole unit.
+fn age(time: SystemTime) -> String {
+    let secs = time.elapsed().unwrap_or_default().as_secs();
+    match secs {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 3600 => format!("{}m ago", s / 60),
+        s if s < 86400 => format!("{}h ago", s / 3600),
+        s => format!("{}d ago", s / 86400),
+    }
+}
+
+// List the runs in the output directory that match the args, the latest first.
+pub fn list(output_dir: &Path, args: &HistoryArgs) -> Result<()> {
+    let mut runs: Vec<PastRun> = RunContext::find_all(output_dir)
+        .into_iter()
+        .map(PastRun::read)
+        .filter(|run| match args.since {
+            Some(since) => run.updated.elapsed().unwrap_or_default() <= since,
+            None => true,
+        })
+        .filter(|run| match (&args.problem, &run.result) {
+            (Some(problem), Some(result)) => result.problem_file.contains(problem.as_str()),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .collect();
+    runs.sort_by_key(|run| std::cmp::Reverse(run.updated));
+    runs.truncate(args.limit.unwrap_or(runs.len()));
+    if runs.is_empty() {
+        output::print(
+            Verbosity::Quiet,
+            &format!("No runs found in {}.", output_dir.display()),
+        );
+        return Ok(());
+    }
+    let mut lines = vec![format!(
+        "{:<24}  {:>8}  {:<10}  {:>9}  {:>9}  {:<16}  {}",
+        "Run", "Updated", "Status", "Proposals", "Cost", "Model", "Problem"
+    )];
+    lines.extend(runs.iter().map(PastRun::row));
+    output::print(Verbosity::Quiet, &lines.join("\n"));
+    Ok(())
+}
+
+fn find(output_dir: &Path, id: &str) -> Result<RunContext> {
+    let run = RunContext::find(output_dir, id).ok_or_else(|| AiCriticError::RunNotFound {
+        id: id.to_string(),
+        dir: output_dir.display().to_string(),
+    })?;
+    Ok(run)
+}
+
+// Print the run's outcome, the timeline of its transcript, and its final code.
+pub fn show(output_dir: &Path, args: &ShowArgs) -> Result<()> {
+    let context = find(output_dir, &args.run_id)?;
+    let final_code = context.final_code();
+    let transcript = context.transcript();
+    let run = PastRun::read(context);
+    let mut lines = vec![format!(
+        "Run {} ({}), {}, updated {}",
+        run.context.id,
+        run.context.dir.display(),
+        run.status,
+        age(run.updated)
+    )];
+    if let Some(result) = &run.result {
+        lines.push(format!("Problem: {}", result.problem_file));
+        lines.push(format!("Outcome: {}", result.outcome));
+        if let (Some(model), Some(cost)) = (&result.model, result.cost_usd) {
+            lines.push(format!("Model: {}, cost: ${:.4}", model, cost));
+        }
+    }
+    lines.push(String::new());
+    lines.extend(timeline(&transcript));
+    if let Some((name, code)) = final_code {
+        lines.push(String::new());
+        lines.push(format!("Final code ({}):", name));
+        lines.push(CodeView::for_terminal().numbered(&code));
+    }
+    output::print(Verbosity::Quiet, &lines.join("\n"));
+    Ok(())
+}
+
+// The events of a transcript that the timeline shows. The rest, and lines that aren't events, are
+// skipped.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    ProposalCreated {
+        number: usize,
+        code: String,
+    },
+    ProposalDiffed {
+        diff: ProposalDiff,
+    },
+    CriticFinished {
+        critic: String,
+        lgtm: bool,
+        corrections: Vec<String>,
+    },
+    CriticFailed {
+        critic: String,
+        error: String,
+    },
+    CriticsAgreed,
+    FixApplied {
+        fixer: String,
+        similarity: f64,
+    },
+    TestFailed {
+        output: String,
+        compile_error: bool,
+    },
+    TestPassed,
+    Notice {
+        message: String,
+    },
+    RunFinished {
+        outcome: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+fn events(transcript: &str) -> Vec<Event> {
+    transcript
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+// A line per proposal, review, fix, and test in the transcript, with each review's corrections.
+fn timeline(transcript: &str) -> Vec<String> {
+    let mut lines = vec![];
+    for event in events(transcript) {
+        match event {
+            Event::ProposalCreated { number, code } => lines.push(format!(
+                "Proposal {} ({} lines)",
+                number,
+                code.lines().count()
+            )),
+            Event::ProposalDiffed { diff } => lines.push(format!("  Changed: {}", diff.summary())),
+            Event::CriticFinished {
+                critic, lgtm: true, ..
+            } => lines.push(format!("  {}: LGTM", critic)),
+            Event::CriticFinished {
+                critic,
+                corrections,
+                ..
+            } => {
+                lines.push(format!("  {}: {} corrections", critic, corrections.len()));
+                lines.extend(corrections.iter().map(|c| format!("    - {}", c)));
+            }
+            Event::CriticFailed { critic, error } => {
+                lines.push(format!("  {} failed: {}", critic, error))
+            }
+            Event::CriticsAgreed => lines.push("  The critics approved it".to_string()),
+            Event::FixApplied { fixer, similarity } => lines.push(format!(
+                "  {} fixed it, keeping {:.0}% of the code",
+                fixer,
+                similarity * 100.0
+            )),
+            Event::TestFailed {
+                output,
+                compile_error,
+            } => lines.push(format!(
+                "  {}: {}",
+                match compile_error {
+                    true => "It didn't compile",
+                    false => "Its tests failed",
+                },
+                output.lines().find(|l| !l.trim().is_empty()).unwrap_or("")
+            )),
+            Event::TestPassed => lines.push("  Its tests passed".to_string()),
+            Event::Notice { message } => lines.push(format!("  Note: {}", message)),
+            Event::RunFinished { outcome } => lines.push(outcome),
+            Event::Other => {}
+        }
+    }
+    lines
+}
+
+// Print how the two runs differ: their outcomes, their proposals side by side, the corrections of
+// each type of critic, and a diff from the first run's final code to the second's.
+pub fn diff(output_dir: &Path, args: &DiffRunsArgs) -> Result<()> {
+    let (first, second) = (
+        find(output_dir, &args.first)?,
+        find(output_dir, &args.second)?,
+    );
+    let (first_code, second_code) = (first.final_code(), second.final_code());
+    let (first_events, second_events) = (events(&first.transcript()), events(&second.transcript()));
+    let (first, second) = (PastRun::read(first), PastRun::read(second));
+    let describe = |run: &PastRun| match &run.result {
+        Some(result) => {
+            let cost = result
+                .cost_usd
+                .map_or(String::new(), |cost| format!(", ${:.4}", cost));
+            let model = result
+                .model
+                .as_ref()
+                .map_or(String::new(), |model| format!(", {}", model));
+            format!("{}: {}{}{}", run.status, result.outcome, cost, model)
+        }
+        None => run.status.to_string(),
+    };
+
+    let mut lines = vec![];
+    let outcomes = [
+        (
+            "Run".to_string(),
+            first.context.id.clone(),
+            second.context.id.clone(),
+        ),
+        ("Outcome".to_string(), describe(&first), describe(&second)),
+    ];
+    lines.extend(columns(&outcomes));
+
+    lines.push(String::new());
+    let (first_steps, second_steps) = (trajectory(&first_events), trajectory(&second_events));
+    let ids = (first.context.id.clone(), second.context.id.clone());
+    let mut steps = vec![("Proposal".to_string(), ids.0.clone(), ids.1.clone())];
+    for i in 0..first_steps.len().max(second_steps.len()) {
+        let step = |steps: &[String]| steps.get(i).cloned().unwrap_or("-".to_string());
+        steps.push(((i + 1).to_string(), step(&first_steps), step(&second_steps)));
+    }
+    lines.extend(columns(&steps));
+
+    lines.push(String::new());
+    let (first_critics, second_critics) = (
+        corrections_by_critic(&first_events),
+        corrections_by_critic(&second_events),
+    );
+    let mut critics: Vec<&String> = first_critics.keys().chain(second_critics.keys()).collect();
+    critics.sort();
+    critics.dedup();
+    let count = |counts: &BTreeMap<String, usize>, critic: &String| {
+        counts
+            .get(critic)
+            .map_or("-".to_string(), |n| n.to_string())
+    };
+    let mut corrections = vec![("Corrections by".to_string(), ids.0, ids.1)];
+    corrections.extend(critics.into_iter().map(|critic| {
+        (
+            critic.clone(),
+            count(&first_critics, critic),
+            count(&second_critics, critic),
+        )
+    }));
+    lines.extend(columns(&corrections));
+
+    lines.push(String::new());
+    match (first_code, second_code) {
+        (Some((first_name, first_code)), Some((second_name, second_code))) => {
+            lines.push(format!(
+                "Final code, from {}'s {} to {}'s {}:",
+                first.context.id, first_name, second.context.id, second_name
+            ));
+            lines.push(CodeView::for_terminal().diff(&first_code, &second_code));
+        }
+        _ => lines.push("A run has no code to compare.".to_string()),
+    }
+    output::print(Verbosity::Quiet, &lines.join("\n"));
+    Ok(())
+}
+
+// The rows in three aligned columns.
+fn columns(rows: &[(String, String, String)]) -> Vec<String> {
+    let width = |column: fn(&(String, String, String)) -> &String| {
+        rows.iter()
+            .map(|row| column(row).chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let (first, second) = (width(|row| &row.0), width(|row| &row.1));
+    rows.iter()
+        .map(|(label, a, b)| {
+            format!("{:<first$}  {:<second$}  {}", label, a, b)
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+// A summary of each proposal: the critics' corrections, the fixes, and how its tests went.
+fn trajectory(events: &[Event]) -> Vec<String> {
+    #[derive(Default)]
+    struct Step {
+        corrections: usize,
+        approved: bool,
+        fixes: usize,
+        test: Option<&'static str>,
+    }
+    let mut steps: Vec<Step> = vec![];
+    for event in events {
+        if let Event::ProposalCreated { .. } = event {
+            steps.push(Step::default());
+        }
+        let Some(step) = steps.last_mut() else {
+            continue;
+        };
+        match event {
+            Event::CriticFinished { corrections, .. } => step.corrections += corrections.len(),
+            Event::CriticsAgreed => step.approved = true,
+            Event::FixApplied { .. } => step.fixes += 1,
+            Event::TestFailed {
+                compile_error: true,
+                ..
+            } => step.test = Some("didn't compile"),
+            Event::TestFailed { .. } => step.test = Some("tests failed"),
+            Event::TestPassed => step.test = Some("tests passed"),
+            _ => {}
+        }
+    }
+    steps
+        .iter()
+        .map(|step| {
+            let mut parts = vec![];
+            if step.corrections > 0 {
+                parts.push(format!("{} corrections", step.corrections));
+            } else if step.approved {
+                parts.push("approved".to_string());
+            }
+            if step.fixes > 0 {
+                parts.push(format!("{} fixes", step.fixes));
+            }
+            parts.extend(step.test.map(String::from));
+            parts.join(", ")
+        })
+        .collect()
+}
+
+// The number of corrections of each type of critic, whose critics are numbered and may name their
+// model or sample, as in "Design Critic 2 (gpt-4o)". A critic of no known type is counted by its
+// name without its number.
+fn corrections_by_critic(events: &[Event]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for event in events {
+        if let Event::CriticFinished {
+            critic,
+            corrections,
+            ..
+        } = event
+        {
+            let critic_type = match CriticType::of_critic(critic) {
+                Some(critic_type) => critic_type.critic_name(),
+                None => critic
+                    .split(" (")
+                    .next()
+                    .unwrap_or(critic)
+                    .trim_end_matches(|c: char| c.is_ascii_digit())
+                    .trim_end_matches([' ', '_']),
+            };
+            *counts.entry(critic_type.to_string()).or_default() += corrections.len();
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("2d"), Ok(Duration::from_secs(2 * 86400)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert!(parse_age("xd").is_err());
+    }
+
+    #[test]
+    fn test_timeline() {
+        let transcript = r#"{"run_id":"r","iteration":0,"type":"problem_loaded","path":"p.txt","problem":"Add."}
+{"run_id":"r","iteration":1,"type":"proposal_created","number":1,"code":"fn add() {}\n"}
+{"run_id":"r","iteration":1,"type":"critic_finished","critic":"General Critic","lgtm":false,"corrections":["Take two arguments."],"evidence":[]}
+{"run_id":"r","iteration":1,"type":"critic_finished","critic":"Syntax Critic","lgtm":true,"corrections":[],"evidence":[]}
+{"run_id":"r","iteration":1,"type":"fix_applied","fixer":"Fixer_1","code":"fn add(a: i32) {}","similarity":0.8}
+not an event
+{"run_id":"r","iteration":2,"type":"proposal_created","number":2,"code":"fn add(a: i32) {}"}
+{"run_id":"r","iteration":2,"type":"proposal_diffed","diff":{"proposal":2,"lines_added":1,"lines_removed":1,"hunks":[],"functions":[{"name":"add","change":"modified"}]}}
+{"run_id":"r","iteration":2,"type":"test_failed","output":"\nerror[E0308]: mismatched types\n","compile_error":true}
+{"run_id":"r","iteration":2,"type":"run_finished","outcome":"Failed to converge in 2 proposals"}"#;
+        assert_eq!(
+            timeline(transcript),
+            vec![
+                "Proposal 1 (1 lines)",
+                "  General Critic: 1 corrections",
+                "    - Take two arguments.",
+                "  Syntax Critic: LGTM",
+                "  Fixer_1 fixed it, keeping 80% of the code",
+                "Proposal 2 (1 lines)",
+                "  Changed: +1 -1 lines, modified add",
+                "  It didn't compile: error[E0308]: mismatched types",
+                "Failed to converge in 2 proposals",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trajectory_and_critics() {
+        let transcript = r#"{"type":"critic_finished","critic":"Design Critic 1","lgtm":false,"corrections":["Before any proposal."]}
+{"type":"proposal_created","number":1,"code":""}
+{"type":"critic_finished","critic":"Design Critic 1","lgtm":false,"corrections":["a","b"]}
+{"type":"critic_finished","critic":"Design Critic 2 (gpt-4o)","lgtm":false,"corrections":["c"]}
+{"type":"critic_finished","critic":"Design Critic 2 (gpt-4o) (sample 2)","lgtm":false,"corrections":["d"]}
+{"type":"critic_finished","critic":"Syntax Critic 1","lgtm":true,"corrections":[]}
+{"type":"fix_applied","fixer":"Fixer_1","code":"","similarity":0.9}
+{"type":"test_failed","output":"","compile_error":true}
+{"type":"proposal_created","number":2,"code":""}
+{"type":"critics_agreed"}
+{"type":"test_passed","proposal":2,"code":"","output":""}"#;
+        let events = events(transcript);
+        assert_eq!(
+            trajectory(&events),
+            vec![
+                "4 corrections, 1 fixes, didn't compile",
+                "approved, tests passed"
+            ]
+        );
+        let critics = corrections_by_critic(&events);
+        assert_eq!(critics["Design Critic"], 5);
+        assert_eq!(critics["Syntax Critic"], 0);
+        assert_eq!(critics.len(), 2);
+
+        let rows = [
+            (
+                "Proposal".to_string(),
+                "run-1".to_string(),
+                "run-2".to_string(),
+            ),
+            ("10".to_string(), "approved".to_string(), String::new()),
+        ];
+        assert_eq!(
+            columns(&rows),
+            vec!["Proposal  run-1     run-2", "10        approved"]
+        );
+    }
+
+    #[test]
+    fn test_past_run() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let context = RunContext::create(output_dir.path(), "run-1").unwrap();
+        let unfinished = PastRun::read(RunContext::open(output_dir.path(), "run-1").unwrap());
+        assert_eq!(unfinished.status, "unfinished");
+        std::fs::write(context.dir.join("state.json"), r#"{"state":"diverged"}"#).unwrap();
+        std::fs::write(
+            context.dir.join("result.json"),
+            r#"{"problem_file":"p.txt","outcome":"Failed to converge in 20 proposals","proposals":20}"#,
+        )
+        .unwrap();
+        let run = PastRun::read(context);
+        assert_eq!(run.status, "diverged");
+        let row = run.row();
+        let columns: Vec<&str> = row.split_whitespace().collect();
+        assert_eq!(columns[3..], ["diverged", "20", "-", "-", "p.txt"]);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::errors::AiCriticError;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPart,
+    ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent, ImageUrl,
+    ImageUrlDetail, Role,
+};
+use base64::Engine;
+use color_eyre::eyre::Result;
+use std::fs;
+use std::path::Path;
+
+// A problem can come with images, such as a photo of a whiteboard or a diagram from a spec, given
+// with --problem-image. They're sent to the coder with the problem statement, each as an image
+// content part holding the image as a base64 data URL, so the coder's model must take images. The
+// critics and the fixer still see only the text.
+
+// The most that a model takes in one image.
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProblemImage {
+    pub media_type: &'static str,
+    // The image's bytes, base64 encoded.
+    pub data: String,
+}
+
+impl ProblemImage {
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+
+    // The image's media type and data, if the URL is a data URL.
+    pub fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+        url.strip_prefix("data:")?.split_once(";base64,")
+    }
+}
+
+// Read the images, checking that each is of a type that the models take.
+pub fn load(paths: &[impl AsRef<Path>]) -> Result<Vec<ProblemImage>> {
+    paths.iter().map(|path| load_image(path.as_ref())).collect()
+}
+
+fn load_image(path: &Path) -> Result<ProblemImage> {
+    let invalid = |reason: &str| AiCriticError::InvalidImage {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    };
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let media_type = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => return Err(invalid("not a PNG, JPEG, GIF, or WebP image").into()),
+    };
+    let bytes = fs::read(path)?;
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(invalid(&format!("larger than {} MB", MAX_IMAGE_BYTES >> 20)).into());
+    }
+    Ok(ProblemImage {
+        media_type,
+        data: base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+// A user message of the text followed by the images, or of only the text if there are none.
+pub fn user_message(text: &str, images: &[ProblemImage]) -> ChatCompletionRequestMessage {
+    let content = match images {
+        [] => ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        images => {
+            let text = ChatCompletionRequestMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartText {
+                    r#type: "text".to_string(),
+                    text: text.to_string(),
+                },
+            );
+            let images = images.iter().map(|image| {
+                ChatCompletionRequestMessageContentPart::Image(
+                    ChatCompletionRequestMessageContentPartImage {
+                        r#type: "image_url".to_string(),
+                        image_url: ImageUrl {
+                            url: image.data_url(),
+                            detail: ImageUrlDetail::Auto,
+                        },
+                    },
+                )
+            });
+            ChatCompletionRequestUserMessageContent::Array(
+                [text].into_iter().chain(images).collect(),
+            )
+        }
+    };
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: Some(content),
+        role: Role::User,
+        name: None,
+    })
+}
+
+// The text of a user message's content, without its images.
+pub fn content_text(content: &ChatCompletionRequestUserMessageContent) -> String {
+    match content {
+        ChatCompletionRequestUserMessageContent::Text(text) => text.clone(),
+        ChatCompletionRequestUserMessageContent::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ChatCompletionRequestMessageContentPart::Text(part) => Some(part.text.as_str()),
+                ChatCompletionRequestMessageContentPart::Image(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+// The width and height of each image of a user message's content, or None for one whose size can't
+// be read from its header.
+pub fn content_dimensions(
+    content: &ChatCompletionRequestUserMessageContent,
+) -> Vec<Option<(u32, u32)>> {
+    let ChatCompletionRequestUserMessageContent::Array(parts) = content else {
+        return vec![];
+    };
+    parts
+        .iter()
+        .filter_map(|part| match part {
+            ChatCompletionRequestMessageContentPart::Image(part) => Some(&part.image_url.url),
+            ChatCompletionRequestMessageContentPart::Text(_) => None,
+        })
+        .map(|url| {
+            let (_, data) = ProblemImage::parse_data_url(url)?;
+            dimensions(
+                &base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .ok()?,
+            )
+        })
+        .collect()
+}
+
+// The width and height of a PNG, GIF, JPEG, or WebP image, read from its header.
+pub fn dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let be32 = |i: usize| Some(u32::from_be_bytes(bytes.get(i..i + 4)?.try_into().ok()?));
+    let le24 = |i: usize| Some(le16(i)? | (*bytes.get(i + 2)? as u32) << 16);
+    if bytes.starts_with(b"\x89PNG") {
+        return Some((be32(16)?, be32(20)?));
+    }
+    if bytes.starts_with(b"GIF8") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return match bytes.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = le24(21)? | (*bytes.get(24)? as u32) << 24;
+                Some(((bits & 0x3fff) + 1, (bits >> 14 & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        };
+    }
+    if bytes.starts_with(&[0xff, 0xd8]) {
+        // Walk the JPEG's segments to the start of its frame, which holds the size.
+        let mut i = 2;
+        while *bytes.get(i)? == 0xff {
+            let marker = *bytes.get(i + 1)?;
+            if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+                return Some((be16(i + 7)?, be16(i + 5)?));
+            }
+            i += 2 + be16(i + 2)? as usize;
+        }
+    }
+    None
+}
+
+// The number of images in a user message's content.
+pub fn content_images(content: &ChatCompletionRequestUserMessageContent) -> usize {
+    match content {
+        ChatCompletionRequestUserMessageContent::Text(_) => 0,
+        ChatCompletionRequestUserMessageContent::Array(parts) => parts
+            .iter()
+            .filter(|part| matches!(part, ChatCompletionRequestMessageContentPart::Image(_)))
+            .count(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = dir.path().join("diagram.PNG");
+        fs::write(&png, [0x89, b'P', b'N', b'G']).unwrap();
+        let images = load(&[&png]).unwrap();
+        assert_eq!(images[0].media_type, "image/png");
+        assert_eq!(images[0].data_url(), "data:image/png;base64,iVBORw==");
+        assert_eq!(
+            ProblemImage::parse_data_url(&images[0].data_url()),
+            Some(("image/png", "iVBORw=="))
+        );
+
+        let bmp = dir.path().join("diagram.bmp");
+        fs::write(&bmp, "BM").unwrap();
+        let error = load(&[&bmp]).unwrap_err();
+        assert!(error.to_string().contains("not a PNG"));
+        assert!(load(&[dir.path().join("missing.png")]).is_err());
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let png = [
+            b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR",
+            &[0, 0, 4, 0, 0, 0, 2, 0][..],
+        ]
+        .concat();
+        assert_eq!(dimensions(&png), Some((1024, 512)));
+        assert_eq!(dimensions(b"GIF89a\x40\x01\xf0\x00"), Some((320, 240)));
+        let jpeg = [
+            &[0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0][..],
+            &[0xff, 0xc0, 0, 17, 8, 0x01, 0xe0, 0x02, 0x80],
+        ]
+        .concat();
+        assert_eq!(dimensions(&jpeg), Some((640, 480)));
+        let webp = |chunk: &[u8], header: &[u8]| [b"RIFF\0\0\0\0WEBP", chunk, header].concat();
+        let vp8x = webp(
+            b"VP8X",
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0x3f, 0x01, 0, 0xef, 0, 0],
+        );
+        assert_eq!(dimensions(&vp8x), Some((320, 240)));
+        // 320 by 240, less one, in 14 bits each after the signature byte.
+        let bits: u32 = 319 | 239 << 14;
+        let vp8l = webp(b"VP8L", &[[0; 5].as_slice(), &bits.to_le_bytes()].concat());
+        assert_eq!(dimensions(&vp8l), Some((320, 240)));
+        let vp8 = webp(
+            b"VP8 ",
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x40, 0x01, 0xf0, 0x00],
+        );
+        assert_eq!(dimensions(&vp8), Some((320, 240)));
+        assert_eq!(dimensions(&[0x89, b'P', b'N', b'G']), None);
+        assert_eq!(dimensions(b"BM"), None);
+    }
+
+    #[test]
+    fn test_user_message() {
+        let image = ProblemImage {
+            media_type: "image/png",
+            data: "iVBORw==".to_string(),
+        };
+        let ChatCompletionRequestMessage::User(message) =
+            user_message("Add.", std::slice::from_ref(&image))
+        else {
+            panic!("not a user message");
+        };
+        let content = message.content.unwrap();
+        assert_eq!(content_text(&content), "Add.");
+        assert_eq!(content_images(&content), 1);
+        let json = serde_json::to_value(&content).unwrap();
+        assert_eq!(json[1]["image_url"]["url"], image.data_url());
+
+        let ChatCompletionRequestMessage::User(message) = user_message("Add.", &[]) else {
+            panic!("not a user message");
+        };
+        assert_eq!(
+            message.content,
+            Some(ChatCompletionRequestUserMessageContent::Text(
+                "Add.".to_string()
+            ))
+        );
+    }
+}
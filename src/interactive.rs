@@ -0,0 +1,217 @@
+use crate::anchors::Anchor;
+use crate::fixer::{ReviewNeeded, ReviewType};
+use color_eyre::eyre::Result;
+use std::io::{self, BufRead, Write};
+
+// The human review gate runs after the critics report and before the fixer. It shows each of the
+// critics' comments and lets the user accept, reject, or edit it, then add comments of their own.
+// The result replaces the critics' ReviewNeeded, so the fixer only sees what the user approved.
+
+// Comments written by the user are labelled so the fixer knows they come from a person.
+const HUMAN_PREFIX: &str = "Human reviewer: ";
+
+enum Choice {
+    Accept,
+    AcceptAll,
+    Reject,
+    Edit,
+}
+
+pub struct ReviewGate<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+impl ReviewGate<io::StdinLock<'static>, io::Stdout> {
+    pub fn new() -> Self {
+        ReviewGate {
+            input: io::stdin().lock(),
+            output: io::stdout(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> ReviewGate<R, W> {
+    #[cfg(test)]
+    pub fn with_io(input: R, output: W) -> Self {
+        ReviewGate { input, output }
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        self.input.read_line(&mut line)?;
+        Ok(line.trim().to_string())
+    }
+
+    fn prompt(&mut self, msg: &str) -> Result<String> {
+        write!(self.output, "{}", msg)?;
+        self.output.flush()?;
+        self.read_line()
+    }
+
+    fn choose(&mut self, index: usize, total: usize, comment: &str) -> Result<Choice> {
+        writeln!(self.output, "\n[{}/{}] {}", index, total, comment)?;
+        loop {
+            let answer = self.prompt("  [a]ccept, accept [A]ll, [r]eject, [e]dit? ")?;
+            match answer.as_str() {
+                "" | "a" => return Ok(Choice::Accept),
+                "A" => return Ok(Choice::AcceptAll),
+                "r" => return Ok(Choice::Reject),
+                "e" => return Ok(Choice::Edit),
+                _ => writeln!(self.output, "  Please answer a, A, r, or e.")?,
+            }
+        }
+    }
+
+    // Show the critics' comments, if any, and return the review the user approved. Return None if the
+    // user rejected every comment and added none, so that the fixer can be skipped.
+    pub fn review(&mut self, review: Option<ReviewNeeded>) -> Result<Option<ReviewNeeded>> {
+        let (comments, anchors) = review.map(|r| (r.comments, r.anchors)).unwrap_or_default();
+        let mut approved = vec![];
+        // The index in `approved` of each critic comment that was kept, for its anchors.
+        let mut kept = vec![None; comments.len()];
+
+        if comments.is_empty() {
+            writeln!(self.output, "\nThe critics have no comments.")?;
+        } else {
+            writeln!(self.output, "\nReview the critics' comments:")?;
+        }
+        let total = comments.len();
+        let mut accept_all = false;
+        for (i, comment) in comments.into_iter().enumerate() {
+            if accept_all {
+                kept[i] = Some(approved.len());
+                approved.push(comment);
+                continue;
+            }
+            match self.choose(i + 1, total, &comment)? {
+                Choice::Accept => {
+                    kept[i] = Some(approved.len());
+                    approved.push(comment);
+                }
+                Choice::AcceptAll => {
+                    accept_all = true;
+                    kept[i] = Some(approved.len());
+                    approved.push(comment);
+                }
+                Choice::Reject => {}
+                Choice::Edit => {
+                    let edited = self.prompt("  Replacement comment: ")?;
+                    if !edited.is_empty() {
+                        kept[i] = Some(approved.len());
+                        approved.push(format!("{}{}", HUMAN_PREFIX, edited));
+                    }
+                }
+            }
+        }
+
+        writeln!(
+            self.output,
+            "\nAdd your own comments, one per line. Enter an empty line to finish."
+        )?;
+        loop {
+            let comment = self.prompt("> ")?;
+            if comment.is_empty() {
+                break;
+            }
+            approved.push(format!("{}{}", HUMAN_PREFIX, comment));
+        }
+
+        if approved.is_empty() {
+            writeln!(self.output, "No comments to fix. Skipping the fixer.")?;
+            return Ok(None);
+        }
+        Ok(Some(ReviewNeeded {
+            review_type: ReviewType::CodeReview,
+            comments: approved,
+            anchors: anchors
+                .into_iter()
+                .filter_map(|anchor| {
+                    let comment = kept.get(anchor.comment).copied().flatten()?;
+                    Some(Anchor { comment, ..anchor })
+                })
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn critic_review(comments: &[&str]) -> Option<ReviewNeeded> {
+        Some(ReviewNeeded::new(
+            ReviewType::CodeReview,
+            comments.iter().map(|c| c.to_string()).collect(),
+        ))
+    }
+
+    fn run_gate(input: &str, review: Option<ReviewNeeded>) -> Option<ReviewNeeded> {
+        let mut output = vec![];
+        let mut gate = ReviewGate::with_io(Cursor::new(input.to_string()), &mut output);
+        gate.review(review).unwrap()
+    }
+
+    #[test]
+    fn test_review_accept_reject_edit() {
+        let review = critic_review(&["first", "second", "third"]);
+        let comments = run_gate("a\nr\ne\nbetter third\nmy own\n\n", review).map(|r| r.comments);
+        assert_eq!(
+            comments,
+            Some(vec![
+                "first".to_string(),
+                "Human reviewer: better third".to_string(),
+                "Human reviewer: my own".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_review_accept_all_and_invalid_answer() {
+        let review = critic_review(&["first", "second"]);
+        let comments = run_gate("x\nA\n\n", review).map(|r| r.comments);
+        assert_eq!(
+            comments,
+            Some(vec!["first".to_string(), "second".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_review_keeps_anchors_of_approved_comments() {
+        let mut review = critic_review(&["first", "second", "third"]).unwrap();
+        review.anchors = vec![
+            Anchor {
+                comment: 0,
+                line: 4,
+            },
+            Anchor {
+                comment: 2,
+                line: 9,
+            },
+        ];
+        let approved = run_gate("r\na\ne\nbetter third\n\n", Some(review)).unwrap();
+        assert_eq!(
+            approved.anchors,
+            vec![Anchor {
+                comment: 1,
+                line: 9,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_review_reject_everything() {
+        let review = critic_review(&["first"]);
+        assert!(run_gate("r\n\n", review).is_none());
+    }
+
+    #[test]
+    fn test_review_inject_when_critics_approve() {
+        let comments = run_gate("use a BTreeMap\n\n", None).map(|r| r.comments);
+        assert_eq!(
+            comments,
+            Some(vec!["Human reviewer: use a BTreeMap".to_string()])
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use serde_json::Value;
+
+// Models in JSON mode still return JSON that's nearly valid: wrapped in a Markdown fence, with a
+// trailing comma, with the code's newlines unescaped in its string, or cut short of its closing
+// brackets. Rather than retry such a response, it's repaired by stripping the fence, dropping the
+// trailing commas, escaping the control characters in strings, and closing whatever is left open.
+// A repair that still doesn't parse gives up, leaving the original error to the caller.
+
+// The value of the text, once repaired, if the repairs make it valid JSON.
+pub fn repair(text: &str) -> Option<Value> {
+    let text = strip_fence(text);
+    let text = match text.find(['{', '[']) {
+        Some(start) => &text[start..],
+        None => text,
+    };
+    // An empty value, such as the braces of a function, has nothing that was asked for.
+    let value: Value = serde_json::from_str(&balance(text)).ok()?;
+    match &value {
+        Value::Object(map) if map.is_empty() => None,
+        Value::Array(array) if array.is_empty() => None,
+        _ => Some(value),
+    }
+}
+
+// The contents of the first fenced block, if the text has one of JSON. A block of code in another
+// language is left for --lenient-parsing.
+fn strip_fence(text: &str) -> &str {
+    let Some(start) = text.find("```") else {
+        return text;
+    };
+    let rest = &text[start + 3..];
+    let (language, rest) = rest.split_once('\n').unwrap_or((rest, ""));
+    if !matches!(language.trim(), "" | "json") {
+        return text;
+    }
+    match closing_fence(rest) {
+        Some(end) => &rest[..end],
+        None => rest,
+    }
+}
+
+// Where the fence closes: the first "```" that isn't in a string, as in a doc comment's example.
+fn closing_fence(text: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if text[i..].starts_with("```") {
+            return Some(i);
+        }
+    }
+    None
+}
+
+// Rewrite the text with the control characters in its strings escaped, without the commas before
+// closing brackets, and with the strings, arrays, and objects that are left open closed.
+fn balance(text: &str) -> String {
+    let mut repaired = String::with_capacity(text.len());
+    let mut open: Vec<char> = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                '\n' => {
+                    repaired.push_str("\\n");
+                    continue;
+                }
+                '\r' => {
+                    repaired.push_str("\\r");
+                    continue;
+                }
+                '\t' => {
+                    repaired.push_str("\\t");
+                    continue;
+                }
+                _ => {}
+            }
+            repaired.push(c);
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => open.push('}'),
+            '[' => open.push(']'),
+            '}' | ']' => {
+                if open.last() == Some(&c) {
+                    open.pop();
+                }
+                trim_trailing_comma(&mut repaired);
+            }
+            _ => {}
+        }
+        repaired.push(c);
+    }
+    if in_string {
+        if escaped {
+            repaired.pop();
+        }
+        repaired.push('"');
+    }
+    for closer in open.into_iter().rev() {
+        trim_trailing_comma(&mut repaired);
+        repaired.push(closer);
+    }
+    repaired
+}
+
+fn trim_trailing_comma(repaired: &mut String) {
+    let end = repaired.trim_end().len();
+    if repaired[..end].ends_with(',') {
+        repaired.truncate(end - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_repair() {
+        assert_eq!(
+            repair("Here it is:\n```json\n{\"code\": \"fn a() {}\"}\n```\nDone."),
+            Some(json!({"code": "fn a() {}"}))
+        );
+        assert_eq!(
+            repair(r#"{"lgtm": false, "corrections": ["a", "b",],}"#),
+            Some(json!({"lgtm": false, "corrections": ["a", "b"]}))
+        );
+        assert_eq!(
+            repair("{\"code\": \"fn a() {\n\tlet s = \\\"x\\\";\n}\"}"),
+            Some(json!({"code": "fn a() {\n\tlet s = \"x\";\n}"}))
+        );
+        // Commas and brackets in strings are left alone.
+        assert_eq!(
+            repair(r#"{"corrections": ["use [1, 2,]", "and {"#),
+            Some(json!({"corrections": ["use [1, 2,]", "and {"]}))
+        );
+        assert_eq!(
+            repair("{\"code\": \"fn a() {}\\"),
+            Some(json!({"code": "fn a() {}"}))
+        );
+        assert_eq!(
+            repair("```json\n{\"code\": \"/// ```\n/// f();\n/// ```\nfn f() {}\"}\n```"),
+            Some(json!({"code": "/// ```\n/// f();\n/// ```\nfn f() {}"}))
+        );
+        assert_eq!(repair(r#"{"code": }"#), None);
+        assert_eq!(repair("```rust\nfn a() {}\n```"), None);
+        assert_eq!(repair("no JSON here"), None);
+    }
+}
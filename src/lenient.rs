@@ -0,0 +1,108 @@
+use regex::Regex;
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+
+// Some models ignore JSON mode and answer in Markdown. With --lenient-parsing, such an answer is
+// read the way the agent would have wanted it in JSON rather than retried. What it's read as
+// depends on the agent: the first fenced block of code is a coder's or fixer's code, and a list of
+// bullets or numbered items is a critic's corrections. A critic's reply without a list is its
+// approval only if it has LGTM as a line of its own, so that prose such as "not LGTM" isn't taken
+// for one. The other agents' replies aren't read.
+
+// The kind of reply that an agent gives, which its Markdown is read as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reply {
+    Code,
+    Review,
+}
+
+fn fence() -> &'static Regex {
+    static FENCE: OnceLock<Regex> = OnceLock::new();
+    FENCE.get_or_init(|| Regex::new(r"(?s)```[A-Za-z0-9_+-]*[^\S\n]*\n(.*?)(?:```|\z)").unwrap())
+}
+
+fn list_item() -> &'static Regex {
+    static ITEM: OnceLock<Regex> = OnceLock::new();
+    ITEM.get_or_init(|| Regex::new(r"^\s*(?:[-*+]|\d+[.)])\s+(.+)$").unwrap())
+}
+
+fn verdict() -> &'static Regex {
+    static VERDICT: OnceLock<Regex> = OnceLock::new();
+    VERDICT.get_or_init(|| Regex::new(r"(?im)^\s*\**LGTM[.!]?\**\s*$").unwrap())
+}
+
+// The JSON that the Markdown stands for as the kind of reply, if it has one.
+pub fn extract(text: &str, reply: Reply) -> Option<Value> {
+    match reply {
+        Reply::Code => code(text),
+        Reply::Review => review(text),
+    }
+}
+
+// The code of the first fenced block.
+fn code(text: &str) -> Option<Value> {
+    let captures = fence().captures(text)?;
+    let code = captures[1].trim_end();
+    (!code.trim().is_empty()).then(|| json!({ "code": code }))
+}
+
+// The corrections that the text lists, or its approval.
+fn review(text: &str) -> Option<Value> {
+    let corrections: Vec<&str> = text
+        .lines()
+        .filter_map(|line| list_item().captures(line))
+        .map(|captures| captures.get(1).map_or("", |item| item.as_str().trim()))
+        .collect();
+    if !corrections.is_empty() {
+        return Some(json!({ "lgtm": false, "corrections": corrections }));
+    }
+    verdict()
+        .is_match(text)
+        .then(|| json!({ "lgtm": true, "corrections": [] }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(
+            extract(
+                "Here's the solution:\n\n```rust\nfn a() {}\n```\n\nIt adds.",
+                Reply::Code
+            ),
+            Some(json!({"code": "fn a() {}"}))
+        );
+        // A fence cut off by the token limit still has its code.
+        assert_eq!(
+            extract("```\nfn a() {\n", Reply::Code),
+            Some(json!({"code": "fn a() {"}))
+        );
+        assert_eq!(extract("- Add tests.\n\nLGTM", Reply::Code), None);
+
+        let review = "Two problems:\n\n- `add` subtracts.\n* It has no tests.\n3. Rename `x`.\n";
+        assert_eq!(
+            extract(review, Reply::Review),
+            Some(json!({
+                "lgtm": false,
+                "corrections": ["`add` subtracts.", "It has no tests.", "Rename `x`."]
+            }))
+        );
+        assert_eq!(extract("```rust\nfn a() {}\n```", Reply::Review), None);
+        for approval in ["LGTM", "The code is correct.\n\n**LGTM.**\n", "  lgtm!"] {
+            assert_eq!(
+                extract(approval, Reply::Review),
+                Some(json!({"lgtm": true, "corrections": []}))
+            );
+        }
+        for prose in [
+            "I can't say LGTM until it has tests.",
+            "Not LGTM.",
+            "lgtm, the code is correct.",
+            "I can't help with that.",
+        ] {
+            assert_eq!(extract(prose, Reply::Review), None);
+        }
+    }
+}
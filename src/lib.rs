@@ -0,0 +1,2537 @@
+use crate::critic::CriticType;
+use anchors::Anchor;
+use assert_ids::TestFixWindows;
+use bench::{BenchArgs, ProblemSpec};
+use best_candidate::BestCandidate;
+use build_service::Sandbox;
+pub use chatter_json::{OpenAIClientTrait, SharedClient};
+use chatter_json::{StreamTimeouts, TEMPERATURE};
+use chrono::Utc;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clients::ClientPool;
+use code_view::CodeView;
+use coder::{Code, CoderAgent};
+use color_eyre::Result;
+use config::Config;
+use convergence::{
+    AnyOf, ConvergencePolicy, CostCeiling, Deadline, MaxProposals, Progress, RepeatedFailures,
+    TokenCeiling,
+};
+use credentials::AuthArgs;
+use critic::{Correction, CriticAgent, Location};
+pub use errors::AiCriticError;
+pub use events::{new_run_id, JsonLinesObserver, Observer, RunEvent};
+use events::{ConsoleObserver, EventBus, Phase, ProgressLinesObserver, ProgressObserver};
+use fix::FixArgs;
+use fixer::{FixerAgent, ReviewNeeded, ReviewType};
+use futures::future::join_all;
+use github::{GitHubClient, IssueRef};
+use history::{DiffRunsArgs, HistoryArgs, ShowArgs};
+use images::ProblemImage;
+use interactive::ReviewGate;
+use mutation::MutationTester;
+use network::NetworkArgs;
+use output::Verbosity;
+use prescreen::Policy;
+use prompts::{Prompt, Prompts};
+use proposal_diff::ProposalDiff;
+use provenance::Provenance;
+use provider::{AzureArgs, GeminiArgs, Provider, ProviderKind};
+use publish::PublishArgs;
+use ranker::RankerAgent;
+use run_context::{RunContext, BUILDS_DIRNAME};
+use run_history::RunHistory;
+pub use run_state::RunState;
+use run_state::{CriticSkipping, FixReason, LoopOrder};
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use steering::Steering;
+use store::Store;
+pub use tester::Tester;
+use tester::{Failure, FailureKind, TesterAgent, TesterResult};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tui::Dashboard;
+use web::WebServer;
+use worker::WorkerArgs;
+
+mod agent;
+mod anchors;
+mod api;
+mod archive;
+mod assert_ids;
+mod backtraces;
+mod bench;
+mod best_candidate;
+mod build_service;
+mod capabilities;
+mod chatter_json;
+mod clients;
+mod code_view;
+mod code_windows;
+mod coder;
+mod complexity;
+mod config;
+mod convergence;
+mod coverage;
+mod credentials;
+mod critic;
+mod differential;
+mod dry_run;
+mod errors;
+mod events;
+mod fake;
+mod fix;
+mod fixer;
+mod gemini;
+mod github;
+mod history;
+mod images;
+mod interactive;
+mod json_repair;
+mod lenient;
+mod lints;
+mod mutation;
+mod network;
+pub mod notify;
+mod output;
+mod prescreen;
+mod preview;
+pub mod pricing;
+mod processes;
+mod progress_bar;
+mod prompts;
+mod proposal_diff;
+mod provenance;
+mod provider;
+mod publish;
+mod ranker;
+mod reasoning;
+mod repeat;
+mod request_limiter;
+mod run_context;
+mod run_history;
+mod run_state;
+mod sample_io;
+mod schema;
+mod screening;
+// The scripted client and tester back `--provider fake` and the integration tests; they aren't
+// part of the supported API.
+#[doc(hidden)]
+pub mod scripted;
+mod similarity;
+mod sse;
+pub mod stats;
+mod steering;
+mod store;
+mod tester;
+mod tokenizer;
+mod tui;
+mod validate;
+mod web;
+mod worker;
+
+// The default problem file if none is specified.
+const DEFAULT_PROBLEM_FILE: &str = "problems/coding_problem1.txt";
+// NUM_CRITICS is the number of each kind of critic that will be used.
+const DEFAULT_NUM_CRITICS: usize = 1;
+// MAX_PROPOSALS is the default maximum number of attempts to solve the coding problem.
+const MAX_PROPOSALS: usize = 20;
+// Retry a critic whose review fails this many times before skipping it.
+const CRITIC_RETRIES: usize = 1;
+const DEFAULT_OUTPUT_DIR: &str = "runs";
+// The exit code of a run cancelled with Ctrl-C, following the shell convention of 128 + SIGINT.
+pub const CANCELLED_EXIT_CODE: i32 = 130;
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Number of critics to use.
+    #[arg(short, long, default_value_t = DEFAULT_NUM_CRITICS)]
+    num_critics: usize,
+
+    /// Problem file to use.
+    #[arg(short, long, default_value_t = DEFAULT_PROBLEM_FILE.to_string())]
+    problem_file: String,
+
+    /// Solve a GitHub issue instead of the problem file, using its title, description, and
+    /// comments as the problem, e.g. https://github.com/owner/repo/issues/12.
+    #[arg(long, conflicts_with = "problem_file")]
+    github_issue: Option<String>,
+
+    /// Image that illustrates the problem, such as a diagram, sent to the coder with the problem.
+    /// May be repeated. Takes PNG, JPEG, GIF, and WebP images, and needs a model that reads images.
+    #[arg(long, value_name = "PATH")]
+    problem_image: Vec<PathBuf>,
+
+    /// GitHub token for reading issues and opening pull requests.
+    #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    github_token: Option<String>,
+
+    /// Use only a general critic.
+    #[arg(short, long, default_value_t = false)]
+    general_critic_only: bool,
+
+    /// Have each critic review the code as all three types of critic in a single request, returning
+    /// a review for each type, instead of sending one request per type. Doesn't apply to the
+    /// critics of --critics-config.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["general_critic_only", "chain_of_verification"]
+    )]
+    batch_critics: bool,
+
+    /// Leave out the critics of this type, to measure what they contribute. May be repeated.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "TYPE",
+        conflicts_with = "general_critic_only"
+    )]
+    disable_critic: Vec<CriticType>,
+
+    /// Add an Idiomatic critic, which reviews the code against the Rust API guidelines, given
+    /// clippy's pedantic lints of it, so that the final code is held to what a reviewer would merge
+    /// rather than only to passing its tests. Needs clippy-driver.
+    #[arg(long, default_value_t = false)]
+    idiomatic: bool,
+
+    /// Have the coder check its solution against the problem and fix obvious omissions before the
+    /// critics review it.
+    #[arg(long, default_value_t = false)]
+    self_review: bool,
+
+    /// Directory of prompt templates that replace the built-in prompts of the same file names, such
+    /// as critic_base.txt or coder.txt. See the prompts directory for the defaults.
+    #[arg(long)]
+    prompts_dir: Option<PathBuf>,
+
+    /// TOML file listing the critics, each with its own provider and model, and where else to
+    /// store the runs' artifacts. Its critics replace those chosen by --num-critics and
+    /// --general-critic-only.
+    #[arg(long, visible_alias = "config")]
+    critics_config: Option<PathBuf>,
+
+    /// Have the correctness critics derive examples from the problem and trace the code on them,
+    /// passing the traced examples to the fixer as evidence.
+    #[arg(long, default_value_t = false)]
+    chain_of_verification: bool,
+
+    /// Model to screen each review with before the usual critics, such as gpt-4o-mini. One critic
+    /// of each type reviews the code with it, and the usual critics confirm their review only if
+    /// they reject the code or it's spot-checked.
+    #[arg(long)]
+    screen_model: Option<String>,
+
+    /// Probability (0.0 to 1.0) that the usual critics confirm a review that the screening
+    /// critics approved.
+    #[arg(long, default_value_t = 0.0, requires = "screen_model")]
+    confirm_probability: f64,
+
+    /// Number of reviews that each critic samples from one request, with the API's `n` parameter.
+    /// Each counts as the vote of an independent critic, such as "Design Critic 1 (sample 2)".
+    /// Gemini returns only one.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    critic_samples: u8,
+
+    /// Minimum number of critics that must complete each review. A critic that fails is retried
+    /// once, then skipped if at least this many critics, and at least one, succeeded. Defaults to
+    /// all of them.
+    #[arg(long)]
+    min_critics: Option<usize>,
+
+    /// Run mutation testing on the accepted solution to score the quality of its tests.
+    #[arg(long, default_value_t = false)]
+    mutation_testing: bool,
+
+    /// Minimum mutation score (0.0 to 1.0). If unmet, the fixer gets one round to strengthen the
+    /// tests. Implies --mutation-testing.
+    #[arg(long)]
+    min_mutation_score: Option<f64>,
+
+    /// Have the critics review code longer than this many tokens in windows of whole functions and
+    /// modules, each up to about this long, in parallel, instead of all at once.
+    #[arg(long)]
+    critic_window_tokens: Option<usize>,
+
+    /// Fix a failed test by sending the fixer only this many lines on either side of the failed
+    /// assertion, located by its assert_id, instead of the whole file. If the assertion fails
+    /// again, the fixer gets the whole file.
+    #[arg(long)]
+    test_fix_window: Option<usize>,
+
+    /// Seed for the generated code's random numbers, given to its tests and programs in the
+    /// AI_CRITICS_TEST_SEED environment variable.
+    #[arg(long)]
+    test_seed: Option<u64>,
+
+    /// Kill a proposal's tests or program once it prints more than this many bytes to stdout or
+    /// stderr, and report the output up to there.
+    #[arg(long, default_value_t = build_service::DEFAULT_MAX_OUTPUT)]
+    max_test_output: usize,
+
+    /// Kill a proposal's tests once they run longer than this, as "90s" or "2m", and report a
+    /// timeout.
+    #[arg(long, value_parser = convergence::parse_duration, default_value = "60s")]
+    test_timeout: Duration,
+
+    /// Run each proposal's tests this many times. Tests whose results differ between the runs are
+    /// reported as flaky, and the fixer is asked to make them deterministic.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    test_runs: u8,
+
+    /// Have this many fixers correct each proposal in parallel and keep the candidate that does
+    /// best in the tests. A ranker breaks ties by comparing the tied candidates two at a time.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    fix_candidates: u8,
+
+    /// The order of each proposal's checks. `review-first` has the critics review it before it's
+    /// tested. `test-first` compiles and tests it first, calling the critics only once it passes,
+    /// to check it against the problem, or after repeated compile failures. `parallel` tests it
+    /// while the critics review it, and has the fixer address both at once.
+    #[arg(long, value_enum, default_value_t = LoopOrder::ReviewFirst)]
+    loop_order: LoopOrder,
+
+    /// Once the critics have unanimously approved this many reviews in a row, send proposals that
+    /// were fixed only for failed tests straight to the tester, skipping the critics' review.
+    #[arg(long)]
+    skip_critics_after_approval: Option<usize>,
+
+    /// Stop after this many proposals if the tests still fail.
+    #[arg(long, default_value_t = MAX_PROPOSALS)]
+    max_proposals: usize,
+
+    /// Stop early once this many consecutive proposals fail their tests with the same output.
+    #[arg(long)]
+    max_repeated_failures: Option<usize>,
+
+    /// Stop once the estimated cost of the run's requests reaches this many dollars, e.g. 2.
+    /// Models without a listed price count as free, with a warning.
+    #[arg(long)]
+    max_cost: Option<f64>,
+
+    /// Stop once the run's requests have used this many prompt and completion tokens.
+    #[arg(long)]
+    max_tokens: Option<u64>,
+
+    /// Stop once the run has taken this long, e.g. 90s, 10m, or 1h. The proposal under way is
+    /// finished first.
+    #[arg(long, value_parser = convergence::parse_duration)]
+    deadline: Option<Duration>,
+
+    /// Measure the line and branch coverage of the accepted solution's tests.
+    #[arg(long, default_value_t = false)]
+    coverage: bool,
+
+    /// Minimum line coverage (0.0 to 1.0). If unmet, the fixer gets one round to add tests for the
+    /// uncovered lines. Implies --coverage.
+    #[arg(long)]
+    min_coverage: Option<f64>,
+
+    /// Where to run the generated code. `wasm` compiles it for wasm32-wasip1 and runs it under
+    /// wasmtime without filesystem or network access. Coverage runs natively, so it's unavailable.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Sandbox::None,
+        conflicts_with_all = ["coverage", "min_coverage"]
+    )]
+    sandbox: Sandbox,
+
+    /// Allow the generated code to run other programs, such as with std::process::Command.
+    /// Otherwise code that does is sent back to the fixer without being run.
+    #[arg(long, default_value_t = false)]
+    allow_process: bool,
+
+    /// Allow the generated code to open network connections.
+    #[arg(long, default_value_t = false)]
+    allow_net: bool,
+
+    /// Allow the generated code to use `unsafe` and to silence the compiler's warnings with
+    /// `#![allow(...)]` attributes.
+    #[arg(long, default_value_t = false)]
+    allow_unsafe: bool,
+
+    /// Review the critics' comments before the fixer runs, accepting, rejecting, or editing each
+    /// one and adding your own.
+    #[arg(long, default_value_t = false, conflicts_with = "tui")]
+    interactive: bool,
+
+    /// Show a live preview of the code below the progress bar while the coder or fixer writes it.
+    #[arg(long, default_value_t = false)]
+    preview: bool,
+
+    /// Show a full-screen dashboard instead of scrolling output. Falls back to plain output when
+    /// stdout is not a terminal.
+    #[arg(long, default_value_t = false)]
+    tui: bool,
+
+    /// Serve a web dashboard that streams the run's events, e.g. --serve 127.0.0.1:8080.
+    #[arg(long)]
+    serve: Option<SocketAddr>,
+
+    /// Write the run's events to this file as JSON lines.
+    #[arg(long)]
+    events_log: Option<PathBuf>,
+
+    /// Directory for the runs' artifacts. Each run writes its transcript, the code of each proposal,
+    /// the accepted code, and its result to <OUTPUT_DIR>/<RUN_ID>/.
+    #[arg(long, default_value = DEFAULT_OUTPUT_DIR)]
+    output_dir: PathBuf,
+
+    /// Copy each build that the tester compiles and tests into <OUTPUT_DIR>/<RUN_ID>/builds/, with
+    /// its source, executables, and the compiler's output, rather than deleting it.
+    #[arg(long)]
+    keep_artifacts: bool,
+
+    /// URL to post a summary of the run to when it finishes: its problem, outcome, proposals, cost,
+    /// and artifacts. Slack's and Discord's incoming webhooks show the summary as a message. With
+    /// --runs, the batch posts once it finishes and whenever one of its runs diverges.
+    #[arg(long)]
+    notify_webhook: Option<String>,
+
+    /// ID of the run, which names its artifact directory. Defaults to the start time and process ID.
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Format of the output on stdout. `json` writes each prompt, response, retry, and test result
+    /// as a JSON line labelled with the run ID and iteration, for analysis across many runs.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Print only the run's result and errors, without its progress.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print each prompt sent to the model and each response in full.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// The service hosting the models. `azure` uses an Azure OpenAI deployment and `gemini` uses
+    /// Google's Gemini models. `fake` answers offline with canned responses, needing no API key.
+    #[arg(long, value_enum, default_value_t = ProviderKind::Openai)]
+    provider: ProviderKind,
+
+    /// Model to use instead of the provider's default. For Azure, the deployment.
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Sampling temperature of the agents' requests.
+    #[arg(long, default_value_t = TEMPERATURE)]
+    temperature: f32,
+
+    /// Temperatures of the critics, e.g. 0.3,0.7,1.0, so that critics of the same type don't give
+    /// near-identical reviews. The Nth critic of each type uses the Nth value, cycling through the
+    /// list. Defaults to --temperature for every critic.
+    #[arg(long, value_delimiter = ',')]
+    critic_temperatures: Vec<f32>,
+
+    /// Nucleus sampling cutoffs (top_p) of the critics, e.g. 0.8,0.95,1.0, assigned like
+    /// --critic-temperatures. Defaults to the provider's.
+    #[arg(long, value_delimiter = ',')]
+    critic_top_p: Vec<f32>,
+
+    /// Ask the provider to sample deterministically with this seed, on a best-effort basis. With
+    /// --runs, the runs are seeded with consecutive seeds starting at this one.
+    #[arg(long)]
+    seed: Option<i64>,
+
+    /// Retry a request whose stream sends no chunk for this long, e.g. 30s.
+    #[arg(long, value_parser = convergence::parse_duration, default_value = "30s")]
+    chunk_timeout: Duration,
+
+    /// Retry a request whose whole response takes longer than this, e.g. 5m.
+    #[arg(long, value_parser = convergence::parse_duration, default_value = "5m")]
+    request_timeout: Duration,
+
+    /// Retry a request whose stream slows to fewer than this many chunks per second over 30
+    /// seconds, counted from its first chunk. 0 turns the check off.
+    #[arg(long, default_value_t = 1.0)]
+    min_chunk_rate: f64,
+
+    /// Accept responses in Markdown from models that ignore JSON mode, taking the first fenced
+    /// block as the code and a list as a critic's corrections, instead of retrying them.
+    #[arg(long, default_value_t = false)]
+    lenient_parsing: bool,
+
+    /// Let a reasoning model, such as o3-mini, reason in up to this many tokens, on top of the
+    /// limit on its response.
+    #[arg(long, default_value_t = capabilities::DEFAULT_REASONING_TOKENS)]
+    reasoning_tokens: u32,
+
+    /// Solve the problem this many times and report the statistics of the runs, such as the solve
+    /// rate and the mean number of proposals, to measure how much they vary.
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["interactive", "tui", "serve", "events_log"]
+    )]
+    runs: u32,
+
+    /// Keep solving the problem after the first success, each attempt a run with a fresh seed,
+    /// until this many distinct solutions pass, and write them to the runs' directory. Gives up
+    /// after 3 attempts per solution. Exits with the number of distinct solutions. Can't be used
+    /// with --out or --git, as each solution would be published.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["runs", "interactive", "tui", "serve", "events_log", "out", "git"]
+    )]
+    collect_k: Option<u32>,
+
+    /// Print the calls that a run of the problem file would make and their estimated cost, from
+    /// the fewest to the most that --max-proposals allows, without sending any requests.
+    #[arg(long, default_value_t = false, conflicts_with = "github_issue")]
+    dry_run: bool,
+
+    #[command(flatten)]
+    azure: AzureArgs,
+
+    #[command(flatten)]
+    gemini: GeminiArgs,
+
+    #[command(flatten)]
+    network: NetworkArgs,
+
+    #[command(flatten)]
+    publish: PublishArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Solve a benchmark of HumanEval- or MBPP-style problems and report pass@k against their
+    /// hidden tests. The top-level options, such as --num-critics, configure each run.
+    Bench(BenchArgs),
+    /// Fix a file of an existing Rust project instead of solving a problem from scratch. The
+    /// project's `cargo test` is the tester, and the critics review the file against the goal.
+    Fix(FixArgs),
+    /// List the past runs in the output directory with their status, proposals, cost, and model.
+    History(HistoryArgs),
+    /// Print a past run's outcome, the timeline of its reviews, fixes, and tests, and its final
+    /// code.
+    Show(ShowArgs),
+    /// Compare two past runs: their outcomes, their proposals side by side, the corrections of each
+    /// type of critic, and a diff of their final code.
+    DiffRuns(DiffRunsArgs),
+    /// Solve problems submitted over HTTP or dropped into a watched directory, several at a time,
+    /// until stopped. Each problem's artifacts are kept like a run's.
+    Worker(WorkerArgs),
+    /// Store the providers' API keys, so that they needn't be set in the environment.
+    Auth(AuthArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+fn setup() -> Result<Args> {
+    pretty_env_logger::init();
+    backtraces::setup_color_eyre()?;
+
+    let args = Args::parse();
+    if args.runs > 1 && args.command.is_some() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--runs solves a problem file or issue repeatedly, so it can't be used with a \
+                 subcommand",
+            )
+            .exit();
+    }
+    if args.sandbox != Sandbox::None && matches!(args.command, Some(Command::Fix(_))) {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--sandbox isn't supported by fix, which runs the project's own `cargo test`",
+            )
+            .exit();
+    }
+    output::set_verbosity(match (args.quiet, args.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (_, true) => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    });
+    Ok(args)
+}
+
+// The provider that the args choose, or exit with what's missing from its configuration.
+fn provider_or_exit(args: &Args) -> Provider {
+    match create_provider(args) {
+        Ok(provider) => provider,
+        Err(e) => {
+            output::print(
+                Verbosity::Quiet,
+                &format!("Please configure the {:?} provider: {}.", args.provider, e),
+            );
+            exit(1);
+        }
+    }
+}
+
+// Create the provider chosen by the args, with their model, temperature, and seed.
+fn create_provider(args: &Args) -> Result<Provider> {
+    let provider = Provider::new(args.provider, &args.azure, &args.gemini)?;
+    let clients = ClientPool::new(http_client(args)?);
+    Ok(configure_provider(args, provider.with_clients(clients)))
+}
+
+// The HTTP client for the API requests, with the network settings of the args, or else of the
+// config.
+fn http_client(args: &Args) -> Result<reqwest::Client> {
+    let config = critics_config(args)?.and_then(|config| config.network);
+    args.network.or(&config.unwrap_or_default()).http_client()
+}
+
+fn configure_provider(args: &Args, provider: Provider) -> Provider {
+    let provider = match &args.model {
+        Some(model) => provider.with_model(model),
+        None => provider,
+    };
+    provider
+        .with_temperature(args.temperature)
+        .with_seed(args.seed)
+        .with_timeouts(stream_timeouts(args))
+        .with_lenient_parsing(args.lenient_parsing)
+        .with_reasoning_tokens(args.reasoning_tokens)
+}
+
+fn stream_timeouts(args: &Args) -> StreamTimeouts {
+    StreamTimeouts {
+        chunk: args.chunk_timeout,
+        request: args.request_timeout,
+        min_rate: args.min_chunk_rate,
+    }
+}
+
+// The APIs that the args allow the generated code to use.
+fn code_policy(args: &Args) -> Policy {
+    Policy {
+        allow_process: args.allow_process,
+        allow_net: args.allow_net,
+        allow_unsafe: args.allow_unsafe,
+    }
+}
+
+// The policies that stop a run that isn't converging: the limit on proposals and whichever others
+// the args set. The first to trigger stops the run.
+fn convergence_policy(args: &Args) -> AnyOf {
+    let mut policies: Vec<Box<dyn ConvergencePolicy>> =
+        vec![Box::new(MaxProposals(args.max_proposals))];
+    if let Some(n) = args.max_repeated_failures {
+        policies.push(Box::new(RepeatedFailures(n)));
+    }
+    if let Some(dollars) = args.max_cost {
+        policies.push(Box::new(CostCeiling(dollars)));
+    }
+    if let Some(tokens) = args.max_tokens {
+        policies.push(Box::new(TokenCeiling(tokens)));
+    }
+    if let Some(duration) = args.deadline {
+        policies.push(Box::new(Deadline(duration)));
+    }
+    AnyOf(policies)
+}
+
+// The models the run's requests may go to: the provider's, the screening model, and those of the
+// critics in the config.
+fn run_models(
+    args: &Args,
+    provider: &Provider,
+    config: Option<&Config>,
+) -> Result<BTreeSet<String>> {
+    let mut models = BTreeSet::from([provider.model().to_string()]);
+    models.extend(args.screen_model.clone());
+    for c in config.map_or(&[][..], |config| &config.critics) {
+        let model = match &c.model {
+            Some(model) => model.clone(),
+            None => Provider::new(c.provider, &args.azure, &args.gemini)?
+                .model()
+                .to_string(),
+        };
+        models.insert(model);
+    }
+    Ok(models)
+}
+
+// Warn of the models without a listed price, whose requests --max-cost counts as free.
+fn warn_unpriced(models: &BTreeSet<String>) {
+    for model in models.iter().filter(|model| !pricing::is_priced(model)) {
+        eprintln!(
+            "Warning: {} has no listed price, so --max-cost counts its requests as free",
+            model
+        );
+    }
+}
+
+// Check the policy before the next proposal, returning why the run should stop, if it should. A
+// run that's stopped reports its best proposal so far, which is written to the run's directory.
+fn check_convergence(
+    bus: &EventBus,
+    policy: &AnyOf,
+    progress: &mut Progress,
+    best: &BestCandidate,
+) -> Option<AiCriticError> {
+    progress.spend = bus.spend();
+    let stop = policy.check(progress)?;
+    if let Some(event) = best.event() {
+        bus.emit(event);
+    }
+    Some(stop)
+}
+
+// Return the path of the file with the given filename in the project root.
+fn project_path(filename: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(filename)
+}
+
+// Read the file at the given path, ignoring lines starting with '#'.
+fn read_file(full_path: &Path) -> Result<String> {
+    let file = match File::open(full_path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Error opening file '{}': {}", full_path.display(), e);
+            return Err(e.into());
+        }
+    };
+    let reader = BufReader::new(file);
+
+    let mut contents = String::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.starts_with('#') {
+            contents.push_str(&line);
+            contents.push('\n'); // Preserve line breaks.
+        }
+    }
+    Ok(contents)
+}
+
+fn read_coding_problem(bus: &EventBus, filename: &str) -> Result<String> {
+    let full_path = project_path(filename);
+    let goal = read_file(&full_path)?;
+    bus.emit(RunEvent::ProblemLoaded {
+        path: full_path.display().to_string(),
+        problem: goal.clone(),
+    });
+    Ok(goal)
+}
+
+// Create the observers that present the run's events. JSON output or the terminal dashboard
+// replace the console output and progress bars, while the web dashboard and the JSON-lines log file
+// are added alongside them.
+async fn create_observers(
+    args: &Args,
+    run_id: &str,
+    steering: &Steering,
+) -> Result<Vec<Box<dyn Observer>>> {
+    let mut observers: Vec<Box<dyn Observer>> = vec![];
+    let dashboard = if args.tui {
+        Dashboard::start(steering.clone())?
+    } else {
+        None
+    };
+    match (args.log_format, dashboard) {
+        (LogFormat::Json, _) => observers.push(Box::new(JsonLinesObserver::stdout(run_id))),
+        (LogFormat::Text, Some(dashboard)) => observers.push(Box::new(dashboard)),
+        (LogFormat::Text, None) => {
+            observers.push(Box::new(ConsoleObserver::new(CodeView::for_terminal())));
+            match (output::shows(Verbosity::Normal), io::stdout().is_terminal()) {
+                (false, _) => {}
+                (true, true) => observers.push(Box::new(ProgressObserver::new(args.preview))),
+                (true, false) => observers.push(Box::new(ProgressLinesObserver::default())),
+            }
+        }
+    }
+    if let Some(addr) = args.serve {
+        observers.push(Box::new(WebServer::start(addr).await?));
+    }
+    if let Some(path) = &args.events_log {
+        observers.push(Box::new(JsonLinesObserver::create(path, run_id)?));
+    }
+    Ok(observers)
+}
+
+// Have the AI Coder write a solution to the given coding problem, then check it if requested.
+async fn ai_write_code(
+    bus: &EventBus,
+    provider: &Provider,
+    prompts: &Prompts,
+    goal: &str,
+    images: &[ProblemImage],
+    self_review: bool,
+) -> Result<Code> {
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::Coding,
+    });
+    let coder1 = CoderAgent::new(1, provider, prompts)?;
+    let progress = bus.agent(&coder1.name);
+    progress.start();
+    let code = coder1.write(&progress, goal, images).await;
+    progress.finish();
+    if !self_review {
+        return code;
+    }
+    let code = code?;
+
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::SelfReviewing,
+    });
+    progress.start();
+    let reviewed = coder1.self_review(&progress, goal, images, &code).await;
+    progress.finish();
+    let reviewed = reviewed?;
+    if reviewed.code != code.code {
+        bus.emit(RunEvent::Notice {
+            message: "The coder revised its solution.".to_string(),
+        });
+    }
+    Ok(reviewed)
+}
+
+// A critic's task returns the critic along with its reviews so that the critic can be retried.
+type CriticTask = JoinHandle<(CriticAgent, Result<Vec<Correction>>)>;
+
+// The critics' tasks, with the critics' names. They are aborted if dropped before they finish, for
+// example when the run is cancelled, which also drops their API streams.
+struct CriticTasks(Vec<(String, CriticTask)>);
+
+impl Drop for CriticTasks {
+    fn drop(&mut self) {
+        for (_, task) in self.0.iter() {
+            task.abort();
+        }
+    }
+}
+
+// A message for the critics to review: the whole code, or one window of it, with the window's
+// lines and its full label.
+#[derive(Clone)]
+struct ReviewPart {
+    window: Option<(String, String)>,
+    msg: String,
+}
+
+// The messages for the critics to review: one of the whole code, or if the args set a window size
+// and the code is over it, one for each window.
+fn review_parts(
+    bus: &EventBus,
+    args: &Args,
+    prompts: &Prompts,
+    problem: &str,
+    code: &Code,
+    tester_output: Option<&str>,
+) -> Vec<ReviewPart> {
+    let windows = args
+        .critic_window_tokens
+        .map(|max_tokens| code_windows::split(&code.code, max_tokens))
+        .unwrap_or_default();
+    if windows.len() <= 1 {
+        return vec![ReviewPart {
+            window: None,
+            msg: critic::review_message(problem, &anchors::numbered(&code.code, 1), tester_output),
+        }];
+    }
+    bus.emit(RunEvent::Notice {
+        message: format!(
+            "The code is over {} tokens, so the critics review it in {} windows.",
+            args.critic_window_tokens.unwrap_or_default(),
+            windows.len()
+        ),
+    });
+    windows
+        .iter()
+        .map(|window| ReviewPart {
+            window: Some((window.lines_label(), window.label())),
+            msg: critic::window_message(prompts, problem, &code.code, window, tester_output),
+        })
+        .collect()
+}
+
+// Spawn the critics' API calls as parallel tasks. Return the tasks so that they can be joined
+// later. A critic reviews the windows of the code in parallel, merging its reviews of them, and a
+// batched critic's reviews are split into those of the critics it stands for. Each review reports
+// its progress on the event bus.
+fn spawn_critics(bus: &EventBus, critics: Vec<CriticAgent>, parts: &[ReviewPart]) -> CriticTasks {
+    let mut tasks = vec![];
+    for c in critics {
+        let reviews: Vec<_> = parts
+            .iter()
+            .map(|part| {
+                let progress = match &part.window {
+                    Some((lines, _)) => bus.agent(&format!("{} ({})", c.name, lines)),
+                    None => bus.agent(&c.name),
+                };
+                progress.start();
+                (progress, part.clone())
+            })
+            .collect();
+        let labels: Option<Vec<String>> = parts
+            .iter()
+            .map(|part| part.window.as_ref().map(|(_, label)| label.clone()))
+            .collect();
+        let name = c.name.clone();
+        let task = tokio::task::spawn(async move {
+            let results = join_all(reviews.iter().map(|(progress, part)| async {
+                let corrections = c.reviews(progress, &part.msg).await;
+                progress.finish();
+                corrections
+            }))
+            .await;
+            let corrections = results
+                .into_iter()
+                .collect::<Result<Vec<Vec<Correction>>>>()
+                .map(|mut corrections| match labels {
+                    Some(labels) => critic::merge_window_reviews(labels, corrections),
+                    None => corrections.remove(0),
+                });
+            (c, corrections)
+        });
+        tasks.push((name, task));
+    }
+    CriticTasks(tasks)
+}
+
+// Run the critics' reviews in parallel. A critic that fails is retried up to CRITIC_RETRIES times,
+// without rerunning the critics that succeeded. Critics that still fail are reported and skipped, as
+// long as at least `min_critics` of them succeeded, and always at least one, since a review that no
+// critic completed would read as their approval.
+async fn run_critics(
+    bus: &EventBus,
+    critics: Vec<CriticAgent>,
+    parts: &[ReviewPart],
+    min_critics: usize,
+) -> Result<Vec<Correction>> {
+    let mut corrections = vec![];
+    let mut succeeded = 0;
+    let mut pending = critics;
+    for attempt in 0..=CRITIC_RETRIES {
+        if pending.is_empty() {
+            break;
+        }
+        if attempt > 0 {
+            bus.emit(RunEvent::Notice {
+                message: format!("Retrying {} failed critic(s).", pending.len()),
+            });
+        }
+        let mut tasks = spawn_critics(bus, pending, parts);
+        let results = join_all(tasks.0.iter_mut().map(|(_, task)| task)).await;
+        pending = vec![];
+        for ((name, _), result) in tasks.0.iter().zip(results) {
+            let error = match result {
+                Ok((_, Ok(reviews))) => {
+                    corrections.extend(reviews);
+                    succeeded += 1;
+                    continue;
+                }
+                Ok((critic, Err(e))) => {
+                    pending.push(critic);
+                    e.to_string()
+                }
+                // The critic panicked, so it can't be retried.
+                Err(e) => e.to_string(),
+            };
+            bus.emit(RunEvent::CriticFailed {
+                critic: name.clone(),
+                error,
+            });
+        }
+    }
+
+    if succeeded < min_critics.max(1) {
+        return Err(AiCriticError::TooFewCritics {
+            succeeded,
+            required: min_critics.max(1),
+        }
+        .into());
+    }
+    Ok(corrections)
+}
+
+// Have the AI Critics review the code, along with the tester's output for the previous proposal, if
+// any. Return ReviewNeeded with their comments or None if all of them agree that the code is correct.
+#[allow(clippy::too_many_arguments)]
+async fn ai_review_code(
+    bus: &EventBus,
+    provider: &Provider,
+    prompts: &Prompts,
+    args: &Args,
+    config: Option<&Config>,
+    problem: &str,
+    code: &Code,
+    tester_output: Option<&str>,
+) -> Result<Option<ReviewNeeded>> {
+    let critics = build_critics(provider, prompts, args, config)?;
+
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::Reviewing,
+    });
+
+    let lints = match critics.iter().any(CriticAgent::wants_lints) {
+        true => lint_code(bus, &code.code).await,
+        false => None,
+    };
+    let critics = give_lints(critics, lints.as_deref());
+
+    let parts = review_parts(bus, args, prompts, problem, code, tester_output);
+    let corrections = match &args.screen_model {
+        Some(model) => {
+            screened_review(
+                bus,
+                provider,
+                prompts,
+                args,
+                model,
+                critics,
+                &parts,
+                code,
+                lints.as_deref(),
+            )
+            .await?
+        }
+        None => {
+            let min_critics = args.min_critics.unwrap_or(critics.len()).min(critics.len());
+            run_critics(bus, critics, &parts, min_critics).await?
+        }
+    };
+
+    for c in corrections.iter() {
+        bus.emit(RunEvent::CriticFinished {
+            critic: c.name.clone(),
+            lgtm: c.lgtm,
+            corrections: c.corrections.clone(),
+            evidence: c.evidence.clone(),
+        });
+    }
+
+    if corrections.iter().all(|item| item.lgtm) {
+        bus.emit(RunEvent::CriticsAgreed);
+        return Ok(None);
+    }
+
+    // For the Corrections that say the code is incorrect, collect the review comments, deduping
+    // them. Note that comments from GPT are often the same idea but using different words, so this
+    // deduplication only removes the less frequent literal duplicates. The locations that the
+    // critics gave are checked against the code, and those that check out anchor their comments to
+    // lines of it. The examples that the rejecting critics traced go along with the comments so
+    // that the fixer can see where the code goes wrong.
+    let mut comments: Vec<String> = vec![];
+    let mut anchors: Vec<Anchor> = vec![];
+    let mut unresolved = 0;
+    let add = |comments: &mut Vec<String>, comment: &String| -> usize {
+        comments
+            .iter()
+            .position(|c| c == comment)
+            .unwrap_or_else(|| {
+                comments.push(comment.clone());
+                comments.len() - 1
+            })
+    };
+    for cs in corrections.iter().filter(|cs| !cs.lgtm) {
+        for (i, correction) in cs.corrections.iter().enumerate() {
+            let comment = add(&mut comments, correction);
+            let Some(location) = cs.locations.get(i) else {
+                continue;
+            };
+            match anchors::resolve(&code.code, location) {
+                Some(line) if !anchors.contains(&Anchor { comment, line }) => {
+                    anchors.push(Anchor { comment, line })
+                }
+                Some(_) => {}
+                None if *location != Location::default() => unresolved += 1,
+                None => {}
+            }
+        }
+        for example in cs.evidence.iter() {
+            add(
+                &mut comments,
+                &format!("Example traced through the code: {}", example),
+            );
+        }
+    }
+    if unresolved > 0 {
+        bus.emit(RunEvent::Notice {
+            message: format!(
+                "Ignored {} correction locations that don't match the code.",
+                unresolved
+            ),
+        });
+    }
+
+    Ok(Some(ReviewNeeded {
+        anchors,
+        ..ReviewNeeded::new(ReviewType::CodeReview, comments)
+    }))
+}
+
+// Review the code with one critic of each type using the cheap screening model, and confirm their
+// review with the usual critics if they reject the code, any of them fails, or it's spot-checked.
+// The screening critics are of the types that the usual critics stand for, so that the screen covers
+// the configured critics and those batched too.
+#[allow(clippy::too_many_arguments)]
+async fn screened_review(
+    bus: &EventBus,
+    provider: &Provider,
+    prompts: &Prompts,
+    args: &Args,
+    screen_model: &str,
+    critics: Vec<CriticAgent>,
+    parts: &[ReviewPart],
+    code: &Code,
+    lints: Option<&str>,
+) -> Result<Vec<Correction>> {
+    let screen_provider = provider.clone().with_model(screen_model);
+    let types = active_critic_types(&critics);
+    let mut screen_critics = vec![];
+    for critic_type in types.iter() {
+        let mut critic = CriticAgent::new(*critic_type, 1, &screen_provider, prompts)?;
+        critic.name = format!("{} (screen)", critic.name);
+        screen_critics.push(critic);
+    }
+    let screen_critics = give_lints(screen_critics, lints);
+    // If every screening critic fails, as when the screening model is down, no type is screened.
+    let screen = match run_typed_critics(bus, screen_critics, parts, 1).await {
+        Err(e) if matches!(e.downcast_ref(), Some(AiCriticError::TooFewCritics { .. })) => vec![],
+        screen => screen?,
+    };
+    let flagged = screen.iter().filter(|(_, c)| !c.lgtm).count();
+    // A type whose screening critic failed hasn't been reviewed, so it can't count as approved.
+    let unscreened = types
+        .iter()
+        .filter(|critic_type| !screen.iter().any(|(screened, _)| screened == *critic_type))
+        .count();
+    if flagged == 0
+        && unscreened == 0
+        && !screening::spot_checked(args.confirm_probability, args.seed, &code.code)
+    {
+        return Ok(screen.into_iter().map(|(_, c)| c).collect());
+    }
+    bus.emit(RunEvent::Notice {
+        message: match (flagged, unscreened) {
+            (0, 0) => "Spot-checking the screening critics' approval.".to_string(),
+            (0, n) => format!(
+                "Confirming the review of the {} unscreened critic types.",
+                n
+            ),
+            (n, 0) => format!("Confirming the {} rejections of the screening critics.", n),
+            (n, m) => format!(
+                "Confirming the {} rejections of the screening critics and the review of the {} \
+                 unscreened critic types.",
+                n, m
+            ),
+        },
+    });
+    let min_critics = args.min_critics.unwrap_or(critics.len()).min(critics.len());
+    let confirmed = run_typed_critics(bus, critics, parts, min_critics).await?;
+    Ok(screening::merge(screen, confirmed))
+}
+
+// Clippy's pedantic lints of the code, for the Idiomatic critics. If clippy can't be run, they
+// review the code without them.
+async fn lint_code(bus: &EventBus, code: &str) -> Option<String> {
+    match lints::pedantic(code).await {
+        Ok(lints) => Some(lints),
+        Err(e) => {
+            bus.emit(RunEvent::Notice {
+                message: format!("The Idiomatic critics review without clippy's lints: {}", e),
+            });
+            None
+        }
+    }
+}
+
+fn give_lints(critics: Vec<CriticAgent>, lints: Option<&str>) -> Vec<CriticAgent> {
+    match lints {
+        Some(lints) => critics
+            .into_iter()
+            .map(|critic| critic.with_lints(lints))
+            .collect(),
+        None => critics,
+    }
+}
+
+// Run the critics, pairing each review with its critic's type.
+async fn run_typed_critics(
+    bus: &EventBus,
+    critics: Vec<CriticAgent>,
+    parts: &[ReviewPart],
+    min_critics: usize,
+) -> Result<Vec<(CriticType, Correction)>> {
+    let types: HashMap<String, CriticType> =
+        critics.iter().flat_map(CriticAgent::members).collect();
+    let corrections = run_critics(bus, critics, parts, min_critics).await?;
+    Ok(corrections
+        .into_iter()
+        .map(|c| (types[&c.name], c))
+        .collect())
+}
+
+// Create the critics of a review: those of the config if there is one, or else those chosen by the
+// args, with chain-of-verification if it's requested.
+fn build_critics(
+    provider: &Provider,
+    prompts: &Prompts,
+    args: &Args,
+    config: Option<&Config>,
+) -> Result<Vec<CriticAgent>> {
+    let critics = match config {
+        Some(config) if !config.critics.is_empty() => {
+            create_configured_critics(config, provider, args, prompts)?
+        }
+        _ => create_critics(provider, prompts, args)?,
+    };
+    if critics.is_empty() {
+        return Err(AiCriticError::MissingSetting {
+            setting: "a critic that --disable-critic leaves in".to_string(),
+        }
+        .into());
+    }
+    match args.chain_of_verification {
+        true => critics
+            .into_iter()
+            .map(|critic| critic.with_chain_of_verification(prompts))
+            .collect(),
+        false => Ok(critics),
+    }
+}
+
+// Create the set of critics, whether general or specific, based on the requested number of critics.
+// Note that if the general_critics_only flag is set, then the number of general critics is the
+// requested number of critics. Otherwise, the total number of critics is the requested number * 3
+// because there is one design, one correctness, and one syntax critic for each requested number of
+// critics.
+// With --batch-critics, there is one batched critic for each requested number of critics instead.
+fn create_critics(provider: &Provider, prompts: &Prompts, args: &Args) -> Result<Vec<CriticAgent>> {
+    let mut critics = vec![];
+    if args.batch_critics && !critic_types(args).is_empty() {
+        for i in 1..=args.num_critics {
+            let provider = critic_provider(provider.clone(), args, i);
+            critics.push(CriticAgent::batched(
+                &critic_types(args),
+                i,
+                &provider,
+                prompts,
+            )?);
+        }
+        return Ok(critics);
+    }
+    for critic_type in critic_types(args) {
+        for i in 1..=args.num_critics {
+            let provider = critic_provider(provider.clone(), args, i);
+            critics.push(CriticAgent::new(critic_type, i, &provider, prompts)?);
+        }
+    }
+    Ok(critics)
+}
+
+// The types of critic that review the code, without those that are disabled, and with an Idiomatic
+// critic if it's asked for.
+fn critic_types(args: &Args) -> Vec<CriticType> {
+    let mut types = match args.general_critic_only {
+        true => vec![CriticType::General],
+        false => [
+            CriticType::Design,
+            CriticType::Correctness,
+            CriticType::Syntax,
+        ]
+        .into_iter()
+        .filter(|critic_type| !args.disable_critic.contains(critic_type))
+        .collect(),
+    };
+    if args.idiomatic && !args.disable_critic.contains(&CriticType::Idiomatic) {
+        types.push(CriticType::Idiomatic);
+    }
+    types
+}
+
+// The types of critic that review the code, as the critics built for a review stand for them, so
+// that those left out by --disable-critic aren't counted.
+fn active_critic_types(critics: &[CriticAgent]) -> Vec<CriticType> {
+    let mut types: Vec<CriticType> = vec![];
+    for (_, critic_type) in critics.iter().flat_map(CriticAgent::members) {
+        if !types.contains(&critic_type) {
+            types.push(critic_type);
+        }
+    }
+    types
+}
+
+// The provider of the critic with the given ID, which is the critic's number among those of its
+// type, sampling with its share of --critic-temperatures and --critic-top-p, and --critic-samples
+// responses.
+fn critic_provider(provider: Provider, args: &Args, id: usize) -> Provider {
+    let provider = provider.with_samples(args.critic_samples);
+    let nth = |values: &[f32]| match values {
+        [] => None,
+        values => Some(values[(id - 1) % values.len()]),
+    };
+    let provider = match nth(&args.critic_temperatures) {
+        Some(temperature) => provider.with_temperature(temperature),
+        None => provider,
+    };
+    match nth(&args.critic_top_p) {
+        Some(top_p) => provider.with_top_p(Some(top_p)),
+        None => provider,
+    }
+}
+
+// Create the critics listed in the config, each with its own provider and model. The model is added
+// to each critic's name so that their results can be told apart. An entry's temperature and top_p
+// take precedence over those of the args.
+fn create_configured_critics(
+    config: &Config,
+    provider: &Provider,
+    args: &Args,
+    prompts: &Prompts,
+) -> Result<Vec<CriticAgent>> {
+    let mut critics: Vec<CriticAgent> = vec![];
+    for c in config.critics.iter() {
+        if args.disable_critic.contains(&c.critic_type) {
+            continue;
+        }
+        let mut entry_provider = Provider::new(c.provider, &args.azure, &args.gemini)?
+            .with_clients(provider.clients.clone())
+            .with_temperature(args.temperature)
+            .with_seed(args.seed)
+            .with_timeouts(stream_timeouts(args))
+            .with_lenient_parsing(args.lenient_parsing)
+            .with_reasoning_tokens(args.reasoning_tokens);
+        if let Some(model) = &c.model {
+            entry_provider = entry_provider.with_model(model);
+        }
+        for _ in 0..c.count {
+            let id = critics
+                .iter()
+                .filter(|critic| critic.critic_type == c.critic_type)
+                .count()
+                + 1;
+            let mut provider = critic_provider(entry_provider.clone(), args, id);
+            if let Some(temperature) = c.temperature {
+                provider = provider.with_temperature(temperature);
+            }
+            if c.top_p.is_some() {
+                provider = provider.with_top_p(c.top_p);
+            }
+            let mut critic = CriticAgent::new(c.critic_type, id, &provider, prompts)?;
+            critic.name = format!("{} ({})", critic.name, provider.model());
+            critics.push(critic);
+        }
+    }
+    if wants_idiomatic(args, config) {
+        let provider = critic_provider(provider.clone(), args, 1);
+        critics.push(CriticAgent::new(
+            CriticType::Idiomatic,
+            1,
+            &provider,
+            prompts,
+        )?);
+    }
+    Ok(critics)
+}
+
+// Whether --idiomatic adds an Idiomatic critic to the config's critics, as it does unless one is
+// listed or it's disabled.
+fn wants_idiomatic(args: &Args, config: &Config) -> bool {
+    args.idiomatic
+        && !args.disable_critic.contains(&CriticType::Idiomatic)
+        && !config
+            .critics
+            .iter()
+            .any(|c| c.critic_type == CriticType::Idiomatic)
+}
+
+// What the fixers work with, which stays the same through a run.
+struct FixContext<'a> {
+    bus: &'a EventBus,
+    provider: &'a Provider,
+    prompts: &'a Prompts,
+    tester: &'a dyn Tester,
+    // The number of fixers that correct each proposal at once.
+    candidates: usize,
+}
+
+// Have the AI Fixer agent correct the code given the critics' comments, reminding it of the issues
+// that it fixed in earlier rounds. The review is then added to the history. A test failure may be
+// fixed in a window of the code around the failed assertion. With more than one candidate, as many
+// fixers correct the code at once, and the best of their candidates is kept, along with the result
+// of testing it, so that it isn't tested again. Each fixer after the first gets the next seed, if
+// there is one, so that they don't all return the same candidate.
+async fn ai_fix_code(
+    context: &FixContext<'_>,
+    code: &Code,
+    review: ReviewNeeded,
+    history: &mut RunHistory,
+    windows: &mut TestFixWindows,
+) -> Result<(Code, Option<TesterResult>)> {
+    let FixContext {
+        bus,
+        provider,
+        prompts,
+        ..
+    } = *context;
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::Fixing,
+    });
+
+    let fixers = (1..=context.candidates.max(1))
+        .map(|id| {
+            let seed = provider.seed.map(|seed| seed + id as i64 - 1);
+            FixerAgent::new(id, &provider.clone().with_seed(seed), prompts)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let resolved = history.resolved_issues(&review);
+    history.record(&review);
+    let window = windows.next(&code.code, &review);
+    if let Some((id, window)) = &window {
+        bus.emit(RunEvent::Notice {
+            message: format!(
+                "Sending the fixer lines {}-{} around the failed assertion {}.",
+                window.start + 1,
+                window.end,
+                id
+            ),
+        });
+    }
+    let results = join_all(fixers.iter().map(|fixer| async {
+        let progress = bus.agent(&fixer.name);
+        progress.start();
+        let fixed = match &window {
+            Some((_, window)) => {
+                fixer
+                    .fix_window(&progress, &code.code, window, review.clone(), &resolved)
+                    .await
+            }
+            None => {
+                fixer
+                    .fix(&progress, &code.code, review.clone(), &resolved)
+                    .await
+            }
+        };
+        progress.finish();
+        fixed
+    }))
+    .await;
+
+    // A fixer that failed is left out, unless they all did.
+    let mut fixes = vec![];
+    let mut first_error = None;
+    for (fixer, result) in fixers.iter().zip(results) {
+        match result {
+            Ok(fixed) => fixes.push((fixer.name.clone(), fixed)),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    let (fixer, fixed, result) = match (fixes.len(), first_error) {
+        (0, Some(e)) => return Err(e),
+        (1, _) => {
+            let (fixer, fixed) = fixes.remove(0);
+            (fixer, fixed, None)
+        }
+        _ => {
+            let (fixer, fixed, result) = choose_fix(context, &review, fixes).await?;
+            (fixer, fixed, Some(result))
+        }
+    };
+    bus.emit(RunEvent::FixApplied {
+        fixer,
+        code: fixed.code.clone(),
+        similarity: similarity::similarity(&code.code, &fixed.code),
+    });
+    Ok((fixed, result))
+}
+
+// Choose among the fixers' candidates by testing them and keeping those that got furthest, from
+// failing to compile to failing tests to being too slow to passing. The ranker settles a tie. Return
+// the chosen fixer, its code, and the result of testing it.
+async fn choose_fix(
+    context: &FixContext<'_>,
+    review: &ReviewNeeded,
+    fixes: Vec<(String, Code)>,
+) -> Result<(String, Code, TesterResult)> {
+    let bus = context.bus;
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::Testing,
+    });
+    let mut tested = vec![];
+    for (fixer, fixed) in fixes {
+        let result = context.tester.compile_and_test(&fixed.code).await?;
+        let stage = match &result {
+            TesterResult::Success { .. } => 3,
+            TesterResult::Failure {
+                failure: Failure::CompileError { .. },
+                ..
+            } => 0,
+            TesterResult::Failure {
+                failure: Failure::ComplexityViolation { .. },
+                ..
+            } => 2,
+            TesterResult::Failure { .. } => 1,
+        };
+        tested.push((stage, fixer, fixed, result));
+    }
+    let furthest = tested.iter().map(|t| t.0).max().unwrap_or_default();
+    let mut finalists: Vec<(String, Code, TesterResult)> = tested
+        .into_iter()
+        .filter(|(stage, ..)| *stage == furthest)
+        .map(|(_, fixer, fixed, result)| (fixer, fixed, result))
+        .collect();
+    if finalists.len() == 1 {
+        return Ok(finalists.remove(0));
+    }
+
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::Ranking,
+    });
+    let ranker = RankerAgent::new(context.provider, context.prompts)?;
+    let progress = bus.agent(&ranker.name);
+    progress.start();
+    let winner = ranker::tournament(finalists.len(), |a, b| {
+        let (progress, ranker, finalists) = (&progress, &ranker, &finalists);
+        async move {
+            let ((name_a, code_a, _), (name_b, code_b, _)) = (&finalists[a], &finalists[b]);
+            let (first_wins, reason) = match ranker
+                .prefers_first(progress, review, &code_a.code, &code_b.code)
+                .await
+            {
+                Ok(ranking) => ranking,
+                Err(e) => (
+                    true,
+                    format!("the ranker failed, so the first was kept: {}", e),
+                ),
+            };
+            let (winner, loser) = match first_wins {
+                true => (name_a, name_b),
+                false => (name_b, name_a),
+            };
+            bus.emit(RunEvent::CandidateRanked {
+                winner: winner.clone(),
+                loser: loser.clone(),
+                reason,
+            });
+            first_wins
+        }
+    })
+    .await;
+    progress.finish();
+    Ok(finalists.swap_remove(winner))
+}
+
+// Announce a new proposal, with what changed from the previous one, if there was one.
+fn emit_proposal(bus: &EventBus, number: usize, code: &str, previous: Option<&str>) {
+    bus.emit(RunEvent::ProposalCreated {
+        number,
+        code: code.to_string(),
+    });
+    if let Some(previous) = previous {
+        bus.emit(RunEvent::ProposalDiffed {
+            diff: ProposalDiff::new(number, previous, code),
+        });
+    }
+}
+
+// Compile and test the code, unless it has been tested already, as a fixer's candidate. Return an
+// optional ReviewNeeded if the code fails to compile or fails the test.
+async fn compile_and_test(
+    bus: &EventBus,
+    tester: &dyn Tester,
+    proposal_count: usize,
+    code: &Code,
+    tested: Option<TesterResult>,
+) -> Result<Option<ReviewNeeded>> {
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::Testing,
+    });
+    let result = match tested {
+        Some(result) => result,
+        None => tester.compile_and_test(&code.code).await?,
+    };
+    Ok(report_test_result(bus, proposal_count, code, result))
+}
+
+// Report the result of testing a proposal. Return a ReviewNeeded if the code failed to compile or
+// failed the tests.
+fn report_test_result(
+    bus: &EventBus,
+    proposal_count: usize,
+    code: &Code,
+    result: TesterResult,
+) -> Option<ReviewNeeded> {
+    match result {
+        TesterResult::Success { stdout, .. } => {
+            bus.emit(RunEvent::TestPassed {
+                proposal: proposal_count,
+                code: code.code.clone(),
+                output: stdout,
+            });
+            None
+        }
+        TesterResult::Failure { failure, review } => {
+            bus.emit(RunEvent::TestFailed {
+                output: failure.output(),
+                compile_error: failure.kind() == FailureKind::CompileError,
+                failure: failure.kind(),
+            });
+            // Continue, seeing if the AI can fix the code/tests so it passes.
+            Some(review)
+        }
+    }
+}
+
+// Describe the result of compiling and testing a proposal for the critics, who review the next
+// proposal with it in review-first and parallel order and the same one in test-first order.
+fn tester_feedback(failure: Option<&ReviewNeeded>, order: LoopOrder) -> String {
+    let code = match order {
+        LoopOrder::ReviewFirst | LoopOrder::Parallel => "The previous version of the code",
+        LoopOrder::TestFirst => "The code",
+    };
+    describe_test_result(code, failure)
+}
+
+fn describe_test_result(code: &str, failure: Option<&ReviewNeeded>) -> String {
+    match failure {
+        None => format!("{} compiled and passed its tests.", code),
+        Some(review) => {
+            let heading = match review.review_type {
+                ReviewType::CompilerFix => "failed to compile:",
+                ReviewType::PolicyFix => "used APIs that the tester doesn't allow:",
+                ReviewType::FlakyTestFix => "compiled but its tests are flaky:",
+                ReviewType::ComplexityFix => "passed its tests but is too slow:",
+                _ => "compiled but failed its tests:",
+            };
+            format!("{} {}\n{}", code, heading, review.comments.join("\n"))
+        }
+    }
+}
+
+// Measure the coverage of the accepted code's tests. If the line coverage is below the minimum and
+// the tests haven't already been augmented, return a ReviewNeeded asking the fixer to add tests for
+// the uncovered lines. Coverage tooling may be missing, so failures are reported but not fatal.
+async fn coverage_test(
+    bus: &EventBus,
+    code: &Code,
+    args: &Args,
+    already_augmented: bool,
+) -> Result<Option<ReviewNeeded>> {
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::MeasuringCoverage,
+    });
+    let tester = TesterAgent::new(1)
+        .with_test_seed(args.test_seed)
+        .with_max_output(args.max_test_output)
+        .with_test_timeout(args.test_timeout);
+    let coverage = match tester.coverage(&code.code).await {
+        Ok(coverage) => coverage,
+        Err(e) => {
+            bus.emit(RunEvent::Notice {
+                message: format!("Skipping coverage: {}", e),
+            });
+            return Ok(None);
+        }
+    };
+    bus.emit(RunEvent::CoverageMeasured {
+        summary: coverage.to_string(),
+        line_fraction: coverage.line_fraction(),
+    });
+
+    match args.min_coverage {
+        Some(min) if coverage.line_fraction() < min && !already_augmented => {
+            bus.emit(RunEvent::Notice {
+                message: format!(
+                    "The line coverage is below {:.0}%. Adding tests.",
+                    min * 100.0
+                ),
+            });
+            Ok(Some(coverage.review()))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Run mutation testing against the accepted code and report the test-quality score. If the score is
+// below the minimum and the tests haven't already been strengthened, return a ReviewNeeded asking the
+// fixer to add tests that kill the surviving mutants.
+async fn mutation_test(
+    bus: &EventBus,
+    code: &Code,
+    sandbox: Sandbox,
+    test_seed: Option<u64>,
+    min_score: Option<f64>,
+    already_strengthened: bool,
+) -> Result<Option<ReviewNeeded>> {
+    bus.emit(RunEvent::PhaseStarted {
+        phase: Phase::MutationTesting,
+    });
+    let mutations = MutationTester::new()
+        .with_sandbox(sandbox)
+        .with_test_seed(test_seed)
+        .run(&code.code)
+        .await?;
+    bus.emit(RunEvent::MutationTested {
+        summary: mutations.to_string(),
+        score: mutations.score(),
+    });
+
+    match min_score {
+        Some(min) if mutations.score() < min && !already_strengthened => {
+            bus.emit(RunEvent::Notice {
+                message: format!(
+                    "The test-quality score is below {:.0}%. Strengthening the tests.",
+                    min * 100.0
+                ),
+            });
+            Ok(Some(mutations.review()))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Tracks which one-time test-improvement rounds have already been given to the fixer, so that a
+// solution whose tests can't reach the thresholds still terminates.
+#[derive(Default)]
+struct TestQualityRounds {
+    augmented: bool,
+    strengthened: bool,
+}
+
+// Evaluate the tests of code that has passed them, as requested by the args. Return a ReviewNeeded if
+// the tests should be improved before the solution is accepted.
+async fn evaluate_tests(
+    bus: &EventBus,
+    args: &Args,
+    code: &Code,
+    rounds: &mut TestQualityRounds,
+) -> Result<Option<ReviewNeeded>> {
+    if args.coverage || args.min_coverage.is_some() {
+        if let Some(review) = coverage_test(bus, code, args, rounds.augmented).await? {
+            rounds.augmented = true;
+            return Ok(Some(review));
+        }
+    }
+    if args.mutation_testing || args.min_mutation_score.is_some() {
+        if let Some(review) = mutation_test(
+            bus,
+            code,
+            args.sandbox,
+            args.test_seed,
+            args.min_mutation_score,
+            rounds.strengthened,
+        )
+        .await?
+        {
+            rounds.strengthened = true;
+            return Ok(Some(review));
+        }
+    }
+    Ok(None)
+}
+
+// Let the user approve the critics' comments if the run is interactive.
+async fn gate_review(
+    bus: &EventBus,
+    args: &Args,
+    review: Option<ReviewNeeded>,
+) -> Result<Option<ReviewNeeded>> {
+    if !args.interactive {
+        return Ok(review);
+    }
+    // Let the critics' results reach the console before prompting.
+    bus.flush().await;
+    ReviewGate::new().review(review)
+}
+
+// The critics config file given in the args, if any.
+fn critics_config(args: &Args) -> Result<Option<Config>> {
+    args.critics_config.as_deref().map(Config::load).transpose()
+}
+
+// The store that the config gives for the runs' artifacts, if any.
+fn artifact_store(args: &Args) -> Result<Option<Arc<dyn Store>>> {
+    critics_config(args)?
+        .and_then(|config| config.store)
+        .map(|config| store::create(&config))
+        .transpose()
+}
+
+// A solution that passes its own tests, with the number of proposals it took.
+struct Solution {
+    proposals: usize,
+    code: Code,
+}
+
+// The state after a proposal is accepted: done, unless its tests should be improved first.
+async fn accept(
+    bus: &EventBus,
+    args: &Args,
+    proposal: usize,
+    code: &Code,
+    rounds: &mut TestQualityRounds,
+) -> Result<RunState> {
+    Ok(match evaluate_tests(bus, args, code, rounds).await? {
+        Some(review) => RunState::Fixing {
+            proposal,
+            reason: FixReason::TestQuality,
+            review,
+        },
+        None => RunState::Done {
+            proposals: proposal,
+        },
+    })
+}
+
+// The run's prompts, with the requirements that the agents can't know from the problem statement.
+fn load_prompts(args: &Args, problem: &str) -> Result<Prompts> {
+    let mut prompts = Prompts::load(args.prompts_dir.as_deref(), problem)?;
+    if complexity::parse(problem).is_some() {
+        prompts = prompts.with_note(
+            &[Prompt::Coder],
+            &format!(
+                "The problem has a `Complexity:` constraint, which is checked by timing the \
+                 solution. Also define `fn {}(n: usize)` outside the tests, which generates an \
+                 input of size n and runs the solution on it.",
+                complexity::BENCH_FN
+            ),
+        );
+    }
+    if args.test_seed.is_some() {
+        prompts = prompts.with_note(
+            &[
+                Prompt::Coder,
+                Prompt::FixerTestFix,
+                Prompt::FixerFlakyTestFix,
+                Prompt::FixerTestFixWindow,
+            ],
+            &format!(
+                "Code that uses random numbers must seed them from the integer in the `{}` \
+                 environment variable, which the tests are run with, so that each run draws the \
+                 same numbers.",
+                build_service::TEST_SEED_VAR
+            ),
+        );
+    }
+    Ok(prompts)
+}
+
+// Main run loop: Run the AI agents to solve the problem. Use a Coder agent to produce an initial
+// solution, then in a loop run the AI critics to review the code, the fixer agent to correct it,
+// and the tester agent to test it, in the order given by the args. Repeat until it works or the
+// convergence policy stops the run. The loop is a state machine whose states are emitted as the run
+// enters them.
+async fn run_loop(
+    bus: &EventBus,
+    args: &Args,
+    provider: &Provider,
+    tester: &dyn Tester,
+    problem: &str,
+) -> Result<Solution> {
+    let config = critics_config(args)?;
+    if args.max_cost.is_some() {
+        warn_unpriced(&run_models(args, provider, config.as_ref())?);
+    }
+    let prompts = load_prompts(args, problem)?;
+    let images = images::load(&args.problem_image)?;
+    let order = args.loop_order;
+    let mut code = Code {
+        code: String::new(),
+    };
+    let mut test_quality_rounds = TestQualityRounds::default();
+    // What the tester reported for the latest proposal tested, which the critics see.
+    let mut tester_output: Option<String> = None;
+    // How the current proposal failed its tests, if it has been tested and failed.
+    let mut failure: Option<ReviewNeeded> = None;
+    // The result of testing the current proposal, if it was tested as one of the fixers' candidates
+    // and hasn't been reported yet.
+    let mut tested: Option<TesterResult> = None;
+    let fix_context = FixContext {
+        bus,
+        provider,
+        prompts: &prompts,
+        tester,
+        candidates: args.fix_candidates as usize,
+    };
+    let mut compile_failures = 0;
+    let mut skipping = CriticSkipping::new(args.skip_critics_after_approval);
+    let mut history = RunHistory::default();
+    let mut windows = TestFixWindows::new(args.test_fix_window);
+    let policy = convergence_policy(args);
+    let mut progress = Progress::start();
+    let mut best = BestCandidate::default();
+    // The code of the latest proposal, which the next one is diffed against.
+    let mut last_proposal: Option<String> = None;
+    bus.emit(RunEvent::CriticsChosen {
+        critic_types: active_critic_types(&build_critics(
+            provider,
+            &prompts,
+            args,
+            config.as_ref(),
+        )?),
+    });
+    let mut state = RunState::Coding;
+    bus.emit(RunEvent::StateEntered {
+        state: state.clone(),
+    });
+
+    loop {
+        let next = match &state {
+            RunState::Coding => {
+                code = ai_write_code(bus, provider, &prompts, problem, &images, args.self_review)
+                    .await?;
+                order.after_coding()
+            }
+            &RunState::Reviewing { proposal } => {
+                compile_failures = 0;
+                if let Some(review) = validate::review(&code.code) {
+                    bus.emit(RunEvent::Notice {
+                        message: format!(
+                            "Returning proposal {} to the fixer without review: {}",
+                            proposal,
+                            review.comments.join(" ")
+                        ),
+                    });
+                    RunState::Fixing {
+                        proposal,
+                        reason: FixReason::Validation,
+                        review,
+                    }
+                } else {
+                    let review = ai_review_code(
+                        bus,
+                        provider,
+                        &prompts,
+                        args,
+                        config.as_ref(),
+                        problem,
+                        &code,
+                        tester_output.as_deref(),
+                    );
+                    // The failure of the tests run alongside the review, if they were and failed.
+                    let mut parallel_failure = None;
+                    let review_res = match order.tests_while_reviewing() {
+                        true => {
+                            let tests =
+                                compile_and_test(bus, tester, proposal, &code, tested.take());
+                            let (review_res, test_result) = tokio::join!(review, tests);
+                            let test_result = test_result?;
+                            record_test(&mut progress, &mut best, proposal, &code, &test_result);
+                            tester_output = Some(tester_feedback(test_result.as_ref(), order));
+                            failure = test_result.clone();
+                            parallel_failure = test_result;
+                            review_res?
+                        }
+                        false => review.await?,
+                    };
+                    let review_res = gate_review(bus, args, review_res).await?;
+                    skipping.record_review(review_res.is_none());
+                    match review_res {
+                        Some(review) => RunState::Fixing {
+                            proposal,
+                            reason: FixReason::Review,
+                            review: match &parallel_failure {
+                                Some(failure) => with_test_failure(review, failure),
+                                None => review,
+                            },
+                        },
+                        None => match order.after_approval(proposal, failure.take()) {
+                            Some(next) => next,
+                            None => {
+                                accept(bus, args, proposal, &code, &mut test_quality_rounds).await?
+                            }
+                        },
+                    }
+                }
+            }
+            RunState::Fixing {
+                proposal,
+                reason,
+                review,
+            } => {
+                (code, tested) = ai_fix_code(
+                    &fix_context,
+                    &code,
+                    review.clone(),
+                    &mut history,
+                    &mut windows,
+                )
+                .await?;
+                let planned = order.after_fix(*proposal, *reason);
+                let next = skipping.after_fix(planned.clone(), review);
+                if next != planned {
+                    bus.emit(RunEvent::Notice {
+                        message: format!(
+                            "Skipping the critics' review of proposal {}: they approved {} \
+                             reviews in a row and only the tests failed.",
+                            proposal + 1,
+                            skipping.approvals()
+                        ),
+                    });
+                }
+                next
+            }
+            &RunState::Testing { proposal } => {
+                let test_result =
+                    compile_and_test(bus, tester, proposal, &code, tested.take()).await?;
+                record_test(&mut progress, &mut best, proposal, &code, &test_result);
+                tester_output = Some(tester_feedback(test_result.as_ref(), order));
+                match test_result {
+                    Some(review) => {
+                        compile_failures = match review.review_type {
+                            ReviewType::CompilerFix => compile_failures + 1,
+                            _ => 0,
+                        };
+                        failure = Some(review.clone());
+                        order.after_test_failure(proposal, review, compile_failures)
+                    }
+                    None => match order.after_test_pass(proposal) {
+                        Some(next) => next,
+                        None => {
+                            accept(bus, args, proposal, &code, &mut test_quality_rounds).await?
+                        }
+                    },
+                }
+            }
+            RunState::Done { .. } | RunState::Diverged { .. } => unreachable!(),
+        };
+        // Each proposal must be allowed by the convergence policy.
+        let (next, stop) = match next.proposal() {
+            Some(proposal) if proposal > progress.proposals => {
+                match check_convergence(bus, &policy, &mut progress, &best) {
+                    Some(stop) => (
+                        RunState::Diverged {
+                            proposals: progress.proposals,
+                            reason: stop.to_string(),
+                        },
+                        Some(stop),
+                    ),
+                    None => {
+                        progress.proposals = proposal;
+                        failure = None;
+                        emit_proposal(bus, proposal, &code.code, last_proposal.as_deref());
+                        last_proposal = Some(code.code.clone());
+                        (next, None)
+                    }
+                }
+            }
+            _ => (next, None),
+        };
+        bus.emit(RunEvent::StateEntered {
+            state: next.clone(),
+        });
+        state = next;
+        if let Some(stop) = stop {
+            return Err(stop.into());
+        }
+        if let RunState::Done { proposals } = state {
+            return Ok(Solution { proposals, code });
+        }
+    }
+}
+
+// Record how the proposal fared in its tests, for the convergence policy and the best candidate.
+fn record_test(
+    progress: &mut Progress,
+    best: &mut BestCandidate,
+    proposal: usize,
+    code: &Code,
+    test_result: &Option<ReviewNeeded>,
+) {
+    progress.record_test(test_result.as_ref());
+    if let Some(failure) = test_result {
+        best.record(proposal, &code.code, failure);
+    }
+}
+
+// The critics' review with the tests' failure of the same proposal added to its comments, so that
+// one fix addresses both.
+fn with_test_failure(review: ReviewNeeded, failure: &ReviewNeeded) -> ReviewNeeded {
+    let mut comments = review.comments;
+    comments.push(describe_test_result("The code", Some(failure)));
+    ReviewNeeded { comments, ..review }
+}
+
+// Read the GitHub issue as the problem to solve.
+async fn read_github_issue(
+    bus: &EventBus,
+    github: &GitHubClient,
+    url: &str,
+) -> Result<ProblemSpec> {
+    let issue_ref = IssueRef::parse(url)?;
+    let spec = github.issue(&issue_ref).await?.to_problem(&issue_ref);
+    bus.emit(RunEvent::ProblemLoaded {
+        path: url.to_string(),
+        problem: spec.prompt.clone(),
+    });
+    Ok(spec)
+}
+
+// The tester of the proposals for the problem, which checks the program against the problem's
+// sample runs and the args' sandbox and policy. With --keep-artifacts, it keeps its builds in the
+// run's directory, if there is one.
+async fn create_tester(args: &Args, problem: &str, run_dir: Option<&Path>) -> Result<TesterAgent> {
+    let mut tester = TesterAgent::new(1)
+        .with_build_cache()?
+        .with_samples(sample_io::parse(problem))
+        .with_sandbox(args.sandbox)
+        .with_policy(code_policy(args))
+        .with_complexity(complexity::parse(problem))
+        .with_test_runs(args.test_runs as usize)
+        .with_test_seed(args.test_seed)
+        .with_max_output(args.max_test_output)
+        .with_test_timeout(args.test_timeout);
+    if let (true, Some(run_dir)) = (args.keep_artifacts, run_dir) {
+        tester = tester.with_kept_builds(&run_dir.join(BUILDS_DIRNAME));
+    }
+    tester.check_sandbox().await?;
+    Ok(tester)
+}
+
+// Solve the problem in the problem file, or the GitHub issue if one was given, and publish the
+// solution as requested, stamped with its provenance if the config asks for it, returning the number
+// of proposals it took. The proposals are tested by the given tester, if any, or else by one created
+// for the problem, which keeps its builds in the run's directory.
+async fn solve_problem_file(
+    bus: &EventBus,
+    args: &Args,
+    provider: &Provider,
+    tester: Option<&dyn Tester>,
+    context: Option<&RunContext>,
+) -> Result<usize> {
+    // The config is read before the run, so that a bad one doesn't waste it.
+    let config = critics_config(args)?;
+    let models: Vec<String> = run_models(args, provider, config.as_ref())?
+        .into_iter()
+        .collect();
+    let github = GitHubClient::new(args.github_token.as_deref());
+    let (name, problem) = match &args.github_issue {
+        Some(url) => {
+            let spec = read_github_issue(bus, &github, url).await?;
+            (spec.id, spec.prompt)
+        }
+        None => {
+            let name = Path::new(&args.problem_file)
+                .file_stem()
+                .map_or("solution".to_string(), |s| s.to_string_lossy().to_string());
+            (name, read_coding_problem(bus, &args.problem_file)?)
+        }
+    };
+    let solution = match tester {
+        Some(tester) => run_loop(bus, args, provider, tester, &problem).await?,
+        None => {
+            let run_dir = context.map(|context| context.dir.as_path());
+            let tester = create_tester(args, &problem, run_dir).await?;
+            run_loop(bus, args, provider, &tester, &problem).await?
+        }
+    };
+    let code = match config.and_then(|config| config.provenance) {
+        Some(provenance) => Provenance {
+            run_id: context.map(|context| context.id.as_str()),
+            models: &models,
+            time: Utc::now(),
+        }
+        .stamp(&provenance, &solution.code.code),
+        None => solution.code.code,
+    };
+    publish::publish(bus, &args.publish, &github, &name, &problem, &code).await?;
+    Ok(solution.proposals)
+}
+
+// Fix the file given to the fix command, or else solve the problem in the problem file.
+async fn solve_command(
+    bus: &EventBus,
+    args: &Args,
+    provider: &Provider,
+    context: &RunContext,
+) -> Result<usize> {
+    match &args.command {
+        Some(Command::Fix(fix_args)) => fix::run(bus, args, fix_args, provider, &context.dir).await,
+        _ => solve_problem_file(bus, args, provider, None, Some(context)).await,
+    }
+}
+
+// Solve the problem in the args' problem file, presenting the run's events only to the given
+// observers. This is the entry point for harnesses that run many problems in one process.
+pub async fn solve(args: &Args, observers: Vec<Box<dyn Observer>>) -> Result<usize> {
+    let provider = create_provider(args)?;
+    let dispatcher = EventBus::start(observers, Steering::default());
+    let result = solve_problem_file(dispatcher.bus(), args, &provider, None, None).await;
+    dispatcher.close().await;
+    result
+}
+
+// Solve the problem as above, but send the requests to the given client and test the proposals with
+// the given tester, as when exercising the run loop with the scripted ones. Test scaffolding, so
+// hidden from the docs.
+#[doc(hidden)]
+pub async fn solve_with(
+    args: &Args,
+    observers: Vec<Box<dyn Observer>>,
+    client: SharedClient,
+    tester: &dyn Tester,
+) -> Result<usize> {
+    let provider = configure_provider(args, Provider::default().with_client(client));
+    let dispatcher = EventBus::start(observers, Steering::default());
+    let result = solve_problem_file(dispatcher.bus(), args, &provider, Some(tester), None).await;
+    dispatcher.close().await;
+    result
+}
+
+// The process exit code for the result of a run: the number of proposals it took to solve the
+// problem, 255 if the critics failed to converge, CANCELLED_EXIT_CODE if the run was cancelled, or 0
+// for any other error.
+pub fn exit_code(result: &Result<usize>) -> i32 {
+    match result {
+        Ok(proposals) => *proposals as i32,
+        Err(e) => match e.downcast_ref::<AiCriticError>() {
+            Some(e) if e.is_divergence() => 255,
+            Some(AiCriticError::Cancelled) => CANCELLED_EXIT_CODE,
+            _ => 0,
+        },
+    }
+}
+
+// Cancel the run on Ctrl-C. The handler runs in its own task and kills the test subprocesses,
+// including any that the run loop is waiting on. Dropping the run loop then aborts the critics'
+// tasks and the API streams. A second Ctrl-C exits immediately, e.g. when the run is
+// waiting for input in the interactive review.
+fn cancel_on_ctrl_c() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("\nCancelling. Press Ctrl-C again to exit immediately.");
+        processes::kill_all();
+        let _ = sender.send(true);
+        if tokio::signal::ctrl_c().await.is_ok() {
+            processes::kill_all();
+            exit(CANCELLED_EXIT_CODE);
+        }
+    });
+    receiver
+}
+
+// Set up the observers and run the command once with the context's run ID, reporting the outcome as
+// the final event. The dispatcher is closed before returning so that every event is presented before
+// the process exits.
+async fn run_once(
+    args: &Args,
+    provider: &Provider,
+    steering: &Steering,
+    cancelled: &mut watch::Receiver<bool>,
+    context: &RunContext,
+    extra_observers: Vec<Box<dyn Observer>>,
+) -> Result<usize> {
+    let problem_file = match (&args.command, &args.github_issue) {
+        (Some(Command::Fix(fix_args)), _) => fix_args.file.display().to_string(),
+        (_, Some(url)) => url.clone(),
+        _ => args.problem_file.clone(),
+    };
+    let mut observers = create_observers(args, &context.id, steering).await?;
+    observers.extend(context.observers(&problem_file)?);
+    observers.extend(extra_observers);
+    let dispatcher = EventBus::start(observers, steering.clone());
+    let result = tokio::select! {
+        result = solve_command(dispatcher.bus(), args, provider, context) => result,
+        _ = cancelled.wait_for(|c| *c) => Err(AiCriticError::Cancelled.into()),
+    };
+    // Killing the test subprocesses may end the loop with a test error before the select sees the
+    // cancellation, so check again.
+    let result = if *cancelled.borrow() {
+        Err(AiCriticError::Cancelled.into())
+    } else {
+        result
+    };
+    dispatcher.bus().emit(RunEvent::RunFinished {
+        outcome: describe_outcome(&result),
+    });
+    dispatcher.close().await;
+    context.flush_store().await;
+    print_note(
+        args,
+        &format!("The run's artifacts are in {}", context.dir.display()),
+    );
+    result
+}
+
+// Describe how the run ended, as recorded in its result.
+fn describe_outcome(result: &Result<usize>) -> String {
+    match result {
+        Ok(proposals) => format!("Solved after {} proposals", proposals),
+        Err(e) => match e.downcast_ref::<AiCriticError>() {
+            Some(AiCriticError::MaxProposalsExceeded { proposals }) => {
+                format!("Failed to converge in {} proposals", proposals)
+            }
+            Some(AiCriticError::Stopped { proposals, reason }) => {
+                format!("Stopped after {} proposals: {}", proposals, reason)
+            }
+            Some(AiCriticError::BudgetExceeded { proposals, reason }) => {
+                format!("Over budget after {} proposals: {}", proposals, reason)
+            }
+            Some(AiCriticError::Cancelled) => "Cancelled".to_string(),
+            _ => format!("Error: {}", e),
+        },
+    }
+}
+
+// Print a note after the run's output, keeping stdout to JSON lines in the JSON format.
+fn print_note(args: &Args, note: &str) {
+    match args.log_format {
+        LogFormat::Text => output::print(Verbosity::Normal, note),
+        LogFormat::Json => eprintln!("{}", note),
+    }
+}
+
+// Solve the problem args.runs times, each run with its own ID, artifacts, and seed, in a directory
+// named for the base ID. Then report each run's outcome and the statistics over all of them. The
+// result is the last run's.
+async fn run_repeatedly(
+    args: &Args,
+    provider: &Provider,
+    steering: &Steering,
+    cancelled: &mut watch::Receiver<bool>,
+    base_id: &str,
+    store: Option<Arc<dyn Store>>,
+) -> Result<usize> {
+    let dir = args.output_dir.join(base_id);
+    let mut records = vec![];
+    let mut reports = vec![];
+    let mut result = Ok(0);
+    for i in 0..args.runs {
+        let mut run_args = args.clone();
+        run_args.seed = args.seed.map(|seed| seed + i64::from(i));
+        let run_provider = provider.clone().with_seed(run_args.seed);
+        let context =
+            RunContext::create(&dir, &format!("{}-{}", base_id, i + 1))?.with_store(store.clone());
+        let tokens = repeat::TokenCounter::default();
+        let summary = notify::RunSummary::default();
+        let start = Instant::now();
+        result = run_once(
+            &run_args,
+            &run_provider,
+            steering,
+            cancelled,
+            &context,
+            vec![Box::new(tokens.clone()), Box::new(summary.clone())],
+        )
+        .await;
+        let report = run_report(&summary, &context, &result);
+        if report.status == notify::Outcome::Diverged {
+            notify::notify(args.notify_webhook.as_deref(), &report).await;
+        }
+        reports.push(report);
+        records.push(repeat::RunRecord::new(
+            &context.id,
+            run_args.seed,
+            &result,
+            start.elapsed(),
+            &tokens,
+        ));
+        if *cancelled.borrow() {
+            break;
+        }
+    }
+    let aggregate = repeat::aggregate(&records);
+    let path = dir.join(repeat::SUMMARY_FILENAME);
+    repeat::write_summary(&path, &records, &aggregate)?;
+    if let Some(store) = &store {
+        store.put(
+            &format!("{}/{}", base_id, repeat::SUMMARY_FILENAME),
+            &fs::read(&path)?,
+        );
+        store::flush(store.as_ref(), base_id).await;
+    }
+    print_note(args, &repeat::report(&records, &aggregate));
+    print_note(args, &format!("The runs' summary is in {}", path.display()));
+    let report = notify::Report::batch(base_id, &reports, &dir);
+    notify::notify(args.notify_webhook.as_deref(), &report).await;
+    result
+}
+
+// Solve the problem in runs with consecutive seeds, in a directory named for the base ID, until k
+// distinct solutions have passed or the attempts run out, archiving each new solution. The result
+// is the number of distinct solutions, or the last run's error if none passed.
+async fn collect_solutions(
+    args: &Args,
+    provider: &Provider,
+    steering: &Steering,
+    cancelled: &watch::Receiver<bool>,
+    base_id: &str,
+    k: u32,
+    store: Option<Arc<dyn Store>>,
+) -> Result<usize> {
+    let dir = args.output_dir.join(base_id);
+    let attempt = |i: u32| {
+        let mut run_args = args.clone();
+        run_args.seed = Some(args.seed.unwrap_or_default() + i64::from(i));
+        let mut cancelled = cancelled.clone();
+        let (dir, store) = (&dir, store.clone());
+        async move {
+            let run_provider = provider.clone().with_seed(run_args.seed);
+            let context =
+                RunContext::create(dir, &format!("{}-{}", base_id, i + 1))?.with_store(store);
+            let result = run_once(
+                &run_args,
+                &run_provider,
+                steering,
+                &mut cancelled,
+                &context,
+                vec![],
+            )
+            .await;
+            Ok(archive::Attempt {
+                run_id: context.id.clone(),
+                result,
+                code: context.final_code().map(|(_, code)| code),
+            })
+        }
+    };
+    let found = |archive: &archive::Archive, path: &Path| {
+        print_note(
+            args,
+            &format!(
+                "Found distinct solution {} of {}: {}",
+                archive.count(),
+                k,
+                path.display()
+            ),
+        );
+    };
+    let (archive, result) = archive::collect(&dir, k, attempt, found).await?;
+    let path = archive.write_index(&dir)?;
+    if let Some(store) = &store {
+        for file in archive
+            .solutions
+            .iter()
+            .map(|s| s.file.as_str())
+            .chain([archive::INDEX_FILENAME])
+        {
+            store.put(&format!("{}/{}", base_id, file), &fs::read(dir.join(file))?);
+        }
+        store::flush(store.as_ref(), base_id).await;
+    }
+    print_note(
+        args,
+        &format!(
+            "Collected {} of {} distinct solutions in {} attempts. Their index is {}",
+            archive.count(),
+            k,
+            archive.attempts,
+            path.display()
+        ),
+    );
+    result
+}
+
+// Print the plan of a run of the problem file and its estimated cost.
+fn dry_run(args: &Args, provider: &Provider) -> Result<()> {
+    let problem = read_file(&project_path(&args.problem_file))?;
+    let prompts = load_prompts(args, &problem)?;
+    let critics = build_critics(provider, &prompts, args, critics_config(args)?.as_ref())?;
+    let plan = dry_run::Plan::new(
+        &prompts,
+        &problem,
+        provider.model(),
+        &critics,
+        args.self_review,
+        args.max_proposals,
+    )?;
+    output::print(Verbosity::Quiet, &plan.to_string());
+    Ok(())
+}
+
+// Run the command as the args ask: a look at the past runs, a benchmark, the worker, a dry run, or
+// one or more runs of the problem.
+pub async fn run() -> Result<usize> {
+    let args = setup()?;
+    // Browsing the past runs needs no provider.
+    match &args.command {
+        Some(Command::History(history_args)) => {
+            history::list(&args.output_dir, history_args)?;
+            exit(0);
+        }
+        Some(Command::Show(show_args)) => {
+            history::show(&args.output_dir, show_args)?;
+            exit(0);
+        }
+        Some(Command::DiffRuns(diff_args)) => {
+            history::diff(&args.output_dir, diff_args)?;
+            exit(0);
+        }
+        Some(Command::Auth(auth_args)) => {
+            credentials::run(auth_args)?;
+            exit(0);
+        }
+        _ => {}
+    }
+    let provider = provider_or_exit(&args);
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        bench::run(&args, bench_args, &provider).await?;
+        exit(0);
+    }
+    if let Some(Command::Worker(worker_args)) = &args.command {
+        worker::run(&args, worker_args, &provider).await?;
+        exit(0);
+    }
+    if args.dry_run {
+        dry_run(&args, &provider)?;
+        exit(0);
+    }
+
+    let run_id = args.run_id.clone().unwrap_or_else(events::new_run_id);
+    // The dashboard reads the regenerate key itself, and the interactive review needs stdin.
+    let steering = Steering::default();
+    if !args.tui && !args.interactive {
+        steering.read_stdin();
+    }
+    let mut cancelled = cancel_on_ctrl_c();
+    let store = artifact_store(&args)?;
+    if args.runs > 1 {
+        return run_repeatedly(&args, &provider, &steering, &mut cancelled, &run_id, store).await;
+    }
+    if let Some(k) = args.collect_k {
+        return collect_solutions(&args, &provider, &steering, &cancelled, &run_id, k, store).await;
+    }
+    let context = RunContext::create(&args.output_dir, &run_id)?.with_store(store);
+    let summary = notify::RunSummary::default();
+    let result = run_once(
+        &args,
+        &provider,
+        &steering,
+        &mut cancelled,
+        &context,
+        vec![Box::new(summary.clone())],
+    )
+    .await;
+    let report = run_report(&summary, &context, &result);
+    notify::notify(args.notify_webhook.as_deref(), &report).await;
+    result
+}
+
+// The summary of the run that the webhook is notified of.
+fn run_report(
+    summary: &notify::RunSummary,
+    context: &RunContext,
+    result: &Result<usize>,
+) -> notify::Report {
+    let outcome = notify::Outcome::of(result);
+    summary.report(
+        &context.id,
+        outcome,
+        &describe_outcome(result),
+        &context.dir,
+    )
+}
@@ -0,0 +1,58 @@
+use crate::errors::AiCriticError;
+use crate::processes;
+use color_eyre::eyre::Result;
+use std::fs;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+// Clippy's pedantic lints of the code, which the Idiomatic critic is given along with it. Clippy's
+// driver takes rustc's arguments, so the code is checked with its tests, as the tester compiles it,
+// but only as far as its metadata, without generating code. main() isn't called from a test build,
+// so dead code isn't reported.
+
+const CLIPPY_DRIVER: &str = "clippy-driver";
+const NO_LINTS: &str = "clippy::pedantic has no warnings for the code.";
+
+// What clippy says about the code, which it calls `code.rs`, or that it has nothing to say. Code
+// that doesn't compile gets the compiler's errors instead.
+pub async fn pedantic(code: &str) -> Result<String> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("code.rs"), code)?;
+    let output = processes::output(Command::new(CLIPPY_DRIVER).current_dir(dir.path()).args([
+        "--test",
+        "--emit=metadata",
+        "-W",
+        "clippy::pedantic",
+        "-A",
+        "dead_code",
+        "code.rs",
+    ]))
+    .await?;
+    if output.status.code().is_none() {
+        return Err(AiCriticError::ProcessTerminated.into());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    match stderr.is_empty() {
+        true => Ok(NO_LINTS.to_string()),
+        false => Ok(stderr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pedantic() {
+        let code = "fn total(v: &Vec<u64>) -> u32 {\n    v.iter().sum::<u64>() as u32\n}\n\nfn \
+                    main() {\n    println!(\"{}\", total(&vec![1]));\n}\n";
+        let lints = pedantic(code).await.unwrap();
+        assert!(lints.contains("clippy::ptr_arg"));
+        assert!(lints.contains("clippy::cast_possible_truncation"));
+        assert!(lints.contains("--> code.rs:1:13"));
+        assert!(!lints.contains("never used"));
+
+        let clean = "fn main() {\n    println!(\"hi\");\n}\n";
+        assert_eq!(pedantic(clean).await.unwrap(), NO_LINTS);
+    }
+}
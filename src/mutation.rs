@@ -0,0 +1,424 @@
+use crate::build_service::{Build, BuildService, Sandbox};
+use crate::fixer::{ReviewNeeded, ReviewType};
+use crate::processes::TrackedChild;
+use color_eyre::eyre::Result;
+use futures::future::join_all;
+use std::fmt;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+// Mutation testing measures how good the AI-written tests are. Each mutant is a copy of the accepted
+// solution with one small change, such as a flipped operator or an off-by-one constant. If the
+// tests still pass with the change in place, the mutant "survived" and the tests failed to notice a
+// real behavior change. The fraction of viable mutants that the tests kill is the test-quality
+// score.
+
+// Generating and compiling mutants is slow, so only test this many of them.
+const MAX_MUTANTS: usize = 25;
+// A mutant may loop forever, for example if it flips the direction of a loop counter. Treat any
+// test run that exceeds this duration as killed.
+const MUTANT_TIMEOUT: Duration = Duration::from_secs(10);
+// Test at most this many mutants at a time when the number of CPUs is unknown.
+const DEFAULT_MUTANT_JOBS: usize = 4;
+
+// Binary operator flips. Each operator must be surrounded by spaces, as rustfmt writes them, so that
+// generics (`Vec<T>`), arrows (`->`, `=>`), and references (`&x`) are not mistaken for operators.
+// Multi-character operators must come before their prefixes.
+const OPERATOR_FLIPS: [(&str, &str); 12] = [
+    (" <= ", " < "),
+    (" >= ", " > "),
+    (" == ", " != "),
+    (" != ", " == "),
+    (" && ", " || "),
+    (" || ", " && "),
+    (" < ", " <= "),
+    (" > ", " >= "),
+    (" + ", " - "),
+    (" - ", " + "),
+    (" * ", " / "),
+    (" / ", " * "),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mutant {
+    pub line: usize,
+    pub description: String,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MutantOutcome {
+    // The tests failed or timed out, so the mutation was detected.
+    Killed,
+    // The tests passed, so the mutation went unnoticed.
+    Survived,
+    // The mutant didn't compile, so it says nothing about the tests.
+    Unviable,
+}
+
+#[derive(Debug, Default)]
+pub struct MutationReport {
+    pub killed: usize,
+    pub survived: Vec<Mutant>,
+    pub unviable: usize,
+}
+
+impl MutationReport {
+    // The fraction of viable mutants killed by the tests. If there are no viable mutants, there is
+    // nothing for the tests to detect, so the score is perfect.
+    pub fn score(&self) -> f64 {
+        let viable = self.killed + self.survived.len();
+        if viable == 0 {
+            return 1.0;
+        }
+        self.killed as f64 / viable as f64
+    }
+
+    // Build a review asking the fixer to add tests that would kill the surviving mutants.
+    pub fn review(&self) -> ReviewNeeded {
+        ReviewNeeded::new(
+            ReviewType::TestStrengthening,
+            self.survived
+                .iter()
+                .map(|m| format!("The tests still pass if {}.", m.description))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for MutationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Mutation testing: {} killed, {} survived, {} unviable. Test-quality score: {:.0}%",
+            self.killed,
+            self.survived.len(),
+            self.unviable,
+            self.score() * 100.0
+        )?;
+        for m in self.survived.iter() {
+            writeln!(f, "  survived: {}", m.description)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct MutationTester {
+    build: BuildService,
+    max_mutants: usize,
+}
+
+impl MutationTester {
+    pub fn new() -> Self {
+        MutationTester {
+            build: BuildService::default(),
+            max_mutants: MAX_MUTANTS,
+        }
+    }
+
+    pub fn with_sandbox(self, sandbox: Sandbox) -> Self {
+        MutationTester {
+            build: self.build.with_sandbox(sandbox),
+            ..self
+        }
+    }
+
+    pub fn with_test_seed(self, test_seed: Option<u64>) -> Self {
+        MutationTester {
+            build: self.build.with_test_seed(test_seed),
+            ..self
+        }
+    }
+
+    // Mutate the given code, which has already passed its tests, and run the tests against each
+    // mutant, compiling and testing the mutants in batches of as many as there are CPUs.
+    pub async fn run(&self, code: &str) -> Result<MutationReport> {
+        let jobs = thread::available_parallelism().map_or(DEFAULT_MUTANT_JOBS, |n| n.get());
+        let mutants: Vec<Mutant> = generate_mutants(code)
+            .into_iter()
+            .take(self.max_mutants)
+            .collect();
+        let mut outcomes = vec![];
+        for batch in mutants.chunks(jobs) {
+            let mut tests = vec![];
+            for mutant in batch {
+                tests.push(self.test_mutant(mutant));
+            }
+            for outcome in join_all(tests).await {
+                outcomes.push(outcome?);
+            }
+        }
+        let mut report = MutationReport::default();
+        for (mutant, outcome) in mutants.into_iter().zip(outcomes) {
+            match outcome {
+                MutantOutcome::Killed => report.killed += 1,
+                MutantOutcome::Survived => report.survived.push(mutant),
+                MutantOutcome::Unviable => report.unviable += 1,
+            }
+        }
+        Ok(report)
+    }
+
+    async fn test_mutant(&self, mutant: &Mutant) -> Result<MutantOutcome> {
+        let temp_dir = TempDir::new()?;
+        let exec_path = match self.build.build_test(temp_dir.path(), &mutant.code).await? {
+            Build::Built { exec_path, .. } => exec_path,
+            Build::Failed { .. } => return Ok(MutantOutcome::Unviable),
+        };
+
+        let mut child = TrackedChild::spawn(
+            self.build
+                .command(&exec_path)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null()),
+        )?;
+        match timeout(MUTANT_TIMEOUT, child.wait()).await {
+            Ok(status) => Ok(match status?.success() {
+                true => MutantOutcome::Survived,
+                false => MutantOutcome::Killed,
+            }),
+            Err(_) => {
+                child.kill().await?;
+                Ok(MutantOutcome::Killed)
+            }
+        }
+    }
+}
+
+// Return the byte offset where the test module starts, or the end of the code if there is none.
+// Mutating the tests themselves would say nothing about their quality.
+fn tests_start(code: &str) -> usize {
+    code.find("#[cfg(test)]").unwrap_or(code.len())
+}
+
+// Return a mask marking the bytes of the code that are inside comments, string literals, or
+// attributes. Mutations are never applied inside these.
+fn ignored_bytes(code: &str) -> Vec<bool> {
+    let bytes = code.as_bytes();
+    let mut mask = vec![false; bytes.len()];
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        if bytes[i..].starts_with(b"//") || bytes[i] == b'#' {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else if bytes[i..].starts_with(b"/*") {
+            i += 2;
+            while i < bytes.len() && !bytes[i..].starts_with(b"*/") {
+                i += 1;
+            }
+            i = (i + 2).min(bytes.len());
+        } else if bytes[i] == b'\'' && bytes.get(i + 1) == Some(&b'\\') {
+            // An escaped char literal such as '\n' or '\''.
+            i += 3;
+            while i < bytes.len() && bytes[i] != b'\'' {
+                i += 1;
+            }
+            i = (i + 1).min(bytes.len());
+        } else if bytes[i] == b'\'' && bytes.get(i + 2) == Some(&b'\'') {
+            // A plain char literal such as 'a' or '"'. Lifetimes never match this pattern.
+            i += 3;
+        } else if bytes[i] == b'"' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+        } else {
+            i += 1;
+            continue;
+        }
+        mask[start..i].fill(true);
+    }
+    mask
+}
+
+fn line_number(code: &str, offset: usize) -> usize {
+    code[..offset].matches('\n').count() + 1
+}
+
+fn replace_at(code: &str, offset: usize, len: usize, replacement: &str) -> String {
+    format!(
+        "{}{}{}",
+        &code[..offset],
+        replacement,
+        &code[offset + len..]
+    )
+}
+
+// Generate the mutants for the non-test portion of the code. Each mutant differs from the original
+// by exactly one operator flip or one integer constant change.
+pub fn generate_mutants(code: &str) -> Vec<Mutant> {
+    let end = tests_start(code);
+    let ignored = ignored_bytes(code);
+    let mut mutants = vec![];
+
+    // Operator flips. Track the positions already claimed so that, e.g., the ` < ` inside ` <= `
+    // isn't flipped separately.
+    let mut claimed = vec![false; end];
+    for (from, to) in OPERATOR_FLIPS.iter() {
+        for (offset, _) in code[..end].match_indices(from) {
+            if ignored[offset] || claimed[offset + 1] {
+                continue;
+            }
+            claimed[offset + 1] = true;
+            let line = line_number(code, offset);
+            mutants.push(Mutant {
+                line,
+                description: format!(
+                    "`{}` is replaced with `{}` on line {}",
+                    from.trim(),
+                    to.trim(),
+                    line
+                ),
+                code: replace_at(code, offset, from.len(), to),
+            });
+        }
+    }
+
+    // Constant changes. Only mutate standalone decimal integers, not digits that are part of an
+    // identifier, a float, or a type suffix such as `u32`.
+    let bytes = code.as_bytes();
+    let mut i = 0;
+    while i < end {
+        if ignored[i] || !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < end && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        let preceded =
+            start > 0 && (bytes[start - 1].is_ascii_alphanumeric() || bytes[start - 1] == b'_');
+        let followed = i < bytes.len()
+            && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'.');
+        if preceded || followed {
+            continue;
+        }
+        let literal = &code[start..i];
+        let Ok(value) = literal.parse::<u64>() else {
+            continue;
+        };
+        let replacement = if value == 0 { 1 } else { value - 1 };
+        let line = line_number(code, start);
+        mutants.push(Mutant {
+            line,
+            description: format!(
+                "the constant `{}` is replaced with `{}` on line {}",
+                literal, replacement, line
+            ),
+            code: replace_at(code, start, literal.len(), &replacement.to_string()),
+        });
+    }
+
+    mutants.sort_by_key(|m| m.line);
+    mutants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_mutants_operators() {
+        let code = "fn f(a: i32, b: i32) -> bool {\n    a <= b\n}\n";
+        let mutants = generate_mutants(code);
+        assert_eq!(mutants.len(), 1);
+        assert_eq!(mutants[0].line, 2);
+        assert_eq!(
+            mutants[0].code,
+            "fn f(a: i32, b: i32) -> bool {\n    a < b\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_mutants_constants() {
+        let code = "fn f() -> u32 {\n    let x: u32 = 10;\n    x + 0\n}\n";
+        let mutants = generate_mutants(code);
+        let codes: Vec<_> = mutants.iter().map(|m| m.code.as_str()).collect();
+        assert_eq!(mutants.len(), 3);
+        assert!(codes.contains(&"fn f() -> u32 {\n    let x: u32 = 9;\n    x + 0\n}\n"));
+        assert!(codes.contains(&"fn f() -> u32 {\n    let x: u32 = 10;\n    x + 1\n}\n"));
+        assert!(codes.contains(&"fn f() -> u32 {\n    let x: u32 = 10;\n    x - 0\n}\n"));
+    }
+
+    #[test]
+    fn test_generate_mutants_skips_tests_strings_and_comments() {
+        let code = indoc::indoc! {r#"
+            // a + b
+            fn f() -> &'static str {
+                "1 + 2"
+            }
+            #[cfg(test)]
+            mod tests {
+                fn g() -> i32 { 1 + 2 }
+            }
+        "#};
+        assert!(generate_mutants(code).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_test_seed() {
+        // The mutant of the seed's default survives only if the tests are given the seed. Without
+        // it, the tests fail against every mutant, and each counts as killed.
+        let code = indoc::indoc! {r#"
+            fn seed() -> u64 {
+                std::env::var("AI_CRITICS_TEST_SEED")
+                    .ok()
+                    .and_then(|seed| seed.parse().ok())
+                    .unwrap_or(1)
+            }
+
+            fn double(x: u64) -> u64 {
+                x * 2
+            }
+
+            #[cfg(test)]
+            mod tests {
+                use super::*;
+
+                #[test]
+                fn test_seed() {
+                    assert_eq!(seed(), 42);
+                    assert_eq!(double(3), 6);
+                }
+            }
+        "#};
+        let report = MutationTester::new()
+            .with_test_seed(Some(42))
+            .run(code)
+            .await
+            .unwrap();
+        assert_eq!((report.killed, report.survived.len()), (2, 1));
+        assert_eq!(report.survived[0].line, 5);
+        let report = MutationTester::new().run(code).await.unwrap();
+        assert_eq!((report.killed, report.survived.len()), (3, 0));
+    }
+
+    #[test]
+    fn test_mutation_report_score() {
+        let mut report = MutationReport::default();
+        assert_eq!(report.score(), 1.0);
+
+        report.killed = 3;
+        report.unviable = 5;
+        report.survived.push(Mutant {
+            line: 1,
+            description: "`+` is replaced with `-` on line 1".to_string(),
+            code: String::new(),
+        });
+        assert_eq!(report.score(), 0.75);
+
+        let review = report.review();
+        assert!(matches!(review.review_type, ReviewType::TestStrengthening));
+        assert_eq!(
+            review.comments,
+            vec!["The tests still pass if `+` is replaced with `-` on line 1."]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::convergence::parse_duration;
+use crate::errors::AiCriticError;
+use color_eyre::eyre::Result;
+use reqwest::{Certificate, NoProxy, Proxy};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// How the model APIs are reached from networks that can't reach them directly, as behind a
+// corporate proxy that inspects TLS with its own certificate authority. The settings come from the
+// command line or their environment variables, or else from the [network] table of the config file:
+//
+//   [network]
+//   proxy = "http://proxy.example.com:8080"
+//   ca_bundle = "/etc/ssl/corp-ca.pem"
+//   connect_timeout = "10s"
+//
+// Without them, reqwest already honors HTTP_PROXY, HTTPS_PROXY, and NO_PROXY and trusts the
+// system's certificates.
+
+#[derive(clap::Args, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkArgs {
+    /// Proxy to send the API requests through, e.g. http://proxy.example.com:8080. Hosts in
+    /// NO_PROXY bypass it.
+    #[arg(long, env = "AI_CRITICS_PROXY")]
+    pub proxy: Option<String>,
+
+    /// PEM file of CA certificates to trust besides the system's, such as a proxy's.
+    #[arg(long, env = "AI_CRITICS_CA_BUNDLE")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Give up connecting to an API after this long, e.g. 10s.
+    #[arg(long, env = "AI_CRITICS_CONNECT_TIMEOUT", value_parser = parse_duration)]
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub connect_timeout: Option<Duration>,
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(d)?;
+    parse_duration(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl NetworkArgs {
+    // These settings, with any that are missing taken from the fallback.
+    pub fn or(&self, fallback: &NetworkArgs) -> NetworkArgs {
+        NetworkArgs {
+            proxy: self.proxy.clone().or_else(|| fallback.proxy.clone()),
+            ca_bundle: self
+                .ca_bundle
+                .clone()
+                .or_else(|| fallback.ca_bundle.clone()),
+            connect_timeout: self.connect_timeout.or(fallback.connect_timeout),
+        }
+    }
+
+    // An HTTP client for the API requests that uses the settings.
+    pub fn http_client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy) = &self.proxy {
+            let invalid = |e: reqwest::Error| AiCriticError::InvalidConfig {
+                path: "--proxy".to_string(),
+                reason: e.to_string(),
+            };
+            builder = builder.proxy(
+                Proxy::all(proxy)
+                    .map_err(invalid)?
+                    .no_proxy(NoProxy::from_env()),
+            );
+        }
+        if let Some(path) = &self.ca_bundle {
+            let invalid = |reason: String| AiCriticError::InvalidConfig {
+                path: path.display().to_string(),
+                reason,
+            };
+            let pem = fs::read(path).map_err(|e| invalid(e.to_string()))?;
+            let certificates =
+                Certificate::from_pem_bundle(&pem).map_err(|e| invalid(e.to_string()))?;
+            if certificates.is_empty() {
+                return Err(invalid("no PEM certificates found".to_string()).into());
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        let config: NetworkArgs =
+            toml::from_str("proxy = \"http://proxy:8080\"\nconnect_timeout = \"10s\"").unwrap();
+        assert_eq!(config.connect_timeout, Some(Duration::from_secs(10)));
+        let args = NetworkArgs {
+            proxy: Some("http://other:3128".to_string()),
+            ..Default::default()
+        };
+        let merged = args.or(&config);
+        assert_eq!(merged.proxy.as_deref(), Some("http://other:3128"));
+        assert_eq!(merged.connect_timeout, Some(Duration::from_secs(10)));
+        assert!(merged.http_client().is_ok());
+        assert!(toml::from_str::<NetworkArgs>("proxy_url = \"x\"").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("ca.pem");
+        fs::write(&bundle, "not a certificate").unwrap();
+        let args = NetworkArgs {
+            ca_bundle: Some(bundle),
+            ..Default::default()
+        };
+        let error = args.http_client().unwrap_err();
+        assert!(error.to_string().contains("ca.pem"));
+    }
+}
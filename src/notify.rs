@@ -0,0 +1,256 @@
+use crate::events::{Observer, RunEvent};
+use crate::pricing::Spend;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub use crate::repeat::Outcome;
+
+// With --notify-webhook, a summary is posted to the URL when a run finishes, so that long runs and
+// batches don't need watching. A batch of --runs posts once when it finishes, and again for each of
+// its runs that diverges, as that's usually worth a look before the batch is done. The summary's
+// text is sent as both `text` and `content`, which Slack's and Discord's incoming webhooks show,
+// and its fields are sent as `details` for other services. A webhook that fails is logged and
+// otherwise ignored, since the run's result doesn't depend on it.
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// The most characters of the problem's first line to show.
+const PROBLEM_PREVIEW_CHARS: usize = 80;
+
+// What the notification of a run says about it, counted from its events.
+#[derive(Clone, Default)]
+pub struct RunSummary(Arc<Mutex<Tally>>);
+
+#[derive(Default)]
+struct Tally {
+    problem: String,
+    proposals: usize,
+    spend: Spend,
+}
+
+impl Observer for RunSummary {
+    fn on_event(&mut self, event: &RunEvent) {
+        let mut tally = self.0.lock().unwrap();
+        tally.spend.record(event);
+        match event {
+            RunEvent::ProblemLoaded { path, problem } => {
+                tally.problem = describe_problem(path, problem);
+            }
+            RunEvent::ProposalCreated { number, .. } => tally.proposals = *number,
+            _ => {}
+        }
+    }
+}
+
+impl RunSummary {
+    pub fn report(&self, run_id: &str, outcome: Outcome, description: &str, dir: &Path) -> Report {
+        let tally = self.0.lock().unwrap();
+        Report {
+            id: run_id.to_string(),
+            problem: tally.problem.clone(),
+            status: outcome,
+            outcome: description.to_string(),
+            proposals: Some(tally.proposals),
+            cost_usd: tally.spend.cost_usd,
+            artifacts: artifacts_link(dir),
+        }
+    }
+}
+
+// The problem's file and the start of its first line.
+fn describe_problem(path: &str, problem: &str) -> String {
+    let first_line = problem
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let mut preview: String = first_line.chars().take(PROBLEM_PREVIEW_CHARS).collect();
+    if preview.len() < first_line.len() {
+        preview.push('…');
+    }
+    format!("{} ({})", path, preview)
+}
+
+// The absolute path of the directory, so that the link works from wherever it's read.
+pub fn artifacts_link(dir: &Path) -> String {
+    dir.canonicalize()
+        .unwrap_or_else(|_| dir.to_path_buf())
+        .display()
+        .to_string()
+}
+
+// The summary of a run or a batch of runs.
+#[derive(Serialize, Debug)]
+pub struct Report {
+    // The ID of the run or the batch.
+    pub id: String,
+    pub problem: String,
+    pub status: Outcome,
+    // How the run ended, or how many of the batch's runs were solved.
+    pub outcome: String,
+    // The proposals of the run, or of all of the batch's runs, if they were counted.
+    pub proposals: Option<usize>,
+    pub cost_usd: f64,
+    pub artifacts: String,
+}
+
+impl Report {
+    // The summary of a batch from those of its runs.
+    pub fn batch(id: &str, runs: &[Report], dir: &Path) -> Self {
+        let count = |status| runs.iter().filter(|run| run.status == status).count();
+        let (solved, diverged) = (count(Outcome::Solved), count(Outcome::Diverged));
+        let status = if count(Outcome::Cancelled) > 0 {
+            Outcome::Cancelled
+        } else if solved == runs.len() {
+            Outcome::Solved
+        } else if diverged > 0 {
+            Outcome::Diverged
+        } else {
+            Outcome::Error
+        };
+        Report {
+            id: id.to_string(),
+            problem: runs
+                .first()
+                .map(|run| run.problem.clone())
+                .unwrap_or_default(),
+            status,
+            outcome: format!(
+                "Solved {} of {} runs ({} diverged, {} errors)",
+                solved,
+                runs.len(),
+                diverged,
+                runs.len() - solved - diverged
+            ),
+            proposals: Some(runs.iter().filter_map(|run| run.proposals).sum()),
+            cost_usd: runs.iter().map(|run| run.cost_usd).sum(),
+            artifacts: artifacts_link(dir),
+        }
+    }
+
+    fn text(&self) -> String {
+        let proposals = match self.proposals {
+            Some(proposals) => format!("Proposals: {}, cost", proposals),
+            None => "Cost".to_string(),
+        };
+        format!(
+            "ai_critics {}: {}\nProblem: {}\n{}: ${:.4}\nArtifacts: {}",
+            self.id, self.outcome, self.problem, proposals, self.cost_usd, self.artifacts
+        )
+    }
+
+    // The webhook's JSON body.
+    fn payload(&self) -> Value {
+        let text = self.text();
+        json!({ "text": text, "content": text, "details": self })
+    }
+}
+
+// Post the report to the webhook, if there is one.
+pub async fn notify(webhook: Option<&str>, report: &Report) {
+    let Some(url) = webhook else {
+        return;
+    };
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .timeout(TIMEOUT)
+        .json(&report.payload())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = response {
+        log::warn!("Couldn't notify the webhook of {}: {}", report.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut summary = RunSummary::default();
+        let events = [
+            RunEvent::ProblemLoaded {
+                path: "problems/p1.txt".to_string(),
+                problem: "\nWrite a function that adds two numbers.\nMore details.".to_string(),
+            },
+            RunEvent::ChatRequested {
+                agent: "Coder".to_string(),
+                model: "gpt-4o".to_string(),
+                messages: vec![],
+                prompt_tokens: 1_000_000,
+            },
+            RunEvent::ProposalCreated {
+                number: 2,
+                code: "fn add() {}".to_string(),
+            },
+        ];
+        for event in events.iter() {
+            summary.on_event(event);
+        }
+        let report = summary.report(
+            "run-1",
+            Outcome::Solved,
+            "Solved after 2 proposals",
+            Path::new("runs/run-1"),
+        );
+        assert_eq!(
+            report.problem,
+            "problems/p1.txt (Write a function that adds two numbers.)"
+        );
+        assert_eq!(report.proposals, Some(2));
+        assert_eq!(report.cost_usd, 2.5);
+
+        let payload = report.payload();
+        assert_eq!(payload["text"], payload["content"]);
+        assert!(payload["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("ai_critics run-1: Solved after 2 proposals\n"));
+        assert_eq!(payload["details"]["status"], "solved");
+        assert_eq!(payload["details"]["cost_usd"], 2.5);
+        assert!(payload["text"]
+            .as_str()
+            .unwrap()
+            .contains("\nProposals: 2, cost: $2.5000\n"));
+    }
+
+    #[test]
+    fn test_batch() {
+        let run = |status, proposals| Report {
+            id: "run".to_string(),
+            problem: "p.txt".to_string(),
+            status,
+            outcome: String::new(),
+            proposals: Some(proposals),
+            cost_usd: 0.5,
+            artifacts: String::new(),
+        };
+        let runs = [
+            run(Outcome::Solved, 2),
+            run(Outcome::Diverged, 20),
+            run(Outcome::Error, 1),
+        ];
+        let batch = Report::batch("batch", &runs, Path::new("runs/batch"));
+        assert_eq!(batch.status, Outcome::Diverged);
+        assert_eq!(batch.outcome, "Solved 1 of 3 runs (1 diverged, 1 errors)");
+        assert_eq!(batch.proposals, Some(23));
+        assert_eq!(batch.cost_usd, 1.5);
+        let solved = Report::batch("batch", &runs[..1], Path::new("runs/batch"));
+        assert_eq!(solved.status, Outcome::Solved);
+    }
+
+    #[test]
+    fn test_describe_problem() {
+        let long = "x".repeat(100);
+        assert_eq!(
+            describe_problem("p.txt", &long),
+            format!("p.txt ({}…)", "x".repeat(80))
+        );
+        assert_eq!(describe_problem("p.txt", ""), "p.txt ()");
+    }
+}
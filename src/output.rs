@@ -0,0 +1,41 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// What a run prints to the console goes through here rather than straight to println!, so that
+// -q/--quiet and -v/--verbose apply to all of it. Each message has the lowest verbosity at which
+// it's shown: the run's result and its errors are always shown, its progress is hidden by
+// --quiet, and the full prompts and responses are shown only with --verbose. The level is set once
+// from the args, before the run starts. JSON lines are data, not console output, so they're
+// written at every level.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+// Whether messages of the level are shown.
+pub fn shows(level: Verbosity) -> bool {
+    level <= verbosity()
+}
+
+// Print the text if messages of its level are shown.
+pub fn print(level: Verbosity, text: &str) {
+    if shows(level) {
+        println!("{}", text);
+    }
+}
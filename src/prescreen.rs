@@ -0,0 +1,486 @@
+use crate::fixer::{ReviewNeeded, ReviewType};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{AttrStyle, Expr, Local, Macro, Pat, Stmt, Token, UseTree};
+
+// Before the tester runs the generated code, it's scanned for APIs that reach outside the test:
+// running other programs, opening network connections, and writing files anywhere but the
+// temporary directory. These are rarely what a problem asks for, and a mistake in them could harm
+// the machine running ai_critics. A violation is sent back to the fixer instead of being run. The
+// scan resolves the code's `use` declarations, so `Command::new` is caught after
+// `use std::process::Command`, and it looks into the arguments of macros such as assert!(). It's a
+// screen rather than a sandbox: code determined to hide a call can get past it. --allow-process and
+// --allow-net permit the first two kinds of call.
+//
+// The scan also rejects `unsafe` code, whether blocks, functions, impls, or traits, and inner
+// `#![allow(...)]` attributes, which would silence the compiler's warnings for the whole file,
+// unless --allow-unsafe permits them. Unlike a denied API, each is reported with its line.
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Policy {
+    pub allow_process: bool,
+    pub allow_net: bool,
+    pub allow_unsafe: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Process,
+    Net,
+    FsWrite,
+    Unsafe,
+    LintAllow,
+}
+
+const DENIED: [(&str, Kind); 23] = [
+    ("std::process::Command", Kind::Process),
+    ("std::os::unix::process::CommandExt", Kind::Process),
+    ("tokio::process::Command", Kind::Process),
+    ("std::net::TcpStream", Kind::Net),
+    ("std::net::TcpListener", Kind::Net),
+    ("std::net::UdpSocket", Kind::Net),
+    ("std::os::unix::net", Kind::Net),
+    ("tokio::net", Kind::Net),
+    ("std::fs::write", Kind::FsWrite),
+    ("std::fs::File::create", Kind::FsWrite),
+    ("std::fs::File::create_new", Kind::FsWrite),
+    ("std::fs::File::options", Kind::FsWrite),
+    ("std::fs::OpenOptions", Kind::FsWrite),
+    ("std::fs::remove_file", Kind::FsWrite),
+    ("std::fs::remove_dir", Kind::FsWrite),
+    ("std::fs::remove_dir_all", Kind::FsWrite),
+    ("std::fs::create_dir", Kind::FsWrite),
+    ("std::fs::create_dir_all", Kind::FsWrite),
+    ("std::fs::rename", Kind::FsWrite),
+    ("std::fs::copy", Kind::FsWrite),
+    ("std::fs::hard_link", Kind::FsWrite),
+    ("std::fs::set_permissions", Kind::FsWrite),
+    ("std::os::unix::fs::symlink", Kind::FsWrite),
+];
+
+// A file write is allowed in a statement that names one of these, or a variable bound to one, as
+// in `let path = std::env::temp_dir().join("out.txt");`.
+const TEMP_NAMES: [&str; 5] = [
+    "temp_dir",
+    "tempdir",
+    "TempDir",
+    "tempfile",
+    "NamedTempFile",
+];
+
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    // The denied API, e.g. std::process::Command.
+    pub api: String,
+    // The path as written in the code, e.g. Command::new.
+    pub used_as: String,
+    pub kind: Kind,
+    // The line of the code that it's on, counting from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.kind {
+            Kind::Process => "runs another program",
+            Kind::Net => "opens a network connection",
+            Kind::FsWrite => "writes to the filesystem outside the temporary directory",
+            Kind::Unsafe => "is unsafe code",
+            Kind::LintAllow => "silences the compiler's warnings for the whole file",
+        };
+        match self.kind {
+            Kind::Unsafe | Kind::LintAllow => {
+                write!(f, "`{}` on line {} {}.", self.used_as, self.line, reason)
+            }
+            _ => write!(f, "`{}` ({}) {}.", self.used_as, self.api, reason),
+        }
+    }
+}
+
+impl Policy {
+    fn allows(&self, kind: Kind) -> bool {
+        match kind {
+            Kind::Process => self.allow_process,
+            Kind::Net => self.allow_net,
+            Kind::FsWrite => false,
+            Kind::Unsafe | Kind::LintAllow => self.allow_unsafe,
+        }
+    }
+
+    // Scan the code for the APIs that the policy denies. Code that doesn't parse has no violations
+    // here; the compiler will report it.
+    pub fn scan(&self, code: &str) -> Vec<Violation> {
+        let Ok(file) = syn::parse_file(code) else {
+            return vec![];
+        };
+        let mut imports = Imports::default();
+        imports.visit_file(&file);
+        let mut scanner = Scanner {
+            policy: *self,
+            imports,
+            temp_vars: HashSet::new(),
+            in_temp_stmt: false,
+            violations: vec![],
+        };
+        scanner.visit_file(&file);
+        scanner.violations
+    }
+
+    // Scan the code and return a ReviewNeeded asking the fixer to remove any violations.
+    pub fn review(&self, code: &str) -> Option<ReviewNeeded> {
+        let violations = self.scan(code);
+        if violations.is_empty() {
+            return None;
+        }
+        Some(ReviewNeeded::new(
+            ReviewType::PolicyFix,
+            violations.iter().map(|v| v.to_string()).collect(),
+        ))
+    }
+}
+
+// The names that the code's `use` declarations bring into scope, wherever they are, mapped to their
+// full paths, and the modules imported with globs.
+#[derive(Default)]
+struct Imports {
+    names: HashMap<String, String>,
+    globs: Vec<String>,
+}
+
+impl Imports {
+    fn add(&mut self, prefix: &str, tree: &UseTree) {
+        let join = |name: String| match prefix.is_empty() {
+            true => name,
+            false => format!("{}::{}", prefix, name),
+        };
+        match tree {
+            UseTree::Path(path) => self.add(&join(path.ident.to_string()), &path.tree),
+            UseTree::Name(name) if name.ident == "self" => {
+                if let Some(last) = prefix.rsplit("::").next() {
+                    self.names.insert(last.to_string(), prefix.to_string());
+                }
+            }
+            UseTree::Name(name) => {
+                self.names
+                    .insert(name.ident.to_string(), join(name.ident.to_string()));
+            }
+            UseTree::Rename(rename) => {
+                self.names
+                    .insert(rename.rename.to_string(), join(rename.ident.to_string()));
+            }
+            UseTree::Glob(_) => self.globs.push(prefix.to_string()),
+            UseTree::Group(group) => group.items.iter().for_each(|item| self.add(prefix, item)),
+        }
+    }
+
+    // The full paths that the given path may refer to.
+    fn resolve(&self, segments: &[String]) -> Vec<String> {
+        let path = segments.join("::");
+        let first = segments[0].as_str();
+        if ["std", "core", "alloc", "tokio"].contains(&first) {
+            return vec![path];
+        }
+        if let Some(full) = self.names.get(first) {
+            let rest = &segments[1..];
+            return vec![std::iter::once(full)
+                .chain(rest)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("::")];
+        }
+        self.globs
+            .iter()
+            .map(|glob| format!("{}::{}", glob, path))
+            .collect()
+    }
+}
+
+impl<'ast> Visit<'ast> for Imports {
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        self.add("", &item.tree);
+    }
+}
+
+struct Scanner {
+    policy: Policy,
+    imports: Imports,
+    // The variables bound to paths in the temporary directory.
+    temp_vars: HashSet<String>,
+    // Whether the statement being scanned refers to the temporary directory.
+    in_temp_stmt: bool,
+    violations: Vec<Violation>,
+}
+
+impl Scanner {
+    fn mentions_temp(&self, stmt: &Stmt) -> bool {
+        let mut words = Words::default();
+        words.visit_stmt(stmt);
+        words.0.iter().any(|word| {
+            TEMP_NAMES.contains(&word.as_str()) || self.temp_vars.contains(word.as_str())
+        })
+    }
+
+    fn check(&mut self, path: &syn::Path) {
+        let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+        if segments.is_empty() {
+            return;
+        }
+        for full in self.imports.resolve(&segments) {
+            let denied = DENIED
+                .iter()
+                .find(|(api, _)| full == *api || full.starts_with(&format!("{}::", api)));
+            let Some(&(api, kind)) = denied else {
+                continue;
+            };
+            let exempt = kind == Kind::FsWrite && self.in_temp_stmt;
+            let used_as = segments.join("::");
+            let seen = self
+                .violations
+                .iter()
+                .any(|v| v.api == api && v.used_as == used_as);
+            if !self.policy.allows(kind) && !exempt && !seen {
+                self.violations.push(Violation {
+                    api: api.to_string(),
+                    used_as,
+                    kind,
+                    line: path.span().start().line,
+                });
+            }
+            return;
+        }
+    }
+
+    // Report the unsafe code or lint attribute unless the policy allows it.
+    fn deny(&mut self, kind: Kind, api: &str, used_as: String, span: proc_macro2::Span) {
+        if !self.policy.allows(kind) {
+            self.violations.push(Violation {
+                api: api.to_string(),
+                used_as,
+                kind,
+                line: span.start().line,
+            });
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Scanner {
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        let outer = self.in_temp_stmt;
+        self.in_temp_stmt = outer || self.mentions_temp(stmt);
+        visit::visit_stmt(self, stmt);
+        if let (Stmt::Local(local), true) = (stmt, self.in_temp_stmt) {
+            bound_names(local, &mut self.temp_vars);
+        }
+        self.in_temp_stmt = outer;
+    }
+
+    fn visit_expr_unsafe(&mut self, expr: &'ast syn::ExprUnsafe) {
+        let span = expr.unsafe_token.span;
+        self.deny(Kind::Unsafe, "unsafe", "unsafe { ... }".to_string(), span);
+        visit::visit_expr_unsafe(self, expr);
+    }
+
+    fn visit_signature(&mut self, sig: &'ast syn::Signature) {
+        if let Some(unsafety) = &sig.unsafety {
+            let used_as = format!("unsafe fn {}", sig.ident);
+            self.deny(Kind::Unsafe, "unsafe", used_as, unsafety.span);
+        }
+        visit::visit_signature(self, sig);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        if let Some(unsafety) = &item.unsafety {
+            self.deny(
+                Kind::Unsafe,
+                "unsafe",
+                "unsafe impl".to_string(),
+                unsafety.span,
+            );
+        }
+        visit::visit_item_impl(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        if let Some(unsafety) = &item.unsafety {
+            let used_as = format!("unsafe trait {}", item.ident);
+            self.deny(Kind::Unsafe, "unsafe", used_as, unsafety.span);
+        }
+        visit::visit_item_trait(self, item);
+    }
+
+    fn visit_attribute(&mut self, attr: &'ast syn::Attribute) {
+        if matches!(attr.style, AttrStyle::Inner(_)) && attr.path().is_ident("allow") {
+            let lints = match &attr.meta {
+                syn::Meta::List(list) => list.tokens.to_string().replace(" ,", ","),
+                _ => String::new(),
+            };
+            let used_as = format!("#![allow({})]", lints);
+            self.deny(Kind::LintAllow, "#![allow]", used_as, attr.span());
+        }
+        visit::visit_attribute(self, attr);
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        self.check(path);
+        visit::visit_path(self, path);
+    }
+
+    // The arguments of macros such as assert!() and println!() are expressions, which are scanned
+    // like the rest of the code.
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        if let Ok(args) = mac.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated) {
+            args.iter().for_each(|arg| self.visit_expr(arg));
+        }
+    }
+}
+
+fn bound_names(local: &Local, names: &mut HashSet<String>) {
+    fn walk(pat: &Pat, names: &mut HashSet<String>) {
+        match pat {
+            Pat::Ident(ident) => {
+                names.insert(ident.ident.to_string());
+            }
+            Pat::Type(typed) => walk(&typed.pat, names),
+            Pat::Tuple(tuple) => tuple.elems.iter().for_each(|p| walk(p, names)),
+            _ => {}
+        }
+    }
+    walk(&local.pat, names);
+}
+
+// The identifiers in a piece of code, including those in macro arguments.
+#[derive(Default)]
+struct Words(Vec<String>);
+
+impl<'ast> Visit<'ast> for Words {
+    fn visit_ident(&mut self, ident: &'ast syn::Ident) {
+        self.0.push(ident.to_string());
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        visit::visit_macro(self, mac);
+        let tokens = mac.tokens.to_string();
+        let words = tokens.split(|c: char| !c.is_alphanumeric() && c != '_');
+        self.0
+            .extend(words.filter(|w| !w.is_empty()).map(str::to_string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apis(policy: Policy, code: &str) -> Vec<String> {
+        policy.scan(code).into_iter().map(|v| v.used_as).collect()
+    }
+
+    #[test]
+    fn test_scan() {
+        let code = r#"
+            use std::process::Command;
+            use std::{fs, net as network};
+            use std::io::prelude::*;
+
+            fn run() -> String {
+                let out = Command::new("ls").output().unwrap();
+                String::from_utf8(out.stdout).unwrap()
+            }
+
+            fn connect() {
+                let _ = network::TcpStream::connect("example.com:80");
+            }
+
+            #[cfg(test)]
+            mod tests {
+                use super::*;
+                use std::fs::File;
+
+                #[test]
+                fn test_files() {
+                    let path = std::env::temp_dir().join("scratch.txt");
+                    fs::write(&path, "ok").unwrap();
+                    assert!(fs::remove_file(&path).is_ok());
+                    let _ = File::open("/etc/hosts");
+                    assert!(File::create("/tmp/../etc/passwd").is_ok());
+                    std::fs::remove_dir_all("/home").unwrap();
+                }
+            }
+        "#;
+        assert_eq!(
+            apis(Policy::default(), code),
+            vec![
+                "Command::new",
+                "network::TcpStream::connect",
+                "File::create",
+                "std::fs::remove_dir_all",
+            ]
+        );
+        let policy = Policy {
+            allow_process: true,
+            allow_net: true,
+            ..Policy::default()
+        };
+        assert_eq!(
+            apis(policy, code),
+            vec!["File::create", "std::fs::remove_dir_all"]
+        );
+
+        let violations =
+            Policy::default().scan("use std::process::*;\nfn f() { Command::new(\"sh\"); }");
+        assert_eq!(violations[0].api, "std::process::Command");
+        assert_eq!(
+            violations[0].to_string(),
+            "`Command::new` (std::process::Command) runs another program."
+        );
+        assert!(Policy::default()
+            .scan("use std::net::IpAddr;\nfn f() { let _: IpAddr = \"::1\".parse().unwrap(); }")
+            .is_empty());
+        assert!(Policy::default().scan("fn f( {").is_empty());
+        assert!(Policy::default().review("fn f() {}").is_none());
+    }
+
+    #[test]
+    fn test_scan_unsafe() {
+        let code = r#"#![allow(dead_code, unused)]
+
+struct Buffer(*mut u8);
+
+unsafe impl Send for Buffer {}
+
+unsafe fn read(p: *const u8) -> u8 {
+    *p
+}
+
+fn main() {
+    let x = 1u8;
+    #[allow(unused_variables)]
+    let y = unsafe { read(&x) };
+    assert_eq!(unsafe { read(&x) }, 1);
+}
+"#;
+        let violations: Vec<String> = Policy::default()
+            .scan(code)
+            .iter()
+            .map(Violation::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                "`#![allow(dead_code, unused)]` on line 1 silences the compiler's warnings for \
+                 the whole file.",
+                "`unsafe impl` on line 5 is unsafe code.",
+                "`unsafe fn read` on line 7 is unsafe code.",
+                "`unsafe { ... }` on line 14 is unsafe code.",
+                "`unsafe { ... }` on line 15 is unsafe code.",
+            ]
+        );
+        let policy = Policy {
+            allow_unsafe: true,
+            ..Policy::default()
+        };
+        assert!(policy.scan(code).is_empty());
+    }
+}
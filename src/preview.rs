@@ -0,0 +1,154 @@
+use crossterm::style::Stylize;
+
+// A live preview of the code an agent is writing. The agents answer with JSON like
+// `{"code": "fn main() {\n..."}`, streamed a few characters at a time, so the preview decodes the
+// `code` string from the partial JSON received so far and shows its last lines with simple syntax
+// highlighting. Responses without a `code` field, such as the critics', have no preview.
+
+const KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+// Decode the `code` string from the start of a JSON object, stopping at the end of the string or of
+// the input. Return None if the `code` string hasn't started yet.
+pub fn partial_code(json: &str) -> Option<String> {
+    let key = json.find("\"code\"")?;
+    let rest = json[key + "\"code\"".len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+
+    let mut code = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => code.push('\n'),
+                Some('t') => code.push('\t'),
+                Some('r') => {}
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let decoded = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                    match decoded.filter(|_| hex.len() == 4) {
+                        Some(c) => code.push(c),
+                        // The escape is incomplete, so wait for the next chunk.
+                        None => break,
+                    }
+                }
+                Some(c) => code.push(c),
+                None => break,
+            },
+            c => code.push(c),
+        }
+    }
+    Some(code)
+}
+
+// Return the last `n` lines of the code.
+pub fn tail(code: &str, n: usize) -> Vec<&str> {
+    let lines: Vec<_> = code.lines().collect();
+    lines[lines.len().saturating_sub(n)..].to_vec()
+}
+
+// Highlight a line of Rust with terminal colors: comments, strings, numbers, and keywords. Each
+// line is highlighted on its own, so multi-line strings and comments are not recognized.
+pub fn highlight(line: &str) -> String {
+    if let Some(start) = comment_start(line) {
+        return format!(
+            "{}{}",
+            highlight(&line[..start]),
+            line[start..].to_string().dark_grey()
+        );
+    }
+
+    let mut out = String::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '"' {
+            let mut end = line.len();
+            let mut escaped = false;
+            for (j, d) in chars.by_ref() {
+                if d == '"' && !escaped {
+                    end = j + 1;
+                    break;
+                }
+                escaped = d == '\\' && !escaped;
+            }
+            out.push_str(&line[i..end].to_string().green().to_string());
+        } else if c.is_alphanumeric() || c == '_' {
+            let mut end = i + c.len_utf8();
+            while let Some(&(j, d)) = chars.peek() {
+                if !(d.is_alphanumeric() || d == '_') {
+                    break;
+                }
+                end = j + d.len_utf8();
+                chars.next();
+            }
+            let word = &line[i..end];
+            if KEYWORDS.contains(&word) {
+                out.push_str(&word.to_string().magenta().to_string());
+            } else if c.is_ascii_digit() {
+                out.push_str(&word.to_string().cyan().to_string());
+            } else {
+                out.push_str(word);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// Return the byte offset of a `//` comment that is outside of any string in the line.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    let bytes = line.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'"' if !escaped => in_string = !in_string,
+            b'/' if !in_string && bytes.get(i + 1) == Some(&b'/') => return Some(i),
+            _ => {}
+        }
+        escaped = b == b'\\' && !escaped;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_code() {
+        assert_eq!(partial_code("{\"co"), None);
+        assert_eq!(partial_code("{\"code\": "), None);
+        assert_eq!(
+            partial_code("{\"code\": \"fn main() {\\n    println!(\\\"hi"),
+            Some("fn main() {\n    println!(\"hi".to_string())
+        );
+        assert_eq!(
+            partial_code("{\"code\":\"let x = 1;\", \"other\": \"ignored\"}"),
+            Some("let x = 1;".to_string())
+        );
+        // An escape split across chunks is left for the next chunk.
+        assert_eq!(partial_code("{\"code\": \"a\\u00"), Some("a".to_string()));
+        assert_eq!(
+            partial_code("{\"code\": \"\\u00e9\""),
+            Some("é".to_string())
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        let line = "let s = \"// not a comment\"; // a comment";
+        let highlighted = highlight(line);
+        assert!(highlighted.contains(&"let".magenta().to_string()));
+        assert!(highlighted.contains(&"\"// not a comment\"".green().to_string()));
+        assert!(highlighted.contains(&"// a comment".dark_grey().to_string()));
+        assert_eq!(tail("a\nb\nc", 2), vec!["b", "c"]);
+    }
+}
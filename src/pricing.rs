@@ -0,0 +1,132 @@
+use crate::events::RunEvent;
+use std::collections::HashMap;
+
+// The list prices of the models, in dollars per million prompt and completion tokens, to estimate
+// the cost of the runs. Model names are matched by their longest listed prefix, so dated versions
+// such as gpt-4o-2024-08-06 match their family. Unlisted models are counted as free, which
+// `is_priced` lets a run warn of.
+const PRICES: [(&str, f64, f64); 9] = [
+    ("gpt-4-1106-preview", 10.0, 30.0),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("gemini-1.5-pro", 1.25, 5.0),
+    ("gemini-1.5-flash", 0.075, 0.3),
+    ("gemini-2.0-flash", 0.1, 0.4),
+];
+
+fn prices(model: &str) -> Option<(f64, f64)> {
+    PRICES
+        .iter()
+        .filter(|(prefix, _, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _, _)| prefix.len())
+        .map(|&(_, prompt, completion)| (prompt, completion))
+}
+
+pub fn is_priced(model: &str) -> bool {
+    prices(model).is_some()
+}
+
+pub fn prompt_cost(model: &str, tokens: u64) -> f64 {
+    prices(model).map_or(0.0, |(prompt, _)| prompt * tokens as f64 / 1e6)
+}
+
+pub fn completion_cost(model: &str, tokens: u64) -> f64 {
+    prices(model).map_or(0.0, |(_, completion)| completion * tokens as f64 / 1e6)
+}
+
+// The tokens and estimated cost of a run's requests so far, counted from its events as they're
+// emitted, for the convergence policies to stop a run that's spent too much.
+#[derive(Clone, Debug, Default)]
+pub struct Spend {
+    // Including the prompts resent by retries.
+    pub prompt_tokens: u64,
+    // Counted as streamed chunks.
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+    // The model and prompt tokens of each agent's latest request, which a retry sends again.
+    requests: HashMap<String, (String, u64)>,
+}
+
+impl Spend {
+    pub fn record(&mut self, event: &RunEvent) {
+        match event {
+            RunEvent::ChatRequested {
+                agent,
+                model,
+                prompt_tokens,
+                ..
+            } => {
+                let tokens = *prompt_tokens as u64;
+                self.add_prompt(model, tokens);
+                self.requests.insert(agent.clone(), (model.clone(), tokens));
+            }
+            RunEvent::ChatRetried { agent, .. } => {
+                if let Some((model, tokens)) = self.requests.get(agent).cloned() {
+                    self.add_prompt(&model, tokens);
+                }
+            }
+            RunEvent::ChatResponded { agent, chunks, .. } => {
+                let tokens = *chunks as u64;
+                self.completion_tokens += tokens;
+                if let Some((model, _)) = self.requests.get(agent) {
+                    self.cost_usd += completion_cost(model, tokens);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn add_prompt(&mut self, model: &str, tokens: u64) {
+        self.prompt_tokens += tokens;
+        self.cost_usd += prompt_cost(model, tokens);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_costs() {
+        assert_eq!(prompt_cost("gpt-4-1106-preview", 1_000_000), 10.0);
+        assert_eq!(completion_cost("gpt-4o-2024-08-06", 1_000_000), 10.0);
+        assert_eq!(completion_cost("gpt-4o-mini", 1_000_000), 0.6);
+        assert_eq!(prompt_cost("my-deployment", 1_000_000), 0.0);
+        assert!(is_priced("gpt-4o-2024-08-06"));
+        assert!(!is_priced("my-deployment"));
+    }
+
+    #[test]
+    fn test_spend() {
+        let mut spend = Spend::default();
+        let events = [
+            RunEvent::ChatRequested {
+                agent: "Coder".to_string(),
+                model: "gpt-4o".to_string(),
+                messages: vec![],
+                prompt_tokens: 400_000,
+            },
+            RunEvent::ChatRetried {
+                agent: "Coder".to_string(),
+                attempt: 1,
+            },
+            RunEvent::ChatResponded {
+                agent: "Coder".to_string(),
+                response: String::new(),
+                finish_reason: None,
+                chunks: 100_000,
+            },
+            RunEvent::ChatRetried {
+                agent: "Critic_1".to_string(),
+                attempt: 1,
+            },
+        ];
+        events.iter().for_each(|event| spend.record(event));
+        assert_eq!(spend.prompt_tokens, 800_000);
+        assert_eq!(spend.completion_tokens, 100_000);
+        assert!((spend.cost_usd - 3.0).abs() < 1e-9);
+    }
+}
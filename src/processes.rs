@@ -0,0 +1,236 @@
+use std::io;
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::Notify;
+
+// The compilers, test binaries, and coverage tools started by the run are tracked here so that they
+// can be killed when the run is cancelled. Otherwise a test stuck in an infinite loop would keep
+// running after ai_critics exits. The processes are awaited on the tokio runtime rather than
+// blocking it, so a long compile doesn't stall the other tasks, and several processes can run at
+// once. A TrackedChild registers a kill signal while it is alive, which drops out of the registry
+// when it is dropped. Dropping a TrackedChild also kills its process, e.g. when the future waiting
+// on it is cancelled.
+
+static RUNNING: Mutex<Vec<Weak<Notify>>> = Mutex::new(vec![]);
+
+pub struct TrackedChild {
+    child: Child,
+    kill: Arc<Notify>,
+}
+
+impl TrackedChild {
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        let child = command.kill_on_drop(true).spawn()?;
+        let kill = Arc::new(Notify::new());
+        let mut running = RUNNING.lock().unwrap();
+        running.retain(|k| k.strong_count() > 0);
+        running.push(Arc::downgrade(&kill));
+        Ok(TrackedChild { child, kill })
+    }
+
+    pub async fn kill(&mut self) -> io::Result<ExitStatus> {
+        self.child.kill().await?;
+        self.child.wait().await
+    }
+
+    // Wait for the process to exit, killing it if kill_all() is called in the meantime.
+    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
+        tokio::select! {
+            status = self.child.wait() => status,
+            _ = self.kill.notified() => self.kill().await,
+        }
+    }
+}
+
+async fn read_to_end(pipe: Option<impl AsyncRead + Unpin>) -> Vec<u8> {
+    let mut buf = vec![];
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut buf).await;
+    }
+    buf
+}
+
+// Read the pipe until it ends or passes the limit. A pipe that passes it is cut off at the limit
+// with a marker, and the process is killed so that it stops writing. Return whether it was cut off.
+async fn read_limited(
+    pipe: Option<impl AsyncRead + Unpin>,
+    limit: usize,
+    kill: &Notify,
+) -> (Vec<u8>, bool) {
+    let mut buf = vec![];
+    let Some(pipe) = pipe else {
+        return (buf, false);
+    };
+    let mut pipe = pipe.take(limit as u64 + 1);
+    let _ = pipe.read_to_end(&mut buf).await;
+    if buf.len() <= limit {
+        return (buf, false);
+    }
+    buf.truncate(limit);
+    buf.extend_from_slice(
+        format!(
+            "\n[The output was cut off at {} bytes and the process was killed.]\n",
+            limit
+        )
+        .as_bytes(),
+    );
+    kill.notify_one();
+    (buf, true)
+}
+
+// The signal that killed the process, if it crashed, as on a segfault or an abort. The processes
+// that kill_all() kills exit on SIGKILL, and didn't crash.
+#[cfg(unix)]
+pub fn crash_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    const SIGKILL: i32 = 9;
+    status.signal().filter(|&signal| signal != SIGKILL)
+}
+
+#[cfg(not(unix))]
+pub fn crash_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+// Like Command::output(), but the process is tracked while it runs.
+pub async fn output(command: &mut Command) -> io::Result<Output> {
+    let mut tracked = TrackedChild::spawn(command.stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    let stdout = tracked.child.stdout.take();
+    let stderr = tracked.child.stderr.take();
+    // Read the pipes while waiting so that a process with a lot of output doesn't block on a full
+    // pipe.
+    let (status, stdout, stderr) =
+        tokio::join!(tracked.wait(), read_to_end(stdout), read_to_end(stderr));
+    Ok(Output {
+        status: status?,
+        stdout,
+        stderr,
+    })
+}
+
+// The output of a process whose stdout and stderr were limited, and whether either passed its limit,
+// which killed the process.
+pub struct LimitedOutput {
+    pub output: Output,
+    pub exceeded: bool,
+}
+
+// As output(), but with the given input, if any, written to the process's stdin, and keeping at most
+// the limit of bytes of stdout and of stderr, as for generated code that may print in a loop. The
+// input is written and the pipes read while waiting, so that neither blocks on a full pipe.
+pub async fn output_limited(
+    command: &mut Command,
+    input: Option<&[u8]>,
+    limit: usize,
+) -> io::Result<LimitedOutput> {
+    if input.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    let mut tracked = TrackedChild::spawn(command.stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    let stdin = tracked.child.stdin.take();
+    let stdout = tracked.child.stdout.take();
+    let stderr = tracked.child.stderr.take();
+    let kill = tracked.kill.clone();
+    let (status, _, (stdout, stdout_exceeded), (stderr, stderr_exceeded)) = tokio::join!(
+        tracked.wait(),
+        write_all(stdin, input),
+        read_limited(stdout, limit, &kill),
+        read_limited(stderr, limit, &kill)
+    );
+    Ok(LimitedOutput {
+        output: Output {
+            status: status?,
+            stdout,
+            stderr,
+        },
+        exceeded: stdout_exceeded || stderr_exceeded,
+    })
+}
+
+async fn write_all(pipe: Option<ChildStdin>, input: Option<&[u8]>) {
+    if let (Some(mut pipe), Some(input)) = (pipe, input) {
+        // A process that exits without reading all of its input closes the pipe, which isn't an
+        // error here. The pipe is closed when dropped, ending the input.
+        let _ = pipe.write_all(input).await;
+    }
+}
+
+// Kill every tracked process that is still running. A process that is between spawning and being
+// awaited is killed once it's awaited.
+pub fn kill_all() {
+    let running: Vec<_> = RUNNING
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for kill in running {
+        kill.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // These run in one test because kill_all() would kill the processes of any test running
+    // concurrently.
+    #[tokio::test]
+    async fn test_output_and_kill_all() {
+        // The shell's builtins stand in for `yes` and `tac`, which not every platform has.
+        let mut yes = Command::new("sh");
+        yes.args(["-c", "while :; do echo y; done"]);
+        let limited = output_limited(&mut yes, None, 1000).await.unwrap();
+        assert!(limited.exceeded);
+        let stdout = String::from_utf8_lossy(&limited.output.stdout);
+        assert!(stdout.starts_with("y\ny\n"));
+        assert!(stdout.ends_with("cut off at 1000 bytes and the process was killed.]\n"));
+        let mut tac = Command::new("sh");
+        tac.args(["-c", "read a; read b; echo $b; echo $a"]);
+        let limited = output_limited(&mut tac, Some(b"1\n2\n"), 1000)
+            .await
+            .unwrap();
+        assert!(!limited.exceeded);
+        assert_eq!(limited.output.stdout, b"2\n1\n");
+
+        let output = output(Command::new("sh").args(["-c", "echo out; echo err >&2; exit 3"]))
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+        assert_eq!(output.status.code(), Some(3));
+
+        // The processes run concurrently.
+        let start = Instant::now();
+        let (mut first, mut second) = (Command::new("sleep"), Command::new("sleep"));
+        let (a, b) = tokio::join!(
+            super::output(first.arg("1")),
+            super::output(second.arg("1"))
+        );
+        assert!(a.unwrap().status.success() && b.unwrap().status.success());
+        assert!(start.elapsed() < Duration::from_millis(1900));
+
+        let start = Instant::now();
+        let handle = tokio::spawn(async {
+            super::output(Command::new("sleep").arg("30"))
+                .await
+                .unwrap()
+        });
+        // Wait for the process to be registered before killing it.
+        while RUNNING
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|k| k.strong_count() == 0)
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        kill_all();
+        let output = handle.await.unwrap();
+        assert!(!output.status.success());
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -1,35 +1,57 @@
+use crate::events::ProgressUpdate;
+use crate::tokenizer::count_tokens;
 use color_eyre::Result;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
 
 const STARTING_MAX: u64 = 50;
+// The expected length of an agent's response is the average of this many of the latest responses of
+// agents of its type.
+const MOVING_AVERAGE_RESPONSES: usize = 5;
 
 #[derive(Debug, Clone)]
 pub struct DoublingProgressBar {
     progress_bar: ProgressBar,
+    // The tokens received so far.
     current_progress: u64,
     max_value: u64,
+    // The tokens that the response is expected to have, if there's an estimate.
+    expected: Option<u64>,
+    retries: usize,
+    // When the current attempt at the response started.
+    started: Instant,
 }
 
 // The DoublingProgressBar struct is a progress bar for open-ended tasks. Instead of progressing
 // toward a known, fixed, maximum value, the progress bar will progress toward a maximum value that
 // is twice the current value. This allows the progress bar to be used for tasks that have an
-// unknown number of steps. The progress bar will start with a maximum value of STARTING_MAX. The
-// effect of the doubling is that each time it reaches the current end of the bar, it drops back to
-// the halfway point and then continues to grow at half the speed as it did previously.
+// unknown number of steps. The progress bar will start with a maximum value of STARTING_MAX, or of
+// the expected number of tokens if there's an estimate. The effect of the doubling is that each
+// time it reaches the current end of the bar, it drops back to the halfway point and then continues
+// to grow at half the speed as it did previously. The bar counts the tokens of the response, and
+// shows the rate at which they arrive, the time left if the length is estimated, and the retries.
 impl DoublingProgressBar {
-    pub fn new(name: &str) -> Result<Self> {
-        let progress_bar = ProgressBar::new(STARTING_MAX);
-        Self::initialize(progress_bar, name)
+    pub fn new_multi(
+        multi_progress: &MultiProgress,
+        name: &str,
+        expected: Option<u64>,
+    ) -> Result<Self> {
+        let progress_bar = multi_progress.add(ProgressBar::new(STARTING_MAX));
+        Self::initialize(progress_bar, name, expected)
     }
 
-    pub fn new_multi(multi_progress: &MultiProgress, name: &str) -> Result<Self> {
-        let progress_bar = multi_progress.add(ProgressBar::new(STARTING_MAX));
-        Self::initialize(progress_bar, name)
+    // A progress bar that tracks progress without drawing, for displays such as the dashboard that
+    // render the bar themselves.
+    pub fn new_hidden(name: &str, expected: Option<u64>) -> Result<Self> {
+        let progress_bar =
+            ProgressBar::with_draw_target(Some(STARTING_MAX), ProgressDrawTarget::hidden());
+        Self::initialize(progress_bar, name, expected)
     }
 
-    fn initialize(progress_bar: ProgressBar, name: &str) -> Result<Self> {
+    fn initialize(progress_bar: ProgressBar, name: &str, expected: Option<u64>) -> Result<Self> {
         let template = format!(
-            "{{spinner:.green}} {} [{{elapsed_precise}}] [{{wide_bar:.cyan/blue}}] {{pos}} chunks received",
+            "{{spinner:.green}} {} [{{elapsed_precise}}] [{{wide_bar:.cyan/blue}}] {{msg}}",
             name
         );
         progress_bar.set_style(
@@ -37,20 +59,27 @@ impl DoublingProgressBar {
                 .template(&template)?
                 .progress_chars("=▷-"),
         );
+        let max_value = expected.unwrap_or(STARTING_MAX).max(1);
+        progress_bar.set_length(max_value);
 
-        Ok(DoublingProgressBar {
+        let bar = DoublingProgressBar {
             progress_bar,
             current_progress: 0,
-            max_value: STARTING_MAX,
-        })
+            max_value,
+            expected,
+            retries: 0,
+            started: Instant::now(),
+        };
+        bar.progress_bar.set_message(bar.status());
+        Ok(bar)
     }
 
-    // Increment the progress, doubling the max value if needed.
-    pub fn inc(&mut self) {
-        self.current_progress += 1;
-        self.progress_bar.inc(1);
+    // Increment the progress by the tokens, doubling the max value if needed.
+    pub fn inc(&mut self, tokens: u64) {
+        self.current_progress += tokens;
+        self.progress_bar.inc(tokens);
 
-        if self.current_progress >= self.max_value {
+        while self.current_progress >= self.max_value {
             self.max_value *= 2;
             self.progress_bar.set_length(self.max_value);
         }
@@ -74,9 +103,127 @@ impl DoublingProgressBar {
     pub fn reset_to_zero(&mut self) {
         self.progress_bar.reset();
         self.current_progress = 0;
-        self.max_value = STARTING_MAX;
+        self.max_value = self.expected.unwrap_or(STARTING_MAX).max(1);
+        self.progress_bar.set_length(self.max_value);
+        self.started = Instant::now();
+    }
+
+    // Apply a progress update reported by an agent. Notes are left to the caller to display.
+    pub fn apply(&mut self, update: &ProgressUpdate) {
+        match update {
+            ProgressUpdate::Chunk { text } => self.inc(count_tokens(text).max(1) as u64),
+            ProgressUpdate::BlankChunk { .. } => self.dec(),
+            ProgressUpdate::Reset => self.reset_to_zero(),
+            ProgressUpdate::Note { .. } => return,
+        }
+        self.progress_bar.set_message(self.status());
+    }
+
+    // Record that the request is being retried for the given time.
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+        self.progress_bar.set_message(self.status());
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.current_progress
     }
-    pub fn println(self, message: &str) {
-        self.progress_bar.println(message);
+
+    // The tokens received, their rate, the time left if it can be estimated, and the retries, e.g.
+    // "240 tokens, 31 tok/s, ~9s left, retry 1".
+    pub fn status(&self) -> String {
+        let secs = self.started.elapsed().as_secs_f64();
+        let rate = match secs > 0.0 {
+            true => self.current_progress as f64 / secs,
+            false => 0.0,
+        };
+        let mut status = format!("{} tokens, {:.0} tok/s", self.current_progress, rate);
+        if let Some(expected) = self.expected.filter(|_| rate > 0.0) {
+            let left = expected.saturating_sub(self.current_progress) as f64 / rate;
+            status.push_str(&format!(", ~{:.0}s left", left));
+        }
+        if self.retries > 0 {
+            status.push_str(&format!(", retry {}", self.retries));
+        }
+        status
+    }
+
+    pub fn bar(&self) -> ProgressBar {
+        self.progress_bar.clone()
+    }
+
+    pub fn finish_and_clear(&self) {
+        self.progress_bar.finish_and_clear();
+    }
+}
+
+// The tokens of the latest responses of each type of agent, from which the length of the next
+// response of an agent of that type is estimated.
+#[derive(Debug, Default)]
+pub struct ResponseEstimates {
+    recent: HashMap<String, VecDeque<u64>>,
+}
+
+impl ResponseEstimates {
+    pub fn record(&mut self, agent: &str, tokens: u64) {
+        if tokens == 0 {
+            return;
+        }
+        let recent = self.recent.entry(agent_type(agent)).or_default();
+        recent.push_back(tokens);
+        if recent.len() > MOVING_AVERAGE_RESPONSES {
+            recent.pop_front();
+        }
+    }
+
+    // The average tokens of the latest responses of the agent's type, if there are any.
+    pub fn expected(&self, agent: &str) -> Option<u64> {
+        let recent = self.recent.get(&agent_type(agent))?;
+        Some(recent.iter().sum::<u64>() / recent.len() as u64)
+    }
+}
+
+// The type of an agent, from its name without its number or window, so that "Design Critic 2
+// (lines 1-80)" is a "Design Critic" and "Fixer_1" is a "Fixer".
+fn agent_type(agent: &str) -> String {
+    let name = agent.split(" (").next().unwrap_or(agent);
+    name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_' || c == ' ')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_estimates() {
+        let mut estimates = ResponseEstimates::default();
+        assert_eq!(estimates.expected("Fixer_1"), None);
+        estimates.record("Fixer_1", 100);
+        estimates.record("Fixer_2", 300);
+        estimates.record("Design Critic 1 (lines 1-80)", 40);
+        assert_eq!(estimates.expected("Fixer_3"), Some(200));
+        assert_eq!(estimates.expected("Design Critic 2"), Some(40));
+        assert_eq!(estimates.expected("Syntax Critic 1"), None);
+        for _ in 0..MOVING_AVERAGE_RESPONSES {
+            estimates.record("Fixer_1", 50);
+        }
+        assert_eq!(estimates.expected("Fixer_1"), Some(50));
+    }
+
+    #[test]
+    fn test_status() {
+        let mut bar = DoublingProgressBar::new_hidden("Fixer_1", Some(100)).unwrap();
+        bar.apply(&ProgressUpdate::Chunk {
+            text: "fn main() {}".to_string(),
+        });
+        assert!(bar.tokens() > 1);
+        bar.set_retries(1);
+        let status = bar.status();
+        assert!(status.starts_with(&format!("{} tokens, ", bar.tokens())));
+        assert!(status.contains("s left") && status.ends_with(", retry 1"));
+        bar.apply(&ProgressUpdate::Reset);
+        assert_eq!(bar.tokens(), 0);
+        assert_eq!(bar.bar().length(), Some(100));
     }
 }
@@ -0,0 +1,296 @@
+use crate::errors::AiCriticError;
+use color_eyre::eyre::Result;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// The agents' prompts are templates in the prompts/ directory, compiled in as the defaults. With
+// --prompts-dir, a file of the same name in that directory replaces the default, so prompts can be
+// tuned without recompiling. Files that are left out keep their defaults. The templates are loaded
+// once per run and may use these variables:
+//   {{language}}  the programming language of the solution
+//   {{problem}}   the problem statement
+// An unknown variable, or a file that isn't one of the prompts, is an error rather than being sent
+// to the model as is.
+
+const LANGUAGE: &str = "Rust";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Prompt {
+    CriticBase,
+    CriticGeneral,
+    CriticDesign,
+    CriticCorrectness,
+    CriticSyntax,
+    CriticIdiomatic,
+    CriticVerification,
+    CriticWindow,
+    CriticBatch,
+    Coder,
+    CoderSelfReview,
+    Fixer,
+    FixerCodeReview,
+    FixerCompileFix,
+    FixerTestFix,
+    FixerFlakyTestFix,
+    FixerRuntimeFix,
+    FixerComplexityFix,
+    FixerTestFixWindow,
+    FixerTestStrengthening,
+    FixerTestAugmentation,
+    FixerPolicyFix,
+    FixerResolvedIssues,
+    FixerAnchors,
+    Ranker,
+}
+
+// Each prompt's file name and default template.
+const TEMPLATES: [(Prompt, &str, &str); 25] = [
+    (
+        Prompt::CriticBase,
+        "critic_base.txt",
+        include_str!("../prompts/critic_base.txt"),
+    ),
+    (
+        Prompt::CriticGeneral,
+        "critic_general.txt",
+        include_str!("../prompts/critic_general.txt"),
+    ),
+    (
+        Prompt::CriticDesign,
+        "critic_design.txt",
+        include_str!("../prompts/critic_design.txt"),
+    ),
+    (
+        Prompt::CriticCorrectness,
+        "critic_correctness.txt",
+        include_str!("../prompts/critic_correctness.txt"),
+    ),
+    (
+        Prompt::CriticSyntax,
+        "critic_syntax.txt",
+        include_str!("../prompts/critic_syntax.txt"),
+    ),
+    (
+        Prompt::CriticIdiomatic,
+        "critic_idiomatic.txt",
+        include_str!("../prompts/critic_idiomatic.txt"),
+    ),
+    (
+        Prompt::CriticVerification,
+        "critic_verification.txt",
+        include_str!("../prompts/critic_verification.txt"),
+    ),
+    (
+        Prompt::CriticWindow,
+        "critic_window.txt",
+        include_str!("../prompts/critic_window.txt"),
+    ),
+    (
+        Prompt::CriticBatch,
+        "critic_batch.txt",
+        include_str!("../prompts/critic_batch.txt"),
+    ),
+    (
+        Prompt::Coder,
+        "coder.txt",
+        include_str!("../prompts/coder.txt"),
+    ),
+    (
+        Prompt::CoderSelfReview,
+        "coder_self_review.txt",
+        include_str!("../prompts/coder_self_review.txt"),
+    ),
+    (
+        Prompt::Fixer,
+        "fixer.txt",
+        include_str!("../prompts/fixer.txt"),
+    ),
+    (
+        Prompt::FixerCodeReview,
+        "fixer_code_review.txt",
+        include_str!("../prompts/fixer_code_review.txt"),
+    ),
+    (
+        Prompt::FixerCompileFix,
+        "fixer_compile_fix.txt",
+        include_str!("../prompts/fixer_compile_fix.txt"),
+    ),
+    (
+        Prompt::FixerTestFix,
+        "fixer_test_fix.txt",
+        include_str!("../prompts/fixer_test_fix.txt"),
+    ),
+    (
+        Prompt::FixerFlakyTestFix,
+        "fixer_flaky_test_fix.txt",
+        include_str!("../prompts/fixer_flaky_test_fix.txt"),
+    ),
+    (
+        Prompt::FixerRuntimeFix,
+        "fixer_runtime_fix.txt",
+        include_str!("../prompts/fixer_runtime_fix.txt"),
+    ),
+    (
+        Prompt::FixerComplexityFix,
+        "fixer_complexity_fix.txt",
+        include_str!("../prompts/fixer_complexity_fix.txt"),
+    ),
+    (
+        Prompt::FixerTestFixWindow,
+        "fixer_test_fix_window.txt",
+        include_str!("../prompts/fixer_test_fix_window.txt"),
+    ),
+    (
+        Prompt::FixerTestStrengthening,
+        "fixer_test_strengthening.txt",
+        include_str!("../prompts/fixer_test_strengthening.txt"),
+    ),
+    (
+        Prompt::FixerTestAugmentation,
+        "fixer_test_augmentation.txt",
+        include_str!("../prompts/fixer_test_augmentation.txt"),
+    ),
+    (
+        Prompt::FixerPolicyFix,
+        "fixer_policy_fix.txt",
+        include_str!("../prompts/fixer_policy_fix.txt"),
+    ),
+    (
+        Prompt::FixerResolvedIssues,
+        "fixer_resolved_issues.txt",
+        include_str!("../prompts/fixer_resolved_issues.txt"),
+    ),
+    (
+        Prompt::FixerAnchors,
+        "fixer_anchors.txt",
+        include_str!("../prompts/fixer_anchors.txt"),
+    ),
+    (
+        Prompt::Ranker,
+        "ranker.txt",
+        include_str!("../prompts/ranker.txt"),
+    ),
+];
+
+// The rendered prompts of a run.
+#[derive(Clone, Debug)]
+pub struct Prompts {
+    texts: HashMap<Prompt, String>,
+}
+
+impl Default for Prompts {
+    // The default prompts, with an empty problem.
+    fn default() -> Self {
+        Prompts::load(None, "").expect("the default prompt templates are valid")
+    }
+}
+
+impl Prompts {
+    // Render the prompts for the problem, reading the templates in the directory, if given, in place
+    // of the defaults.
+    pub fn load(dir: Option<&Path>, problem: &str) -> Result<Self> {
+        if let Some(dir) = dir {
+            check_dir(dir)?;
+        }
+        let vars = HashMap::from([("language", LANGUAGE), ("problem", problem.trim())]);
+        let mut texts = HashMap::new();
+        for (prompt, filename, default) in TEMPLATES {
+            let path = dir.map(|dir| dir.join(filename));
+            let template = match &path {
+                Some(path) if path.is_file() => fs::read_to_string(path)?,
+                _ => default.to_string(),
+            };
+            let source = path.map_or(filename.to_string(), |p| p.display().to_string());
+            texts.insert(prompt, render(&template, &vars, &source)?);
+        }
+        Ok(Prompts { texts })
+    }
+
+    pub fn get(&self, prompt: Prompt) -> &str {
+        &self.texts[&prompt]
+    }
+
+    // Add a paragraph to the end of each of the prompts, for a requirement that comes from the run
+    // rather than from the problem statement.
+    pub fn with_note(mut self, prompts: &[Prompt], note: &str) -> Self {
+        for prompt in prompts {
+            if let Some(text) = self.texts.get_mut(prompt) {
+                text.push('\n');
+                text.push_str(note);
+            }
+        }
+        self
+    }
+}
+
+// Check that each file in the directory is a prompt, catching misspelled file names, which would
+// otherwise be ignored.
+fn check_dir(dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if !TEMPLATES.iter().any(|(_, filename, _)| *filename == name) {
+            let known: Vec<&str> = TEMPLATES.iter().map(|(_, filename, _)| *filename).collect();
+            return Err(AiCriticError::InvalidConfig {
+                path: dir.join(&name).display().to_string(),
+                reason: format!("not a prompt file. The prompts are {}", known.join(", ")),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+fn render(template: &str, vars: &HashMap<&str, &str>, source: &str) -> Result<String> {
+    let re = Regex::new(r"\{\{\s*(\w+)\s*\}\}")?;
+    if let Some(unknown) = re
+        .captures_iter(template)
+        .map(|c| c[1].to_string())
+        .find(|name| !vars.contains_key(name.as_str()))
+    {
+        return Err(AiCriticError::InvalidConfig {
+            path: source.to_string(),
+            reason: format!("unknown variable {{{{{}}}}}", unknown),
+        }
+        .into());
+    }
+    let text = re.replace_all(template, |c: &regex::Captures| vars[&c[1]].to_string());
+    Ok(text.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let defaults = Prompts::default();
+        assert!(defaults
+            .get(Prompt::Coder)
+            .starts_with("Write the requested program in Rust."));
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("coder.txt"),
+            "Solve this in {{ language }}:\n{{problem}}\n",
+        )
+        .unwrap();
+        let prompts = Prompts::load(Some(dir.path()), "Add two numbers.\n").unwrap();
+        assert_eq!(
+            prompts.get(Prompt::Coder),
+            "Solve this in Rust:\nAdd two numbers."
+        );
+        assert_eq!(
+            prompts.get(Prompt::Fixer),
+            defaults.get(Prompt::Fixer),
+            "The prompts without files keep their defaults."
+        );
+
+        fs::write(dir.path().join("fixer.txt"), "Fix the {{code}}.").unwrap();
+        assert!(Prompts::load(Some(dir.path()), "").is_err());
+        fs::remove_file(dir.path().join("fixer.txt")).unwrap();
+        fs::write(dir.path().join("critic.txt"), "Review.").unwrap();
+        assert!(Prompts::load(Some(dir.path()), "").is_err());
+    }
+}
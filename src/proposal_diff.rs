@@ -0,0 +1,163 @@
+use crate::anchors;
+use serde::{Deserialize, Serialize};
+use similar::{DiffTag, TextDiff};
+use std::collections::BTreeMap;
+
+// What changed from one proposal to the next, kept in the transcript and the run's result so that
+// the changes can be analyzed after the run, such as how many lines a critic's correction changes.
+// The diff is structured rather than a unified diff: the lines that each hunk removed and added,
+// with their line numbers, and the functions that were added, removed, or modified, by their names
+// qualified by their impl's type. A function is modified if any of its lines changed.
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct ProposalDiff {
+    // The proposal that the diff leads to, from the one before it.
+    pub proposal: usize,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    pub hunks: Vec<Hunk>,
+    pub functions: Vec<FunctionChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Hunk {
+    // The first line of the hunk in the old and new code, counting from 1.
+    pub old_start: usize,
+    pub new_start: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FunctionChange {
+    pub name: String,
+    pub change: Change,
+}
+
+impl ProposalDiff {
+    pub fn new(proposal: usize, old: &str, new: &str) -> Self {
+        let old_lines: Vec<&str> = old.lines().collect();
+        let new_lines: Vec<&str> = new.lines().collect();
+        let hunks: Vec<Hunk> = TextDiff::from_slices(&old_lines, &new_lines)
+            .ops()
+            .iter()
+            .filter(|op| op.tag() != DiffTag::Equal)
+            .map(|op| {
+                let (old_range, new_range) = (op.old_range(), op.new_range());
+                Hunk {
+                    old_start: old_range.start + 1,
+                    new_start: new_range.start + 1,
+                    removed: lines(&old_lines[old_range]),
+                    added: lines(&new_lines[new_range]),
+                }
+            })
+            .collect();
+        ProposalDiff {
+            proposal,
+            lines_added: hunks.iter().map(|h| h.added.len()).sum(),
+            lines_removed: hunks.iter().map(|h| h.removed.len()).sum(),
+            functions: function_changes(old, new),
+            hunks,
+        }
+    }
+
+    // A one-line summary, such as "+3 -1 lines, modified push, added Stack::peek".
+    pub fn summary(&self) -> String {
+        let mut parts = vec![format!(
+            "+{} -{} lines",
+            self.lines_added, self.lines_removed
+        )];
+        for (change, label) in [
+            (Change::Modified, "modified"),
+            (Change::Added, "added"),
+            (Change::Removed, "removed"),
+        ] {
+            let names: Vec<&str> = self
+                .functions
+                .iter()
+                .filter(|f| f.change == change)
+                .map(|f| f.name.as_str())
+                .collect();
+            if !names.is_empty() {
+                parts.push(format!("{} {}", label, names.join(", ")));
+            }
+        }
+        parts.join(", ")
+    }
+}
+
+fn lines(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| line.to_string()).collect()
+}
+
+// The functions that differ between the code's versions, in the order of their names. Code that
+// doesn't parse has no functions.
+fn function_changes(old: &str, new: &str) -> Vec<FunctionChange> {
+    let (old, new) = (function_sources(old), function_sources(new));
+    let mut changes = vec![];
+    for (name, source) in old.iter() {
+        match new.get(name) {
+            None => changes.push((name, Change::Removed)),
+            Some(new_source) if new_source != source => changes.push((name, Change::Modified)),
+            Some(_) => {}
+        }
+    }
+    for name in new.keys().filter(|name| !old.contains_key(*name)) {
+        changes.push((name, Change::Added));
+    }
+    changes.sort_by(|a, b| a.0.cmp(b.0));
+    changes
+        .into_iter()
+        .map(|(name, change)| FunctionChange {
+            name: name.clone(),
+            change,
+        })
+        .collect()
+}
+
+// The source of each of the code's functions, by its qualified name.
+fn function_sources(code: &str) -> BTreeMap<String, String> {
+    let lines: Vec<&str> = code.lines().collect();
+    anchors::functions(code)
+        .into_iter()
+        .map(|f| {
+            let source = lines[f.start - 1..f.end.min(lines.len())].join("\n");
+            (f.qualified.unwrap_or(f.name), source)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = "struct Stack;\n\nimpl Stack {\n    fn push(&self) {\n        todo!()\n    \
+                   }\n}\n\nfn old() {}\n\nfn main() {}\n";
+        let new = "struct Stack;\n\nimpl Stack {\n    fn push(&self) {\n        \
+                   println!(\"push\");\n    }\n\n    fn peek(&self) {}\n}\n\nfn main() {}\n";
+        let diff = ProposalDiff::new(2, old, new);
+        assert_eq!(diff.lines_added, 3);
+        assert_eq!(diff.lines_removed, 3);
+        assert_eq!(diff.hunks[0].old_start, 5);
+        assert_eq!(diff.hunks[0].removed, vec!["        todo!()"]);
+        assert_eq!(diff.hunks[0].added, vec!["        println!(\"push\");"]);
+        assert_eq!(
+            diff.summary(),
+            "+3 -3 lines, modified Stack::push, added Stack::peek, removed old"
+        );
+
+        let unchanged = ProposalDiff::new(3, new, new);
+        assert!(unchanged.hunks.is_empty() && unchanged.functions.is_empty());
+        assert_eq!(unchanged.summary(), "+0 -0 lines");
+    }
+}
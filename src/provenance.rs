@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+// Organizations that must track AI-generated code can have the accepted code stamped with where it
+// came from before it's published with --out or --git. A [provenance] table in the config file
+// turns the stamp on and gives the notice that ends it, such as a license:
+//
+//   [provenance]
+//   notice = """
+//   SPDX-License-Identifier: Apache-2.0
+//   Generated code; review before merging."""
+//
+// The stamp is a block of line comments at the top of the file, naming the tool, the run's ID, the
+// models that the run may have used, and the time, in UTC. Solutions outside a run, as from the
+// library's solve(), have no run ID to name.
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProvenanceConfig {
+    #[serde(default)]
+    pub notice: String,
+}
+
+// Where the accepted code came from.
+pub struct Provenance<'a> {
+    pub run_id: Option<&'a str>,
+    pub models: &'a [String],
+    pub time: DateTime<Utc>,
+}
+
+impl Provenance<'_> {
+    // The header of comments for the config, which ends with a blank line.
+    pub fn header(&self, config: &ProvenanceConfig) -> String {
+        let mut lines = vec!["Generated by ai_critics.".to_string()];
+        if let Some(run_id) = self.run_id {
+            lines.push(format!("Run: {}", run_id));
+        }
+        lines.push(match self.models {
+            [model] => format!("Model: {}", model),
+            models => format!("Models: {}", models.join(", ")),
+        });
+        lines.push(format!("Date: {}", self.time.format("%Y-%m-%dT%H:%M:%SZ")));
+        let notice = config.notice.trim();
+        if !notice.is_empty() {
+            lines.push(String::new());
+            lines.extend(notice.lines().map(str::to_string));
+        }
+        let comments: Vec<String> = lines
+            .iter()
+            .map(|line| format!("// {}", line).trim_end().to_string())
+            .collect();
+        format!("{}\n\n", comments.join("\n"))
+    }
+
+    // The code with the header at its top.
+    pub fn stamp(&self, config: &ProvenanceConfig, code: &str) -> String {
+        format!("{}{}", self.header(config), code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stamp() {
+        let provenance = Provenance {
+            run_id: Some("20261016-120000-ab12"),
+            models: &["gpt-4o".to_string()],
+            time: DateTime::from_timestamp(1_792_152_000, 0).unwrap(),
+        };
+        let config = ProvenanceConfig {
+            notice: "SPDX-License-Identifier: MIT\n\nReview before merging.\n".to_string(),
+        };
+        assert_eq!(
+            provenance.stamp(&config, "fn main() {}\n"),
+            "// Generated by ai_critics.\n// Run: 20261016-120000-ab12\n// Model: gpt-4o\n// \
+             Date: 2026-10-16T12:00:00Z\n//\n// SPDX-License-Identifier: MIT\n//\n// Review \
+             before merging.\n\nfn main() {}\n"
+        );
+
+        let provenance = Provenance {
+            run_id: None,
+            ..provenance
+        };
+        let header = provenance.header(&ProvenanceConfig::default());
+        assert_eq!(
+            header,
+            "// Generated by ai_critics.\n// Model: gpt-4o\n// Date: 2026-10-16T12:00:00Z\n\n"
+        );
+
+        let models = ["gpt-4o".to_string(), "gpt-4o-mini".to_string()];
+        let provenance = Provenance {
+            models: &models,
+            ..provenance
+        };
+        let header = provenance.header(&ProvenanceConfig::default());
+        assert!(header.contains("// Models: gpt-4o, gpt-4o-mini\n"));
+    }
+}
@@ -0,0 +1,267 @@
+use crate::capabilities;
+use crate::chatter_json::{SharedClient, StreamTimeouts, MODEL, TEMPERATURE};
+use crate::clients::ClientPool;
+use crate::credentials;
+use crate::errors::AiCriticError;
+use crate::fake::FAKE_MODEL;
+use crate::gemini::DEFAULT_GEMINI_MODEL;
+use crate::request_limiter::RequestLimiter;
+use async_openai::config::{AzureConfig, OpenAIConfig};
+use clap::ValueEnum;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use std::sync::Arc;
+
+// The service that hosts the models. OpenAI's API is authorized by an OpenAI key. An Azure OpenAI
+// deployment is addressed by its resource endpoint, API version, and deployment name, where the
+// deployment also selects the model, and is authorized by an Azure key. Google's Gemini models are
+// authorized by a Gemini key. The keys are found by the credentials module. A Provider is the
+// service along with the settings of the requests sent to it. The fake provider needs no key: it
+// answers offline with canned responses, for development and CI. A seed asks the service to sample
+// deterministically, which OpenAI, Azure, and Gemini make a best effort at.
+
+pub const AZURE_API_VERSION: &str = "2024-02-01";
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Openai,
+    Azure,
+    Gemini,
+    Fake,
+}
+
+// The Azure settings from the command line or their environment variables.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct AzureArgs {
+    /// Azure OpenAI resource endpoint, e.g. https://my-resource.openai.azure.com.
+    #[arg(long, env = "AZURE_OPENAI_ENDPOINT")]
+    pub azure_endpoint: Option<String>,
+
+    /// Azure OpenAI deployment name.
+    #[arg(long, env = "AZURE_OPENAI_DEPLOYMENT")]
+    pub azure_deployment: Option<String>,
+
+    /// Azure OpenAI API version.
+    #[arg(long, env = "AZURE_OPENAI_API_VERSION", default_value = AZURE_API_VERSION)]
+    pub azure_api_version: String,
+}
+
+// The Gemini settings from the command line or their environment variables.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct GeminiArgs {
+    /// Gemini model to use.
+    #[arg(long, env = "GEMINI_MODEL", default_value = DEFAULT_GEMINI_MODEL)]
+    pub gemini_model: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum Service {
+    OpenAi {
+        config: OpenAIConfig,
+        model: String,
+    },
+    Azure {
+        config: AzureConfig,
+        deployment: String,
+    },
+    Gemini {
+        api_key: String,
+        model: String,
+    },
+    Fake,
+}
+
+#[derive(Clone, Debug)]
+pub struct Provider {
+    pub service: Service,
+    pub temperature: f32,
+    // The nucleus sampling cutoff, or None for the service's default.
+    pub top_p: Option<f32>,
+    pub seed: Option<i64>,
+    pub timeouts: StreamTimeouts,
+    // A client to send the requests to in place of the service's, such as a scripted one.
+    pub client: Option<SharedClient>,
+    // Spaces out the requests of the runs that share it.
+    pub request_limiter: Option<Arc<RequestLimiter>>,
+    // Read responses in Markdown instead of JSON rather than retrying them.
+    pub lenient_parsing: bool,
+    // The number of responses sampled from each request, with the API's `n`.
+    pub samples: u8,
+    // The tokens that a reasoning model may reason in, on top of the limit on its response.
+    pub reasoning_tokens: u32,
+    // The clients that the requests are sent with, shared by the providers derived from this one.
+    pub clients: ClientPool,
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Provider {
+            service: Service::OpenAi {
+                config: OpenAIConfig::default(),
+                model: MODEL.to_string(),
+            },
+            temperature: TEMPERATURE,
+            top_p: None,
+            seed: None,
+            timeouts: StreamTimeouts::default(),
+            client: None,
+            request_limiter: None,
+            lenient_parsing: false,
+            samples: 1,
+            reasoning_tokens: capabilities::DEFAULT_REASONING_TOKENS,
+            clients: ClientPool::default(),
+        }
+    }
+}
+
+impl Provider {
+    // Configure the chosen provider, checking that its settings and API key are present.
+    pub fn new(kind: ProviderKind, azure: &AzureArgs, gemini: &GeminiArgs) -> Result<Self> {
+        let service = match kind {
+            ProviderKind::Openai => Service::OpenAi {
+                config: OpenAIConfig::new().with_api_key(api_key(kind)?),
+                model: MODEL.to_string(),
+            },
+            ProviderKind::Azure => {
+                let deployment = required(
+                    "--azure-deployment or AZURE_OPENAI_DEPLOYMENT",
+                    azure.azure_deployment.clone(),
+                )?;
+                let config = AzureConfig::new()
+                    .with_api_base(required(
+                        "--azure-endpoint or AZURE_OPENAI_ENDPOINT",
+                        azure.azure_endpoint.clone(),
+                    )?)
+                    .with_deployment_id(&deployment)
+                    .with_api_version(&azure.azure_api_version)
+                    .with_api_key(api_key(kind)?);
+                Service::Azure { config, deployment }
+            }
+            ProviderKind::Gemini => Service::Gemini {
+                api_key: api_key(kind)?,
+                model: gemini.gemini_model.clone(),
+            },
+            ProviderKind::Fake => Service::Fake,
+        };
+        Ok(Provider {
+            service,
+            ..Default::default()
+        })
+    }
+
+    // The model that answers the requests. For Azure, it's the deployment.
+    pub fn model(&self) -> &str {
+        match &self.service {
+            Service::OpenAi { model, .. } | Service::Gemini { model, .. } => model,
+            Service::Azure { deployment, .. } => deployment,
+            Service::Fake => FAKE_MODEL,
+        }
+    }
+
+    // Use another model from the same provider.
+    pub fn with_model(self, new_model: &str) -> Self {
+        let service = match self.service {
+            Service::OpenAi { config, .. } => Service::OpenAi {
+                config,
+                model: new_model.to_string(),
+            },
+            Service::Azure { config, .. } => Service::Azure {
+                config: config.with_deployment_id(new_model),
+                deployment: new_model.to_string(),
+            },
+            Service::Gemini { api_key, .. } => Service::Gemini {
+                api_key,
+                model: new_model.to_string(),
+            },
+            Service::Fake => Service::Fake,
+        };
+        Provider { service, ..self }
+    }
+
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        Provider {
+            temperature,
+            ..self
+        }
+    }
+
+    pub fn with_top_p(self, top_p: Option<f32>) -> Self {
+        Provider { top_p, ..self }
+    }
+
+    pub fn with_seed(self, seed: Option<i64>) -> Self {
+        Provider { seed, ..self }
+    }
+
+    pub fn with_timeouts(self, timeouts: StreamTimeouts) -> Self {
+        Provider { timeouts, ..self }
+    }
+
+    pub fn with_clients(self, clients: ClientPool) -> Self {
+        Provider { clients, ..self }
+    }
+
+    pub fn with_client(self, client: SharedClient) -> Self {
+        Provider {
+            client: Some(client),
+            ..self
+        }
+    }
+
+    pub fn with_request_limiter(self, limiter: Arc<RequestLimiter>) -> Self {
+        Provider {
+            request_limiter: Some(limiter),
+            ..self
+        }
+    }
+
+    pub fn with_lenient_parsing(self, lenient_parsing: bool) -> Self {
+        Provider {
+            lenient_parsing,
+            ..self
+        }
+    }
+
+    pub fn with_samples(self, samples: u8) -> Self {
+        Provider { samples, ..self }
+    }
+
+    pub fn with_reasoning_tokens(self, reasoning_tokens: u32) -> Self {
+        Provider {
+            reasoning_tokens,
+            ..self
+        }
+    }
+}
+
+// The provider's API key, from wherever the credentials module finds it.
+fn api_key(kind: ProviderKind) -> Result<String> {
+    required(&credentials::sources(kind), credentials::api_key(kind)?)
+}
+
+fn required(setting: &str, value: Option<String>) -> Result<String> {
+    match value {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(AiCriticError::MissingSetting {
+            setting: setting.to_string(),
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_azure_requires_endpoint_and_deployment() {
+        let azure = AzureArgs {
+            azure_endpoint: Some("https://example.openai.azure.com".to_string()),
+            ..Default::default()
+        };
+        let err = Provider::new(ProviderKind::Azure, &azure, &GeminiArgs::default()).unwrap_err();
+        assert!(err.to_string().contains("AZURE_OPENAI_DEPLOYMENT"));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::errors::AiCriticError;
+use crate::events::{EventBus, RunEvent};
+use crate::github::{self, GitHubClient};
+use color_eyre::eyre::Result;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Deliver the accepted solution: write it to a file, commit it to a branch of another repository,
+// and open a GitHub pull request from that branch. The commit is built with git's plumbing commands
+// in a temporary index, so the target repository's working tree and index are left alone, and
+// running again adds a commit to the same branch.
+
+const DEFAULT_GIT_FILE: &str = "src/solution.rs";
+// Longer problem statements are cut to this many characters in the commit subject.
+const MAX_SUBJECT_CHARS: usize = 72;
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct PublishArgs {
+    /// Write the accepted code to this file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Commit the accepted code to a branch of the git repository at this path, with the problem
+    /// statement as the commit message.
+    #[arg(long)]
+    git: Option<PathBuf>,
+
+    /// Path of the committed file within the --git repository.
+    #[arg(long, default_value = DEFAULT_GIT_FILE, requires = "git")]
+    git_file: PathBuf,
+
+    /// Branch to commit to. Defaults to ai-critics/<problem name>.
+    #[arg(long, requires = "git")]
+    git_branch: Option<String>,
+
+    /// Push the branch to the repository's origin on GitHub and open a pull request for it, using
+    /// the --github-token.
+    #[arg(long, default_value_t = false, requires = "git")]
+    github_pr: bool,
+
+    /// Branch the pull request merges into. Defaults to the repository's current branch.
+    #[arg(long, requires = "github_pr")]
+    github_base: Option<String>,
+}
+
+// Publish the code as requested by the args. The problem's name, such as its file name, names the
+// default branch.
+pub async fn publish(
+    bus: &EventBus,
+    args: &PublishArgs,
+    github: &GitHubClient,
+    name: &str,
+    problem: &str,
+    code: &str,
+) -> Result<()> {
+    if let Some(path) = &args.out {
+        fs::write(path, code)?;
+        notice(bus, format!("Wrote the solution to {}.", path.display()));
+    }
+    let Some(repo) = &args.git else {
+        return Ok(());
+    };
+    let branch = match &args.git_branch {
+        Some(branch) => branch.clone(),
+        None => default_branch(name),
+    };
+    let message = commit_message(problem);
+    let commit = commit_file(repo, &branch, &args.git_file, code, &message)?;
+    notice(
+        bus,
+        format!(
+            "Committed the solution to {} on branch {} of {} ({}).",
+            args.git_file.display(),
+            branch,
+            repo.display(),
+            &commit[..commit.len().min(12)]
+        ),
+    );
+    if !args.github_pr {
+        return Ok(());
+    }
+
+    let base = match &args.github_base {
+        Some(base) => base.clone(),
+        None => git(repo, &["symbolic-ref", "--short", "HEAD"], None, None)?,
+    };
+    git(repo, &["push", "origin", &branch], None, None)?;
+    let remote = git(repo, &["remote", "get-url", "origin"], None, None)?;
+    let (owner, repo_name) =
+        github::repo_from_remote(&remote).ok_or_else(|| AiCriticError::InvalidConfig {
+            path: repo.display().to_string(),
+            reason: format!("its origin {} is not a GitHub repository", remote),
+        })?;
+    let (title, body) = message.split_once("\n\n").unwrap_or((&message, ""));
+    let url = github
+        .open_pull_request(&owner, &repo_name, &branch, &base, title, body)
+        .await?;
+    notice(bus, format!("Opened the pull request {}.", url));
+    Ok(())
+}
+
+fn notice(bus: &EventBus, message: String) {
+    bus.emit(RunEvent::Notice { message });
+}
+
+// The branch named for the problem, keeping only the characters that are safe in branch names.
+fn default_branch(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "-_./".contains(c) {
+            true => c,
+            false => '-',
+        })
+        .collect();
+    format!("ai-critics/{}", name.trim_matches(|c| c == '-' || c == '/'))
+}
+
+// The first line of the problem is the subject, and the whole problem is the body.
+fn commit_message(problem: &str) -> String {
+    let first_line = problem
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("Add the solution");
+    let mut subject: String = first_line.chars().take(MAX_SUBJECT_CHARS).collect();
+    if subject.len() < first_line.len() {
+        subject.push_str("...");
+    }
+    format!(
+        "{}\n\n{}\n\nWritten and reviewed by ai_critics.\n",
+        subject,
+        problem.trim()
+    )
+}
+
+// Commit the code as the file at path on the branch, creating the branch from HEAD if it doesn't
+// exist. Return the commit's hash.
+fn commit_file(
+    repo: &Path,
+    branch: &str,
+    path: &Path,
+    code: &str,
+    message: &str,
+) -> Result<String> {
+    let reference = format!("refs/heads/{}", branch);
+    if git(repo, &["symbolic-ref", "-q", "HEAD"], None, None).ok() == Some(reference.clone()) {
+        // Moving the checked-out branch would leave the working tree out of step with it.
+        return Err(AiCriticError::InvalidConfig {
+            path: repo.display().to_string(),
+            reason: format!("the branch {} is checked out", branch),
+        }
+        .into());
+    }
+    // An empty repository has no commit to branch from.
+    let parent = git(
+        repo,
+        &["rev-parse", "--verify", "-q", &reference],
+        None,
+        None,
+    )
+    .or_else(|_| git(repo, &["rev-parse", "--verify", "-q", "HEAD"], None, None))
+    .ok();
+
+    let index_dir = tempfile::tempdir()?;
+    let index = index_dir.path().join("index");
+    if let Some(parent) = &parent {
+        git(repo, &["read-tree", parent], None, Some(&index))?;
+    }
+    let blob = git(repo, &["hash-object", "-w", "--stdin"], Some(code), None)?;
+    let path = path.to_string_lossy().replace('\\', "/");
+    let cacheinfo = format!("100644,{},{}", blob, path);
+    git(
+        repo,
+        &["update-index", "--add", "--cacheinfo", &cacheinfo],
+        None,
+        Some(&index),
+    )?;
+    let tree = git(repo, &["write-tree"], None, Some(&index))?;
+
+    let mut args = vec!["commit-tree", &tree, "-F", "-"];
+    if let Some(parent) = &parent {
+        args.extend(["-p", parent]);
+    }
+    let commit = git(repo, &args, Some(message), None)?;
+    git(repo, &["update-ref", &reference, &commit], None, None)?;
+    Ok(commit)
+}
+
+// Run git in the repository, returning its trimmed stdout.
+fn git(repo: &Path, args: &[&str], stdin: Option<&str>, index: Option<&Path>) -> Result<String> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(index) = index {
+        command.env("GIT_INDEX_FILE", index);
+    }
+    let mut child = command.spawn()?;
+    if let Some(mut pipe) = child.stdin.take() {
+        pipe.write_all(stdin.unwrap_or_default().as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(AiCriticError::GitFailed {
+            command: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        git(repo, &["init", "-q", "-b", "main"], None, None).unwrap();
+        git(repo, &["config", "user.name", "Test"], None, None).unwrap();
+        git(
+            repo,
+            &["config", "user.email", "test@example.com"],
+            None,
+            None,
+        )
+        .unwrap();
+        fs::write(repo.join("README"), "readme\n").unwrap();
+        git(repo, &["add", "README"], None, None).unwrap();
+        git(repo, &["commit", "-q", "-m", "Initial"], None, None).unwrap();
+
+        let message = commit_message("Add two numbers.\nReturn their sum.\n");
+        let path = Path::new("src/add.rs");
+        commit_file(repo, "ai-critics/add", path, "fn add() {}\n", &message).unwrap();
+        commit_file(
+            repo,
+            "ai-critics/add",
+            path,
+            "fn add(a: i32) {}\n",
+            &message,
+        )
+        .unwrap();
+
+        let show = |object: &str| git(repo, &["show", object], None, None).unwrap();
+        assert_eq!(show("ai-critics/add:src/add.rs"), "fn add(a: i32) {}");
+        assert_eq!(show("ai-critics/add~1:src/add.rs"), "fn add() {}");
+        assert_eq!(show("ai-critics/add:README"), "readme");
+        let log = git(
+            repo,
+            &["log", "-1", "--format=%B", "ai-critics/add"],
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(log.starts_with("Add two numbers.\n\nAdd two numbers.\nReturn their sum."));
+        // The working tree and the checked-out branch are untouched.
+        assert!(!repo.join("src").exists());
+        assert_eq!(
+            git(repo, &["status", "--porcelain"], None, None).unwrap(),
+            ""
+        );
+        assert!(commit_file(repo, "main", path, "", &message).is_err());
+        assert_eq!(default_branch("owner/repo#12"), "ai-critics/owner/repo-12");
+    }
+}
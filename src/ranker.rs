@@ -0,0 +1,131 @@
+use crate::agent::{user_message, Agent};
+use crate::chatter_json::ChatterJSON;
+use crate::errors::AiCriticError;
+use crate::events::AgentProgress;
+use crate::fixer::ReviewNeeded;
+use crate::prompts::{Prompt, Prompts};
+use crate::provider::Provider;
+use crate::schema::{self, Kind, Schema};
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::future::Future;
+
+// With --fix-candidates, several fixers correct each proposal in parallel and their candidates are
+// tested. When more than one does equally well, as when they all compile but fail a flaky test, the
+// ranker picks among them in a single-elimination tournament. Each match shows it two candidates
+// and the review that they address, and asks which addresses it better.
+
+const RANKER_NAME: &str = "Ranker";
+
+pub struct RankerAgent {
+    pub name: String,
+    system_prompt: String,
+    chatter: ChatterJSON,
+}
+
+#[derive(Deserialize, Debug)]
+struct Ranking {
+    better: String,
+    #[serde(default)]
+    reason: String,
+}
+
+impl RankerAgent {
+    pub fn new(provider: &Provider, prompts: &Prompts) -> Result<Self> {
+        Ok(RankerAgent {
+            name: RANKER_NAME.to_string(),
+            system_prompt: prompts.get(Prompt::Ranker).to_string(),
+            chatter: ChatterJSON::new(provider),
+        })
+    }
+
+    // Whether candidate A addresses the review better than candidate B, and the ranker's reason.
+    pub async fn prefers_first(
+        &self,
+        progress: &AgentProgress,
+        review: &ReviewNeeded,
+        a: &str,
+        b: &str,
+    ) -> Result<(bool, String)> {
+        let msg = json!({
+            "review": review.comments,
+            "A": a,
+            "B": b,
+        });
+        let ranking: Ranking = self
+            .chat(progress, &[user_message(&msg.to_string())?])
+            .await?;
+        match ranking.better.trim().to_uppercase().as_str() {
+            "A" => Ok((true, ranking.reason)),
+            "B" => Ok((false, ranking.reason)),
+            _ => Err(AiCriticError::UnexpectedJsonStructure {
+                json: json!({ "better": ranking.better }),
+            }
+            .into()),
+        }
+    }
+}
+
+impl Agent for RankerAgent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn system_prompt(&self) -> &str {
+        &self.system_prompt
+    }
+
+    fn chatter(&self) -> &ChatterJSON {
+        &self.chatter
+    }
+
+    fn schema(&self) -> &'static Schema {
+        schema::latest(Kind::Ranking)
+    }
+}
+
+// The index of the winner of a single-elimination tournament among the candidates, in which `play`
+// says whether the first of two candidates beats the second. A candidate without an opponent in a
+// round goes through to the next.
+pub async fn tournament<F, Fut>(candidates: usize, mut play: F) -> usize
+where
+    F: FnMut(usize, usize) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut round: Vec<usize> = (0..candidates).collect();
+    while round.len() > 1 {
+        let mut next = vec![];
+        for pair in round.chunks(2) {
+            match *pair {
+                [a, b] => next.push(if play(a, b).await { a } else { b }),
+                [a] => next.push(a),
+                _ => unreachable!("chunks of 2"),
+            }
+        }
+        round = next;
+    }
+    round.first().copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[tokio::test]
+    async fn test_tournament() {
+        // The higher index wins each match.
+        let matches = RefCell::new(vec![]);
+        let winner = tournament(5, |a, b| {
+            matches.borrow_mut().push((a, b));
+            async move { a > b }
+        })
+        .await;
+        assert_eq!(winner, 4);
+        assert_eq!(matches.into_inner(), vec![(0, 1), (2, 3), (1, 3), (3, 4)]);
+
+        assert_eq!(tournament(1, |_, _| async { true }).await, 0);
+        assert_eq!(tournament(2, |_, _| async { false }).await, 1);
+    }
+}
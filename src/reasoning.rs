@@ -0,0 +1,186 @@
+use crate::capabilities::{Capabilities, DEFAULT_REASONING_TOKENS};
+use crate::chatter_json::OpenAIClientTrait;
+use crate::sse;
+use async_openai::config::Config;
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatCompletionResponseStream, ChatCompletionResponseStreamMessage,
+    ChatCompletionStreamResponseDelta, CreateChatCompletionRequest,
+    CreateChatCompletionStreamResponse, FinishReason, Role,
+};
+use async_trait::async_trait;
+use futures::stream;
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use std::time::Duration;
+
+// A client for the OpenAI and Azure models whose requests async-openai can't make, such as the
+// reasoning models, which looks like the OpenAI client to ChatterJSON. The request is sent as the
+// JSON body that the model's capabilities call for. A model that streams its response is read as
+// server-sent events, as the OpenAI client would. One that doesn't returns its whole response at
+// once, after it's done reasoning, which is passed on as a chunk for each choice. Since no chunk
+// arrives until then, the wait for it is limited by the request timeout rather than the chunk
+// timeout.
+
+pub struct ReasoningClient {
+    http: reqwest::Client,
+    url: String,
+    headers: HeaderMap,
+    query: Vec<(String, String)>,
+    capabilities: &'static Capabilities,
+    timeout: Duration,
+    reasoning_tokens: u32,
+}
+
+impl ReasoningClient {
+    pub fn new<C: Config>(
+        config: &C,
+        capabilities: &'static Capabilities,
+        timeout: Duration,
+        http: &reqwest::Client,
+    ) -> Self {
+        ReasoningClient {
+            http: http.clone(),
+            url: config.url("/chat/completions"),
+            headers: config.headers(),
+            query: config
+                .query()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            capabilities,
+            timeout,
+            reasoning_tokens: DEFAULT_REASONING_TOKENS,
+        }
+    }
+
+    pub fn with_reasoning_tokens(self, reasoning_tokens: u32) -> Self {
+        ReasoningClient {
+            reasoning_tokens,
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl OpenAIClientTrait for ReasoningClient {
+    async fn create_chat_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let response = self
+            .http
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .query(&self.query)
+            .timeout(self.timeout)
+            .json(
+                &self
+                    .capabilities
+                    .request_body(&request, self.reasoning_tokens),
+            )
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(OpenAIError::StreamError(format!(
+                "{} returned {}: {}",
+                request.model, status, body
+            )));
+        }
+
+        if !self.capabilities.streaming {
+            let completion: Completion = response.json().await?;
+            return Ok(Box::pin(stream::iter(
+                completion
+                    .choices
+                    .into_iter()
+                    .map(|choice| Ok(to_chunk(choice))),
+            )));
+        }
+        Ok(sse::stream(response, parse_event))
+    }
+}
+
+// The parts of a response that wasn't streamed that ChatterJSON uses.
+#[derive(Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    index: u32,
+    message: Message,
+    finish_reason: Option<FinishReason>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Option<String>,
+}
+
+// The whole of a choice's response as a single streamed chunk.
+fn to_chunk(choice: Choice) -> CreateChatCompletionStreamResponse {
+    CreateChatCompletionStreamResponse {
+        id: String::new(),
+        choices: vec![ChatCompletionResponseStreamMessage {
+            index: choice.index,
+            #[allow(deprecated)]
+            delta: ChatCompletionStreamResponseDelta {
+                content: choice.message.content,
+                role: Some(Role::Assistant),
+                tool_calls: None,
+                function_call: None, // Deprecated.
+            },
+            finish_reason: choice.finish_reason,
+        }],
+        created: 0,
+        model: String::new(),
+        object: "chat.completion.chunk".to_string(),
+        system_fingerprint: None,
+    }
+}
+
+// The chunk of an event of the stream.
+fn parse_event(data: &str) -> Result<CreateChatCompletionStreamResponse, OpenAIError> {
+    serde_json::from_str(data).map_err(OpenAIError::JSONDeserialize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_chunk() {
+        let completion: Completion = serde_json::from_str(
+            r#"{"id": "1", "choices": [
+                {"index": 0, "message": {"role": "assistant", "content": "{\"lgtm\": true}"},
+                 "finish_reason": "stop"},
+                {"index": 1, "message": {"role": "assistant", "content": "{\"lgtm\""},
+                 "finish_reason": "length"}
+            ], "usage": {"completion_tokens_details": {"reasoning_tokens": 1024}}}"#,
+        )
+        .unwrap();
+        let chunks: Vec<_> = completion.choices.into_iter().map(to_chunk).collect();
+        assert_eq!(
+            chunks[0].choices[0].delta.content.as_deref(),
+            Some("{\"lgtm\": true}")
+        );
+        assert_eq!(chunks[1].choices[0].index, 1);
+        assert_eq!(
+            chunks[1].choices[0].finish_reason,
+            Some(FinishReason::Length)
+        );
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let event = r#"{"id": "1", "object": "chat.completion.chunk", "created": 0, "model": "o3-mini", "choices": [{"index": 0, "delta": {"content": "{}"}, "finish_reason": "stop"}]}"#;
+        let chunk = parse_event(event).unwrap();
+        assert_eq!(chunk.choices[0].delta.content.as_deref(), Some("{}"));
+        assert_eq!(chunk.choices[0].finish_reason, Some(FinishReason::Stop));
+        assert!(parse_event("{\"id\"").is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use crate::errors::AiCriticError;
+use crate::events::{Observer, RunEvent};
+use crate::stats::{self, Summary};
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// With --runs, the problem is solved several times in one invocation to measure how much the
+// outcome varies from one attempt to the next. Each run keeps its own artifacts, and with --seed
+// the runs are seeded with consecutive seeds, so the whole set can be repeated. Once they're done,
+// each run's outcome and the statistics over all of them are reported and written to summary.json
+// beside the runs' directories.
+
+pub const SUMMARY_FILENAME: &str = "summary.json";
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Solved,
+    Diverged,
+    Cancelled,
+    Error,
+}
+
+impl Outcome {
+    pub fn of(result: &Result<usize>) -> Self {
+        match result {
+            Ok(_) => Outcome::Solved,
+            Err(e) => match e.downcast_ref::<AiCriticError>() {
+                Some(e) if e.is_divergence() => Outcome::Diverged,
+                Some(AiCriticError::Cancelled) => Outcome::Cancelled,
+                _ => Outcome::Error,
+            },
+        }
+    }
+}
+
+// What one of the runs produced.
+#[derive(Serialize, Debug)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub seed: Option<i64>,
+    pub outcome: Outcome,
+    // The number of proposals that a solved run took.
+    pub proposals: Option<usize>,
+    pub duration_secs: f64,
+    pub prompt_tokens: usize,
+    // Approximated by the streamed chunks.
+    pub completion_tokens: usize,
+}
+
+// The statistics over the runs. The runs that ended in an error or were cancelled say nothing about
+// how well the problem is solved, so they're left out of the solve rate.
+#[derive(Serialize, Debug)]
+pub struct Aggregate {
+    pub runs: usize,
+    pub solved: usize,
+    pub diverged: usize,
+    pub errors: usize,
+    pub solve_rate: f64,
+    // The 95% Wilson interval of the solve rate.
+    pub solve_rate_ci: (f64, f64),
+    // The proposals of the solved runs.
+    pub proposals: Option<Summary>,
+    pub duration_secs: Option<Summary>,
+    pub total_tokens: Option<Summary>,
+}
+
+#[derive(Serialize)]
+struct RunsSummary<'a> {
+    runs: &'a [RunRecord],
+    aggregate: &'a Aggregate,
+}
+
+// Counts the tokens of a run's requests and responses.
+#[derive(Clone, Default)]
+pub struct TokenCounter(Arc<Mutex<(usize, usize)>>);
+
+impl Observer for TokenCounter {
+    fn on_event(&mut self, event: &RunEvent) {
+        let mut counts = self.0.lock().unwrap();
+        match event {
+            RunEvent::ChatRequested { prompt_tokens, .. } => counts.0 += prompt_tokens,
+            RunEvent::ChatResponded { chunks, .. } => counts.1 += chunks,
+            _ => {}
+        }
+    }
+}
+
+impl RunRecord {
+    pub fn new(
+        run_id: &str,
+        seed: Option<i64>,
+        result: &Result<usize>,
+        duration: Duration,
+        tokens: &TokenCounter,
+    ) -> Self {
+        let (prompt_tokens, completion_tokens) = *tokens.0.lock().unwrap();
+        RunRecord {
+            run_id: run_id.to_string(),
+            seed,
+            outcome: Outcome::of(result),
+            proposals: result.as_ref().ok().copied(),
+            duration_secs: duration.as_secs_f64(),
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+}
+
+pub fn aggregate(runs: &[RunRecord]) -> Aggregate {
+    let count = |outcome| runs.iter().filter(|run| run.outcome == outcome).count();
+    let (solved, diverged) = (count(Outcome::Solved), count(Outcome::Diverged));
+    let values = |value: fn(&RunRecord) -> Option<f64>| -> Vec<f64> {
+        runs.iter().filter_map(value).collect()
+    };
+    Aggregate {
+        runs: runs.len(),
+        solved,
+        diverged,
+        errors: runs.len() - solved - diverged,
+        solve_rate: match solved + diverged {
+            0 => 0.0,
+            n => solved as f64 / n as f64,
+        },
+        solve_rate_ci: stats::wilson_interval(solved, solved + diverged),
+        proposals: stats::summarize(&values(|run| run.proposals.map(|p| p as f64))),
+        duration_secs: stats::summarize(&values(|run| Some(run.duration_secs))),
+        total_tokens: stats::summarize(&values(|run| {
+            Some((run.prompt_tokens + run.completion_tokens) as f64)
+        })),
+    }
+}
+
+pub fn write_summary(path: &Path, runs: &[RunRecord], aggregate: &Aggregate) -> Result<()> {
+    let summary = RunsSummary { runs, aggregate };
+    fs::write(path, serde_json::to_string_pretty(&summary)?)?;
+    Ok(())
+}
+
+// A table of the runs followed by the statistics over them.
+pub fn report(runs: &[RunRecord], aggregate: &Aggregate) -> String {
+    let mut lines = vec![format!(
+        "{:<24}  {:>6}  {:<9}  {:>9}  {:>8}  {:>7}",
+        "Run", "Seed", "Outcome", "Proposals", "Duration", "Tokens"
+    )];
+    for run in runs {
+        lines.push(format!(
+            "{:<24}  {:>6}  {:<9}  {:>9}  {:>7.1}s  {:>7}",
+            run.run_id,
+            run.seed.map_or("-".to_string(), |s| s.to_string()),
+            format!("{:?}", run.outcome).to_lowercase(),
+            run.proposals.map_or("-".to_string(), |p| p.to_string()),
+            run.duration_secs,
+            run.prompt_tokens + run.completion_tokens
+        ));
+    }
+    lines.push(format!(
+        "Solved {} of {} runs ({} diverged, {} errors): solve rate {:.2}, 95% CI [{:.2}, {:.2}].",
+        aggregate.solved,
+        aggregate.runs,
+        aggregate.diverged,
+        aggregate.errors,
+        aggregate.solve_rate,
+        aggregate.solve_rate_ci.0,
+        aggregate.solve_rate_ci.1
+    ));
+    let summaries = [
+        ("Proposals", &aggregate.proposals),
+        ("Duration (s)", &aggregate.duration_secs),
+        ("Tokens", &aggregate.total_tokens),
+    ];
+    for (name, summary) in summaries {
+        if let Some(s) = summary {
+            lines.push(format!(
+                "{}: mean {:.2}, median {:.2}, stddev {:.2}, 95% CI [{:.2}, {:.2}].",
+                name, s.mean, s.median, s.stddev, s.ci.0, s.ci.1
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate() {
+        let mut tokens = TokenCounter::default();
+        tokens.on_event(&RunEvent::ChatRequested {
+            agent: "Coder".to_string(),
+            model: "gpt-4o".to_string(),
+            messages: vec![],
+            prompt_tokens: 100,
+        });
+        tokens.on_event(&RunEvent::ChatResponded {
+            agent: "Coder".to_string(),
+            response: String::new(),
+            finish_reason: None,
+            chunks: 20,
+        });
+        let results: Vec<Result<usize>> = vec![
+            Ok(2),
+            Ok(4),
+            Err(AiCriticError::MaxProposalsExceeded { proposals: 20 }.into()),
+            Err(AiCriticError::NotJsonObject.into()),
+        ];
+        let runs: Vec<RunRecord> = results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let run_id = format!("run-{}", i + 1);
+                RunRecord::new(&run_id, Some(i as i64), result, Duration::ZERO, &tokens)
+            })
+            .collect();
+        assert_eq!(runs[2].outcome, Outcome::Diverged);
+        assert_eq!(runs[3].outcome, Outcome::Error);
+        assert_eq!(runs[0].completion_tokens, 20);
+
+        let aggregate = aggregate(&runs);
+        assert_eq!(
+            (aggregate.solved, aggregate.diverged, aggregate.errors),
+            (2, 1, 1)
+        );
+        assert!((aggregate.solve_rate - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(aggregate.proposals.as_ref().unwrap().mean, 3.0);
+        assert_eq!(aggregate.total_tokens.as_ref().unwrap().mean, 120.0);
+        let report = report(&runs, &aggregate);
+        assert!(report.contains("Solved 2 of 4 runs (1 diverged, 1 errors)"));
+        assert!(report.contains("Proposals: mean 3.00, median 3.00"));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::chatter_json::OpenAIClientTrait;
+use async_openai::error::OpenAIError;
+use async_openai::types::{ChatCompletionResponseStream, CreateChatCompletionRequest};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+
+// Runs that share an API key share its rate limits, so when several run at once, as in the worker,
+// their requests to the model are spaced out by one RequestLimiter. Every chatter of a provider
+// that has the limiter waits its turn before sending a request.
+
+pub struct RequestLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RequestLimiter {
+    // Allow `per_minute` requests per minute. Zero means no limit.
+    pub fn per_minute(per_minute: u32) -> Self {
+        let interval = match per_minute {
+            0 => Duration::ZERO,
+            n => Duration::from_secs(60) / n,
+        };
+        RequestLimiter {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    // Wait until the next request is allowed. The slot is reserved before waiting so that
+    // concurrent callers queue up behind each other.
+    pub async fn wait(&self) {
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let start = (*next).max(Instant::now());
+            *next = start + self.interval;
+            start
+        };
+        if start > Instant::now() {
+            sleep_until(start).await;
+        }
+    }
+}
+
+impl std::fmt::Debug for RequestLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RequestLimiter({:?})", self.interval)
+    }
+}
+
+// A client that waits for the limiter before each request.
+pub struct LimitedClient {
+    pub client: Box<dyn OpenAIClientTrait + Send + Sync>,
+    pub limiter: Arc<RequestLimiter>,
+}
+
+#[async_trait]
+impl OpenAIClientTrait for LimitedClient {
+    async fn create_chat_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        self.limiter.wait().await;
+        self.client.create_chat_stream(request).await
+    }
+
+    fn clean_response(&self, response: String) -> String {
+        self.client.clean_response(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_spaces_out_requests() {
+        let limiter = RequestLimiter::per_minute(60 * 20); // One request every 50 ms.
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.wait().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let unlimited = RequestLimiter::per_minute(0);
+        let start = Instant::now();
+        for _ in 0..100 {
+            unlimited.wait().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
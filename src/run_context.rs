@@ -0,0 +1,524 @@
+use crate::critic::CriticType;
+use crate::events::{Anomaly, JsonLinesObserver, Observer, RunEvent};
+use crate::pricing::Spend;
+use crate::proposal_diff::ProposalDiff;
+use crate::store::{self, Store};
+use crate::tester::FailureKind;
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+// A run's artifacts are kept under <output-dir>/<run-id>/ so that they outlive the terminal's
+// scrollback:
+//   events.jsonl     the transcript of the run's events
+//   proposal_N.rs    a checkpoint of the code of each proposal
+//   solution.rs      the accepted code
+//   best_candidate.rs  the best proposal of a run that failed to converge
+//   result.json      the outcome of the run, with its model and cost, how much of the code each
+//                    fix kept, what changed between proposals, and how often the API misbehaved
+//   state.json       the latest state of the run loop
+//   builds/build_N/  with --keep-artifacts, the source, executables, and compiler and test output
+//                    of each build that the tester compiled, which the tester copies itself and
+//                    which are saved to the store when the run ends
+// The files are written by an observer as the events arrive, so a run that fails or is cancelled
+// still leaves its transcript and checkpoints behind. With a store, they're also saved to it as
+// <run-id>/<file>.
+
+const TRANSCRIPT_FILENAME: &str = "events.jsonl";
+const SOLUTION_FILENAME: &str = "solution.rs";
+const RESULT_FILENAME: &str = "result.json";
+const BEST_CANDIDATE_FILENAME: &str = "best_candidate.rs";
+const STATE_FILENAME: &str = "state.json";
+pub const BUILDS_DIRNAME: &str = "builds";
+
+#[derive(Debug)]
+pub struct RunContext {
+    pub id: String,
+    pub dir: PathBuf,
+    store: Option<Arc<dyn Store>>,
+}
+
+impl RunContext {
+    pub fn create(output_dir: &Path, id: &str) -> Result<Self> {
+        let dir = output_dir.join(id);
+        fs::create_dir_all(&dir)?;
+        Ok(RunContext {
+            id: id.to_string(),
+            dir,
+            store: None,
+        })
+    }
+
+    // Also save the run's artifacts to the store, if there is one.
+    pub fn with_store(self, store: Option<Arc<dyn Store>>) -> Self {
+        RunContext { store, ..self }
+    }
+
+    // Save the kept builds, which the tester writes itself, then wait for the store to save the
+    // artifacts written so far, warning of any it couldn't.
+    pub async fn flush_store(&self) {
+        if let Some(store) = &self.store {
+            if let Err(e) = self.store_builds(store.as_ref()) {
+                log::warn!("Couldn't read the kept builds of {}: {}", self.id, e);
+            }
+            store::flush(store.as_ref(), &self.id).await;
+        }
+    }
+
+    // Save each file of the kept builds as <run-id>/builds/build_N/<file>.
+    fn store_builds(&self, store: &dyn Store) -> io::Result<()> {
+        let builds = self.dir.join(BUILDS_DIRNAME);
+        if !builds.is_dir() {
+            return Ok(());
+        }
+        for build in fs::read_dir(&builds)? {
+            let build = build?.path();
+            for file in fs::read_dir(&build)? {
+                let file = file?.path();
+                if let (Some(build_name), Some(name)) = (build.file_name(), file.file_name()) {
+                    let key = format!(
+                        "{}/{}/{}/{}",
+                        self.id,
+                        BUILDS_DIRNAME,
+                        build_name.to_string_lossy(),
+                        name.to_string_lossy()
+                    );
+                    store.put(&key, &fs::read(&file)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The existing run of the ID, if it's a run's ID rather than a path elsewhere.
+    pub fn open(output_dir: &Path, id: &str) -> Option<Self> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        let dir = output_dir.join(id);
+        (valid && dir.is_dir()).then(|| RunContext {
+            id: id.to_string(),
+            dir,
+            store: None,
+        })
+    }
+
+    // The run's outcome, once it has finished.
+    pub fn result(&self) -> Option<serde_json::Value> {
+        self.read_json(RESULT_FILENAME)
+    }
+
+    // The latest state of the run loop, once it has started.
+    pub fn state(&self) -> Option<serde_json::Value> {
+        self.read_json(STATE_FILENAME)
+    }
+
+    // The transcript of the run's events so far, as JSON lines.
+    pub fn transcript(&self) -> String {
+        fs::read_to_string(self.dir.join(TRANSCRIPT_FILENAME)).unwrap_or_default()
+    }
+
+    fn read_json(&self, filename: &str) -> Option<serde_json::Value> {
+        let text = fs::read_to_string(self.dir.join(filename)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    // The runs in the output directory, including those of batches in their subdirectories.
+    pub fn find_all(output_dir: &Path) -> Vec<Self> {
+        let mut runs = vec![];
+        find_runs(output_dir, 2, &mut runs);
+        runs
+    }
+
+    // The run of the ID in the output directory or a batch's subdirectory.
+    pub fn find(output_dir: &Path, id: &str) -> Option<Self> {
+        Self::find_all(output_dir)
+            .into_iter()
+            .find(|run| run.id == id)
+    }
+
+    // When the run's transcript was last written to.
+    pub fn updated(&self) -> Option<SystemTime> {
+        fs::metadata(self.dir.join(TRANSCRIPT_FILENAME))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    // The run's last code and what it is: the accepted solution, the best candidate of a run that
+    // didn't converge, or else the latest proposal.
+    pub fn final_code(&self) -> Option<(String, String)> {
+        let latest_proposal = (1..)
+            .map(|n| format!("proposal_{}.rs", n))
+            .take_while(|name| self.dir.join(name).exists())
+            .last();
+        [SOLUTION_FILENAME, BEST_CANDIDATE_FILENAME]
+            .into_iter()
+            .map(String::from)
+            .chain(latest_proposal)
+            .find_map(|name| {
+                let code = fs::read_to_string(self.dir.join(&name)).ok()?;
+                Some((name, code))
+            })
+    }
+
+    // The observers that write the run's artifacts.
+    pub fn observers(&self, problem_file: &str) -> Result<Vec<Box<dyn Observer>>> {
+        Ok(vec![
+            Box::new(JsonLinesObserver::create(
+                &self.dir.join(TRANSCRIPT_FILENAME),
+                &self.id,
+            )?),
+            Box::new(ArtifactObserver {
+                dir: self.dir.clone(),
+                store: self.store.clone(),
+                result: RunResult {
+                    run_id: self.id.clone(),
+                    problem_file: problem_file.to_string(),
+                    ..Default::default()
+                },
+                spend: Spend::default(),
+            }),
+        ])
+    }
+}
+
+// Add the directory to the runs if it's one, and otherwise look for runs in its subdirectories, to
+// the given depth.
+fn find_runs(dir: &Path, depth: usize, runs: &mut Vec<RunContext>) {
+    if dir.join(TRANSCRIPT_FILENAME).is_file() {
+        if let Some(id) = dir.file_name().and_then(|name| name.to_str()) {
+            runs.push(RunContext {
+                id: id.to_string(),
+                dir: dir.to_path_buf(),
+                store: None,
+            });
+        }
+        return;
+    }
+    if depth == 0 {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    for dir in dirs {
+        find_runs(&dir, depth - 1, runs);
+    }
+}
+
+#[derive(Serialize, Default, Debug)]
+struct RunResult {
+    run_id: String,
+    problem_file: String,
+    outcome: String,
+    // The number of proposals made.
+    proposals: usize,
+    // The model of the run's first request, which is the coder's.
+    model: Option<String>,
+    // The estimated cost of the run's requests.
+    cost_usd: f64,
+    // The file of the accepted code, if the problem was solved.
+    solution: Option<String>,
+    // The file of the best proposal and why it falls short, if the run ended without passing
+    // tests.
+    best_candidate: Option<String>,
+    best_candidate_summary: Option<String>,
+    // The similarity of the code after each fix to the code before it, in order.
+    fix_similarities: Vec<f64>,
+    mean_fix_similarity: Option<f64>,
+    // What changed from each proposal to the next.
+    proposal_diffs: Vec<ProposalDiff>,
+    // The types of critic that reviewed the proposals.
+    critic_types: Vec<CriticType>,
+    // The number of each kind of API anomaly in the run's responses.
+    api_anomalies: BTreeMap<Anomaly, usize>,
+    // The number of each kind of failure that the tester reported.
+    test_failures: BTreeMap<FailureKind, usize>,
+}
+
+struct ArtifactObserver {
+    dir: PathBuf,
+    store: Option<Arc<dyn Store>>,
+    result: RunResult,
+    spend: Spend,
+}
+
+impl ArtifactObserver {
+    fn write(&self, filename: &str, contents: &str) -> Option<PathBuf> {
+        self.store_copy(filename, contents.as_bytes());
+        let path = self.dir.join(filename);
+        match fs::write(&path, contents) {
+            Ok(()) => Some(path),
+            Err(e) => {
+                log::warn!("Failed to write {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn store_copy(&self, filename: &str, contents: &[u8]) {
+        if let Some(store) = &self.store {
+            store.put(&format!("{}/{}", self.result.run_id, filename), contents);
+        }
+    }
+
+    // Save the transcript to the store. The transcript observer precedes this one, so the event
+    // being handled is already in it.
+    fn store_transcript(&self) {
+        if self.store.is_some() {
+            if let Ok(transcript) = fs::read(self.dir.join(TRANSCRIPT_FILENAME)) {
+                self.store_copy(TRANSCRIPT_FILENAME, &transcript);
+            }
+        }
+    }
+}
+
+impl Observer for ArtifactObserver {
+    fn on_event(&mut self, event: &RunEvent) {
+        self.spend.record(event);
+        self.result.cost_usd = self.spend.cost_usd;
+        match event {
+            RunEvent::ChatRequested { model, .. } if self.result.model.is_none() => {
+                self.result.model = Some(model.clone());
+            }
+            RunEvent::ProposalCreated { number, code } => {
+                self.result.proposals = *number;
+                self.write(&format!("proposal_{}.rs", number), code);
+                self.store_transcript();
+            }
+            RunEvent::FixApplied { similarity, .. } => {
+                let similarities = &mut self.result.fix_similarities;
+                similarities.push(*similarity);
+                self.result.mean_fix_similarity =
+                    Some(similarities.iter().sum::<f64>() / similarities.len() as f64);
+            }
+            RunEvent::ProposalDiffed { diff } => {
+                self.result.proposal_diffs.push(diff.clone());
+            }
+            RunEvent::CriticsChosen { critic_types } => {
+                self.result.critic_types = critic_types.clone();
+            }
+            RunEvent::ApiAnomaly { anomaly, .. } => {
+                *self.result.api_anomalies.entry(*anomaly).or_default() += 1;
+            }
+            RunEvent::TestFailed { failure, .. } => {
+                *self.result.test_failures.entry(*failure).or_default() += 1;
+            }
+            RunEvent::TestPassed { code, .. } => {
+                self.result.solution = self
+                    .write(SOLUTION_FILENAME, code)
+                    .map(|path| path.display().to_string());
+            }
+            RunEvent::BestCandidate { code, summary, .. } => {
+                self.result.best_candidate = self
+                    .write(BEST_CANDIDATE_FILENAME, code)
+                    .map(|path| path.display().to_string());
+                self.result.best_candidate_summary = Some(summary.clone());
+            }
+            RunEvent::StateEntered { state } => {
+                if let Ok(json) = serde_json::to_string_pretty(state) {
+                    self.write(STATE_FILENAME, &json);
+                }
+            }
+            RunEvent::RunFinished { outcome } => {
+                self.result.outcome = outcome.clone();
+                if let Ok(json) = serde_json::to_string_pretty(&self.result) {
+                    self.write(RESULT_FILENAME, &json);
+                }
+                self.store_transcript();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_state::RunState;
+
+    #[test]
+    fn test_artifacts() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let context = RunContext::create(output_dir.path(), "run-1").unwrap();
+        let mut observers = context.observers("problems/p1.txt").unwrap();
+        let events = [
+            RunEvent::CriticsChosen {
+                critic_types: vec![CriticType::Design, CriticType::Syntax],
+            },
+            RunEvent::ChatRequested {
+                agent: "Coder".to_string(),
+                model: "gpt-4o".to_string(),
+                messages: vec![],
+                prompt_tokens: 1_000_000,
+            },
+            RunEvent::ProposalCreated {
+                number: 1,
+                code: "fn a() {}".to_string(),
+            },
+            RunEvent::FixApplied {
+                fixer: "Fixer_1".to_string(),
+                code: "fn b() {}".to_string(),
+                similarity: 0.25,
+            },
+            RunEvent::ProposalDiffed {
+                diff: ProposalDiff::new(2, "fn a() {}", "fn b() {}"),
+            },
+            RunEvent::FixApplied {
+                fixer: "Fixer_1".to_string(),
+                code: "fn b() {}".to_string(),
+                similarity: 0.75,
+            },
+            RunEvent::ApiAnomaly {
+                agent: "Coder".to_string(),
+                anomaly: Anomaly::LengthStop,
+            },
+            RunEvent::ApiAnomaly {
+                agent: "Fixer_1".to_string(),
+                anomaly: Anomaly::LengthStop,
+            },
+            RunEvent::BestCandidate {
+                proposal: 1,
+                code: "fn a() {}".to_string(),
+                summary: "it doesn't compile".to_string(),
+            },
+            RunEvent::TestPassed {
+                proposal: 1,
+                code: "fn b() {}".to_string(),
+                output: "ok".to_string(),
+            },
+            RunEvent::StateEntered {
+                state: RunState::Done { proposals: 1 },
+            },
+            RunEvent::RunFinished {
+                outcome: "Solved after 1 proposals".to_string(),
+            },
+        ];
+        for event in events.iter() {
+            for observer in observers.iter_mut() {
+                observer.on_event(event);
+            }
+        }
+        drop(observers);
+
+        let dir = output_dir.path().join("run-1");
+        assert_eq!(
+            fs::read_to_string(dir.join("proposal_1.rs")).unwrap(),
+            "fn a() {}"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join(SOLUTION_FILENAME)).unwrap(),
+            "fn b() {}"
+        );
+        let result: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join(RESULT_FILENAME)).unwrap()).unwrap();
+        assert_eq!(result["outcome"], "Solved after 1 proposals");
+        assert_eq!(result["proposals"], 1);
+        assert_eq!(result["model"], "gpt-4o");
+        assert_eq!(result["cost_usd"], 2.5);
+        assert!(result["best_candidate"]
+            .as_str()
+            .unwrap()
+            .ends_with(BEST_CANDIDATE_FILENAME));
+        assert_eq!(result["best_candidate_summary"], "it doesn't compile");
+        assert_eq!(
+            result["api_anomalies"],
+            serde_json::json!({"length_stop": 2})
+        );
+        assert_eq!(result["fix_similarities"], serde_json::json!([0.25, 0.75]));
+        assert_eq!(result["mean_fix_similarity"], 0.5);
+        assert_eq!(
+            result["critic_types"],
+            serde_json::json!(["design", "syntax"])
+        );
+        let transcript = fs::read_to_string(dir.join(TRANSCRIPT_FILENAME)).unwrap();
+        assert_eq!(result["proposal_diffs"][0]["proposal"], 2);
+        assert_eq!(
+            result["proposal_diffs"][0]["functions"][0]["change"],
+            "removed"
+        );
+        assert_eq!(transcript.lines().count(), 12);
+        let state: RunState =
+            serde_json::from_str(&fs::read_to_string(dir.join(STATE_FILENAME)).unwrap()).unwrap();
+        assert_eq!(state, RunState::Done { proposals: 1 });
+    }
+
+    #[test]
+    fn test_find() {
+        let output_dir = tempfile::tempdir().unwrap();
+        for id in ["run-1", "batch/batch-1", "batch/batch-2"] {
+            let dir = output_dir.path().join(id);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(TRANSCRIPT_FILENAME), "").unwrap();
+        }
+        fs::create_dir_all(output_dir.path().join("empty")).unwrap();
+        let ids: Vec<String> = RunContext::find_all(output_dir.path())
+            .into_iter()
+            .map(|run| run.id)
+            .collect();
+        assert_eq!(ids, vec!["batch-1", "batch-2", "run-1"]);
+
+        let run = RunContext::find(output_dir.path(), "batch-2").unwrap();
+        assert!(run.final_code().is_none());
+        fs::write(run.dir.join("proposal_1.rs"), "fn a() {}").unwrap();
+        fs::write(run.dir.join("proposal_2.rs"), "fn b() {}").unwrap();
+        assert_eq!(
+            run.final_code(),
+            Some(("proposal_2.rs".to_string(), "fn b() {}".to_string()))
+        );
+        fs::write(run.dir.join(SOLUTION_FILENAME), "fn c() {}").unwrap();
+        assert_eq!(run.final_code().unwrap().0, SOLUTION_FILENAME);
+        assert!(RunContext::find(output_dir.path(), "empty").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_store() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let copies = tempfile::tempdir().unwrap();
+        let store = crate::store::create(&crate::store::StoreConfig::Local {
+            dir: copies.path().to_path_buf(),
+        })
+        .unwrap();
+        let context = RunContext::create(output_dir.path(), "run-1")
+            .unwrap()
+            .with_store(Some(store));
+        let mut observers = context.observers("problems/p1.txt").unwrap();
+        let events = [
+            RunEvent::ProposalCreated {
+                number: 1,
+                code: "fn a() {}".to_string(),
+            },
+            RunEvent::RunFinished {
+                outcome: "Solved after 1 proposals".to_string(),
+            },
+        ];
+        for event in events.iter() {
+            for observer in observers.iter_mut() {
+                observer.on_event(event);
+            }
+        }
+        let build = context.dir.join(BUILDS_DIRNAME).join("build_1");
+        fs::create_dir_all(&build).unwrap();
+        fs::write(build.join("code.rs"), "fn a() {}").unwrap();
+        context.flush_store().await;
+        let copy = |file: &str| fs::read_to_string(copies.path().join("run-1").join(file));
+        assert_eq!(copy("builds/build_1/code.rs").unwrap(), "fn a() {}");
+        assert_eq!(copy("proposal_1.rs").unwrap(), "fn a() {}");
+        assert!(copy(RESULT_FILENAME)
+            .unwrap()
+            .contains("Solved after 1 proposals"));
+        assert_eq!(copy(TRANSCRIPT_FILENAME).unwrap(), context.transcript());
+        assert_eq!(context.transcript().lines().count(), 2);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::fixer::{ReviewNeeded, ReviewType};
+
+// The issues raised over a run, so that the fixer can be reminded of the ones it already fixed. Only
+// the latest review is sent to the fixer, which would otherwise reintroduce an issue fixed a couple
+// of rounds earlier. The critics' comments are kept whole. Compiler and test output is long, so only
+// its first line is kept, which names the error or the failed test.
+
+#[derive(Default)]
+pub struct RunHistory {
+    // The issues of each review given to the fixer, oldest first.
+    rounds: Vec<Vec<String>>,
+}
+
+impl RunHistory {
+    pub fn record(&mut self, review: &ReviewNeeded) {
+        let issues = match review.review_type {
+            ReviewType::CodeReview => review.comments.clone(),
+            ReviewType::CompilerFix => first_lines("Compile error", &review.comments),
+            ReviewType::TestFix => first_lines("Test failure", &review.comments),
+            ReviewType::FlakyTestFix => first_lines("Flaky tests", &review.comments),
+            ReviewType::RuntimeFix => first_lines("Runtime failure", &review.comments),
+            ReviewType::ComplexityFix => first_lines("Too slow", &review.comments),
+            ReviewType::PolicyFix => review.comments.clone(),
+            // Requests for more tests aren't issues that can be reintroduced.
+            ReviewType::TestStrengthening | ReviewType::TestAugmentation => vec![],
+        };
+        self.rounds.push(issues);
+    }
+
+    // The issues of the earlier rounds, which the fixer has since addressed, most recent first and
+    // without duplicates. Issues raised again by the current review aren't resolved, so they're left
+    // out.
+    pub fn resolved_issues(&self, current: &ReviewNeeded) -> Vec<String> {
+        let mut issues: Vec<String> = vec![];
+        for issue in self.rounds.iter().rev().flatten() {
+            if !current.comments.contains(issue) && !issues.contains(issue) {
+                issues.push(issue.clone());
+            }
+        }
+        issues
+    }
+}
+
+fn first_lines(label: &str, outputs: &[String]) -> Vec<String> {
+    outputs
+        .iter()
+        .filter_map(|output| output.lines().map(str::trim).find(|line| !line.is_empty()))
+        .map(|line| format!("{}: {}", label, line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(review_type: ReviewType, comments: &[&str]) -> ReviewNeeded {
+        ReviewNeeded::new(
+            review_type,
+            comments.iter().map(|c| c.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_resolved_issues() {
+        let mut history = RunHistory::default();
+        history.record(&review(
+            ReviewType::CodeReview,
+            &["Handle empty input.", "Rename x."],
+        ));
+        history.record(&review(
+            ReviewType::CompilerFix,
+            &["\nerror[E0425]: cannot find value `y`\n  --> code.rs:3:5"],
+        ));
+        history.record(&review(ReviewType::TestAugmentation, &["line 7"]));
+        history.record(&review(ReviewType::CodeReview, &["Handle empty input."]));
+
+        let current = review(ReviewType::CodeReview, &["Rename x."]);
+        assert_eq!(
+            history.resolved_issues(&current),
+            [
+                "Handle empty input.",
+                "Compile error: error[E0425]: cannot find value `y`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use crate::fixer::{ReviewNeeded, ReviewType};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// The run loop is a state machine. The coder writes the first proposal, then each proposal is
+// reviewed by the critics, fixed for their corrections, and tested, until one passes or the
+// convergence policy stops the run. The loop emits each state that it enters, so the transcript
+// records the path that the run took, and the run's directory keeps the latest state. The
+// transitions that depend only on the state, rather than on what the agents returned, are made by
+// the LoopOrder, so that each ordering of the loop is defined in one place.
+
+// Test-first has the critics review code that fails to compile after this many proposals in a row
+// do, in case the fixer is stuck on a misunderstanding that the compiler's errors don't reveal.
+const REVIEW_AFTER_COMPILE_FAILURES: usize = 2;
+
+// The order of each proposal's checks. Review-first has the critics review each proposal before it's
+// tested. Test-first tests it first, so that no critic tokens are spent on code that doesn't
+// compile, and has the critics check code that passes against the problem. Parallel tests it while
+// the critics review it, overlapping the two slowest steps, and fixes it for both at once.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Default)]
+pub enum LoopOrder {
+    #[default]
+    ReviewFirst,
+    TestFirst,
+    Parallel,
+}
+
+// Why a proposal is being fixed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FixReason {
+    // The proposal failed the checks made before the review, such as that it parses.
+    Validation,
+    Review,
+    Tests,
+    // The proposal passed its tests, but they are too weak.
+    TestQuality,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RunState {
+    Coding,
+    Reviewing {
+        proposal: usize,
+    },
+    Fixing {
+        proposal: usize,
+        reason: FixReason,
+        review: ReviewNeeded,
+    },
+    Testing {
+        proposal: usize,
+    },
+    Done {
+        proposals: usize,
+    },
+    Diverged {
+        proposals: usize,
+        reason: String,
+    },
+}
+
+impl RunState {
+    // The proposal that the state is working on, if any.
+    pub fn proposal(&self) -> Option<usize> {
+        match self {
+            RunState::Reviewing { proposal }
+            | RunState::Fixing { proposal, .. }
+            | RunState::Testing { proposal } => Some(*proposal),
+            RunState::Coding | RunState::Done { .. } | RunState::Diverged { .. } => None,
+        }
+    }
+}
+
+impl LoopOrder {
+    // The state after the coder writes the first proposal.
+    pub fn after_coding(self) -> RunState {
+        self.check(1)
+    }
+
+    // The state after fixing the proposal. Review-first tests code fixed for the critics as the
+    // same proposal. Any other fix makes the next proposal.
+    pub fn after_fix(self, proposal: usize, reason: FixReason) -> RunState {
+        match (self, reason) {
+            (LoopOrder::ReviewFirst, FixReason::Review) => RunState::Testing { proposal },
+            _ => self.check(proposal + 1),
+        }
+    }
+
+    // The state after the proposal fails its tests, the `compile_failures`th proposal in a row to
+    // fail to compile if it did.
+    pub fn after_test_failure(
+        self,
+        proposal: usize,
+        review: ReviewNeeded,
+        compile_failures: usize,
+    ) -> RunState {
+        match self {
+            LoopOrder::TestFirst if compile_failures >= REVIEW_AFTER_COMPILE_FAILURES => {
+                RunState::Reviewing { proposal }
+            }
+            _ => RunState::Fixing {
+                proposal,
+                reason: FixReason::Tests,
+                review,
+            },
+        }
+    }
+
+    // The state after the proposal passes its tests, or None if it's accepted. Parallel tests a
+    // proposal on its own only when its review is skipped.
+    pub fn after_test_pass(self, proposal: usize) -> Option<RunState> {
+        match self {
+            LoopOrder::ReviewFirst | LoopOrder::Parallel => None,
+            LoopOrder::TestFirst => Some(RunState::Reviewing { proposal }),
+        }
+    }
+
+    // The state after the critics approve the proposal, given how it failed its tests, if it has
+    // been tested and failed, or None if it's accepted.
+    pub fn after_approval(
+        self,
+        proposal: usize,
+        failure: Option<ReviewNeeded>,
+    ) -> Option<RunState> {
+        match (self, failure) {
+            (LoopOrder::ReviewFirst, _) => Some(RunState::Testing { proposal }),
+            (LoopOrder::TestFirst | LoopOrder::Parallel, Some(review)) => Some(RunState::Fixing {
+                proposal,
+                reason: FixReason::Tests,
+                review,
+            }),
+            (LoopOrder::TestFirst | LoopOrder::Parallel, None) => None,
+        }
+    }
+
+    // Whether the proposal is tested while the critics review it.
+    pub fn tests_while_reviewing(self) -> bool {
+        self == LoopOrder::Parallel
+    }
+
+    // The first check of a new proposal.
+    fn check(self, proposal: usize) -> RunState {
+        match self {
+            LoopOrder::ReviewFirst | LoopOrder::Parallel => RunState::Reviewing { proposal },
+            LoopOrder::TestFirst => RunState::Testing { proposal },
+        }
+    }
+}
+
+// Skips the critics' review of proposals that were fixed only for a failed test, once the critics
+// have approved enough reviews in a row. The critics have already accepted the design, so a fix to
+// the tests is unlikely to need another round of them. Any other fix, or a rejection, has them
+// review again.
+pub struct CriticSkipping {
+    // The approvals in a row after which reviews are skipped, or None to never skip them.
+    after: Option<usize>,
+    approvals: usize,
+}
+
+impl CriticSkipping {
+    pub fn new(after: Option<usize>) -> Self {
+        CriticSkipping {
+            after,
+            approvals: 0,
+        }
+    }
+
+    pub fn record_review(&mut self, approved: bool) {
+        self.approvals = if approved { self.approvals + 1 } else { 0 };
+    }
+
+    // The approvals in a row so far.
+    pub fn approvals(&self) -> usize {
+        self.approvals
+    }
+
+    // The state after fixing a proposal for the review, given the order's next state: testing the
+    // new proposal instead of reviewing it, if its review is skipped.
+    pub fn after_fix(&self, next: RunState, review: &ReviewNeeded) -> RunState {
+        match next {
+            RunState::Reviewing { proposal } if self.skips(review) => {
+                RunState::Testing { proposal }
+            }
+            next => next,
+        }
+    }
+
+    fn skips(&self, review: &ReviewNeeded) -> bool {
+        matches!(
+            review.review_type,
+            ReviewType::TestFix | ReviewType::FlakyTestFix | ReviewType::RuntimeFix
+        ) && self.after.is_some_and(|after| self.approvals >= after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixer::ReviewType;
+
+    fn failure() -> ReviewNeeded {
+        ReviewNeeded::new(
+            ReviewType::CompilerFix,
+            vec!["error[E0425]: cannot find value `x`".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_review_first() {
+        let order = LoopOrder::ReviewFirst;
+        assert_eq!(order.after_coding(), RunState::Reviewing { proposal: 1 });
+        assert_eq!(
+            order.after_fix(2, FixReason::Review),
+            RunState::Testing { proposal: 2 }
+        );
+        for reason in [
+            FixReason::Validation,
+            FixReason::Tests,
+            FixReason::TestQuality,
+        ] {
+            assert_eq!(
+                order.after_fix(2, reason),
+                RunState::Reviewing { proposal: 3 }
+            );
+        }
+        assert!(matches!(
+            order.after_test_failure(2, failure(), 5),
+            RunState::Fixing {
+                proposal: 2,
+                reason: FixReason::Tests,
+                ..
+            }
+        ));
+        assert_eq!(order.after_test_pass(2), None);
+        assert_eq!(
+            order.after_approval(2, None),
+            Some(RunState::Testing { proposal: 2 })
+        );
+    }
+
+    #[test]
+    fn test_test_first() {
+        let order = LoopOrder::TestFirst;
+        assert_eq!(order.after_coding(), RunState::Testing { proposal: 1 });
+        assert_eq!(
+            order.after_fix(2, FixReason::Review),
+            RunState::Testing { proposal: 3 }
+        );
+        assert!(matches!(
+            order.after_test_failure(2, failure(), 1),
+            RunState::Fixing { .. }
+        ));
+        assert_eq!(
+            order.after_test_failure(2, failure(), REVIEW_AFTER_COMPILE_FAILURES),
+            RunState::Reviewing { proposal: 2 }
+        );
+        assert_eq!(
+            order.after_test_pass(2),
+            Some(RunState::Reviewing { proposal: 2 })
+        );
+        assert!(matches!(
+            order.after_approval(2, Some(failure())),
+            Some(RunState::Fixing {
+                reason: FixReason::Tests,
+                ..
+            })
+        ));
+        assert_eq!(order.after_approval(2, None), None);
+    }
+
+    #[test]
+    fn test_parallel() {
+        let order = LoopOrder::Parallel;
+        assert!(order.tests_while_reviewing());
+        assert!(!LoopOrder::ReviewFirst.tests_while_reviewing());
+        assert_eq!(order.after_coding(), RunState::Reviewing { proposal: 1 });
+        for reason in [FixReason::Review, FixReason::Tests] {
+            assert_eq!(
+                order.after_fix(2, reason),
+                RunState::Reviewing { proposal: 3 }
+            );
+        }
+        assert!(matches!(
+            order.after_approval(2, Some(failure())),
+            Some(RunState::Fixing {
+                reason: FixReason::Tests,
+                ..
+            })
+        ));
+        assert_eq!(order.after_approval(2, None), None);
+        assert_eq!(order.after_test_pass(2), None);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let state = RunState::Fixing {
+            proposal: 3,
+            reason: FixReason::Tests,
+            review: ReviewNeeded::new(
+                ReviewType::TestFix,
+                vec!["test tests::test_add ... FAILED".to_string()],
+            ),
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.starts_with(r#"{"state":"fixing","proposal":3,"reason":"tests","#));
+        assert_eq!(serde_json::from_str::<RunState>(&json).unwrap(), state);
+    }
+
+    #[test]
+    fn test_critic_skipping() {
+        let test_fix = ReviewNeeded::new(
+            ReviewType::TestFix,
+            vec!["test tests::test_add ... FAILED".to_string()],
+        );
+        let next = || RunState::Reviewing { proposal: 3 };
+        let mut skipping = CriticSkipping::new(Some(2));
+        skipping.record_review(true);
+        assert_eq!(skipping.after_fix(next(), &test_fix), next());
+        skipping.record_review(true);
+        assert_eq!(
+            skipping.after_fix(next(), &test_fix),
+            RunState::Testing { proposal: 3 }
+        );
+        // Only test failures skip the review, and only the reviewing of a new proposal is skipped.
+        assert_eq!(skipping.after_fix(next(), &failure()), next());
+        assert_eq!(
+            skipping.after_fix(RunState::Testing { proposal: 2 }, &test_fix),
+            RunState::Testing { proposal: 2 }
+        );
+        skipping.record_review(false);
+        assert_eq!(skipping.after_fix(next(), &test_fix), next());
+        assert_eq!(
+            CriticSkipping::new(None).after_fix(next(), &test_fix),
+            next()
+        );
+    }
+}
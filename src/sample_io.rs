@@ -0,0 +1,129 @@
+// A problem that asks for a program, rather than a function, can give sample runs of it, as in
+// programming contests:
+//
+//   Sample Input 1:
+//   3 4
+//   Sample Output 1:
+//   7
+//
+// Each section starts with a heading line beginning with "Sample Input", "Sample Output", "Example
+// Input", or "Example Output" and ending in a colon, in any case and with an optional number. The
+// section's text runs until the next blank line or heading. Each input is paired with the output
+// that follows it. The samples stay in the problem that the agents see, and the tester runs the
+// compiled program on each input and compares what it prints with the expected output.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub input: String,
+    pub output: String,
+}
+
+#[derive(PartialEq)]
+enum Section {
+    Input,
+    Output,
+}
+
+fn heading(line: &str) -> Option<Section> {
+    let line = line.trim().to_lowercase();
+    if !line.ends_with(':') {
+        return None;
+    }
+    let rest = line
+        .strip_prefix("sample ")
+        .or_else(|| line.strip_prefix("example "))?;
+    let label = rest.trim_end_matches(':').trim_end();
+    let label = label
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end();
+    match label {
+        "input" => Some(Section::Input),
+        "output" => Some(Section::Output),
+        _ => None,
+    }
+}
+
+pub fn parse(problem: &str) -> Vec<Sample> {
+    let mut samples = vec![];
+    let mut input: Option<String> = None;
+    let mut section: Option<(Section, Vec<&str>)> = None;
+    let mut close = |section: Option<(Section, Vec<&str>)>, input: &mut Option<String>| {
+        if let Some((kind, lines)) = section {
+            let mut text = lines.join("\n");
+            text.push('\n');
+            match kind {
+                Section::Input => *input = Some(text),
+                Section::Output => {
+                    if let Some(input) = input.take() {
+                        samples.push(Sample {
+                            input,
+                            output: text,
+                        });
+                    }
+                }
+            }
+        }
+    };
+    for line in problem.lines() {
+        if let Some(kind) = heading(line) {
+            close(section.take(), &mut input);
+            section = Some((kind, vec![]));
+        } else if line.trim().is_empty() {
+            close(section.take(), &mut input);
+        } else if let Some((_, lines)) = section.as_mut() {
+            lines.push(line);
+        }
+    }
+    close(section.take(), &mut input);
+    samples
+}
+
+// Whether the program's output matches the expected output, ignoring trailing whitespace on each
+// line and trailing blank lines.
+pub fn matches(expected: &str, actual: &str) -> bool {
+    let normalize = |text: &str| -> Vec<String> {
+        let mut lines: Vec<String> = text.lines().map(|l| l.trim_end().to_string()).collect();
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines
+    };
+    normalize(expected) == normalize(actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let problem = "Read two numbers and print their sum.\n\n\
+                       Sample Input 1:\n3 4\nSample Output 1:\n7\n\n\
+                       Example input:\n1 2\n\nexample output:\n3\n\
+                       Sample Output 3:\n9\n\
+                       The input may be large.\n";
+        assert_eq!(
+            parse(problem),
+            vec![
+                Sample {
+                    input: "3 4\n".to_string(),
+                    output: "7\n".to_string()
+                },
+                Sample {
+                    input: "1 2\n".to_string(),
+                    output: "3\n".to_string()
+                },
+            ]
+        );
+        assert!(parse("Write a function that adds two numbers.\n").is_empty());
+        assert!(parse("Sample inputs are given below:\n").is_empty());
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("1\n2\n", "1  \n2\n\n"));
+        assert!(matches("1\n2", "1\n2\n"));
+        assert!(!matches("1\n2\n", "1\n3\n"));
+        assert!(!matches("1\n\n2\n", "1\n2\n"));
+    }
+}
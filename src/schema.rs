@@ -0,0 +1,227 @@
+use crate::errors::AiCriticError;
+use color_eyre::eyre::Result;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+// The fields of the agents' JSON responses are kept here rather than in each agent. Each kind of
+// response has a versioned schema, and an agent asks for the latest version of its kind, so that a
+// field is added, such as a severity or a confidence, by adding a version here and the field to the
+// type that the response is deserialized into. A field that a model may leave out, as it may with
+// one that its prompt doesn't mention yet, is optional: its absence isn't an error, and its
+// presence isn't warned about as an extra key.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    // The code written by the coder or the fixer.
+    Code,
+    // A critic's review.
+    Review,
+    // The review of a critic that traces examples through the code.
+    VerifiedReview,
+    // The reviews of a batched critic, one for each type of critic.
+    BatchedReview,
+    // The ranker's choice between two candidate fixes.
+    Ranking,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub required: bool,
+}
+
+const fn required(name: &'static str) -> Field {
+    Field {
+        name,
+        required: true,
+    }
+}
+
+const fn optional(name: &'static str) -> Field {
+    Field {
+        name,
+        required: false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Schema {
+    pub kind: Kind,
+    pub version: u32,
+    pub fields: &'static [Field],
+}
+
+// Every version of every schema, from the oldest of each kind to the latest.
+static SCHEMAS: &[Schema] = &[
+    Schema {
+        kind: Kind::Code,
+        version: 1,
+        fields: &[required("code")],
+    },
+    Schema {
+        kind: Kind::Review,
+        version: 1,
+        fields: &[required("lgtm"), required("corrections")],
+    },
+    // Critics sometimes trace examples even when they aren't asked to.
+    Schema {
+        kind: Kind::Review,
+        version: 2,
+        fields: &[
+            required("lgtm"),
+            required("corrections"),
+            optional("evidence"),
+        ],
+    },
+    Schema {
+        kind: Kind::VerifiedReview,
+        version: 1,
+        fields: &[
+            required("lgtm"),
+            required("corrections"),
+            required("evidence"),
+        ],
+    },
+    // A batched critic reviews only the types that --disable-critic leaves in, so the critic checks
+    // for the reviews it asked for.
+    Schema {
+        kind: Kind::BatchedReview,
+        version: 1,
+        fields: &[
+            optional("design"),
+            optional("correctness"),
+            optional("syntax"),
+        ],
+    },
+    // The Idiomatic critic's review, with --idiomatic.
+    Schema {
+        kind: Kind::BatchedReview,
+        version: 2,
+        fields: &[
+            optional("design"),
+            optional("correctness"),
+            optional("syntax"),
+            optional("idiomatic"),
+        ],
+    },
+    Schema {
+        kind: Kind::Ranking,
+        version: 1,
+        fields: &[required("better"), optional("reason")],
+    },
+];
+
+// The latest version of the kind's schema.
+pub fn latest(kind: Kind) -> &'static Schema {
+    SCHEMAS
+        .iter()
+        .filter(|schema| schema.kind == kind)
+        .max_by_key(|schema| schema.version)
+        .expect("every kind has a schema")
+}
+
+// The given version of the kind's schema, if there is one.
+#[cfg(test)]
+pub fn find(kind: Kind, version: u32) -> Option<&'static Schema> {
+    SCHEMAS
+        .iter()
+        .find(|schema| schema.kind == kind && schema.version == version)
+}
+
+impl Schema {
+    // Check that the value is an object with the schema's required fields, returning the keys that
+    // aren't in the schema.
+    pub fn validate(&self, value: &Value) -> Result<Vec<String>> {
+        let object = value.as_object().ok_or(AiCriticError::NotJsonObject)?;
+        let missing = self.missing(value);
+        if !missing.is_empty() {
+            return Err(AiCriticError::MissingJsonFields { fields: missing }.into());
+        }
+        let known: BTreeSet<&str> = self.fields.iter().map(|field| field.name).collect();
+        Ok(object
+            .keys()
+            .filter(|key| !known.contains(key.as_str()))
+            .cloned()
+            .collect())
+    }
+
+    // The schema's required fields that the value, an object, doesn't have.
+    pub fn missing(&self, value: &Value) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|field| field.required && value.get(field.name).is_none())
+            .map(|field| field.name.to_string())
+            .collect()
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} v{}", self.kind, self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(latest(Kind::Review).version, 2);
+        assert_eq!(latest(Kind::Review).to_string(), "Review v2");
+        assert_eq!(find(Kind::Code, 1), Some(latest(Kind::Code)));
+        assert_eq!(find(Kind::Code, 2), None);
+        // A later version keeps every field of the earlier ones, and requires no new ones, so that
+        // the responses to older prompts still validate.
+        for schema in SCHEMAS {
+            if let Some(earlier) = find(schema.kind, schema.version - 1) {
+                for field in earlier.fields {
+                    assert!(
+                        schema.fields.contains(field),
+                        "{} lost {}",
+                        schema,
+                        field.name
+                    );
+                }
+                assert!(schema
+                    .fields
+                    .iter()
+                    .all(|field| !field.required || earlier.fields.contains(field)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let review = latest(Kind::Review);
+        let extra = review
+            .validate(&json!({"lgtm": true, "corrections": [], "evidence": [], "notes": ""}))
+            .unwrap();
+        assert_eq!(extra, vec!["notes"]);
+
+        let error = review.validate(&json!({"lgtm": true})).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AiCriticError>(),
+            Some(AiCriticError::MissingJsonFields { fields }) if *fields == ["corrections"]
+        ));
+        let error = latest(Kind::VerifiedReview)
+            .validate(&json!({"lgtm": true, "corrections": []}))
+            .unwrap_err();
+        assert!(error.to_string().contains("evidence"));
+
+        let review = json!({"lgtm": true, "corrections": []});
+        let batch = json!({"design": review, "syntax": review, "idiomatic": review});
+        let extra = latest(Kind::BatchedReview).validate(&batch).unwrap();
+        assert!(extra.is_empty(), "{:?}", extra);
+
+        let error = latest(Kind::Code)
+            .validate(&json!("fn a() {}"))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AiCriticError>(),
+            Some(AiCriticError::NotJsonObject)
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::critic::{Correction, CriticType};
+
+// With --screen-model, each review is done in two tiers to save on the expensive model. One critic
+// of each type, using the cheap screening model, reviews the code first. If they all approve, the
+// review ends there, unless it's picked for a spot check, which happens with the probability of
+// --confirm-probability. Otherwise, as when a screening critic fails, the usual critics confirm the
+// review. The confirming critics have the final say: a screening critic's corrections are kept only
+// if a confirming critic of its type also rejected the code, and are dropped as false alarms if not.
+
+// Whether to confirm a review that the screening critics approved. The choice is a function of the
+// seed and the code, so that a seeded run is reproducible. It's drawn with FNV-1a rather than std's
+// hasher, whose output may change between Rust releases.
+pub fn spot_checked(probability: f64, seed: Option<i64>, code: &str) -> bool {
+    let seed = match seed {
+        Some(seed) => [&[1][..], &seed.to_le_bytes()].concat(),
+        None => vec![0],
+    };
+    let draw = fnv1a(&[&seed[..], code.as_bytes()].concat()) as f64 / u64::MAX as f64;
+    draw < probability
+}
+
+// The 64-bit FNV-1a hash of the bytes.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// The corrections of the review: the confirming critics', followed by the rejections of the
+// screening critics whose type the confirming critics also rejected.
+pub fn merge(
+    screen: Vec<(CriticType, Correction)>,
+    confirmed: Vec<(CriticType, Correction)>,
+) -> Vec<Correction> {
+    let rejected: Vec<CriticType> = confirmed
+        .iter()
+        .filter(|(_, c)| !c.lgtm)
+        .map(|(critic_type, _)| *critic_type)
+        .collect();
+    let kept = screen
+        .into_iter()
+        .filter(|(critic_type, c)| !c.lgtm && rejected.contains(critic_type));
+    confirmed
+        .into_iter()
+        .chain(kept)
+        .map(|(_, correction)| correction)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn correction(name: &str, lgtm: bool) -> Correction {
+        Correction {
+            name: name.to_string(),
+            lgtm,
+            corrections: match lgtm {
+                true => vec![],
+                false => vec![format!("{} says fix it", name)],
+            },
+            locations: vec![],
+            evidence: vec![],
+        }
+    }
+
+    #[test]
+    fn test_spot_checked() {
+        assert!(!spot_checked(0.0, Some(1), "fn a() {}"));
+        assert!(spot_checked(1.0, Some(1), "fn a() {}"));
+        assert_eq!(
+            spot_checked(0.5, Some(1), "fn a() {}"),
+            spot_checked(0.5, Some(1), "fn a() {}")
+        );
+        let checked = (0..1000)
+            .filter(|i| spot_checked(0.25, None, &format!("fn a{}() {{}}", i)))
+            .count();
+        assert!((150..350).contains(&checked), "{}", checked);
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn test_merge() {
+        let screen = vec![
+            (
+                CriticType::Design,
+                correction("Design Critic 1 (screen)", false),
+            ),
+            (
+                CriticType::Correctness,
+                correction("Correctness Critic 1 (screen)", false),
+            ),
+            (
+                CriticType::Syntax,
+                correction("Syntax Critic 1 (screen)", true),
+            ),
+        ];
+        let confirmed = vec![
+            (CriticType::Design, correction("Design Critic 1", true)),
+            (
+                CriticType::Correctness,
+                correction("Correctness Critic 1", false),
+            ),
+            (CriticType::Syntax, correction("Syntax Critic 1", true)),
+        ];
+        let names: Vec<String> = merge(screen, confirmed)
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Design Critic 1",
+                "Correctness Critic 1",
+                "Syntax Critic 1",
+                "Correctness Critic 1 (screen)"
+            ]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::chatter_json::OpenAIClientTrait;
+use crate::images;
+use crate::tester::{self, Tester, TesterResult};
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionResponseStream,
+    ChatCompletionResponseStreamMessage, ChatCompletionStreamResponseDelta,
+    CreateChatCompletionRequest, CreateChatCompletionStreamResponse, FinishReason, Role,
+};
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use futures::{stream, StreamExt};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Stand-ins for the model and the tester, so that the run loop can be driven end to end without an
+// API key or a compiler. A ScriptedClient answers each request with the response that its script
+// returns for the request's system prompt and user messages. The agents can be told apart by their
+// system prompts, and the critics run in parallel, so a script that matches on the prompts is
+// deterministic where a fixed sequence of responses wouldn't be. The response is streamed as one
+// chunk, or in chunks of a few characters at a time, as a model would, once for each of the choices
+// that the request asks for. A ScriptedTester reports a list of results in order, or judges each
+// code with a script of its own.
+
+type Script = dyn Fn(&str, &str) -> String + Send + Sync;
+type Judge = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+pub struct ScriptedClient {
+    script: Box<Script>,
+    // The characters per chunk, or 0 to send the response in one chunk, and the wait between them.
+    chunk_chars: usize,
+    chunk_delay: Duration,
+}
+
+impl ScriptedClient {
+    // The script is given the system prompt and the user messages, joined by newlines, and returns
+    // the JSON response.
+    pub fn new(script: impl Fn(&str, &str) -> String + Send + Sync + 'static) -> Self {
+        ScriptedClient {
+            script: Box::new(script),
+            chunk_chars: 0,
+            chunk_delay: Duration::ZERO,
+        }
+    }
+
+    // Stream the responses in chunks of `chars` characters, waiting `delay` before each one.
+    pub fn with_chunks(self, chars: usize, delay: Duration) -> Self {
+        ScriptedClient {
+            chunk_chars: chars,
+            chunk_delay: delay,
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl OpenAIClientTrait for ScriptedClient {
+    async fn create_chat_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let mut system = vec![];
+        let mut user = vec![];
+        for msg in &request.messages {
+            match msg {
+                ChatCompletionRequestMessage::System(m) => system.extend(m.content.clone()),
+                ChatCompletionRequestMessage::User(m) => {
+                    user.extend(m.content.as_ref().map(images::content_text))
+                }
+                _ => {}
+            }
+        }
+        let response = (self.script)(&system.join("\n"), &user.join("\n"));
+        let chars: Vec<char> = response.chars().collect();
+        let mut contents: Vec<String> = match self.chunk_chars {
+            0 => vec![response],
+            n => chars.chunks(n).map(|c| c.iter().collect()).collect(),
+        };
+        let last = contents.pop().unwrap_or_default();
+        let mut chunks = vec![];
+        for index in 0..request.n.unwrap_or(1).max(1) as u32 {
+            chunks.extend(
+                contents
+                    .iter()
+                    .map(|content| chunk(index, content.clone(), None))
+                    .chain([chunk(index, last.clone(), Some(FinishReason::Stop))]),
+            );
+        }
+        let delay = self.chunk_delay;
+        Ok(Box::pin(stream::iter(chunks).then(
+            move |chunk| async move {
+                tokio::time::sleep(delay).await;
+                Ok(chunk)
+            },
+        )))
+    }
+}
+
+// A streamed chunk of the response's choice, with the finish reason if it's the last.
+fn chunk(
+    index: u32,
+    content: String,
+    finish_reason: Option<FinishReason>,
+) -> CreateChatCompletionStreamResponse {
+    CreateChatCompletionStreamResponse {
+        id: String::new(),
+        choices: vec![ChatCompletionResponseStreamMessage {
+            index,
+            #[allow(deprecated)]
+            delta: ChatCompletionStreamResponseDelta {
+                content: Some(content),
+                role: Some(Role::Assistant),
+                tool_calls: None,
+                function_call: None, // Deprecated.
+            },
+            finish_reason,
+        }],
+        created: 0,
+        model: String::new(),
+        object: "chat.completion.chunk".to_string(),
+        system_fingerprint: None,
+    }
+}
+
+pub struct ScriptedTester {
+    // The output of each test run, in order, or None for a run that passes. Once they run out, the
+    // code passes.
+    results: Mutex<VecDeque<Option<String>>>,
+    // Returns the output of testing the code, or None if it passes, in place of the results.
+    judge: Option<Box<Judge>>,
+    tested: Mutex<Vec<String>>,
+}
+
+impl ScriptedTester {
+    pub fn new(results: Vec<Option<&str>>) -> Self {
+        ScriptedTester {
+            results: Mutex::new(results.into_iter().map(|r| r.map(String::from)).collect()),
+            judge: None,
+            tested: Mutex::new(vec![]),
+        }
+    }
+
+    // A tester whose result depends on the code rather than on the order of the runs, as when
+    // candidates are tested in parallel.
+    pub fn judging(judge: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        ScriptedTester {
+            judge: Some(Box::new(judge)),
+            ..ScriptedTester::new(vec![])
+        }
+    }
+
+    // The code of each test run, in order.
+    pub fn tested(&self) -> Vec<String> {
+        self.tested.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Tester for ScriptedTester {
+    async fn compile_and_test(&self, code: &str) -> Result<TesterResult> {
+        self.tested.lock().unwrap().push(code.to_string());
+        let result = match &self.judge {
+            Some(judge) => judge(code),
+            None => self.results.lock().unwrap().pop_front().flatten(),
+        };
+        Ok(match result {
+            None => TesterResult::Success {
+                stdout: "test result: ok.".to_string(),
+                exec_path: "".into(),
+            },
+            Some(output) => tester::test_failure(output),
+        })
+    }
+}
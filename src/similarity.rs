@@ -0,0 +1,54 @@
+// How much of the code a fix keeps, to tell surgical fixes from rewrites and to relate either to how
+// quickly a run converges. The similarity of two versions of the code is the share of their lines
+// in common, as in Python's difflib: twice the length of their longest common subsequence of lines
+// over their total number of lines. It's 1 for identical code and 0 for code with no line in
+// common. The lines are compared without their indentation, so re-indenting a block doesn't count
+// as rewriting it, and blank lines are ignored.
+
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let lines = |code: &str| -> Vec<String> {
+        code.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    let (a, b) = (lines(a), lines(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    2.0 * longest_common_subsequence(&a, &b) as f64 / (a.len() + b.len()) as f64
+}
+
+fn longest_common_subsequence(a: &[String], b: &[String]) -> usize {
+    // One row of the dynamic-programming table at a time.
+    let mut previous = vec![0; b.len() + 1];
+    for line in a {
+        let mut current = vec![0; b.len() + 1];
+        for (j, other) in b.iter().enumerate() {
+            current[j + 1] = match line == other {
+                true => previous[j] + 1,
+                false => current[j].max(previous[j + 1]),
+            };
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similarity() {
+        let code = "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+        assert_eq!(similarity(code, code), 1.0);
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity(code, "struct S;\n"), 0.0);
+        let fixed = "fn add(a: i32, b: i32) -> i32 {\n    a.wrapping_add(b)\n}\n";
+        assert!((similarity(code, fixed) - 4.0 / 6.0).abs() < 1e-9);
+        let reindented = "mod m {\nfn add(a: i32, b: i32) -> i32 {\n\n        a + b\n}\n}\n";
+        assert!((similarity(code, reindented) - 6.0 / 8.0).abs() < 1e-9);
+    }
+}
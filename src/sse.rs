@@ -0,0 +1,69 @@
+use async_openai::error::OpenAIError;
+use async_openai::types::{ChatCompletionResponseStream, CreateChatCompletionStreamResponse};
+use futures::{future, stream, StreamExt};
+
+// The clients that make their own HTTP requests, for Gemini and the reasoning models, read the
+// streamed responses as server-sent events. Each `data:` line is an event, parsed into a chunk by
+// the client, and the `[DONE]` that OpenAI ends its streams with is skipped. The bytes are only
+// decoded a line at a time, since a chunk of the HTTP response can end in the middle of a
+// character.
+
+// The chunks of the response's events, parsed with the client's parser.
+pub fn stream(
+    response: reqwest::Response,
+    parse: fn(&str) -> Result<CreateChatCompletionStreamResponse, OpenAIError>,
+) -> ChatCompletionResponseStream {
+    let chunks = response
+        .bytes_stream()
+        .scan(vec![], move |buffer, bytes| {
+            future::ready(Some(match bytes {
+                Ok(bytes) => events(buffer, &bytes)
+                    .iter()
+                    .map(|data| parse(data))
+                    .collect(),
+                Err(e) => vec![Err(OpenAIError::Reqwest(e))],
+            }))
+        })
+        .flat_map(stream::iter);
+    Box::pin(chunks)
+}
+
+// The data of the complete events in the buffer after appending the received bytes. A partial event
+// is left in the buffer for the next call.
+fn events(buffer: &mut Vec<u8>, bytes: &[u8]) -> Vec<String> {
+    buffer.extend_from_slice(bytes);
+    let mut events = vec![];
+    while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = buffer.drain(..=end).collect();
+        let line = String::from_utf8_lossy(&line);
+        match line.trim_end().strip_prefix("data:").map(str::trim) {
+            Some("[DONE]") | None => {}
+            Some(data) => events.push(data.to_string()),
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let mut buffer = vec![];
+        let event = "data: {\"text\": \"π\"}\n\n".as_bytes();
+        // A character split between two chunks of the response.
+        let middle = event.iter().position(|&b| b > 0x7f).unwrap() + 1;
+        assert!(events(&mut buffer, &event[..middle]).is_empty());
+        assert_eq!(
+            events(&mut buffer, &event[middle..]),
+            vec!["{\"text\": \"π\"}"]
+        );
+        let events = events(
+            &mut buffer,
+            b": comment\nevent: x\ndata: 1\r\n\ndata: [DONE]\n\n",
+        );
+        assert_eq!(events, vec!["1"]);
+        assert!(buffer.is_empty());
+    }
+}
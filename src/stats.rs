@@ -0,0 +1,186 @@
+use serde::Serialize;
+
+// Statistics for comparing the outcomes of runs, used by the data tools. A handful of runs per cell
+// is too few to assume a normal distribution, so the confidence intervals of means are bootstrapped
+// and differences of means are tested by permutation. Both resample with a fixed seed, so the same
+// runs always give the same results.
+
+const RESAMPLES: usize = 1000;
+const SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+// The z-score of a two-sided 95% interval.
+const Z_95: f64 = 1.959_964;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    // The 95% confidence interval of the mean.
+    pub ci: (f64, f64),
+}
+
+pub fn summarize(values: &[f64]) -> Option<Summary> {
+    if values.is_empty() {
+        return None;
+    }
+    let n = values.len();
+    let mean = mean(values);
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = match n % 2 {
+        0 => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+        _ => sorted[n / 2],
+    };
+    let stddev = match n {
+        1 => 0.0,
+        _ => (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt(),
+    };
+    Some(Summary {
+        n,
+        mean,
+        median,
+        stddev,
+        ci: bootstrap_ci(values),
+    })
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// The percentile bootstrap: the 2.5th and 97.5th percentiles of the means of resamples drawn with
+// replacement.
+fn bootstrap_ci(values: &[f64]) -> (f64, f64) {
+    let mut rng = Rng(SEED);
+    let mut means: Vec<f64> = (0..RESAMPLES)
+        .map(|_| {
+            let resample: Vec<f64> = (0..values.len())
+                .map(|_| values[rng.below(values.len())])
+                .collect();
+            mean(&resample)
+        })
+        .collect();
+    means.sort_by(f64::total_cmp);
+    let percentile = |p: f64| means[((means.len() - 1) as f64 * p).round() as usize];
+    (percentile(0.025), percentile(0.975))
+}
+
+// The Wilson score interval of a proportion, which stays within [0, 1] and is reasonable for small
+// samples and for proportions near 0 or 1, unlike the normal approximation.
+pub fn wilson_interval(successes: usize, n: usize) -> (f64, f64) {
+    if n == 0 {
+        return (0.0, 1.0);
+    }
+    let n = n as f64;
+    let p = successes as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let half = Z_95 / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    ((center - half).max(0.0), (center + half).min(1.0))
+}
+
+// The two-sided p-value of the difference between two proportions, by the pooled z-test.
+pub fn proportion_p_value(successes_a: usize, n_a: usize, successes_b: usize, n_b: usize) -> f64 {
+    if n_a == 0 || n_b == 0 {
+        return 1.0;
+    }
+    let (n_a, n_b) = (n_a as f64, n_b as f64);
+    let pooled = (successes_a + successes_b) as f64 / (n_a + n_b);
+    let se = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+    if se == 0.0 {
+        return 1.0;
+    }
+    let z = (successes_a as f64 / n_a - successes_b as f64 / n_b) / se;
+    2.0 * (1.0 - normal_cdf(z.abs()))
+}
+
+// The two-sided p-value of the difference between the means of two samples: the share of random
+// relabelings of the pooled values whose difference of means is at least as large as the observed
+// one.
+pub fn permutation_p_value(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+    let observed = (mean(a) - mean(b)).abs();
+    let mut pooled: Vec<f64> = a.iter().chain(b).copied().collect();
+    let mut rng = Rng(SEED);
+    let mut extreme = 0;
+    for _ in 0..RESAMPLES {
+        // A Fisher-Yates shuffle.
+        for i in (1..pooled.len()).rev() {
+            pooled.swap(i, rng.below(i + 1));
+        }
+        let (x, y) = pooled.split_at(a.len());
+        if (mean(x) - mean(y)).abs() >= observed - 1e-12 {
+            extreme += 1;
+        }
+    }
+    // Counting the observed labeling keeps the p-value above 0.
+    (extreme + 1) as f64 / (RESAMPLES + 1) as f64
+}
+
+// The standard normal CDF, from the error function approximation 7.1.26 of Abramowitz and Stegun,
+// which is accurate to about 1e-7.
+fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * z);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    match x >= 0.0 {
+        true => 0.5 * (1.0 + erf),
+        false => 0.5 * (1.0 - erf),
+    }
+}
+
+// A xorshift64* generator, which is plenty for resampling.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize(&[]), None);
+
+        let summary = summarize(&[4.0, 1.0, 3.0, 2.0]).unwrap();
+        assert_eq!(summary.n, 4);
+        assert_eq!(summary.mean, 2.5);
+        assert_eq!(summary.median, 2.5);
+        assert!((summary.stddev - 1.2910).abs() < 1e-4);
+        let (low, high) = summary.ci;
+        assert!((1.0..2.5).contains(&low) && (2.5..=4.0).contains(&high));
+
+        let single = summarize(&[3.0]).unwrap();
+        assert_eq!((single.stddev, single.ci), (0.0, (3.0, 3.0)));
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let (low, high) = wilson_interval(8, 10);
+        assert!((low - 0.4902).abs() < 1e-3 && (high - 0.9433).abs() < 1e-3);
+        assert_eq!(wilson_interval(0, 0), (0.0, 1.0));
+
+        assert!((normal_cdf(1.959_964) - 0.975).abs() < 1e-6);
+        assert!((proportion_p_value(45, 50, 30, 50) - 0.0006).abs() < 1e-4);
+        assert_eq!(proportion_p_value(5, 5, 5, 5), 1.0);
+
+        let a = [1.0, 2.0, 1.0, 2.0, 1.0, 2.0, 1.0, 2.0];
+        let b = [5.0, 6.0, 5.0, 6.0, 5.0, 6.0, 5.0, 6.0];
+        assert!(permutation_p_value(&a, &b) < 0.01);
+        assert!(permutation_p_value(&a, &a) > 0.9);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::Notify;
+
+// Steering lets the user abandon a response while it streams in and have it regenerated, optionally
+// with a hint about what to do differently, e.g. when the preview shows that the coder misunderstood
+// the problem. A request is for the response that started streaming most recently, so that of the
+// critics streaming in parallel, only one is abandoned. A request made while nothing is streaming is
+// dropped, and a stream starts without any request left over from before it, so that a stale press
+// doesn't throw away a response that nobody asked to redo.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regenerate {
+    pub hint: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    // The agents whose responses are streaming, in the order they started.
+    streams: Vec<String>,
+    // The requests for each agent that it hasn't picked up yet.
+    pending: HashMap<String, Regenerate>,
+}
+
+#[derive(Clone, Default)]
+pub struct Steering {
+    state: Arc<Mutex<State>>,
+    notify: Arc<Notify>,
+}
+
+// An agent's response streaming, which ends when this is dropped.
+pub struct Stream {
+    steering: Steering,
+    agent: String,
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let mut state = self.steering.state.lock().unwrap();
+        if let Some(i) = state.streams.iter().rposition(|a| *a == self.agent) {
+            state.streams.remove(i);
+        }
+        state.pending.remove(&self.agent);
+    }
+}
+
+impl Steering {
+    // Ask for the latest response that's streaming to be regenerated.
+    pub fn request(&self, hint: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(agent) = state.streams.last().cloned() else {
+            return;
+        };
+        state.pending.insert(agent, Regenerate { hint });
+        self.notify.notify_waiters();
+    }
+
+    // Note that the agent's response has started streaming, until the returned Stream is dropped.
+    pub fn stream(&self, agent: &str) -> Stream {
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(agent);
+        state.streams.push(agent.to_string());
+        Stream {
+            steering: self.clone(),
+            agent: agent.to_string(),
+        }
+    }
+
+    // Wait for the user to request that the agent's response be regenerated.
+    pub async fn requested(&self, agent: &str) -> Regenerate {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(regenerate) = self.state.lock().unwrap().pending.remove(agent) {
+                return regenerate;
+            }
+            notified.await;
+        }
+    }
+
+    #[cfg(test)]
+    pub fn is_streaming(&self, agent: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .streams
+            .iter()
+            .any(|a| a == agent)
+    }
+
+    // Read requests from stdin in a background thread: `r` regenerates the response and `r <hint>`
+    // regenerates it with the hint. Nothing is read unless stdin is a terminal.
+    pub fn read_stdin(&self) {
+        if !io::stdin().is_terminal() {
+            return;
+        }
+        let steering = self.clone();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if let Some(hint) = parse_command(&line) {
+                    steering.request(hint);
+                }
+            }
+        });
+    }
+}
+
+// Parse a line typed by the user. Return Some(hint) for a regenerate command.
+fn parse_command(line: &str) -> Option<Option<String>> {
+    let line = line.trim();
+    if line == "r" {
+        return Some(None);
+    }
+    let hint = line.strip_prefix("r ")?.trim();
+    Some((!hint.is_empty()).then(|| hint.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("r"), Some(None));
+        assert_eq!(
+            parse_command(" r use recursion "),
+            Some(Some("use recursion".to_string()))
+        );
+        assert_eq!(parse_command("run"), None);
+        assert_eq!(parse_command(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_request_during_stream_is_kept() {
+        let steering = Steering::default();
+        let _stream = steering.stream("Coder");
+        steering.request(Some("shorter".to_string()));
+        assert_eq!(
+            steering.requested("Coder").await,
+            Regenerate {
+                hint: Some("shorter".to_string())
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_before_stream_is_dropped() {
+        let steering = Steering::default();
+        steering.request(None);
+        let _stream = steering.stream("Coder");
+        assert!(steering.requested("Coder").now_or_never().is_none());
+
+        // Nor does a request that the last stream didn't pick up carry over to the next.
+        let stream = steering.stream("Fixer");
+        steering.request(None);
+        drop(stream);
+        let _stream = steering.stream("Fixer");
+        assert!(steering.requested("Fixer").now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_request_is_for_latest_stream() {
+        let steering = Steering::default();
+        let _first = steering.stream("Design Critic 1");
+        let second = steering.stream("Syntax Critic 1");
+        steering.request(None);
+        assert!(steering
+            .requested("Design Critic 1")
+            .now_or_never()
+            .is_none());
+        assert_eq!(
+            steering.requested("Syntax Critic 1").await,
+            Regenerate { hint: None }
+        );
+        drop(second);
+        steering.request(Some("cite lines".to_string()));
+        assert_eq!(
+            steering.requested("Design Critic 1").await.hint.as_deref(),
+            Some("cite lines")
+        );
+    }
+}
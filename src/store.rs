@@ -0,0 +1,320 @@
+use crate::errors::AiCriticError;
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+// A run's artifacts are always written to its directory under --output-dir, which the history
+// and the worker's API read. A store configured in the TOML config also keeps a copy of each one,
+// so that a batch on a machine that may disappear, such as a spot instance, doesn't lose its data:
+//
+//   [store]
+//   kind = "s3"
+//   bucket = "my-experiments"
+//   prefix = "ai_critics/runs"
+//   region = "us-east-1"
+//   # For S3-compatible services such as MinIO, which are addressed by path:
+//   endpoint = "http://localhost:9000"
+//
+// or, to copy them to another directory, such as a mounted volume:
+//
+//   [store]
+//   kind = "local"
+//   dir = "/mnt/shared/runs"
+//
+// Each artifact is saved as <run-id>/<file>, under the prefix for S3. Its checkpoints are saved as
+// they're written and its transcript at each proposal and when it finishes. S3 uploads go through
+// the object_store crate, with the credentials and settings of the AWS_* environment variables,
+// such as AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, and AWS_SESSION_TOKEN, or else the instance's
+// role. They're sent in the background, in order, and a run waits for them before it ends. The
+// artifacts that couldn't be saved are reported then, as a run would otherwise seem to be backed up
+// when it isn't.
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StoreConfig {
+    Local {
+        dir: PathBuf,
+    },
+    S3 {
+        bucket: String,
+        #[serde(default)]
+        prefix: String,
+        // Defaults to AWS_REGION, then us-east-1.
+        region: Option<String>,
+        endpoint: Option<String>,
+    },
+}
+
+#[async_trait]
+pub trait Store: Send + Sync + Debug {
+    // Save the contents under the key, such as <run-id>/result.json, replacing any earlier
+    // version. A store may save them in the background.
+    fn put(&self, key: &str, contents: &[u8]);
+
+    // Wait until everything put so far is saved, failing if anything of the run's put since its
+    // last flush couldn't be. The runs of a worker share a store, so each hears only of its own.
+    async fn flush(&self, run_id: &str) -> Result<()>;
+}
+
+pub fn create(config: &StoreConfig) -> Result<Arc<dyn Store>> {
+    Ok(match config {
+        StoreConfig::Local { dir } => Arc::new(LocalStore {
+            dir: dir.clone(),
+            failures: Failures::default(),
+        }),
+        StoreConfig::S3 {
+            bucket,
+            prefix,
+            region,
+            endpoint,
+        } => {
+            let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+            if let Some(region) = region {
+                builder = builder.with_region(region);
+            }
+            if let Some(endpoint) = endpoint {
+                builder = builder
+                    .with_endpoint(endpoint.trim_end_matches('/'))
+                    .with_allow_http(endpoint.starts_with("http://"));
+            }
+            let s3 = builder.build().map_err(|e| AiCriticError::MissingSetting {
+                setting: format!("the S3 store's settings ({})", e),
+            })?;
+            Arc::new(S3Store::start(
+                Arc::new(s3),
+                prefix.trim_matches('/').to_string(),
+                format!("s3://{}/{}", bucket, prefix.trim_matches('/')),
+            ))
+        }
+    })
+}
+
+// Wait for the store to save everything put so far, warning on stderr of anything of the run's
+// that it couldn't.
+pub async fn flush(store: &dyn Store, run_id: &str) {
+    if let Err(e) = store.flush(run_id).await {
+        eprintln!("Warning: {}", e);
+    }
+}
+
+// The saves that failed since their run's last flush, each with the ID of its run, shared with a
+// store's background task.
+#[derive(Debug, Default, Clone)]
+struct Failures(Arc<Mutex<Vec<(String, String)>>>);
+
+impl Failures {
+    // Record that the key, saved as the target, couldn't be.
+    fn add(&self, key: &str, target: &str, error: impl std::fmt::Display) {
+        let run_id = key.split('/').next().unwrap_or_default().to_string();
+        self.0
+            .lock()
+            .unwrap()
+            .push((run_id, format!("{} ({})", target, error)));
+    }
+
+    // Fail if any of the run's saves failed, and start the run over.
+    fn take(&self, run_id: &str) -> Result<()> {
+        let mut failures = self.0.lock().unwrap();
+        let (failed, others): (Vec<_>, Vec<_>) = mem::take(&mut *failures)
+            .into_iter()
+            .partition(|(id, _)| id == run_id);
+        *failures = others;
+        let failed: Vec<String> = failed.into_iter().map(|(_, failure)| failure).collect();
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(AiCriticError::StoreFailed { failed }.into()),
+        }
+    }
+}
+
+// Copies the artifacts to a directory.
+#[derive(Debug)]
+pub struct LocalStore {
+    dir: PathBuf,
+    failures: Failures,
+}
+
+#[async_trait]
+impl Store for LocalStore {
+    fn put(&self, key: &str, contents: &[u8]) {
+        let path = self.dir.join(key);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, contents));
+        if let Err(e) = written {
+            self.failures.add(key, &path.display().to_string(), e);
+        }
+    }
+
+    async fn flush(&self, run_id: &str) -> Result<()> {
+        self.failures.take(run_id)
+    }
+}
+
+enum Upload {
+    Put { key: String, contents: Vec<u8> },
+    // Answer once the uploads before it are done.
+    Flush(oneshot::Sender<()>),
+}
+
+// Uploads the artifacts to an S3 bucket, one at a time, in a background task.
+pub struct S3Store {
+    sender: mpsc::UnboundedSender<Upload>,
+    // Taken by the task that uploads, which the first put or flush starts, since the store may be
+    // created before the runtime is.
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<Upload>>>,
+    s3: Arc<dyn ObjectStore>,
+    prefix: String,
+    failures: Failures,
+    description: String,
+}
+
+impl Debug for S3Store {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "S3Store({})", self.description)
+    }
+}
+
+impl S3Store {
+    fn start(s3: Arc<dyn ObjectStore>, prefix: String, description: String) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        S3Store {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            s3,
+            prefix,
+            failures: Failures::default(),
+            description,
+        }
+    }
+
+    // Start the uploading task if it isn't running yet.
+    fn ensure_started(&self) {
+        if let Some(mut receiver) = self.receiver.lock().unwrap().take() {
+            let (s3, prefix, failures) =
+                (self.s3.clone(), self.prefix.clone(), self.failures.clone());
+            tokio::spawn(async move {
+                while let Some(upload) = receiver.recv().await {
+                    match upload {
+                        Upload::Put { key, contents } => {
+                            let path = object_path(&prefix, &key);
+                            if let Err(e) = s3.put(&path, contents.into()).await {
+                                failures.add(&key, &format!("s3 {}", path), e);
+                            }
+                        }
+                        Upload::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl Store for S3Store {
+    fn put(&self, key: &str, contents: &[u8]) {
+        self.ensure_started();
+        let _ = self.sender.send(Upload::Put {
+            key: key.to_string(),
+            contents: contents.to_vec(),
+        });
+    }
+
+    async fn flush(&self, run_id: &str) -> Result<()> {
+        self.ensure_started();
+        let (done, flushed) = oneshot::channel();
+        if self.sender.send(Upload::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+        self.failures.take(run_id)
+    }
+}
+
+// The object's path in the bucket, under the prefix if there is one.
+fn object_path(prefix: &str, key: &str) -> ObjectPath {
+    match prefix.is_empty() {
+        true => ObjectPath::from(key),
+        false => ObjectPath::from(format!("{}/{}", prefix, key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let s3: StoreConfig = toml::from_str(
+            r#"
+            kind = "s3"
+            bucket = "runs"
+            endpoint = "http://localhost:9000"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            s3,
+            StoreConfig::S3 {
+                bucket: "runs".to_string(),
+                prefix: String::new(),
+                region: None,
+                endpoint: Some("http://localhost:9000".to_string()),
+            }
+        );
+        assert!(toml::from_str::<StoreConfig>("kind = \"local\"\ndir = \"/tmp\"").is_ok());
+        assert!(toml::from_str::<StoreConfig>("kind = \"local\"\nbucket = \"runs\"").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_s3_store() {
+        let s3 = Arc::new(object_store::memory::InMemory::new());
+        let store = S3Store::start(s3.clone(), "ai critics".to_string(), String::new());
+        store.put("run-1/result.json", b"{}");
+        store.flush("run-1").await.unwrap();
+        let path = ObjectPath::from("ai critics/run-1/result.json");
+        let saved = s3.get(&path).await.unwrap().bytes().await.unwrap();
+        assert_eq!(&saved[..], b"{}");
+        assert_eq!(
+            object_path("", "run-1/result.json"),
+            ObjectPath::from("run-1/result.json")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = create(&StoreConfig::Local {
+            dir: dir.path().join("copies"),
+        })
+        .unwrap();
+        store.put("run-1/result.json", b"{}");
+        store.flush("run-1").await.unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("copies/run-1/result.json")).unwrap(),
+            "{}"
+        );
+
+        // A file stands where the run's directory would be, so the failure is reported once, and
+        // only to its run.
+        fs::write(dir.path().join("copies/run-2"), "").unwrap();
+        store.put("run-2/result.json", b"{}");
+        store.put("run-1/result.json", b"{}");
+        store.flush("run-1").await.unwrap();
+        let error = store.flush("run-2").await.unwrap_err().to_string();
+        assert!(error.starts_with("the store couldn't save 1 artifact(s):"));
+        assert!(error.contains("run-2/result.json"));
+        store.flush("run-2").await.unwrap();
+    }
+}
@@ -1,18 +1,101 @@
+use crate::assert_ids;
+use crate::build_service::{remove_stacktrace, Build, BuildService, Sandbox, TestRun};
+use crate::complexity::{self, Constraint, Measurement};
+use crate::coverage::{self, CoverageReport};
 use crate::errors::AiCriticError;
 use crate::fixer::{ReviewNeeded, ReviewType};
+use crate::prescreen::Policy;
+use crate::processes;
+use crate::sample_io::{self, Sample};
+use async_trait::async_trait;
 use color_eyre::eyre::Result;
-use std::io::Write;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
 use tempfile::TempDir;
+use tokio::process::Command;
 
 const TESTER_AGENT_NAME: &str = "Tester";
 // Compiler errors can be long. Truncate them to this length to reduce the token lengths given to
 // GPT-4.
 const MAX_COMPILER_OUTPUT: usize = 500;
+// A program given a sample input is killed if it runs longer than this, e.g. waiting for more
+// input or stuck in a loop.
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(10);
+// The file of a kept build with the output that the tester judged it by.
+const KEPT_OUTPUT_FILENAME: &str = "output.txt";
 
 pub struct TesterAgent {
     _name: String,
+    // Compiles the proposals and runs their tests.
+    build: BuildService,
+    // The sample runs that the problem gives for its program, which is checked against them once
+    // it passes its tests.
+    samples: Vec<Sample>,
+    // The APIs that the code may use. Code that uses others isn't run.
+    policy: Policy,
+    // How many times the tests are run, so that tests whose results differ between runs are caught
+    // as flaky.
+    test_runs: usize,
+    // Where the builds are copied to, if they're kept.
+    kept_builds: Option<KeptBuilds>,
+    // How the problem requires the code's running time to grow, if it says.
+    complexity: Option<Constraint>,
+}
+
+// The builds are compiled in a temporary directory that is deleted, or reused by the next build.
+// Kept builds are copied out of it after testing into numbered directories, each with the code, the
+// executables, the compiler's output, and the tester's output, so that a build that the tester
+// failed can be examined after the run, as when it passes on another machine. A project tested
+// with `cargo test` keeps only the file under test and the tester's output.
+struct KeptBuilds {
+    dir: PathBuf,
+    count: AtomicUsize,
+}
+
+impl KeptBuilds {
+    // Copy the files into the next build's directory, with the output of the result.
+    fn keep(&self, files: &[PathBuf], result: &Result<TesterResult>) -> io::Result<PathBuf> {
+        let number = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let dir = self.dir.join(format!("build_{}", number));
+        fs::create_dir_all(&dir)?;
+        for path in files {
+            if let Some(name) = path.file_name() {
+                fs::copy(path, dir.join(name))?;
+            }
+        }
+        let output = match result {
+            Ok(TesterResult::Success { stdout, .. }) => stdout.clone(),
+            Ok(TesterResult::Failure { failure, .. }) => failure.output(),
+            Err(e) => e.to_string(),
+        };
+        fs::write(dir.join(KEPT_OUTPUT_FILENAME), output)?;
+        Ok(dir)
+    }
+}
+
+// The files of the build directory, leaving out its incremental compilation state.
+fn files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+// Remove the files that an earlier build left in the build directory, so that a kept build has only
+// its own.
+fn remove_files(dir: &Path) -> io::Result<()> {
+    files(dir)?.into_iter().try_for_each(fs::remove_file)
 }
 
 pub enum TesterResult {
@@ -21,68 +104,672 @@ pub enum TesterResult {
         exec_path: PathBuf,
     },
     Failure {
-        output: String,
+        failure: Failure,
         review: ReviewNeeded,
     },
 }
 
+// How the code failed, with what the tester knows of it. The kind of failure chooses the fixer's
+// prompt and is recorded in the run's result.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Failure {
+    CompileError {
+        stderr: String,
+    },
+    // The named tests failed their assertions, or the program printed the wrong output for a
+    // sample.
+    TestAssertionFailure {
+        tests: Vec<String>,
+        output: String,
+    },
+    // The named tests panicked other than in an assertion, as on an unwrap() of None.
+    TestPanic {
+        tests: Vec<String>,
+        output: String,
+    },
+    // The program was killed after running for the limit.
+    Timeout {
+        limit: Duration,
+        output: String,
+    },
+    // The test harness or program was killed by the signal, as on a stack overflow.
+    Crash {
+        signal: i32,
+        output: String,
+    },
+    // The test harness or program exited with a code that doesn't report failed tests.
+    NonZeroExit {
+        code: i32,
+        output: String,
+    },
+    // The test harness or program printed more bytes than the limit and was killed.
+    OutputExceeded {
+        limit: usize,
+        output: String,
+    },
+    // The code passed its tests, but its running time grows faster than the problem allows, by the
+    // fitted exponent if it was measured.
+    ComplexityViolation {
+        exponent: Option<f64>,
+        output: String,
+    },
+    // The tests passed in only some of the runs, or failed different tests in different runs.
+    FlakyTests {
+        passed: usize,
+        runs: usize,
+        output: String,
+    },
+    // The code uses APIs outside the policy, so it wasn't compiled.
+    PolicyViolation {
+        comments: Vec<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    CompileError,
+    TestAssertionFailure,
+    TestPanic,
+    Timeout,
+    Crash,
+    NonZeroExit,
+    OutputExceeded,
+    ComplexityViolation,
+    FlakyTests,
+    PolicyViolation,
+}
+
+impl Failure {
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            Failure::CompileError { .. } => FailureKind::CompileError,
+            Failure::TestAssertionFailure { .. } => FailureKind::TestAssertionFailure,
+            Failure::TestPanic { .. } => FailureKind::TestPanic,
+            Failure::Timeout { .. } => FailureKind::Timeout,
+            Failure::Crash { .. } => FailureKind::Crash,
+            Failure::NonZeroExit { .. } => FailureKind::NonZeroExit,
+            Failure::OutputExceeded { .. } => FailureKind::OutputExceeded,
+            Failure::ComplexityViolation { .. } => FailureKind::ComplexityViolation,
+            Failure::FlakyTests { .. } => FailureKind::FlakyTests,
+            Failure::PolicyViolation { .. } => FailureKind::PolicyViolation,
+        }
+    }
+
+    // The output of the failure as it's shown, with the compiler's truncated.
+    pub fn output(&self) -> String {
+        match self {
+            Failure::CompileError { stderr } => stderr.chars().take(MAX_COMPILER_OUTPUT).collect(),
+            Failure::PolicyViolation { comments } => comments.join("\n"),
+            _ => self.details(),
+        }
+    }
+
+    // The whole output of the failure, which the fixer is given.
+    fn details(&self) -> String {
+        match self {
+            Failure::CompileError { stderr } => stderr.clone(),
+            Failure::TestAssertionFailure { output, .. }
+            | Failure::TestPanic { output, .. }
+            | Failure::Timeout { output, .. }
+            | Failure::Crash { output, .. }
+            | Failure::NonZeroExit { output, .. }
+            | Failure::OutputExceeded { output, .. }
+            | Failure::ComplexityViolation { output, .. }
+            | Failure::FlakyTests { output, .. } => output.clone(),
+            Failure::PolicyViolation { comments } => comments.join("\n"),
+        }
+    }
+
+    fn review_type(&self) -> ReviewType {
+        match self.kind() {
+            FailureKind::CompileError => ReviewType::CompilerFix,
+            FailureKind::TestAssertionFailure => ReviewType::TestFix,
+            FailureKind::TestPanic
+            | FailureKind::Timeout
+            | FailureKind::Crash
+            | FailureKind::NonZeroExit
+            | FailureKind::OutputExceeded => ReviewType::RuntimeFix,
+            FailureKind::ComplexityViolation => ReviewType::ComplexityFix,
+            FailureKind::FlakyTests => ReviewType::FlakyTestFix,
+            FailureKind::PolicyViolation => ReviewType::PolicyFix,
+        }
+    }
+}
+
+// What the run loop needs of a tester, so that it can be given a scripted one.
+#[async_trait]
+pub trait Tester: Send + Sync {
+    async fn compile_and_test(&self, code: &str) -> Result<TesterResult>;
+}
+
+#[async_trait]
+impl Tester for TesterAgent {
+    async fn compile_and_test(&self, code: &str) -> Result<TesterResult> {
+        TesterAgent::compile_and_test(self, code).await
+    }
+}
+
+// The result of code that failed, with a review that asks the fixer to fix it.
+fn fail(failure: Failure) -> TesterResult {
+    let review = ReviewNeeded::new(failure.review_type(), vec![failure.details()]);
+    TesterResult::Failure { failure, review }
+}
+
+// The result of running the same tests several times. They failed if they failed the same tests in
+// every run, and are flaky if they passed in some runs but not others, or failed different tests. A
+// run that crashed or exited abnormally is reported whatever the others did.
+fn judge_runs(runs: Vec<TestRun>) -> TesterResult {
+    let total = runs.len();
+    let mut passed = None;
+    // The failed runs, by their number counting from 1.
+    let mut failures: Vec<(usize, String)> = vec![];
+    for (i, run) in runs.into_iter().enumerate() {
+        match run {
+            TestRun::Passed { stdout } => {
+                passed.get_or_insert(stdout);
+            }
+            TestRun::Failed { output } => failures.push((i + 1, output)),
+            TestRun::Crashed { signal, output } => return fail(Failure::Crash { signal, output }),
+            TestRun::Exited { code, output } => return fail(Failure::NonZeroExit { code, output }),
+            TestRun::OutputExceeded { limit, output } => {
+                return fail(Failure::OutputExceeded { limit, output })
+            }
+            TestRun::TimedOut { limit } => return fail(timed_out(limit)),
+        }
+    }
+    let failed_sets: BTreeSet<Vec<&str>> = failures
+        .iter()
+        .map(|(_, output)| failed_tests(output))
+        .collect();
+    match (passed, failures.len()) {
+        (Some(stdout), 0) => TesterResult::Success {
+            stdout,
+            exec_path: "".into(),
+        },
+        (None, _) if failed_sets.len() == 1 => test_failure(failures.swap_remove(0).1),
+        (_, failed_runs) => {
+            let mut output = format!(
+                "The tests passed in {} of {} runs and failed in {}.",
+                total - failed_runs,
+                total,
+                failed_runs
+            );
+            for (run, run_output) in failures.iter() {
+                output.push_str(&format!(
+                    "\nRun {} failed: {}",
+                    run,
+                    failed_tests(run_output).join(", ")
+                ));
+            }
+            output.push_str(&format!("\nOutput of a failed run:\n{}", failures[0].1));
+            fail(Failure::FlakyTests {
+                passed: total - failed_runs,
+                runs: total,
+                output,
+            })
+        }
+    }
+}
+
+fn timed_out(limit: Duration) -> Failure {
+    Failure::Timeout {
+        limit,
+        output: format!("The tests ran longer than {:?} and were killed.", limit),
+    }
+}
+
+// The names of the tests that the test harness's output says failed, in order by name, so that
+// runs in which the same tests fail in another order are seen to agree.
+pub fn failed_tests(output: &str) -> Vec<&str> {
+    let tests: BTreeSet<&str> = output
+        .lines()
+        .filter_map(|line| line.strip_prefix("test ")?.strip_suffix(" ... FAILED"))
+        .collect();
+    tests.into_iter().collect()
+}
+
+// Cargo's report of a test harness that didn't exit successfully, with the signal that killed it or
+// its exit status.
+fn harness_exit() -> &'static Regex {
+    static HARNESS_EXIT: OnceLock<Regex> = OnceLock::new();
+    HARNESS_EXIT.get_or_init(|| {
+        Regex::new(r"process didn't exit successfully: .*\((?:signal: (\d+)|exit status: (\d+))")
+            .unwrap()
+    })
+}
+
+// The result of tests that the test harness reported as failed. They panicked rather than failing
+// an assertion if the output has a panic but no assertion, as the asserts' messages carry their
+// assert_ids.
+pub fn test_failure(output: String) -> TesterResult {
+    let tests = failed_tests(&output)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let panicked = output.contains("panicked at")
+        && !output.contains("assertion")
+        && assert_ids::failed_ids(&output).is_empty();
+    fail(match panicked {
+        true => Failure::TestPanic { tests, output },
+        false => Failure::TestAssertionFailure { tests, output },
+    })
+}
+
 impl TesterAgent {
     pub fn new(id: usize) -> Self {
         TesterAgent {
             _name: format!("{}_{}", TESTER_AGENT_NAME, id),
+            build: BuildService::default(),
+            samples: vec![],
+            policy: Policy::default(),
+            test_runs: 1,
+            kept_builds: None,
+            complexity: None,
+        }
+    }
+
+    // Keep a build directory across the compiles of compile_and_test(), as for the proposals of a
+    // run.
+    pub fn with_build_cache(self) -> Result<Self> {
+        Ok(TesterAgent {
+            build: self.build.with_build_cache()?,
+            ..self
+        })
+    }
+
+    pub fn with_samples(self, samples: Vec<Sample>) -> Self {
+        TesterAgent { samples, ..self }
+    }
+
+    pub fn with_sandbox(self, sandbox: Sandbox) -> Self {
+        TesterAgent {
+            build: self.build.with_sandbox(sandbox),
+            ..self
+        }
+    }
+
+    pub fn with_complexity(self, complexity: Option<Constraint>) -> Self {
+        TesterAgent { complexity, ..self }
+    }
+
+    pub fn with_policy(self, policy: Policy) -> Self {
+        TesterAgent { policy, ..self }
+    }
+
+    pub fn with_test_seed(self, test_seed: Option<u64>) -> Self {
+        TesterAgent {
+            build: self.build.with_test_seed(test_seed),
+            ..self
+        }
+    }
+
+    pub fn with_max_output(self, max_output: usize) -> Self {
+        TesterAgent {
+            build: self.build.with_max_output(max_output),
+            ..self
+        }
+    }
+
+    pub fn with_test_timeout(self, test_timeout: Duration) -> Self {
+        TesterAgent {
+            build: self.build.with_test_timeout(test_timeout),
+            ..self
+        }
+    }
+
+    pub fn with_test_runs(self, test_runs: usize) -> Self {
+        TesterAgent {
+            test_runs: test_runs.max(1),
+            ..self
+        }
+    }
+
+    // Copy each build into its own directory under the given one after testing it.
+    pub fn with_kept_builds(self, dir: &Path) -> Self {
+        TesterAgent {
+            build: self.build.with_kept_output(),
+            kept_builds: Some(KeptBuilds {
+                dir: dir.to_path_buf(),
+                count: AtomicUsize::new(0),
+            }),
+            ..self
         }
     }
 
-    // Compile the given code and return the path to the executable. If compilation fails, return a
-    // TesterResult with a comment that tells the critics what to review.
+    // Check that the sandbox's tools are installed before the run.
+    pub async fn check_sandbox(&self) -> Result<()> {
+        self.build.check_sandbox().await
+    }
+
+    // Compile the given code's tests and return the path to the executable. If compilation fails,
+    // return a TesterResult with a comment that tells the critics what to review.
     pub async fn compile(&self, temp_dir_path: &Path, code: &str) -> Result<TesterResult> {
-        let rs_file_path = temp_dir_path.join("code.rs");
-        let exec_path = temp_dir_path.join("test");
-
-        let mut file = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&rs_file_path)?;
-        write!(file, "{}", code)?;
-
-        // Below, the unwrap()s guard against invalid UTF-8, but tempfile::Builder::new() generates
-        // valid UTF-8.
-        let output = Command::new("rustc")
-            .arg("--test")
-            .arg("-o")
-            .arg(exec_path.to_str().unwrap())
-            .arg(rs_file_path.to_str().unwrap())
-            .output()?;
+        Ok(match self.build.build_test(temp_dir_path, code).await? {
+            Build::Built { exec_path, stdout } => TesterResult::Success { stdout, exec_path },
+            Build::Failed { stderr } => fail(Failure::CompileError { stderr }),
+        })
+    }
 
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    // Run the given test executable, as many times as the tester's test runs. If the test fails,
+    // return a TesterResult with a comment that tells the critics what to review.
+    pub async fn test(&self, exec_path: PathBuf) -> Result<TesterResult> {
+        let mut runs = vec![];
+        for _ in 0..self.test_runs {
+            runs.push(self.build.run_tests(&exec_path).await?);
+        }
+        Ok(judge_runs(runs))
+    }
 
-        match output.status.code() {
-            Some(0) => Ok(TesterResult::Success { stdout, exec_path }),
-            Some(_) => Ok(TesterResult::Failure {
-                output: stderr.chars().take(MAX_COMPILER_OUTPUT).collect(),
-                review: ReviewNeeded {
-                    review_type: ReviewType::CompilerFix,
-                    comments: vec![stderr],
+    // Compile the code as a program and run it on each of the problem's sample inputs, failing if
+    // its output differs from the expected output.
+    async fn run_samples(&self, temp_dir_path: &Path, code: &str) -> Result<TesterResult> {
+        let exec_path = match self.build.build_program(temp_dir_path, code).await? {
+            Build::Built { exec_path, .. } => exec_path,
+            Build::Failed { stderr } => return Ok(fail(Failure::CompileError { stderr })),
+        };
+        for (i, sample) in self.samples.iter().enumerate() {
+            let mut command = self.build.command(&exec_path);
+            let limit = self.build.max_output();
+            let run = processes::output_limited(&mut command, Some(sample.input.as_bytes()), limit);
+            let tests = vec![format!("sample {}", i + 1)];
+            let report = |problem: String, actual: &str| {
+                format!(
+                    "On sample input {}, the program {}.\nInput:\n{}Expected output:\n{}Actual \
+                     output:\n{}",
+                    i + 1,
+                    problem,
+                    sample.input,
+                    sample.output,
+                    actual
+                )
+            };
+            let failure = match tokio::time::timeout(SAMPLE_TIMEOUT, run).await {
+                Err(_) => Failure::Timeout {
+                    limit: SAMPLE_TIMEOUT,
+                    output: report(format!("timed out after {:?}", SAMPLE_TIMEOUT), ""),
                 },
-            }),
-            None => Err(AiCriticError::ProcessTerminated.into()),
+                Ok(limited) => {
+                    let limited = limited?;
+                    let output = limited.output;
+                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let stderr = remove_stacktrace(&stderr).trim();
+                    match (
+                        output.status.code(),
+                        processes::crash_signal(&output.status),
+                    ) {
+                        _ if limited.exceeded => Failure::OutputExceeded {
+                            limit,
+                            output: report(format!("printed more than {} bytes", limit), &stdout),
+                        },
+                        (Some(0), _) if sample_io::matches(&sample.output, &stdout) => continue,
+                        (Some(0), _) => Failure::TestAssertionFailure {
+                            tests,
+                            output: report("printed the wrong output".to_string(), &stdout),
+                        },
+                        // A Rust program that panics exits with 101.
+                        (Some(101), _) => Failure::TestPanic {
+                            tests,
+                            output: report(format!("exited with code 101: {}", stderr), &stdout),
+                        },
+                        (Some(code), _) => Failure::NonZeroExit {
+                            code,
+                            output: report(
+                                format!("exited with code {}: {}", code, stderr),
+                                &stdout,
+                            ),
+                        },
+                        (None, Some(signal)) => Failure::Crash {
+                            signal,
+                            output: report(
+                                format!("was killed by signal {}: {}", signal, stderr),
+                                &stdout,
+                            ),
+                        },
+                        (None, None) => return Err(AiCriticError::ProcessTerminated.into()),
+                    }
+                }
+            };
+            return Ok(fail(failure));
         }
+        Ok(TesterResult::Success {
+            stdout: "The program produced the expected output on the samples.\n".to_string(),
+            exec_path,
+        })
     }
 
-    // The project uses stack traces, but we don't need to send them to GPT-4.
-    fn remove_stacktrace(input: &str) -> &str {
-        match input.find("stack backtrace:") {
-            Some(index) => &input[0..index],
-            None => input,
+    // Compile the code then run the test executable, returning the stdout and stderr of the
+    // outputs. If the problem gives sample runs, the program must then pass them too. Code that
+    // uses APIs outside the policy is returned for fixing without being compiled.
+    pub async fn compile_and_test(&self, code: &str) -> Result<TesterResult> {
+        if let Some(review) = self.policy.review(code) {
+            return Ok(TesterResult::Failure {
+                failure: Failure::PolicyViolation {
+                    comments: review.comments.clone(),
+                },
+                review,
+            });
         }
+        // Compile the given code in the build directory, or else in a temporary directory, which
+        // will be deleted along with its contents when the returned future is dropped.
+        let temp_dir;
+        let temp_dir_path = match self.build.build_dir() {
+            Some(build_dir) => build_dir,
+            None => {
+                temp_dir = TempDir::new()?;
+                temp_dir.path()
+            }
+        };
+        let Some(kept_builds) = &self.kept_builds else {
+            return self.build_and_test(temp_dir_path, code).await;
+        };
+        remove_files(temp_dir_path)?;
+        let result = self.build_and_test(temp_dir_path, code).await;
+        let kept = files(temp_dir_path).and_then(|files| kept_builds.keep(&files, &result));
+        if let Err(e) = kept {
+            log::warn!(
+                "Couldn't keep the build of {}: {}",
+                temp_dir_path.display(),
+                e
+            );
+        }
+        result
     }
 
-    // Run the given test executable and return the exit code. If the test fails, return a
-    // TesterResult with a comment that tells the critics what to review.
-    pub async fn test(&self, exec_path: PathBuf) -> Result<TesterResult> {
-        let output = Command::new(exec_path).output()?;
-        let stdout = String::from_utf8_lossy(&output.stdout.to_owned()).to_string();
+    // Compile the code in the directory, then run its tests and its samples.
+    async fn build_and_test(&self, temp_dir_path: &Path, code: &str) -> Result<TesterResult> {
+        let compilation_outcome = self.compile(temp_dir_path, code).await?;
+        let exec_path = match compilation_outcome {
+            TesterResult::Success { exec_path, .. } => exec_path,
+            TesterResult::Failure { .. } => return Ok(compilation_outcome),
+        };
+        let result = match (self.test(exec_path).await?, self.samples.is_empty()) {
+            (TesterResult::Success { stdout, .. }, false) => {
+                match self.run_samples(temp_dir_path, code).await? {
+                    TesterResult::Success {
+                        stdout: samples_stdout,
+                        exec_path,
+                    } => TesterResult::Success {
+                        stdout: format!("{}{}", stdout, samples_stdout),
+                        exec_path,
+                    },
+                    failure => failure,
+                }
+            }
+            (result, _) => result,
+        };
+        match (result, &self.complexity) {
+            (TesterResult::Success { stdout, exec_path }, Some(constraint)) => {
+                let complexity = self
+                    .check_complexity(temp_dir_path, code, constraint)
+                    .await?;
+                Ok(match complexity {
+                    Ok(report) => TesterResult::Success {
+                        stdout: format!("{}{}", stdout, report),
+                        exec_path,
+                    },
+                    Err(failure) => fail(failure),
+                })
+            }
+            (result, _) => Ok(result),
+        }
+    }
+
+    // Time the code's bench() on growing sizes, and check that its running time grows within the
+    // constraint. Return a report of the timing, or else the failure. Code whose timings can't be
+    // taken, or are too few to fit, fails.
+    async fn check_complexity(
+        &self,
+        temp_dir_path: &Path,
+        code: &str,
+        constraint: &Constraint,
+    ) -> Result<std::result::Result<String, Failure>> {
+        let failure = |exponent, output| Failure::ComplexityViolation { exponent, output };
+        Ok(
+            match complexity::measure(&self.build, temp_dir_path, code).await? {
+                Measurement::Unbuilt { stderr } => Err(failure(
+                    None,
+                    format!(
+                    "The problem requires {}. To measure the code's complexity, it must define \
+                     `fn {}(n: usize)`, which runs the solution on a generated input of size n. \
+                     With the timing harness, it failed to compile:\n{}",
+                    constraint,
+                    complexity::BENCH_FN,
+                    stderr.chars().take(MAX_COMPILER_OUTPUT).collect::<String>()
+                ),
+                )),
+                Measurement::TimedOut { limit } => Err(failure(
+                    None,
+                    format!(
+                    "The problem requires {}, but timing `{}()` on growing sizes took longer than \
+                     {:?}.",
+                    constraint,
+                    complexity::BENCH_FN,
+                    limit
+                ),
+                )),
+                Measurement::Crashed { reason, stderr } => Err(failure(
+                    None,
+                    format!(
+                        "The problem requires {}, but timing `{}()` on growing sizes {}:\n{}",
+                        constraint,
+                        complexity::BENCH_FN,
+                        reason,
+                        remove_stacktrace(&stderr)
+                            .trim()
+                            .chars()
+                            .take(MAX_COMPILER_OUTPUT)
+                            .collect::<String>()
+                    ),
+                )),
+                Measurement::Timed {
+                    exponent: None,
+                    timings,
+                } => Err(failure(
+                    None,
+                    format!(
+                        "The problem requires {}, but `{}()` was too slow to time on more than {} \
+                         sizes, starting from n = 64, where each size needs a call under 100 ms.",
+                        constraint,
+                        complexity::BENCH_FN,
+                        timings.len()
+                    ),
+                )),
+                Measurement::Timed {
+                    exponent: Some(exponent),
+                    ..
+                } if constraint.allows(exponent) => Ok(format!(
+                    "The code's running time grows as n^{:.1}, within {}.\n",
+                    exponent, constraint
+                )),
+                Measurement::Timed {
+                    exponent: Some(exponent),
+                    timings,
+                } => {
+                    let lines: Vec<String> = timings
+                        .iter()
+                        .map(|(n, secs)| format!("n = {}: {:.6} s", n, secs))
+                        .collect();
+                    Err(failure(
+                        Some(exponent),
+                        format!(
+                        "The problem requires {}, but the code's running time grows as n^{:.1}. \
+                         The time per call of `{}(n)`:\n{}",
+                        constraint,
+                        exponent,
+                        complexity::BENCH_FN,
+                        lines.join("\n")
+                    ),
+                    ))
+                }
+            },
+        )
+    }
+
+    // Run `cargo test` in an existing project whose file under test is the given one, keeping the
+    // file with the tester's output if the builds are kept.
+    pub async fn cargo_test(&self, project_dir: &Path, file: &Path) -> Result<TesterResult> {
+        let result = self.run_cargo_test(project_dir).await;
+        if let Some(kept_builds) = &self.kept_builds {
+            if let Err(e) = kept_builds.keep(&[file.to_path_buf()], &result) {
+                log::warn!("Couldn't keep the build of {}: {}", file.display(), e);
+            }
+        }
+        result
+    }
+
+    // Run `cargo test` in the project. The project is compiled first, so that the tests'
+    // run, and not the compile, is held to the test timeout and output limit. Cargo exits with 101
+    // whenever a test harness fails, so a harness that crashed or exited with another code than a
+    // test failure's is told apart by cargo's report of its signal or exit status.
+    async fn run_cargo_test(&self, project_dir: &Path) -> Result<TesterResult> {
+        let cargo_test = |args: &[&str]| {
+            let mut command = Command::new("cargo");
+            command
+                .arg("test")
+                .arg("--quiet")
+                .args(args)
+                .envs(self.build.test_env())
+                .current_dir(project_dir);
+            command
+        };
+        let compiled = processes::output(&mut cargo_test(&["--no-run"])).await?;
+        match compiled.status.code() {
+            Some(0) => {}
+            Some(_) => {
+                let stderr = String::from_utf8_lossy(&compiled.stderr).to_string();
+                return Ok(fail(Failure::CompileError { stderr }));
+            }
+            None => return Err(AiCriticError::ProcessTerminated.into()),
+        }
+
+        let limit = self.build.max_output();
+        let timeout = self.build.test_timeout();
+        let mut command = cargo_test(&[]);
+        let run = processes::output_limited(&mut command, None, limit);
+        let limited = match tokio::time::timeout(timeout, run).await {
+            Ok(limited) => limited?,
+            Err(_) => return Ok(fail(timed_out(timeout))),
+        };
+        let output = limited.output;
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let report = || {
+            format!(
+                "{}{}",
+                remove_stacktrace(&stdout),
+                remove_stacktrace(&stderr)
+            )
+        };
+
+        if limited.exceeded {
+            return Ok(fail(Failure::OutputExceeded {
+                limit,
+                output: format!("{}{}", stdout, stderr),
+            }));
+        }
 
         match output.status.code() {
             Some(0) => Ok(TesterResult::Success {
@@ -90,37 +777,364 @@ impl TesterAgent {
                 exec_path: "".into(),
             }),
             Some(101) => {
-                let output = Self::remove_stacktrace(&stdout).to_string();
-                Ok(TesterResult::Failure {
-                    output: output.clone(),
-                    review: ReviewNeeded {
-                        review_type: ReviewType::TestFix,
-                        comments: vec![output],
-                    },
+                let failure = harness_exit().captures(&stderr).and_then(|captures| {
+                    let number = |i| captures.get(i)?.as_str().parse().ok();
+                    match (number(1), number(2)) {
+                        (Some(signal), _) => Some(Failure::Crash {
+                            signal,
+                            output: report(),
+                        }),
+                        (_, Some(code)) if code != 101 => Some(Failure::NonZeroExit {
+                            code,
+                            output: report(),
+                        }),
+                        _ => None,
+                    }
+                });
+                Ok(match failure {
+                    Some(failure) => fail(failure),
+                    None => test_failure(remove_stacktrace(&stdout).to_string()),
                 })
             }
-            Some(code) => {
-                println!("Test exited with unexpected code {}", code);
-                println!("Stdout: {}", stdout);
-                println!("Stderr: {}", String::from_utf8_lossy(&output.stderr));
-                Err(AiCriticError::TestingFailed { exit_code: code }.into())
-            }
-            None => Err(AiCriticError::ProcessTerminated.into()),
+            Some(code) => Ok(fail(Failure::NonZeroExit {
+                code,
+                output: report(),
+            })),
+            None => match processes::crash_signal(&output.status) {
+                Some(signal) => Ok(fail(Failure::Crash {
+                    signal,
+                    output: report(),
+                })),
+                None => Err(AiCriticError::ProcessTerminated.into()),
+            },
         }
     }
 
-    // Compile the code then run the test executable, returning the stdout and stderr of the
-    // outputs.
-    pub async fn compile_and_test(&self, code: &str) -> Result<TesterResult> {
-        // Create a temporary directory and compile the given code. The directory and its contents
-        // will be deleted when the returned future is dropped.
+    // Compile the code with coverage instrumentation, run its tests, and report how much of the code
+    // the tests execute. The code is expected to pass its tests already. Its tests are held to the
+    // test timeout and output limit, as they are when they're tested.
+    pub async fn coverage(&self, code: &str) -> Result<CoverageReport> {
         let temp_dir = TempDir::new()?;
         let temp_dir_path = temp_dir.path();
-        let compilation_outcome = self.compile(temp_dir_path, code).await?;
-        let exec_path = match compilation_outcome {
-            TesterResult::Success { exec_path, .. } => exec_path,
-            TesterResult::Failure { .. } => return Ok(compilation_outcome),
+        let exec_path = match self
+            .build
+            .build(
+                temp_dir_path,
+                code,
+                "test",
+                &["--test", "-C", "instrument-coverage"],
+            )
+            .await?
+        {
+            Build::Built { exec_path, .. } => exec_path,
+            Build::Failed { stderr } => {
+                return Err(AiCriticError::CoverageFailed {
+                    reason: stderr.chars().take(MAX_COMPILER_OUTPUT).collect(),
+                }
+                .into())
+            }
         };
-        self.test(exec_path).await
+        let mut command = self.build.command(&exec_path);
+        command.env(
+            "LLVM_PROFILE_FILE",
+            temp_dir_path.join("coverage-%p.profraw"),
+        );
+        let (limit, timeout) = (self.build.max_output(), self.build.test_timeout());
+        let run = processes::output_limited(&mut command, None, limit);
+        let failed = |reason: String| Err(AiCriticError::CoverageFailed { reason }.into());
+        let limited = match tokio::time::timeout(timeout, run).await {
+            Ok(limited) => limited?,
+            Err(_) => {
+                return failed(format!(
+                    "the instrumented tests ran longer than {:?}",
+                    timeout
+                ))
+            }
+        };
+        if limited.exceeded {
+            return failed(format!(
+                "the instrumented tests printed more than {} bytes",
+                limit
+            ));
+        }
+        if !limited.output.status.success() {
+            return failed("the instrumented tests failed".to_string());
+        }
+        coverage::report(temp_dir_path, &exec_path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(tests: &[&str]) -> TestRun {
+        let lines: Vec<String> = tests
+            .iter()
+            .map(|test| format!("test {} ... FAILED", test))
+            .collect();
+        TestRun::Failed {
+            output: lines.join("\n"),
+        }
+    }
+
+    fn passed() -> TestRun {
+        TestRun::Passed {
+            stdout: "test result: ok.".to_string(),
+        }
+    }
+
+    fn review_type(result: TesterResult) -> Option<ReviewType> {
+        match result {
+            TesterResult::Success { .. } => None,
+            TesterResult::Failure { review, .. } => Some(review.review_type),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_kept_builds() {
+        let dir = tempfile::tempdir().unwrap();
+        let tester = TesterAgent::new(1)
+            .with_build_cache()
+            .unwrap()
+            .with_kept_builds(dir.path());
+        let passing = "#[test]\nfn passes() {}\n";
+        assert!(matches!(
+            tester.compile_and_test(passing).await.unwrap(),
+            TesterResult::Success { .. }
+        ));
+        tester.compile_and_test("fn broken() {").await.unwrap();
+
+        let first = dir.path().join("build_1");
+        assert_eq!(fs::read_to_string(first.join("code.rs")).unwrap(), passing);
+        assert!(first.join("test").is_file() && first.join("test.stderr").is_file());
+        assert!(fs::read_to_string(first.join(KEPT_OUTPUT_FILENAME))
+            .unwrap()
+            .contains("test passes ... ok"));
+        // The second build failed to compile, so it has no executable from the first.
+        let second = dir.path().join("build_2");
+        assert!(!second.join("test").exists());
+        assert!(fs::read_to_string(second.join("test.stderr"))
+            .unwrap()
+            .contains("error"));
+
+        // A tester that doesn't keep its builds leaves no compiler output beside the code.
+        let tester = TesterAgent::new(1).with_build_cache().unwrap();
+        tester.compile_and_test(passing).await.unwrap();
+        let build_dir = tester.build.build_dir().unwrap();
+        assert!(build_dir.join("code.rs").is_file() && !build_dir.join("test.stderr").exists());
+    }
+
+    fn failure_kind(result: TesterResult) -> Option<FailureKind> {
+        match result {
+            TesterResult::Success { .. } => None,
+            TesterResult::Failure { failure, .. } => Some(failure.kind()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failure_kinds() {
+        let tester = TesterAgent::new(1);
+        let test = |body: &str| format!("#[test]\nfn t() {{\n    {}\n}}\n", body);
+        let cases = [
+            ("fn broken() {", FailureKind::CompileError),
+            (
+                &test("assert_eq!(1, 2);"),
+                FailureKind::TestAssertionFailure,
+            ),
+            (&test("None::<u8>.unwrap();"), FailureKind::TestPanic),
+            (&test("std::process::exit(3);"), FailureKind::NonZeroExit),
+            (&test("std::process::abort();"), FailureKind::Crash),
+            // The test harness captures println!(), but not writes to stdout.
+            (
+                &test(
+                    "loop { std::io::Write::write_all(&mut std::io::stdout(), b\"y\").unwrap(); }",
+                ),
+                FailureKind::OutputExceeded,
+            ),
+        ];
+        for (code, kind) in cases {
+            let result = tester.compile_and_test(code).await.unwrap();
+            assert_eq!(failure_kind(result), Some(kind), "{}", code);
+        }
+        let result = TesterAgent::new(1)
+            .with_test_timeout(Duration::from_millis(500))
+            .compile_and_test(&test("loop { std::thread::yield_now(); }"))
+            .await
+            .unwrap();
+        assert_eq!(failure_kind(result), Some(FailureKind::Timeout));
+        let TesterResult::Failure { review, .. } = tester
+            .compile_and_test(&test("None::<u8>.unwrap();"))
+            .await
+            .unwrap()
+        else {
+            panic!("the panic passed");
+        };
+        assert_eq!(review.review_type, ReviewType::RuntimeFix);
+    }
+
+    #[tokio::test]
+    async fn test_coverage_limits() {
+        let test = |body: &str| format!("#[test]\nfn t() {{\n    {}\n}}\n", body);
+        let printing =
+            test("loop { std::io::Write::write_all(&mut std::io::stdout(), b\"y\").unwrap(); }");
+        let error = TesterAgent::new(1)
+            .with_max_output(1000)
+            .coverage(&printing)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().ends_with("printed more than 1000 bytes"));
+        let error = TesterAgent::new(1)
+            .with_test_timeout(Duration::from_millis(500))
+            .coverage(&test("loop { std::thread::yield_now(); }"))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().ends_with("ran longer than 500ms"));
+    }
+
+    #[tokio::test]
+    async fn test_complexity() {
+        // The constraints leave a margin of at least half a degree around the code's exponent, so
+        // that noisy timings don't fail the test.
+        let tester =
+            |constraint: &str| TesterAgent::new(1).with_complexity(complexity::parse(constraint));
+        let bench = |body: &str| {
+            format!(
+                "fn bench(n: usize) {{\n    let mut sum = 0u64;\n    {}\n    \
+                 std::hint::black_box(sum);\n}}\n",
+                body
+            )
+        };
+        let linear = bench("for i in 0..n { sum = sum.wrapping_add(i as u64); }");
+        let result = tester("Complexity: no O(n^3)")
+            .compile_and_test(&linear)
+            .await
+            .unwrap();
+        assert_eq!(failure_kind(result), None);
+
+        let quadratic =
+            bench("for i in 0..n { for j in 0..n { sum = sum.wrapping_add((i ^ j) as u64); } }");
+        let TesterResult::Failure { failure, review } = tester("Complexity: O(n)")
+            .compile_and_test(&quadratic)
+            .await
+            .unwrap()
+        else {
+            panic!("the quadratic code passed");
+        };
+        assert!(matches!(
+            failure,
+            Failure::ComplexityViolation {
+                exponent: Some(exponent),
+                ..
+            } if exponent > 1.5
+        ));
+        assert_eq!(review.review_type, ReviewType::ComplexityFix);
+
+        let tester = tester("Complexity: O(n)");
+        let result = tester.compile_and_test("fn f() {}\n").await.unwrap();
+        assert_eq!(failure_kind(result), Some(FailureKind::ComplexityViolation));
+
+        // A bench() that panics, or is too slow to time, fails too.
+        let panics = bench("assert!(n < 1000, \"too big\");");
+        let TesterResult::Failure { failure, .. } = tester.compile_and_test(&panics).await.unwrap()
+        else {
+            panic!("the panicking bench passed");
+        };
+        assert!(failure.output().contains("exited with code 101"));
+        let slow = bench("std::thread::sleep(std::time::Duration::from_millis(150));");
+        let result = tester.compile_and_test(&slow).await.unwrap();
+        assert_eq!(failure_kind(result), Some(FailureKind::ComplexityViolation));
+    }
+
+    #[tokio::test]
+    async fn test_test_seed() {
+        // Code seeded from the environment, as the prompts ask for under --test-seed.
+        let code = "fn seed() -> u64 {\n    std::env::var(\"AI_CRITICS_TEST_SEED\")\n        \
+                    .ok()\n        .and_then(|seed| seed.parse().ok())\n        \
+                    .unwrap_or(1)\n}\n\n#[test]\nfn test_seed() {\n    \
+                    assert_eq!(seed(), 42);\n}\n";
+        let seeded = TesterAgent::new(1).with_test_seed(Some(42));
+        let result = seeded.compile_and_test(code).await.unwrap();
+        assert_eq!(failure_kind(result), None);
+        let result = TesterAgent::new(1).compile_and_test(code).await.unwrap();
+        assert_eq!(
+            failure_kind(result),
+            Some(FailureKind::TestAssertionFailure)
+        );
+        // The instrumented tests are given the seed too.
+        let coverage = seeded.coverage(code).await.unwrap();
+        assert_eq!(coverage.line_fraction(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_cargo_test_failure_kinds() {
+        let kept = tempfile::tempdir().unwrap();
+        let tester = TesterAgent::new(1)
+            .with_test_timeout(Duration::from_secs(2))
+            .with_kept_builds(kept.path());
+        let cases = [
+            ("let x: u8 = \"\";", FailureKind::CompileError),
+            ("assert_eq!(1, 2);", FailureKind::TestAssertionFailure),
+            ("std::process::exit(3);", FailureKind::NonZeroExit),
+            ("std::process::abort();", FailureKind::Crash),
+            ("loop { std::thread::yield_now(); }", FailureKind::Timeout),
+            (
+                "loop { std::io::Write::write_all(&mut std::io::stdout(), b\"y\").unwrap(); }",
+                FailureKind::OutputExceeded,
+            ),
+        ];
+        for (body, kind) in cases {
+            let dir = TempDir::new().unwrap();
+            fs::write(
+                dir.path().join("Cargo.toml"),
+                "[package]\nname = \"t\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+            )
+            .unwrap();
+            fs::create_dir(dir.path().join("src")).unwrap();
+            let code = format!("#[test]\nfn t() {{\n    {}\n}}\n", body);
+            let file = dir.path().join("src/lib.rs");
+            fs::write(&file, code).unwrap();
+            let result = tester.cargo_test(dir.path(), &file).await.unwrap();
+            assert_eq!(failure_kind(result), Some(kind), "{}", body);
+        }
+        // Each run kept the file under test and what the tester judged it by.
+        let first = kept.path().join("build_1");
+        assert!(fs::read_to_string(first.join("lib.rs"))
+            .unwrap()
+            .contains("let x: u8"));
+        assert!(fs::read_to_string(first.join(KEPT_OUTPUT_FILENAME))
+            .unwrap()
+            .contains("error[E0308]"));
+    }
+
+    #[test]
+    fn test_judge_runs() {
+        assert_eq!(review_type(judge_runs(vec![passed(), passed()])), None);
+        let same = vec![failed(&["tests::a"]), failed(&["tests::a"])];
+        assert_eq!(review_type(judge_runs(same)), Some(ReviewType::TestFix));
+        let reordered = vec![
+            failed(&["tests::a", "tests::b"]),
+            failed(&["tests::b", "tests::a"]),
+        ];
+        assert_eq!(
+            review_type(judge_runs(reordered)),
+            Some(ReviewType::TestFix)
+        );
+
+        let TesterResult::Failure { failure, review } =
+            judge_runs(vec![passed(), failed(&["tests::a"]), passed()])
+        else {
+            panic!("flaky tests passed");
+        };
+        assert_eq!(review.review_type, ReviewType::FlakyTestFix);
+        let output = failure.output();
+        assert!(output.starts_with("The tests passed in 2 of 3 runs and failed in 1."));
+        assert!(output.contains("Run 2 failed: tests::a"));
+
+        let different = vec![failed(&["tests::a"]), failed(&["tests::b"])];
+        assert_eq!(
+            review_type(judge_runs(different)),
+            Some(ReviewType::FlakyTestFix)
+        );
     }
 }
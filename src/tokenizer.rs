@@ -0,0 +1,157 @@
+use crate::images;
+use async_openai::types::ChatCompletionRequestMessage;
+use std::sync::OnceLock;
+use tiktoken_rs::{cl100k_base, CoreBPE};
+
+// Measure text in the model's tokens so that prompts can be trimmed to fit its context window before
+// they are sent, rather than discovering the overflow from a Length finish reason. The GPT-4 models
+// use the cl100k_base encoding.
+
+fn bpe() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| cl100k_base().expect("cl100k_base is embedded in tiktoken-rs"))
+}
+
+pub fn count_tokens(text: &str) -> usize {
+    bpe().encode_ordinary(text).len()
+}
+
+// An image sent at high detail is scaled to fit in 2048 by 2048 pixels, then its shorter side to 768
+// if it's longer, and costs 85 tokens plus 170 for each 512-pixel tile that covers it. An image of
+// unknown size is counted as the largest, 768 by 2048, so that the estimate isn't short.
+const IMAGE_BASE_TOKENS: usize = 85;
+const TILE_TOKENS: usize = 170;
+const MAX_IMAGE_TOKENS: usize = IMAGE_BASE_TOKENS + TILE_TOKENS * 8;
+
+fn image_tokens(dimensions: Option<(u32, u32)>) -> usize {
+    let Some((width, height)) = dimensions else {
+        return MAX_IMAGE_TOKENS;
+    };
+    let (mut width, mut height) = (width as f64, height as f64);
+    let fit = (2048.0 / width.max(height)).min(1.0);
+    (width, height) = (width * fit, height * fit);
+    let shorten = (768.0 / width.min(height)).min(1.0);
+    (width, height) = (width * shorten, height * shorten);
+    let tiles = (width / 512.0).ceil() * (height / 512.0).ceil();
+    IMAGE_BASE_TOKENS + TILE_TOKENS * tiles.max(1.0) as usize
+}
+
+// The tokens of a chat request's prompt. As in OpenAI's cookbook, each message costs 3 tokens for
+// its role and separators, and the reply is primed with 3 more. Images are counted at high detail,
+// from their sizes.
+pub fn count_prompt_tokens(messages: &[ChatCompletionRequestMessage]) -> usize {
+    let tokens = |msg: &ChatCompletionRequestMessage| match msg {
+        ChatCompletionRequestMessage::System(m) => m.content.as_deref().map_or(0, count_tokens),
+        ChatCompletionRequestMessage::User(m) => m.content.as_ref().map_or(0, |content| {
+            count_tokens(&images::content_text(content))
+                + images::content_dimensions(content)
+                    .into_iter()
+                    .map(image_tokens)
+                    .sum::<usize>()
+        }),
+        ChatCompletionRequestMessage::Assistant(m) => m.content.as_deref().map_or(0, count_tokens),
+        _ => 0,
+    };
+    messages.iter().map(|msg| 3 + tokens(msg)).sum::<usize>() + 3
+}
+
+// Shorten the text to at most about `max_tokens` tokens by removing whole lines from its middle. The
+// start and end of compiler and test output and of code usually say the most, so they are kept.
+// Return the text and the number of tokens removed.
+pub fn truncate_middle(text: &str, max_tokens: usize) -> (String, usize) {
+    let total = count_tokens(text);
+    if total <= max_tokens {
+        return (text.to_string(), 0);
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let counts: Vec<usize> = lines.iter().map(|l| count_tokens(l) + 1).collect();
+
+    // Take lines alternately from the start and the end while they fit.
+    let (mut head, mut tail, mut used) = (0, lines.len(), 0);
+    let mut from_start = true;
+    while head < tail {
+        let i = if from_start { head } else { tail - 1 };
+        if used + counts[i] > max_tokens {
+            break;
+        }
+        used += counts[i];
+        if from_start {
+            head += 1;
+        } else {
+            tail -= 1;
+        }
+        from_start = !from_start;
+    }
+
+    let omitted = total.saturating_sub(used);
+    let mut kept = lines[..head].to_vec();
+    let marker = format!("... [{} tokens omitted] ...", omitted);
+    kept.push(&marker);
+    kept.extend(&lines[tail..]);
+    (kept.join("\n"), omitted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::ChatCompletionRequestUserMessageArgs;
+    use base64::Engine;
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("hello world"), 2);
+
+        let messages = [ChatCompletionRequestUserMessageArgs::default()
+            .content("hello world")
+            .build()
+            .unwrap()
+            .into()];
+        assert_eq!(count_prompt_tokens(&messages), 3 + 2 + 3);
+
+        let image = images::ProblemImage {
+            media_type: "image/png",
+            data: "iVBORw==".to_string(),
+        };
+        let messages = [images::user_message("hello world", &[image])];
+        assert_eq!(count_prompt_tokens(&messages), 3 + 2 + MAX_IMAGE_TOKENS + 3);
+        // A 1024 by 512 PNG, which two tiles cover.
+        let png = [
+            b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR",
+            &[0, 0, 4, 0, 0, 0, 2, 0][..],
+        ]
+        .concat();
+        let image = images::ProblemImage {
+            media_type: "image/png",
+            data: base64::engine::general_purpose::STANDARD.encode(png),
+        };
+        let messages = [images::user_message("hello world", &[image])];
+        assert_eq!(count_prompt_tokens(&messages), 3 + 2 + 85 + 170 * 2 + 3);
+    }
+
+    #[test]
+    fn test_image_tokens() {
+        assert_eq!(image_tokens(Some((100, 100))), 255);
+        assert_eq!(image_tokens(Some((1024, 1024))), 765);
+        // Fitted to 1024 by 2048, then shortened to 768 by 1536.
+        assert_eq!(image_tokens(Some((4096, 8192))), 85 + 170 * 6);
+        assert_eq!(image_tokens(Some((768, 2048))), MAX_IMAGE_TOKENS);
+        assert_eq!(image_tokens(None), MAX_IMAGE_TOKENS);
+    }
+
+    #[test]
+    fn test_truncate_middle() {
+        assert_eq!(truncate_middle("short", 10), ("short".to_string(), 0));
+
+        let text = (1..=100)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (truncated, omitted) = truncate_middle(&text, 30);
+        assert!(omitted > 0);
+        assert!(truncated.starts_with("line 1\n"));
+        assert!(truncated.ends_with("\nline 100"));
+        assert!(truncated.contains("tokens omitted"));
+        assert!(count_tokens(&truncated) < count_tokens(&text));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::events::{describe, Observer, Phase, RunEvent};
+use crate::progress_bar::{DoublingProgressBar, ResponseEstimates};
+use crate::steering::Steering;
+use crate::{processes, CANCELLED_EXIT_CODE};
+use color_eyre::eyre::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// The Dashboard is an optional full-screen view of the run loop. It shows the current code, the
+// critics' progress and verdicts, the latest tester output, and a history of what has happened so
+// far. It is an Observer of the run's events. A background thread redraws the screen several times a
+// second so that the progress bars, which are indicatif bars drawn hidden, animate. When the
+// dashboard is stopped, the history is printed to the normal terminal so that the run remains in the
+// scrollback.
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+// Show only this many of the most recent history entries.
+const MAX_HISTORY_LINES: usize = 200;
+
+struct AgentStatus {
+    name: String,
+    progress: DoublingProgressBar,
+    verdict: Option<bool>,
+}
+
+#[derive(Default)]
+struct DashboardState {
+    phase: String,
+    proposal: Option<usize>,
+    code: String,
+    agents: Vec<AgentStatus>,
+    estimates: ResponseEstimates,
+    tester_output: String,
+    history: Vec<String>,
+}
+
+pub struct Dashboard {
+    state: Arc<Mutex<DashboardState>>,
+    stop: Arc<AtomicBool>,
+    render_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Dashboard {
+    // Start the dashboard if stdout is a terminal. Otherwise, return None so that the caller falls
+    // back to plain output. Pressing `r` asks the steering to regenerate the streaming response.
+    pub fn start(steering: Steering) -> Result<Option<Self>> {
+        if !io::stdout().is_terminal() {
+            println!("Not a terminal; using plain output instead of the dashboard.");
+            return Ok(None);
+        }
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+        let state = Arc::new(Mutex::new(DashboardState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let render_state = state.clone();
+        let render_stop = stop.clone();
+        let render_thread = thread::spawn(move || {
+            while !render_stop.load(Ordering::Relaxed) {
+                {
+                    let state = render_state.lock().unwrap();
+                    if terminal.draw(|f| render(f, &state)).is_err() {
+                        return;
+                    }
+                }
+                match Self::read_key() {
+                    Some(key) if Self::is_quit(&key) => {
+                        restore_terminal();
+                        println!("Dashboard closed. Exiting.");
+                        processes::kill_all();
+                        std::process::exit(CANCELLED_EXIT_CODE);
+                    }
+                    Some(key) if key.code == KeyCode::Char('r') => steering.request(None),
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(Some(Dashboard {
+            state,
+            stop,
+            render_thread: Some(render_thread),
+        }))
+    }
+
+    // Wait up to REFRESH_INTERVAL for a key press.
+    fn read_key() -> Option<KeyEvent> {
+        if !event::poll(REFRESH_INTERVAL).unwrap_or(false) {
+            return None;
+        }
+        match event::read() {
+            Ok(Event::Key(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    // Raw mode swallows Ctrl-C, so handle it here along with `q`.
+    fn is_quit(key: &KeyEvent) -> bool {
+        key.code == KeyCode::Char('q')
+            || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL))
+    }
+
+    fn update(&self, f: impl FnOnce(&mut DashboardState)) {
+        f(&mut self.state.lock().unwrap());
+    }
+}
+
+impl DashboardState {
+    fn on_event(&mut self, event: &RunEvent) {
+        match event {
+            // Each phase shows only the agents working in it.
+            RunEvent::PhaseStarted { phase } => {
+                self.phase = phase.to_string();
+                for agent in self.agents.drain(..) {
+                    self.estimates.record(&agent.name, agent.progress.tokens());
+                }
+            }
+            RunEvent::ProposalCreated { number, code } => {
+                self.proposal = Some(*number);
+                self.code = code.clone();
+            }
+            RunEvent::FixApplied { code, .. } => self.code = code.clone(),
+            RunEvent::AgentStarted { agent } => {
+                let expected = self.estimates.expected(agent);
+                if let Ok(progress) = DoublingProgressBar::new_hidden(agent, expected) {
+                    self.agents.push(AgentStatus {
+                        name: agent.clone(),
+                        progress,
+                        verdict: None,
+                    });
+                }
+            }
+            RunEvent::AgentProgress { agent, update } => {
+                if let Some(a) = self.agents.iter_mut().find(|a| &a.name == agent) {
+                    a.progress.apply(update);
+                }
+            }
+            RunEvent::ChatRetried { agent, attempt } => {
+                if let Some(a) = self.agents.iter_mut().find(|a| &a.name == agent) {
+                    a.progress.set_retries(*attempt);
+                }
+            }
+            RunEvent::CriticFinished { critic, lgtm, .. } => {
+                if let Some(a) = self.agents.iter_mut().find(|a| &a.name == critic) {
+                    a.verdict = Some(*lgtm);
+                }
+            }
+            RunEvent::TestPassed { output, .. } | RunEvent::TestFailed { output, .. } => {
+                self.tester_output = output.clone();
+            }
+            _ => {}
+        }
+        // The code is shown in its own pane, so it isn't repeated in the history.
+        if matches!(event, RunEvent::ProposalCreated { .. }) {
+            return;
+        }
+        if let Some(text) = describe(event) {
+            self.history
+                .extend(text.lines().filter(|l| !l.is_empty()).map(String::from));
+        }
+    }
+
+    fn title(&self) -> String {
+        match self.proposal {
+            Some(n) if self.phase == Phase::Reviewing.to_string() => {
+                format!("{} proposal #{}", self.phase, n)
+            }
+            _ => self.phase.clone(),
+        }
+    }
+}
+
+impl Observer for Dashboard {
+    fn on_event(&mut self, event: &RunEvent) {
+        self.update(|s| s.on_event(event));
+    }
+}
+
+// Stop redrawing, restore the terminal, and print the history to the normal screen.
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.render_thread.take() {
+            let _ = handle.join();
+        }
+        restore_terminal();
+        for line in self.state.lock().unwrap().history.iter() {
+            println!("{}", line);
+        }
+    }
+}
+
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+}
+
+fn titled(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+// Return the last `n` lines of the text, so that panes show the newest output.
+fn tail(text: &str, n: usize) -> String {
+    let lines: Vec<_> = text.lines().collect();
+    lines[lines.len().saturating_sub(n)..].join("\n")
+}
+
+fn render(f: &mut Frame, state: &DashboardState) {
+    let [header, body] =
+        *Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).split(f.size())
+    else {
+        return;
+    };
+    f.render_widget(
+        Paragraph::new(format!(
+            "AI Critics — {}   (press q to quit, r to regenerate)",
+            state.title()
+        )),
+        header,
+    );
+
+    let [left, right] =
+        *Layout::horizontal([Constraint::Percentage(55), Constraint::Min(0)]).split(body)
+    else {
+        return;
+    };
+    f.render_widget(
+        Paragraph::new(state.code.as_str()).block(titled("Current code")),
+        left,
+    );
+
+    let agent_height = (state.agents.len() as u16) * 3;
+    let [agents, tester, history] = *Layout::vertical([
+        Constraint::Length(agent_height),
+        Constraint::Percentage(40),
+        Constraint::Min(0),
+    ])
+    .split(right) else {
+        return;
+    };
+
+    let rows = Layout::vertical(vec![Constraint::Length(3); state.agents.len()]).split(agents);
+    for (agent, row) in state.agents.iter().zip(rows.iter()) {
+        let (color, verdict) = match agent.verdict {
+            None => (Color::Cyan, "working"),
+            Some(true) => (Color::Green, "LGTM"),
+            Some(false) => (Color::Red, "changes requested"),
+        };
+        let bar = agent.progress.bar();
+        let length = bar.length().unwrap_or(1).max(1);
+        let ratio = (bar.position() as f64 / length as f64).min(1.0);
+        f.render_widget(
+            Gauge::default()
+                .block(titled(&agent.name))
+                .gauge_style(Style::default().fg(color))
+                .ratio(ratio)
+                .label(format!(
+                    "{}, {}s, {}",
+                    agent.progress.status(),
+                    bar.elapsed().as_secs(),
+                    verdict
+                )),
+            *row,
+        );
+    }
+
+    let tester_lines = tester.height.saturating_sub(2) as usize;
+    f.render_widget(
+        Paragraph::new(tail(&state.tester_output, tester_lines))
+            .block(titled("Tester output"))
+            .wrap(Wrap { trim: false }),
+        tester,
+    );
+
+    let history_lines = history.height.saturating_sub(2) as usize;
+    let start = state
+        .history
+        .len()
+        .saturating_sub(history_lines.min(MAX_HISTORY_LINES));
+    let lines: Vec<Line> = state.history[start..]
+        .iter()
+        .map(|h| Line::from(h.as_str()))
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(titled("History")), history);
+}
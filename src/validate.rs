@@ -0,0 +1,106 @@
+use crate::fixer::{ReviewNeeded, ReviewType};
+use syn::{Attribute, Item};
+
+// Each proposal is parsed before the critics review it. A response cut off by the token limit, or
+// otherwise not valid Rust, would only have the critics point out what the parser can, so it goes
+// straight back to the fixer instead. So does code without the unit tests that the coder prompt
+// asks for, since there would be nothing for the tester to run.
+
+// Return a ReviewNeeded if the code doesn't parse or has no tests.
+pub fn review(code: &str) -> Option<ReviewNeeded> {
+    let file = match syn::parse_file(code) {
+        Ok(file) => file,
+        Err(e) => {
+            let start = e.span().start();
+            // The tokenizer's only error is an unclosed delimiter or literal, which it reports at
+            // the opening one.
+            let error = match e.to_string().as_str() {
+                "cannot parse string into token stream" => {
+                    "its brackets or quotes are unbalanced, starting".to_string()
+                }
+                error => error.to_string(),
+            };
+            return Some(ReviewNeeded::new(
+                ReviewType::CompilerFix,
+                vec![format!(
+                    "The code doesn't parse: {} at line {}, column {}. If the code was cut off, \
+                     return all of it.",
+                    error,
+                    start.line,
+                    start.column + 1
+                )],
+            ));
+        }
+    };
+    let test_modules: Vec<&Vec<Item>> = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Mod(module) if is_cfg_test(&module.attrs) => module.content.as_ref(),
+            _ => None,
+        })
+        .map(|(_, items)| items)
+        .collect();
+    let comment = match test_modules.iter().any(|items| has_test(items)) {
+        true => return None,
+        false if test_modules.is_empty() => {
+            "The code has no `#[cfg(test)]` module. Add one with unit tests that show that the code \
+             solves the problem."
+        }
+        false => {
+            "The `#[cfg(test)]` module has no `#[test]` functions. Add unit tests that show that \
+             the code solves the problem."
+        }
+    };
+    Some(ReviewNeeded::new(
+        ReviewType::CodeReview,
+        vec![comment.to_string()],
+    ))
+}
+
+fn is_cfg_test(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("cfg")
+            && attr
+                .parse_args::<syn::Ident>()
+                .is_ok_and(|arg| arg == "test")
+    })
+}
+
+// Whether the items include a #[test] function, looking into nested modules.
+fn has_test(items: &[Item]) -> bool {
+    items.iter().any(|item| match item {
+        Item::Fn(f) => f.attrs.iter().any(|attr| attr.path().is_ident("test")),
+        Item::Mod(module) => module
+            .content
+            .as_ref()
+            .is_some_and(|(_, items)| has_test(items)),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_review() {
+        let code = "fn add(a: i32, b: i32) -> i32 { a + b }\n\
+                    #[cfg(test)]\nmod tests {\n    use super::*;\n    #[test]\n    fn test_add() { \
+                    assert_eq!(add(1, 2), 3); }\n}\n";
+        assert!(review(code).is_none());
+
+        let truncated = &code[..code.len() - 3];
+        let review = super::review(truncated).unwrap();
+        assert_eq!(review.review_type, ReviewType::CompilerFix);
+        assert!(review.comments[0].starts_with("The code doesn't parse: "));
+        // The unclosed brace is that of the test module.
+        assert!(review.comments[0].contains("unbalanced, starting at line 3, column 11."));
+
+        let review = super::review("fn add(a: i32, b: i32) -> i32 { a + b }\n").unwrap();
+        assert_eq!(review.review_type, ReviewType::CodeReview);
+        assert!(review.comments[0].contains("no `#[cfg(test)]` module"));
+        let review = super::review("#[cfg(test)]\nmod tests {\n    fn helper() {}\n}\n").unwrap();
+        assert!(review.comments[0].contains("no `#[test]` functions"));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::events::{Observer, RunEvent};
+use crate::output::{self, Verbosity};
+use axum::extract::State;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Html;
+use axum::routing::get;
+use axum::Router;
+use color_eyre::eyre::Result;
+use futures::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+// The web dashboard streams the run's events to a browser with server-sent events (SSE), so that
+// long batch runs can be monitored remotely. Each event is sent as the RunEvent's JSON. `GET /`
+// serves a small page that renders the events and `GET /events` is the SSE stream. Every event is
+// also kept in a history that is replayed to each new connection, so a page opened midway through a
+// run shows everything that has happened.
+
+// Live events are buffered per connection. A client that falls further behind than this misses
+// events rather than slowing the run.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone)]
+struct WebState {
+    sender: broadcast::Sender<RunEvent>,
+    history: Arc<Mutex<Vec<RunEvent>>>,
+}
+
+pub struct WebServer {
+    state: WebState,
+}
+
+impl WebServer {
+    // Bind to the address and serve the dashboard in a background task.
+    pub async fn start(addr: SocketAddr) -> Result<Self> {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let state = WebState {
+            sender,
+            history: Arc::new(Mutex::new(vec![])),
+        };
+        let app = Router::new()
+            .route("/", get(index))
+            .route("/events", get(events))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        output::print(
+            Verbosity::Normal,
+            &format!(
+                "Serving the run dashboard at http://{}/",
+                listener.local_addr()?
+            ),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                log::error!("The web dashboard stopped: {}", e);
+            }
+        });
+        Ok(WebServer { state })
+    }
+
+    pub fn publish(&self, event: RunEvent) {
+        self.state.history.lock().unwrap().push(event.clone());
+        // Sending fails only when no browser is connected, which is fine.
+        let _ = self.state.sender.send(event);
+    }
+}
+
+// The page shows neither progress bars nor the raw chat messages, so those events aren't sent.
+impl Observer for WebServer {
+    fn on_event(&mut self, event: &RunEvent) {
+        if !matches!(
+            event,
+            RunEvent::AgentProgress { .. }
+                | RunEvent::ChatRequested { .. }
+                | RunEvent::ChatResponded { .. }
+        ) {
+            self.publish(event.clone());
+        }
+    }
+}
+
+async fn index() -> Html<&'static str> {
+    Html(INDEX_HTML)
+}
+
+fn to_sse(event: &RunEvent) -> Result<Event, Infallible> {
+    Ok(Event::default().data(serde_json::to_string(event).unwrap_or_default()))
+}
+
+// Replay the history, then stream live events. Subscribe before copying the history so that no
+// event falls between the two.
+async fn events(
+    State(state): State<WebState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.sender.subscribe();
+    let history: Vec<_> = state.history.lock().unwrap().iter().map(to_sse).collect();
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((to_sse(&event), receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream::iter(history).chain(live)).keep_alive(KeepAlive::default())
+}
+
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>AI Critics</title>
+<style>
+  body { font-family: sans-serif; margin: 0; display: grid; grid-template-columns: 55% 45%; height: 100vh; }
+  section { overflow: auto; padding: 0 1em; border-left: 1px solid #ccc; }
+  pre { background: #f6f6f6; padding: 0.5em; white-space: pre-wrap; }
+  .lgtm { color: green; } .changes { color: firebrick; } .phase { font-weight: bold; margin-top: 1em; }
+</style>
+</head>
+<body>
+<section><h2 id="proposal">Waiting for a proposal...</h2><pre id="code"></pre></section>
+<section><h2 id="status">Running</h2><div id="log"></div></section>
+<script>
+  const log = document.getElementById("log");
+  function add(html, cls) {
+    const div = document.createElement("div");
+    if (cls) div.className = cls;
+    div.innerHTML = html;
+    log.appendChild(div);
+    div.scrollIntoView();
+  }
+  function esc(s) { const d = document.createElement("div"); d.textContent = s; return d.innerHTML; }
+  const phases = {
+    coding: "Coder writing solution", reviewing: "Critics reviewing", fixing: "Fixer correcting",
+    testing: "Tester compiling and testing", measuring_coverage: "Measuring test coverage",
+    mutation_testing: "Mutation testing",
+  };
+  const source = new EventSource("/events");
+  source.onmessage = (msg) => {
+    const e = JSON.parse(msg.data);
+    switch (e.type) {
+      case "phase_started": add(esc(phases[e.phase] || e.phase), "phase"); break;
+      case "proposal_created":
+        document.getElementById("proposal").textContent = "Proposal #" + e.number;
+        document.getElementById("code").textContent = e.code;
+        break;
+      case "fix_applied": document.getElementById("code").textContent = e.code; break;
+      case "critic_finished":
+        add(esc(e.critic) + ": " + (e.lgtm ? "LGTM" : "changes requested") +
+            "<ul>" + e.corrections.map((c) => "<li>" + esc(c) + "</li>").join("") + "</ul>",
+            e.lgtm ? "lgtm" : "changes");
+        break;
+      case "critic_failed": add(esc(e.critic) + " failed: " + esc(e.error), "changes"); break;
+      case "critics_agreed": add("All of the critics agree that the code is correct.", "lgtm"); break;
+      case "test_passed": add("Tests passed<pre>" + esc(e.output) + "</pre>", "lgtm"); break;
+      case "test_failed": add("Tests failed<pre>" + esc(e.output) + "</pre>", "changes"); break;
+      case "coverage_measured": case "mutation_tested": add(esc(e.summary)); break;
+      case "notice": add(esc(e.message)); break;
+      case "run_finished":
+        document.getElementById("status").textContent = e.outcome;
+        source.close();
+        break;
+    }
+  };
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ProgressUpdate;
+
+    #[tokio::test]
+    async fn test_web_server_skips_progress_updates() {
+        let mut server = WebServer::start("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        server.on_event(&RunEvent::AgentProgress {
+            agent: "Coder_1".to_string(),
+            update: ProgressUpdate::Chunk {
+                text: "fn".to_string(),
+            },
+        });
+        server.on_event(&RunEvent::CriticsAgreed);
+        assert_eq!(
+            *server.state.history.lock().unwrap(),
+            vec![RunEvent::CriticsAgreed]
+        );
+    }
+}
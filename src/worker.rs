@@ -0,0 +1,355 @@
+use crate::api;
+use crate::errors::AiCriticError;
+use crate::events::{self, EventBus, RunEvent};
+use crate::notify::{self, RunSummary};
+use crate::output::{self, Verbosity};
+use crate::provider::Provider;
+use crate::request_limiter::RequestLimiter;
+use crate::run_context::RunContext;
+use crate::steering::Steering;
+use crate::store::Store;
+use crate::{artifact_store, describe_outcome, run_report, solve_problem_file, Args};
+use color_eyre::eyre::Result;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinSet;
+
+// The worker turns the tool into a small code-generation service. Problems are submitted to a
+// queue, as the bodies of `POST /problems` requests or as `.txt` files dropped into a watched
+// directory, and a pool of workers solves them, several at a time. Each job is a run like any
+// other: its ID names its directory of artifacts, which holds the problem, the transcript, and the
+// result. The runs share one RequestLimiter, so that together they stay under the API's rate
+// limits.
+
+// How often the watched directory is checked for new problems.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+// Where the watched directory's problems are moved once they're queued.
+const QUEUED_DIR: &str = "queued";
+// Where the watched directory's files are moved if they can't be queued.
+const FAILED_DIR: &str = "failed";
+// The problem of a job, kept with its artifacts.
+const PROBLEM_FILENAME: &str = "problem.txt";
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(required = true, multiple = true, args = ["listen", "watch_dir"])]
+pub struct WorkerArgs {
+    /// Number of problems to solve at a time.
+    #[arg(long, default_value_t = 2)]
+    pub workers: usize,
+
+    /// Address to serve the REST API on, which accepts problems at `POST /problems` and reports on
+    /// their runs at `GET /runs/{id}`, e.g. 127.0.0.1:8090.
+    #[arg(long)]
+    pub listen: Option<SocketAddr>,
+
+    /// Directory to watch for problems. Each `.txt` file put there is queued and moved to its
+    /// `queued/` subdirectory, or moved to `failed/` if it can't be queued.
+    #[arg(long)]
+    pub watch_dir: Option<PathBuf>,
+
+    /// Most requests to the model per minute, shared by all of the workers' runs. 0 for no limit.
+    #[arg(long, default_value_t = 0)]
+    pub requests_per_minute: u32,
+
+    /// Token that every request to the REST API must give as `Authorization: Bearer <token>`.
+    /// Required to listen on an address other than a loopback one.
+    #[arg(long, env = "AI_CRITICS_API_TOKEN", hide_env_values = true)]
+    pub api_token: Option<String>,
+}
+
+// A problem waiting to be solved, whose artifact directory has been created.
+#[derive(Debug)]
+struct Job {
+    context: RunContext,
+    // Where the problem came from, for the log.
+    source: String,
+}
+
+// Creates each submitted job's directory and queues it for the workers.
+pub struct JobQueue {
+    output_dir: PathBuf,
+    base_id: String,
+    submitted: AtomicUsize,
+    sender: mpsc::UnboundedSender<Job>,
+    // Where else the jobs' artifacts are saved, if anywhere.
+    store: Option<Arc<dyn Store>>,
+}
+
+impl JobQueue {
+    // Queue the problem and return its job's ID.
+    pub fn submit(&self, problem: &str, source: &str) -> Result<String> {
+        let n = self.submitted.fetch_add(1, Ordering::Relaxed) + 1;
+        let context = RunContext::create(&self.output_dir, &format!("{}-{}", self.base_id, n))?
+            .with_store(self.store.clone());
+        fs::write(context.dir.join(PROBLEM_FILENAME), problem)?;
+        let id = context.id.clone();
+        output::print(
+            Verbosity::Normal,
+            &format!("[worker] Queued {} from {}.", id, source),
+        );
+        self.sender.send(Job {
+            context,
+            source: source.to_string(),
+        })?;
+        Ok(id)
+    }
+
+    // Where the jobs' artifacts are kept.
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    // Queue each `.txt` file in the directory, moving it aside so that it's queued only once. A file
+    // that can't be queued, such as one that isn't UTF-8, is moved to the failed directory instead,
+    // so that it doesn't hold up the files after it.
+    fn take_files(&self, dir: &Path) -> Result<Vec<String>> {
+        let queued = dir.join(QUEUED_DIR);
+        fs::create_dir_all(&queued)?;
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "txt"))
+            .collect();
+        paths.sort();
+        let mut ids = vec![];
+        for path in paths {
+            match self.take_file(&path, &queued) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    log::error!("Couldn't queue the problem in {}: {}", path.display(), e);
+                    if let Err(e) = set_aside(&path, &dir.join(FAILED_DIR)) {
+                        log::error!("Couldn't move {} aside: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    fn take_file(&self, path: &Path, queued: &Path) -> Result<String> {
+        let problem = fs::read_to_string(path)?;
+        let taken = queued.join(path.file_name().unwrap_or_default());
+        fs::rename(path, &taken)?;
+        self.submit(&problem, &taken.display().to_string())
+    }
+}
+
+// Move the file into the directory, if it's still where it was.
+fn set_aside(path: &Path, dir: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    fs::rename(path, dir.join(path.file_name().unwrap_or_default()))
+}
+
+// Take problems from the sources that the args give and solve them until the process is stopped.
+pub async fn run(args: &Args, worker: &WorkerArgs, provider: &Provider) -> Result<()> {
+    let limiter = Arc::new(RequestLimiter::per_minute(worker.requests_per_minute));
+    let provider = provider.clone().with_request_limiter(limiter);
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let queue = Arc::new(JobQueue {
+        output_dir: args.output_dir.clone(),
+        base_id: events::new_run_id(),
+        submitted: AtomicUsize::new(0),
+        sender,
+        store: artifact_store(args)?,
+    });
+
+    if let Some(addr) = worker.listen {
+        check_listen(addr, worker.api_token.as_deref())?;
+        let app = api::router(queue.clone(), worker.api_token.clone());
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        output::print(
+            Verbosity::Normal,
+            &format!(
+                "[worker] Accepting problems at http://{}/problems",
+                listener.local_addr()?
+            ),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                log::error!("The worker's server stopped: {}", e);
+            }
+        });
+    }
+    if let Some(dir) = worker.watch_dir.clone() {
+        let queue = queue.clone();
+        output::print(
+            Verbosity::Normal,
+            &format!("[worker] Watching {} for problems.", dir.display()),
+        );
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = queue.take_files(&dir) {
+                    log::error!("Couldn't queue the problems in {}: {}", dir.display(), e);
+                }
+                tokio::time::sleep(WATCH_INTERVAL).await;
+            }
+        });
+    }
+
+    let receiver = Arc::new(Mutex::new(receiver));
+    let mut workers = JoinSet::new();
+    for _ in 0..worker.workers.max(1) {
+        let (args, provider, receiver) = (args.clone(), provider.clone(), receiver.clone());
+        workers.spawn(async move {
+            loop {
+                let Some(job) = receiver.lock().await.recv().await else {
+                    break;
+                };
+                solve_job(&args, &provider, job).await;
+            }
+        });
+    }
+    while workers.join_next().await.is_some() {}
+    Ok(())
+}
+
+// Only a loopback address may be served without a token.
+fn check_listen(addr: SocketAddr, token: Option<&str>) -> Result<()> {
+    if !addr.ip().is_loopback() && token.is_none() {
+        return Err(AiCriticError::MissingSetting {
+            setting: format!("--api-token, which serving on {} requires", addr),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// Solve the job's problem, writing its events and result to its directory.
+async fn solve_job(args: &Args, provider: &Provider, job: Job) {
+    let context = job.context;
+    let problem_file = match context.dir.join(PROBLEM_FILENAME).canonicalize() {
+        Ok(path) => path,
+        Err(e) => {
+            log::error!("The problem of {} is missing: {}", context.id, e);
+            return;
+        }
+    };
+    let mut args = args.clone();
+    args.problem_file = problem_file.display().to_string();
+    args.command = None;
+    let summary = RunSummary::default();
+    let mut observers = match context.observers(&job.source) {
+        Ok(observers) => observers,
+        Err(e) => {
+            log::error!("Couldn't record the run of {}: {}", context.id, e);
+            return;
+        }
+    };
+    observers.push(Box::new(summary.clone()));
+    let dispatcher = EventBus::start(observers, Steering::default());
+    let result = solve_problem_file(dispatcher.bus(), &args, provider, None, Some(&context)).await;
+    let outcome = describe_outcome(&result);
+    dispatcher.bus().emit(RunEvent::RunFinished {
+        outcome: outcome.clone(),
+    });
+    dispatcher.close().await;
+    context.flush_store().await;
+    output::print(
+        Verbosity::Normal,
+        &format!(
+            "[worker] {}: {}. The artifacts are in {}",
+            context.id,
+            outcome,
+            context.dir.display()
+        ),
+    );
+    let report = run_report(&summary, &context, &result);
+    notify::notify(args.notify_webhook.as_deref(), &report).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(output_dir: &Path) -> (JobQueue, mpsc::UnboundedReceiver<Job>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = JobQueue {
+            output_dir: output_dir.to_path_buf(),
+            base_id: "worker".to_string(),
+            submitted: AtomicUsize::new(0),
+            sender,
+            store: None,
+        };
+        (queue, receiver)
+    }
+
+    #[tokio::test]
+    async fn test_api_token() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let (queue, mut receiver) = queue(output_dir.path());
+        let app = api::router(Arc::new(queue), Some("s3cret".to_string()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let submit = client
+            .post(format!("{}/problems", url))
+            .body("Add two numbers.");
+        let response = submit.try_clone().unwrap().send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client
+            .get(format!("{}/runs/worker-1", url))
+            .bearer_auth("wrong")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert!(receiver.try_recv().is_err());
+
+        let response = submit.bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(response.status(), 202);
+        assert!(receiver.try_recv().is_ok());
+
+        assert!(check_listen("127.0.0.1:8090".parse().unwrap(), None).is_ok());
+        assert!(check_listen("[::1]:8090".parse().unwrap(), None).is_ok());
+        assert!(check_listen("0.0.0.0:8090".parse().unwrap(), None).is_err());
+        assert!(check_listen("0.0.0.0:8090".parse().unwrap(), Some("s3cret")).is_ok());
+    }
+
+    #[test]
+    fn test_take_files() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let watch_dir = tempfile::tempdir().unwrap();
+        let (queue, mut receiver) = queue(output_dir.path());
+        fs::write(watch_dir.path().join("b.txt"), "Add two numbers.").unwrap();
+        fs::write(watch_dir.path().join("a.txt"), "Reverse a string.").unwrap();
+        fs::write(watch_dir.path().join("notes.md"), "Not a problem.").unwrap();
+
+        let ids = queue.take_files(watch_dir.path()).unwrap();
+        assert_eq!(ids, vec!["worker-1", "worker-2"]);
+        let job = receiver.try_recv().unwrap();
+        assert!(job.source.ends_with("a.txt"));
+        let problem = fs::read_to_string(job.context.dir.join(PROBLEM_FILENAME)).unwrap();
+        assert_eq!(problem, "Reverse a string.");
+        assert!(watch_dir.path().join(QUEUED_DIR).join("b.txt").exists());
+        assert!(watch_dir.path().join("notes.md").exists());
+        // The files are queued only once.
+        assert!(queue.take_files(watch_dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_take_files_sets_aside_invalid_files() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let watch_dir = tempfile::tempdir().unwrap();
+        let (queue, mut receiver) = queue(output_dir.path());
+        fs::write(watch_dir.path().join("a.txt"), b"\xff\xfe not UTF-8").unwrap();
+        fs::write(watch_dir.path().join("b.txt"), "Add two numbers.").unwrap();
+
+        let ids = queue.take_files(watch_dir.path()).unwrap();
+        assert_eq!(ids, vec!["worker-1"]);
+        assert!(receiver.try_recv().unwrap().source.ends_with("b.txt"));
+        assert!(receiver.try_recv().is_err());
+        assert!(watch_dir.path().join(FAILED_DIR).join("a.txt").exists());
+        assert!(!watch_dir.path().join("a.txt").exists());
+        assert!(queue.take_files(watch_dir.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,625 @@
+use ai_critics::scripted::{ScriptedClient, ScriptedTester};
+use ai_critics::{solve_with, Args, Observer, RunEvent, RunState};
+use clap::Parser;
+use serde_json::json;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Drive the whole run loop with scripted agents and tester: the coder's first proposal is rejected
+// by the syntax critic, the fixed code fails its tests, and the code fixed again is accepted by the
+// critics and passes. In test-first order, the coder's proposal is tested first, and its fix is
+// only reviewed once it passes. In parallel order, it's tested while it's reviewed, and one fix
+// addresses both the critic's correction and the failed test.
+
+fn code(body: &str) -> String {
+    format!(
+        "pub fn add(a: i32, b: i32) -> i32 {{\n    {}\n}}\n\n#[cfg(test)]\nmod tests {{\n    \
+         #[test]\n    fn test_add() {{\n        assert_eq!(super::add(1, 2), 3);\n    }}\n}}\n",
+        body
+    )
+}
+
+fn script(system: &str, user: &str) -> String {
+    let response = if system.contains("Write the requested program") {
+        json!({"code": code("a - b")})
+    } else if system.contains("Correct the code") && user.contains("test result: FAILED") {
+        json!({"code": code("a + b")})
+    } else if system.contains("Correct the code") {
+        json!({"code": code("a.wrapping_sub(-b)")})
+    } else if system.contains("_syntax_") && user.contains("a - b") {
+        json!({"lgtm": false, "corrections": ["`add` subtracts instead of adding."]})
+    } else {
+        json!({"lgtm": true, "corrections": []})
+    };
+    response.to_string()
+}
+
+struct Recorder(Arc<Mutex<Vec<RunEvent>>>);
+
+impl Observer for Recorder {
+    fn on_event(&mut self, event: &RunEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[tokio::test]
+async fn test_scripted_run() {
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "Write a function that adds two integers.").unwrap();
+    let args = Args::try_parse_from([
+        "ai_critics",
+        "--problem-file",
+        problem.path().to_str().unwrap(),
+    ])
+    .unwrap();
+    let tester = ScriptedTester::new(vec![Some(
+        "test tests::test_add ... FAILED\n\ntest result: FAILED. 0 passed; 1 failed",
+    )]);
+    let events = Arc::new(Mutex::new(vec![]));
+
+    let proposals = solve_with(
+        &args,
+        vec![Box::new(Recorder(events.clone()))],
+        Arc::new(ScriptedClient::new(script)),
+        &tester,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(proposals, 2);
+    assert_eq!(
+        tester.tested(),
+        vec![code("a.wrapping_sub(-b)"), code("a + b")]
+    );
+    let events = events.lock().unwrap();
+    let rejections = events
+        .iter()
+        .filter(|e| matches!(e, RunEvent::CriticFinished { lgtm: false, .. }))
+        .count();
+    assert_eq!(rejections, 1);
+    let fixes = events
+        .iter()
+        .filter(|e| matches!(e, RunEvent::FixApplied { .. }))
+        .count();
+    assert_eq!(fixes, 2);
+    assert!(events.iter().any(|e| matches!(
+        e,
+        RunEvent::TestPassed { proposal: 2, code: passed, .. } if *passed == code("a + b")
+    )));
+    let states: Vec<String> = events
+        .iter()
+        .filter_map(|e| match e {
+            RunEvent::StateEntered { state } => Some(match state {
+                RunState::Reviewing { proposal } => format!("reviewing {}", proposal),
+                RunState::Fixing {
+                    proposal, reason, ..
+                } => format!("fixing {} {:?}", proposal, reason),
+                RunState::Testing { proposal } => format!("testing {}", proposal),
+                RunState::Done { proposals } => format!("done {}", proposals),
+                state => format!("{:?}", state),
+            }),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        states,
+        [
+            "Coding",
+            "reviewing 1",
+            "fixing 1 Review",
+            "testing 1",
+            "fixing 1 Tests",
+            "reviewing 2",
+            "testing 2",
+            "done 2"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_scripted_test_first_run() {
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "Write a function that adds two integers.").unwrap();
+    let args = Args::try_parse_from([
+        "ai_critics",
+        "--problem-file",
+        problem.path().to_str().unwrap(),
+        "--loop-order",
+        "test-first",
+    ])
+    .unwrap();
+    let tester = ScriptedTester::new(vec![Some(
+        "test tests::test_add ... FAILED\n\ntest result: FAILED. 0 passed; 1 failed",
+    )]);
+    let events = Arc::new(Mutex::new(vec![]));
+
+    let proposals = solve_with(
+        &args,
+        vec![Box::new(Recorder(events.clone()))],
+        Arc::new(ScriptedClient::new(script)),
+        &tester,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(proposals, 2);
+    assert_eq!(tester.tested(), vec![code("a - b"), code("a + b")]);
+    let events = events.lock().unwrap();
+    // The critics only reviewed the code that passed, and approved it.
+    assert!(events
+        .iter()
+        .all(|e| !matches!(e, RunEvent::CriticFinished { lgtm: false, .. })));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        RunEvent::StateEntered {
+            state: RunState::Reviewing { proposal: 2 }
+        }
+    )));
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        RunEvent::StateEntered {
+            state: RunState::Reviewing { proposal: 1 }
+        }
+    )));
+}
+
+#[tokio::test]
+async fn test_scripted_parallel_run() {
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "Write a function that adds two integers.").unwrap();
+    let args = Args::try_parse_from([
+        "ai_critics",
+        "--problem-file",
+        problem.path().to_str().unwrap(),
+        "--loop-order",
+        "parallel",
+    ])
+    .unwrap();
+    let tester = ScriptedTester::new(vec![Some(
+        "test tests::test_add ... FAILED\n\ntest result: FAILED. 0 passed; 1 failed",
+    )]);
+    let events = Arc::new(Mutex::new(vec![]));
+
+    let proposals = solve_with(
+        &args,
+        vec![Box::new(Recorder(events.clone()))],
+        Arc::new(ScriptedClient::new(script)),
+        &tester,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(proposals, 2);
+    assert_eq!(tester.tested(), vec![code("a - b"), code("a + b")]);
+    let events = events.lock().unwrap();
+    let fixes: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            RunEvent::StateEntered {
+                state: RunState::Fixing { review, .. },
+            } => Some(review),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(fixes.len(), 1);
+    assert_eq!(fixes[0].comments[0], "`add` subtracts instead of adding.");
+    assert!(fixes[0].comments[1].contains("test result: FAILED"));
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        RunEvent::StateEntered {
+            state: RunState::Testing { .. }
+        }
+    )));
+}
+
+// Solve the problem with the given extra args and critics' script, while the code passes its tests.
+async fn solve_reviewed(
+    extra_args: &[&str],
+    critic: impl Fn(&str) -> String + Send + Sync + 'static,
+) -> (color_eyre::Result<usize>, Vec<RunEvent>) {
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "Write a function that adds two integers.").unwrap();
+    let problem_file = problem.path().to_str().unwrap().to_string();
+    let mut args = vec!["ai_critics", "--problem-file", &problem_file];
+    args.extend(extra_args);
+    let args = Args::try_parse_from(args).unwrap();
+    let events = Arc::new(Mutex::new(vec![]));
+    let client = ScriptedClient::new(move |system, _| match system.contains("lgtm") {
+        true => critic(system),
+        false => json!({"code": code("a + b")}).to_string(),
+    });
+    let result = solve_with(
+        &args,
+        vec![Box::new(Recorder(events.clone()))],
+        Arc::new(client),
+        &ScriptedTester::new(vec![]),
+    )
+    .await;
+    let events = events.lock().unwrap().clone();
+    (result, events)
+}
+
+fn approve() -> String {
+    json!({"lgtm": true, "corrections": []}).to_string()
+}
+
+fn failures(events: &[RunEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, RunEvent::CriticFailed { .. }))
+        .count()
+}
+
+#[tokio::test]
+async fn test_failed_critic_is_retried() {
+    // The syntax critic's first response isn't a JSON object, so it fails, and its retry approves
+    // the code.
+    let requests = AtomicUsize::new(0);
+    let (result, events) = solve_reviewed(&[], move |system| {
+        match system.contains("_syntax_") && requests.fetch_add(1, Ordering::SeqCst) == 0 {
+            true => "[]".to_string(),
+            false => approve(),
+        }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(failures(&events), 1);
+    assert!(events.iter().any(|e| matches!(
+        e,
+        RunEvent::Notice { message } if message == "Retrying 1 failed critic(s)."
+    )));
+}
+
+#[tokio::test]
+async fn test_failed_critic_is_skipped() {
+    let failing = |system: &str| match system.contains("_syntax_") {
+        true => "[]".to_string(),
+        false => approve(),
+    };
+    let (result, events) = solve_reviewed(&["--min-critics", "1"], failing).await;
+    assert_eq!(result.unwrap(), 1);
+    // It failed, and failed again when it was retried.
+    assert_eq!(failures(&events), 2);
+
+    let (result, _) = solve_reviewed(&[], failing).await;
+    let error = result.unwrap_err().to_string();
+    assert!(error.starts_with("only 2 critics succeeded, but 3 are required"));
+}
+
+#[tokio::test]
+async fn test_all_critics_failing_is_not_approval() {
+    let (result, events) = solve_reviewed(&["--min-critics", "0"], |_| "[]".to_string()).await;
+    let error = result.unwrap_err().to_string();
+    assert!(error.starts_with("only 0 critics succeeded, but 1 are required"));
+    assert!(failures(&events) > 0);
+}
+
+// Solve the problem, approving the coder's first code, and return the coder's system prompt.
+async fn coder_prompt(problem_text: &str, extra_args: &[&str]) -> String {
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "{}", problem_text).unwrap();
+    let problem_file = problem.path().to_str().unwrap().to_string();
+    let mut args = vec!["ai_critics", "--problem-file", &problem_file];
+    args.extend(extra_args);
+    let args = Args::try_parse_from(args).unwrap();
+    let prompt = Arc::new(Mutex::new(String::new()));
+    let seen = prompt.clone();
+    let client = ScriptedClient::new(move |system, _| match system.contains("lgtm") {
+        true => approve(),
+        false => {
+            *seen.lock().unwrap() = system.to_string();
+            json!({"code": code("a + b")}).to_string()
+        }
+    });
+    solve_with(
+        &args,
+        vec![],
+        Arc::new(client),
+        &ScriptedTester::new(vec![]),
+    )
+    .await
+    .unwrap();
+    let prompt = prompt.lock().unwrap().clone();
+    prompt
+}
+
+#[tokio::test]
+async fn test_coder_notes() {
+    let problem = "Write a function that adds two integers.";
+    assert!(!coder_prompt(problem, &[]).await.contains("fn bench"));
+    let constrained = format!("{}\nComplexity: O(1)", problem);
+    assert!(coder_prompt(&constrained, &[])
+        .await
+        .contains("Also define `fn bench(n: usize)`"));
+    assert!(!coder_prompt(problem, &[])
+        .await
+        .contains("AI_CRITICS_TEST_SEED"));
+    assert!(coder_prompt(problem, &["--test-seed", "42"])
+        .await
+        .contains("seed them from the integer in the `AI_CRITICS_TEST_SEED` environment variable"));
+}
+
+// Solve the problem with two fix candidates, whose code comes from the fixer's script, in order of
+// the fixers' requests. The tester fails code that subtracts.
+async fn solve_with_candidates(
+    fixes: [&'static str; 2],
+) -> (color_eyre::Result<usize>, Vec<RunEvent>, Vec<String>) {
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "Write a function that adds two integers.").unwrap();
+    let args = Args::try_parse_from([
+        "ai_critics",
+        "--problem-file",
+        problem.path().to_str().unwrap(),
+        "--fix-candidates",
+        "2",
+    ])
+    .unwrap();
+    let requests = AtomicUsize::new(0);
+    let client = ScriptedClient::new(move |system, user| {
+        if system.contains("Correct the code") {
+            json!({"code": code(fixes[requests.fetch_add(1, Ordering::SeqCst) % 2])}).to_string()
+        } else if system.contains("Two candidate corrections") {
+            json!({"better": "B", "reason": "B is shorter."}).to_string()
+        } else {
+            script(system, user)
+        }
+    });
+    let tester = ScriptedTester::judging(|code| {
+        code.contains(" - ")
+            .then(|| "test tests::test_add ... FAILED\n\ntest result: FAILED.".to_string())
+    });
+    let events = Arc::new(Mutex::new(vec![]));
+    let result = solve_with(
+        &args,
+        vec![Box::new(Recorder(events.clone()))],
+        Arc::new(client),
+        &tester,
+    )
+    .await;
+    let events = events.lock().unwrap().clone();
+    (result, events, tester.tested())
+}
+
+fn ranked(events: &[RunEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, RunEvent::CandidateRanked { .. }))
+        .count()
+}
+
+#[tokio::test]
+async fn test_fix_candidates() {
+    // The candidate that passes its tests is chosen without the ranker, and its result is reused.
+    let (result, events, tested) = solve_with_candidates(["a - b - 0", "a + b"]).await;
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(ranked(&events), 0);
+    // Each candidate was tested once, and the fix's own test was the chosen candidate's.
+    assert_eq!(tested.len(), 2);
+    assert!(tested.contains(&code("a + b")));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        RunEvent::TestPassed { proposal: 1, code: passed, .. } if *passed == code("a + b")
+    )));
+
+    // When both pass, the ranker chooses between them.
+    let (result, events, tested) = solve_with_candidates(["a + b", "b + a"]).await;
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(ranked(&events), 1);
+    assert_eq!(tested.len(), 2);
+    let RunEvent::CandidateRanked { winner, .. } = events
+        .iter()
+        .find(|e| matches!(e, RunEvent::CandidateRanked { .. }))
+        .unwrap()
+    else {
+        unreachable!();
+    };
+    let fix = events.iter().find_map(|e| match e {
+        RunEvent::FixApplied { fixer, .. } => Some(fixer.clone()),
+        _ => None,
+    });
+    assert_eq!(fix.as_ref(), Some(winner));
+}
+
+// Solve the problem with the critics of a config and the extra args, approving the coder's first
+// code, and return the system prompt and user messages of each critic's request.
+async fn configured_critics(config: &str, extra_args: &[&str]) -> Vec<(String, String)> {
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "Write a function that adds two integers.").unwrap();
+    let mut config_file = tempfile::NamedTempFile::new().unwrap();
+    write!(config_file, "{}", config).unwrap();
+    let (problem_file, config_path) = (
+        problem.path().to_str().unwrap().to_string(),
+        config_file.path().to_str().unwrap().to_string(),
+    );
+    let mut args = vec![
+        "ai_critics",
+        "--problem-file",
+        &problem_file,
+        "--critics-config",
+        &config_path,
+    ];
+    args.extend(extra_args);
+    let args = Args::try_parse_from(args).unwrap();
+    let requests = Arc::new(Mutex::new(vec![]));
+    let seen = requests.clone();
+    let client = ScriptedClient::new(move |system, user| match system.contains("lgtm") {
+        true => {
+            seen.lock()
+                .unwrap()
+                .push((system.to_string(), user.to_string()));
+            approve()
+        }
+        false => json!({"code": code("a + b")}).to_string(),
+    });
+    solve_with(
+        &args,
+        vec![],
+        Arc::new(client),
+        &ScriptedTester::new(vec![]),
+    )
+    .await
+    .unwrap();
+    let requests = requests.lock().unwrap().clone();
+    requests
+}
+
+#[tokio::test]
+async fn test_configured_idiomatic_critic() {
+    // The configured critic answers offline, so only an Idiomatic critic that --idiomatic adds
+    // reaches the scripted client.
+    let config = "[[critics]]\ntype = \"correctness\"\nprovider = \"fake\"\n";
+    assert!(configured_critics(config, &[]).await.is_empty());
+    let requests = configured_critics(config, &["--idiomatic"]).await;
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].0.contains("Rust API guidelines"));
+    assert!(requests[0].1.contains("clippy::pedantic"));
+
+    let disabled = configured_critics(config, &["--idiomatic", "--disable-critic", "idiomatic"]);
+    assert!(disabled.await.is_empty());
+}
+
+#[tokio::test]
+async fn test_anchored_fix() {
+    // The syntax critic anchors its correction to the numbered line that subtracts. The fixer sees
+    // the line marked and keeps the marker, which is stripped from the code it returns.
+    let mut problem = tempfile::NamedTempFile::new().unwrap();
+    writeln!(problem, "Write a function that adds two integers.").unwrap();
+    let args = Args::try_parse_from([
+        "ai_critics",
+        "--problem-file",
+        problem.path().to_str().unwrap(),
+    ])
+    .unwrap();
+    let fixer_requests = Arc::new(Mutex::new(vec![]));
+    let seen = fixer_requests.clone();
+    let client = ScriptedClient::new(move |system, user| {
+        let response = if system.contains("Write the requested program") {
+            json!({"code": code("a - b")})
+        } else if system.contains("Correct the code") {
+            seen.lock().unwrap().push(user.to_string());
+            json!({"code": code("a + b // <- review [1]")})
+        } else if system.contains("_syntax_") && user.contains(" 2 |     a - b") {
+            json!({"lgtm": false, "corrections": [
+                {"correction": "`add` subtracts instead of adding.", "line": 2}
+            ]})
+        } else {
+            json!({"lgtm": true, "corrections": []})
+        };
+        response.to_string()
+    });
+    let tester = ScriptedTester::new(vec![]);
+
+    let proposals = solve_with(&args, vec![], Arc::new(client), &tester)
+        .await
+        .unwrap();
+
+    assert_eq!(proposals, 1);
+    let fixer_requests = fixer_requests.lock().unwrap();
+    assert_eq!(fixer_requests.len(), 1);
+    assert!(fixer_requests[0].contains("    a - b // <- review [1]\n"));
+    assert!(fixer_requests[0].contains("[1] `add` subtracts instead of adding."));
+    assert_eq!(tester.tested(), vec![code("a + b")]);
+}
+
+fn chosen(events: &[RunEvent]) -> Option<String> {
+    events.iter().find_map(|e| match e {
+        RunEvent::CriticsChosen { critic_types } => Some(format!("{:?}", critic_types)),
+        _ => None,
+    })
+}
+
+#[tokio::test]
+async fn test_disabled_critics() {
+    let disabled = ["--disable-critic", "design", "--disable-critic", "syntax"];
+    let (result, events) = solve_reviewed(&disabled, |_| approve()).await;
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(chosen(&events).as_deref(), Some("[Correctness]"));
+    let critics: Vec<&str> = events
+        .iter()
+        .filter_map(|e| match e {
+            RunEvent::CriticFinished { critic, .. } => Some(critic.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(critics, ["Correctness Critic 1"]);
+
+    let batched = [&disabled[..], &["--batch-critics"]].concat();
+    let review = |_: &str| json!({"correctness": {"lgtm": true, "corrections": []}}).to_string();
+    let (result, events) = solve_reviewed(&batched, review).await;
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(chosen(&events).as_deref(), Some("[Correctness]"));
+
+    // With every type disabled, the run fails before the coder is asked for code.
+    for extra in [&[][..], &["--batch-critics"]] {
+        let all = [&disabled[..], &["--disable-critic", "correctness"], extra].concat();
+        let (result, events) = solve_reviewed(&all, |_| approve()).await;
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("--disable-critic leaves in"), "{}", error);
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, RunEvent::ProposalCreated { .. })));
+    }
+}
+
+#[tokio::test]
+async fn test_failed_screen_is_confirmed() {
+    // The syntax screening critic fails, and fails again when it's retried, while the others
+    // approve. Its type hasn't been reviewed, so the usual critics confirm the review.
+    let requests = AtomicUsize::new(0);
+    let screened = ["--screen-model", "gpt-4o-mini"];
+    let (result, events) = solve_reviewed(&screened, move |system| {
+        match system.contains("_syntax_") && requests.fetch_add(1, Ordering::SeqCst) < 2 {
+            true => "[]".to_string(),
+            false => approve(),
+        }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(failures(&events), 2);
+    assert!(events.iter().any(|e| matches!(
+        e,
+        RunEvent::Notice { message }
+            if message == "Confirming the review of the 1 unscreened critic types."
+    )));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        RunEvent::CriticFinished { critic, .. } if critic == "Syntax Critic 1"
+    )));
+}
+
+#[tokio::test]
+async fn test_failed_screens_are_confirmed() {
+    // Every screening critic fails, as when the screening model is down, and fails again when it's
+    // retried. The usual critics review every type instead.
+    let requests = AtomicUsize::new(0);
+    let screened = ["--screen-model", "gpt-4o-mini"];
+    let (result, events) = solve_reviewed(&screened, move |_| {
+        match requests.fetch_add(1, Ordering::SeqCst) < 6 {
+            true => "[]".to_string(),
+            false => approve(),
+        }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(failures(&events), 6);
+    assert!(events.iter().any(|e| matches!(
+        e,
+        RunEvent::Notice { message }
+            if message == "Confirming the review of the 3 unscreened critic types."
+    )));
+    let confirmed = events
+        .iter()
+        .filter(|e| matches!(e, RunEvent::CriticFinished { lgtm: true, .. }))
+        .count();
+    assert_eq!(confirmed, 3);
+}
+
+#[tokio::test]
+async fn test_configured_critics_are_screened() {
+    // The screening critics are of the configured critics' types.
+    let config = "[[critics]]\ntype = \"correctness\"\nprovider = \"fake\"\n";
+    let requests = configured_critics(config, &["--screen-model", "gpt-4o-mini"]).await;
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].0.contains("_correctness_"));
+}